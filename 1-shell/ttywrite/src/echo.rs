@@ -0,0 +1,53 @@
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Returns the name of the echo log file for a session started at `now`:
+/// `ttywrite-<seconds since the epoch>.log`.
+pub fn log_file_name(now: SystemTime) -> String {
+    let secs = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    format!("ttywrite-{}.log", secs)
+}
+
+/// A log of every byte received from the TTY.
+///
+/// Bytes are written to the inner writer unmodified, except that every
+/// non-empty line is prefixed with the time since the session started at which
+/// its first byte arrived, as in `[   12.345] `.
+pub struct EchoLog<W> {
+    inner: W,
+    line_start: bool,
+}
+
+impl<W: io::Write> EchoLog<W> {
+    /// Returns a log writing to `inner`.
+    pub fn new(inner: W) -> EchoLog<W> {
+        EchoLog { inner, line_start: true }
+    }
+
+    /// Logs `bytes`, received `elapsed` after the session started.
+    pub fn log(&mut self, elapsed: Duration, bytes: &[u8]) -> io::Result<()> {
+        for (i, line) in bytes.split(|&b| b == b'\n').enumerate() {
+            if i > 0 {
+                self.inner.write_all(b"\n")?;
+                self.line_start = true;
+            }
+
+            if !line.is_empty() {
+                if self.line_start {
+                    write!(self.inner, "[{:5}.{:03}] ", elapsed.as_secs(),
+                           elapsed.subsec_nanos() / 1_000_000)?;
+                    self.line_start = false;
+                }
+
+                self.inner.write_all(line)?;
+            }
+        }
+
+        self.inner.flush()
+    }
+
+    /// Returns the inner writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
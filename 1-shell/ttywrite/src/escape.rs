@@ -0,0 +1,76 @@
+use std::fmt;
+
+/// Detects an escape sequence in a stream of typed bytes.
+///
+/// Bytes are fed in one at a time with `feed()`. Bytes that might be the start
+/// of the sequence are held back until it is clear whether they are; every
+/// other byte is passed through untouched. The sequence itself is never passed
+/// through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Escape {
+    seq: Vec<u8>,
+    matched: usize,
+}
+
+impl Escape {
+    /// Returns a detector for the sequence `seq`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `seq` is empty.
+    pub fn new(seq: Vec<u8>) -> Escape {
+        assert!(!seq.is_empty(), "escape sequence is empty");
+        Escape { seq, matched: 0 }
+    }
+
+    /// Returns the sequence being detected.
+    pub fn sequence(&self) -> &[u8] {
+        &self.seq
+    }
+
+    /// Feeds the typed byte `byte` to the detector, appending any bytes that
+    /// are now known not to be part of the sequence to `out`. Returns `true`
+    /// when `byte` completes the sequence, after which detection starts over.
+    pub fn feed(&mut self, byte: u8, out: &mut Vec<u8>) -> bool {
+        let mut pending = self.seq[..self.matched].to_vec();
+        pending.push(byte);
+
+        // Keep the longest tail of what's pending that could still become the
+        // sequence; everything before it is ordinary input.
+        let keep_from = (0..pending.len() + 1)
+            .find(|&i| self.seq.starts_with(&pending[i..]))
+            .expect("the empty tail is always a prefix");
+
+        out.extend_from_slice(&pending[..keep_from]);
+        self.matched = pending.len() - keep_from;
+        if self.matched == self.seq.len() {
+            self.matched = 0;
+            return true;
+        }
+
+        false
+    }
+
+    /// Returns the bytes currently held back as a possible start of the
+    /// sequence and starts detection over.
+    pub fn flush(&mut self) -> Vec<u8> {
+        let held = self.seq[..self.matched].to_vec();
+        self.matched = 0;
+        held
+    }
+}
+
+impl fmt::Display for Escape {
+    /// Writes the sequence in the notation `parse_escape()` accepts.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &byte in &self.seq {
+            match byte {
+                0x7f => write!(f, "^?")?,
+                0...0x1f => write!(f, "^{}", (byte + b'@') as char)?,
+                _ => write!(f, "{}", byte as char)?,
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,20 +1,34 @@
 extern crate serial;
 extern crate structopt;
+extern crate termios;
 extern crate xmodem;
 #[macro_use] extern crate structopt_derive;
 
-use std::io::Write;
+use std::io::{self, Write};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 
 use std::path::PathBuf;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use structopt::StructOpt;
-use serial::core::{CharSize, BaudRate, StopBits, FlowControl, SerialDevice, SerialPortSettings};
+use serial::core::{CharSize, BaudRate, Parity, StopBits, FlowControl};
+use serial::core::{SerialDevice, SerialPortSettings};
 use xmodem::{Xmodem, Progress};
+use xmodem::ymodem::Header;
+use xmodem::baud::{self, DEFAULT_BAUD};
 
 mod parsers;
+mod escape;
+mod echo;
+mod terminal;
+#[cfg(test)] mod tests;
 
-use parsers::{parse_width, parse_stop_bits, parse_flow_control, parse_baud_rate};
+use parsers::{parse_width, parse_stop_bits, parse_flow_control, parse_baud_rate, parse_escape};
+use parsers::parse_parity;
+use escape::Escape;
+use echo::EchoLog;
+use terminal::RawMode;
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "Write to TTY using the XMODEM protocol by default.")]
@@ -22,10 +36,16 @@ struct Opt {
     #[structopt(short = "i", help = "Input file (defaults to stdin if not set)", parse(from_os_str))]
     input: Option<PathBuf>,
 
+    // XMODEM transfers always start at 115200, the bootloader's default, and
+    // ask the receiver to switch to this rate. See `xmodem::baud`.
     #[structopt(short = "b", long = "baud", parse(try_from_str = "parse_baud_rate"),
-                help = "Set baud rate", default_value = "115200")]
+                help = "Set baud rate (negotiated with the receiver for XMODEM transfers)",
+                default_value = "115200")]
     baud_rate: BaudRate,
 
+    // The receiver re-sends its start byte every time its own read timeout
+    // expires, so any timeout longer than the bootloader's (750ms) lets the
+    // transmitter synchronize with a receiver that is already waiting.
     #[structopt(short = "t", long = "timeout", parse(try_from_str),
                 help = "Set timeout in seconds", default_value = "10")]
     timeout: u64,
@@ -45,48 +65,318 @@ struct Opt {
                 help = "Set number of stop bits", default_value = "1")]
     stop_bits: StopBits,
 
+    #[structopt(long = "parity", parse(try_from_str = "parse_parity"),
+                help = "Set parity ('none', 'odd', or 'even')", default_value = "none")]
+    parity: Parity,
+
     #[structopt(short = "r", long = "raw", help = "Disable XMODEM")]
     raw: bool,
+
+    #[structopt(short = "y", long = "ymodem", parse(from_os_str),
+                help = "Send this file in a YMODEM batch instead; repeat for more files")]
+    batch: Vec<PathBuf>,
+
+    #[structopt(long = "retries", parse(try_from_str),
+                help = "Give up after this many consecutive XMODEM errors", default_value = "10")]
+    retries: usize,
+
+    #[structopt(long = "terminal",
+                help = "After sending the input file, if any, bridge this terminal and the TTY")]
+    terminal: bool,
+
+    #[structopt(long = "echo",
+                help = "In terminal mode, also log received bytes to ttywrite-<time>.log")]
+    echo: bool,
+
+    #[structopt(long = "escape", parse(try_from_str = "parse_escape"),
+                help = "In terminal mode, the keys that quit", default_value = "^A^X")]
+    escape: Escape,
+}
+
+/// How long to wait for the receiver to acknowledge a new baud rate. This is
+/// shorter than one round of the bootloader's listen-then-receive loop (about
+/// 3.75s while it asks for CRC-16), so the start bytes it sends at the old rate
+/// can't stretch the wait.
+const ACK_TIMEOUT_MS: u64 = 1000;
+
+/// The number of times to ask the receiver to switch rates before falling
+/// back to the default rate.
+const NEGOTIATION_ATTEMPTS: usize = 4;
+
+impl Opt {
+    /// Checks the line settings against what the Raspberry Pi's mini UART, as
+    /// set up by `pi::uart::MiniUart`, can do. Returns a description of the
+    /// first setting it can't match.
+    fn check_mini_uart(&self) -> Result<(), String> {
+        let speed = self.baud_rate.speed();
+        if speed > u32::max_value() as usize || baud::divisor(speed as u32).is_none() {
+            return Err(format!("the mini UART can't run at {} baud", speed));
+        }
+
+        if self.char_width != CharSize::Bits8 {
+            return Err("the mini UART is set up for 8 data bits".to_string());
+        }
+
+        if self.parity != Parity::ParityNone {
+            return Err("the mini UART has no parity bit".to_string());
+        }
+
+        if self.stop_bits != StopBits::Stop1 {
+            return Err("the mini UART only sends 1 stop bit".to_string());
+        }
+
+        // The mini UART can do RTS/CTS (see `MiniUart::set_flow_control()`)
+        // but not XON/XOFF, which binary XMODEM packets would trip anyway.
+        if self.flow_control == FlowControl::FlowSoftware {
+            return Err("the mini UART has no software flow control".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a YMODEM batch isn't combined with another way of sending.
+    /// Returns a description of the conflict if it is.
+    fn check_batch(&self) -> Result<(), String> {
+        if !self.batch.is_empty() && (self.input.is_some() || self.raw) {
+            return Err("-y can't be combined with -i or -r".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// The size of the file being sent, or 0 if it isn't known, for drawing the
+/// progress bar.
+static TOTAL_BYTES: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The width of the progress bar, in characters.
+const BAR_WIDTH: usize = 40;
+
+/// Returns the progress line for `bytes` sent out of `total`, or out of an
+/// unknown total if `total` is 0, after recovering from `retries` errors.
+/// Padding and the trailer can take `bytes` past `total`.
+fn progress_line(bytes: usize, total: usize, retries: usize) -> String {
+    let mut line = if total == 0 {
+        format!("{} bytes", bytes)
+    } else {
+        let done = bytes.min(total);
+        let filled = done * BAR_WIDTH / total;
+        format!("[{}{}] {:>3}% {}/{} bytes", "#".repeat(filled), " ".repeat(BAR_WIDTH - filled),
+                done * 100 / total, done, total)
+    };
+
+    if retries > 0 {
+        line.push_str(&format!(", retries: {}", retries));
+    }
+
+    line
+}
+
+/// Prints transfer progress to stdout.
+fn print_progress(progress: Progress) {
+    match progress {
+        Progress::Waiting => println!("Ready"),
+        Progress::Started => {  },
+        Progress::Packet { bytes, retries, .. } => {
+            let total = TOTAL_BYTES.load(Ordering::Relaxed);
+            print!("\r{}", progress_line(bytes, total, retries));
+            io::stdout().flush().unwrap();
+        }
+        Progress::Finished(summary) if summary.errors() > 0 => {
+            println!("");
+            println!("Recovered from {} NAKs, {} timeouts and {} duplicate packets.",
+                     summary.naks, summary.timeouts, summary.duplicates);
+        }
+        Progress::Finished(_) => {  },
+    }
+}
+
+/// Switches `serial` to `rate` baud.
+fn set_baud<P: SerialDevice>(serial: &mut P, rate: u32) -> io::Result<()> {
+    let mut settings = serial.read_settings()?;
+    settings.set_baud_rate(BaudRate::from_speed(rate as usize))?;
+    serial.write_settings(&settings)?;
+    Ok(())
+}
+
+/// Asks the receiver at the other end of `serial`, which must be at
+/// `DEFAULT_BAUD`, to switch both sides to `rate` baud. Returns `true` if
+/// they did. Otherwise `serial` is left at `DEFAULT_BAUD`.
+fn negotiate<P: SerialDevice>(serial: &mut P, rate: u32) -> io::Result<bool> {
+    let timeout = serial.timeout();
+    serial.set_timeout(Duration::from_millis(ACK_TIMEOUT_MS))?;
+    let switched = baud::request(serial, rate, NEGOTIATION_ATTEMPTS, set_baud);
+    serial.set_timeout(timeout)?;
+    switched
+}
+
+/// Sends everything in `data` to `port`, either as raw bytes if `raw` is
+/// `true` or using the XMODEM protocol otherwise. Returns the number of bytes
+/// sent, excluding any XMODEM padding.
+///
+/// The transfer gives up when an XMODEM packet fails `retries` times in a
+/// row. The bootloader's own limit is `xmodem::MAX_RETRIES`. Receivers that ask
+/// for CRC-16, like the bootloader, are sent 1K packets. XMODEM transfers
+/// end with a trailer holding the length and CRC-32 of `data`, and only
+/// succeed once the receiver confirms that what it received matches.
+fn send<R, T>(mut data: R, mut port: T, raw: bool, retries: usize) -> io::Result<u64>
+    where R: io::Read, T: io::Read + io::Write
+{
+    if raw {
+        io::copy(&mut data, &mut port)
+    } else {
+        let mut transmitter = Xmodem::new_with_progress(port, print_progress);
+        transmitter.set_max_retries(retries);
+        transmitter.set_1k_blocks(true);
+        let bytes = transmitter.transmit_all_verified(data)?;
+        println!("");
+        println!("Receiver verified the image.");
+        Ok(bytes as u64)
+    }
+}
+
+/// Sends the files at `paths` to `port` in one YMODEM batch, each named by its
+/// file name. Returns the number of bytes sent.
+///
+/// The transfer gives up when a packet fails `retries` times in a row.
+fn send_batch<T: io::Read + io::Write>(paths: &[PathBuf], port: T, retries: usize)
+    -> io::Result<u64>
+{
+    use std::fs::File;
+    use std::io::BufReader;
+
+    let mut transmitter = Xmodem::new_with_progress(port, print_progress);
+    transmitter.set_max_retries(retries);
+    transmitter.set_1k_blocks(true);
+
+    let mut total = 0;
+    for path in paths {
+        let file = File::open(path)?;
+        let size = file.metadata()?.len();
+        let header = path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| Header::new(name, size))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+                                          format!("{} can't be named in YMODEM", path.display())))?;
+
+        println!("Sending {} ({} bytes)", header.name(), size);
+        TOTAL_BYTES.store(size as usize, Ordering::Relaxed);
+        total += transmitter.transmit_file(&header, BufReader::new(file))? as u64;
+        println!("");
+    }
+
+    transmitter.end_batch()?;
+    Ok(total)
 }
 
 fn main() {
     use std::fs::File;
-    use std::io::{self, BufReader};
+    use std::io::BufReader;
 
     let opt = Opt::from_args();
+    if let Err(msg) = opt.check_batch() {
+        eprintln!("ttywrite: {}", msg);
+        process::exit(1);
+    }
+
+    // In terminal mode, stdin belongs to the terminal: only send a file.
+    let batch = !opt.batch.is_empty();
+    let reader: Option<Box<io::Read>> = if batch {
+        None
+    } else if let Some(path) = opt.input.as_ref() {
+        let file = File::open(path).expect("Failed to open file");
+        let len = file.metadata().map(|metadata| metadata.len() as usize).unwrap_or(0);
+        TOTAL_BYTES.store(len, Ordering::Relaxed);
+        Some(Box::new(BufReader::new(file)))
+    } else if !opt.terminal {
+        Some(Box::new(BufReader::new(io::stdin())))
+    } else {
+        None
+    };
+
+    // XMODEM transfers start at the default rate and negotiate from there.
+    let transfer = reader.is_some() || batch;
+    let xmodem = transfer && !opt.raw;
+
+    // Only XMODEM sessions are known to be talking to the bootloader's mini
+    // UART; raw and terminal sessions may be talking to anything.
+    if xmodem {
+        if let Err(msg) = opt.check_mini_uart() {
+            eprintln!("ttywrite: {}", msg);
+            process::exit(1);
+        }
+    }
+
+    let baud_rate = opt.baud_rate.speed() as u32;
+    let initial = if xmodem { BaudRate::from_speed(DEFAULT_BAUD as usize) } else { opt.baud_rate };
+
     let mut serial = serial::open(&opt.tty_path).expect("Path points to invalid TTY");
 
     let mut settings = serial.read_settings().expect("Failed to load settings");
-    settings.set_baud_rate(opt.baud_rate).expect("Invalid baud rate");
+    settings.set_baud_rate(initial).expect("Invalid baud rate");
     settings.set_char_size(opt.char_width);
+    settings.set_parity(opt.parity);
     settings.set_flow_control(opt.flow_control);
     settings.set_stop_bits(opt.stop_bits);
     serial.write_settings(&settings).expect("Failed to apply serial settings");
 
     serial.set_timeout(Duration::from_secs(opt.timeout)).expect("Invalid timeout");
 
-    let mut reader: Box<io::Read> = if let Some(path) = opt.input {
-        let file = File::open(path).expect("Failed to open file");
-        Box::new(BufReader::new(file))
-    } else {
-        Box::new(BufReader::new(io::stdin()))
-    };
-
-    if opt.raw {
-        let bytes = io::copy(&mut reader, &mut serial).expect("Write failed");
-        println!("Wrote {} bytes.", bytes);
-    } else {
-        let bytes = Xmodem::transmit_with_progress(reader, serial, |progress| {
-            if let Progress::Packet(_) = progress {
-                print!(".");
-                io::stdout().flush().unwrap();
-            } else if let Progress::Waiting = progress {
-                println!("Ready");
+    if transfer {
+        let mut switched = false;
+        if xmodem && baud_rate != DEFAULT_BAUD {
+            switched = negotiate(&mut serial, baud_rate).expect("Baud negotiation failed");
+            if switched {
+                println!("Switched to {} baud.", baud_rate);
             } else {
-                assert!(false);
+                eprintln!("ttywrite: the receiver didn't acknowledge {} baud; \
+                           falling back to {} baud", baud_rate, DEFAULT_BAUD);
             }
-        }).expect("Write failed");
-        println!("");
+        }
+
+        let bytes = match reader {
+            Some(reader) => send(reader, &mut serial, opt.raw, opt.retries),
+            None => send_batch(&opt.batch, &mut serial, opt.retries),
+        };
+
+        let bytes = bytes.expect("Write failed");
         println!("Wrote {} bytes.", bytes);
+
+        // The bootloader returns to the default rate after a transfer, and so
+        // does whatever it loaded.
+        if switched {
+            set_baud(&mut serial, DEFAULT_BAUD).expect("Failed to restore baud rate");
+        }
+    }
+
+    if opt.terminal {
+        run_terminal(serial, opt.escape, opt.echo);
     }
 }
+
+/// Bridges this terminal and `serial` until `escape` is typed, logging
+/// received bytes to a new file if `echo` is `true`.
+fn run_terminal<P: SerialDevice>(mut serial: P, escape: Escape, echo: bool) {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    let log = if echo {
+        let name = echo::log_file_name(SystemTime::now());
+        let file = File::create(&name).expect("Failed to create echo log");
+        println!("Logging received bytes to {}.", name);
+        Some(EchoLog::new(file))
+    } else {
+        None
+    };
+
+    // Short reads keep typed keys flowing while the other side is quiet.
+    serial.set_timeout(Duration::from_millis(10)).expect("Invalid timeout");
+
+    println!("Connected. Type {} to quit.", escape);
+    let raw_mode = RawMode::enable(io::stdin().as_raw_fd()).expect("stdin is not a terminal");
+    let result = terminal::bridge(&mut serial, io::stdin(), &mut io::stdout(), escape, log);
+    drop(raw_mode);
+
+    println!("");
+    result.expect("Terminal connection failed");
+}
@@ -1,4 +1,6 @@
-use serial::core::{CharSize, BaudRate, StopBits, FlowControl};
+use serial::core::{CharSize, BaudRate, Parity, StopBits, FlowControl};
+
+use escape::Escape;
 
 pub fn parse_width(s: &str) -> Result<CharSize, &str> {
     match s {
@@ -18,6 +20,15 @@ pub fn parse_stop_bits(s: &str) -> Result<StopBits, &str> {
     }
 }
 
+pub fn parse_parity(s: &str) -> Result<Parity, &str> {
+    match s {
+        "none" => Ok(Parity::ParityNone),
+        "odd" => Ok(Parity::ParityOdd),
+        "even" => Ok(Parity::ParityEven),
+        _ => Err("value must be 'none', 'odd', or 'even'")
+    }
+}
+
 pub fn parse_flow_control(s: &str) -> Result<FlowControl, &str> {
     match s {
         "none" => Ok(FlowControl::FlowNone),
@@ -30,3 +41,31 @@ pub fn parse_flow_control(s: &str) -> Result<FlowControl, &str> {
 pub fn parse_baud_rate(s: &str) -> Result<BaudRate, ::std::num::ParseIntError> {
     Ok(BaudRate::from_speed(s.parse()?))
 }
+
+/// Parses a key sequence such as `^A^X`. `^` followed by a letter or one of
+/// `@[\]^_?` is that control character; any other character stands for
+/// itself.
+pub fn parse_escape(s: &str) -> Result<Escape, &str> {
+    let mut seq = vec![];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        let byte = match c {
+            '^' => match chars.next() {
+                Some('?') => 0x7f,
+                Some(c @ '@'...'_') => c as u8 - b'@',
+                Some(c @ 'a'...'z') => c as u8 - b'a' + 1,
+                _ => return Err("'^' must be followed by a letter or one of '@[\\]^_?'"),
+            },
+            c if c.is_ascii() => c as u8,
+            _ => return Err("sequence must be ASCII"),
+        };
+
+        seq.push(byte);
+    }
+
+    if seq.is_empty() {
+        return Err("sequence must not be empty");
+    }
+
+    Ok(Escape::new(seq))
+}
@@ -0,0 +1,108 @@
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::mpsc::{channel, TryRecvError};
+use std::thread;
+use std::time::Instant;
+
+use termios::{Termios, tcsetattr, cfmakeraw, TCSANOW};
+
+use escape::Escape;
+use echo::EchoLog;
+
+/// Keeps a terminal in raw mode for as long as it is alive, so that keys like
+/// backspace and the arrows reach the other side untouched. The original
+/// settings are restored when the guard is dropped, including while unwinding
+/// from a panic.
+pub struct RawMode {
+    fd: RawFd,
+    original: Termios,
+}
+
+impl RawMode {
+    /// Puts the terminal `fd` in raw mode.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `fd` is not a terminal or its settings can't be
+    /// changed.
+    pub fn enable(fd: RawFd) -> io::Result<RawMode> {
+        let original = Termios::from_fd(fd)?;
+        let mut raw = original;
+        cfmakeraw(&mut raw);
+        tcsetattr(fd, TCSANOW, &raw)?;
+        Ok(RawMode { fd, original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = tcsetattr(self.fd, TCSANOW, &self.original);
+    }
+}
+
+/// Bridges `input` and `port` until `escape` is typed or `input` ends.
+///
+/// Bytes read from `input` are written to `port`, minus the escape sequence.
+/// Bytes read from `port` are written to `output` and, if there is one, to
+/// `log`. `port` must time out reads with `TimedOut` so that input is
+/// forwarded while the other side is quiet.
+pub fn bridge<P, I, O, W>(
+    port: &mut P,
+    input: I,
+    output: &mut O,
+    mut escape: Escape,
+    mut log: Option<EchoLog<W>>
+) -> io::Result<()>
+    where P: io::Read + io::Write, I: io::Read + Send + 'static, O: io::Write, W: io::Write
+{
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for byte in input.bytes() {
+            match byte {
+                Ok(byte) => if tx.send(byte).is_err() { break },
+                Err(_) => break,
+            }
+        }
+    });
+
+    let start = Instant::now();
+    let mut typed = vec![];
+    let mut buf = [0u8; 256];
+    loop {
+        let mut done = false;
+        loop {
+            match rx.try_recv() {
+                Ok(byte) => if escape.feed(byte, &mut typed) {
+                    done = true;
+                    break;
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    typed.extend(escape.flush());
+                    done = true;
+                    break;
+                }
+            }
+        }
+
+        port.write_all(&typed)?;
+        port.flush()?;
+        typed.clear();
+        if done {
+            return Ok(());
+        }
+
+        match port.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(n) => {
+                output.write_all(&buf[..n])?;
+                output.flush()?;
+                if let Some(ref mut log) = log {
+                    log.log(start.elapsed(), &buf[..n])?;
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {  }
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::io;
+use std::sync::mpsc::{Receiver, Sender, channel};
+
+use serial::core::{CharSize, BaudRate, Parity, StopBits, FlowControl};
+use structopt::StructOpt;
+use xmodem::{Xmodem, trailer, MAX_RETRIES};
+
+use parsers::*;
+use escape::Escape;
+use echo::{EchoLog, log_file_name};
+use super::{Opt, send, send_batch, progress_line, BAR_WIDTH};
+
+/// One end of an in-memory, bidirectional byte pipe.
+struct Pipe(Sender<u8>, Receiver<u8>);
+
+fn pipe() -> (Pipe, Pipe) {
+    let ((tx1, rx1), (tx2, rx2)) = (channel(), channel());
+    (Pipe(tx1, rx2), Pipe(tx2, rx1))
+}
+
+impl io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for i in 0..buf.len() {
+            match self.1.recv() {
+                Ok(byte) => buf[i] = byte,
+                Err(_) => return Ok(i)
+            }
+        }
+
+        Ok(buf.len())
+    }
+}
+
+impl io::Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for (i, byte) in buf.iter().cloned().enumerate() {
+            if self.0.send(byte).is_err() {
+                return Ok(i);
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn opt_from(args: &[&str]) -> Opt {
+    let mut argv = vec!["ttywrite"];
+    argv.extend_from_slice(args);
+    Opt::from_clap(Opt::clap().get_matches_from_safe(argv).expect("valid arguments"))
+}
+
+fn opt_error(args: &[&str]) {
+    let mut argv = vec!["ttywrite"];
+    argv.extend_from_slice(args);
+    assert!(Opt::clap().get_matches_from_safe(argv).is_err(), "{:?} should fail", args);
+}
+
+#[test]
+fn parsers_accept_valid_values() {
+    assert_eq!(parse_width("5"), Ok(CharSize::Bits5));
+    assert_eq!(parse_width("8"), Ok(CharSize::Bits8));
+    assert_eq!(parse_stop_bits("1"), Ok(StopBits::Stop1));
+    assert_eq!(parse_stop_bits("2"), Ok(StopBits::Stop2));
+    assert_eq!(parse_flow_control("none"), Ok(FlowControl::FlowNone));
+    assert_eq!(parse_flow_control("software"), Ok(FlowControl::FlowSoftware));
+    assert_eq!(parse_flow_control("hardware"), Ok(FlowControl::FlowHardware));
+    assert_eq!(parse_parity("none"), Ok(Parity::ParityNone));
+    assert_eq!(parse_parity("odd"), Ok(Parity::ParityOdd));
+    assert_eq!(parse_parity("even"), Ok(Parity::ParityEven));
+    assert_eq!(parse_baud_rate("115200"), Ok(BaudRate::Baud115200));
+    assert_eq!(parse_baud_rate("230400"), Ok(BaudRate::BaudOther(230400)));
+}
+
+#[test]
+fn parsers_reject_invalid_values() {
+    assert!(parse_width("4").is_err());
+    assert!(parse_width("9").is_err());
+    assert!(parse_stop_bits("3").is_err());
+    assert!(parse_flow_control("rts").is_err());
+    assert!(parse_parity("mark").is_err());
+    assert!(parse_baud_rate("fast").is_err());
+    assert!(parse_baud_rate("-9600").is_err());
+}
+
+#[test]
+fn cli_defaults() {
+    let opt = opt_from(&["/dev/ttyUSB0"]);
+    assert_eq!(opt.tty_path.to_str(), Some("/dev/ttyUSB0"));
+    assert_eq!(opt.baud_rate, BaudRate::Baud115200);
+    assert_eq!(opt.timeout, 10);
+    assert_eq!(opt.char_width, CharSize::Bits8);
+    assert_eq!(opt.flow_control, FlowControl::FlowNone);
+    assert_eq!(opt.stop_bits, StopBits::Stop1);
+    assert_eq!(opt.parity, Parity::ParityNone);
+    assert!(opt.input.is_none());
+    assert!(opt.batch.is_empty());
+    assert!(!opt.raw);
+    assert_eq!(opt.retries, MAX_RETRIES);
+    assert!(!opt.terminal);
+    assert!(!opt.echo);
+    assert_eq!(opt.escape.sequence(), &[0x01, 0x18]);
+}
+
+#[test]
+fn cli_flags() {
+    let opt = opt_from(&["-i", "kernel.bin", "-b", "9600", "-t", "3", "-w", "7",
+                         "-f", "hardware", "-s", "2", "-r", "--retries", "30", "/dev/tty"]);
+    assert_eq!(opt.input.as_ref().and_then(|p| p.to_str()), Some("kernel.bin"));
+    assert_eq!(opt.baud_rate, BaudRate::Baud9600);
+    assert_eq!(opt.timeout, 3);
+    assert_eq!(opt.char_width, CharSize::Bits7);
+    assert_eq!(opt.flow_control, FlowControl::FlowHardware);
+    assert_eq!(opt.stop_bits, StopBits::Stop2);
+    assert!(opt.raw);
+    assert_eq!(opt.retries, 30);
+}
+
+#[test]
+fn cli_terminal_flags() {
+    let opt = opt_from(&["-i", "kernel.bin", "--terminal", "--echo", "--escape", "^]q", "/dev/tty"]);
+    assert!(opt.terminal);
+    assert!(opt.echo);
+    assert_eq!(opt.escape.sequence(), b"\x1dq");
+}
+
+#[test]
+fn cli_batch_flags() {
+    let opt = opt_from(&["-y", "kernel.img", "--ymodem", "init", "/dev/tty"]);
+    let names: Vec<_> = opt.batch.iter().map(|p| p.to_str().unwrap()).collect();
+    assert_eq!(names, ["kernel.img", "init"]);
+    assert!(opt.check_batch().is_ok());
+
+    assert!(opt_from(&["-y", "a", "-i", "b", "/dev/tty"]).check_batch().is_err());
+    assert!(opt_from(&["-y", "a", "-r", "/dev/tty"]).check_batch().is_err());
+}
+
+#[test]
+fn cli_rejects_bad_flags() {
+    opt_error(&[]);
+    opt_error(&["-w", "9", "/dev/tty"]);
+    opt_error(&["-s", "0", "/dev/tty"]);
+    opt_error(&["-f", "maybe", "/dev/tty"]);
+    opt_error(&["--parity", "space", "/dev/tty"]);
+    opt_error(&["-t", "soon", "/dev/tty"]);
+    opt_error(&["--escape", "", "/dev/tty"]);
+}
+
+#[test]
+fn mini_uart_accepts_defaults_and_fast_rates() {
+    assert_eq!(opt_from(&["/dev/tty"]).check_mini_uart(), Ok(()));
+    assert_eq!(opt_from(&["-b", "921600", "/dev/tty"]).check_mini_uart(), Ok(()));
+    assert_eq!(opt_from(&["--baud", "9600", "--parity", "none", "/dev/tty"]).check_mini_uart(), Ok(()));
+    assert_eq!(opt_from(&["-f", "hardware", "/dev/tty"]).check_mini_uart(), Ok(()));
+}
+
+#[test]
+fn mini_uart_rejects_unsupported_settings() {
+    let rejected = [
+        &["-b", "2000000", "/dev/tty"][..],
+        &["-b", "110", "/dev/tty"][..],
+        &["-w", "7", "/dev/tty"][..],
+        &["--parity", "even", "/dev/tty"][..],
+        &["-s", "2", "/dev/tty"][..],
+        &["--flow-control", "software", "/dev/tty"][..],
+    ];
+
+    for args in rejected.iter() {
+        assert!(opt_from(args).check_mini_uart().is_err(), "{:?} should be rejected", args);
+    }
+}
+
+#[test]
+fn send_raw() {
+    let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+    let mut port = io::Cursor::new(vec![]);
+    assert_eq!(send(&data[..], &mut port, true, MAX_RETRIES).expect("raw send"), 1000);
+    assert_eq!(port.into_inner(), data);
+}
+
+/// Receives a transfer over `port` like the bootloader does: verifies it
+/// against its trailer, corrupting it first if `damage` is `true`, and sends
+/// the verdict.
+fn receive_verified(mut port: Pipe, damage: bool) -> io::Result<Vec<u8>> {
+    let mut output = vec![];
+    Xmodem::receive(&mut port, &mut output)?;
+    if damage {
+        output[0] ^= 1;
+    }
+
+    trailer::write_verdict(&mut port, trailer::verify(&output).is_ok())?;
+    Ok(output)
+}
+
+#[test]
+fn send_xmodem_to_receiver() {
+    let data: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+    let expected = data.clone();
+
+    let (sender, receiver) = pipe();
+    let tx_thread = ::std::thread::spawn(move || send(&data[..], sender, false, MAX_RETRIES));
+    let rx_thread = ::std::thread::spawn(move || receive_verified(receiver, false));
+
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 1000);
+    let output = rx_thread.join().expect("rx join okay").expect("rx okay");
+    assert_eq!(output.len(), 1024 + 128, "data is padded to 128 bytes and has a trailer");
+    assert_eq!(&output[..1000], &expected[..]);
+    assert!(output[1000..1024].iter().all(|&b| b == 0));
+    assert_eq!(trailer::verify(&output), Ok(1000));
+}
+
+#[test]
+fn send_xmodem_fails_when_receiver_rejects_image() {
+    let (sender, receiver) = pipe();
+    let tx_thread = ::std::thread::spawn(move || send(&[7u8; 300][..], sender, false, MAX_RETRIES));
+    let rx_thread = ::std::thread::spawn(move || receive_verified(receiver, true));
+
+    let e = tx_thread.join().expect("tx join okay").expect_err("image rejected");
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    rx_thread.join().expect("rx join okay").expect("rx okay");
+}
+
+#[test]
+fn send_xmodem_fails_when_receiver_hangs_up() {
+    let (sender, receiver) = pipe();
+    drop(receiver);
+    assert!(send(&[1u8, 2, 3][..], sender, false, MAX_RETRIES).is_err());
+}
+
+#[test]
+fn send_batch_names_files() {
+    use std::fs;
+
+    let dir = ::std::env::temp_dir().join(format!("ttywrite-batch-{}", ::std::process::id()));
+    fs::create_dir_all(&dir).expect("temp dir");
+    let files = [("kernel.img", vec![3u8; 2000]), ("init", vec![])];
+    let paths: Vec<_> = files.iter().map(|&(name, ref data)| {
+        let path = dir.join(name);
+        fs::write(&path, data).expect("temp file");
+        path
+    }).collect();
+
+    let (sender, receiver) = pipe();
+    let tx_thread = ::std::thread::spawn(move || send_batch(&paths, sender, MAX_RETRIES));
+
+    let mut receiver = Xmodem::new(receiver);
+    let mut received: Vec<(String, Vec<u8>)> = vec![];
+    while let Some(header) = receiver.receive_header().expect("header okay") {
+        let mut output = vec![];
+        receiver.receive_file(&header, &mut output).expect("rx okay");
+        received.push((header.name().to_string(), output));
+    }
+
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 2000);
+    let expected: Vec<_> = files.iter().map(|&(n, ref d)| (n.to_string(), d.clone())).collect();
+    assert_eq!(received, expected);
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn parse_escape_sequences() {
+    let parse = |s| parse_escape(s).map(|e| e.sequence().to_vec());
+    assert_eq!(parse("^A^X"), Ok(vec![0x01, 0x18]));
+    assert_eq!(parse("^a^x"), Ok(vec![0x01, 0x18]));
+    assert_eq!(parse("^@^[^\\^]^^^_^?"), Ok(vec![0x00, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f, 0x7f]));
+    assert_eq!(parse("~."), Ok(vec![b'~', b'.']));
+
+    assert!(parse("").is_err());
+    assert!(parse("^").is_err());
+    assert!(parse("^1").is_err());
+    assert!(parse("é").is_err());
+}
+
+#[test]
+fn escape_round_trips_through_display() {
+    for s in &["^A^X", "~.", "^]^?q"] {
+        assert_eq!(&parse_escape(s).expect("valid").to_string(), s);
+    }
+}
+
+/// Feeds `input` to `escape`, returning the bytes passed through and the index
+/// of the byte that completed the sequence, if any.
+fn feed_all(escape: &mut Escape, input: &[u8]) -> (Vec<u8>, Option<usize>) {
+    let mut out = vec![];
+    for (i, &byte) in input.iter().enumerate() {
+        if escape.feed(byte, &mut out) {
+            return (out, Some(i));
+        }
+    }
+
+    (out, None)
+}
+
+#[test]
+fn escape_detects_sequence() {
+    let mut escape = Escape::new(vec![0x01, 0x18]);
+    assert_eq!(feed_all(&mut escape, b"ls\r\x01\x18rest"), (b"ls\r".to_vec(), Some(4)));
+
+    // Detection starts over afterwards.
+    assert_eq!(feed_all(&mut escape, b"\x01\x18"), (vec![], Some(1)));
+}
+
+#[test]
+fn escape_passes_through_false_starts() {
+    let mut escape = Escape::new(vec![0x01, 0x18]);
+    assert_eq!(feed_all(&mut escape, b"\x01a\x18"), (b"\x01a\x18".to_vec(), None));
+
+    let mut escape = Escape::new(vec![0x01, 0x18]);
+    assert_eq!(feed_all(&mut escape, b"\x01\x01\x18"), (vec![0x01], Some(2)));
+
+    let mut escape = Escape::new(b"aab".to_vec());
+    assert_eq!(feed_all(&mut escape, b"aaab"), (b"a".to_vec(), Some(3)));
+
+    let mut escape = Escape::new(b"abac".to_vec());
+    assert_eq!(feed_all(&mut escape, b"ababac"), (b"ab".to_vec(), Some(5)));
+}
+
+#[test]
+fn escape_holds_partial_match_until_flushed() {
+    let mut escape = Escape::new(b"~.".to_vec());
+    assert_eq!(feed_all(&mut escape, b"x~"), (b"x".to_vec(), None));
+    assert_eq!(escape.flush(), b"~");
+    assert_eq!(escape.flush(), b"");
+    assert_eq!(feed_all(&mut escape, b"."), (b".".to_vec(), None));
+}
+
+#[test]
+fn echo_log_timestamps_lines() {
+    use std::time::Duration;
+
+    let mut log = EchoLog::new(vec![]);
+    log.log(Duration::from_millis(5), b"boot").unwrap();
+    log.log(Duration::from_millis(1500), b"ing\r\n> ").unwrap();
+    log.log(Duration::new(12, 345_678_901), b"ls\r\n\r\nok\n").unwrap();
+
+    let expected = "[    0.005] booting\r\n\
+                    [    1.500] > ls\r\n\
+                    [   12.345] \r\n\
+                    [   12.345] ok\n";
+    assert_eq!(String::from_utf8(log.into_inner()).unwrap(), expected);
+}
+
+#[test]
+fn echo_log_file_name() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let now = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    assert_eq!(log_file_name(now), "ttywrite-1500000000.log");
+}
+
+#[cfg(feature = "tty-tests")]
+#[test]
+fn raw_mode_is_restored() {
+    use std::os::unix::io::AsRawFd;
+    use termios::Termios;
+    use terminal::RawMode;
+
+    let fd = io::stdin().as_raw_fd();
+    let before = Termios::from_fd(fd).expect("stdin is a terminal");
+    {
+        let _raw = RawMode::enable(fd).expect("raw mode");
+        assert_ne!(Termios::from_fd(fd).unwrap(), before);
+    }
+
+    assert_eq!(Termios::from_fd(fd).unwrap(), before);
+}
+
+#[test]
+fn progress_line_draws_a_bar() {
+    let half = progress_line(500, 1000, 0);
+    let bar = format!("[{}{}]", "#".repeat(BAR_WIDTH / 2), " ".repeat(BAR_WIDTH / 2));
+    assert!(half.starts_with(&bar), "{}", half);
+    assert!(half.ends_with(" 50% 500/1000 bytes"), "{}", half);
+
+    // Padding and the trailer don't take the bar past the end.
+    let done = progress_line(1152, 1000, 2);
+    assert!(done.starts_with(&format!("[{}]", "#".repeat(BAR_WIDTH))));
+    assert!(done.ends_with("100% 1000/1000 bytes, retries: 2"), "{}", done);
+
+    assert_eq!(progress_line(384, 0, 0), "384 bytes");
+    assert_eq!(progress_line(384, 0, 1), "384 bytes, retries: 1");
+}
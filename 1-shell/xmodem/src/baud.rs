@@ -0,0 +1,275 @@
+//! A preamble for agreeing on a faster baud rate before an XMODEM session.
+//!
+//! Both sides start at `DEFAULT_BAUD`. To change rates:
+//!
+//!   1. The host sends a `Frame::Request` at the default rate, switches to the
+//!      requested rate and waits for a `Frame::Ack`.
+//!   2. A device that hears a valid request for a rate it can produce switches
+//!      too, waits `SETTLE_MS` so that the host has finished switching, and
+//!      acknowledges at the new rate.
+//!   3. The host answers the acknowledgement with a `Frame::Confirm`.
+//!
+//! If the host doesn't hear the acknowledgement, it switches back to the
+//! default rate; so does the device if it doesn't hear the confirmation. A
+//! device that never hears a request never leaves the default rate.
+//!
+//! A frame is `FRAME_LEN` bytes:
+//!
+//! | offset | contents                                        |
+//! |--------|-------------------------------------------------|
+//! | 0      | `REQUEST_MAGIC`, `ACK_MAGIC` or `CONFIRM_MAGIC` |
+//! | 4      | the baud rate, little-endian `u32`              |
+//! | 8      | CRC-32 of bytes 0..8, little-endian             |
+//!
+//! Rates are limited to those the Raspberry Pi's mini UART can produce from
+//! its 250MHz clock to within `MAX_ERROR_PERMILLE`; see `divisor()`.
+
+use io;
+use crc32::crc32;
+use trailer::{read_u32, write_u32};
+
+/// The rate both sides use until they agree on another.
+pub const DEFAULT_BAUD: u32 = 115200;
+
+/// The length of a frame in bytes.
+pub const FRAME_LEN: usize = 12;
+
+/// Magic number opening a request frame.
+pub const REQUEST_MAGIC: [u8; 4] = *b"BAUD";
+
+/// Magic number opening an acknowledgement frame.
+pub const ACK_MAGIC: [u8; 4] = *b"BDOK";
+
+/// Magic number opening a confirmation frame.
+pub const CONFIRM_MAGIC: [u8; 4] = *b"BDGO";
+
+/// How long the device waits after switching rates before acknowledging, in
+/// milliseconds.
+pub const SETTLE_MS: u64 = 100;
+
+/// The clock the mini UART's baud rate is derived from, in Hz.
+pub const MINI_UART_CLOCK: u32 = 250_000_000;
+
+/// The largest difference between a requested rate and the rate the mini UART
+/// actually produces, in thousandths of the requested rate.
+pub const MAX_ERROR_PERMILLE: u32 = 20;
+
+/// The most bytes that are scanned for a frame before giving up.
+const MAX_NOISE: usize = 64;
+
+/// A negotiation frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Frame {
+    /// The host asks to switch to `.0` baud.
+    Request(u32),
+    /// The device has switched to `.0` baud.
+    Ack(u32),
+    /// The host heard the device at `.0` baud.
+    Confirm(u32),
+}
+
+/// The reasons a frame can fail to decode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The frame isn't `FRAME_LEN` bytes long.
+    Length(usize),
+    /// The frame doesn't start with a known magic number.
+    Magic,
+    /// The frame's CRC doesn't match its contents.
+    Checksum,
+    /// The frame names a rate the mini UART can't produce.
+    Unsupported(u32),
+}
+
+impl Frame {
+    /// Returns the `FRAME_LEN` byte encoding of this frame.
+    pub fn to_bytes(&self) -> [u8; FRAME_LEN] {
+        let (magic, baud) = match *self {
+            Frame::Request(baud) => (REQUEST_MAGIC, baud),
+            Frame::Ack(baud) => (ACK_MAGIC, baud),
+            Frame::Confirm(baud) => (CONFIRM_MAGIC, baud),
+        };
+
+        let mut bytes = [0u8; FRAME_LEN];
+        bytes[0..4].copy_from_slice(&magic);
+        write_u32(&mut bytes[4..8], baud);
+        let check = crc32(&bytes[0..8]);
+        write_u32(&mut bytes[8..12], check);
+        bytes
+    }
+
+    /// Decodes the frame in `bytes`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Error` describing the first problem found: the length, the
+    /// magic number, the CRC, and finally the rate, in that order.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Frame, Error> {
+        if bytes.len() != FRAME_LEN {
+            return Err(Error::Length(bytes.len()));
+        }
+
+        let baud = read_u32(&bytes[4..8]);
+        let frame = if bytes[0..4] == REQUEST_MAGIC {
+            Frame::Request(baud)
+        } else if bytes[0..4] == ACK_MAGIC {
+            Frame::Ack(baud)
+        } else if bytes[0..4] == CONFIRM_MAGIC {
+            Frame::Confirm(baud)
+        } else {
+            return Err(Error::Magic);
+        };
+
+        if read_u32(&bytes[8..12]) != crc32(&bytes[0..8]) {
+            return Err(Error::Checksum);
+        }
+
+        if divisor(baud).is_none() {
+            return Err(Error::Unsupported(baud));
+        }
+
+        Ok(frame)
+    }
+}
+
+/// Returns the value for the mini UART's `AUX_MU_BAUD` register that produces
+/// `baud`, or `None` if no value produces a rate within `MAX_ERROR_PERMILLE`
+/// of `baud`.
+///
+/// The mini UART runs at `MINI_UART_CLOCK / (8 * (divisor + 1))` baud.
+pub fn divisor(baud: u32) -> Option<u16> {
+    if baud == 0 {
+        return None;
+    }
+
+    let clock = MINI_UART_CLOCK as u64;
+    let baud = baud as u64;
+    let ticks = (clock + 4 * baud) / (8 * baud);
+    if ticks == 0 || ticks - 1 > u16::MAX as u64 {
+        return None;
+    }
+
+    let actual = clock / (8 * ticks);
+    let error = actual.abs_diff(baud);
+    if error * 1000 > baud * MAX_ERROR_PERMILLE as u64 {
+        return None;
+    }
+
+    Some((ticks - 1) as u16)
+}
+
+/// Asks the device at the other end of `port` to switch to `baud`, making up
+/// to `attempts` attempts. `set_baud` switches `port` itself to a new rate.
+/// `port` should be at `DEFAULT_BAUD` and its reads should time out; each
+/// attempt waits for the acknowledgement until a read times out.
+///
+/// Returns `true` if the device acknowledged `baud` and was sent the
+/// confirmation. Returns `false`, with `port` back at `DEFAULT_BAUD`, if no
+/// acknowledgement was heard. Asking for `DEFAULT_BAUD` itself always succeeds
+/// without sending anything.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if the mini UART can't produce
+/// `baud`. Returns any error from `set_baud`, or from reading or writing
+/// `port` other than a timeout.
+pub fn request<T, F>(port: &mut T, baud: u32, attempts: usize, mut set_baud: F) -> io::Result<bool>
+    where T: io::Read + io::Write, F: FnMut(&mut T, u32) -> io::Result<()>
+{
+    if divisor(baud).is_none() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "unsupported baud rate"));
+    }
+
+    if baud == DEFAULT_BAUD {
+        return Ok(true);
+    }
+
+    for _ in 0..attempts {
+        port.write_all(&Frame::Request(baud).to_bytes())?;
+        port.flush()?;
+        set_baud(port, baud)?;
+        if scan(port, |frame| frame == Frame::Ack(baud))?.is_some() {
+            port.write_all(&Frame::Confirm(baud).to_bytes())?;
+            port.flush()?;
+            return Ok(true);
+        }
+
+        set_baud(port, DEFAULT_BAUD)?;
+    }
+
+    Ok(false)
+}
+
+/// Listens on `port`, which should be at `DEFAULT_BAUD`, for a request to
+/// switch rates. If one arrives, switches with `set_baud`, acknowledges at the
+/// new rate and waits for the host's confirmation. `set_baud` must wait
+/// `SETTLE_MS` after switching.
+///
+/// Listening ends when a read from `port` times out or after a few dozen bytes
+/// without a request, so a device can alternate between listening and
+/// starting a transfer at the default rate.
+///
+/// Returns the new rate, or `None` if no request was heard or the switch
+/// wasn't confirmed. In the latter case `port` is switched back to
+/// `DEFAULT_BAUD`.
+///
+/// # Errors
+///
+/// Returns any error from `set_baud`, or from reading or writing `port` other
+/// than a timeout.
+pub fn answer<T, F>(port: &mut T, mut set_baud: F) -> io::Result<Option<u32>>
+    where T: io::Read + io::Write, F: FnMut(&mut T, u32) -> io::Result<()>
+{
+    let baud = match scan(port, is_request)? {
+        Some(Frame::Request(baud)) => baud,
+        _ => return Ok(None),
+    };
+
+    set_baud(port, baud)?;
+    port.write_all(&Frame::Ack(baud).to_bytes())?;
+    port.flush()?;
+    if scan(port, |frame| frame == Frame::Confirm(baud))?.is_some() {
+        return Ok(Some(baud));
+    }
+
+    set_baud(port, DEFAULT_BAUD)?;
+    Ok(None)
+}
+
+/// Returns `true` if `frame` is a request.
+fn is_request(frame: Frame) -> bool {
+    matches!(frame, Frame::Request(_))
+}
+
+/// Reads from `from` until the last `FRAME_LEN` bytes read are a valid frame
+/// for which `wanted` returns `true`, and returns that frame. Returns `None`
+/// if a read times out, `from` reaches end of file, or `MAX_NOISE` bytes go by
+/// without a wanted frame.
+fn scan<R, F>(from: &mut R, wanted: F) -> io::Result<Option<Frame>>
+    where R: io::Read, F: Fn(Frame) -> bool
+{
+    let mut window = [0u8; FRAME_LEN];
+    for read in 0..MAX_NOISE {
+        let mut byte = [0u8; 1];
+        match from.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        for i in 1..FRAME_LEN {
+            window[i - 1] = window[i];
+        }
+        window[FRAME_LEN - 1] = byte[0];
+
+        if read + 1 >= FRAME_LEN {
+            match Frame::from_bytes(&window) {
+                Ok(frame) if wanted(frame) => return Ok(Some(frame)),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(None)
+}
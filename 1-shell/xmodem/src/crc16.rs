@@ -0,0 +1,17 @@
+/// The CRC-16 polynomial used by XMODEM-CRC, as in CCITT.
+const POLYNOMIAL: u16 = 0x1021;
+
+/// Returns the CRC-16 of `data` as XMODEM-CRC computes it: MSB first, starting
+/// from zero, with no final inversion.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            let mask = (crc >> 15).wrapping_neg();
+            crc = (crc << 1) ^ (POLYNOMIAL & mask);
+        }
+    }
+
+    crc
+}
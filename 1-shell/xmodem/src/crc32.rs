@@ -0,0 +1,42 @@
+/// The reflected CRC-32 polynomial used by zlib, PNG, and Ethernet.
+const POLYNOMIAL: u32 = 0xEDB8_8320;
+
+/// An incremental CRC-32 (IEEE 802.3) computation.
+#[derive(Debug, Copy, Clone)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    /// Returns the CRC-32 of no data.
+    pub fn new() -> Crc32 {
+        Crc32(!0)
+    }
+
+    /// Adds `data` to the checksummed data.
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.0 ^= byte as u32;
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (POLYNOMIAL & mask);
+            }
+        }
+    }
+
+    /// Returns the CRC-32 of the data added so far.
+    pub fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Crc32 {
+        Crc32::new()
+    }
+}
+
+/// Returns the CRC-32 of `data`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
@@ -0,0 +1,154 @@
+//! The I/O traits the XMODEM implementation is generic over.
+//!
+//! With the `std` feature (enabled by default) these are `std::io`'s own
+//! traits and types, so any `std::io::Read + std::io::Write` transport can be
+//! used directly. Without it, a minimal `core`-only equivalent is provided for
+//! bare-metal users with no `std` at all.
+
+#[cfg(feature = "std")]
+pub use std::io::{Read, Write, Error, ErrorKind, Result};
+
+#[cfg(not(feature = "std"))]
+pub use self::core_io::*;
+
+#[cfg(not(feature = "std"))]
+mod core_io {
+    use core::{fmt, result};
+
+    /// The kinds of errors the XMODEM implementation produces or inspects.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum ErrorKind {
+        UnexpectedEof,
+        InvalidData,
+        InvalidInput,
+        Interrupted,
+        ConnectionAborted,
+        BrokenPipe,
+        TimedOut,
+        WouldBlock,
+        WriteZero,
+        Other,
+    }
+
+    /// An I/O error: an `ErrorKind` and a static description.
+    #[derive(Debug)]
+    pub struct Error {
+        kind: ErrorKind,
+        msg: &'static str,
+    }
+
+    impl Error {
+        /// Creates a new error of kind `kind` described by `msg`.
+        pub fn new(kind: ErrorKind, msg: &'static str) -> Error {
+            Error { kind, msg }
+        }
+
+        /// Returns the kind of this error.
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+    }
+
+    impl From<ErrorKind> for Error {
+        fn from(kind: ErrorKind) -> Error {
+            Error::new(kind, "")
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{:?}: {}", self.kind, self.msg)
+        }
+    }
+
+    pub type Result<T> = result::Result<T, Error>;
+
+    /// A source of bytes.
+    pub trait Read {
+        /// Reads at most `buf.len()` bytes into `buf`, returning the number
+        /// of bytes read. `Ok(0)` indicates end of file.
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+        /// Reads exactly `buf.len()` bytes into `buf`.
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.read(buf) {
+                    Ok(0) => break,
+                    Ok(n) => { let tmp = buf; buf = &mut tmp[n..]; }
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+
+            if !buf.is_empty() {
+                Err(Error::new(ErrorKind::UnexpectedEof, "failed to fill whole buffer"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    /// A sink for bytes.
+    pub trait Write {
+        /// Writes at most `buf.len()` bytes from `buf`, returning the number
+        /// of bytes written.
+        fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+        /// Ensures all buffered bytes reach their destination.
+        fn flush(&mut self) -> Result<()>;
+
+        /// Writes all of `buf`.
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.write(buf) {
+                    Ok(0) => return Err(Error::new(ErrorKind::WriteZero,
+                                                   "failed to write whole buffer")),
+                    Ok(n) => buf = &buf[n..],
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    impl<'a, R: Read + ?Sized> Read for &'a mut R {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            (**self).read(buf)
+        }
+    }
+
+    impl<'a, W: Write + ?Sized> Write for &'a mut W {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            (**self).write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            (**self).flush()
+        }
+    }
+
+    impl<'a> Read for &'a [u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let n = ::core::cmp::min(buf.len(), self.len());
+            buf[..n].copy_from_slice(&self[..n]);
+            *self = &self[n..];
+            Ok(n)
+        }
+    }
+
+    impl<'a> Write for &'a mut [u8] {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            let n = ::core::cmp::min(buf.len(), self.len());
+            let (head, tail) = ::core::mem::replace(self, &mut []).split_at_mut(n);
+            head.copy_from_slice(&buf[..n]);
+            *self = tail;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+}
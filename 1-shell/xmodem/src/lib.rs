@@ -1,24 +1,69 @@
-use std::io;
-
-#[cfg(test)] mod tests;
+//! An implementation of the XMODEM file transfer protocol.
+//!
+//! The implementation is generic over any transport implementing `io::Read`
+//! and `io::Write`. With the `std` feature (the default), these are
+//! `std::io`'s traits; without it the crate is `no_std` and uses the minimal
+//! equivalents in [`io`]. Whole-file transfers are provided by
+//! `Xmodem::transmit` and `Xmodem::receive`; callers that need to pace a
+//! transfer themselves can drive `read_packet` and `write_packet` directly.
+//!
+//! Besides classic XMODEM's 128-byte packets with a one byte checksum, the
+//! XMODEM-CRC and XMODEM-1K extensions are supported. The receiver picks the
+//! check: it starts the transfer with `C` to ask for a CRC-16 instead of `NAK`
+//! (see `Xmodem::set_crc()`). Once CRC-16 is in use, a transmitter can send
+//! 1024-byte packets, which start with `STX` rather than `SOH` (see
+//! `Xmodem::set_1k_blocks()`). Receivers accept either packet size. YMODEM
+//! batches of named files are built on top in [`ymodem`].
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(all(test, feature = "std"))] mod tests;
 mod read_ext;
 mod progress;
-
-pub use progress::{Progress, ProgressFn};
+pub mod io;
+pub mod crc16;
+pub mod crc32;
+pub mod trailer;
+pub mod ymodem;
+pub mod baud;
+
+pub use progress::{Progress, ProgressFn, Summary};
+pub use crc16::crc16;
+pub use crc32::{Crc32, crc32};
+pub use trailer::Trailer;
 
 use read_ext::ReadExt;
 
 const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
 const EOT: u8 = 0x04;
 const ACK: u8 = 0x06;
 const NAK: u8 = 0x15;
 const CAN: u8 = 0x18;
+const CRC: u8 = b'C';
+
+/// The number of times a receiver asks for CRC-16 with `C` before falling back
+/// to checksums with `NAK`, for transmitters that don't support CRC-16 and
+/// ignore the `C`.
+const CRC_REQUESTS: usize = 3;
+
+/// The default number of consecutive failed attempts at a packet before a
+/// transfer is aborted. The transmitter and the receiver use the same default;
+/// see `Xmodem::set_max_retries()` to change it.
+pub const MAX_RETRIES: usize = 10;
 
 /// Implementation of the XMODEM protocol.
 pub struct Xmodem<R> {
     packet: u8,
     inner: R,
     started: bool,
+    heard: bool,
+    crc: bool,
+    crc_requests: usize,
+    large_blocks: bool,
+    bytes: usize,
+    max_retries: usize,
+    summary: Summary,
     progress: ProgressFn
 }
 
@@ -43,34 +88,32 @@ impl Xmodem<()> {
     /// the transmission. See the [`Progress`] enum for more information.
     ///
     /// Returns the number of bytes written to `to`, excluding padding zeroes.
-    pub fn transmit_with_progress<R, W>(mut data: R, to: W, f: ProgressFn) -> io::Result<usize>
+    pub fn transmit_with_progress<R, W>(data: R, to: W, f: ProgressFn) -> io::Result<usize>
         where W: io::Read + io::Write, R: io::Read
     {
-        let mut transmitter = Xmodem::new_with_progress(to, f);
-        let mut packet = [0u8; 128];
-        let mut written = 0;
-        'next_packet: loop {
-            let n = data.read_max(&mut packet)?;
-            packet[n..].iter_mut().for_each(|b| *b = 0);
-
-            if n == 0 {
-                transmitter.write_packet(&[])?;
-                return Ok(written);
-            }
-
-            for _ in 0..10 {
-                match transmitter.write_packet(&packet) {
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                    Err(e) => return Err(e),
-                    Ok(_) => {
-                        written += n;
-                        continue 'next_packet;
-                    }
-                }
-            }
+        Xmodem::new_with_progress(to, f).transmit_all(data)
+    }
 
-            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "bad transmit"));
-        }
+    /// Transmits `data` to the receiver `to` like [`Xmodem::transmit()`], then
+    /// sends a [`trailer`] packet with the length and CRC-32 of `data` before
+    /// ending the transmission. Once the transmission has ended, waits for the
+    /// receiver's verdict on the trailer.
+    ///
+    /// The function `f` is used as a callback to indicate progress throughout
+    /// the transmission. See the [`Progress`] enum for more information.
+    ///
+    /// Returns the number of bytes written to `to`, excluding padding zeroes
+    /// and the trailer.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors of `transmit()`, returns an error of kind
+    /// `InvalidData` if the receiver rejects the image. An error reading the
+    /// verdict, such as a timeout, is returned as is.
+    pub fn transmit_verified_with_progress<R, W>(data: R, to: W, f: ProgressFn) -> io::Result<usize>
+        where W: io::Read + io::Write, R: io::Read
+    {
+        Xmodem::new_with_progress(to, f).transmit_all_verified(data)
     }
 
     /// Receives `data` from `from` using the XMODEM protocol and writes it into
@@ -85,32 +128,16 @@ impl Xmodem<()> {
     /// Receives `data` from `from` using the XMODEM protocol and writes it into
     /// `into`. Returns the number of bytes read from `from`, a multiple of 128.
     ///
+    /// If writing a packet to `into` fails, for instance because `into` is a
+    /// slice that is already full, the transfer is cancelled and the write
+    /// error is returned.
+    ///
     /// The function `f` is used as a callback to indicate progress throughout
     /// the reception. See the [`Progress`] enum for more information.
-    pub fn receive_with_progress<R, W>(from: R, mut into: W, f: ProgressFn) -> io::Result<usize>
+    pub fn receive_with_progress<R, W>(from: R, into: W, f: ProgressFn) -> io::Result<usize>
        where R: io::Read + io::Write, W: io::Write
     {
-        let mut receiver = Xmodem::new_with_progress(from, f);
-        let mut packet = [0u8; 128];
-        let mut received = 0;
-        'next_packet: loop {
-            for _ in 0..10 {
-                match receiver.read_packet(&mut packet) {
-                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                    Err(e) => return Err(e),
-                    Ok(0) => break 'next_packet,
-                    Ok(n) => {
-                        received += n;
-                        into.write_all(&packet)?;
-                        continue 'next_packet;
-                    }
-                }
-            }
-
-            return Err(io::Error::new(io::ErrorKind::BrokenPipe, "bad receive"));
-        }
-
-        Ok(received)
+        Xmodem::new_with_progress(from, f).receive_all(into)
     }
 }
 
@@ -119,7 +146,7 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// `inner`. The returned instance can be used for both receiving
     /// (downloading) and sending (uploading).
     pub fn new(inner: T) -> Self {
-        Xmodem { packet: 1, started: false, inner, progress: progress::noop}
+        Xmodem::new_with_progress(inner, progress::noop)
     }
 
     /// Returns a new `Xmodem` instance with the internal reader/writer set to
@@ -128,7 +155,196 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// callback to indicate progress throughout the transfer. See the
     /// [`Progress`] enum for more information.
     pub fn new_with_progress(inner: T, f: ProgressFn) -> Self {
-        Xmodem { packet: 1, started: false, inner, progress: f }
+        Xmodem {
+            packet: 1,
+            started: false,
+            heard: false,
+            crc: false,
+            crc_requests: 0,
+            large_blocks: false,
+            bytes: 0,
+            max_retries: MAX_RETRIES,
+            summary: Summary::default(),
+            inner,
+            progress: f
+        }
+    }
+
+    /// Sets the number of consecutive failed attempts at a single packet after
+    /// which `transmit_all()` and `receive_all()` give up. Defaults to
+    /// `MAX_RETRIES`. Successfully transferring a packet resets the count.
+    pub fn set_max_retries(&mut self, retries: usize) {
+        self.max_retries = retries;
+    }
+
+    /// Sets whether a receiver asks for packets checked with a CRC-16 rather
+    /// than a checksum. If the transmitter doesn't answer the request, the
+    /// receiver falls back to checksums. Defaults to `false`. Transmitters use
+    /// whichever check the receiver asks for regardless of this setting.
+    pub fn set_crc(&mut self, enabled: bool) {
+        self.crc = enabled;
+    }
+
+    /// Sets whether a transmitter sends data in 1024-byte packets when the
+    /// receiver asks for CRC-16. Only full 1024-byte blocks are sent that way;
+    /// the rest of the data goes in 128-byte packets, so that the padding at
+    /// the end of a transfer stays under 128 bytes. Defaults to `false`.
+    pub fn set_1k_blocks(&mut self, enabled: bool) {
+        self.large_blocks = enabled;
+    }
+
+    /// Returns the counts of packets and errors for the transfer so far. The
+    /// same summary is passed to the progress callback as
+    /// `Progress::Finished` when the transfer ends.
+    pub fn summary(&self) -> Summary {
+        self.summary
+    }
+
+    /// Transmits all of `data` like [`Xmodem::transmit()`], retrying each
+    /// packet up to the configured number of times.
+    ///
+    /// Returns the number of bytes written, excluding padding zeroes.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error that can't be retried. If a packet fails too
+    /// many times in a row, returns an error of kind `TimedOut` if the last
+    /// attempt timed out and `BrokenPipe` otherwise.
+    pub fn transmit_all<R: io::Read>(&mut self, data: R) -> io::Result<usize> {
+        self.transmit_packets(data, false)
+    }
+
+    /// Transmits all of `data` like [`Xmodem::transmit_verified_with_progress()`],
+    /// retrying each packet up to the configured number of times.
+    ///
+    /// Returns the number of bytes written, excluding padding zeroes and the
+    /// trailer.
+    ///
+    /// # Errors
+    ///
+    /// In addition to the errors of `transmit_all()`, returns an error of kind
+    /// `InvalidData` if the receiver rejects the image.
+    pub fn transmit_all_verified<R: io::Read>(&mut self, data: R) -> io::Result<usize> {
+        let written = self.transmit_packets(data, true)?;
+        if !trailer::read_verdict(&mut self.inner)? {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Receiver rejected the image"));
+        }
+
+        Ok(written)
+    }
+
+    /// Sends `data`, followed by a trailer packet if `with_trailer` is `true`,
+    /// and ends the transmission.
+    fn transmit_packets<R: io::Read>(&mut self, mut data: R, with_trailer: bool) -> io::Result<usize> {
+        self.wait_for_start()?;
+        let block = if self.crc && self.large_blocks { 1024 } else { 128 };
+
+        let mut buffer = [0u8; 1024];
+        let mut written = 0;
+        let mut crc = Crc32::new();
+        loop {
+            let n = data.read_max(&mut buffer[..block])?;
+            if n == 0 {
+                break;
+            }
+
+            crc.update(&buffer[..n]);
+            let size = if n == 1024 { 1024 } else { 128 };
+            let padded = n.div_ceil(size) * size;
+            buffer[n..padded].iter_mut().for_each(|b| *b = 0);
+            for packet in buffer[..padded].chunks(size) {
+                self.write_packet_retrying(packet)?;
+            }
+
+            written += n;
+        }
+
+        if with_trailer {
+            let trailer = Trailer { length: written as u32, crc: crc.finish() };
+            self.write_packet_retrying(&trailer.to_packet())?;
+        }
+
+        self.write_packet(&[])?;
+        Ok(written)
+    }
+
+    /// Sends `packet`, retrying up to the configured number of times.
+    fn write_packet_retrying(&mut self, packet: &[u8]) -> io::Result<()> {
+        let mut timed_out = false;
+        for _ in 0..self.max_retries {
+            let timeouts = self.summary.timeouts;
+            match self.write_packet(packet) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {
+                    timed_out = self.summary.timeouts != timeouts;
+                    continue
+                }
+                Err(e) => return Err(e),
+                Ok(_) => return Ok(()),
+            }
+        }
+
+        Err(retries_exhausted(timed_out, "bad transmit"))
+    }
+
+    /// Receives a whole transfer like [`Xmodem::receive_with_progress()`] and
+    /// writes it into `into`, retrying each packet up to the configured number
+    /// of times.
+    ///
+    /// Returns the number of bytes received, a multiple of 128.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error that can't be retried. If a packet fails too
+    /// many times in a row, returns an error of kind `TimedOut` if the last
+    /// attempt timed out and `BrokenPipe` otherwise.
+    pub fn receive_all<W: io::Write>(&mut self, mut into: W) -> io::Result<usize> {
+        let mut packet = [0u8; 1024];
+        let mut received = 0;
+        loop {
+            let n = self.read_packet_retrying(&mut packet)?;
+            if n == 0 {
+                return Ok(received);
+            }
+
+            received += n;
+            if let Err(e) = into.write_all(&packet[..n]) {
+                // The sender may hang up on the first `CAN`; the write error
+                // is what the caller needs to see.
+                let _ = self.cancel();
+                return Err(e);
+            }
+        }
+    }
+
+    /// Reads the next packet into `buf`, retrying up to the configured number
+    /// of times. Returns the size of the packet, or 0 at the end of the
+    /// transmission.
+    fn read_packet_retrying(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut timed_out = false;
+        for _ in 0..self.max_retries {
+            let timeouts = self.summary.timeouts;
+            match self.read_packet(buf) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {
+                    timed_out = self.summary.timeouts != timeouts;
+                    continue
+                }
+                result => return result,
+            }
+        }
+
+        Err(retries_exhausted(timed_out, "bad receive"))
+    }
+
+    /// Cancels the transfer by sending `CAN` to the other side. Two `CAN`
+    /// bytes are sent, as some implementations require.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing to the inner stream fails.
+    pub fn cancel(&mut self) -> io::Result<()> {
+        self.write_byte(CAN)?;
+        self.write_byte(CAN)?;
+        self.inner.flush()
     }
 
     /// Reads a single byte from the inner I/O stream. If `abort_on_can` is
@@ -202,12 +418,52 @@ impl<T: io::Read + io::Write> Xmodem<T> {
         }
     }
 
+    /// Discards incoming bytes until the line has been idle for a guard time,
+    /// so that a retransmitted packet isn't read from the middle of the
+    /// remains of a damaged one. The guard time is the inner stream's read
+    /// timeout: draining ends when a read fails with `TimedOut` or
+    /// `WouldBlock`, or at end of file.
+    ///
+    /// # Errors
+    ///
+    /// Returns any other error from reading the inner stream.
+    fn drain(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 1];
+        loop {
+            match self.inner.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(_) => continue,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut
+                    || e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Asks the sender to send the current packet again: drains the line,
+    /// sends `NAK` and returns an error of kind `Interrupted` with the message
+    /// `msg`.
+    fn request_retransmit(&mut self, msg: &'static str) -> io::Result<usize> {
+        self.drain()?;
+        self.write_byte(NAK)?;
+        Err(io::Error::new(io::ErrorKind::Interrupted, msg))
+    }
+
     /// Reads (downloads) a single packet from the inner stream using the XMODEM
-    /// protocol. On success, returns the number of bytes read (always 128).
+    /// protocol into the start of `buf`. On success, returns the number of
+    /// bytes read: 128, or 1024 for a 1K packet.
+    ///
+    /// The first call starts the transfer by sending `C` if CRC-16 was asked
+    /// for with `set_crc()`, or `NAK` otherwise. If the sender doesn't answer a
+    /// `C` before the read times out, an error of kind `Interrupted` is
+    /// returned and the next call asks again, `CRC_REQUESTS` times in all
+    /// before falling back to checksums with `NAK`.
     ///
     /// The progress callback is called with `Progress::Start` when reception
-    /// for the first packet has started and subsequently with
-    /// `Progress::Packet` when a packet is received successfully.
+    /// for the first packet has started, subsequently with `Progress::Packet`
+    /// when a packet is received successfully and with `Progress::Finished`
+    /// when the transmission ends.
     ///
     /// # Errors
     ///
@@ -215,58 +471,179 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// point. Also returns an error if the XMODEM protocol indicates an error.
     /// In particular, an `InvalidData` error is returned when:
     ///
-    ///   * The sender's first byte for a packet isn't `EOT` or `SOH`.
+    ///   * The sender's first byte isn't `EOT`, `SOH` or `STX`.
     ///   * The sender doesn't send a second `EOT` after the first.
-    ///   * The received packet numbers don't match the expected values.
+    ///   * The packet number is neither the expected one nor the previous one.
+    ///   * The packet is a 1K packet and `buf` is shorter than 1024 bytes.
+    ///
+    /// An error of kind `Interrupted` is returned, after sending `NAK`, if a
+    /// packet is damaged: its checksum or CRC fails, its packet number doesn't
+    /// match its complement, or the line goes quiet partway through it. The
+    /// line is drained before the `NAK` so the retransmission starts cleanly.
+    /// Once the sender has been heard from, a packet that starts with a byte
+    /// other than `EOT`, `SOH` or `STX` is handled the same way, and so is
+    /// timing out waiting for a packet, since the sender may be waiting on a
+    /// lost `ACK`.
     ///
-    /// An error of kind `Interrupted` is returned if a packet checksum fails.
+    /// An error of kind `Interrupted` is also returned, after sending `ACK`,
+    /// if the packet is a retransmission of the previous one. Its contents are
+    /// left in `buf` but must not be used.
     ///
     /// An error of kind `ConnectionAborted` is returned if a `CAN` byte is
     /// received when not expected.
     ///
     /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128`.
     pub fn read_packet(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        if buf.len() != 128 {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Packet not length 128"));
+        if buf.len() < 128 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Packet buffer too short"));
         }
 
         if !self.started {
-            self.write_byte(NAK)?;
+            let start = if self.crc { CRC } else { NAK };
+            self.write_byte(start)?;
             self.started = true;
             (self.progress)(Progress::Started);
         }
 
-        let header: u8 = self.read_byte(true)?;
-        match header {
-            SOH => {
-                let expected_packet_number: u8 = self.packet;
-                self.expect_byte_or_cancel(expected_packet_number, "Invalid packet number")?;
-                self.expect_byte_or_cancel(255 - expected_packet_number, "Invalid packet number")?;
-                self.inner.read_exact(buf)?;
-
-                let checksum: u8 = buf.iter().fold(0, |acc, &x| {
-                    acc.wrapping_add(x)
-                });
-                if checksum != self.read_byte(false)? {
-                    self.write_byte(NAK)?;
-                    Err(io::Error::new(io::ErrorKind::Interrupted, "Checksum failed"))
+        let header: u8 = match self.read_byte(true) {
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut && self.heard => {
+                self.summary.timeouts += 1;
+                return self.request_retransmit("Timed out waiting for packet");
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut && self.crc => {
+                // The sender may not support CRC-16 and be waiting for a NAK.
+                self.summary.timeouts += 1;
+                self.crc_requests += 1;
+                if self.crc_requests < CRC_REQUESTS {
+                    self.write_byte(CRC)?;
                 } else {
-                    self.packet = self.packet.wrapping_add(1);
-                    self.write_byte(ACK)?;
-                    (self.progress)(Progress::Packet(expected_packet_number));
-                    Ok(128)
+                    self.crc = false;
+                    self.write_byte(NAK)?;
                 }
+
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "Timed out waiting for start"));
             }
+            result => result?,
+        };
+
+        let size = match header {
+            SOH => 128,
+            STX => 1024,
             EOT => {
                 self.write_byte(NAK)?;
-                self.expect_byte(EOT, "Expected second EOT byte to end transmission")?;
+                // Anything but a second EOT means the two sides disagree about
+                // where the transfer is; there is nothing to retry.
+                self.expect_byte_or_cancel(EOT, "Expected second EOT byte to end transmission")?;
                 self.write_byte(ACK)?;
-                Ok(0)
+                (self.progress)(Progress::Finished(self.summary));
+                return Ok(0);
+            }
+            // Once the sender has been heard from, anything else is a
+            // damaged header or line noise, and the packet is sent again.
+            _ if self.heard => {
+                self.summary.naks += 1;
+                return self.request_retransmit("Expected EOT, SOH or STX to start packet");
             }
             _ => {
-                Err(io::Error::new(io::ErrorKind::InvalidData, "Expected EOT or SOH to start packet"))
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "Expected EOT, SOH or STX to start packet"));
+            }
+        };
+
+        self.heard = true;
+
+        if buf.len() < size {
+            self.write_byte(CAN)?;
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "1K packet doesn't fit in buffer"));
+        }
+
+        let buf = &mut buf[..size];
+        let mut numbers = [0u8; 2];
+        let mut check = [0u8; 2];
+        let check_len = if self.crc { 2 } else { 1 };
+        let body = self.inner.read_exact(&mut numbers)
+            .and_then(|_| self.inner.read_exact(buf))
+            .and_then(|_| self.inner.read_exact(&mut check[..check_len]));
+        match body {
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                self.summary.timeouts += 1;
+                return self.request_retransmit("Timed out partway through packet");
             }
+            result => result?,
+        }
+
+        if numbers[0] != 255 - numbers[1] {
+            self.summary.naks += 1;
+            return self.request_retransmit("Packet number corrupted");
+        }
+
+        let valid = if self.crc {
+            crc16(buf) == (check[0] as u16) << 8 | check[1] as u16
+        } else {
+            checksum(buf) == check[0]
+        };
+
+        if !valid {
+            self.summary.naks += 1;
+            return self.request_retransmit("Checksum failed");
+        }
+
+        let expected_packet_number: u8 = self.packet;
+        if numbers[0] == expected_packet_number {
+            self.write_byte(ACK)?;
+            self.packet_done(size);
+            Ok(size)
+        } else if numbers[0] == expected_packet_number.wrapping_sub(1)
+            && self.summary.packets > 0 {
+            // Our ACK for it was lost; acknowledge it again.
+            self.summary.duplicates += 1;
+            self.write_byte(ACK)?;
+            Err(io::Error::new(io::ErrorKind::Interrupted, "Duplicate packet"))
+        } else {
+            self.write_byte(CAN)?;
+            Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid packet number"))
+        }
+    }
+
+    /// Counts the current packet, `len` bytes long, as transferred, reports it
+    /// to the progress callback and moves on to the next packet number.
+    fn packet_done(&mut self, len: usize) {
+        let number = self.packet;
+        self.packet = self.packet.wrapping_add(1);
+        self.summary.packets += 1;
+        self.bytes += len;
+        (self.progress)(Progress::Packet {
+            number,
+            bytes: self.bytes,
+            retries: self.summary.errors(),
+        });
+    }
+
+    /// Waits for the receiver to start the transmission, unless it already
+    /// has, and takes up the check it asks for: `NAK` asks for checksums and
+    /// `C` for CRC-16.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidData` if the receiver's first byte is
+    /// neither `NAK` nor `C`.
+    fn wait_for_start(&mut self) -> io::Result<()> {
+        if self.started {
+            return Ok(());
         }
+
+        (self.progress)(Progress::Waiting);
+        self.crc = match self.read_byte(true)? {
+            NAK => false,
+            CRC => true,
+            _ => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "Expected NAK or 'C' from receiver to indicate start"));
+            }
+        };
+
+        self.started = true;
+        Ok(())
     }
 
     /// Sends (uploads) a single packet to the inner stream using the XMODEM
@@ -275,10 +652,16 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// transmission is complete. On success, returns the number of bytes
     /// written.
     ///
+    /// A 128-byte `buf` is sent in a classic packet and a 1024-byte `buf` in a
+    /// 1K packet, which receivers that don't support XMODEM-1K will reject. The
+    /// packet is checked with a checksum or a CRC-16, as the receiver asked
+    /// when it started the transmission.
+    ///
     /// The progress callback is called with `Progress::Waiting` before waiting
-    /// for the receiver's `NAK`, `Progress::Start` when transmission of the
-    /// first packet has started and subsequently with `Progress::Packet` when a
-    /// packet is sent successfully.
+    /// for the receiver's `NAK` or `C`, `Progress::Start` when transmission of
+    /// the first packet has started, subsequently with `Progress::Packet` when
+    /// a packet is sent successfully and with `Progress::Finished` when the
+    /// transmission ends.
     ///
     /// # Errors
     ///
@@ -286,54 +669,73 @@ impl<T: io::Read + io::Write> Xmodem<T> {
     /// point. Also returns an error if the XMODEM protocol indicates an error.
     /// In particular, an `InvalidData` error is returned when:
     ///
-    ///   * The receiver's first byte isn't a `NAK`.
+    ///   * The receiver's first byte isn't a `NAK` or `C`.
     ///   * The receiver doesn't respond with a `NAK` to the first `EOT`.
     ///   * The receiver doesn't respond with an `ACK` to the second `EOT`.
     ///   * The receiver responds to a complete packet with something besides
     ///     `ACK` or `NAK`.
     ///
-    /// An error of kind `UnexpectedEof` is returned if `buf.len() < 128 &&
-    /// buf.len() != 0`.
+    /// An error of kind `UnexpectedEof` is returned if `buf.len()` isn't 0,
+    /// 128 or 1024.
     ///
     /// An error of kind `ConnectionAborted` is returned if a `CAN` byte is
     /// received when not expected.
     ///
-    /// An error of kind `Interrupted` is returned if a packet checksum fails.
+    /// An error of kind `Interrupted` is returned if the receiver `NAK`s the
+    /// packet or if reading its response times out. Until the first packet is
+    /// acknowledged, a `C` in response is taken as a `NAK`: it is a request
+    /// to start that was repeated before the packet arrived.
     pub fn write_packet(&mut self, buf: &[u8]) -> io::Result<usize> {
-        if buf.len() != 128 && buf.len() != 0 {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Invalid packet length"));
-        }
-
-        if !self.started {
-            (self.progress)(Progress::Waiting);
-            self.expect_byte(NAK, "Expected NAK from receiver to indicate start")?;
-            self.started = true;
-        }
-
-        if buf.len() == 0 {
+        let header = match buf.len() {
+            0 => EOT,
+            128 => SOH,
+            1024 => STX,
+            _ => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Invalid packet length")),
+        };
+
+        self.wait_for_start()?;
+        if buf.is_empty() {
             self.write_byte(EOT)?;
             self.expect_byte(NAK, "Expected NAK after EOT")?;
             self.write_byte(EOT)?;
             self.expect_byte(ACK, "Expected ACK after second EOT")?;
+            (self.progress)(Progress::Finished(self.summary));
             Ok(0)
         } else {
             let packet_number: u8 = self.packet;
 
-            self.write_byte(SOH)?;
+            self.write_byte(header)?;
             self.write_byte(packet_number)?;
             self.write_byte(255 - packet_number)?;
-            self.inner.write(buf)?;
-
-            let checksum: u8 = buf.iter().fold(0, |acc, &x| {
-                acc.wrapping_add(x)
-            });
-            self.write_byte(checksum)?;
-
-            self.expect_byte(ACK, "Expected ACK after sending packet")?;
-            (self.progress)(Progress::Packet(packet_number));
+            self.inner.write_all(buf)?;
+            if self.crc {
+                let crc = crc16(buf);
+                self.inner.write_all(&[(crc >> 8) as u8, crc as u8])?;
+            } else {
+                self.write_byte(checksum(buf))?;
+            }
 
-            self.packet = self.packet.wrapping_add(1);
-            Ok(128)
+            match self.read_byte(true) {
+                Ok(ACK) => {
+                    self.packet_done(buf.len());
+                    Ok(buf.len())
+                }
+                Ok(NAK) => {
+                    self.summary.naks += 1;
+                    Err(io::Error::new(io::ErrorKind::Interrupted, "Receiver NAKed packet"))
+                }
+                Ok(CRC) if self.crc && self.summary.packets == 0 => {
+                    self.summary.naks += 1;
+                    Err(io::Error::new(io::ErrorKind::Interrupted, "Receiver asked to start again"))
+                }
+                Ok(_) => Err(io::Error::new(io::ErrorKind::InvalidData,
+                                            "Expected ACK or NAK after sending packet")),
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {
+                    self.summary.timeouts += 1;
+                    Err(io::Error::new(io::ErrorKind::Interrupted, "Timed out waiting for ACK"))
+                }
+                Err(e) => Err(e),
+            }
         }
     }
 
@@ -348,3 +750,18 @@ impl<T: io::Read + io::Write> Xmodem<T> {
         self.inner.flush()
     }
 }
+
+/// Returns the error for a packet that failed too many times in a row. If the
+/// last attempt timed out, the other side has most likely gone away.
+fn retries_exhausted(timed_out: bool, msg: &'static str) -> io::Error {
+    if timed_out {
+        io::Error::new(io::ErrorKind::TimedOut, msg)
+    } else {
+        io::Error::new(io::ErrorKind::BrokenPipe, msg)
+    }
+}
+
+/// Returns the classic XMODEM checksum of `data`: the sum of its bytes.
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |acc, &x| acc.wrapping_add(x))
+}
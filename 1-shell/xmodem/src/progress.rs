@@ -10,8 +10,35 @@ pub enum Progress {
     Waiting,
     /// Download/upload has started.
     Started,
-    /// Packet `.0` was transmitted/received.
-    Packet(u8),
+    /// Packet `number` was transmitted/received. `bytes` counts the data
+    /// transferred so far, padding included, and `retries` the errors
+    /// recovered from so far, as in `Summary::errors()`.
+    Packet { number: u8, bytes: usize, retries: usize },
+    /// Download/upload has ended; `.0` counts the errors recovered from.
+    Finished(Summary),
+}
+
+/// The number of packets transferred and of errors recovered from during one
+/// transfer, as seen from one side of it.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Summary {
+    /// Packets transferred successfully, not counting retransmissions.
+    pub packets: usize,
+    /// Packets rejected with a `NAK`: sent by the receiver for a damaged
+    /// packet, or received by the sender for any reason.
+    pub naks: usize,
+    /// Times a side timed out waiting for the other and retried.
+    pub timeouts: usize,
+    /// Retransmissions of an already received packet that were acknowledged
+    /// again without being written. Only counted by the receiver.
+    pub duplicates: usize,
+}
+
+impl Summary {
+    /// Returns the total number of errors recovered from.
+    pub fn errors(&self) -> usize {
+        self.naks + self.timeouts + self.duplicates
+    }
 }
 
 /// Type for progress callbacks.
@@ -1,6 +1,20 @@
 use super::*;
-use std::sync::mpsc::{Receiver, Sender, channel};
+use ymodem::Header;
+use baud::Frame;
+use std::sync::Mutex;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
 use std::io::Cursor;
+use std::time::Duration;
+use std::thread;
+
+macro_rules! expect_variant {
+    ($e:expr, $variant:pat) => {
+        match $e {
+            $variant => {  },
+            ref o => panic!("expected '{}' but found '{:?}'", stringify!($variant), o)
+        }
+    }
+}
 
 struct Pipe(Sender<u8>, Receiver<u8>, Vec<u8>);
 
@@ -11,9 +25,9 @@ fn pipe() -> (Pipe, Pipe) {
 
 impl io::Read for Pipe {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        for i in 0..buf.len() {
+        for (i, slot) in buf.iter_mut().enumerate() {
             match self.1.recv() {
-                Ok(byte) => buf[i] = byte,
+                Ok(byte) => *slot = byte,
                 Err(_) => return Ok(i)
             }
         }
@@ -157,7 +171,7 @@ fn test_transmit_reported_bytes() {
 fn test_raw_transmission() {
     let mut input = [0u8; 256];
     let mut output = [0u8; 256];
-    (0..256usize).into_iter().enumerate().for_each(|(i, b)| input[i] = b as u8);
+    (0..256usize).enumerate().for_each(|(i, b)| input[i] = b as u8);
 
     let (mut tx, mut rx) = pipe();
     let tx_thread = std::thread::spawn(move || {
@@ -227,3 +241,900 @@ fn test_eot() {
 
     assert_eq!(&buffer[..], &[NAK, EOT, NAK, EOT, ACK]);
 }
+
+/// A fault injected into the bytes written to one end of a `Link`. The index
+/// counts every byte written to that end, starting at 0.
+#[derive(Debug, Copy, Clone)]
+enum Fault {
+    /// Invert the bits of the byte at this index.
+    Flip(usize),
+    /// Never deliver the byte at this index.
+    Drop(usize),
+    /// Deliver the byte at this index twice.
+    Repeat(usize),
+}
+
+/// One end of an in-memory duplex link. Reads time out after `timeout` with
+/// `TimedOut`; bytes written are subject to `faults`.
+///
+/// Each byte carries the baud rate of the end that wrote it. A byte read at a
+/// different rate than it was written arrives garbled, as on a real line.
+///
+/// End `a` is meant for the sender and end `b` for the receiver. The
+/// receiver's timeout, which is also its drain guard time, is the shorter of
+/// the two, as it is between `ttywrite` and the bootloader.
+struct Link {
+    tx: Sender<(u8, u32)>,
+    rx: Receiver<(u8, u32)>,
+    baud: u32,
+    timeout: Duration,
+    faults: Vec<Fault>,
+    written: usize,
+}
+
+fn link(a_faults: Vec<Fault>, b_faults: Vec<Fault>) -> (Link, Link) {
+    let ((tx1, rx1), (tx2, rx2)) = (channel(), channel());
+    let a = Link {
+        tx: tx1, rx: rx2, baud: baud::DEFAULT_BAUD,
+        timeout: Duration::from_millis(300), faults: a_faults, written: 0
+    };
+    let b = Link {
+        tx: tx2, rx: rx1, baud: baud::DEFAULT_BAUD,
+        timeout: Duration::from_millis(100), faults: b_faults, written: 0
+    };
+    (a, b)
+}
+
+/// Switches `link` to `baud`, for `baud::request()` and `baud::answer()`.
+fn set_baud(link: &mut Link, baud: u32) -> io::Result<()> {
+    link.baud = baud;
+    Ok(())
+}
+
+impl io::Read for Link {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        for (i, slot) in buf.iter_mut().enumerate() {
+            match self.rx.recv_timeout(self.timeout) {
+                Ok((byte, baud)) if baud == self.baud => *slot = byte,
+                Ok((byte, _)) => *slot = byte.rotate_left(3) ^ 0x5A,
+                Err(RecvTimeoutError::Timeout) if i == 0 => {
+                    return Err(io::Error::new(io::ErrorKind::TimedOut, "link timed out"));
+                }
+                Err(_) => return Ok(i),
+            }
+        }
+
+        Ok(buf.len())
+    }
+}
+
+impl io::Write for Link {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            let index = self.written;
+            self.written += 1;
+
+            let (mut byte, mut copies) = (byte, 1);
+            for fault in &self.faults {
+                match *fault {
+                    Fault::Flip(i) if i == index => byte = !byte,
+                    Fault::Drop(i) if i == index => copies = 0,
+                    Fault::Repeat(i) if i == index => copies = 2,
+                    _ => {}
+                }
+            }
+
+            // Like a serial line, writes succeed whether or not anyone is
+            // still listening at the other end.
+            for _ in 0..copies {
+                let _ = self.tx.send((byte, self.baud));
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A peer that plays back `input` and records everything written to it.
+struct Script {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Script {
+    fn new(input: Vec<u8>) -> Script {
+        Script { input: Cursor::new(input), output: vec![] }
+    }
+}
+
+impl io::Read for Script {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl io::Write for Script {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Offset of the checksum byte of the `n`th packet (from 0) in the sender's
+/// output.
+fn checksum_index(n: usize) -> usize {
+    132 * n + 131
+}
+
+fn data(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i * 7) as u8).collect()
+}
+
+/// A complete packet numbered `number` holding `byte` 128 times.
+fn packet(number: u8, byte: u8) -> Vec<u8> {
+    let mut packet = vec![SOH, number, 255 - number];
+    packet.extend(::std::iter::repeat_n(byte, 128));
+    packet.push(byte.wrapping_mul(128));
+    packet
+}
+
+/// The result of each side of a transfer over a `Link` and the summary each
+/// side reported.
+struct Outcome {
+    sent: io::Result<usize>,
+    received: io::Result<Vec<u8>>,
+    tx: Summary,
+    rx: Summary,
+}
+
+/// Transmits `input` to a receiver over a `Link` with the given faults on the
+/// sender's and receiver's writes. Both sides give up after `retries`
+/// consecutive failures.
+fn transfer_with_retries(
+    input: Vec<u8>,
+    tx_faults: Vec<Fault>,
+    rx_faults: Vec<Fault>,
+    retries: usize
+) -> Outcome {
+    let (tx_end, rx_end) = link(tx_faults, rx_faults);
+    let tx_thread = thread::spawn(move || {
+        let mut transmitter = Xmodem::new(tx_end);
+        transmitter.set_max_retries(retries);
+        (transmitter.transmit_all(&input[..]), transmitter.summary())
+    });
+    let rx_thread = thread::spawn(move || {
+        let mut receiver = Xmodem::new(rx_end);
+        receiver.set_max_retries(retries);
+        let mut output = vec![];
+        let received = receiver.receive_all(&mut output).map(|_| output);
+        (received, receiver.summary())
+    });
+
+    let (sent, tx) = tx_thread.join().expect("tx join okay");
+    let (received, rx) = rx_thread.join().expect("rx join okay");
+    Outcome { sent, received, tx, rx }
+}
+
+/// Transmits `input` to a receiver over a `Link` with the given faults on the
+/// sender's and receiver's writes.
+fn transfer(
+    input: Vec<u8>,
+    tx_faults: Vec<Fault>,
+    rx_faults: Vec<Fault>
+) -> (io::Result<usize>, io::Result<Vec<u8>>) {
+    let outcome = transfer_with_retries(input, tx_faults, rx_faults, MAX_RETRIES);
+    (outcome.sent, outcome.received)
+}
+
+#[test]
+fn transfers_of_any_length_round_trip() {
+    for &len in &[0, 1, 127, 128, 129, 1000] {
+        let (sent, received) = transfer(data(len), vec![], vec![]);
+        assert_eq!(sent.expect("tx okay"), len);
+
+        // The last packet is padded with zeroes to 128 bytes.
+        let mut expected = data(len);
+        expected.resize(len.div_ceil(128) * 128, 0);
+        assert_eq!(received.expect("rx okay"), expected, "length {}", len);
+    }
+}
+
+#[test]
+fn corrupted_checksum_is_retried() {
+    let input = data(300);
+    // Packet 1 is sent twice, so the sender's fourth packet is packet 3.
+    let faults = vec![Fault::Flip(checksum_index(0)), Fault::Flip(checksum_index(3))];
+    let (sent, received) = transfer(input.clone(), faults, vec![]);
+
+    assert_eq!(sent.expect("tx okay"), 300);
+    let output = received.expect("rx okay");
+    assert_eq!(output.len(), 384);
+    assert_eq!(&output[..300], &input[..]);
+    assert!(output[300..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn persistent_corruption_exhausts_retries() {
+    let faults = (0..MAX_RETRIES).map(|i| Fault::Flip(checksum_index(i))).collect();
+    let (sent, received) = transfer(data(128), faults, vec![]);
+
+    assert_eq!(sent.expect_err("tx gives up").kind(), io::ErrorKind::BrokenPipe);
+    assert_eq!(received.expect_err("rx gives up").kind(), io::ErrorKind::BrokenPipe);
+}
+
+#[test]
+fn retry_limit_is_configurable() {
+    // More consecutive failures than the default limit tolerates...
+    let faults = || (0..MAX_RETRIES + 2).map(|i| Fault::Flip(checksum_index(i))).collect();
+    let outcome = transfer_with_retries(data(256), faults(), vec![], MAX_RETRIES + 3);
+    assert_eq!(outcome.sent.expect("tx okay"), 256);
+    assert_eq!(outcome.received.expect("rx okay"), data(256));
+    assert_eq!(outcome.tx.naks, MAX_RETRIES + 2);
+
+    // ...and a lower limit gives up sooner.
+    let outcome = transfer_with_retries(data(256), faults(), vec![], 3);
+    assert_eq!(outcome.sent.expect_err("tx gives up").kind(), io::ErrorKind::BrokenPipe);
+    assert_eq!(outcome.received.expect_err("rx gives up").kind(), io::ErrorKind::BrokenPipe);
+    assert_eq!(outcome.tx.naks, 3);
+    assert_eq!(outcome.rx.naks, 3);
+}
+
+#[test]
+fn receiver_resyncs_after_damaged_packet() {
+    // A repeated data byte leaves the real checksum byte on the line after
+    // the receiver has read a full-length packet. Unless it is drained, it is
+    // read as the header of the next packet.
+    let faults = vec![Fault::Repeat(50), Fault::Repeat(132 + 3)];
+    let outcome = transfer_with_retries(data(256), faults, vec![], MAX_RETRIES);
+
+    assert_eq!(outcome.sent.expect("tx okay"), 256);
+    assert_eq!(outcome.received.expect("rx okay"), data(256));
+    assert_eq!(outcome.rx.naks, 2);
+    assert_eq!(outcome.rx.packets, 2);
+}
+
+#[test]
+fn truncated_packet_is_retried() {
+    // The receiver times out waiting for the rest of packet 2, then NAKs it.
+    let outcome = transfer_with_retries(data(384), vec![Fault::Drop(132 + 70)], vec![], MAX_RETRIES);
+
+    assert_eq!(outcome.sent.expect("tx okay"), 384);
+    assert_eq!(outcome.received.expect("rx okay"), data(384));
+    assert_eq!(outcome.rx.timeouts, 1);
+    assert_eq!(outcome.tx.naks, 1);
+}
+
+#[test]
+fn corrupted_packet_number_is_retried() {
+    let outcome = transfer_with_retries(data(256), vec![Fault::Flip(132 + 2)], vec![], MAX_RETRIES);
+
+    assert_eq!(outcome.received.expect("rx okay"), data(256));
+    assert_eq!(outcome.rx.naks, 1);
+}
+
+#[test]
+fn corrupted_header_is_retried() {
+    // The header of packet 2 arrives damaged: the receiver drains the rest of
+    // the packet and NAKs it instead of giving up on the transfer.
+    let outcome = transfer_with_retries(data(256), vec![Fault::Flip(132)], vec![], MAX_RETRIES);
+
+    assert_eq!(outcome.sent.expect("tx okay"), 256);
+    assert_eq!(outcome.received.expect("rx okay"), data(256));
+    assert_eq!(outcome.rx.naks, 1);
+    assert_eq!(outcome.tx.naks, 1);
+}
+
+#[test]
+fn dropped_ack_is_recovered() {
+    // The receiver writes NAK to start, then ACKs packet 1. Drop that ACK: the
+    // receiver times out waiting for packet 2 and NAKs, the sender resends
+    // packet 1, and the receiver ACKs it again without writing it twice.
+    let outcome = transfer_with_retries(data(256), vec![], vec![Fault::Drop(1)], MAX_RETRIES);
+
+    assert_eq!(outcome.sent.expect("tx okay"), 256);
+    assert_eq!(outcome.received.expect("rx okay"), data(256));
+    assert_eq!(outcome.rx, Summary { packets: 2, naks: 0, timeouts: 1, duplicates: 1 });
+    assert_eq!(outcome.tx, Summary { packets: 2, naks: 1, timeouts: 0, duplicates: 0 });
+}
+
+#[test]
+fn silent_receiver_times_out_sender() {
+    // The receiver starts the transfer and then never answers, so the sender
+    // only ever times out and finally reports that rather than a broken pipe.
+    let (tx_end, mut rx_end) = link(vec![], vec![]);
+    io::Write::write_all(&mut rx_end, &[NAK]).expect("start okay");
+
+    let mut transmitter = Xmodem::new(tx_end);
+    transmitter.set_max_retries(3);
+    let e = transmitter.transmit_all(&data(128)[..]).expect_err("tx gives up");
+
+    assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    assert_eq!(transmitter.summary().timeouts, 3);
+    drop(rx_end);
+}
+
+#[test]
+fn duplicated_packet_is_acknowledged_once() {
+    let mut input = packet(1, 0xAB);
+    input.extend(packet(1, 0xAB));
+    input.extend(packet(2, 0xCD));
+    input.extend(&[EOT, EOT]);
+
+    let mut script = Script::new(input);
+    let mut output = vec![];
+    let received = Xmodem::receive(&mut script, &mut output).expect("duplicate skipped");
+
+    assert_eq!(received, 256);
+    let mut expected = vec![0xAB; 128];
+    expected.extend(vec![0xCD; 128]);
+    assert_eq!(output, expected);
+    assert_eq!(&script.output, &[NAK, ACK, ACK, ACK, NAK, ACK]);
+}
+
+#[test]
+fn out_of_order_packet_aborts_receiver() {
+    let mut input = packet(1, 0xAB);
+    input.extend(packet(3, 0xAB));
+
+    let mut script = Script::new(input);
+    let mut output = vec![];
+    let e = Xmodem::receive(&mut script, &mut output).expect_err("packet 2 skipped");
+
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(output, vec![0xAB; 128]);
+    assert_eq!(&script.output, &[NAK, ACK, CAN]);
+}
+
+static SUMMARIES: Mutex<Vec<Summary>> = Mutex::new(Vec::new());
+
+fn record_summary(progress: Progress) {
+    if let Progress::Finished(summary) = progress {
+        SUMMARIES.lock().expect("lock okay").push(summary);
+    }
+}
+
+#[test]
+fn summary_is_reported_through_progress() {
+    let (tx_end, rx_end) = link(vec![Fault::Flip(checksum_index(1))], vec![]);
+    let tx_thread = thread::spawn(move || {
+        Xmodem::transmit_with_progress(&data(384)[..], tx_end, record_summary)
+    });
+    let mut output = vec![];
+    Xmodem::receive_with_progress(rx_end, &mut output, record_summary).expect("rx okay");
+    tx_thread.join().expect("tx join okay").expect("tx okay");
+
+    let summaries = SUMMARIES.lock().expect("lock okay");
+    let expected = Summary { packets: 3, naks: 1, timeouts: 0, duplicates: 0 };
+    assert_eq!(&summaries[..], &[expected, expected]);
+    assert_eq!(expected.errors(), 1);
+}
+
+static PACKETS: Mutex<Vec<(u8, usize, usize)>> = Mutex::new(Vec::new());
+
+fn record_packet(progress: Progress) {
+    if let Progress::Packet { number, bytes, retries } = progress {
+        PACKETS.lock().expect("lock okay").push((number, bytes, retries));
+    }
+}
+
+#[test]
+fn packets_are_reported_through_progress() {
+    // The receiver rejects the second packet once.
+    let mut script = Script::new(vec![NAK, ACK, NAK, ACK, ACK, NAK, ACK]);
+    Xmodem::transmit_with_progress(&data(300)[..], &mut script, record_packet).expect("tx okay");
+
+    let packets = PACKETS.lock().expect("lock okay");
+    assert_eq!(&packets[..], &[(1, 128, 0), (2, 256, 1), (3, 384, 1)]);
+}
+
+#[test]
+fn premature_eot_ends_transfer() {
+    let (tx_end, rx_end) = link(vec![], vec![]);
+    let tx_thread = thread::spawn(move || {
+        let mut transmitter = Xmodem::new(tx_end);
+        transmitter.write_packet(&[0x42; 128])?;
+        transmitter.write_packet(&[])
+    });
+
+    let mut output = vec![];
+    let received = Xmodem::receive(rx_end, &mut output).expect("rx okay");
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 0);
+
+    // XMODEM itself can't tell a short transfer from a complete one.
+    assert_eq!(received, 128);
+    assert_eq!(output, vec![0x42; 128]);
+}
+
+#[test]
+fn single_eot_is_rejected() {
+    let mut script = Script::new(vec![EOT, SOH]);
+    let e = Xmodem::receive(&mut script, vec![]).expect_err("second EOT required");
+
+    // The sides are out of step, so the receiver cancels.
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(&script.output, &[NAK, NAK, CAN]);
+}
+
+#[test]
+fn receiver_cancels_before_start() {
+    let mut script = Script::new(vec![CAN]);
+    let e = Xmodem::transmit(&data(128)[..], &mut script).expect_err("CAN");
+
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+    assert!(script.output.is_empty());
+}
+
+#[test]
+fn receiver_cancels_mid_transfer() {
+    let mut script = Script::new(vec![NAK, ACK, CAN]);
+    let e = Xmodem::transmit(&data(384)[..], &mut script).expect_err("CAN");
+
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+    assert_eq!(script.output.len(), 2 * 132);
+}
+
+#[test]
+fn sender_cancels_mid_transfer() {
+    let mut input = packet(1, 1);
+    input.push(CAN);
+
+    let mut script = Script::new(input);
+    let e = Xmodem::receive(&mut script, vec![]).expect_err("CAN");
+
+    assert_eq!(e.kind(), io::ErrorKind::ConnectionAborted);
+    assert_eq!(&script.output, &[NAK, ACK]);
+}
+
+#[test]
+fn crc16_known_answers() {
+    assert_eq!(crc16(b""), 0);
+    assert_eq!(crc16(b"123456789"), 0x31C3);
+    assert_eq!(crc16(&[0u8; 128]), 0);
+}
+
+#[test]
+fn crc_receiver_gets_crc_packets() {
+    // A receiver that asks for CRC-16 starts with 'C' rather than NAK.
+    let input = data(128);
+    let mut script = Script::new(vec![CRC, ACK, NAK, ACK]);
+    assert_eq!(Xmodem::transmit(&input[..], &mut script).expect("tx okay"), 128);
+
+    let crc = crc16(&input);
+    let mut expected = vec![SOH, 1, 254];
+    expected.extend_from_slice(&input);
+    expected.extend_from_slice(&[(crc >> 8) as u8, crc as u8, EOT, EOT]);
+    assert_eq!(script.output, expected);
+}
+
+#[test]
+fn crc_and_1k_transfers_round_trip() {
+    for &(len, packets) in &[(0, 0), (1, 1), (1023, 8), (1024, 1), (1025, 2), (2500, 6)] {
+        let (tx_end, rx_end) = link(vec![Fault::Flip(1040)], vec![]);
+        let image = data(len);
+        let tx_thread = thread::spawn(move || {
+            let mut transmitter = Xmodem::new(tx_end);
+            transmitter.set_1k_blocks(true);
+            (transmitter.transmit_all(&image[..]), transmitter.summary())
+        });
+        let rx_thread = thread::spawn(move || {
+            let mut receiver = Xmodem::new(rx_end);
+            receiver.set_crc(true);
+            let mut output = vec![];
+            receiver.receive_all(&mut output).map(|_| output)
+        });
+
+        let (sent, summary) = tx_thread.join().expect("tx join okay");
+        assert_eq!(sent.expect("tx okay"), len);
+        assert_eq!(summary.packets, packets, "length {}", len);
+
+        // Only full blocks go in 1K packets, so padding stays under 128.
+        let mut expected = data(len);
+        expected.resize(len.div_ceil(128) * 128, 0);
+        let received = rx_thread.join().expect("rx join okay").expect("rx okay");
+        assert_eq!(received, expected, "length {}", len);
+    }
+}
+
+#[test]
+fn crc_receiver_falls_back_to_checksums() {
+    let (mut tx_end, rx_end) = link(vec![], vec![]);
+    let rx_thread = thread::spawn(move || {
+        let mut receiver = Xmodem::new(rx_end);
+        receiver.set_crc(true);
+        let mut output = vec![];
+        receiver.receive_all(&mut output).map(|_| (output, receiver.summary()))
+    });
+
+    // A sender without CRC-16 support ignores the 'C's and waits for a NAK.
+    let mut starts = vec![];
+    while starts.last() != Some(&NAK) {
+        let mut byte = [0u8];
+        io::Read::read_exact(&mut tx_end, &mut byte).expect("start byte");
+        starts.push(byte[0]);
+    }
+
+    assert_eq!(starts, [CRC, CRC, CRC, NAK]);
+    let mut input = packet(1, 9);
+    input.extend_from_slice(&[EOT, EOT]);
+    io::Write::write_all(&mut tx_end, &input).expect("tx okay");
+
+    let (output, summary) = rx_thread.join().expect("rx join okay").expect("rx okay");
+    assert_eq!(output, vec![9u8; 128]);
+    assert_eq!(summary, Summary { packets: 1, naks: 0, timeouts: 3, duplicates: 0 });
+
+    let mut responses = [0u8; 3];
+    io::Read::read_exact(&mut tx_end, &mut responses).expect("responses");
+    assert_eq!(responses, [ACK, NAK, ACK]);
+}
+
+#[test]
+fn short_buffer_rejects_1k_packet() {
+    let mut input = vec![STX, 1, 254];
+    input.extend_from_slice(&[0u8; 1026]);
+
+    let mut script = Script::new(input);
+    let mut buffer = [0u8; 128];
+    let e = Xmodem::new(&mut script).read_packet(&mut buffer).expect_err("too short");
+
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(&script.output, &[NAK, CAN]);
+}
+
+#[test]
+fn receiver_cancels_when_full() {
+    let mut input = packet(1, 1);
+    input.extend(packet(2, 2));
+
+    let mut script = Script::new(input);
+    let mut output = [0u8; 128];
+    let e = Xmodem::receive(&mut script, &mut output[..]).expect_err("no room");
+
+    assert_eq!(e.kind(), io::ErrorKind::WriteZero);
+    assert_eq!(&output[..], &[1u8; 128][..]);
+    assert_eq!(&script.output, &[NAK, ACK, ACK, CAN, CAN]);
+}
+
+#[test]
+fn oversized_transfer_aborts_both_sides() {
+    let (tx_end, rx_end) = link(vec![], vec![]);
+    let tx_thread = thread::spawn(move || Xmodem::transmit(&data(384)[..], tx_end));
+    let rx_thread = thread::spawn(move || {
+        let mut output = [0u8; 256];
+        Xmodem::receive(rx_end, &mut output[..])
+    });
+
+    let sent = tx_thread.join().expect("tx join okay");
+    let received = rx_thread.join().expect("rx join okay");
+    assert_eq!(sent.expect_err("cancelled").kind(), io::ErrorKind::ConnectionAborted);
+    assert_eq!(received.expect_err("no room").kind(), io::ErrorKind::WriteZero);
+}
+
+#[test]
+fn crc32_known_answers() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"a"), 0xE8B7BE43);
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    assert_eq!(crc32(b"The quick brown fox jumps over the lazy dog"), 0x414FA339);
+    assert_eq!(crc32(&[0u8; 32]), 0x190A55AD);
+    assert_eq!(crc32(&[0xFFu8; 32]), 0xFF6CAB0B);
+
+    let mut crc = Crc32::new();
+    crc.update(b"1234");
+    crc.update(b"");
+    crc.update(b"56789");
+    assert_eq!(crc.finish(), 0xCBF43926);
+}
+
+#[test]
+fn trailer_packet_layout() {
+    let trailer = Trailer::for_image(b"123456789");
+    assert_eq!(trailer, Trailer { length: 9, crc: 0xCBF43926 });
+
+    let packet = trailer.to_packet();
+    assert_eq!(&packet[..16], &[b'T', b'R', b'L', b'R',
+                                9, 0, 0, 0,
+                                0x26, 0x39, 0xF4, 0xCB,
+                                0x70, 0x47, 0x92, 0x71]);
+    assert!(packet[16..].iter().all(|&b| b == 0));
+    assert_eq!(Trailer::from_packet(&packet), Some(trailer));
+}
+
+#[test]
+fn trailer_packet_rejects_lookalikes() {
+    let packet = Trailer::for_image(b"image").to_packet();
+    for &i in &[0, 5, 9, 13, 16, 127] {
+        let mut bad = packet;
+        bad[i] ^= 0x01;
+        assert_eq!(Trailer::from_packet(&bad), None, "byte {} flipped", i);
+    }
+
+    assert_eq!(Trailer::from_packet(&packet[..127]), None);
+    assert_eq!(Trailer::from_packet(&[0u8; 128]), None);
+}
+
+/// The bytes a receiver writes for a verified transfer of `image`.
+fn received(image: &[u8]) -> Vec<u8> {
+    let mut received = image.to_vec();
+    received.resize(image.len().div_ceil(128) * 128, 0);
+    received.extend_from_slice(&Trailer::for_image(image).to_packet());
+    received
+}
+
+#[test]
+fn verify_accepts_exact_transfers() {
+    for &len in &[0, 1, 127, 128, 129, 1000] {
+        let image = data(len);
+        assert_eq!(trailer::verify(&received(&image)), Ok(len));
+    }
+}
+
+#[test]
+fn verify_rejects_bad_transfers() {
+    let image = data(300);
+    let good = received(&image);
+
+    let mut corrupted = good.clone();
+    corrupted[100] ^= 0x80;
+    expect_variant!(trailer::verify(&corrupted), Err(trailer::Error::Crc { .. }));
+
+    // The last image packet never arrived.
+    let mut truncated = good[..128].to_vec();
+    truncated.extend_from_slice(&good[384..]);
+    expect_variant!(trailer::verify(&truncated), Err(trailer::Error::Length { .. }));
+
+    // An extra packet arrived.
+    let mut padded = good[..384].to_vec();
+    padded.extend_from_slice(&[0; 128]);
+    padded.extend_from_slice(&good[384..]);
+    expect_variant!(trailer::verify(&padded), Err(trailer::Error::Length { .. }));
+
+    // The trailer itself never arrived.
+    expect_variant!(trailer::verify(&good[..384]), Err(trailer::Error::Missing));
+    expect_variant!(trailer::verify(&[]), Err(trailer::Error::Missing));
+    expect_variant!(trailer::verify(&good[1..]), Err(trailer::Error::Missing));
+}
+
+#[test]
+fn verify_errors_explain_themselves() {
+    let length = trailer::Error::Length { expected: 300, received: 256 };
+    assert_eq!(length.to_string(), "expected 300 bytes but received 256, padding included");
+
+    let crc = trailer::Error::Crc { expected: 0xCBF43926, actual: 0x1234 };
+    assert_eq!(crc.to_string(), "expected CRC-32 0xcbf43926 but computed 0x00001234");
+    assert_eq!(trailer::Error::Missing.to_string(), "the transfer didn't end with a trailer");
+}
+
+#[test]
+fn verdict_round_trips() {
+    let mut buffer = vec![];
+    trailer::write_verdict(&mut buffer, true).unwrap();
+    trailer::write_verdict(&mut buffer, false).unwrap();
+
+    let mut cursor = Cursor::new(buffer);
+    assert!(trailer::read_verdict(&mut cursor).unwrap());
+    assert!(!trailer::read_verdict(&mut cursor).unwrap());
+
+    let e = trailer::read_verdict(Cursor::new(vec![b'?'])).expect_err("not a verdict");
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+}
+
+/// Receives a transfer over `from` and sends back the verdict, like the
+/// bootloader does. If `damage` is `true`, the received data is corrupted
+/// before verification, as if memory had been overwritten.
+fn receive_and_verify(mut from: Link, damage: bool) -> io::Result<Result<usize, trailer::Error>> {
+    let mut output = vec![];
+    Xmodem::receive(&mut from, &mut output)?;
+    if damage {
+        output[0] ^= 0xFF;
+    }
+
+    let result = trailer::verify(&output);
+    trailer::write_verdict(&mut from, result.is_ok())?;
+    Ok(result)
+}
+
+#[test]
+fn verified_transfer_end_to_end() {
+    let image = data(1000);
+    let (tx_end, rx_end) = link(vec![Fault::Flip(checksum_index(3))], vec![]);
+    let tx_thread = thread::spawn(move || {
+        Xmodem::transmit_verified_with_progress(&image[..], tx_end, progress::noop)
+    });
+    let rx_thread = thread::spawn(move || receive_and_verify(rx_end, false));
+
+    assert_eq!(tx_thread.join().expect("tx join okay").expect("tx okay"), 1000);
+    assert_eq!(rx_thread.join().expect("rx join okay").expect("rx okay"), Ok(1000));
+}
+
+#[test]
+fn verified_transfer_reports_rejection() {
+    let (tx_end, rx_end) = link(vec![], vec![]);
+    let tx_thread = thread::spawn(move || {
+        Xmodem::transmit_verified_with_progress(&data(200)[..], tx_end, progress::noop)
+    });
+    let rx_thread = thread::spawn(move || receive_and_verify(rx_end, true));
+
+    let e = tx_thread.join().expect("tx join okay").expect_err("rejected");
+    assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    let result = rx_thread.join().expect("rx join okay").expect("rx okay");
+    expect_variant!(result, Err(trailer::Error::Crc { .. }));
+}
+
+#[test]
+fn baud_frame_round_trips() {
+    let frames = [Frame::Request(230400), Frame::Ack(921600), Frame::Confirm(baud::DEFAULT_BAUD)];
+    for &frame in frames.iter() {
+        assert_eq!(Frame::from_bytes(&frame.to_bytes()), Ok(frame));
+    }
+
+    let bytes = Frame::Request(230400).to_bytes();
+    assert_eq!(&bytes[..4], b"BAUD");
+    assert_eq!(&bytes[4..8], &[0x00, 0x84, 0x03, 0x00]);
+    assert_eq!(&Frame::Ack(230400).to_bytes()[..4], b"BDOK");
+    assert_eq!(&Frame::Confirm(230400).to_bytes()[..4], b"BDGO");
+}
+
+#[test]
+fn baud_frame_rejects_damage() {
+    let bytes = Frame::Request(230400).to_bytes();
+    assert_eq!(Frame::from_bytes(&bytes[..11]), Err(baud::Error::Length(11)));
+
+    for i in 0..baud::FRAME_LEN {
+        let mut damaged = bytes;
+        damaged[i] ^= 0x10;
+        let expected = if i < 4 { baud::Error::Magic } else { baud::Error::Checksum };
+        assert_eq!(Frame::from_bytes(&damaged), Err(expected), "byte {}", i);
+    }
+
+    let unsupported = Frame::Request(3_000_000).to_bytes();
+    assert_eq!(Frame::from_bytes(&unsupported), Err(baud::Error::Unsupported(3_000_000)));
+}
+
+#[test]
+fn mini_uart_divisors() {
+    assert_eq!(baud::divisor(115200), Some(270));
+    assert_eq!(baud::divisor(230400), Some(135));
+    assert_eq!(baud::divisor(921600), Some(33));
+    assert_eq!(baud::divisor(1_000_000), Some(30));
+
+    // Too far from anything 250MHz / (8 * n) can produce.
+    assert_eq!(baud::divisor(2_000_000), None);
+    // Needs a divisor wider than 16 bits.
+    assert_eq!(baud::divisor(300), None);
+    assert_eq!(baud::divisor(0), None);
+}
+
+/// Plays the bootloader's part: alternates between answering a rate switch
+/// and receiving a transfer at the resulting rate until a transfer succeeds.
+/// A failed transfer drops back to the default rate.
+fn answer_and_receive(mut port: Link) -> io::Result<(Option<u32>, u32, Vec<u8>)> {
+    loop {
+        let switched = baud::answer(&mut port, set_baud)?;
+        let rate = port.baud;
+        let mut output = vec![];
+        match Xmodem::receive(&mut port, &mut output) {
+            Ok(_) => return Ok((switched, rate, output)),
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => set_baud(&mut port, baud::DEFAULT_BAUD)?,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[test]
+fn rate_switch_end_to_end() {
+    let (mut tx_end, rx_end) = link(vec![], vec![]);
+    let rx_thread = thread::spawn(move || answer_and_receive(rx_end));
+
+    assert!(baud::request(&mut tx_end, 921600, 3, set_baud).expect("request okay"));
+    assert_eq!(tx_end.baud, 921600);
+    assert_eq!(Xmodem::transmit(&data(1000)[..], &mut tx_end).expect("tx okay"), 1000);
+
+    let (switched, rate, output) = rx_thread.join().expect("rx join okay").expect("rx okay");
+    assert_eq!(switched, Some(921600));
+    assert_eq!(rate, 921600);
+    assert_eq!(&output[..1000], &data(1000)[..]);
+}
+
+#[test]
+fn lost_ack_falls_back_to_default() {
+    // The device's acknowledgement is its first write. Without it, the host
+    // gives up and the device never hears a confirmation: both end up back at
+    // the default rate, where the transfer still works.
+    let (mut tx_end, rx_end) = link(vec![], (0..baud::FRAME_LEN).map(Fault::Drop).collect());
+    let rx_thread = thread::spawn(move || answer_and_receive(rx_end));
+
+    // Like ttywrite, wait for the acknowledgement for less time than the
+    // device takes to go around its listen-and-receive loop, so its start
+    // NAKs, garbled at the new rate, don't keep the wait going.
+    tx_end.timeout = Duration::from_millis(150);
+    assert!(!baud::request(&mut tx_end, 921600, 1, set_baud).expect("request okay"));
+    assert_eq!(tx_end.baud, baud::DEFAULT_BAUD);
+    tx_end.timeout = Duration::from_millis(300);
+    assert_eq!(Xmodem::transmit(&data(300)[..], &mut tx_end).expect("tx okay"), 300);
+
+    let (switched, rate, output) = rx_thread.join().expect("rx join okay").expect("rx okay");
+    assert_eq!(switched, None);
+    assert_eq!(rate, baud::DEFAULT_BAUD);
+    assert_eq!(&output[..300], &data(300)[..]);
+}
+
+#[test]
+fn no_request_keeps_default_rate() {
+    let (tx_end, rx_end) = link(vec![], vec![]);
+    let rx_thread = thread::spawn(move || answer_and_receive(rx_end));
+
+    // Only start the transfer once the device has stopped listening.
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(Xmodem::transmit(&data(128)[..], tx_end).expect("tx okay"), 128);
+
+    let (switched, rate, output) = rx_thread.join().expect("rx join okay").expect("rx okay");
+    assert_eq!(switched, None);
+    assert_eq!(rate, baud::DEFAULT_BAUD);
+    assert_eq!(output, data(128));
+}
+
+#[test]
+fn unsupported_rate_is_refused() {
+    let mut script = Script::new(vec![]);
+    let e = baud::request(&mut script, 2_000_000, 3, |_, _| Ok(())).expect_err("unsupported");
+
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    assert!(script.output.is_empty());
+}
+
+#[test]
+fn ymodem_header_round_trips() {
+    let header = Header::new("kernel.img", 123_456).expect("valid header");
+    let packet = header.to_packet();
+    assert_eq!(&packet[..18], b"kernel.img\x00123456\x00");
+
+    let parsed = Header::from_packet(&packet).expect("a header").expect("not the end");
+    assert_eq!((parsed.name(), parsed.size), ("kernel.img", 123_456));
+
+    // Senders may add fields after the size, but the size is required.
+    assert!(Header::from_packet(b"a\0 7").is_none());
+    let parsed = Header::from_packet(b"a\x007 1234 0644\x00").map(|h| h.map(|h| h.size));
+    assert_eq!(parsed, Some(Some(7)));
+
+    assert!(Header::from_packet(&[0u8; 128]).expect("the end").is_none());
+    assert!(Header::from_packet(b"a\0x1\0").is_none());
+    assert!(Header::new("", 1).is_none());
+    assert!(Header::new("a\0b", 1).is_none());
+    assert!(Header::new(&"a".repeat(ymodem::MAX_NAME + 1), 1).is_none());
+}
+
+#[test]
+fn ymodem_batch_round_trips() {
+    let files = vec![("kernel.img", data(1500)), ("empty", vec![]), ("init", data(128))];
+    let sent = files.clone();
+    let (tx_end, rx_end) = link(vec![Fault::Flip(200)], vec![]);
+    let tx_thread = thread::spawn(move || -> io::Result<()> {
+        let mut transmitter = Xmodem::new(tx_end);
+        transmitter.set_1k_blocks(true);
+        for &(name, ref data) in &sent {
+            let header = Header::new(name, data.len() as u64).expect("valid header");
+            assert_eq!(transmitter.transmit_file(&header, &data[..])?, data.len());
+        }
+
+        transmitter.end_batch()
+    });
+
+    let mut receiver = Xmodem::new(rx_end);
+    let mut received = vec![];
+    while let Some(header) = receiver.receive_header().expect("header okay") {
+        let mut output = vec![];
+        assert_eq!(receiver.receive_file(&header, &mut output).expect("rx okay"), header.size);
+        received.push((header.name().to_string(), output));
+    }
+
+    tx_thread.join().expect("tx join okay").expect("tx okay");
+    let expected: Vec<_> = files.into_iter().map(|(name, data)| (name.to_string(), data)).collect();
+    assert_eq!(received, expected);
+}
@@ -0,0 +1,148 @@
+//! End-to-end integrity checking for whole transfers.
+//!
+//! XMODEM's per-packet checksum can't tell when the last packets of a
+//! transfer never arrived or the sender's file was truncated. To catch those,
+//! the sender follows the image with one more packet, the _trailer_, holding
+//! the image's length and CRC-32. The receiver checks the trailer against what
+//! it received with `verify()` and answers with a one-byte verdict after the
+//! transfer ends.
+
+#[cfg(feature = "std")] use std::fmt;
+#[cfg(not(feature = "std"))] use core::fmt;
+
+use io;
+use crc32::crc32;
+
+/// The first four bytes of a trailer packet.
+pub const MAGIC: [u8; 4] = *b"TRLR";
+
+/// Verdict byte: the image matched its trailer.
+pub const VERDICT_OK: u8 = 0x06;
+
+/// Verdict byte: the image didn't match its trailer.
+pub const VERDICT_BAD: u8 = 0x15;
+
+/// The length and CRC-32 of an image.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Trailer {
+    pub length: u32,
+    pub crc: u32,
+}
+
+/// Why a received transfer failed verification.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The transfer didn't end with a trailer packet.
+    Missing,
+    /// The trailer's length doesn't match the amount of data received.
+    Length { expected: u32, received: usize },
+    /// The CRC-32 of the received image doesn't match the trailer's.
+    Crc { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Missing => write!(f, "the transfer didn't end with a trailer"),
+            Error::Length { expected, received } => {
+                write!(f, "expected {} bytes but received {}, padding included", expected, received)
+            }
+            Error::Crc { expected, actual } => {
+                write!(f, "expected CRC-32 {:#010x} but computed {:#010x}", expected, actual)
+            }
+        }
+    }
+}
+
+/// Reads a little-endian `u32` from the first four bytes of `bytes`.
+pub(crate) fn read_u32(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u32)
+}
+
+/// Writes `value` as a little-endian `u32` to the first four bytes of `bytes`.
+pub(crate) fn write_u32(bytes: &mut [u8], value: u32) {
+    for (i, byte) in bytes[..4].iter_mut().enumerate() {
+        *byte = (value >> (8 * i)) as u8;
+    }
+}
+
+impl Trailer {
+    /// Returns the trailer for `image`.
+    pub fn for_image(image: &[u8]) -> Trailer {
+        Trailer { length: image.len() as u32, crc: crc32(image) }
+    }
+
+    /// Returns the trailer packet: `MAGIC`, then the length, the CRC and the
+    /// CRC-32 of the preceding 12 bytes as little-endian `u32`s, then zeroes.
+    pub fn to_packet(&self) -> [u8; 128] {
+        let mut packet = [0u8; 128];
+        packet[..4].copy_from_slice(&MAGIC);
+        write_u32(&mut packet[4..], self.length);
+        write_u32(&mut packet[8..], self.crc);
+        let check = crc32(&packet[..12]);
+        write_u32(&mut packet[12..], check);
+        packet
+    }
+
+    /// Parses a trailer packet. Returns `None` if `packet` isn't a well-formed
+    /// trailer packet.
+    pub fn from_packet(packet: &[u8]) -> Option<Trailer> {
+        if packet.len() != 128 || packet[..4] != MAGIC {
+            return None;
+        }
+
+        let trailer = Trailer { length: read_u32(&packet[4..]), crc: read_u32(&packet[8..]) };
+        if read_u32(&packet[12..]) != crc32(&packet[..12]) || packet[16..].iter().any(|&b| b != 0) {
+            return None;
+        }
+
+        Some(trailer)
+    }
+}
+
+/// Verifies a transfer: `received` is everything the receiver wrote, which
+/// must be the zero-padded image followed by its trailer packet. On success,
+/// returns the length of the image.
+pub fn verify(received: &[u8]) -> Result<usize, Error> {
+    if received.len() < 128 || !received.len().is_multiple_of(128) {
+        return Err(Error::Missing);
+    }
+
+    let (padded, packet) = received.split_at(received.len() - 128);
+    let trailer = Trailer::from_packet(packet).ok_or(Error::Missing)?;
+
+    let length = trailer.length as usize;
+    if length > padded.len() || padded.len() - length >= 128 {
+        return Err(Error::Length { expected: trailer.length, received: padded.len() });
+    }
+
+    let actual = crc32(&padded[..length]);
+    if actual != trailer.crc {
+        return Err(Error::Crc { expected: trailer.crc, actual });
+    }
+
+    Ok(length)
+}
+
+/// Sends the verdict on a verified transfer to the sender.
+pub fn write_verdict<W: io::Write>(mut to: W, ok: bool) -> io::Result<()> {
+    to.write_all(&[if ok { VERDICT_OK } else { VERDICT_BAD }])?;
+    to.flush()
+}
+
+/// Reads the receiver's verdict on a transfer. Returns `true` if the receiver
+/// verified the image.
+///
+/// # Errors
+///
+/// Returns an error if reading fails, for instance by timing out, or if the
+/// byte read isn't a verdict.
+pub fn read_verdict<R: io::Read>(mut from: R) -> io::Result<bool> {
+    let mut byte = [0u8];
+    from.read_exact(&mut byte)?;
+    match byte[0] {
+        VERDICT_OK => Ok(true),
+        VERDICT_BAD => Ok(false),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Expected a verdict byte")),
+    }
+}
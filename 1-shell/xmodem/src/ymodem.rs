@@ -0,0 +1,227 @@
+//! YMODEM batch transfers: several files in one session, each introduced by a
+//! header naming it and giving its size.
+//!
+//! A YMODEM file is an XMODEM transfer preceded by packet 0, the _header_,
+//! which holds the file's name, a NUL, and its size in decimal ASCII. The
+//! receiver acknowledges the header and asks for the data with `C` as it would
+//! at the start of any transfer. Data packets are numbered from 1 and the file
+//! ends with the usual `EOT`. The batch ends with a header with an empty name.
+//! Since the header carries the size, the receiver can strip the padding.
+//!
+//! ```rust,ignore
+//! // Sender
+//! for &(name, data) in files {
+//!     let header = Header::new(name, data.len() as u64).unwrap();
+//!     xmodem.transmit_file(&header, data)?;
+//! }
+//! xmodem.end_batch()?;
+//!
+//! // Receiver
+//! while let Some(header) = xmodem.receive_header()? {
+//!     xmodem.receive_file(&header, storage_for(header.name()))?;
+//! }
+//! ```
+
+#[cfg(feature = "std")] use std::{fmt, str};
+#[cfg(not(feature = "std"))] use core::{fmt, str};
+
+use io;
+use Xmodem;
+
+/// The longest file name a header can hold, in bytes. The rest of the header
+/// packet is room for the size.
+pub const MAX_NAME: usize = 100;
+
+/// The name and size of a file in a YMODEM batch.
+#[derive(Copy, Clone)]
+pub struct Header {
+    name: [u8; MAX_NAME],
+    name_len: usize,
+    /// The size of the file in bytes.
+    pub size: u64,
+}
+
+impl Header {
+    /// Returns the header for the file `name` of `size` bytes, or `None` if
+    /// `name` is empty, longer than `MAX_NAME` bytes or contains a NUL.
+    pub fn new(name: &str, size: u64) -> Option<Header> {
+        if name.is_empty() || name.len() > MAX_NAME || name.bytes().any(|b| b == 0) {
+            return None;
+        }
+
+        let mut header = Header { name: [0; MAX_NAME], name_len: name.len(), size };
+        header.name[..name.len()].copy_from_slice(name.as_bytes());
+        Some(header)
+    }
+
+    /// Returns the name of the file.
+    pub fn name(&self) -> &str {
+        // Names are checked to be UTF-8 when the header is made.
+        str::from_utf8(&self.name[..self.name_len]).unwrap_or("")
+    }
+
+    /// Returns the header packet: the name, a NUL, the size in decimal and
+    /// zeroes.
+    pub fn to_packet(&self) -> [u8; 128] {
+        let mut packet = [0u8; 128];
+        packet[..self.name_len].copy_from_slice(&self.name[..self.name_len]);
+
+        let mut digits = [0u8; 20];
+        let (mut size, mut len) = (self.size, 0);
+        loop {
+            digits[len] = b'0' + (size % 10) as u8;
+            len += 1;
+            size /= 10;
+            if size == 0 {
+                break;
+            }
+        }
+
+        let start = self.name_len + 1;
+        for (i, &digit) in digits[..len].iter().rev().enumerate() {
+            packet[start + i] = digit;
+        }
+
+        packet
+    }
+
+    /// Parses the header packet `packet`. Returns `Some(None)` for the empty
+    /// header that ends a batch, and `None` if `packet` isn't a header. The
+    /// size may be followed by a space and further fields, which are ignored.
+    pub fn from_packet(packet: &[u8]) -> Option<Option<Header>> {
+        let name_len = packet.iter().position(|&b| b == 0)?;
+        if name_len == 0 {
+            return Some(None);
+        }
+
+        let name = str::from_utf8(&packet[..name_len]).ok()?;
+        let digits = packet[name_len + 1..].iter()
+            .take_while(|&&b| b != 0 && b != b' ');
+
+        let mut size: u64 = 0;
+        let mut any = false;
+        for &digit in digits {
+            if !digit.is_ascii_digit() {
+                return None;
+            }
+
+            size = size.checked_mul(10)?.checked_add((digit - b'0') as u64)?;
+            any = true;
+        }
+
+        if !any {
+            return None;
+        }
+
+        Header::new(name, size).map(Some)
+    }
+}
+
+impl fmt::Debug for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Header").field("name", &self.name()).field("size", &self.size).finish()
+    }
+}
+
+/// A writer that passes on the first `remaining` bytes written to it and
+/// discards the rest, to strip the padding after a file's last byte.
+struct Truncate<W> {
+    inner: W,
+    remaining: u64,
+}
+
+impl<W: io::Write> io::Write for Truncate<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = (buf.len() as u64).min(self.remaining) as usize;
+        self.inner.write_all(&buf[..n])?;
+        self.remaining -= n as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: io::Read + io::Write> Xmodem<T> {
+    /// Resets the transfer state for the next file of a batch, which starts
+    /// with the receiver asking for its header.
+    fn next_file(&mut self) {
+        self.packet = 0;
+        self.started = false;
+        self.heard = false;
+        self.crc = true;
+        self.crc_requests = 0;
+    }
+
+    /// Transmits one file of a YMODEM batch: the header `header`, then `data`,
+    /// which must yield `header.size` bytes, as an XMODEM transfer. Returns the
+    /// number of bytes of `data` sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `transmit_all()`.
+    pub fn transmit_file<R: io::Read>(&mut self, header: &Header, data: R) -> io::Result<usize> {
+        self.next_file();
+        self.write_packet_retrying(&header.to_packet())?;
+
+        // The receiver asks for the data separately. Progress counts the
+        // file's bytes only.
+        self.started = false;
+        self.bytes = 0;
+        self.transmit_packets(data, false)
+    }
+
+    /// Ends a YMODEM batch by sending the empty header.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `transmit_all()`.
+    pub fn end_batch(&mut self) -> io::Result<()> {
+        self.next_file();
+        self.write_packet_retrying(&[0u8; 128])
+    }
+
+    /// Receives the header of the next file of a YMODEM batch. Returns `None`
+    /// once the batch has ended. The file itself must then be received with
+    /// `receive_file()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `receive_all()`, and an error of kind
+    /// `InvalidData` if the sender ends the transmission instead of sending a
+    /// header, or if the header is malformed.
+    pub fn receive_header(&mut self) -> io::Result<Option<Header>> {
+        self.next_file();
+        let mut packet = [0u8; 1024];
+        let n = self.read_packet_retrying(&mut packet)?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Expected a YMODEM header"));
+        }
+
+        // The receiver asks for the data separately. Progress counts the
+        // file's bytes only.
+        self.started = false;
+        self.bytes = 0;
+        Header::from_packet(&packet[..n])
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Malformed YMODEM header"))
+    }
+
+    /// Receives the file whose header `receive_header()` returned and writes
+    /// its `header.size` bytes into `into`; the padding is discarded. Returns
+    /// the number of bytes written.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `receive_all()`, and an error of kind
+    /// `UnexpectedEof` if the transmission ends before `header.size` bytes.
+    pub fn receive_file<W: io::Write>(&mut self, header: &Header, into: W) -> io::Result<u64> {
+        let mut into = Truncate { inner: into, remaining: header.size };
+        self.receive_all(&mut into)?;
+        if into.remaining != 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "YMODEM file is truncated"));
+        }
+
+        Ok(header.size)
+    }
+}
@@ -2,22 +2,113 @@ use std::{fmt, io};
 
 use traits::BlockDevice;
 
+/// A cylinder-head-sector address.
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct CHS {
-    // FIXME: Fill me in.
+    head: u8,
+    /// Bits 0-5 are the sector; bits 6-7 are the high bits of the cylinder.
+    sector_cylinder: u8,
+    /// The low 8 bits of the cylinder.
+    cylinder: u8,
 }
 
+impl CHS {
+    /// The head.
+    pub fn head(&self) -> u8 {
+        self.head
+    }
+
+    /// The sector, from 1 to 63.
+    pub fn sector(&self) -> u8 {
+        self.sector_cylinder & 0b111111
+    }
+
+    /// The cylinder, from 0 to 1023.
+    pub fn cylinder(&self) -> u16 {
+        (((self.sector_cylinder as u16) & 0b11000000) << 2) | self.cylinder as u16
+    }
+}
+
+impl fmt::Debug for CHS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CHS")
+            .field("head", &self.head())
+            .field("sector", &self.sector())
+            .field("cylinder", &self.cylinder())
+            .finish()
+    }
+}
+
+/// The partition type of a FAT32 partition addressed by LBA.
+pub const PARTITION_TYPE_FAT32_LBA: u8 = 0xC;
+
+/// The partition type of a FAT32 partition addressed by CHS.
+pub const PARTITION_TYPE_FAT32_CHS: u8 = 0xB;
+
+/// The partition type of a FAT12 partition.
+pub const PARTITION_TYPE_FAT12: u8 = 0x1;
+
+/// The partition type of a FAT16 partition of fewer than 65536 sectors.
+pub const PARTITION_TYPE_FAT16_SMALL: u8 = 0x4;
+
+/// The partition type of a FAT16 partition addressed by CHS.
+pub const PARTITION_TYPE_FAT16_CHS: u8 = 0x6;
+
+/// The partition type of a FAT16 partition addressed by LBA.
+pub const PARTITION_TYPE_FAT16_LBA: u8 = 0xE;
+
 #[repr(C, packed)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Copy, Clone)]
 pub struct PartitionEntry {
-    // FIXME: Fill me in.
+    /// `0x80` if the partition is bootable, `0` otherwise.
+    pub boot_indicator: u8,
+    pub start: CHS,
+    /// The partition type, such as `PARTITION_TYPE_FAT32_LBA`. `0` means the
+    /// entry is unused.
+    pub partition_type: u8,
+    pub end: CHS,
+    /// The first sector of the partition.
+    pub relative_sector: u32,
+    /// The number of sectors in the partition.
+    pub total_sectors: u32,
+}
+
+impl PartitionEntry {
+    /// Returns `true` if the partition is marked bootable.
+    pub fn bootable(&self) -> bool {
+        self.boot_indicator == 0x80
+    }
+
+    /// Returns `true` if the entry describes a partition.
+    pub fn in_use(&self) -> bool {
+        self.partition_type != 0
+    }
+
+    /// Returns `true` if the partition holds a FAT32 file system.
+    pub fn is_fat32(&self) -> bool {
+        self.partition_type == PARTITION_TYPE_FAT32_LBA
+            || self.partition_type == PARTITION_TYPE_FAT32_CHS
+    }
+
+    /// Returns `true` if the partition holds a FAT12, FAT16 or FAT32 file
+    /// system.
+    pub fn is_fat(&self) -> bool {
+        match self.partition_type {
+            PARTITION_TYPE_FAT12 | PARTITION_TYPE_FAT16_SMALL | PARTITION_TYPE_FAT16_CHS
+                | PARTITION_TYPE_FAT16_LBA => true,
+            _ => self.is_fat32(),
+        }
+    }
 }
 
 /// The master boot record (MBR).
 #[repr(C, packed)]
 pub struct MasterBootRecord {
-    // FIXME: Fill me in.
+    bootstrap: [u8; 436],
+    disk_id: [u8; 10],
+    pub partitions: [PartitionEntry; 4],
+    signature: [u8; 2],
 }
 
 #[derive(Debug)]
@@ -40,12 +131,44 @@ impl MasterBootRecord {
     /// boot indicator. Returns `Io(err)` if the I/O error `err` occured while
     /// reading the MBR.
     pub fn from<T: BlockDevice>(mut device: T) -> Result<MasterBootRecord, Error> {
-        unimplemented!("MasterBootRecord::from()")
+        let mut sector = [0u8; 512];
+        let read = device.read_sector(0, &mut sector).map_err(Error::Io)?;
+        if read != sector.len() {
+            return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                "short read of the MBR")));
+        }
+
+        let mbr: MasterBootRecord = unsafe { ::std::mem::transmute(sector) };
+        if mbr.signature != [0x55, 0xAA] {
+            return Err(Error::BadSignature);
+        }
+
+        for (i, partition) in mbr.partitions.iter().enumerate() {
+            if partition.boot_indicator != 0 && partition.boot_indicator != 0x80 {
+                return Err(Error::UnknownBootIndicator(i as u8));
+            }
+        }
+
+        Ok(mbr)
+    }
+
+    /// Returns the first partition holding a FAT32 file system, if any.
+    pub fn first_fat32(&self) -> Option<&PartitionEntry> {
+        self.partitions.iter().find(|partition| partition.is_fat32())
+    }
+
+    /// Returns the first partition holding a FAT12, FAT16 or FAT32 file
+    /// system, if any.
+    pub fn first_fat(&self) -> Option<&PartitionEntry> {
+        self.partitions.iter().find(|partition| partition.is_fat())
     }
 }
 
 impl fmt::Debug for MasterBootRecord {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        unimplemented!("MasterBootRecord::fmt()")
+        f.debug_struct("MasterBootRecord")
+            .field("disk_id", &self.disk_id)
+            .field("partitions", &self.partitions)
+            .finish()
     }
 }
@@ -3,11 +3,263 @@ extern crate rand;
 use std::io::prelude::*;
 use std::io::Cursor;
 use std::path::Path;
+use std::sync::{Arc, Mutex};
 
 use vfat::{Shared, VFat, BiosParameterBlock};
 use mbr::{MasterBootRecord, CHS, PartitionEntry};
 use traits::*;
 
+/// Builds a FAT32 disk image in memory: an MBR whose first partition starts
+/// at sector 1 and holds a file system with 512-byte sectors, one sector per
+/// cluster, two FATs of one sector each and the root directory at cluster 2.
+/// Directories are a single cluster long.
+struct Image {
+    data: Vec<u8>,
+    next_cluster: u32,
+}
+
+const IMAGE_PARTITION: usize = 1;
+const IMAGE_FAT: usize = IMAGE_PARTITION + 2;
+const IMAGE_DATA: usize = IMAGE_FAT + 2;
+const IMAGE_CLUSTERS: u32 = 128;
+const IMAGE_EOC: u32 = 0x0FFFFFFF;
+
+/// A directory entry for the 8.3 name `name` (padded, without the dot).
+fn dir_entry(name: &[u8; 11], attributes: u8, cluster: u32, size: u32) -> [u8; 32] {
+    let mut entry = [0u8; 32];
+    entry[..11].copy_from_slice(name);
+    entry[11] = attributes;
+    entry[20..22].copy_from_slice(&[(cluster >> 16) as u8, (cluster >> 24) as u8]);
+    entry[26..28].copy_from_slice(&[cluster as u8, (cluster >> 8) as u8]);
+    entry[28..32].copy_from_slice(&[size as u8, (size >> 8) as u8, (size >> 16) as u8,
+                                    (size >> 24) as u8]);
+    entry
+}
+
+/// The LFN entries naming the entry with 8.3 name `short` `name`, in the
+/// order they appear on disk.
+fn lfn_entries(name: &str, short: &[u8; 11]) -> Vec<[u8; 32]> {
+    let checksum = short.iter().fold(0u8, |sum, &b| {
+        ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b)
+    });
+
+    let mut chars: Vec<u16> = name.encode_utf16().collect();
+    if chars.len() % 13 != 0 {
+        chars.push(0);
+    }
+
+    while chars.len() % 13 != 0 {
+        chars.push(0xFFFF);
+    }
+
+    let parts = chars.len() / 13;
+    let mut entries = Vec::new();
+    for (i, part) in chars.chunks(13).enumerate().rev() {
+        let mut entry = [0u8; 32];
+        entry[0] = (i + 1) as u8 | if i + 1 == parts { 0x40 } else { 0 };
+        entry[11] = 0x0F;
+        entry[13] = checksum;
+        let offsets = (0..5).map(|j| 1 + 2 * j)
+            .chain((0..6).map(|j| 14 + 2 * j))
+            .chain((0..2).map(|j| 28 + 2 * j));
+        for (offset, &c) in offsets.zip(part.iter()) {
+            put_u16(&mut entry, offset, c);
+        }
+
+        entries.push(entry);
+    }
+
+    entries
+}
+
+fn put_u16(data: &mut [u8], at: usize, value: u16) {
+    data[at..at + 2].copy_from_slice(&[value as u8, (value >> 8) as u8]);
+}
+
+fn put_u32(data: &mut [u8], at: usize, value: u32) {
+    put_u16(data, at, value as u16);
+    put_u16(data, at + 2, (value >> 16) as u16);
+}
+
+impl Image {
+    fn new() -> Image {
+        let sectors = IMAGE_DATA + IMAGE_CLUSTERS as usize - 2;
+        let mut data = vec![0u8; sectors * 512];
+
+        // The MBR, with one FAT32 partition.
+        data[446 + 4] = 0x0C;
+        put_u32(&mut data, 446 + 8, IMAGE_PARTITION as u32);
+        put_u32(&mut data, 446 + 12, (sectors - IMAGE_PARTITION) as u32);
+        data[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+        // The EBPB.
+        let ebpb = IMAGE_PARTITION * 512;
+        put_u16(&mut data, ebpb + 11, 512);
+        data[ebpb + 13] = 1;
+        put_u16(&mut data, ebpb + 14, (IMAGE_FAT - IMAGE_PARTITION) as u16);
+        data[ebpb + 16] = 2;
+        put_u32(&mut data, ebpb + 32, (sectors - IMAGE_PARTITION) as u32);
+        put_u32(&mut data, ebpb + 36, 1);
+        put_u32(&mut data, ebpb + 44, 2);
+        put_u16(&mut data, ebpb + 48, 1);
+        data[ebpb + 66] = 0x29;
+        data[ebpb + 510..ebpb + 512].copy_from_slice(&[0x55, 0xAA]);
+
+        let mut image = Image { data, next_cluster: 2 };
+        image.set_fat(0, 0x0FFFFFF8);
+        image.set_fat(1, IMAGE_EOC);
+        image.add_chain(&[0; 512], 1);
+        image
+    }
+
+    fn set_fat(&mut self, cluster: u32, value: u32) {
+        for fat in 0..2 {
+            let at = (IMAGE_FAT + fat) * 512 + cluster as usize * 4;
+            put_u32(&mut self.data, at, value);
+        }
+    }
+
+    fn cluster(&mut self, cluster: u32) -> &mut [u8] {
+        let start = (IMAGE_DATA + cluster as usize - 2) * 512;
+        &mut self.data[start..start + 512]
+    }
+
+    /// Writes `contents` to newly allocated clusters `stride` clusters apart
+    /// and chains them. Returns the first cluster, or 0 if `contents` is
+    /// empty.
+    fn add_chain(&mut self, contents: &[u8], stride: u32) -> u32 {
+        let mut first = 0;
+        let mut previous = None;
+        for chunk in contents.chunks(512) {
+            let cluster = self.next_cluster;
+            self.next_cluster += stride;
+            self.cluster(cluster)[..chunk.len()].copy_from_slice(chunk);
+            self.set_fat(cluster, IMAGE_EOC);
+            match previous {
+                Some(previous) => self.set_fat(previous, cluster),
+                None => first = cluster,
+            }
+
+            previous = Some(cluster);
+        }
+
+        first
+    }
+
+    /// Adds the raw entry `entry` to the directory at cluster `dir`.
+    fn add_entry(&mut self, dir: u32, entry: [u8; 32]) {
+        let slot = self.cluster(dir).chunks(32).position(|e| e[0] == 0).expect("room");
+        self.cluster(dir)[slot * 32..(slot + 1) * 32].copy_from_slice(&entry);
+    }
+
+    fn add_file(&mut self, dir: u32, name: &[u8; 11], contents: &[u8], stride: u32) -> u32 {
+        let cluster = self.add_chain(contents, stride);
+        self.add_entry(dir, dir_entry(name, 0x20, cluster, contents.len() as u32));
+        cluster
+    }
+
+    /// Adds a directory to the directory at cluster `dir`, which is the root
+    /// directory if `dir` is 2.
+    fn add_dir(&mut self, dir: u32, name: &[u8; 11]) -> u32 {
+        let cluster = self.add_chain(&[0; 512], 1);
+        self.add_entry(dir, dir_entry(name, 0x10, cluster, 0));
+        self.add_entry(cluster, dir_entry(b".          ", 0x10, cluster, 0));
+        self.add_entry(cluster, dir_entry(b"..         ", 0x10, if dir == 2 { 0 } else { dir }, 0));
+        cluster
+    }
+
+    /// Adds a directory holding `entries` to the directory at cluster `dir`.
+    /// Unlike `add_dir`'s, its entries can span several clusters.
+    fn add_dir_entries(&mut self, dir: u32, name: &[u8; 11], entries: &[[u8; 32]]) -> u32 {
+        let mut contents: Vec<u8> = entries.iter().flat_map(|e| e.iter().cloned()).collect();
+        let len = (contents.len() / 512 + 1) * 512;
+        contents.resize(len, 0);
+        let cluster = self.add_chain(&contents, 1);
+        self.add_entry(dir, dir_entry(name, 0x10, cluster, 0));
+        cluster
+    }
+
+    /// Writes a valid FSInfo sector holding the hints `free` and `next`.
+    fn set_fsinfo(&mut self, free: u32, next: u32) {
+        let at = (IMAGE_PARTITION + 1) * 512;
+        put_u32(&mut self.data, at, 0x41615252);
+        put_u32(&mut self.data, at + 484, 0x61417272);
+        put_u32(&mut self.data, at + 488, free);
+        put_u32(&mut self.data, at + 492, next);
+        put_u32(&mut self.data, at + 508, 0xAA550000);
+    }
+
+    fn vfat(self) -> Shared<VFat> {
+        VFat::from(Cursor::new(self.data)).expect("valid image")
+    }
+
+    fn disk(self) -> Disk {
+        Disk(Arc::new(Mutex::new(self.data)))
+    }
+}
+
+/// A disk whose contents outlive the file systems mounted from it, so that
+/// what a file system wrote back can be checked by mounting it again.
+#[derive(Clone)]
+struct Disk(Arc<Mutex<Vec<u8>>>);
+
+impl Disk {
+    fn vfat(&self) -> Shared<VFat> {
+        VFat::from(self.clone()).expect("valid image")
+    }
+
+    fn read_u32(&self, at: usize) -> u32 {
+        let data = self.0.lock().unwrap();
+        (0..4).fold(0, |value, i| value | (data[at + i] as u32) << (8 * i))
+    }
+}
+
+impl BlockDevice for Disk {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> ::std::io::Result<usize> {
+        let data = self.0.lock().unwrap();
+        let start = n as usize * 512;
+        let len = ::std::cmp::min(buf.len(), 512);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> ::std::io::Result<usize> {
+        let mut data = self.0.lock().unwrap();
+        let start = n as usize * 512;
+        let len = ::std::cmp::min(buf.len(), 512);
+        data[start..start + len].copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
+
+/// Returns `len` bytes of a pattern that differs from cluster to cluster.
+fn pattern(len: usize, seed: u8) -> Vec<u8> {
+    (0..len).map(|i| (i as u8).wrapping_mul(31).wrapping_add((i / 512) as u8 ^ seed)).collect()
+}
+
+/// The image most tests use: two files in the root directory, one of them
+/// fragmented, and a directory holding one more.
+fn mock_image() -> Image {
+    let mut image = Image::new();
+    image.add_entry(2, dir_entry(b"VOLUME     ", 0x08, 0, 0));
+    image.add_file(2, b"HELLO   TXT", b"Hello, world!\n", 1);
+    image.add_file(2, b"BIG     BIN", &pattern(1500, 7), 2);
+    let mut deleted = dir_entry(b"GONE    TXT", 0x20, 0, 0);
+    deleted[0] = 0xE5;
+    image.add_entry(2, deleted);
+    image.add_file(2, b"EMPTY      ", &[], 1);
+
+    let docs = image.add_dir(2, b"DOCS       ");
+    let mut notes = dir_entry(b"NOTES   MD ", 0x21, 0, 0);
+    notes[12] = 0x18;
+    image.add_entry(docs, notes);
+    image
+}
+
+fn names<T: Dir>(dir: T) -> Vec<String> {
+    dir.entries().expect("entries").map(|entry| entry.name().to_string()).collect()
+}
+
 macro check_size($T:ty, $size:expr) {
     assert_eq!(::std::mem::size_of::<$T>(), $size,
         "'{}' does not have the expected size of {}", stringify!($T), $size);
@@ -90,6 +342,29 @@ fn check_mbr_boot_indicator() {
     MasterBootRecord::from(Cursor::new(&mut data[..])).unwrap();
 }
 
+#[test]
+fn check_mbr_partitions() {
+    let mut data = [0u8; 512];
+    data[510..].copy_from_slice(&[0x55, 0xAA]);
+    data[446 + 16..446 + 32].copy_from_slice(&[
+        0x80, 0x01, 0xC1, 0x02, 0x0C, 0x03, 0xC4, 0xFF,
+        0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00
+    ]);
+
+    let mbr = MasterBootRecord::from(Cursor::new(&mut data[..])).unwrap();
+    assert!(!mbr.partitions[0].in_use());
+
+    let partition = &mbr.partitions[1];
+    assert!(partition.bootable() && partition.in_use() && partition.is_fat32());
+    assert_eq!({ partition.relative_sector }, 2048);
+    assert_eq!({ partition.total_sectors }, 1 << 20);
+    assert_eq!(partition.start.head(), 1);
+    assert_eq!(partition.start.sector(), 1);
+    assert_eq!(partition.start.cylinder(), 0x302);
+    assert_eq!(partition.end.cylinder(), 0x3FF);
+    assert_eq!({ mbr.first_fat32().unwrap().relative_sector }, 2048);
+}
+
 #[test]
 fn test_mbr() {
     let mut mbr = resource!("mbr.img");
@@ -349,3 +624,714 @@ fn shared_fs_is_sync_send_static() {
     fn f<T: Sync + Send + 'static>() {  }
     f::<Shared<VFat>>();
 }
+
+#[test]
+fn fat_entry_status() {
+    use vfat::{FatEntry, Status, Cluster};
+
+    assert_eq!(FatEntry(0).status(), Status::Free);
+    assert_eq!(FatEntry(1).status(), Status::Reserved);
+    assert_eq!(FatEntry(2).status(), Status::Data(Cluster::from(2)));
+    assert_eq!(FatEntry(0xF0000009).status(), Status::Data(Cluster::from(9)));
+    assert_eq!(FatEntry(0x0FFFFFF0).status(), Status::Reserved);
+    assert_eq!(FatEntry(0x0FFFFFF7).status(), Status::Bad);
+    assert_eq!(FatEntry(0x0FFFFFF8).status(), Status::Eoc(0x0FFFFFF8));
+    assert_eq!(FatEntry(0xFFFFFFFF).status(), Status::Eoc(0x0FFFFFFF));
+
+    use vfat::FatType;
+    assert_eq!(FatType::Fat16.entry(0x1234).status(), Status::Data(Cluster::from(0x1234)));
+    assert_eq!(FatType::Fat16.entry(0xFFF7).status(), Status::Bad);
+    assert_eq!(FatType::Fat16.entry(0xFFF8).status(), Status::Eoc(0x0FFFFFF8));
+    assert_eq!(FatType::Fat12.entry(0xFEF).status(), Status::Data(Cluster::from(0xFEF)));
+    assert_eq!(FatType::Fat12.entry(0xFF0).status(), Status::Reserved);
+    assert_eq!(FatType::Fat12.entry(0xFFF).status(), Status::Eoc(0x0FFFFFFF));
+    assert_eq!(FatType::Fat32.entry(0xF0000009).status(), Status::Data(Cluster::from(9)));
+    assert_eq!(FatType::from_clusters(4084), FatType::Fat12);
+    assert_eq!(FatType::from_clusters(4085), FatType::Fat16);
+}
+
+#[test]
+fn mock_root_entries() {
+    let vfat = mock_image().vfat();
+    assert_eq!(names(vfat.open_dir("/").unwrap()),
+               vec!["HELLO.TXT", "BIG.BIN", "EMPTY", "DOCS"]);
+
+    let docs = vfat.open_dir("/docs").unwrap();
+    assert_eq!(names(docs), vec![".", "..", "notes.md"]);
+
+    let notes = vfat.open("/DOCS/NOTES.MD").unwrap();
+    assert!(notes.is_file() && notes.metadata().read_only() && !notes.metadata().hidden());
+    assert_eq!(notes.name(), "notes.md");
+    assert!(vfat.open("/docs").unwrap().is_dir());
+}
+
+#[test]
+fn mock_timestamps() {
+    let mut image = mock_image();
+    let mut entry = dir_entry(b"STAMPED    ", 0x20, 0, 0);
+    // 2018-03-17 13:45:58 created, 2018-03-18 accessed, 2019-12-31 modified.
+    put_u16(&mut entry, 14, 13 << 11 | 45 << 5 | 29);
+    put_u16(&mut entry, 16, 38 << 9 | 3 << 5 | 17);
+    put_u16(&mut entry, 18, 38 << 9 | 3 << 5 | 18);
+    put_u16(&mut entry, 22, 23 << 11 | 59 << 5 | 1);
+    put_u16(&mut entry, 24, 39 << 9 | 12 << 5 | 31);
+    image.add_entry(2, entry);
+
+    let vfat = image.vfat();
+    let entry = vfat.open("/stamped").unwrap();
+    let metadata = entry.metadata();
+    let created = metadata.created();
+    assert_eq!((created.year(), created.month(), created.day()), (2018, 3, 17));
+    assert_eq!((created.hour(), created.minute(), created.second()), (13, 45, 58));
+    let accessed = metadata.accessed();
+    assert_eq!((accessed.year(), accessed.month(), accessed.day(), accessed.hour()),
+               (2018, 3, 18, 0));
+    let modified = metadata.modified();
+    assert_eq!((modified.year(), modified.month(), modified.day()), (2019, 12, 31));
+    assert_eq!((modified.hour(), modified.minute(), modified.second()), (23, 59, 2));
+    assert_eq!(metadata.to_string(), "----a 2019-12-31 23:59:02");
+
+    // Timestamps convert to calendar dates, which order chronologically.
+    assert_eq!(created.date_time(),
+               DateTime { year: 2018, month: 3, day: 17, hour: 13, minute: 45, second: 58 });
+    assert_eq!(accessed.date_time().to_string(), "2018-03-18 00:00:00");
+    assert!(created.date_time() < accessed.date_time());
+    assert!(accessed.date_time() < modified.date_time());
+}
+
+#[test]
+fn mock_attributes() {
+    let mut image = mock_image();
+    image.add_entry(2, dir_entry(b"FLAGGED    ", 0x27, 0, 0));
+    image.add_entry(2, dir_entry(b"PLAIN      ", 0x00, 0, 0));
+
+    let vfat = image.vfat();
+    let flagged = vfat.open("/flagged").unwrap();
+    let metadata = flagged.metadata();
+    assert!(metadata.read_only() && metadata.hidden() && metadata.system() && metadata.archive());
+    assert_eq!(metadata.attributes.to_string(), "-rhsa");
+
+    let plain = vfat.open("/plain").unwrap();
+    let metadata = plain.metadata();
+    assert!(!metadata.read_only() && !metadata.hidden() && !metadata.system() && !metadata.archive());
+    assert_eq!(vfat.open("/docs").unwrap().metadata().attributes.to_string(), "d----");
+}
+
+#[test]
+fn mock_file_reads() {
+    let vfat = mock_image().vfat();
+
+    let mut hello = String::new();
+    vfat.open_file("/hello.txt").unwrap().read_to_string(&mut hello).unwrap();
+    assert_eq!(hello, "Hello, world!\n");
+
+    let mut empty = Vec::new();
+    let file = vfat.open_file("/EMPTY").unwrap();
+    assert_eq!(file.size(), 0);
+    file.take(16).read_to_end(&mut empty).unwrap();
+    assert!(empty.is_empty());
+
+    // Reads of every size cross the fragmented file's cluster boundaries.
+    let expected = pattern(1500, 7);
+    for &chunk in [1, 7, 511, 512, 513, 2000].iter() {
+        let mut file = vfat.open_file("/big.bin").unwrap();
+        let mut contents = Vec::new();
+        let mut buffer = vec![0; chunk];
+        loop {
+            match file.read(&mut buffer).unwrap() {
+                0 => break,
+                n => contents.extend_from_slice(&buffer[..n]),
+            }
+        }
+
+        assert!(contents == expected, "reading {} bytes at a time", chunk);
+    }
+}
+
+#[test]
+fn mock_file_seeks() {
+    use std::io::SeekFrom;
+
+    let vfat = mock_image().vfat();
+    let expected = pattern(1500, 7);
+    let mut file = vfat.open_file("/big.bin").unwrap();
+
+    let mut buffer = [0u8; 8];
+    for &offset in [1020, 0, 512, 1499, 3].iter() {
+        assert_eq!(file.seek(SeekFrom::Start(offset)).unwrap(), offset);
+        let n = file.read(&mut buffer).unwrap();
+        let offset = offset as usize;
+        assert_eq!(&buffer[..n], &expected[offset..::std::cmp::min(offset + 8, 1500)]);
+    }
+
+    assert_eq!(file.seek(SeekFrom::End(-4)).unwrap(), 1496);
+    assert_eq!(file.seek(SeekFrom::Current(-1496)).unwrap(), 0);
+    assert_eq!(file.seek(SeekFrom::End(0)).unwrap(), 1500);
+    assert_eq!(file.read(&mut buffer).unwrap(), 0);
+
+    let e = file.seek(SeekFrom::End(1)).unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::InvalidInput);
+    let e = file.seek(SeekFrom::Current(-1501)).unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::InvalidInput);
+    assert_eq!(file.seek(SeekFrom::Current(0)).unwrap(), 1500);
+
+    // Reads continue from the position across clusters, in any size.
+    file.seek(SeekFrom::Start(500)).unwrap();
+    let mut contents = Vec::new();
+    let mut chunk = [0u8; 37];
+    loop {
+        match file.read(&mut chunk).unwrap() {
+            0 => break,
+            n => contents.extend_from_slice(&chunk[..n]),
+        }
+    }
+
+    assert_eq!(contents, &expected[500..]);
+    file.seek(SeekFrom::Current(-1000)).unwrap();
+    file.read_exact(&mut buffer).unwrap();
+    assert_eq!(&buffer, &expected[500..508]);
+}
+
+#[test]
+fn mock_open_paths() {
+    use std::io::ErrorKind;
+
+    let vfat = mock_image().vfat();
+    assert_eq!(vfat.open("/docs/../hello.txt").unwrap().name(), "HELLO.TXT");
+    assert_eq!(vfat.open("/docs/./notes.md").unwrap().name(), "notes.md");
+    assert_eq!(vfat.open("/../docs/..").unwrap().name(), "");
+    assert_eq!(names(vfat.open_dir("/docs/..").unwrap()).len(), 4);
+
+    assert_eq!(vfat.open("docs").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(vfat.open("/nothing").unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(vfat.open("/docs/nothing").unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(vfat.open("/hello.txt/x").unwrap_err().kind(), ErrorKind::InvalidInput);
+    assert_eq!(vfat.open_dir("/hello.txt").unwrap_err().kind(), ErrorKind::Other);
+    assert_eq!(vfat.open_file("/docs").unwrap_err().kind(), ErrorKind::Other);
+}
+
+#[test]
+fn mock_broken_chains() {
+    let mut image = mock_image();
+    let cluster = image.add_file(2, b"BROKEN     ", &pattern(1024, 1), 1);
+    image.set_fat(cluster, 0);
+    let looped = image.add_dir(2, b"LOOP       ");
+    image.set_fat(looped, looped);
+
+    let vfat = image.vfat();
+    let mut contents = Vec::new();
+    let e = vfat.open_file("/broken").unwrap().read_to_end(&mut contents).unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::InvalidData);
+
+    // The cluster before the break is still read; the error comes next.
+    let mut file = vfat.open_file("/broken").unwrap();
+    let mut buffer = [0u8; 1024];
+    assert_eq!(file.read(&mut buffer).unwrap(), 512);
+    assert_eq!(&buffer[..512], &pattern(1024, 1)[..512]);
+    assert_eq!(file.read(&mut buffer).unwrap_err().kind(), ::std::io::ErrorKind::InvalidData);
+
+    let e = vfat.open_dir("/loop").unwrap().entries().map(|_| ()).unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn mock_requires_a_fat32_partition() {
+    let mut image = Image::new();
+    image.data[446 + 4] = 0x83;
+    match VFat::from(Cursor::new(image.data)) {
+        Err(::vfat::Error::NotFound) => {  },
+        Err(e) => panic!("expected NotFound but found {:?}", e),
+        Ok(_) => panic!("expected NotFound but mounted"),
+    }
+}
+
+#[test]
+fn mock_long_names() {
+    let mut image = mock_image();
+    let mut entries = Vec::new();
+    let long = image.add_chain(b"long", 1);
+    {
+        let mut add = |name: &str, short: &[u8; 11], cluster: u32, size: u32| {
+            entries.extend(lfn_entries(name, short));
+            entries.push(dir_entry(short, 0x20, cluster, size));
+        };
+
+        add("A long file name.txt", b"ALONGF~1TXT", long, 4);
+        add("exactly 13 ch", b"EXACTL~1   ", 0, 0);
+        add("na\u{EF}ve \u{2603}.md", b"NAVE~1  MD ", 0, 0);
+        add(&"x".repeat(255), b"XXXXXX~1   ", 0, 0);
+    }
+
+    // A stray LFN entry, a name whose checksum doesn't match and one whose
+    // entries are out of order all leave the 8.3 name.
+    entries.push(lfn_entries("stray", b"STRAY      ")[0]);
+    entries.push(dir_entry(b"PLAIN      ", 0x20, 0, 0));
+    entries.extend(lfn_entries("mismatched", b"OTHER      "));
+    entries.push(dir_entry(b"MISMAT~1   ", 0x20, 0, 0));
+    entries.extend(lfn_entries("a name in two parts", b"ANAMEI~1   ").into_iter().rev());
+    entries.push(dir_entry(b"ANAMEI~1   ", 0x20, 0, 0));
+
+    // A deleted name doesn't carry over to the next entry.
+    for mut entry in lfn_entries("deleted", b"AFTER      ") {
+        entry[0] = 0xE5;
+        entries.push(entry);
+    }
+
+    entries.push(dir_entry(b"AFTER      ", 0x20, 0, 0));
+    image.add_dir_entries(2, b"LONG       ", &entries);
+
+    let vfat = image.vfat();
+    assert_eq!(names(vfat.open_dir("/long").unwrap()),
+               vec!["A long file name.txt".to_string(), "exactly 13 ch".to_string(),
+                    "na\u{EF}ve \u{2603}.md".to_string(), "x".repeat(255),
+                    "PLAIN".to_string(), "MISMAT~1".to_string(), "ANAMEI~1".to_string(),
+                    "AFTER".to_string()]);
+
+    let mut contents = String::new();
+    let mut file = vfat.open_file("/long/a LONG file name.TXT").unwrap();
+    file.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "long");
+    assert!(vfat.open("/long/na\u{EF}ve \u{2603}.md").is_ok());
+    assert!(vfat.open(format!("/long/{}", "X".repeat(255))).is_ok());
+    assert!(vfat.open("/long/alongf~1.txt").is_err());
+}
+
+#[test]
+fn mock_file_writes() {
+    use std::io::SeekFrom;
+
+    let disk = mock_image().disk();
+    let expected = pattern(1300, 3);
+    {
+        let vfat = disk.vfat();
+        let mut file = vfat.open_file("/hello.txt").unwrap();
+        file.seek(SeekFrom::Start(7)).unwrap();
+        file.write_all(b"there").unwrap();
+        file.seek(SeekFrom::End(0)).unwrap();
+        file.write_all(b"Bye.\n").unwrap();
+        assert_eq!(file.size(), 19);
+
+        // An empty file gets its first cluster, and more, as it grows.
+        let mut file = vfat.open_file("/empty").unwrap();
+        for chunk in expected.chunks(100) {
+            file.write_all(chunk).unwrap();
+        }
+
+        assert_eq!(file.size(), 1300);
+    }
+
+    let vfat = disk.vfat();
+    let mut hello = String::new();
+    vfat.open_file("/hello.txt").unwrap().read_to_string(&mut hello).unwrap();
+    assert_eq!(hello, "Hello, there!\nBye.\n");
+
+    let mut contents = Vec::new();
+    let empty = vfat.open("/empty").unwrap();
+    assert!(empty.metadata().attributes.archive());
+    empty.into_file().unwrap().read_to_end(&mut contents).unwrap();
+    assert!(contents == expected);
+}
+
+#[test]
+fn mock_file_set_len() {
+    let mut image = mock_image();
+    image.set_fsinfo(100, 2);
+    let disk = image.disk();
+    let vfat = disk.vfat();
+    let free = vfat.borrow().free_clusters().unwrap();
+
+    let mut file = vfat.open_file("/big.bin").unwrap();
+    file.set_len(600).unwrap();
+    assert_eq!(vfat.borrow().free_clusters(), Some(free + 1));
+    file.set_len(0).unwrap();
+    assert_eq!(vfat.borrow().free_clusters(), Some(free + 3));
+    file.set_len(1000).unwrap();
+    assert_eq!(vfat.borrow().free_clusters(), Some(free + 1));
+
+    let mut contents = Vec::new();
+    disk.vfat().open_file("/big.bin").unwrap().read_to_end(&mut contents).unwrap();
+    assert!(contents == vec![0; 1000]);
+    assert_eq!(disk.read_u32((IMAGE_PARTITION + 1) * 512 + 488), free + 1);
+}
+
+#[test]
+fn mock_create_and_remove() {
+    use std::io::ErrorKind;
+
+    let mut image = mock_image();
+    image.set_fsinfo(100, 2);
+    let disk = image.disk();
+    {
+        let vfat = disk.vfat();
+        vfat.create_dir("/a/b", true).unwrap();
+        vfat.create_file("/a/b/A long file name.txt").unwrap().write_all(b"long").unwrap();
+        vfat.create_file("/a/short.txt").unwrap();
+        vfat.create_file("/a/Mixed.Txt").unwrap();
+
+        let e = |r: ::std::io::Result<::vfat::File>| r.map(|_| ()).unwrap_err().kind();
+        assert_eq!(e(vfat.create_file("/A/SHORT.TXT")), ErrorKind::AlreadyExists);
+        assert_eq!(e(vfat.create_file("/a/b")), ErrorKind::AlreadyExists);
+        assert_eq!(e(vfat.create_file("/none/file")), ErrorKind::InvalidInput);
+        assert_eq!(e(vfat.create_file("/a/what?")), ErrorKind::InvalidInput);
+        assert_eq!(e(vfat.create_file("a/file")), ErrorKind::InvalidInput);
+        assert_eq!(vfat.create_dir("/x/y", false).map(|_| ()).unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+    }
+
+    let vfat = disk.vfat();
+    assert_eq!(names(vfat.open_dir("/a").unwrap()),
+               vec![".", "..", "b", "short.txt", "Mixed.Txt"]);
+    assert_eq!(names(vfat.open_dir("/a/b").unwrap()),
+               vec![".", "..", "A long file name.txt"]);
+    assert_eq!(vfat.open("/a/b/..").unwrap().name(), "a");
+    assert_eq!(vfat.open_file("/a/b/a long file name.txt").unwrap().size(), 4);
+
+    let free = vfat.borrow().free_clusters().unwrap();
+    let e = vfat.remove("/a", false).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::Other);
+    vfat.remove("/a/short.txt", false).unwrap();
+    vfat.remove("/a", true).unwrap();
+    assert_eq!(vfat.borrow().free_clusters(), Some(free + 3));
+    assert_eq!(vfat.remove("/a", true).unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(vfat.remove("/", true).unwrap_err().kind(), ErrorKind::InvalidInput);
+
+    let vfat = disk.vfat();
+    assert_eq!(names(vfat.open_dir("/").unwrap()),
+               vec!["HELLO.TXT", "BIG.BIN", "EMPTY", "DOCS"]);
+    assert_eq!(vfat.borrow().free_clusters(), Some(free + 3));
+}
+
+#[test]
+fn mock_directory_grows() {
+    let vfat = mock_image().vfat();
+    vfat.create_dir("/many", false).unwrap();
+    for i in 0..40 {
+        vfat.create_file(format!("/many/a file with a long name {}", i)).unwrap();
+    }
+
+    let names = names(vfat.open_dir("/many").unwrap());
+    assert_eq!(names.len(), 42);
+    assert_eq!(names[41], "a file with a long name 39");
+    assert!(vfat.open("/many/AFILEW~9").is_err());
+    assert!(vfat.open("/many/a file with a long name 9").is_ok());
+}
+
+#[test]
+fn mock_rename() {
+    use std::io::ErrorKind;
+
+    let disk = mock_image().disk();
+    {
+        let vfat = disk.vfat();
+        vfat.create_dir("/new", false).unwrap();
+        vfat.rename("/hello.txt", "/new/Greeting.txt").unwrap();
+        vfat.rename("/docs", "/new/documents").unwrap();
+
+        assert_eq!(vfat.rename("/big.bin", "/empty").unwrap_err().kind(),
+                   ErrorKind::AlreadyExists);
+        assert_eq!(vfat.rename("/gone", "/here").unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(vfat.rename("/new", "/new/inner").unwrap_err().kind(),
+                   ErrorKind::InvalidInput);
+    }
+
+    // The new directory took the deleted entry's slot.
+    let vfat = disk.vfat();
+    assert_eq!(names(vfat.open_dir("/").unwrap()), vec!["BIG.BIN", "new", "EMPTY"]);
+    assert_eq!(names(vfat.open_dir("/new").unwrap()),
+               vec![".", "..", "Greeting.txt", "documents"]);
+    assert_eq!(vfat.open("/new/documents/..").unwrap().name(), "new");
+
+    let mut hello = String::new();
+    vfat.open_file("/new/greeting.txt").unwrap().read_to_string(&mut hello).unwrap();
+    assert_eq!(hello, "Hello, world!\n");
+    assert!(vfat.open("/new/documents/notes.md").unwrap().metadata().read_only());
+}
+
+#[test]
+fn mock_fsinfo_hints() {
+    let mut image = mock_image();
+    image.set_fsinfo(100, 2);
+    let disk = image.disk();
+    let vfat = disk.vfat();
+    assert_eq!(vfat.borrow().free_clusters(), Some(100));
+
+    // The file takes the first two free clusters, which the fragmented file
+    // left between its own, and the hints move past them.
+    vfat.create_file("/new").unwrap().write_all(&[1; 1024]).unwrap();
+    assert_eq!(vfat.borrow().free_clusters(), Some(98));
+    let fsinfo = (IMAGE_PARTITION + 1) * 512;
+    assert_eq!(disk.read_u32(fsinfo + 488), 98);
+    assert_eq!(disk.read_u32(fsinfo + 492), 8);
+
+    // Both copies of the FAT chain them.
+    for fat in 0..2 {
+        let at = |cluster: usize| (IMAGE_FAT + fat) * 512 + cluster * 4;
+        assert_eq!(disk.read_u32(at(5)), 7);
+        assert_eq!(disk.read_u32(at(7)), IMAGE_EOC);
+    }
+}
+
+#[test]
+fn mock_full_file_system() {
+    let mut image = Image::new();
+    image.next_cluster = IMAGE_CLUSTERS - 1;
+    image.add_chain(&[0; 512], 1);
+    for cluster in 3..IMAGE_CLUSTERS - 1 {
+        image.set_fat(cluster, IMAGE_EOC);
+    }
+
+    let vfat = image.vfat();
+    let mut file = vfat.create_file("/file").unwrap();
+    let e = file.write(&[1; 1024]).unwrap_err();
+    assert_eq!(e.kind(), ::std::io::ErrorKind::Other);
+}
+
+/// A disk with one FAT12 or FAT16 partition at sector 1 holding `clusters`
+/// one-sector clusters, two FATs and a root directory of 16 entries.
+/// `/HELLO.TXT` is in clusters 2 and 3 and `/CHAIN.BIN` in clusters 339 and
+/// 340, just before cluster 341, whose FAT12 entry straddles the FAT's first
+/// sector boundary.
+struct SmallImage {
+    data: Vec<u8>,
+    fat12: bool,
+    fat_sectors: usize,
+}
+
+const SMALL_FAT: usize = 2;
+
+impl SmallImage {
+    fn new(partition_type: u8, clusters: usize) -> SmallImage {
+        let fat12 = partition_type == 0x01;
+        let fat_bytes = if fat12 { (clusters + 2) * 3 / 2 + 1 } else { (clusters + 2) * 2 };
+        let fat_sectors = (fat_bytes + 511) / 512;
+        let sectors = SMALL_FAT + 2 * fat_sectors + 1 + clusters;
+        let mut data = vec![0u8; sectors * 512];
+
+        data[446 + 4] = partition_type;
+        put_u32(&mut data, 446 + 8, 1);
+        put_u32(&mut data, 446 + 12, (sectors - 1) as u32);
+        data[510..512].copy_from_slice(&[0x55, 0xAA]);
+
+        let bpb = 512;
+        put_u16(&mut data, bpb + 11, 512);
+        data[bpb + 13] = 1;
+        put_u16(&mut data, bpb + 14, 1);
+        data[bpb + 16] = 2;
+        put_u16(&mut data, bpb + 17, 16);
+        put_u16(&mut data, bpb + 19, (sectors - 1) as u16);
+        put_u16(&mut data, bpb + 22, fat_sectors as u16);
+        data[bpb + 38] = 0x29;
+        data[bpb + 510..bpb + 512].copy_from_slice(&[0x55, 0xAA]);
+
+        let mut image = SmallImage { data, fat12, fat_sectors };
+        image.set_fat(0, 0xFFF8);
+        image.set_fat(1, 0xFFFF);
+        image.add_file(b"HELLO   TXT", &pattern(600, 3), 2);
+        image.add_file(b"CHAIN   BIN", &pattern(1024, 5), 339);
+        image
+    }
+
+    /// Where entry `cluster` of FAT copy `fat` starts.
+    fn fat_offset(&self, fat: usize, cluster: u32) -> usize {
+        let start = (SMALL_FAT + fat * self.fat_sectors) * 512;
+        start + if self.fat12 { cluster as usize * 3 / 2 } else { cluster as usize * 2 }
+    }
+
+    fn fat(&self, fat: usize, cluster: u32) -> u32 {
+        let at = self.fat_offset(fat, cluster);
+        let bytes = self.data[at] as u32 | (self.data[at + 1] as u32) << 8;
+        match (self.fat12, cluster % 2) {
+            (true, 1) => bytes >> 4,
+            (true, _) => bytes & 0xFFF,
+            (false, _) => bytes,
+        }
+    }
+
+    fn set_fat(&mut self, cluster: u32, value: u32) {
+        for fat in 0..2 {
+            let at = self.fat_offset(fat, cluster);
+            let bytes = self.data[at] as u32 | (self.data[at + 1] as u32) << 8;
+            let bytes = match (self.fat12, cluster % 2) {
+                (true, 1) => bytes & 0xF | (value & 0xFFF) << 4,
+                (true, _) => bytes & 0xF000 | value & 0xFFF,
+                (false, _) => value,
+            };
+
+            put_u16(&mut self.data, at, bytes as u16);
+        }
+    }
+
+    /// Writes `contents` to the clusters from `first` on, chains them and
+    /// adds an entry for them to the root directory.
+    fn add_file(&mut self, name: &[u8; 11], contents: &[u8], first: u32) {
+        let data_start = SMALL_FAT + 2 * self.fat_sectors + 1;
+        let clusters = (contents.len() as u32 + 511) / 512;
+        for (i, chunk) in contents.chunks(512).enumerate() {
+            let at = (data_start + first as usize - 2 + i) * 512;
+            self.data[at..at + chunk.len()].copy_from_slice(chunk);
+            let cluster = first + i as u32;
+            self.set_fat(cluster, if i as u32 + 1 == clusters { 0xFFFF } else { cluster + 1 });
+        }
+
+        let root = (data_start - 1) * 512;
+        let slot = self.data[root..root + 512].chunks(32).position(|e| e[0] == 0).expect("room");
+        let entry = dir_entry(name, 0x20, first, contents.len() as u32);
+        self.data[root + slot * 32..root + slot * 32 + 32].copy_from_slice(&entry);
+    }
+}
+
+#[test]
+fn mock_fat12_and_fat16() {
+    use vfat::FatType;
+
+    for &(partition_type, clusters, fat_type) in [(0x01, 400, FatType::Fat12),
+                                                  (0x06, 4200, FatType::Fat16)].iter() {
+        let image = SmallImage::new(partition_type, clusters);
+        assert_eq!((image.fat(0, 339), image.fat(0, 341)), (340, 0));
+        let disk = Disk(Arc::new(Mutex::new(image.data.clone())));
+        let vfat = disk.vfat();
+        assert_eq!(vfat.borrow().fat_type(), fat_type);
+
+        let read = |vfat: &Shared<VFat>, path: &str| {
+            let mut contents = Vec::new();
+            vfat.open_file(path).unwrap().read_to_end(&mut contents).unwrap();
+            contents
+        };
+
+        assert_eq!(names(vfat.open_dir("/").unwrap()), vec!["HELLO.TXT", "CHAIN.BIN"]);
+        assert_eq!(read(&vfat, "/hello.txt"), pattern(600, 3));
+        assert_eq!(read(&vfat, "/chain.bin"), pattern(1024, 5));
+
+        // A write long enough to allocate cluster 341 and those after it.
+        let big = pattern(360 * 512, 9);
+        vfat.create_file("/big.bin").unwrap().write_all(&big).unwrap();
+        vfat.create_dir("/sub", false).unwrap();
+        vfat.create_file("/sub/note.txt").unwrap().write_all(b"note").unwrap();
+        let parent = vfat.open_dir("/sub").unwrap().find("..").unwrap();
+        assert_eq!(names(parent.into_dir().unwrap()).len(), 4);
+
+        // The root directory can't grow past its 16 entries.
+        let mut created = 0;
+        let e = loop {
+            match vfat.create_file(format!("/F{}", created)) {
+                Ok(_) => created += 1,
+                Err(e) => break e,
+            }
+        };
+
+        assert_eq!(e.kind(), ::std::io::ErrorKind::Other);
+        assert_eq!(created, 12);
+        drop(vfat);
+
+        let vfat = disk.vfat();
+        assert_eq!(read(&vfat, "/big.bin"), big);
+        assert_eq!(read(&vfat, "/sub/note.txt"), b"note");
+        assert_eq!(read(&vfat, "/chain.bin"), pattern(1024, 5));
+        assert_eq!(names(vfat.open_dir("/").unwrap()).len(), 16);
+
+        let image = SmallImage { data: disk.0.lock().unwrap().clone(), ..image };
+        let fat_len = image.fat_sectors * 512;
+        assert_eq!(image.data[SMALL_FAT * 512..][..fat_len],
+                   image.data[(SMALL_FAT + image.fat_sectors) * 512..][..fat_len]);
+        assert_eq!((image.fat(0, 339), image.fat(0, 340)), (340, fat_type.mask()));
+        assert_eq!((image.fat(0, 338), image.fat(0, 341)), (341, 342));
+    }
+}
+
+#[test]
+fn mock_check() {
+    use vfat::{Problem, Report};
+
+    let report = VFat::check(&mock_image().vfat(), false).unwrap();
+    assert_eq!(report, Report { dirs: 2, files: 4, used: 6, free: 120, problems: vec![],
+                                repaired: 0 });
+
+    let mut image = mock_image();
+    image.add_entry(2, dir_entry(b"CROSS      ", 0x20, 3, 14));
+    let long = image.add_chain(&pattern(1024, 1), 1);
+    image.add_entry(2, dir_entry(b"LONG       ", 0x20, long, 100));
+    let short = image.add_chain(&pattern(512, 2), 1);
+    image.add_entry(2, dir_entry(b"SHORT      ", 0x20, short, 2000));
+    image.add_entry(2, dir_entry(b"BAD        ", 0x20, 1, 10));
+    let broken = image.add_file(2, b"BROKEN     ", &pattern(512, 3), 1);
+    image.set_fat(broken, 0);
+    image.add_chain(&pattern(1024, 4), 1);
+    image.set_fsinfo(50, 2);
+
+    let unrepaired = vec![
+        Problem::CrossLinked { path: "/CROSS".into(), other: "/HELLO.TXT".into(), cluster: 3 },
+        Problem::SizeMismatch { path: "/SHORT".into(), size: 2000, clusters: 1, needed: 4 },
+        Problem::InvalidEntry { path: "/BAD".into(), reason: "names an invalid cluster" },
+        Problem::BrokenChain { path: "/BROKEN".into(), reason: "chain runs into a free cluster" },
+    ];
+
+    let mut problems = unrepaired.clone();
+    problems.insert(1, Problem::SizeMismatch { path: "/LONG".into(), size: 100, clusters: 2,
+                                               needed: 1 });
+    problems.push(Problem::LostClusters { chains: 1, clusters: 2 });
+    problems.push(Problem::FreeCount { recorded: 50, actual: 115 });
+
+    let disk = image.disk();
+    let report = VFat::check(&disk.vfat(), false).unwrap();
+    assert_eq!((report.dirs, report.files, report.used, report.free), (2, 9, 10, 115));
+    assert_eq!(report.problems, problems);
+    assert!(!report.is_clean());
+    assert_eq!(problems[0].to_string(), "/CROSS: cross-linked with /HELLO.TXT at cluster 3");
+    assert_eq!(problems[1].to_string(), "/LONG: 100 bytes need 1 clusters but 2 are allocated");
+
+    // Only the trivial problems are repaired, and the repairs reach the disk.
+    let report = VFat::check(&disk.vfat(), true).unwrap();
+    assert_eq!(report.problems, problems);
+    assert_eq!(report.repaired, 3);
+
+    let vfat = disk.vfat();
+    assert_eq!(vfat.borrow().free_clusters(), Some(118));
+    assert_eq!(disk.read_u32(2 * 512 + 488), 118);
+    let report = VFat::check(&vfat, false).unwrap();
+    assert_eq!(report.problems, unrepaired);
+    assert_eq!(report.free, 118);
+    let mut contents = Vec::new();
+    vfat.open_file("/long").unwrap().read_to_end(&mut contents).unwrap();
+    assert_eq!(contents, &pattern(1024, 1)[..100]);
+
+    let image = SmallImage::new(0x01, 1000);
+    let report = VFat::check(&VFat::from(Cursor::new(image.data)).unwrap(), false).unwrap();
+    assert_eq!((report.files, report.problems.len()), (2, 0));
+}
+
+#[test]
+fn cached_device_lru() {
+    use vfat::{CachedDevice, Partition};
+
+    let disk = Image::new().disk();
+    let partition = Partition { start: 1, sector_size: 1024 };
+    let mut device = CachedDevice::with_capacity(disk.clone(), partition, 3);
+
+    // Logical sectors past the start of the partition span two physical ones.
+    assert_eq!(device.get(0).unwrap()[510..], [0x55, 0xAA]);
+    assert_eq!(device.get(1).unwrap().len(), 1024);
+    device.get_mut(2).unwrap()[0] = 0xAB;
+    assert_eq!(device.len(), 3);
+
+    // Sector 0 was used more recently than sector 1, so 1 goes first.
+    device.get(0).unwrap();
+    device.get(3).unwrap();
+    assert_eq!(device.len(), 3);
+    disk.0.lock().unwrap()[512] = 0xCD;
+    disk.0.lock().unwrap()[0] = 0xCD;
+    assert_eq!(device.get(0).unwrap()[0], 0);
+    assert_eq!(device.get(1).unwrap()[0], 0xCD);
+
+    // The dirty sector stays cached, however old, until it is synced.
+    for sector in 4..10 {
+        device.get(sector).unwrap();
+    }
+
+    // Logical sector 2 starts at physical sector 3, the first FAT's.
+    assert!(device.is_dirty(2));
+    assert_eq!(disk.0.lock().unwrap()[3 * 512], 0xF8);
+    device.sync().unwrap();
+    assert!(!device.is_dirty(2));
+    assert_eq!(disk.0.lock().unwrap()[3 * 512], 0xAB);
+    assert_eq!(device.len(), 3);
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 /// Trait for a timestamp (year, month, day, hour, minute, second).
 pub trait Timestamp: Copy + Clone + Sized {
     /// The calendar year.
@@ -21,6 +23,67 @@ pub trait Timestamp: Copy + Clone + Sized {
 
     /// The second. Always in range [0, 60).
     fn second(&self) -> u8;
+
+    /// The timestamp as a calendar date and time.
+    fn date_time(&self) -> DateTime {
+        DateTime {
+            year: self.year(),
+            month: self.month(),
+            day: self.day(),
+            hour: self.hour(),
+            minute: self.minute(),
+            second: self.second(),
+        }
+    }
+}
+
+/// A calendar date and time. Comparing two orders them in time.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DateTime {
+    pub year: usize,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Timestamp for DateTime {
+    fn year(&self) -> usize {
+        self.year
+    }
+
+    fn month(&self) -> u8 {
+        self.month
+    }
+
+    fn day(&self) -> u8 {
+        self.day
+    }
+
+    fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    fn second(&self) -> u8 {
+        self.second
+    }
+
+    fn date_time(&self) -> DateTime {
+        *self
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// Writes the date and time as `YYYY-MM-DD HH:MM:SS`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{:02}-{:02} {:02}:{:02}:{:02}", self.year, self.month, self.day,
+               self.hour, self.minute, self.second)
+    }
 }
 
 /// Trait for directory entry metadata.
@@ -34,6 +97,12 @@ pub trait Metadata: Sized {
     /// Whether the entry should be "hidden" from directory traversals.
     fn hidden(&self) -> bool;
 
+    /// Whether the entry belongs to the operating system.
+    fn system(&self) -> bool;
+
+    /// Whether the entry has changed since it was last backed up.
+    fn archive(&self) -> bool;
+
     /// The timestamp when the entry was created.
     fn created(&self) -> Self::Timestamp;
 
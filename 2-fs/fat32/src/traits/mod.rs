@@ -4,6 +4,6 @@ mod metadata;
 mod dummy;
 
 pub use self::fs::{Dir, Entry, File, FileSystem};
-pub use self::metadata::{Metadata, Timestamp};
+pub use self::metadata::{Metadata, Timestamp, DateTime};
 pub use self::block_device::BlockDevice;
 pub use self::dummy::Dummy;
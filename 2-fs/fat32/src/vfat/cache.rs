@@ -1,14 +1,19 @@
 use std::{io, fmt};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use traits::BlockDevice;
 
 #[derive(Debug)]
 struct CacheEntry {
     data: Vec<u8>,
-    dirty: bool
+    dirty: bool,
+    /// The value of the cache's clock when the sector was last accessed.
+    used: u64
 }
 
+/// The number of sectors a `CachedDevice` keeps by default.
+pub const DEFAULT_CAPACITY: usize = 256;
+
 pub struct Partition {
     /// The physical sector where the partition begins.
     pub start: u64,
@@ -18,8 +23,12 @@ pub struct Partition {
 
 pub struct CachedDevice {
     device: Box<BlockDevice>,
-    cache: HashMap<u64, CacheEntry>,
-    partition: Partition
+    cache: BTreeMap<u64, CacheEntry>,
+    partition: Partition,
+    /// The number of sectors to keep before evicting the least recently used.
+    capacity: usize,
+    /// Counts accesses, to order sectors by when they were last used.
+    clock: u64
 }
 
 impl CachedDevice {
@@ -39,21 +48,53 @@ impl CachedDevice {
     /// `partition.sector_size` must be an integer multiple of
     /// `device.sector_size()`.
     ///
+    /// At most `DEFAULT_CAPACITY` clean sectors are kept; see
+    /// `with_capacity()`.
+    ///
     /// # Panics
     ///
     /// Panics if the partition's sector size is < the device's sector size.
     pub fn new<T>(device: T, partition: Partition) -> CachedDevice
         where T: BlockDevice + 'static
+    {
+        CachedDevice::with_capacity(device, partition, DEFAULT_CAPACITY)
+    }
+
+    /// Creates a new `CachedDevice` like `new()` that keeps at most `capacity`
+    /// sectors. Once it is full, reading a sector evicts the least recently
+    /// used clean sector. Dirty sectors are never evicted, as writing them
+    /// back out of order could leave the file system inconsistent, so the
+    /// cache grows past `capacity` until they are written back.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the partition's sector size is < the device's sector size or
+    /// `capacity` is 0.
+    pub fn with_capacity<T>(device: T, partition: Partition, capacity: usize) -> CachedDevice
+        where T: BlockDevice + 'static
     {
         assert!(partition.sector_size >= device.sector_size());
+        assert!(capacity > 0);
 
         CachedDevice {
             device: Box::new(device),
-            cache: HashMap::new(),
-            partition: partition
+            cache: BTreeMap::new(),
+            partition: partition,
+            capacity: capacity,
+            clock: 0
         }
     }
 
+    /// The number of sectors in the cache.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Returns `true` if sector `sector` is cached and differs from the disk.
+    pub fn is_dirty(&self, sector: u64) -> bool {
+        self.cache.get(&sector).map_or(false, |entry| entry.dirty)
+    }
+
     /// Maps a user's request for a sector `virt` to the physical sector and
     /// number of physical sectors required to access `virt`.
     fn virtual_to_physical(&self, virt: u64) -> (u64, u64) {
@@ -81,7 +122,9 @@ impl CachedDevice {
     ///
     /// Returns an error if there is an error reading the sector from the disk.
     pub fn get_mut(&mut self, sector: u64) -> io::Result<&mut [u8]> {
-        unimplemented!("CachedDevice::get_mut()")
+        let entry = self.entry(sector)?;
+        entry.dirty = true;
+        Ok(&mut entry.data)
     }
 
     /// Returns a reference to the cached sector `sector`. If the sector is not
@@ -91,17 +134,106 @@ impl CachedDevice {
     ///
     /// Returns an error if there is an error reading the sector from the disk.
     pub fn get(&mut self, sector: u64) -> io::Result<&[u8]> {
-        unimplemented!("CachedDevice::get()")
+        Ok(&self.entry(sector)?.data)
+    }
+
+    /// Writes the dirty cached sectors for which `which` returns `true` back
+    /// to the disk, in ascending order, and marks them clean.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing a sector fails. The sectors before it have
+    /// been written; it and the sectors after it stay dirty.
+    pub fn write_back<F: Fn(u64) -> bool>(&mut self, which: F) -> io::Result<()> {
+        let sectors: Vec<u64> = self.cache.iter()
+            .filter(|&(&sector, entry)| entry.dirty && which(sector))
+            .map(|(&sector, _)| sector)
+            .collect();
+
+        let size = self.device.sector_size() as usize;
+        for sector in sectors {
+            let (physical, count) = self.virtual_to_physical(sector);
+            let entry = self.cache.get_mut(&sector).expect("dirty sector is cached");
+            for (i, data) in entry.data.chunks(size).take(count as usize).enumerate() {
+                self.device.write_sector(physical + i as u64, data)?;
+            }
+
+            entry.dirty = false;
+        }
+
+        Ok(())
+    }
+
+    /// Writes every dirty cached sector back to the disk, in ascending order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing a sector fails. See `write_back()`.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.write_back(|_| true)
+    }
+
+    /// Returns the cache entry for sector `sector`, reading the sector from
+    /// the disk if it isn't cached.
+    fn entry(&mut self, sector: u64) -> io::Result<&mut CacheEntry> {
+        if !self.cache.contains_key(&sector) {
+            let (physical, count) = self.virtual_to_physical(sector);
+            let mut data = Vec::with_capacity((count * self.device.sector_size()) as usize);
+            for i in 0..count {
+                self.device.read_all_sector(physical + i, &mut data)?;
+            }
+
+            if self.cache.len() >= self.capacity {
+                self.evict();
+            }
+
+            self.cache.insert(sector, CacheEntry { data, dirty: false, used: 0 });
+        }
+
+        self.clock += 1;
+        let entry = self.cache.get_mut(&sector).expect("sector was just cached");
+        entry.used = self.clock;
+        Ok(entry)
+    }
+
+    /// Drops the least recently used clean sector, if there is one.
+    fn evict(&mut self) {
+        let victim = self.cache.iter()
+            .filter(|&(_, entry)| !entry.dirty)
+            .min_by_key(|&(_, entry)| entry.used)
+            .map(|(&sector, _)| sector);
+
+        if let Some(sector) = victim {
+            self.cache.remove(&sector);
+        }
     }
 }
 
-// FIXME: Implement `BlockDevice` for `CacheDevice`. The `read_sector` and
-// `write_sector` methods should only read/write from/to cached sectors.
+impl BlockDevice for CachedDevice {
+    fn sector_size(&self) -> u64 {
+        self.partition.sector_size
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.get(n)?;
+        let len = ::std::cmp::min(data.len(), buf.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len)
+    }
+
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        let data = self.get_mut(n)?;
+        let len = ::std::cmp::min(data.len(), buf.len());
+        data[..len].copy_from_slice(&buf[..len]);
+        Ok(len)
+    }
+}
 
 impl fmt::Debug for CachedDevice {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("CachedDevice")
             .field("device", &"<block device>")
+            .field("capacity", &self.capacity)
             .field("cache", &self.cache)
             .finish()
     }
@@ -0,0 +1,349 @@
+use std::{fmt, io};
+use std::collections::BTreeSet;
+
+use traits::{Dir as DirTrait, Entry as EntryTrait, File as FileTrait};
+use vfat::{VFat, Shared, Cluster, File, Dir, Entry, Status};
+
+/// An inconsistency `VFat::check()` found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The entry at `path` names a cluster that can't start its data.
+    InvalidEntry { path: String, reason: &'static str },
+    /// The chain of the entry at `path` doesn't end with an EOC marker.
+    BrokenChain { path: String, reason: &'static str },
+    /// The chain of the entry at `path` runs into `cluster`, which is in the
+    /// chain of the entry at `other`.
+    CrossLinked { path: String, other: String, cluster: u32 },
+    /// The file at `path` has `clusters` clusters, but its size, `size`
+    /// bytes, needs `needed`.
+    SizeMismatch { path: String, size: u64, clusters: u64, needed: u64 },
+    /// `clusters` clusters in `chains` chains are allocated but no entry
+    /// uses them.
+    LostClusters { chains: usize, clusters: usize },
+    /// The FSInfo structure records `recorded` free clusters, but `actual`
+    /// are free.
+    FreeCount { recorded: u32, actual: u32 },
+}
+
+/// The result of `VFat::check()`. The clusters are counted before any
+/// problem is repaired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub dirs: usize,
+    pub files: usize,
+    /// The number of clusters the chains of files and directories hold.
+    pub used: u32,
+    pub free: u32,
+    pub problems: Vec<Problem>,
+    /// How many of `problems` were repaired.
+    pub repaired: usize,
+}
+
+impl Problem {
+    /// Returns `true` if the problem can be repaired without losing data
+    /// that any entry uses: lost clusters are freed, chains longer than
+    /// their file are cut short, and the free cluster count is recounted.
+    pub fn is_trivial(&self) -> bool {
+        match *self {
+            Problem::SizeMismatch { clusters, needed, .. } => clusters > needed,
+            Problem::LostClusters { .. } | Problem::FreeCount { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl Report {
+    /// Returns `true` if every problem found was repaired.
+    pub fn is_clean(&self) -> bool {
+        self.repaired == self.problems.len()
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Problem::InvalidEntry { ref path, reason } => write!(f, "{}: {}", path, reason),
+            Problem::BrokenChain { ref path, reason } => write!(f, "{}: {}", path, reason),
+            Problem::CrossLinked { ref path, ref other, cluster } => {
+                write!(f, "{}: cross-linked with {} at cluster {}", path, other, cluster)
+            }
+            Problem::SizeMismatch { ref path, size, clusters, needed } => {
+                write!(f, "{}: {} bytes need {} clusters but {} are allocated",
+                       path, size, needed, clusters)
+            }
+            Problem::LostClusters { chains, clusters } => {
+                write!(f, "{} lost clusters in {} chains", clusters, chains)
+            }
+            Problem::FreeCount { recorded, actual } => {
+                write!(f, "FSInfo records {} free clusters but {} are free", recorded, actual)
+            }
+        }
+    }
+}
+
+/// The state of a check in progress.
+struct Checker {
+    report: Report,
+    /// For each cluster, one more than the index in `paths` of the entry
+    /// whose chain holds it, or 0 if none does yet.
+    owners: Vec<u32>,
+    paths: Vec<String>,
+    /// The files whose chains are longer than their sizes need.
+    long_files: Vec<File>,
+    /// The clusters that are allocated but that no chain holds.
+    lost: Vec<Cluster>,
+}
+
+impl VFat {
+    /// Walks the directory tree and the FAT of `vfat`, looking for entries
+    /// naming invalid clusters, broken and cross-linked chains, files whose
+    /// sizes don't match their chains, lost clusters and a wrong free cluster
+    /// count. If `repair` is `true`, the trivial problems are repaired; see
+    /// `Problem::is_trivial()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading the file system fails, or writing it when
+    /// repairing.
+    pub fn check(vfat: &Shared<VFat>, repair: bool) -> io::Result<Report> {
+        let limit = vfat.borrow().cluster_limit() as usize;
+        let mut checker = Checker {
+            report: Report { dirs: 0, files: 0, used: 0, free: 0, problems: Vec::new(),
+                             repaired: 0 },
+            owners: vec![0; limit],
+            paths: Vec::new(),
+            long_files: Vec::new(),
+            lost: Vec::new(),
+        };
+
+        checker.walk(vfat)?;
+        checker.scan(&mut vfat.borrow_mut())?;
+        if repair {
+            checker.repair(vfat)?;
+        }
+
+        Ok(checker.report)
+    }
+}
+
+impl Checker {
+    /// Records `path` as an entry whose chain may hold clusters. Returns its
+    /// index in `paths`.
+    fn add_path(&mut self, path: String) -> usize {
+        self.paths.push(path);
+        self.paths.len() - 1
+    }
+
+    /// Marks the clusters of the chain starting at `start` as held by the
+    /// entry at `paths[path]`. Returns the length of the chain, or `None` if
+    /// a problem stopped the walk, which is then reported.
+    fn claim(&mut self, vfat: &mut VFat, path: usize, start: Cluster) -> io::Result<Option<u64>> {
+        let limit = vfat.cluster_limit();
+        let mut cluster = start;
+        let mut count = 0;
+        loop {
+            if cluster.data_index().is_none() || cluster.number() >= limit {
+                let path = self.paths[path].clone();
+                self.report.problems.push(match count {
+                    0 => Problem::InvalidEntry { path, reason: "names an invalid cluster" },
+                    _ => Problem::BrokenChain { path, reason: "chain runs past the last cluster" },
+                });
+
+                return Ok(None);
+            }
+
+            let owner = self.owners[cluster.number() as usize] as usize;
+            if owner == path + 1 {
+                let path = self.paths[path].clone();
+                self.report.problems.push(Problem::BrokenChain { path, reason: "chain loops" });
+                return Ok(None);
+            } else if owner != 0 {
+                let (path, other) = (self.paths[path].clone(), self.paths[owner - 1].clone());
+                let cluster = cluster.number();
+                self.report.problems.push(Problem::CrossLinked { path, other, cluster });
+                return Ok(None);
+            }
+
+            self.owners[cluster.number() as usize] = path as u32 + 1;
+            self.report.used += 1;
+            count += 1;
+            let reason = match vfat.fat_entry(cluster)?.status() {
+                Status::Data(next) => {
+                    cluster = next;
+                    continue;
+                }
+                Status::Eoc(_) => return Ok(Some(count)),
+                Status::Free => "chain runs into a free cluster",
+                Status::Bad => "chain runs into a bad cluster",
+                Status::Reserved => "chain runs into a reserved cluster",
+            };
+
+            let path = self.paths[path].clone();
+            self.report.problems.push(Problem::BrokenChain { path, reason });
+            return Ok(None);
+        }
+    }
+
+    /// Walks the directory tree from the root, claiming the chain of every
+    /// entry.
+    fn walk(&mut self, vfat: &Shared<VFat>) -> io::Result<()> {
+        let root = Dir::root(vfat.clone());
+        let root_start = root.start();
+        let path = self.add_path("/".to_string());
+        self.report.dirs += 1;
+        if !vfat.borrow().is_fixed_root(root_start) {
+            if self.claim(&mut vfat.borrow_mut(), path, root_start)?.is_none() {
+                return Ok(());
+            }
+        }
+
+        // The directories left to read, their paths, and the first clusters
+        // of their parents.
+        let mut dirs = vec![(root, String::new(), None)];
+        while let Some((dir, path, parent)) = dirs.pop() {
+            let entries = match dir.entries() {
+                Ok(entries) => entries,
+                // The broken chain was reported when it was claimed.
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidData => continue,
+                Err(e) => return Err(e),
+            };
+
+            for entry in entries {
+                if entry.name() == "." {
+                    continue;
+                } else if entry.name() == ".." {
+                    let start = entry.as_dir().map(|dir| dir.start());
+                    if parent.is_some() && start != parent {
+                        self.report.problems.push(Problem::InvalidEntry {
+                            path: format!("{}/..", path),
+                            reason: "doesn't name the parent directory",
+                        });
+                    }
+
+                    continue;
+                }
+
+                let child = format!("{}/{}", path, entry.name());
+                match entry {
+                    Entry::File(file) => {
+                        self.report.files += 1;
+                        self.check_file(vfat, file, child)?;
+                    }
+                    Entry::Dir(child_dir) => {
+                        self.report.dirs += 1;
+                        if child_dir.start() == root_start {
+                            self.report.problems.push(Problem::InvalidEntry {
+                                path: child,
+                                reason: "names the root directory",
+                            });
+                            continue;
+                        }
+
+                        let index = self.add_path(child.clone());
+                        if self.claim(&mut vfat.borrow_mut(), index, child_dir.start())?.is_some() {
+                            dirs.push((child_dir, child, Some(dir.start())));
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Claims the chain of `file`, at `path`, and compares its length with
+    /// the file's size.
+    fn check_file(&mut self, vfat: &Shared<VFat>, file: File, path: String) -> io::Result<()> {
+        let cluster_size = vfat.borrow().cluster_size() as u64;
+        let size = file.size();
+        let needed = (size + cluster_size - 1) / cluster_size;
+        let clusters = match file.start().number() {
+            0 => 0,
+            _ => {
+                let index = self.add_path(path.clone());
+                match self.claim(&mut vfat.borrow_mut(), index, file.start())? {
+                    Some(clusters) => clusters,
+                    None => return Ok(()),
+                }
+            }
+        };
+
+        if clusters != needed {
+            self.report.problems.push(Problem::SizeMismatch { path, size, clusters, needed });
+            if clusters > needed {
+                self.long_files.push(file);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads the FAT for clusters that are allocated but unclaimed, and
+    /// counts the free ones.
+    fn scan(&mut self, vfat: &mut VFat) -> io::Result<()> {
+        // The lost clusters that another lost cluster links to. The rest
+        // start lost chains.
+        let mut linked = BTreeSet::new();
+        for number in 2..vfat.cluster_limit() {
+            let cluster = Cluster::from(number);
+            match vfat.fat_entry(cluster)?.status() {
+                Status::Free => self.report.free += 1,
+                Status::Data(_) | Status::Eoc(_) if self.owners[number as usize] != 0 => {}
+                Status::Data(next) => {
+                    linked.insert(next);
+                    self.lost.push(cluster);
+                }
+                Status::Eoc(_) => self.lost.push(cluster),
+                Status::Bad | Status::Reserved => {}
+            }
+        }
+
+        if !self.lost.is_empty() {
+            let chains = self.lost.iter().filter(|cluster| !linked.contains(cluster)).count();
+            self.report.problems.push(Problem::LostClusters {
+                chains: ::std::cmp::max(chains, 1),
+                clusters: self.lost.len(),
+            });
+        }
+
+        if let Some(recorded) = vfat.free_clusters() {
+            if recorded != self.report.free {
+                let actual = self.report.free;
+                self.report.problems.push(Problem::FreeCount { recorded, actual });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Repairs the trivial problems: cuts the chains of long files short,
+    /// frees lost clusters and records the number of free clusters.
+    fn repair(&mut self, vfat: &Shared<VFat>) -> io::Result<()> {
+        for mut file in self.long_files.drain(..) {
+            let size = file.size();
+            file.set_len(size)?;
+        }
+
+        let mut vfat = vfat.borrow_mut();
+        for &cluster in self.lost.iter() {
+            vfat.set_fat_entry(cluster, 0)?;
+        }
+
+        let mut free = 0;
+        for number in 2..vfat.cluster_limit() {
+            if vfat.fat_entry(Cluster::from(number))?.status() == Status::Free {
+                free += 1;
+            }
+        }
+
+        if vfat.free_clusters().is_some() {
+            vfat.set_free_clusters(free)?;
+        }
+
+        vfat.sync()?;
+        self.report.repaired = self.report.problems.iter()
+            .filter(|problem| problem.is_trivial())
+            .count();
+        Ok(())
+    }
+}
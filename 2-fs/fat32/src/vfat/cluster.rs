@@ -9,4 +9,15 @@ impl From<u32> for Cluster {
     }
 }
 
-// TODO: Implement any useful helper methods on `Cluster`.
+impl Cluster {
+    /// The cluster's number, as stored in the FAT and directory entries.
+    pub fn number(&self) -> u32 {
+        self.0
+    }
+
+    /// The cluster's index in the data region, where the first cluster is
+    /// number 2. Returns `None` for clusters 0 and 1, which have no data.
+    pub fn data_index(&self) -> Option<u32> {
+        self.0.checked_sub(2)
+    }
+}
@@ -1,7 +1,8 @@
 use std::ffi::OsStr;
 use std::char::decode_utf16;
 use std::borrow::Cow;
-use std::io;
+use std::collections::BTreeSet;
+use std::{io, mem};
 
 use traits;
 use util::VecExt;
@@ -10,25 +11,69 @@ use vfat::{Metadata, Attributes, Timestamp, Time, Date};
 
 #[derive(Debug)]
 pub struct Dir {
-    // FIXME: Fill me in.
+    vfat: Shared<VFat>,
+    /// The first cluster of the directory's entries.
+    start: Cluster,
+    name: String,
+    metadata: Metadata,
+    /// Where the directory's entry is. The root directory has none.
+    location: Option<Location>,
+}
+
+/// Where an entry is in its directory: the slots from that of its first LFN
+/// entry to that of its regular entry, which is the last.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// The first cluster of the directory holding the entry.
+    dir: Cluster,
+    first: u64,
+    entry: u64,
 }
 
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatRegularDirEntry {
-    // FIXME: Fill me in.
+    /// The name, padded with spaces. A first byte of `0xE5` marks a deleted
+    /// entry, `0x05` a name that starts with `0xE5`, and `0` the end of the
+    /// directory.
+    name: [u8; 8],
+    /// The extension, padded with spaces.
+    extension: [u8; 3],
+    attributes: Attributes,
+    /// Bit 3 set means the name is lower case, and bit 4 the extension.
+    case: u8,
+    created_tenths: u8,
+    created_time: Time,
+    created_date: Date,
+    accessed_date: Date,
+    cluster_high: u16,
+    modified_time: Time,
+    modified_date: Date,
+    cluster_low: u16,
+    size: u32,
 }
 
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatLfnDirEntry {
-    // FIXME: Fill me in.
+    sequence: u8,
+    name_1: [u16; 5],
+    attributes: Attributes,
+    kind: u8,
+    checksum: u8,
+    name_2: [u16; 6],
+    cluster: u16,
+    name_3: [u16; 2],
 }
 
 #[repr(C, packed)]
 #[derive(Copy, Clone)]
 pub struct VFatUnknownDirEntry {
-    // FIXME: Fill me in.
+    /// `0` marks the end of the directory and `0xE5` a deleted entry.
+    id: u8,
+    __r0: [u8; 10],
+    attributes: Attributes,
+    __r1: [u8; 20],
 }
 
 pub union VFatDirEntry {
@@ -37,9 +82,417 @@ pub union VFatDirEntry {
     long_filename: VFatLfnDirEntry,
 }
 
+/// The first byte of the name of a deleted entry.
+const DELETED: u8 = 0xE5;
+
+/// The first byte of the name of the entry after the last.
+const END: u8 = 0x00;
+
+/// The first byte of a name that really starts with `DELETED`.
+const ESCAPED_DELETED: u8 = 0x05;
+
+/// `case` bits: the name and the extension are lower case.
+const LOWER_CASE_NAME: u8 = 1 << 3;
+const LOWER_CASE_EXTENSION: u8 = 1 << 4;
+
+/// `sequence` bit of the LFN entry holding the end of a long name, which is
+/// the first of its entries on disk.
+const LFN_LAST: u8 = 0x40;
+
+/// `sequence` bits holding the place of an LFN entry's part in the name,
+/// from 1.
+const LFN_ORDINAL: u8 = 0x1F;
+
+/// The number of UCS-2 characters in each LFN entry.
+const LFN_CHARS: usize = 13;
+
+/// The longest long name, in UCS-2 characters.
+const LFN_MAX_CHARS: usize = 255;
+
+/// A directory can hold no more entries than this.
+const MAX_ENTRIES: u64 = 65536;
+
+/// Characters other than letters and digits that an 8.3 name may hold.
+const SHORT_NAME_SYMBOLS: &[u8] = b"!#$%&'()-@^_`{}~";
+
+/// Characters that no name may hold, besides control characters.
+const INVALID_CHARS: &str = "\"*/:<>?\\|";
+
+impl VFatRegularDirEntry {
+    /// Returns an entry with no name that holds the data starting at
+    /// `cluster`. Its timestamps are `Timestamp::EPOCH`.
+    fn new(attributes: Attributes, cluster: Cluster, size: u32) -> VFatRegularDirEntry {
+        let epoch = Timestamp::EPOCH;
+        let mut entry = VFatRegularDirEntry {
+            name: [b' '; 8],
+            extension: [b' '; 3],
+            attributes,
+            case: 0,
+            created_tenths: 0,
+            created_time: epoch.time,
+            created_date: epoch.date,
+            accessed_date: epoch.date,
+            cluster_high: 0,
+            modified_time: epoch.time,
+            modified_date: epoch.date,
+            cluster_low: 0,
+            size,
+        };
+
+        entry.set_cluster(cluster);
+        entry
+    }
+
+    fn from_bytes(bytes: [u8; 32]) -> VFatRegularDirEntry {
+        unsafe { mem::transmute(bytes) }
+    }
+
+    fn to_bytes(&self) -> [u8; 32] {
+        unsafe { mem::transmute(*self) }
+    }
+
+    /// Sets the 8.3 name to the space-padded `name`, whose first 8 bytes are
+    /// the name and last 3 the extension.
+    fn set_short_name(&mut self, name: &[u8; 11], case: u8) {
+        self.name.copy_from_slice(&name[..8]);
+        self.extension.copy_from_slice(&name[8..]);
+        if self.name[0] == DELETED {
+            self.name[0] = ESCAPED_DELETED;
+        }
+
+        self.case = case;
+    }
+
+    fn set_cluster(&mut self, cluster: Cluster) {
+        self.cluster_high = (cluster.number() >> 16) as u16;
+        self.cluster_low = cluster.number() as u16;
+    }
+
+    /// The entry's 8.3 name, with the extension after a `.` if there is one.
+    fn short_name(&self) -> String {
+        let mut name = self.name;
+        if name[0] == ESCAPED_DELETED {
+            name[0] = DELETED;
+        }
+
+        let mut short_name = short_name_part(&name, self.case & LOWER_CASE_NAME != 0);
+        let extension = short_name_part(&self.extension, self.case & LOWER_CASE_EXTENSION != 0);
+        if !extension.is_empty() {
+            short_name.push('.');
+            short_name.push_str(&extension);
+        }
+
+        short_name
+    }
+
+    /// The first cluster of the entry's data.
+    fn cluster(&self) -> Cluster {
+        Cluster::from((self.cluster_high as u32) << 16 | self.cluster_low as u32)
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            attributes: self.attributes,
+            created: Timestamp { date: self.created_date, time: self.created_time },
+            accessed: Timestamp { date: self.accessed_date, time: Time::default() },
+            modified: Timestamp { date: self.modified_date, time: self.modified_time },
+        }
+    }
+
+    /// The checksum of the 8.3 name, which the entry's LFN entries carry.
+    fn checksum(&self) -> u8 {
+        self.name.iter().chain(self.extension.iter())
+            .fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+    }
+}
+
+impl VFatLfnDirEntry {
+    /// Returns the LFN entry holding part `ordinal` of a name, from 1, for
+    /// the regular entry whose 8.3 name has checksum `checksum`.
+    fn new(ordinal: usize, last: bool, checksum: u8, chars: &[u16]) -> VFatLfnDirEntry {
+        let mut name_1 = [0; 5];
+        let mut name_2 = [0; 6];
+        let mut name_3 = [0; 2];
+        name_1.copy_from_slice(&chars[..5]);
+        name_2.copy_from_slice(&chars[5..11]);
+        name_3.copy_from_slice(&chars[11..LFN_CHARS]);
+
+        VFatLfnDirEntry {
+            sequence: ordinal as u8 | if last { LFN_LAST } else { 0 },
+            name_1,
+            attributes: Attributes::new(Attributes::LFN),
+            kind: 0,
+            checksum,
+            name_2,
+            cluster: 0,
+            name_3,
+        }
+    }
+
+    fn to_bytes(&self) -> [u8; 32] {
+        unsafe { mem::transmute(*self) }
+    }
+
+    /// The place of the entry's part in the name, from 1.
+    fn ordinal(&self) -> usize {
+        (self.sequence & LFN_ORDINAL) as usize
+    }
+
+    /// The entry's part of the name, padding included.
+    fn chars(&self) -> [u16; LFN_CHARS] {
+        let (name_1, name_2, name_3) = (self.name_1, self.name_2, self.name_3);
+        let mut chars = [0u16; LFN_CHARS];
+        chars[..5].copy_from_slice(&name_1);
+        chars[5..11].copy_from_slice(&name_2);
+        chars[11..].copy_from_slice(&name_3);
+        chars
+    }
+}
+
+/// A long name being put together from its LFN entries, which come before the
+/// regular entry they name, the end of the name first.
+struct LongName {
+    chars: Vec<u16>,
+    checksum: u8,
+    /// The ordinal of the last entry added. The name is complete at 1.
+    ordinal: usize,
+}
+
+impl LongName {
+    /// Starts a name from `lfn`, if it holds the end of one.
+    fn start(lfn: &VFatLfnDirEntry) -> Option<LongName> {
+        if lfn.sequence & LFN_LAST == 0 || lfn.ordinal() == 0 {
+            return None;
+        }
+
+        let name = LongName {
+            chars: vec![0xFFFF; lfn.ordinal() * LFN_CHARS],
+            checksum: lfn.checksum,
+            ordinal: lfn.ordinal() + 1,
+        };
+
+        name.add(lfn)
+    }
+
+    /// Adds the part of the name in `lfn`. Returns `None` if it isn't the
+    /// next part of this name, which is then dropped.
+    fn add(mut self, lfn: &VFatLfnDirEntry) -> Option<LongName> {
+        if lfn.ordinal() + 1 != self.ordinal || lfn.checksum != self.checksum {
+            return None;
+        }
+
+        self.ordinal -= 1;
+        let start = self.ordinal.checked_sub(1)? * LFN_CHARS;
+        self.chars[start..start + LFN_CHARS].copy_from_slice(&lfn.chars());
+        Some(self)
+    }
+
+    /// Returns the name if it is complete and belongs to `entry`. The name
+    /// ends at the first NUL or padding character. Characters that aren't
+    /// valid UCS-2 become U+FFFD.
+    fn finish(self, entry: &VFatRegularDirEntry) -> Option<String> {
+        if self.ordinal != 1 || self.checksum != entry.checksum() {
+            return None;
+        }
+
+        let len = self.chars.iter().position(|&c| c == 0 || c == 0xFFFF)
+            .unwrap_or(self.chars.len());
+        let name: String = decode_utf16(self.chars[..len].iter().cloned())
+            .map(|c| c.unwrap_or('\u{FFFD}'))
+            .collect();
+
+        if name.is_empty() { None } else { Some(name) }
+    }
+}
+
+/// Decodes one space-padded part of an 8.3 name. Bytes outside of ASCII are
+/// taken to be Latin-1.
+fn short_name_part(bytes: &[u8], lower_case: bool) -> String {
+    let len = bytes.iter().rposition(|&b| b != b' ').map_or(0, |i| i + 1);
+    let part: Cow<str> = String::from_utf8_lossy(&bytes[..len]);
+    if !part.bytes().all(|b| b.is_ascii()) {
+        bytes[..len].iter().map(|&b| b as char).collect()
+    } else if lower_case {
+        part.to_ascii_lowercase()
+    } else {
+        part.into_owned()
+    }
+}
+
+impl Location {
+    /// Reads the entry's regular entry.
+    fn read(&self, vfat: &mut VFat) -> io::Result<VFatRegularDirEntry> {
+        Ok(VFatRegularDirEntry::from_bytes(vfat.read_entry(self.dir, self.entry)?))
+    }
+
+    /// Overwrites the entry's regular entry with `entry`.
+    fn write(&self, vfat: &mut VFat, entry: &VFatRegularDirEntry) -> io::Result<()> {
+        vfat.write_entry(self.dir, self.entry, &entry.to_bytes())
+    }
+
+    /// Sets the first cluster, size and attributes of the regular entry.
+    pub(crate) fn update(
+        &self,
+        vfat: &mut VFat,
+        cluster: Cluster,
+        size: u32,
+        attributes: Attributes
+    ) -> io::Result<()> {
+        let mut entry = self.read(vfat)?;
+        entry.set_cluster(cluster);
+        entry.size = size;
+        entry.attributes = attributes;
+        self.write(vfat, &entry)
+    }
+
+    /// Marks the regular entry and its LFN entries deleted.
+    pub(crate) fn delete(&self, vfat: &mut VFat) -> io::Result<()> {
+        for index in self.first..self.entry + 1 {
+            let mut entry = vfat.read_entry(self.dir, index)?;
+            entry[0] = DELETED;
+            vfat.write_entry(self.dir, index, &entry)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns an error of `InvalidInput` if `name` can't name an entry.
+fn check_name(name: &str) -> io::Result<()> {
+    let invalid = name.is_empty() || name == "." || name == ".."
+        || name.ends_with(' ') || name.ends_with('.')
+        || name.encode_utf16().count() > LFN_MAX_CHARS
+        || name.chars().any(|c| c < ' ' || INVALID_CHARS.contains(c));
+
+    if invalid {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid file name"));
+    }
+
+    Ok(())
+}
+
+/// Returns `true` if `b` may be part of an 8.3 name in either case.
+fn is_short_name_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || SHORT_NAME_SYMBOLS.contains(&b)
+}
+
+/// Returns `name` as a space-padded 8.3 name and the `case` bits that give it
+/// back, if it is a valid 8.3 name whose parts are each in one case.
+fn exact_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, extension) = match name.rfind('.') {
+        Some(dot) => (&name[..dot], &name[dot + 1..]),
+        None => (name, ""),
+    };
+
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 {
+        return None;
+    }
+
+    let mut short_name = [b' '; 11];
+    let mut case = 0;
+    for &(part, start, lower_case) in [(base, 0, LOWER_CASE_NAME),
+                                       (extension, 8, LOWER_CASE_EXTENSION)].iter() {
+        if !part.bytes().all(is_short_name_byte) {
+            return None;
+        }
+
+        let has_lower = part.bytes().any(|b| b.is_ascii_lowercase());
+        if has_lower && part.bytes().any(|b| b.is_ascii_uppercase()) {
+            return None;
+        }
+
+        if has_lower {
+            case |= lower_case;
+        }
+
+        for (i, b) in part.bytes().enumerate() {
+            short_name[start + i] = b.to_ascii_uppercase();
+        }
+    }
+
+    Some((short_name, case))
+}
+
+/// Returns the first `BASIS~N` 8.3 name for the long name `name` that isn't
+/// in `taken`.
+fn generate_short_name(name: &str, taken: &BTreeSet<[u8; 11]>) -> io::Result<[u8; 11]> {
+    let to_short = |part: &str| -> Vec<u8> {
+        part.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| if c.is_ascii() && is_short_name_byte(c as u8) {
+                (c as u8).to_ascii_uppercase()
+            } else {
+                b'_'
+            })
+            .collect()
+    };
+
+    let (base, extension) = match name.trim_left_matches('.').rfind('.') {
+        Some(dot) => {
+            let name = name.trim_left_matches('.');
+            (to_short(&name[..dot]), to_short(&name[dot + 1..]))
+        }
+        None => (to_short(name), Vec::new()),
+    };
+
+    for n in 1..1000000u32 {
+        let tail = format!("~{}", n);
+        let len = ::std::cmp::min(base.len(), 8 - tail.len());
+        let mut short_name = [b' '; 11];
+        short_name[..len].copy_from_slice(&base[..len]);
+        short_name[len..len + tail.len()].copy_from_slice(tail.as_bytes());
+        for (i, &b) in extension.iter().take(3).enumerate() {
+            short_name[8 + i] = b;
+        }
+
+        if !taken.contains(&short_name) {
+            return Ok(short_name);
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::Other, "no short name is left for the name"))
+}
+
+/// The checksum of a space-padded 8.3 name.
+fn short_name_checksum(short_name: &[u8; 11]) -> u8 {
+    let mut entry = VFatRegularDirEntry::new(Attributes::default(), Cluster::from(0), 0);
+    entry.set_short_name(short_name, 0);
+    entry.checksum()
+}
+
 impl Dir {
-    /// Finds the entry named `name` in `self` and returns it. Comparison is
-    /// case-insensitive.
+    /// Returns the root directory of `vfat`.
+    pub fn root(vfat: Shared<VFat>) -> Dir {
+        let start = vfat.borrow().root_dir_cluster();
+        let metadata = Metadata {
+            attributes: Attributes::new(Attributes::DIRECTORY),
+            ..Metadata::default()
+        };
+
+        Dir { vfat, start, name: String::new(), metadata, location: None }
+    }
+
+    /// The name of the directory. The root directory's name is empty.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// The first cluster of the directory's entries.
+    pub(crate) fn start(&self) -> Cluster {
+        self.start
+    }
+
+    /// Where the directory's entry is, or `None` for the root directory.
+    pub(crate) fn location(&self) -> Option<Location> {
+        self.location
+    }
+
+    /// Finds the entry named `name` in `self` and returns it. An entry with a
+    /// long name is only found by that name. Comparison is case-insensitive.
     ///
     /// # Errors
     ///
@@ -49,8 +502,335 @@ impl Dir {
     /// If `name` contains invalid UTF-8 characters, an error of `InvalidInput`
     /// is returned.
     pub fn find<P: AsRef<OsStr>>(&self, name: P) -> io::Result<Entry> {
-        unimplemented!("Dir::find()")
+        use traits::{Dir as DirTrait, Entry as EntryTrait};
+
+        let name = name.as_ref().to_str()
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "name is not valid UTF-8"))?;
+
+        let found = self.entries()?.find(|entry| entry.name().eq_ignore_ascii_case(name));
+        found.ok_or(io::Error::new(io::ErrorKind::NotFound, "no such file or directory"))
+    }
+
+    /// Returns an iterator over the entries of `self` read through `vfat`,
+    /// which must be the locked file system `self` belongs to.
+    fn iter(&self, vfat: &mut VFat) -> io::Result<DirIter> {
+        let mut data = Vec::new();
+        vfat.read_chain(self.start, &mut data)?;
+
+        Ok(DirIter {
+            vfat: self.vfat.clone(),
+            entries: unsafe { data.cast() },
+            index: 0,
+            dir: self.start,
+            root: vfat.root_dir_cluster(),
+            long_name: None,
+        })
+    }
+
+    /// Adds an entry named `name` that is a copy of `entry` with a new name.
+    /// Returns where the entry was put.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `name` isn't a valid name and of
+    /// `AlreadyExists` if an entry named `name` exists.
+    fn add_entry(
+        &self,
+        vfat: &mut VFat,
+        name: &str,
+        mut entry: VFatRegularDirEntry
+    ) -> io::Result<Location> {
+        use traits::Entry as EntryTrait;
+
+        check_name(name)?;
+        if self.iter(vfat)?.any(|entry| entry.name().eq_ignore_ascii_case(name)) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+        }
+
+        let mut data = Vec::new();
+        vfat.read_chain(self.start, &mut data)?;
+        let slots: Vec<VFatDirEntry> = unsafe { data.cast() };
+        let end = slots.iter().position(|slot| unsafe { slot.unknown.id } == END)
+            .unwrap_or(slots.len());
+
+        let mut taken = BTreeSet::new();
+        for slot in slots[..end].iter() {
+            let regular = unsafe { slot.regular };
+            if regular.name[0] != DELETED && !regular.attributes.lfn() {
+                let mut short_name = [0; 11];
+                short_name[..8].copy_from_slice(&regular.name);
+                short_name[8..].copy_from_slice(&regular.extension);
+                taken.insert(short_name);
+            }
+        }
+
+        let mut lfn_entries = Vec::new();
+        match exact_short_name(name) {
+            Some((ref short_name, _)) if taken.contains(short_name) => {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+            }
+            Some((short_name, case)) => entry.set_short_name(&short_name, case),
+            None => {
+                let short_name = generate_short_name(name, &taken)?;
+                entry.set_short_name(&short_name, 0);
+
+                let mut chars: Vec<u16> = name.encode_utf16().collect();
+                if chars.len() % LFN_CHARS != 0 {
+                    chars.push(0);
+                }
+
+                while chars.len() % LFN_CHARS != 0 {
+                    chars.push(0xFFFF);
+                }
+
+                let checksum = short_name_checksum(&short_name);
+                let count = chars.len() / LFN_CHARS;
+                for ordinal in (1..count + 1).rev() {
+                    let part = &chars[(ordinal - 1) * LFN_CHARS..ordinal * LFN_CHARS];
+                    let lfn = VFatLfnDirEntry::new(ordinal, ordinal == count, checksum, part);
+                    lfn_entries.push(lfn.to_bytes());
+                }
+            }
+        }
+
+        // Find the first run of free slots long enough, counting the slots
+        // past the end of the directory as free.
+        let needed = lfn_entries.len() + 1;
+        let mut first = 0;
+        while first < end {
+            match (first..end).take(needed).position(|i| unsafe { slots[i].unknown.id } != DELETED) {
+                Some(used) => first += used + 1,
+                None => break,
+            }
+        }
+
+        let (first, last) = (first as u64, (first + needed - 1) as u64);
+        if last >= MAX_ENTRIES {
+            return Err(io::Error::new(io::ErrorKind::Other, "directory is full"));
+        }
+
+        let per_cluster = (vfat.cluster_size() / 32) as u64;
+        let mut len = slots.len() as u64;
+        while last >= len {
+            vfat.extend_chain(self.start)?;
+            len += per_cluster;
+        }
+
+        for (i, lfn) in lfn_entries.iter().enumerate() {
+            vfat.write_entry(self.start, first + i as u64, lfn)?;
+        }
+
+        vfat.write_entry(self.start, last, &entry.to_bytes())?;
+
+        // The slot after a run that took the end marker's place becomes the
+        // end, whatever it held.
+        let after = last as usize + 1;
+        if last as usize >= end && after < slots.len() && unsafe { slots[after].unknown.id } != END {
+            vfat.write_entry(self.start, after as u64, &[0; 32])?;
+        }
+
+        Ok(Location { dir: self.start, first, entry: last })
+    }
+
+    /// The cluster a `..` entry names for `self`, which is 0 for the root
+    /// directory.
+    fn dot_dot_cluster(&self, vfat: &VFat) -> Cluster {
+        if self.start == vfat.root_dir_cluster() {
+            Cluster::from(0)
+        } else {
+            self.start
+        }
+    }
+
+    /// Creates an empty file named `name` in `self`.
+    pub(crate) fn create_file(&self, vfat: &mut VFat, name: &str) -> io::Result<File> {
+        let attributes = Attributes::new(Attributes::ARCHIVE);
+        let entry = VFatRegularDirEntry::new(attributes, Cluster::from(0), 0);
+        let location = self.add_entry(vfat, name, entry)?;
+
+        Ok(File::new(self.vfat.clone(), entry.cluster(), name.to_string(), entry.metadata(),
+                     0, location))
+    }
+
+    /// Creates an empty directory named `name` in `self`.
+    pub(crate) fn create_dir(&self, vfat: &mut VFat, name: &str) -> io::Result<Dir> {
+        check_name(name)?;
+        let start = vfat.alloc_cluster(None)?;
+        let attributes = Attributes::new(Attributes::DIRECTORY);
+        let entry = VFatRegularDirEntry::new(attributes, start, 0);
+
+        let mut dot = entry;
+        dot.set_short_name(b".          ", 0);
+        let mut dot_dot = VFatRegularDirEntry::new(attributes, self.dot_dot_cluster(vfat), 0);
+        dot_dot.set_short_name(b"..         ", 0);
+
+        let location = vfat.write_entry(start, 0, &dot.to_bytes())
+            .and_then(|_| vfat.write_entry(start, 1, &dot_dot.to_bytes()))
+            .and_then(|_| self.add_entry(vfat, name, entry));
+
+        let location = match location {
+            Ok(location) => location,
+            Err(e) => {
+                vfat.free_chain(start)?;
+                return Err(e);
+            }
+        };
+
+        Ok(Dir {
+            vfat: self.vfat.clone(),
+            start,
+            name: name.to_string(),
+            metadata: entry.metadata(),
+            location: Some(location),
+        })
+    }
+
+    /// Moves the entry at `from` into `self` under the name `name`. A moved
+    /// directory's `..` entry is pointed at `self`.
+    pub(crate) fn move_entry(&self, vfat: &mut VFat, from: Location, name: &str) -> io::Result<()> {
+        let entry = from.read(vfat)?;
+        self.add_entry(vfat, name, entry)?;
+        from.delete(vfat)?;
+
+        if entry.attributes.directory() && from.dir != self.start {
+            let mut dot_dot = VFatRegularDirEntry::from_bytes(vfat.read_entry(entry.cluster(), 1)?);
+            dot_dot.set_cluster(self.dot_dot_cluster(vfat));
+            vfat.write_entry(entry.cluster(), 1, &dot_dot.to_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes `self` and everything in it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` for the root directory.
+    pub(crate) fn remove(self, vfat: &mut VFat) -> io::Result<()> {
+        use traits::Entry as EntryTrait;
+
+        let location = self.location.ok_or(
+            io::Error::new(io::ErrorKind::InvalidInput, "can't remove the root directory"))?;
+
+        let children: Vec<Entry> = self.iter(vfat)?
+            .filter(|entry| entry.name() != "." && entry.name() != "..")
+            .collect();
+
+        for child in children {
+            match child {
+                Entry::File(file) => file.remove(vfat)?,
+                Entry::Dir(dir) => dir.remove(vfat)?,
+            }
+        }
+
+        location.delete(vfat)?;
+        vfat.sync()?;
+        vfat.free_chain(self.start)?;
+        vfat.sync()
     }
 }
 
-// FIXME: Implement `trait::Dir` for `Dir`.
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = DirIter;
+
+    fn entries(&self) -> io::Result<DirIter> {
+        let mut data = Vec::new();
+        let root = {
+            let mut vfat = self.vfat.borrow_mut();
+            vfat.read_chain(self.start, &mut data)?;
+            vfat.root_dir_cluster()
+        };
+
+        Ok(DirIter {
+            vfat: self.vfat.clone(),
+            entries: unsafe { data.cast() },
+            index: 0,
+            dir: self.start,
+            root,
+            long_name: None,
+        })
+    }
+}
+
+/// An iterator over the entries of a directory.
+pub struct DirIter {
+    vfat: Shared<VFat>,
+    entries: Vec<VFatDirEntry>,
+    index: usize,
+    /// The first cluster of the directory.
+    dir: Cluster,
+    /// The first cluster of the root directory, which `..` entries in its
+    /// subdirectories name as cluster 0.
+    root: Cluster,
+    /// The long name the LFN entries seen since the last regular entry hold,
+    /// and the slot of the first of them.
+    long_name: Option<(LongName, usize)>,
+}
+
+impl Iterator for DirIter {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        while self.index < self.entries.len() {
+            let (unknown, regular, lfn) = unsafe {
+                let entry = &self.entries[self.index];
+                (entry.unknown, entry.regular, entry.long_filename)
+            };
+
+            let index = self.index;
+            self.index += 1;
+            if unknown.id == END {
+                self.index = self.entries.len();
+                return None;
+            }
+
+            if unknown.id == DELETED {
+                self.long_name = None;
+                continue;
+            }
+
+            if unknown.attributes.lfn() {
+                self.long_name = match self.long_name.take() {
+                    Some((name, first)) if lfn.sequence & LFN_LAST == 0 => {
+                        name.add(&lfn).map(|name| (name, first))
+                    }
+                    _ => LongName::start(&lfn).map(|name| (name, index)),
+                };
+
+                continue;
+            }
+
+            let long_name = self.long_name.take();
+            if unknown.attributes.volume_id() {
+                continue;
+            }
+
+            let (name, first) = match long_name {
+                Some((name, first)) => match name.finish(&regular) {
+                    Some(name) => (name, first),
+                    None => (regular.short_name(), index),
+                },
+                None => (regular.short_name(), index),
+            };
+
+            let location = Location { dir: self.dir, first: first as u64, entry: index as u64 };
+            let metadata = regular.metadata();
+            let vfat = self.vfat.clone();
+            if metadata.attributes.directory() {
+                let start = match regular.cluster().number() {
+                    0 => self.root,
+                    _ => regular.cluster(),
+                };
+
+                let location = Some(location);
+                return Some(Entry::Dir(Dir { vfat, start, name, metadata, location }));
+            }
+
+            return Some(Entry::File(File::new(vfat, regular.cluster(), name, metadata,
+                                              regular.size, location)));
+        }
+
+        None
+    }
+}
@@ -1,16 +1,51 @@
 use std::fmt;
 
 use traits::BlockDevice;
-use vfat::Error;
+use vfat::{Error, FatType};
 
 #[repr(C, packed)]
 pub struct BiosParameterBlock {
-    // FIXME: Fill me in.
+    jump: [u8; 3],
+    oem_id: [u8; 8],
+    /// The number of bytes in a logical sector.
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u8,
+    /// The number of sectors before the first FAT, this one included.
+    pub reserved_sectors: u16,
+    pub num_fats: u8,
+    /// The number of entries in the root directory of FAT12 and FAT16.
+    max_dir_entries: u16,
+    total_logical_sectors_16: u16,
+    media_descriptor: u8,
+    sectors_per_fat_16: u16,
+    sectors_per_track: u16,
+    num_heads: u16,
+    hidden_sectors: u32,
+    total_logical_sectors_32: u32,
+    /// The number of sectors in each FAT of FAT32. See `fat_sectors()`.
+    pub sectors_per_fat: u32,
+    flags: u16,
+    version: u16,
+    /// The first cluster of the root directory of FAT32.
+    pub root_dir_cluster: u32,
+    /// The sector of the FSInfo structure of FAT32.
+    pub fsinfo_sector: u16,
+    backup_boot_sector: u16,
+    reserved: [u8; 12],
+    drive_number: u8,
+    reserved_flags: u8,
+    signature: u8,
+    volume_id: u32,
+    volume_label: [u8; 11],
+    system_id: [u8; 8],
+    boot_code: [u8; 420],
+    boot_signature: [u8; 2],
 }
 
 impl BiosParameterBlock {
-    /// Reads the FAT32 extended BIOS parameter block from sector `sector` of
-    /// device `device`.
+    /// Reads the BIOS parameter block from sector `sector` of device
+    /// `device`. The fields past the common ones are only meaningful for
+    /// FAT32; see `fat_type()`.
     ///
     /// # Errors
     ///
@@ -19,12 +54,88 @@ impl BiosParameterBlock {
         mut device: T,
         sector: u64
     ) -> Result<BiosParameterBlock, Error> {
-        unimplemented!("BiosParameterBlock::from()")
+        let mut data = [0u8; 512];
+        device.read_sector(sector, &mut data)?;
+
+        let ebpb: BiosParameterBlock = unsafe { ::std::mem::transmute(data) };
+        if ebpb.boot_signature != [0x55, 0xAA] {
+            return Err(Error::BadSignature);
+        }
+
+        Ok(ebpb)
+    }
+
+    /// The total number of logical sectors in the file system.
+    pub fn total_sectors(&self) -> u32 {
+        match self.total_logical_sectors_16 {
+            0 => self.total_logical_sectors_32,
+            sectors => sectors as u32,
+        }
+    }
+
+    /// The number of sectors in each FAT. FAT12 and FAT16 give it in a
+    /// 16-bit field, which FAT32 leaves 0.
+    pub fn fat_sectors(&self) -> u32 {
+        match self.sectors_per_fat_16 {
+            0 => self.sectors_per_fat,
+            sectors => sectors as u32,
+        }
+    }
+
+    /// The number of entries in the root directory of FAT12 and FAT16, whose
+    /// root directory sits between the FATs and the data. 0 for FAT32.
+    pub fn root_dir_entries(&self) -> u16 {
+        self.max_dir_entries
+    }
+
+    /// The number of sectors the root directory of FAT12 and FAT16 takes.
+    pub fn root_dir_sectors(&self) -> u32 {
+        let bytes_per_sector = self.bytes_per_sector as u32;
+        match bytes_per_sector {
+            0 => 0,
+            _ => (self.max_dir_entries as u32 * 32 + bytes_per_sector - 1) / bytes_per_sector,
+        }
+    }
+
+    /// The number of clusters in the data region.
+    pub fn data_clusters(&self) -> u64 {
+        let metadata = self.reserved_sectors as u64
+            + self.num_fats as u64 * self.fat_sectors() as u64
+            + self.root_dir_sectors() as u64;
+        match self.sectors_per_cluster {
+            0 => 0,
+            per_cluster => (self.total_sectors() as u64).saturating_sub(metadata) / per_cluster as u64,
+        }
+    }
+
+    /// The variant of the file system. A BPB that only gives the size of a
+    /// FAT in the FAT32 field is FAT32, however few clusters it has, as other
+    /// implementations also decide; otherwise the cluster count decides
+    /// between FAT12 and FAT16, as the specification requires.
+    pub fn fat_type(&self) -> FatType {
+        match self.sectors_per_fat_16 {
+            0 => FatType::Fat32,
+            _ => FatType::from_clusters(self.data_clusters()),
+        }
     }
 }
 
 impl fmt::Debug for BiosParameterBlock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        unimplemented!("BiosParameterBlock::debug()")
+        f.debug_struct("BiosParameterBlock")
+            .field("oem_id", &String::from_utf8_lossy(&self.oem_id))
+            .field("bytes_per_sector", &{ self.bytes_per_sector })
+            .field("sectors_per_cluster", &self.sectors_per_cluster)
+            .field("reserved_sectors", &{ self.reserved_sectors })
+            .field("num_fats", &self.num_fats)
+            .field("total_sectors", &self.total_sectors())
+            .field("fat_type", &self.fat_type())
+            .field("fat_sectors", &self.fat_sectors())
+            .field("root_dir_entries", &{ self.max_dir_entries })
+            .field("root_dir_cluster", &{ self.root_dir_cluster })
+            .field("fsinfo_sector", &{ self.fsinfo_sector })
+            .field("volume_id", &{ self.volume_id })
+            .field("volume_label", &String::from_utf8_lossy(&self.volume_label))
+            .finish()
     }
 }
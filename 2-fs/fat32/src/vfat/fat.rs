@@ -19,20 +19,84 @@ pub enum Status {
     Eoc(u32)
 }
 
+/// The variant of a FAT file system, which is the width of its FAT entries.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// Returns the variant of a FAT12 or FAT16 file system with `clusters`
+    /// data clusters: FAT12 if there are fewer than 4085, FAT16 otherwise.
+    pub fn from_clusters(clusters: u64) -> FatType {
+        if clusters < 4085 {
+            FatType::Fat12
+        } else {
+            FatType::Fat16
+        }
+    }
+
+    /// The number of bits in a FAT entry.
+    pub fn bits(self) -> u64 {
+        match self {
+            FatType::Fat12 => 12,
+            FatType::Fat16 => 16,
+            FatType::Fat32 => 32,
+        }
+    }
+
+    /// The bits of a FAT entry that hold its value. FAT32 entries keep their
+    /// top four bits reserved.
+    pub fn mask(self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+            FatType::Fat32 => 0x0FFFFFFF,
+        }
+    }
+
+    /// One more than the highest cluster number a FAT entry can point to.
+    /// The sixteen values from there up are reserved, bad and EOC markers.
+    pub fn cluster_limit(self) -> u32 {
+        self.mask() - 0xF
+    }
+
+    /// Returns the FAT entry holding `value`, an entry read from a FAT of this
+    /// variant. Reserved, bad and EOC markers are widened to their FAT32
+    /// values, so that `status()` reads entries of every variant alike.
+    pub fn entry(self, value: u32) -> FatEntry {
+        let value = value & self.mask();
+        if value >= self.cluster_limit() {
+            FatEntry(value | 0x0FFFFFFF & !self.mask())
+        } else {
+            FatEntry(value)
+        }
+    }
+}
+
 #[repr(C, packed)]
 pub struct FatEntry(pub u32);
 
 impl FatEntry {
     /// Returns the `Status` of the FAT entry `self`.
     pub fn status(&self) -> Status {
-        unimplemented!("FatEntry::status()")
+        match self.0 & !(0xF << 28) {
+            0x0000000 => Free,
+            0x0000001 => Reserved,
+            0x0000002...0xFFFFFEF => Data(Cluster::from(self.0)),
+            0xFFFFFF7 => Bad,
+            value @ 0xFFFFFF8...0xFFFFFFF => Eoc(value),
+            _ => Reserved,
+        }
     }
 }
 
 impl fmt::Debug for FatEntry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("FatEntry")
-            .field("value", &self.0)
+            .field("value", &{ self.0 })
             .field("status", &self.status())
             .finish()
     }
@@ -1,15 +1,271 @@
-use std::cmp::{min, max};
-use std::io::{self, SeekFrom};
+use std::cmp::min;
+use std::io::{self, SeekFrom, Write};
 
 use traits;
-use vfat::{VFat, Shared, Cluster, Metadata};
+use vfat::{VFat, Shared, Cluster, Metadata, Attributes, Location};
 
 #[derive(Debug)]
 pub struct File {
-    // FIXME: Fill me in.
+    vfat: Shared<VFat>,
+    /// The first cluster of the file's data.
+    start: Cluster,
+    name: String,
+    metadata: Metadata,
+    size: u32,
+    /// Where the file's directory entry is.
+    location: Location,
+    /// The offset reads and writes continue from.
+    position: u64,
+    /// The cluster holding the byte at `position` and its index in the chain,
+    /// remembered so that sequential reads don't walk the chain from the start.
+    current: Option<(Cluster, u64)>,
+    /// Whether the file was written to since it was last synced.
+    dirty: bool,
 }
 
-// FIXME: Implement `traits::File` (and its supertraits) for `File`.
+impl File {
+    pub(crate) fn new(
+        vfat: Shared<VFat>,
+        start: Cluster,
+        name: String,
+        metadata: Metadata,
+        size: u32,
+        location: Location
+    ) -> File {
+        File { vfat, start, name, metadata, size, location, position: 0, current: None,
+               dirty: false }
+    }
+
+    /// The name of the file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// The first cluster of the file's data, or cluster 0 if it has none.
+    pub(crate) fn start(&self) -> Cluster {
+        self.start
+    }
+
+    /// Where the file's directory entry is.
+    pub(crate) fn location(&self) -> Location {
+        self.location
+    }
+
+    /// Returns the cluster at index `index` of the file's chain, walking from
+    /// the current cluster if it is at or before `index`. If `extend` is
+    /// `true`, clusters are allocated for the chain to reach `index`.
+    fn cluster_at(&mut self, vfat: &mut VFat, index: u64, extend: bool) -> io::Result<Cluster> {
+        if self.start.number() == 0 && extend {
+            self.start = vfat.alloc_cluster(None)?;
+            self.current = None;
+        }
+
+        let (mut cluster, mut at) = match self.current {
+            Some((cluster, at)) if at <= index => (cluster, at),
+            _ => (self.start, 0),
+        };
+
+        while at < index {
+            cluster = match vfat.next_cluster(cluster)? {
+                Some(next) => next,
+                None if extend => vfat.alloc_cluster(Some(cluster))?,
+                None => {
+                    return Err(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                              "cluster chain is shorter than file"));
+                }
+            };
+
+            at += 1;
+        }
+
+        self.current = Some((cluster, at));
+        Ok(cluster)
+    }
+
+    /// Writes the file's first cluster, size and attributes to its directory
+    /// entry.
+    fn update_entry(&self, vfat: &mut VFat) -> io::Result<()> {
+        self.location.update(vfat, self.start, self.size, self.metadata.attributes)
+    }
+
+    /// Truncates or extends the file to `size` bytes. Extending the file
+    /// fills it with zeroes. The position is moved back to the new end of the
+    /// file if it lies beyond it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `size` is larger than the largest
+    /// file FAT32 can hold, 4GiB - 1.
+    pub fn set_len(&mut self, size: u64) -> io::Result<()> {
+        if size > u32::max_value() as u64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "file size is too large"));
+        }
+
+        if size > self.size as u64 {
+            let position = self.position;
+            self.position = self.size as u64;
+            let zeroes = [0; 512];
+            while self.position < size {
+                let len = min(zeroes.len() as u64, size - self.position) as usize;
+                self.write_all(&zeroes[..len])?;
+            }
+
+            self.position = position;
+            return self.flush();
+        }
+
+        let vfat = self.vfat.clone();
+        let mut vfat = vfat.borrow_mut();
+        let cluster_size = vfat.cluster_size() as u64;
+        let (start, last) = match size {
+            0 => (self.start, None),
+            _ => (self.start, Some(self.cluster_at(&mut vfat, (size - 1) / cluster_size, false)?)),
+        };
+
+        self.size = size as u32;
+        self.position = min(self.position, size);
+        self.current = None;
+        if last.is_none() {
+            self.start = Cluster::from(0);
+        }
+
+        // The entry must stop pointing at the clusters before they are freed.
+        self.update_entry(&mut vfat)?;
+        vfat.sync()?;
+        match last {
+            Some(last) => vfat.truncate_chain(last)?,
+            None if start.number() != 0 => vfat.free_chain(start)?,
+            None => (),
+        }
+
+        self.dirty = false;
+        vfat.sync()
+    }
+
+    /// Removes the file's directory entry and frees its clusters.
+    pub(crate) fn remove(mut self, vfat: &mut VFat) -> io::Result<()> {
+        self.dirty = false;
+        self.location.delete(vfat)?;
+        vfat.sync()?;
+        if self.start.number() != 0 {
+            vfat.free_chain(self.start)?;
+        }
+
+        vfat.sync()
+    }
+}
+
+impl traits::File for File {
+    /// Writes the file's data and directory entry, and every other change to
+    /// the file system, to the disk.
+    fn sync(&mut self) -> io::Result<()> {
+        self.vfat.borrow_mut().sync()?;
+        self.dirty = false;
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.size as u64
+    }
+}
+
+impl io::Read for File {
+    /// Reads from the current position, walking the cluster chain as the
+    /// position crosses cluster boundaries.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cluster chain is broken before any byte is
+    /// read. If some bytes were read, they are returned and the next call
+    /// returns the error.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = (self.size as u64).saturating_sub(self.position);
+        let len = min(buf.len() as u64, remaining) as usize;
+        if len == 0 {
+            return Ok(0);
+        }
+
+        let vfat = self.vfat.clone();
+        let mut vfat = vfat.borrow_mut();
+        let cluster_size = vfat.cluster_size() as u64;
+
+        let mut read = 0;
+        while read < len {
+            let cluster = match self.cluster_at(&mut vfat, self.position / cluster_size, false) {
+                Ok(cluster) => cluster,
+                Err(_) if read > 0 => break,
+                Err(e) => return Err(e),
+            };
+
+            let offset = (self.position % cluster_size) as usize;
+            let n = vfat.read_cluster(cluster, offset, &mut buf[read..len])?;
+            read += n;
+            self.position += n as u64;
+        }
+
+        Ok(read)
+    }
+}
+
+impl io::Write for File {
+    /// Writes `buf` at the current position, allocating clusters as the file
+    /// grows. The data reaches the disk when the file is flushed, synced or
+    /// dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` if the file would grow past 4GiB - 1 or the
+    /// file system is full.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let room = (u32::max_value() as u64).saturating_sub(self.position);
+        let len = min(buf.len() as u64, room) as usize;
+        if len == 0 {
+            if buf.is_empty() {
+                return Ok(0);
+            }
+
+            return Err(io::Error::new(io::ErrorKind::Other, "file is too large"));
+        }
+
+        let vfat = self.vfat.clone();
+        let mut vfat = vfat.borrow_mut();
+        let cluster_size = vfat.cluster_size() as u64;
+
+        let mut written = 0;
+        while written < len {
+            let cluster = self.cluster_at(&mut vfat, self.position / cluster_size, true)?;
+            let offset = (self.position % cluster_size) as usize;
+            let n = vfat.write_cluster(cluster, offset, &buf[written..len])?;
+            written += n;
+            self.position += n as u64;
+        }
+
+        self.size = ::std::cmp::max(self.size, self.position as u32);
+        self.metadata.attributes = Attributes::new(self.metadata.attributes.bits()
+                                                   | Attributes::ARCHIVE);
+        self.update_entry(&mut vfat)?;
+        self.dirty = true;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        traits::File::sync(self)
+    }
+}
+
+impl Drop for File {
+    /// Syncs the file if it was written to, ignoring errors. Call `sync` to
+    /// see them.
+    fn drop(&mut self) {
+        if self.dirty {
+            let _ = self.vfat.borrow_mut().sync();
+        }
+    }
+}
 
 impl io::Seek for File {
     /// Seek to offset `pos` in the file.
@@ -26,6 +282,18 @@ impl io::Seek for File {
     /// Seeking before the start of a file or beyond the end of the file results
     /// in an `InvalidInput` error.
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
-        unimplemented!("File::seek()")
+        let size = self.size as i64;
+        let position = match pos {
+            SeekFrom::Start(offset) => min(offset, i64::max_value() as u64) as i64,
+            SeekFrom::End(offset) => size.saturating_add(offset),
+            SeekFrom::Current(offset) => (self.position as i64).saturating_add(offset),
+        };
+
+        if position < 0 || position > size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek out of bounds"));
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
     }
 }
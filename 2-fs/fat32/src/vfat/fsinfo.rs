@@ -0,0 +1,64 @@
+use std::fmt;
+
+use util::SliceExt;
+
+/// The FSInfo structure, which keeps hints for allocating clusters: how many
+/// are free and where to start looking for one. Either may be stale or
+/// `UNKNOWN`.
+#[repr(C, packed)]
+pub struct FsInfo {
+    lead_signature: u32,
+    __r0: [u8; 480],
+    struct_signature: u32,
+    /// The number of free clusters.
+    pub free_clusters: u32,
+    /// The cluster to start looking for a free one at.
+    pub next_free: u32,
+    __r1: [u8; 12],
+    trail_signature: u32,
+}
+
+/// The value of a hint that isn't known.
+pub const UNKNOWN: u32 = 0xFFFFFFFF;
+
+const LEAD_SIGNATURE: u32 = 0x41615252;
+const STRUCT_SIGNATURE: u32 = 0x61417272;
+const TRAIL_SIGNATURE: u32 = 0xAA550000;
+
+impl FsInfo {
+    /// Returns the FSInfo structure at the start of the sector `sector`, or
+    /// `None` if its signatures are invalid.
+    pub fn from_sector(sector: &[u8]) -> Option<&FsInfo> {
+        if sector.len() < 512 {
+            return None;
+        }
+
+        let fsinfo = unsafe { &sector[..512].cast::<FsInfo>()[0] };
+        if fsinfo.lead_signature != LEAD_SIGNATURE
+            || fsinfo.struct_signature != STRUCT_SIGNATURE
+            || fsinfo.trail_signature != TRAIL_SIGNATURE {
+            return None;
+        }
+
+        Some(fsinfo)
+    }
+
+    /// Returns the FSInfo structure at the start of the sector `sector` for
+    /// writing, or `None` if its signatures are invalid.
+    pub fn from_sector_mut(sector: &mut [u8]) -> Option<&mut FsInfo> {
+        if FsInfo::from_sector(sector).is_none() {
+            return None;
+        }
+
+        Some(unsafe { &mut sector[..512].cast_mut::<FsInfo>()[0] })
+    }
+}
+
+impl fmt::Debug for FsInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FsInfo")
+            .field("free_clusters", &{ self.free_clusters })
+            .field("next_free", &{ self.next_free })
+            .finish()
+    }
+}
@@ -17,6 +17,94 @@ pub struct Time(u16);
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Attributes(u8);
 
+impl Date {
+    /// The year, from 1980.
+    pub fn year(&self) -> usize {
+        1980 + (self.0 >> 9) as usize
+    }
+
+    /// The month, from 1.
+    pub fn month(&self) -> u8 {
+        ((self.0 >> 5) & 0b1111) as u8
+    }
+
+    /// The day of the month, from 1.
+    pub fn day(&self) -> u8 {
+        (self.0 & 0b11111) as u8
+    }
+}
+
+impl Time {
+    pub fn hour(&self) -> u8 {
+        (self.0 >> 11) as u8
+    }
+
+    pub fn minute(&self) -> u8 {
+        ((self.0 >> 5) & 0b111111) as u8
+    }
+
+    /// The second. FAT32 stores seconds in units of two.
+    pub fn second(&self) -> u8 {
+        ((self.0 & 0b11111) * 2) as u8
+    }
+}
+
+impl Attributes {
+    pub const READ_ONLY: u8 = 0x01;
+    pub const HIDDEN: u8 = 0x02;
+    pub const SYSTEM: u8 = 0x04;
+    pub const VOLUME_ID: u8 = 0x08;
+    pub const DIRECTORY: u8 = 0x10;
+    pub const ARCHIVE: u8 = 0x20;
+
+    /// The combination of attributes that marks a long file name entry.
+    pub const LFN: u8 = 0x0F;
+
+    /// Returns the attributes with the bits `bits` set.
+    pub fn new(bits: u8) -> Attributes {
+        Attributes(bits)
+    }
+
+    /// Returns the raw attribute bits.
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// Returns `true` if every bit of `attributes` is set.
+    pub fn has(&self, attributes: u8) -> bool {
+        self.0 & attributes == attributes
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.has(Attributes::READ_ONLY)
+    }
+
+    pub fn hidden(&self) -> bool {
+        self.has(Attributes::HIDDEN)
+    }
+
+    pub fn system(&self) -> bool {
+        self.has(Attributes::SYSTEM)
+    }
+
+    pub fn volume_id(&self) -> bool {
+        self.has(Attributes::VOLUME_ID)
+    }
+
+    pub fn directory(&self) -> bool {
+        self.has(Attributes::DIRECTORY)
+    }
+
+    pub fn archive(&self) -> bool {
+        self.has(Attributes::ARCHIVE)
+    }
+
+    /// Returns `true` if these are the attributes of a long file name entry.
+    pub fn lfn(&self) -> bool {
+        self.0 == Attributes::LFN
+    }
+}
+
 /// A structure containing a date and time.
 #[derive(Default, Copy, Clone, Debug, PartialEq, Eq)]
 pub struct Timestamp {
@@ -27,11 +115,108 @@ pub struct Timestamp {
 /// Metadata for a directory entry.
 #[derive(Default, Debug, Clone)]
 pub struct Metadata {
-    // FIXME: Fill me in.
+    pub attributes: Attributes,
+    pub created: Timestamp,
+    /// FAT32 only records the date of the last access; the time is midnight.
+    pub accessed: Timestamp,
+    pub modified: Timestamp,
+}
+
+impl Timestamp {
+    /// The earliest time FAT32 can record, 1980-01-01 00:00:00. New entries
+    /// are stamped with it, as there is no clock to read.
+    pub const EPOCH: Timestamp = Timestamp { date: Date(1 << 5 | 1), time: Time(0) };
+}
+
+impl traits::Timestamp for Timestamp {
+    fn year(&self) -> usize {
+        self.date.year()
+    }
+
+    fn month(&self) -> u8 {
+        self.date.month()
+    }
+
+    fn day(&self) -> u8 {
+        self.date.day()
+    }
+
+    fn hour(&self) -> u8 {
+        self.time.hour()
+    }
+
+    fn minute(&self) -> u8 {
+        self.time.minute()
+    }
+
+    fn second(&self) -> u8 {
+        self.time.second()
+    }
+}
+
+impl traits::Metadata for Metadata {
+    type Timestamp = Timestamp;
+
+    fn read_only(&self) -> bool {
+        self.attributes.read_only()
+    }
+
+    fn hidden(&self) -> bool {
+        self.attributes.hidden()
+    }
+
+    fn system(&self) -> bool {
+        self.attributes.system()
+    }
+
+    fn archive(&self) -> bool {
+        self.attributes.archive()
+    }
+
+    fn created(&self) -> Timestamp {
+        self.created
+    }
+
+    fn accessed(&self) -> Timestamp {
+        self.accessed
+    }
+
+    fn modified(&self) -> Timestamp {
+        self.modified
+    }
 }
 
-// FIXME: Implement `traits::Timestamp` for `Timestamp`.
+impl fmt::Display for Timestamp {
+    /// Writes the timestamp as `YYYY-MM-DD HH:MM:SS`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", traits::Timestamp::date_time(self))
+    }
+}
+
+impl fmt::Display for Attributes {
+    /// Writes the attributes as flags, `d` for a directory, `r` for read
+    /// only, `h` for hidden, `s` for system and `a` for archive, or `-` for
+    /// each that isn't set.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [
+            (self.directory(), 'd'),
+            (self.read_only(), 'r'),
+            (self.hidden(), 'h'),
+            (self.system(), 's'),
+            (self.archive(), 'a'),
+        ];
 
-// FIXME: Implement `traits::Metadata` for `Metadata`.
+        for &(set, flag) in flags.iter() {
+            write!(f, "{}", if set { flag } else { '-' })?;
+        }
 
-// FIXME: Implement `fmt::Display` (to your liking) for `Metadata`.
+        Ok(())
+    }
+}
+
+impl fmt::Display for Metadata {
+    /// Writes the attributes as flags, followed by the modification time.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.attributes, self.modified)
+    }
+}
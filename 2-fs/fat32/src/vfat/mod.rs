@@ -2,6 +2,7 @@ pub(crate) mod file;
 pub(crate) mod dir;
 pub(crate) mod vfat;
 pub(crate) mod ebpb;
+pub(crate) mod fsinfo;
 pub(crate) mod error;
 pub(crate) mod cluster;
 pub(crate) mod fat;
@@ -9,6 +10,7 @@ pub(crate) mod entry;
 pub(crate) mod metadata;
 pub(crate) mod cache;
 pub(crate) mod shared;
+pub(crate) mod check;
 
 pub use self::ebpb::BiosParameterBlock;
 pub use self::file::File;
@@ -18,7 +20,11 @@ pub use self::vfat::VFat;
 pub use self::entry::Entry;
 pub use self::metadata::{Metadata, Attributes, Date, Time, Timestamp};
 pub use self::shared::Shared;
+pub use self::check::{Report, Problem};
 
 pub(crate) use self::cache::{CachedDevice, Partition};
 pub(crate) use self::fat::{Status, FatEntry};
+pub use self::fat::FatType;
 pub(crate) use self::cluster::Cluster;
+pub(crate) use self::fsinfo::FsInfo;
+pub(crate) use self::dir::Location;
@@ -1,84 +1,699 @@
 use std::io;
-use std::path::Path;
-use std::mem::size_of;
+use std::path::{Component, Path, PathBuf};
+use std::mem;
 use std::cmp::min;
+use std::collections::BTreeSet;
 
-use util::SliceExt;
 use mbr::MasterBootRecord;
-use vfat::{Shared, Cluster, File, Dir, Entry, FatEntry, Error, Status};
-use vfat::{BiosParameterBlock, CachedDevice, Partition};
-use traits::{FileSystem, BlockDevice};
+use vfat::{Shared, Cluster, File, Dir, Entry, FatEntry, FatType, Error, Status};
+use vfat::{BiosParameterBlock, CachedDevice, Partition, FsInfo};
+use vfat::fsinfo::UNKNOWN;
+use traits::{FileSystem, BlockDevice, Entry as EntryTrait, Dir as DirTrait};
+
+/// The FAT entry value that marks the last cluster of a chain. FAT12 and
+/// FAT16 store its low bits.
+const EOC: u32 = 0x0FFFFFFF;
+
+/// The FAT entry bits that are reserved and must be kept when writing one.
+const RESERVED_BITS: u32 = 0xF << 28;
 
 #[derive(Debug)]
 pub struct VFat {
     device: CachedDevice,
+    fat_type: FatType,
     bytes_per_sector: u16,
     sectors_per_cluster: u8,
     sectors_per_fat: u32,
+    num_fats: u8,
     fat_start_sector: u64,
     data_start_sector: u64,
+    /// The first cluster of the root directory. FAT12 and FAT16 keep the
+    /// root directory outside of the data region; it is cluster 0 there.
     root_dir_cluster: Cluster,
+    /// The first sector of the root directory of FAT12 and FAT16.
+    root_dir_sector: u64,
+    /// The number of entries in the root directory of FAT12 and FAT16.
+    root_dir_entries: u32,
+    /// One more than the number of the last cluster.
+    cluster_limit: u32,
+    /// The sector of the FSInfo structure, if it is valid.
+    fsinfo_sector: Option<u64>,
+    /// The number of free clusters, if it is known.
+    free_clusters: Option<u32>,
+    /// The cluster to start looking for a free one at.
+    next_free: u32,
+    /// The sectors holding directory entries that were written since the last
+    /// sync.
+    entry_sectors: BTreeSet<u64>,
 }
 
 impl VFat {
+    /// Mounts the first FAT12, FAT16 or FAT32 partition of `device`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Mbr` if the MBR is invalid, `NotFound` if no partition holds
+    /// a FAT file system, `BadSignature` if its BPB is invalid, and `Io` if
+    /// reading from `device` fails.
     pub fn from<T>(mut device: T) -> Result<Shared<VFat>, Error>
         where T: BlockDevice + 'static
     {
-        unimplemented!("VFat::from()")
-    }
-
-    // TODO: The following methods may be useful here:
-    //
-    //  * A method to read from an offset of a cluster into a buffer.
-    //
-    //    fn read_cluster(
-    //        &mut self,
-    //        cluster: Cluster,
-    //        offset: usize,
-    //        buf: &mut [u8]
-    //    ) -> io::Result<usize>;
-    //
-    //  * A method to read all of the clusters chained from a starting cluster
-    //    into a vector.
-    //
-    //    fn read_chain(
-    //        &mut self,
-    //        start: Cluster,
-    //        buf: &mut Vec<u8>
-    //    ) -> io::Result<usize>;
-    //
-    //  * A method to return a reference to a `FatEntry` for a cluster where the
-    //    reference points directly into a cached sector.
-    //
-    //    fn fat_entry(&mut self, cluster: Cluster) -> io::Result<&FatEntry>;
+        let mbr = MasterBootRecord::from(&mut device)?;
+        let start = match mbr.first_fat() {
+            Some(partition) => partition.relative_sector as u64,
+            None => return Err(Error::NotFound),
+        };
+
+        let ebpb = BiosParameterBlock::from(&mut device, start)?;
+        let fat_type = ebpb.fat_type();
+        if ebpb.bytes_per_sector == 0 || ebpb.bytes_per_sector as u64 % device.sector_size() != 0
+            || ebpb.sectors_per_cluster == 0 || ebpb.fat_sectors() == 0
+            || (fat_type != FatType::Fat32 && ebpb.root_dir_entries() == 0) {
+            return Err(Error::BadSignature);
+        }
+
+        let (root_dir_cluster, root_dir_entries) = match fat_type {
+            FatType::Fat32 => (Cluster::from(ebpb.root_dir_cluster), 0),
+            _ => (Cluster::from(0), ebpb.root_dir_entries() as u32),
+        };
+
+        let fat_start_sector = start + ebpb.reserved_sectors as u64;
+        let root_dir_sector = fat_start_sector
+            + ebpb.num_fats as u64 * ebpb.fat_sectors() as u64;
+        let data_start_sector = root_dir_sector + ebpb.root_dir_sectors() as u64;
+        let fat_entries = ebpb.fat_sectors() as u64 * ebpb.bytes_per_sector as u64 * 8
+            / fat_type.bits();
+        let cluster_limit = min(fat_entries, 2 + ebpb.data_clusters());
+        let partition = Partition { start, sector_size: ebpb.bytes_per_sector as u64 };
+
+        let mut vfat = VFat {
+            device: CachedDevice::new(device, partition),
+            fat_type,
+            bytes_per_sector: ebpb.bytes_per_sector,
+            sectors_per_cluster: ebpb.sectors_per_cluster,
+            sectors_per_fat: ebpb.fat_sectors(),
+            num_fats: ebpb.num_fats,
+            fat_start_sector,
+            data_start_sector,
+            root_dir_cluster,
+            root_dir_sector,
+            root_dir_entries,
+            cluster_limit: min(cluster_limit, fat_type.cluster_limit() as u64) as u32,
+            fsinfo_sector: None,
+            free_clusters: None,
+            next_free: 2,
+            entry_sectors: BTreeSet::new(),
+        };
+
+        let fsinfo_sector = start + ebpb.fsinfo_sector as u64;
+        if fat_type == FatType::Fat32 && ebpb.fsinfo_sector != 0
+            && fsinfo_sector < fat_start_sector {
+            vfat.read_fsinfo(fsinfo_sector)?;
+        }
+
+        Ok(Shared::new(vfat))
+    }
+
+    /// Takes the allocation hints from the FSInfo structure in sector
+    /// `sector`, if it is valid, ignoring hints that are out of range.
+    fn read_fsinfo(&mut self, sector: u64) -> io::Result<()> {
+        let (free_clusters, next_free) = match FsInfo::from_sector(self.device.get(sector)?) {
+            Some(fsinfo) => (fsinfo.free_clusters, fsinfo.next_free),
+            None => return Ok(()),
+        };
+
+        self.fsinfo_sector = Some(sector);
+        if free_clusters != UNKNOWN && free_clusters < self.cluster_limit {
+            self.free_clusters = Some(free_clusters);
+        }
+
+        if next_free >= 2 && next_free < self.cluster_limit {
+            self.next_free = next_free;
+        }
+
+        Ok(())
+    }
+
+    /// Updates the FSInfo structure, if there is one, with the current hints.
+    fn write_fsinfo(&mut self) -> io::Result<()> {
+        let sector = match self.fsinfo_sector {
+            Some(sector) => sector,
+            None => return Ok(()),
+        };
+
+        let (free_clusters, next_free) = (self.free_clusters.unwrap_or(UNKNOWN), self.next_free);
+        if let Some(fsinfo) = FsInfo::from_sector_mut(self.device.get_mut(sector)?) {
+            fsinfo.free_clusters = free_clusters;
+            fsinfo.next_free = next_free;
+        }
+
+        Ok(())
+    }
+
+    /// The size of a cluster in bytes.
+    pub fn cluster_size(&self) -> usize {
+        self.bytes_per_sector as usize * self.sectors_per_cluster as usize
+    }
+
+    /// The variant of the file system.
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    /// The first cluster of the root directory. On FAT12 and FAT16, whose
+    /// root directory is outside of the data region, it is cluster 0.
+    pub fn root_dir_cluster(&self) -> Cluster {
+        self.root_dir_cluster
+    }
+
+    /// Returns `true` if `dir`, the first cluster of a directory, stands for
+    /// the fixed-size root directory of FAT12 and FAT16.
+    pub(crate) fn is_fixed_root(&self, dir: Cluster) -> bool {
+        self.fat_type != FatType::Fat32 && dir.number() == 0
+    }
+
+    /// The number of entries the FAT has room for.
+    fn fat_entries(&self) -> u64 {
+        self.sectors_per_fat as u64 * self.bytes_per_sector as u64 * 8 / self.fat_type.bits()
+    }
+
+    /// The number of free clusters, if it is known.
+    pub fn free_clusters(&self) -> Option<u32> {
+        self.free_clusters
+    }
+
+    /// Records that `free` clusters are free, in the FSInfo structure too if
+    /// there is one.
+    pub(crate) fn set_free_clusters(&mut self, free: u32) -> io::Result<()> {
+        self.free_clusters = Some(free);
+        self.write_fsinfo()
+    }
+
+    /// One more than the number of the last cluster.
+    pub(crate) fn cluster_limit(&self) -> u32 {
+        self.cluster_limit
+    }
+
+    /// Returns an error of `InvalidData` if `cluster` has no data or lies
+    /// beyond the end of the file system.
+    fn check_cluster(&self, cluster: Cluster) -> io::Result<u32> {
+        match cluster.data_index() {
+            Some(index) if cluster.number() < self.cluster_limit => Ok(index),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "invalid cluster number")),
+        }
+    }
+
+    /// Returns the sector holding byte `offset` of `cluster` and the offset
+    /// of that byte in the sector.
+    fn sector_of(&self, cluster: Cluster, offset: usize) -> io::Result<(u64, usize)> {
+        let index = self.check_cluster(cluster)?;
+        let sector_size = self.bytes_per_sector as usize;
+        let first_sector = self.data_start_sector
+            + index as u64 * self.sectors_per_cluster as u64;
+
+        Ok((first_sector + (offset / sector_size) as u64, offset % sector_size))
+    }
+
+    /// Reads from `offset` bytes into `cluster` into `buf`, stopping at the
+    /// end of the cluster. Returns the number of bytes read.
+    pub fn read_cluster(
+        &mut self,
+        cluster: Cluster,
+        offset: usize,
+        buf: &mut [u8]
+    ) -> io::Result<usize> {
+        self.check_cluster(cluster)?;
+        let len = min(buf.len(), self.cluster_size().saturating_sub(offset));
+        let mut read = 0;
+        while read < len {
+            let (sector, start) = self.sector_of(cluster, offset + read)?;
+            let data = self.device.get(sector)?;
+            let n = min(len - read, data.len() - start);
+            buf[read..read + n].copy_from_slice(&data[start..start + n]);
+            read += n;
+        }
+
+        Ok(read)
+    }
+
+    /// Writes `buf` to `cluster` from `offset` bytes into it, stopping at the
+    /// end of the cluster. Returns the number of bytes written.
+    ///
+    /// The data only reaches the disk when the file system is synced.
+    pub fn write_cluster(
+        &mut self,
+        cluster: Cluster,
+        offset: usize,
+        buf: &[u8]
+    ) -> io::Result<usize> {
+        self.check_cluster(cluster)?;
+        let len = min(buf.len(), self.cluster_size().saturating_sub(offset));
+        let mut written = 0;
+        while written < len {
+            let (sector, start) = self.sector_of(cluster, offset + written)?;
+            let data = self.device.get_mut(sector)?;
+            let n = min(len - written, data.len() - start);
+            data[start..start + n].copy_from_slice(&buf[written..written + n]);
+            written += n;
+        }
+
+        Ok(written)
+    }
+
+    /// Appends the contents of every cluster in the chain starting at
+    /// `start` to `buf`. Returns the number of bytes read.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if the chain runs into a free, bad
+    /// or reserved cluster, or is longer than the FAT, which means it loops.
+    pub fn read_chain(&mut self, start: Cluster, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let first = buf.len();
+        if self.is_fixed_root(start) {
+            let len = self.root_dir_entries as usize * 32;
+            buf.resize(first + len, 0);
+            let sector_size = self.bytes_per_sector as usize;
+            for (i, chunk) in buf[first..].chunks_mut(sector_size).enumerate() {
+                let data = self.device.get(self.root_dir_sector + i as u64)?;
+                let n = chunk.len();
+                chunk.copy_from_slice(&data[..n]);
+            }
+
+            return Ok(len);
+        }
+
+        let cluster_size = self.cluster_size();
+        let mut cluster = start;
+        let mut remaining = self.fat_entries();
+
+        loop {
+            let len = buf.len();
+            buf.resize(len + cluster_size, 0);
+            self.read_cluster(cluster, 0, &mut buf[len..])?;
+
+            match self.next_cluster(cluster)? {
+                Some(next) => cluster = next,
+                None => return Ok(buf.len() - first),
+            }
+
+            remaining -= 1;
+            if remaining == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "cluster chain loops"));
+            }
+        }
+    }
+
+    /// Returns the cluster after `cluster` in its chain, or `None` if it is
+    /// the last.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if `cluster` isn't part of a chain.
+    pub fn next_cluster(&mut self, cluster: Cluster) -> io::Result<Option<Cluster>> {
+        match self.fat_entry(cluster)?.status() {
+            Status::Data(next) => Ok(Some(next)),
+            Status::Eoc(_) => Ok(None),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidData, "broken cluster chain")),
+        }
+    }
+
+    /// Returns the FAT entry for `cluster`. FAT12 and FAT16 entries are
+    /// widened to FAT32 values; see `FatType::entry()`.
+    pub fn fat_entry(&mut self, cluster: Cluster) -> io::Result<FatEntry> {
+        self.check_cluster(cluster)?;
+        let bytes = self.read_fat_bytes(0, cluster)?;
+        let value = match self.fat_type {
+            FatType::Fat12 if cluster.number() % 2 == 1 => bytes >> 4,
+            _ => bytes,
+        };
+
+        Ok(self.fat_type.entry(value))
+    }
+
+    /// Sets the FAT entry for `cluster` to `value` in every copy of the FAT.
+    /// Only the low bits of `value` are stored on FAT12 and FAT16.
+    pub(crate) fn set_fat_entry(&mut self, cluster: Cluster, value: u32) -> io::Result<()> {
+        self.check_cluster(cluster)?;
+        for fat in 0..self.num_fats as u64 {
+            let bytes = self.read_fat_bytes(fat, cluster)?;
+            let bytes = match self.fat_type {
+                FatType::Fat32 => bytes & RESERVED_BITS | value & !RESERVED_BITS,
+                FatType::Fat16 => value & 0xFFFF,
+                // Two FAT12 entries share the middle byte of three.
+                FatType::Fat12 if cluster.number() % 2 == 1 => bytes & 0xF | (value & 0xFFF) << 4,
+                FatType::Fat12 => bytes & 0xF000 | value & 0xFFF,
+            };
+
+            self.write_fat_bytes(fat, cluster, bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the sector of FAT copy `fat` holding byte `offset` of the FAT
+    /// and the offset of that byte in the sector.
+    fn fat_byte(&self, fat: u64, offset: u64) -> (u64, usize) {
+        let sector_size = self.bytes_per_sector as u64;
+        let sector = self.fat_start_sector + fat * self.sectors_per_fat as u64 + offset / sector_size;
+        (sector, (offset % sector_size) as usize)
+    }
+
+    /// The offset in the FAT of the first byte holding the entry for
+    /// `cluster`, and the number of bytes to read to have all of it. A FAT12
+    /// entry is a byte and a half, so it may cross a sector boundary.
+    fn fat_bytes(&self, cluster: Cluster) -> (u64, u64) {
+        let offset = cluster.number() as u64 * self.fat_type.bits() / 8;
+        match self.fat_type {
+            FatType::Fat32 => (offset, 4),
+            _ => (offset, 2),
+        }
+    }
+
+    /// Reads the bytes holding the entry for `cluster` from FAT copy `fat`,
+    /// as a little-endian number.
+    fn read_fat_bytes(&mut self, fat: u64, cluster: Cluster) -> io::Result<u32> {
+        let (offset, len) = self.fat_bytes(cluster);
+        let mut value = 0;
+        for i in 0..len {
+            let (sector, start) = self.fat_byte(fat, offset + i);
+            value |= (self.device.get(sector)?[start] as u32) << (8 * i);
+        }
+
+        Ok(value)
+    }
+
+    /// Writes `value` as the bytes holding the entry for `cluster` in FAT
+    /// copy `fat`, little-endian.
+    fn write_fat_bytes(&mut self, fat: u64, cluster: Cluster, value: u32) -> io::Result<()> {
+        let (offset, len) = self.fat_bytes(cluster);
+        for i in 0..len {
+            let (sector, start) = self.fat_byte(fat, offset + i);
+            self.device.get_mut(sector)?[start] = (value >> (8 * i)) as u8;
+        }
+
+        Ok(())
+    }
+
+    /// Allocates a free cluster, fills it with zeroes and makes it the end
+    /// of a chain. If `previous` is given, the cluster is linked after it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` if every cluster is in use.
+    pub fn alloc_cluster(&mut self, previous: Option<Cluster>) -> io::Result<Cluster> {
+        let count = self.cluster_limit.saturating_sub(2);
+        for i in 0..count {
+            let cluster = Cluster::from(2 + (self.next_free - 2 + i) % count);
+            if self.fat_entry(cluster)?.status() != Status::Free {
+                continue;
+            }
+
+            self.set_fat_entry(cluster, EOC)?;
+            let zeroes = vec![0; self.cluster_size()];
+            self.write_cluster(cluster, 0, &zeroes)?;
+            if let Some(previous) = previous {
+                self.set_fat_entry(previous, cluster.number())?;
+            }
+
+            self.next_free = if cluster.number() + 1 < self.cluster_limit {
+                cluster.number() + 1
+            } else {
+                2
+            };
+
+            self.free_clusters = self.free_clusters.map(|free| free.saturating_sub(1));
+            self.write_fsinfo()?;
+            return Ok(cluster);
+        }
+
+        self.free_clusters = Some(0);
+        self.write_fsinfo()?;
+        Err(io::Error::new(io::ErrorKind::Other, "no space left on the file system"))
+    }
+
+    /// Frees every cluster in the chain starting at `start`.
+    pub fn free_chain(&mut self, start: Cluster) -> io::Result<()> {
+        let mut cluster = start;
+        let mut remaining = self.fat_entries();
+        loop {
+            let next = self.next_cluster(cluster)?;
+            self.set_fat_entry(cluster, 0)?;
+            self.free_clusters = self.free_clusters.map(|free| free + 1);
+
+            match next {
+                Some(next) => cluster = next,
+                None => break,
+            }
+
+            remaining -= 1;
+            if remaining == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "cluster chain loops"));
+            }
+        }
+
+        self.write_fsinfo()
+    }
+
+    /// Makes `last` the end of its chain and frees the clusters after it.
+    pub fn truncate_chain(&mut self, last: Cluster) -> io::Result<()> {
+        let next = self.next_cluster(last)?;
+        self.set_fat_entry(last, EOC)?;
+        match next {
+            Some(next) => self.free_chain(next),
+            None => Ok(()),
+        }
+    }
+
+    /// Allocates a cluster and links it to the end of the chain starting at
+    /// `start`. Returns the new cluster.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` for the root directory of FAT12 and FAT16,
+    /// which can't grow.
+    pub(crate) fn extend_chain(&mut self, start: Cluster) -> io::Result<Cluster> {
+        if self.is_fixed_root(start) {
+            return Err(io::Error::new(io::ErrorKind::Other, "directory is full"));
+        }
+
+        let mut last = start;
+        let mut remaining = self.fat_entries();
+        while let Some(next) = self.next_cluster(last)? {
+            last = next;
+            remaining -= 1;
+            if remaining == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "cluster chain loops"));
+            }
+        }
+
+        self.alloc_cluster(Some(last))
+    }
+
+    /// Returns the sector holding directory entry `index` of the directory
+    /// starting at `dir`, and the entry's offset in the sector.
+    fn entry_position(&mut self, dir: Cluster, index: u64) -> io::Result<(u64, usize)> {
+        if self.is_fixed_root(dir) {
+            if index >= self.root_dir_entries as u64 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          "directory entry is past its end"));
+            }
+
+            let sector_size = self.bytes_per_sector as u64;
+            let offset = index * 32;
+            return Ok((self.root_dir_sector + offset / sector_size, (offset % sector_size) as usize));
+        }
+
+        let per_cluster = (self.cluster_size() / 32) as u64;
+        let mut cluster = dir;
+        for _ in 0..index / per_cluster {
+            cluster = self.next_cluster(cluster)?.ok_or(
+                io::Error::new(io::ErrorKind::InvalidData, "directory entry is past its end"))?;
+        }
+
+        self.sector_of(cluster, (index % per_cluster) as usize * 32)
+    }
+
+    /// Reads directory entry `index` of the directory starting at `dir`.
+    pub(crate) fn read_entry(&mut self, dir: Cluster, index: u64) -> io::Result<[u8; 32]> {
+        let (sector, start) = self.entry_position(dir, index)?;
+        let mut entry = [0; 32];
+        entry.copy_from_slice(&self.device.get(sector)?[start..start + 32]);
+        Ok(entry)
+    }
+
+    /// Overwrites directory entry `index` of the directory starting at `dir`.
+    pub(crate) fn write_entry(&mut self, dir: Cluster, index: u64, entry: &[u8]) -> io::Result<()> {
+        let (sector, start) = self.entry_position(dir, index)?;
+        self.device.get_mut(sector)?[start..start + 32].copy_from_slice(&entry[..32]);
+        self.entry_sectors.insert(sector);
+        Ok(())
+    }
+
+    /// Writes every change back to the disk. Data goes first, then the FATs
+    /// that chain it, then the directory entries that point to the chains,
+    /// and the FSInfo hints last, so that a sync that is cut short can at
+    /// worst leave clusters allocated that nothing uses.
+    pub fn sync(&mut self) -> io::Result<()> {
+        let entries = mem::replace(&mut self.entry_sectors, BTreeSet::new());
+        let (fat_start, data_start) = (self.fat_start_sector, self.data_start_sector);
+
+        let result = self.device.write_back(|s| s >= data_start && !entries.contains(&s))
+            .and_then(|_| {
+                self.device.write_back(|s| s >= fat_start && s < data_start && !entries.contains(&s))
+            })
+            .and_then(|_| self.device.write_back(|s| entries.contains(&s)))
+            .and_then(|_| self.device.sync());
+
+        if result.is_err() {
+            self.entry_sectors.extend(entries);
+        }
+
+        result
+    }
+
+    /// Returns the directory that will hold the entry at `path` and the name
+    /// of the entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `path` isn't absolute, has no
+    /// final name, or its parent isn't an existing directory.
+    fn parent_of(vfat: &Shared<VFat>, path: &Path) -> io::Result<(Dir, String)> {
+        if !path.is_absolute() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "path is not absolute"));
+        }
+
+        let name = path.file_name().and_then(|name| name.to_str()).ok_or(
+            io::Error::new(io::ErrorKind::InvalidInput, "path has no valid final name"))?;
+        let parent = path.parent().expect("an absolute path with a name has a parent");
+        let dir = match vfat.open(parent) {
+            Ok(Entry::Dir(dir)) => dir,
+            Ok(Entry::File(_)) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "parent is not a directory"));
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "parent does not exist"));
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok((dir, name.to_string()))
+    }
 }
 
 impl<'a> FileSystem for &'a Shared<VFat> {
-    type File = ::traits::Dummy;
-    type Dir = ::traits::Dummy;
-    type Entry = ::traits::Dummy;
+    type File = File;
+    type Dir = Dir;
+    type Entry = Entry;
 
     fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
-        unimplemented!("FileSystem::open()")
+        let path = path.as_ref();
+        if !path.is_absolute() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "path is not absolute"));
+        }
+
+        // The directories leading to the current entry, so that `..` can go
+        // back up even from the root directory, which has no `..` entry.
+        let mut entries = vec![Entry::Dir(Dir::root(self.clone()))];
+        for component in path.components() {
+            match component {
+                Component::ParentDir => {
+                    if entries.len() > 1 {
+                        entries.pop();
+                    }
+                }
+                Component::Normal(name) => {
+                    let entry = match entries.last() {
+                        Some(&Entry::Dir(ref dir)) => dir.find(name)?,
+                        _ => {
+                            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                      "path component is not a directory"));
+                        }
+                    };
+
+                    entries.push(entry);
+                }
+                _ => continue,
+            }
+        }
+
+        Ok(entries.pop().expect("the root directory is never popped"))
     }
 
-    fn create_file<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::File> {
-        unimplemented!("read only file system")
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let (dir, name) = VFat::parent_of(self, path.as_ref())?;
+        let mut vfat = self.borrow_mut();
+        let file = dir.create_file(&mut vfat, &name)?;
+        vfat.sync()?;
+        Ok(file)
     }
 
-    fn create_dir<P>(self, _path: P, _parents: bool) -> io::Result<Self::Dir>
+    fn create_dir<P>(self, path: P, parents: bool) -> io::Result<Self::Dir>
         where P: AsRef<Path>
     {
-        unimplemented!("read only file system")
+        let path = path.as_ref();
+        if parents {
+            let mut ancestor = PathBuf::from("/");
+            let parent = path.parent().unwrap_or(path);
+            for component in parent.components() {
+                ancestor.push(component.as_os_str());
+                match self.open(&ancestor) {
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                        self.create_dir(&ancestor, false)?;
+                    }
+                    _ => continue,
+                }
+            }
+        }
+
+        let (dir, name) = VFat::parent_of(self, path)?;
+        let mut vfat = self.borrow_mut();
+        let dir = dir.create_dir(&mut vfat, &name)?;
+        vfat.sync()?;
+        Ok(dir)
     }
 
-    fn rename<P, Q>(self, _from: P, _to: Q) -> io::Result<()>
+    fn rename<P, Q>(self, from: P, to: Q) -> io::Result<()>
         where P: AsRef<Path>, Q: AsRef<Path>
     {
-        unimplemented!("read only file system")
+        let (from, to) = (from.as_ref(), to.as_ref());
+        if !from.is_absolute() || !to.is_absolute() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "path is not absolute"));
+        }
+
+        let entry = self.open(from)?;
+        let location = match entry {
+            Entry::File(ref file) => Some(file.location()),
+            Entry::Dir(ref dir) => dir.location(),
+        };
+
+        let location = location.ok_or(
+            io::Error::new(io::ErrorKind::InvalidInput, "can't rename the root directory"))?;
+        if entry.is_dir() && to.starts_with(from) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "can't move a directory into itself"));
+        }
+
+        let (dir, name) = VFat::parent_of(self, to)?;
+        let mut vfat = self.borrow_mut();
+        dir.move_entry(&mut vfat, location, &name)?;
+        vfat.sync()
     }
 
-    fn remove<P: AsRef<Path>>(self, _path: P, _children: bool) -> io::Result<()> {
-        unimplemented!("read only file system")
+    fn remove<P: AsRef<Path>>(self, path: P, children: bool) -> io::Result<()> {
+        let path = path.as_ref();
+        let entry = self.open(path)?;
+        if entry.is_dir() && !children {
+            let dir = entry.as_dir().expect("entry is a directory");
+            if dir.entries()?.any(|entry| entry.name() != "." && entry.name() != "..") {
+                return Err(io::Error::new(io::ErrorKind::Other, "directory is not empty"));
+            }
+        }
+
+        let mut vfat = self.borrow_mut();
+        match entry {
+            Entry::File(file) => file.remove(&mut vfat),
+            Entry::Dir(dir) => dir.remove(&mut vfat),
+        }
     }
 }
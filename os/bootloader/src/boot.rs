@@ -0,0 +1,367 @@
+//! The bootloader proper: receives a binary over the UART, reporting status
+//! on the status LED, and jumps to it. If nothing is sent for a while after
+//! startup, it loads `/kernel.img` from the SD card instead when built with
+//! the `sd` feature, or failing that jumps to the binary a previous boot left
+//! in memory. Only built for the Raspberry Pi.
+
+use std::fmt::Write;
+use std::io;
+#[cfg(feature = "sd")]
+use std::io::Read;
+
+#[cfg(feature = "sd")]
+use fat32::traits::{BlockDevice, File, FileSystem};
+#[cfg(feature = "sd")]
+use fat32::vfat::VFat;
+use xmodem::{Xmodem, Progress};
+use xmodem::{baud, trailer};
+use pi::{pm, timer};
+#[cfg(feature = "sd")]
+use pi::emmc::{self, Emmc};
+use pi::uart::MiniUart;
+use pi::led::Led;
+#[cfg(feature = "sd")]
+use heap::Heap;
+use status::{Status, ErrorCode, STATUS_LED_PIN, JUMP_DELAY_MS, transfer_level};
+use layout::{self, Region};
+
+/// How long a freshly loaded kernel has to start up and stop the watchdog
+/// before the board is reset back into the bootloader, in milliseconds.
+const KERNEL_WATCHDOG_MS: u32 = 15_000;
+
+/// How long a read from the UART waits for data before timing out, in
+/// milliseconds.
+const READ_TIMEOUT_MS: u64 = 750;
+
+/// How often the status LED is advanced while a read waits for data, in
+/// microseconds.
+const LED_TICK_US: u64 = 10_000;
+
+/// How long after starting up the bootloader waits for a transfer before it
+/// loads a binary from the SD card or jumps to the one already in memory, in
+/// microseconds.
+const FALLBACK_TIMEOUT_US: u64 = 10_000_000;
+
+/// The binary loaded from the first FAT partition of the SD card when nothing
+/// is sent.
+#[cfg(feature = "sd")]
+const SD_BINARY_PATH: &str = "/kernel.img";
+
+extern "C" {
+    /// The first byte of the bootloader image, from `layout.ld`.
+    static _start: u8;
+    /// The end of the bootloader image, including its BSS, from `layout.ld`.
+    static _end: u8;
+}
+
+/// The heap, which the FAT32 file system allocates from.
+#[cfg(feature = "sd")]
+#[global_allocator]
+static HEAP: Heap = Heap::uninitialized();
+
+/// The status LED and the status it is showing. The bootloader runs on one
+/// core with interrupts disabled, so only `kmain` and the progress callback it
+/// hands to `Xmodem` ever touch this, and never at the same time.
+static mut INDICATOR: Option<(Led, Status)> = None;
+
+/// Branches to the address `addr` unconditionally.
+fn jump_to(addr: *mut u8) -> ! {
+    unsafe {
+        asm!("br $0" : : "r"(addr as usize));
+        loop { asm!("nop" :::: "volatile")  }
+    }
+}
+
+/// Halts forever after writing `msg` to `uart`.
+fn halt(uart: &mut MiniUart, msg: &str) -> ! {
+    let _ = writeln!(uart, "bootloader: {}", msg);
+    loop { unsafe { asm!("wfe" :::: "volatile") } }
+}
+
+/// Returns the region occupied by the running bootloader's image. When the
+/// bootloader has relocated itself (see `init.S`), this is the relocated copy.
+fn bootloader_region() -> Region {
+    unsafe { Region::new(&_start as *const u8 as usize, &_end as *const u8 as usize) }
+}
+
+/// Switches `uart` to `baud` once everything already written to it has been
+/// sent, then gives the host `baud::SETTLE_MS` to switch too.
+fn switch_baud(uart: &mut MiniUart, baud: u32) -> io::Result<()> {
+    let divisor = baud::divisor(baud)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unsupported baud rate"))?;
+
+    uart.wait_for_idle();
+    uart.set_baud_divisor(divisor);
+    timer::spin_sleep_ms(baud::SETTLE_MS);
+    Ok(())
+}
+
+/// The SD card as the FAT32 file system reads it.
+#[cfg(feature = "sd")]
+struct Sd(Emmc);
+
+#[cfg(feature = "sd")]
+impl BlockDevice for Sd {
+    fn sector_size(&self) -> u64 {
+        emmc::BlockDevice::sector_size(&self.0)
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        emmc::BlockDevice::read_sector(&mut self.0, n, buf)
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "reading the SD card failed"))
+    }
+
+    fn write_sector(&mut self, _n: u64, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "the SD card is read only"))
+    }
+}
+
+/// Reads `SD_BINARY_PATH` from the first FAT partition of the SD card into
+/// `load` and returns its size. If reading fails partway, the first word of
+/// `load` is cleared so that the partial binary isn't taken for one a
+/// previous boot left.
+#[cfg(feature = "sd")]
+fn load_from_sd(load: Region) -> io::Result<usize> {
+    let emmc = Emmc::new().map_err(|_| io::Error::new(io::ErrorKind::NotFound, "no SD card"))?;
+    let vfat = VFat::from(Sd(emmc))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "no FAT file system"))?;
+    let mut file = (&vfat).open_file(SD_BINARY_PATH)?;
+    let size = file.size() as usize;
+    if size > layout::sd_capacity(load) {
+        return Err(io::Error::new(io::ErrorKind::WriteZero, "binary is too large"));
+    }
+
+    let storage = unsafe { ::std::slice::from_raw_parts_mut(load.start as *mut u8, size) };
+    if let Err(e) = file.read_exact(storage) {
+        unsafe { ::std::ptr::write_volatile(load.start as *mut u32, 0) };
+        return Err(e);
+    }
+
+    Ok(size)
+}
+
+/// Loads `SD_BINARY_PATH` from the SD card into `load`, reporting the result
+/// on `uart`, and returns whether it was loaded. The heap is set up first.
+#[cfg(feature = "sd")]
+fn try_sd(uart: &mut MiniUart, load: Region) -> bool {
+    HEAP.initialize(layout::heap_region(bootloader_region()));
+    match load_from_sd(load) {
+        Ok(size) => {
+            let _ = writeln!(uart, "bootloader: nothing sent; loaded {} ({} bytes) from the SD \
+                                    card", SD_BINARY_PATH, size);
+            true
+        }
+        Err(e) => {
+            let _ = writeln!(uart, "bootloader: can't load {} from the SD card: {}",
+                             SD_BINARY_PATH, e);
+            false
+        }
+    }
+}
+
+/// Without the `sd` feature, there is no SD card to load from.
+#[cfg(not(feature = "sd"))]
+fn try_sd(_uart: &mut MiniUart, _load: Region) -> bool {
+    false
+}
+
+/// The UART as transfers see it: reads wait for data like `MiniUart`'s do,
+/// but advance the status LED every `LED_TICK_US` while they wait, so that its
+/// pattern keeps playing while the line is idle.
+struct Blinking<'a> {
+    uart: &'a mut MiniUart,
+}
+
+impl<'a> io::Read for Blinking<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let deadline = timer::current_time_us() + READ_TIMEOUT_MS * 1000;
+        let mut next_tick = 0;
+        while !self.uart.has_byte() {
+            let now = timer::current_time_us();
+            if now >= deadline {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "Timeout waiting for data"));
+            } else if now >= next_tick {
+                tick(now);
+                next_tick = now + LED_TICK_US;
+            }
+        }
+
+        io::Read::read(self.uart, buf)
+    }
+}
+
+impl<'a> io::Write for Blinking<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::Write::write(self.uart, buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::Write::flush(self.uart)
+    }
+}
+
+/// Advances the status LED's pattern, if it is playing one, to `now` in
+/// microseconds.
+fn tick(now: u64) {
+    if let Some(&mut (ref mut led, _)) = unsafe { INDICATOR.as_mut() } {
+        led.tick(now);
+    }
+}
+
+/// Shows `status` on the status LED, then advances its pattern to the current
+/// time. Switching to the status already shown doesn't restart its pattern.
+fn show(status: Status) {
+    if let Some(&mut (ref mut led, ref mut current)) = unsafe { INDICATOR.as_mut() } {
+        *current = status;
+        if let Some(steps) = status.pattern() {
+            led.pattern(steps);
+        }
+    }
+
+    tick(timer::current_time_us());
+}
+
+/// Returns the status currently shown on the status LED.
+fn shown() -> Status {
+    unsafe { INDICATOR.as_ref().map_or(Status::Waiting, |&(_, status)| status) }
+}
+
+/// Shows `status` for one full repetition of its pattern.
+fn show_once(status: Status) {
+    let period: u64 = status.pattern().map_or(0, |steps| steps.iter().map(|s| s.1).sum());
+    let end = timer::current_time_us() + period;
+    while timer::current_time_us() < end {
+        show(status);
+    }
+}
+
+/// Progress callback for transfers: advances the status LED, toggling it every
+/// `TOGGLE_PACKETS` packets while packets are arriving.
+fn on_progress(progress: Progress) {
+    show(Status::from_progress(progress));
+    if let Progress::Packet { number, .. } = progress {
+        if let Some(&mut (ref mut led, _)) = unsafe { INDICATOR.as_mut() } {
+            if transfer_level(number) { led.on() } else { led.off() }
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn kmain() {
+    {
+        unsafe { INDICATOR = Led::new(STATUS_LED_PIN).ok().map(|led| (led, Status::Waiting)); }
+        show(Status::Waiting);
+
+        let mut uart = MiniUart::new();
+        uart.set_read_timeout(READ_TIMEOUT_MS as u32);
+
+        let load = match layout::load_region(bootloader_region()) {
+            Some(load) => load,
+            None => halt(&mut uart, "no room below the bootloader to load a binary"),
+        };
+
+        // If nothing is ever sent, the binary is loaded from the SD card. If
+        // there isn't one, the binary a previous boot loaded may still be in
+        // memory, since a reset doesn't clear it.
+        let fallback_at = timer::current_time_us() + FALLBACK_TIMEOUT_US;
+        let mut heard = false;
+        let mut tried_sd = false;
+        loop {
+            // Each attempt starts at the default rate, listening for a
+            // request to switch before the transfer.
+            let switched = baud::answer(&mut uart, switch_baud).unwrap_or(None);
+
+            // Every attempt writes from the start of the region: a failed
+            // transfer may have advanced a previous attempt's slice. Writes
+            // past the end fail, which cancels the transfer.
+            let storage = unsafe {
+                ::std::slice::from_raw_parts_mut(load.start as *mut u8, layout::capacity(load))
+            };
+
+            // Ask for CRC-16, so that ttywrite can send 1K packets.
+            let result = {
+                let blinking = Blinking { uart: &mut uart };
+                let mut receiver = Xmodem::new_with_progress(blinking, on_progress);
+                receiver.set_crc(true);
+                let result = receiver.receive_all(storage);
+                let summary = receiver.summary();
+                heard |= summary.packets > 0 || summary.naks > 0;
+                result
+            };
+
+            match result {
+                // Receive failed, retry. The partially written image is never
+                // jumped to.
+                Err(e) => {
+                    if switched.is_some() {
+                        let _ = switch_baud(&mut uart, baud::DEFAULT_BAUD);
+                    }
+
+                    if !heard && timer::current_time_us() >= fallback_at {
+                        // The card is only tried once: it doesn't change
+                        // while the bootloader waits.
+                        if !tried_sd {
+                            tried_sd = true;
+                            if try_sd(&mut uart, load) {
+                                show(Status::Jumping);
+                                timer::spin_sleep_ms(JUMP_DELAY_MS);
+                                break;
+                            }
+                        }
+
+                        let first_word = unsafe {
+                            ::std::ptr::read_volatile(load.start as *const u32)
+                        };
+
+                        if layout::holds_binary(first_word) {
+                            let _ = writeln!(uart, "bootloader: nothing sent; jumping to old binary");
+                            show(Status::Jumping);
+                            timer::spin_sleep_ms(JUMP_DELAY_MS);
+                            break;
+                        }
+                    }
+
+                    match shown().after_error(e.kind()) {
+                        Status::Waiting => show(Status::Waiting),
+                        error => {
+                            show_once(error);
+                            show(Status::Waiting);
+                        }
+                    }
+                }
+                // Check the image against its trailer. Only a verified image
+                // is jumped to; the sender is told either way.
+                Ok(received) => {
+                    let image = unsafe {
+                        ::std::slice::from_raw_parts(load.start as *const u8, received)
+                    };
+
+                    let verified = trailer::verify(image);
+                    let _ = trailer::write_verdict(&mut uart, verified.is_ok());
+                    if switched.is_some() {
+                        let _ = switch_baud(&mut uart, baud::DEFAULT_BAUD);
+                    }
+
+                    if let Err(error) = verified {
+                        let _ = writeln!(uart, "bootloader: rejected image: {}", error);
+                        show_once(Status::Error(ErrorCode::Integrity));
+                        show(Status::Waiting);
+                        continue;
+                    }
+
+                    // Break out of the retry loop and load the binary.
+                    show(Status::Jumping);
+                    timer::spin_sleep_ms(JUMP_DELAY_MS);
+                    break;
+                },
+            }
+        }
+    }
+
+    // Binary is loaded. Hand the peripherals over as the firmware left them,
+    // then jump to the start. The watchdog stays armed so that a kernel that
+    // hangs before taking it over is reset back into the bootloader.
+    ::pi::quiesce();
+    pm::Watchdog::new().start(KERNEL_WATCHDOG_MS);
+    jump_to(layout::BINARY_START_ADDR as *mut u8);
+}
@@ -0,0 +1,55 @@
+//! The bootloader's heap. Only loading a binary from the SD card allocates,
+//! for the FAT32 file system, and the bootloader jumps away right after, so
+//! a bump allocator that never reuses freed memory is enough. Only built for
+//! the Raspberry Pi.
+
+use std::heap::{Alloc, AllocErr, Layout};
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use layout::Region;
+
+/// A bump allocator over the region given to `initialize()`. Until then,
+/// every allocation fails.
+pub struct Heap {
+    current: AtomicUsize,
+    end: AtomicUsize,
+}
+
+impl Heap {
+    /// Returns a heap with no memory to hand out.
+    pub const fn uninitialized() -> Heap {
+        Heap { current: ATOMIC_USIZE_INIT, end: ATOMIC_USIZE_INIT }
+    }
+
+    /// Hands out the memory in `region` from now on.
+    pub fn initialize(&self, region: Region) {
+        self.current.store(region.start, Ordering::Relaxed);
+        self.end.store(region.end, Ordering::Relaxed);
+    }
+}
+
+// The bootloader runs on one core with interrupts disabled, so allocations
+// never race.
+unsafe impl<'a> Alloc for &'a Heap {
+    unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let current = self.current.load(Ordering::Relaxed);
+        let region = current.checked_add(layout.align() - 1)
+            .map(|start| start & !(layout.align() - 1))
+            .and_then(|start| start.checked_add(layout.size()).map(|end| (start, end)));
+
+        match region {
+            Some((start, end)) if end <= self.end.load(Ordering::Relaxed) => {
+                self.current.store(end, Ordering::Relaxed);
+                Ok(start as *mut u8)
+            }
+            _ => Err(AllocErr::Exhausted { request: layout }),
+        }
+    }
+
+    /// Freed memory is never reused.
+    unsafe fn dealloc(&mut self, _ptr: *mut u8, _layout: Layout) {  }
+
+    fn oom(&mut self, err: AllocErr) -> ! {
+        panic!("out of memory: {:?}", err);
+    }
+}
@@ -0,0 +1,92 @@
+/// Start address of the binary to load.
+pub const BINARY_START_ADDR: usize = 0x80000;
+
+/// Space reserved for the bootloader's stack, which grows down from the
+/// bootloader's start address.
+pub const STACK_SIZE: usize = 0x10000;
+
+/// The size of the bootloader's heap, which directly follows its image. The
+/// heap is only used to load a binary from the SD card.
+pub const HEAP_SIZE: usize = 0x200_0000;
+
+/// The size of an XMODEM packet. Received images are padded to a multiple of
+/// this size.
+pub const PACKET_SIZE: usize = 128;
+
+/// A half-open range of addresses, `[start, end)`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Region {
+    /// Returns the region `[start, end)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `end < start`.
+    pub fn new(start: usize, end: usize) -> Region {
+        assert!(start <= end, "region ends before it starts");
+        Region { start, end }
+    }
+
+    /// Returns the number of bytes in the region.
+    pub fn size(&self) -> usize {
+        self.end - self.start
+    }
+
+    /// Returns `true` if `self` and `other` share at least one address. Empty
+    /// regions overlap nothing.
+    pub fn overlaps(&self, other: Region) -> bool {
+        self.size() != 0 && other.size() != 0
+            && self.start < other.end && other.start < self.end
+    }
+
+    /// Returns `true` if every address in `other` is also in `self`.
+    pub fn contains(&self, other: Region) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+}
+
+/// Returns the region a loaded binary may occupy when the bootloader's image
+/// occupies `bootloader`: from `BINARY_START_ADDR` up to the bottom of the
+/// bootloader's stack. Returns `None` if there is no room at all.
+pub fn load_region(bootloader: Region) -> Option<Region> {
+    let stack_bottom = bootloader.start.checked_sub(STACK_SIZE)?;
+    if stack_bottom <= BINARY_START_ADDR {
+        return None;
+    }
+
+    Some(Region::new(BINARY_START_ADDR, stack_bottom))
+}
+
+/// Returns the region the heap occupies when the bootloader's image occupies
+/// `bootloader`: the `HEAP_SIZE` bytes from the end of the image, aligned to
+/// 16 bytes.
+pub fn heap_region(bootloader: Region) -> Region {
+    let start = (bootloader.end + 15) & !15;
+    Region::new(start, start + HEAP_SIZE)
+}
+
+/// Returns the size of the largest binary that can be loaded from the SD card
+/// into `load`. Reading a file allocates about as much again for the file
+/// system's caches, and the heap never reuses memory, so only half of it is
+/// counted on.
+pub fn sd_capacity(load: Region) -> usize {
+    load.size().min(HEAP_SIZE / 2)
+}
+
+/// Returns the number of bytes a transfer into `load` may write: the size of
+/// `load` rounded down to a whole number of packets.
+pub fn capacity(load: Region) -> usize {
+    load.size() - load.size() % PACKET_SIZE
+}
+
+/// Returns `true` if `first_word`, the first word of the load region, could
+/// be the first instruction of a binary left there earlier. Memory nothing was
+/// loaded into reads as all zeroes, which is also a permanently undefined
+/// instruction, or as all ones.
+pub fn holds_binary(first_word: u32) -> bool {
+    first_word != 0 && first_word != !0
+}
@@ -0,0 +1,126 @@
+use std::io;
+
+use pi::led::Step;
+use xmodem::Progress;
+
+/// The GPIO pin driving the bootloader's status LED.
+pub const STATUS_LED_PIN: u8 = 16;
+
+/// The number of packets received between toggles of the status LED during a
+/// transfer. Divides 256 so the blink stays even as packet numbers wrap.
+pub const TOGGLE_PACKETS: u8 = 8;
+
+/// How long the status LED is held on before jumping to the loaded binary, in
+/// milliseconds.
+pub const JUMP_DELAY_MS: u64 = 2000;
+
+/// Slow blink: waiting for a sender.
+const WAITING: [Step; 2] = [(true, 500_000), (false, 500_000)];
+
+/// Solid on: about to jump to the loaded binary.
+const SOLID: [Step; 1] = [(true, JUMP_DELAY_MS * 1000)];
+
+/// Two flashes: too many checksum failures.
+const ERROR_CHECKSUM: [Step; 4] = [
+    (true, 200_000), (false, 300_000),
+    (true, 200_000), (false, 1_500_000),
+];
+
+/// Three flashes: the image doesn't fit in the load region.
+const ERROR_TOO_LARGE: [Step; 6] = [
+    (true, 200_000), (false, 300_000),
+    (true, 200_000), (false, 300_000),
+    (true, 200_000), (false, 1_500_000),
+];
+
+/// Four flashes: the sender stopped mid-transfer.
+const ERROR_TIMEOUT: [Step; 8] = [
+    (true, 200_000), (false, 300_000),
+    (true, 200_000), (false, 300_000),
+    (true, 200_000), (false, 300_000),
+    (true, 200_000), (false, 1_500_000),
+];
+
+/// Five flashes: the image didn't match its trailer.
+const ERROR_INTEGRITY: [Step; 10] = [
+    (true, 200_000), (false, 300_000),
+    (true, 200_000), (false, 300_000),
+    (true, 200_000), (false, 300_000),
+    (true, 200_000), (false, 300_000),
+    (true, 200_000), (false, 1_500_000),
+];
+
+/// A failed transfer, blinked on the status LED as a group of `code as u8`
+/// flashes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ErrorCode {
+    /// A packet failed its checksum `xmodem::MAX_RETRIES` times in a row.
+    Checksum = 2,
+    /// The image is larger than the load region.
+    TooLarge = 3,
+    /// The sender stopped sending partway through a transfer.
+    Timeout = 4,
+    /// The received image is missing its trailer or doesn't match it.
+    Integrity = 5,
+}
+
+/// What the bootloader is doing, as shown on the status LED.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// Waiting for a sender to start a transfer.
+    Waiting,
+    /// Receiving packets.
+    Transferring,
+    /// The image was received; about to jump to it.
+    Jumping,
+    /// The last transfer failed.
+    Error(ErrorCode),
+}
+
+impl Status {
+    /// Returns the status to show after the XMODEM progress event `progress`.
+    pub fn from_progress(progress: Progress) -> Status {
+        match progress {
+            Progress::Waiting | Progress::Started => Status::Waiting,
+            Progress::Packet { .. } | Progress::Finished(_) => Status::Transferring,
+        }
+    }
+
+    /// Returns the status to show after a transfer fails with an error of
+    /// kind `kind` while `self` was being shown.
+    ///
+    /// Timing out before any packet arrives is the normal way to wait for a
+    /// sender and is not an error. Failures that don't have an error code
+    /// restart the wait.
+    pub fn after_error(self, kind: io::ErrorKind) -> Status {
+        match kind {
+            io::ErrorKind::TimedOut if self == Status::Transferring => {
+                Status::Error(ErrorCode::Timeout)
+            }
+            io::ErrorKind::BrokenPipe => Status::Error(ErrorCode::Checksum),
+            io::ErrorKind::WriteZero => Status::Error(ErrorCode::TooLarge),
+            _ => Status::Waiting,
+        }
+    }
+
+    /// Returns the blink pattern for this status, or `None` while
+    /// transferring, when the LED is toggled by packet instead of by time.
+    /// See `transfer_level()`.
+    pub fn pattern(&self) -> Option<&'static [Step]> {
+        match *self {
+            Status::Waiting => Some(&WAITING),
+            Status::Transferring => None,
+            Status::Jumping => Some(&SOLID),
+            Status::Error(ErrorCode::Checksum) => Some(&ERROR_CHECKSUM),
+            Status::Error(ErrorCode::TooLarge) => Some(&ERROR_TOO_LARGE),
+            Status::Error(ErrorCode::Timeout) => Some(&ERROR_TIMEOUT),
+            Status::Error(ErrorCode::Integrity) => Some(&ERROR_INTEGRITY),
+        }
+    }
+}
+
+/// Returns the level of the status LED after receiving packet number
+/// `packet`: the level flips every `TOGGLE_PACKETS` packets.
+pub fn transfer_level(packet: u8) -> bool {
+    (packet / TOGGLE_PACKETS).is_multiple_of(2)
+}
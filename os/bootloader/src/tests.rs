@@ -0,0 +1,174 @@
+use std::io;
+
+use xmodem::{Progress, Summary};
+use status::{Status, ErrorCode, transfer_level, TOGGLE_PACKETS};
+use layout::*;
+
+#[test]
+fn progress_maps_to_status() {
+    assert_eq!(Status::from_progress(Progress::Waiting), Status::Waiting);
+    assert_eq!(Status::from_progress(Progress::Started), Status::Waiting);
+    let packet = |number| Progress::Packet { number, bytes: 128, retries: 0 };
+    assert_eq!(Status::from_progress(packet(1)), Status::Transferring);
+    assert_eq!(Status::from_progress(packet(0)), Status::Transferring);
+    assert_eq!(Status::from_progress(Progress::Finished(Summary::default())), Status::Transferring);
+}
+
+#[test]
+fn timeout_is_only_an_error_mid_transfer() {
+    let kind = io::ErrorKind::TimedOut;
+    assert_eq!(Status::Waiting.after_error(kind), Status::Waiting);
+    assert_eq!(Status::Transferring.after_error(kind), Status::Error(ErrorCode::Timeout));
+}
+
+#[test]
+fn errors_map_to_codes() {
+    for &status in &[Status::Waiting, Status::Transferring] {
+        assert_eq!(status.after_error(io::ErrorKind::BrokenPipe),
+                   Status::Error(ErrorCode::Checksum));
+        assert_eq!(status.after_error(io::ErrorKind::WriteZero),
+                   Status::Error(ErrorCode::TooLarge));
+        assert_eq!(status.after_error(io::ErrorKind::ConnectionAborted), Status::Waiting);
+        assert_eq!(status.after_error(io::ErrorKind::InvalidData), Status::Waiting);
+    }
+}
+
+#[test]
+fn error_patterns_flash_their_code() {
+    let codes = [ErrorCode::Checksum, ErrorCode::TooLarge, ErrorCode::Timeout,
+                 ErrorCode::Integrity];
+    for &code in &codes {
+        let steps = Status::Error(code).pattern().expect("errors have a pattern");
+        let flashes = steps.iter().filter(|&&(on, _)| on).count();
+        assert_eq!(flashes, code as usize);
+
+        // Flashes are separated by short gaps and the group by a long one.
+        let gaps: Vec<u64> = steps.iter().filter(|&&(on, _)| !on).map(|s| s.1).collect();
+        let (last, rest) = gaps.split_last().expect("pattern has gaps");
+        assert!(rest.iter().all(|gap| gap * 2 < *last));
+    }
+}
+
+#[test]
+fn status_patterns() {
+    assert!(Status::Transferring.pattern().is_none());
+
+    let waiting = Status::Waiting.pattern().expect("waiting blinks");
+    assert_eq!(waiting.len(), 2);
+    assert!(waiting[0].0 && !waiting[1].0);
+
+    let jumping = Status::Jumping.pattern().expect("jumping is solid");
+    assert!(jumping.iter().all(|&(on, _)| on));
+}
+
+#[test]
+fn transfer_toggles_every_n_packets() {
+    let mut toggles = 0;
+    let mut level = transfer_level(0);
+    for packet in (1..256).map(|n: usize| n as u8) {
+        if transfer_level(packet) != level {
+            assert_eq!(packet % TOGGLE_PACKETS, 0);
+            level = !level;
+            toggles += 1;
+        }
+    }
+
+    assert_eq!(toggles, 256 / TOGGLE_PACKETS as usize - 1);
+    assert_ne!(transfer_level(255), transfer_level(0), "blink stays even across wraparound");
+}
+
+#[test]
+fn region_overlap() {
+    let a = Region::new(0x1000, 0x2000);
+    assert!(a.overlaps(a));
+    assert!(a.overlaps(Region::new(0x1fff, 0x3000)));
+    assert!(a.overlaps(Region::new(0x0, 0x1001)));
+    assert!(a.overlaps(Region::new(0x1800, 0x1900)));
+    assert!(!a.overlaps(Region::new(0x2000, 0x3000)), "regions are half-open");
+    assert!(!a.overlaps(Region::new(0x0, 0x1000)), "regions are half-open");
+    assert!(!a.overlaps(Region::new(0x1800, 0x1800)), "empty regions overlap nothing");
+
+    assert!(a.contains(a));
+    assert!(a.contains(Region::new(0x1800, 0x1900)));
+    assert!(!a.contains(Region::new(0x1800, 0x2001)));
+    assert!(!a.contains(Region::new(0xfff, 0x1800)));
+}
+
+#[test]
+fn load_region_stops_below_the_stack() {
+    let bootloader = Region::new(0x4000000, 0x4010000);
+    let load = load_region(bootloader).expect("room to load");
+    assert_eq!(load, Region::new(BINARY_START_ADDR, 0x4000000 - STACK_SIZE));
+
+    let stack = Region::new(bootloader.start - STACK_SIZE, bootloader.start);
+    assert!(!load.overlaps(stack));
+    assert!(!load.overlaps(bootloader));
+    assert_eq!(load.end, stack.start, "no space is wasted");
+}
+
+#[test]
+fn heap_follows_the_bootloader() {
+    let bootloader = Region::new(0x4000000, 0x4010004);
+    let heap = heap_region(bootloader);
+    assert_eq!(heap, Region::new(0x4010010, 0x4010010 + HEAP_SIZE));
+    assert!(!heap.overlaps(bootloader));
+    assert!(!heap.overlaps(load_region(bootloader).expect("room to load")));
+}
+
+#[test]
+fn sd_images_are_limited_by_the_heap() {
+    let load = load_region(Region::new(0x4000000, 0x4010000)).expect("room to load");
+    assert_eq!(sd_capacity(load), HEAP_SIZE / 2);
+    assert_eq!(sd_capacity(Region::new(0x80000, 0x81000)), 0x1000);
+}
+
+#[test]
+fn load_region_grows_when_relocated() {
+    let low = load_region(Region::new(0x4000000, 0x4010000)).expect("room to load");
+    let high = load_region(Region::new(0x3b000000, 0x3b010000)).expect("room to load");
+    assert!(high.contains(low));
+    assert!(high.size() > low.size());
+}
+
+#[test]
+fn load_region_needs_room() {
+    assert!(load_region(Region::new(0, 0x100)).is_none());
+    assert!(load_region(Region::new(STACK_SIZE - 1, STACK_SIZE)).is_none());
+    assert!(load_region(Region::new(BINARY_START_ADDR + STACK_SIZE, 0x100000)).is_none());
+
+    let tight = load_region(Region::new(BINARY_START_ADDR + STACK_SIZE + 1, 0x100000));
+    assert_eq!(tight.map(|r| r.size()), Some(1));
+}
+
+#[test]
+fn capacity_rounds_down_to_packets() {
+    let load = Region::new(0x80000, 0x80000 + 3 * PACKET_SIZE + 100);
+    assert_eq!(capacity(load), 3 * PACKET_SIZE, "a partial last packet wouldn't fit");
+    assert_eq!(capacity(Region::new(0x80000, 0x80000 + 3 * PACKET_SIZE)), 3 * PACKET_SIZE);
+    assert_eq!(capacity(Region::new(0x80000, 0x80000 + PACKET_SIZE - 1)), 0);
+
+    for &bootloader in &[Region::new(0x4000000, 0x4010000), Region::new(0x3b000010, 0x3b010000)] {
+        let load = load_region(bootloader).expect("room to load");
+        let capacity = capacity(load);
+        assert_eq!(capacity % PACKET_SIZE, 0);
+        assert!(load.size() - capacity < PACKET_SIZE, "no whole packet is left out");
+        assert!(!Region::new(load.start, load.start + capacity).overlaps(bootloader));
+    }
+
+    let tight = load_region(Region::new(BINARY_START_ADDR + STACK_SIZE + 1, 0x100000));
+    assert_eq!(tight.map(capacity), Some(0));
+}
+
+#[test]
+#[should_panic]
+fn region_must_not_be_inverted() {
+    Region::new(0x2000, 0x1000);
+}
+
+#[test]
+fn fallback_needs_a_binary_in_memory() {
+    assert!(!holds_binary(0));
+    assert!(!holds_binary(!0));
+    // `mrs x0, mpidr_el1`, the first instruction of `init.S`.
+    assert!(holds_binary(0xD53800A0));
+}
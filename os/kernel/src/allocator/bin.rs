@@ -0,0 +1,119 @@
+//! A bin allocator: freed blocks are kept in bins by size class and handed
+//! out again by later allocations of the same class.
+//!
+//! The size classes are the powers of two from `MIN_BLOCK` up. A request is
+//! served from the bin of the smallest class that fits both its size and its
+//! alignment. Blocks are always aligned to their size, so any block in a bin
+//! satisfies any request that maps to it. When the bin is empty, a block is
+//! carved from the memory not yet handed out, and the gap left by aligning it
+//! is split into smaller free blocks rather than lost. Once that memory runs
+//! out, a free block of a larger class is split instead.
+
+use std::heap::{AllocErr, Layout};
+use std::mem;
+
+use allocator::linked_list::LinkedList;
+use allocator::util::{align_down, align_up, checked_align_up};
+
+/// The size of the smallest class: the free list's link.
+pub const MIN_BLOCK: usize = mem::size_of::<usize>();
+
+/// The number of size classes, from `MIN_BLOCK` up to `MIN_BLOCK << 31`.
+pub const NUM_BINS: usize = 32;
+
+/// Returns the size of the blocks in bin `bin`.
+pub fn block_size(bin: usize) -> usize {
+    MIN_BLOCK << bin
+}
+
+/// Returns the bin that serves `layout`, or `None` if it is larger than the
+/// largest class.
+pub fn bin_for(layout: &Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align()).max(MIN_BLOCK).checked_next_power_of_two()?;
+    let bin = (size.trailing_zeros() - MIN_BLOCK.trailing_zeros()) as usize;
+    if bin < NUM_BINS { Some(bin) } else { None }
+}
+
+/// A bin allocator over the memory from `start` to `end`.
+#[derive(Debug)]
+pub struct Allocator {
+    bins: [LinkedList; NUM_BINS],
+    /// The start of the memory not yet carved into blocks.
+    current: usize,
+    end: usize,
+}
+
+impl Allocator {
+    /// Returns an allocator handing out the memory from `start` to `end`.
+    pub fn new(start: usize, end: usize) -> Allocator {
+        let current = align_up(start.max(MIN_BLOCK), MIN_BLOCK);
+        let end = align_down(end, MIN_BLOCK);
+        Allocator { bins: [LinkedList::new(); NUM_BINS], current, end: end.max(current) }
+    }
+
+    /// Allocates memory for `layout`, aligned as it requires.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AllocErr::Exhausted` if there is no free block large enough.
+    pub fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let bin = match bin_for(&layout) {
+            Some(bin) => bin,
+            None => return Err(AllocErr::Exhausted { request: layout }),
+        };
+
+        let block = match self.bins[bin].pop() {
+            Some(block) => Some(block as usize),
+            None => self.carve(bin).or_else(|| self.split(bin)),
+        };
+
+        match block {
+            Some(block) => Ok(block as *mut u8),
+            None => Err(AllocErr::Exhausted { request: layout }),
+        }
+    }
+
+    /// Returns the block at `ptr`, allocated for `layout`, to its bin.
+    pub fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        if let Some(bin) = bin_for(&layout) {
+            unsafe { self.bins[bin].push(ptr as *mut usize) }
+        }
+    }
+
+    /// Carves a block for bin `bin` from the memory not yet handed out.
+    fn carve(&mut self, bin: usize) -> Option<usize> {
+        let size = block_size(bin);
+        let start = checked_align_up(self.current, size)?;
+        if start > self.end || self.end - start < size {
+            return None;
+        }
+
+        let gap = self.current;
+        self.free_range(gap, start);
+        self.current = start + size;
+        Some(start)
+    }
+
+    /// Splits the smallest free block of a class larger than bin `bin`'s,
+    /// returning its first `block_size(bin)` bytes and freeing the rest.
+    fn split(&mut self, bin: usize) -> Option<usize> {
+        let larger = (bin + 1..NUM_BINS).find(|&larger| !self.bins[larger].is_empty())?;
+        let block = self.bins[larger].pop()? as usize;
+        self.free_range(block + block_size(bin), block + block_size(larger));
+        Some(block)
+    }
+
+    /// Frees the memory from `start` to `end`, both multiples of `MIN_BLOCK`,
+    /// as the fewest blocks aligned to their size that cover it.
+    fn free_range(&mut self, mut start: usize, end: usize) {
+        while start < end {
+            let mut bin = NUM_BINS - 1;
+            while start % block_size(bin) != 0 || block_size(bin) > end - start {
+                bin -= 1;
+            }
+
+            unsafe { self.bins[bin].push(start as *mut usize) }
+            start += block_size(bin);
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! A bump allocator: allocations are carved off the front of the free memory
+//! in order and never reused.
+
+use std::heap::{AllocErr, Layout};
+
+use allocator::util::checked_align_up;
+
+/// A bump allocator over the memory from `start` to `end`.
+#[derive(Debug)]
+pub struct Allocator {
+    current: usize,
+    end: usize,
+}
+
+impl Allocator {
+    /// Returns an allocator handing out the memory from `start` to `end`.
+    pub fn new(start: usize, end: usize) -> Allocator {
+        Allocator { current: start, end }
+    }
+
+    /// Allocates memory for `layout`, aligned as it requires.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AllocErr::Exhausted` if there isn't enough memory left.
+    pub fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let region = checked_align_up(self.current, layout.align())
+            .and_then(|start| start.checked_add(layout.size()).map(|end| (start, end)));
+
+        match region {
+            Some((start, end)) if end <= self.end => {
+                self.current = end;
+                Ok(start as *mut u8)
+            }
+            _ => Err(AllocErr::Exhausted { request: layout }),
+        }
+    }
+
+    /// Deallocates the memory at `ptr`. A bump allocator can't reuse memory,
+    /// so it is leaked.
+    pub fn dealloc(&mut self, _ptr: *mut u8, _layout: Layout) {  }
+}
@@ -0,0 +1,70 @@
+//! Canaries around heap allocations, to catch writes past either end of one,
+//! and poisoning of freed memory, to make writes through dangling pointers
+//! and reads of freed data stand out.
+//!
+//! With the `heap_canaries` feature, each allocation is placed in a larger
+//! block between two runs of `CANARY` bytes. They are checked when the
+//! allocation is freed, and the whole block is filled with `POISON`.
+
+use std::heap::Layout;
+use std::{ptr, slice};
+
+/// The byte canaries are made of.
+pub const CANARY: u8 = 0xCA;
+
+/// The byte freed memory is filled with.
+pub const POISON: u8 = 0xDF;
+
+/// The number of canary bytes on either side of an allocation, at least.
+pub const CANARY_SIZE: usize = 16;
+
+/// Returns the layout of the block holding an allocation for `layout` between
+/// its canaries, and the offset of the allocation in the block. Returns `None`
+/// if the block would be too large.
+pub fn padded(layout: &Layout) -> Option<(Layout, usize)> {
+    // The allocation stays aligned since the offset is a multiple of its
+    // alignment.
+    let offset = layout.align().max(CANARY_SIZE);
+    let size = offset.checked_add(layout.size())?.checked_add(CANARY_SIZE)?;
+    Some((unsafe { Layout::from_size_align_unchecked(size, layout.align()) }, offset))
+}
+
+/// Writes the canaries around the `size` byte allocation `offset` bytes into
+/// `block`, and returns the allocation's address.
+///
+/// # Safety
+///
+/// `block` must be a block laid out by `padded()` for the allocation.
+pub unsafe fn guard(block: *mut u8, offset: usize, size: usize) -> *mut u8 {
+    ptr::write_bytes(block, CANARY, offset);
+    ptr::write_bytes(block.offset((offset + size) as isize), CANARY, CANARY_SIZE);
+    block.offset(offset as isize)
+}
+
+/// Checks the canaries `guard()` wrote around the `size` byte allocation
+/// `offset` bytes into `block`.
+///
+/// # Errors
+///
+/// Returns the address of the first canary byte that was overwritten.
+///
+/// # Safety
+///
+/// `block` must be a block laid out by `padded()` for the allocation.
+pub unsafe fn check(block: *const u8, offset: usize, size: usize) -> Result<(), usize> {
+    let before = slice::from_raw_parts(block, offset);
+    let after = slice::from_raw_parts(block.offset((offset + size) as isize), CANARY_SIZE);
+    match before.iter().chain(after.iter()).find(|&&byte| byte != CANARY) {
+        Some(byte) => Err(byte as *const u8 as usize),
+        None => Ok(()),
+    }
+}
+
+/// Fills the `len` bytes at `block` with `POISON`.
+///
+/// # Safety
+///
+/// The bytes must be writable and unused.
+pub unsafe fn poison(block: *mut u8, len: usize) {
+    ptr::write_bytes(block, POISON, len);
+}
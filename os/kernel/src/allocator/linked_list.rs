@@ -0,0 +1,47 @@
+//! An intrusive singly linked list of free memory blocks. Each block holds
+//! the address of the next one in its first word, so the list needs no memory
+//! of its own.
+
+use std::ptr;
+
+/// A list of free blocks, each at least a `usize` in size and aligned to one.
+#[derive(Debug, Copy, Clone)]
+pub struct LinkedList {
+    head: *mut usize,
+}
+
+unsafe impl Send for LinkedList {  }
+
+impl LinkedList {
+    /// Returns an empty list.
+    pub const fn new() -> LinkedList {
+        LinkedList { head: ptr::null_mut() }
+    }
+
+    /// Returns `true` if the list has no blocks.
+    pub fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    /// Pushes the block at `item` onto the front of the list.
+    ///
+    /// # Safety
+    ///
+    /// `item` must point to an unused block of at least a `usize` that is
+    /// aligned to one, and stays unused for as long as it is in the list.
+    pub unsafe fn push(&mut self, item: *mut usize) {
+        *item = self.head as usize;
+        self.head = item;
+    }
+
+    /// Removes and returns the block at the front of the list.
+    pub fn pop(&mut self) -> Option<*mut usize> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let item = self.head;
+        self.head = unsafe { *item as *mut usize };
+        Some(item)
+    }
+}
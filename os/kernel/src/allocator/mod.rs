@@ -0,0 +1,252 @@
+//! The kernel's heap, which backs `Box`, `Vec`, `String` and the rest of the
+//! `alloc` collections.
+//!
+//! The heap is the first `HEAP_SIZE` bytes of the memory the firmware's ATAGs
+//! describe past the end of the kernel binary and the hotload staging area.
+//! The memory past it is left to the frame allocator in `frames`. `ALLOCATOR` in `kmain` is the
+//! global allocator; it must be initialized with `initialize()` before the
+//! first allocation. With the `heap_canaries` feature, overruns of heap
+//! allocations are caught when they are freed; see `canary`.
+
+pub mod util;
+pub mod bump;
+pub mod bin;
+pub mod canary;
+mod linked_list;
+
+// The bin allocator reuses freed memory. The bump allocator, which never does,
+// can be selected with the `bump_allocator` feature when chasing a
+// use-after-free.
+#[cfg(not(feature = "bump_allocator"))]
+use self::bin as imp;
+#[cfg(feature = "bump_allocator")]
+use self::bump as imp;
+
+use std::fmt;
+use std::heap::{Alloc, AllocErr, Layout};
+
+use pi::atags::Atags;
+
+use hotload;
+use mutex::Mutex;
+
+/// The end of the memory hotloading writes to: the staging area, and the
+/// trampoline copied past the largest image it can hold.
+pub const HOTLOAD_END: usize = hotload::STAGING_ADDR + hotload::STAGING_CAPACITY + 0x1000;
+
+/// Counters describing the heap's use since it was initialized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Stats {
+    /// The size of the heap in bytes.
+    pub heap_size: usize,
+    /// The number of bytes requested by all allocations so far.
+    pub allocated: u64,
+    /// The number of those bytes freed since.
+    pub freed: u64,
+    /// The most bytes allocated and not yet freed at any one time.
+    pub peak: u64,
+    pub allocations: u64,
+    pub frees: u64,
+    /// The number of allocations of each size class, as `bin::bin_for()`
+    /// assigns them, whichever allocator is in use.
+    pub classes: [u64; bin::NUM_BINS],
+}
+
+impl Stats {
+    /// Returns the counters for a fresh heap of `heap_size` bytes.
+    pub fn new(heap_size: usize) -> Stats {
+        Stats {
+            heap_size,
+            allocated: 0,
+            freed: 0,
+            peak: 0,
+            allocations: 0,
+            frees: 0,
+            classes: [0; bin::NUM_BINS],
+        }
+    }
+
+    /// Returns the number of bytes allocated and not yet freed.
+    pub fn in_use(&self) -> u64 {
+        self.allocated - self.freed
+    }
+
+    /// Counts an allocation for `layout`.
+    pub fn record_alloc(&mut self, layout: &Layout) {
+        self.allocated += layout.size() as u64;
+        self.allocations += 1;
+        self.peak = self.peak.max(self.in_use());
+        if let Some(bin) = bin::bin_for(layout) {
+            self.classes[bin] += 1;
+        }
+    }
+
+    /// Counts freeing an allocation for `layout`.
+    pub fn record_dealloc(&mut self, layout: &Layout) {
+        self.freed += layout.size() as u64;
+        self.frees += 1;
+    }
+}
+
+impl fmt::Display for Stats {
+    /// Writes the heap's size and usage, then the number of allocations of
+    /// each size class that has had any.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "heap:      {:>12} bytes", self.heap_size)?;
+        writeln!(f, "in use:    {:>12} bytes (peak {})", self.in_use(), self.peak)?;
+        writeln!(f, "allocated: {:>12} bytes in {} allocations", self.allocated, self.allocations)?;
+        writeln!(f, "freed:     {:>12} bytes in {} frees", self.freed, self.frees)?;
+        writeln!(f, "{:>12}  {}", "class", "allocations")?;
+        for (bin, &count) in self.classes.iter().enumerate().filter(|&(_, &count)| count != 0) {
+            writeln!(f, "{:>12}  {}", bin::block_size(bin), count)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The heap's allocator and its counters.
+struct Heap {
+    allocator: imp::Allocator,
+    stats: Stats,
+    /// The memory the allocator hands out, as `(start, end)`.
+    region: (usize, usize),
+}
+
+impl Heap {
+    #[cfg(not(feature = "heap_canaries"))]
+    fn alloc(&mut self, layout: &Layout) -> Result<*mut u8, AllocErr> {
+        self.allocator.alloc(layout.clone())
+    }
+
+    #[cfg(not(feature = "heap_canaries"))]
+    fn dealloc(&mut self, ptr: *mut u8, layout: &Layout) {
+        self.allocator.dealloc(ptr, layout.clone());
+    }
+
+    /// Allocates a block for `layout` with room for canaries around it.
+    #[cfg(feature = "heap_canaries")]
+    fn alloc(&mut self, layout: &Layout) -> Result<*mut u8, AllocErr> {
+        let (block, offset) = canary::padded(layout)
+            .ok_or_else(|| AllocErr::Exhausted { request: layout.clone() })?;
+        let start = self.allocator.alloc(block)?;
+        Ok(unsafe { canary::guard(start, offset, layout.size()) })
+    }
+
+    /// Checks the canaries around the allocation at `ptr` and poisons its
+    /// block before freeing it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a canary was overwritten.
+    #[cfg(feature = "heap_canaries")]
+    fn dealloc(&mut self, ptr: *mut u8, layout: &Layout) {
+        let (block, offset) = canary::padded(layout).expect("freed layout was allocated");
+        unsafe {
+            let start = ptr.offset(-(offset as isize));
+            if let Err(at) = canary::check(start, offset, layout.size()) {
+                panic!("heap corruption: {:#x} overwritten around the {} byte allocation at {:p}",
+                       at, layout.size(), ptr);
+            }
+
+            canary::poison(start, block.size());
+            self.allocator.dealloc(start, block);
+        }
+    }
+}
+
+/// The size of the heap in bytes, at most.
+pub const HEAP_SIZE: usize = 64 * 1024 * 1024;
+
+/// A thread-safe (locking) wrapper around the heap's allocator.
+pub struct Allocator(Mutex<Option<Heap>>);
+
+impl Allocator {
+    /// Returns an uninitialized `Allocator`. Allocating before `initialize()`
+    /// is called panics.
+    pub const fn uninitialized() -> Allocator {
+        Allocator(Mutex::new(None))
+    }
+
+    /// Initializes the allocator with the first `HEAP_SIZE` bytes of the
+    /// memory the firmware describes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ATAGs describe no memory past the kernel.
+    #[cfg(target_arch = "aarch64")]
+    pub fn initialize(&self) {
+        let (start, end) = memory_map().expect("no memory for the heap");
+        self.initialize_with(start, end.min(start + HEAP_SIZE));
+    }
+
+    /// Initializes the allocator to hand out the memory from `start` to `end`.
+    pub fn initialize_with(&self, start: usize, end: usize) {
+        let allocator = imp::Allocator::new(start, end);
+        let stats = Stats::new(end - start);
+        *self.0.lock() = Some(Heap { allocator, stats, region: (start, end) });
+    }
+
+    /// Returns the start and end of the heap's memory, or `None` if it isn't
+    /// initialized.
+    pub fn region(&self) -> Option<(usize, usize)> {
+        self.0.lock().as_ref().map(|heap| heap.region)
+    }
+
+    /// Returns the heap's counters, or `None` if it isn't initialized.
+    pub fn stats(&self) -> Option<Stats> {
+        self.0.lock().as_ref().map(|heap| heap.stats)
+    }
+}
+
+unsafe impl<'a> Alloc for &'a Allocator {
+    unsafe fn alloc(&mut self, layout: Layout) -> Result<*mut u8, AllocErr> {
+        let mut guard = self.0.lock();
+        let heap = guard.as_mut().expect("allocator uninitialized");
+        let ptr = heap.alloc(&layout)?;
+        heap.stats.record_alloc(&layout);
+        Ok(ptr)
+    }
+
+    unsafe fn dealloc(&mut self, ptr: *mut u8, layout: Layout) {
+        let mut guard = self.0.lock();
+        let heap = guard.as_mut().expect("allocator uninitialized");
+        heap.stats.record_dealloc(&layout);
+        heap.dealloc(ptr, &layout);
+    }
+
+    fn oom(&mut self, err: AllocErr) -> ! {
+        panic!("out of memory: {:?}", err);
+    }
+}
+
+/// Returns the kernel heap's counters, or `None` if it isn't initialized.
+pub fn stats() -> Option<Stats> {
+    ::ALLOCATOR.stats()
+}
+
+/// Returns the range of memory the heap and the frame allocator share as
+/// `(start, end)`: the first `MEM` region in `atags` with memory past both
+/// `binary_end`, the end of the kernel binary, and `HOTLOAD_END`. Returns
+/// `None` if there is no such region.
+pub fn heap_range(atags: Atags, binary_end: usize) -> Option<(usize, usize)> {
+    let floor = util::align_up(binary_end.max(HOTLOAD_END), 16);
+    atags.filter_map(|atag| atag.mem())
+        .map(|mem| (mem.start as usize, mem.start as usize + mem.size as usize))
+        .filter(|&(_, end)| end > floor)
+        .map(|(start, end)| (start.max(floor), end))
+        .next()
+}
+
+#[cfg(target_arch = "aarch64")]
+extern "C" {
+    static _end: u8;
+}
+
+/// Returns the range of memory the heap and the frame allocator share on
+/// this board; see `heap_range()`.
+#[cfg(target_arch = "aarch64")]
+pub fn memory_map() -> Option<(usize, usize)> {
+    let binary_end = unsafe { &_end as *const u8 as usize };
+    heap_range(Atags::get(), binary_end)
+}
@@ -0,0 +1,33 @@
+//! Address alignment helpers.
+
+use console::kassert;
+
+/// Aligns `addr` downwards to the nearest multiple of `align`.
+///
+/// # Panics
+///
+/// Panics if `align` is not a power of 2.
+pub fn align_down(addr: usize, align: usize) -> usize {
+    kassert!(align.is_power_of_two(), "alignment must be a power of 2");
+    addr & !(align - 1)
+}
+
+/// Aligns `addr` upwards to the nearest multiple of `align`.
+///
+/// # Panics
+///
+/// Panics if `align` is not a power of 2, or if aligning overflows.
+pub fn align_up(addr: usize, align: usize) -> usize {
+    checked_align_up(addr, align).expect("aligned address overflows")
+}
+
+/// Aligns `addr` upwards to the nearest multiple of `align`, or returns `None`
+/// if the result doesn't fit in a `usize`.
+///
+/// # Panics
+///
+/// Panics if `align` is not a power of 2.
+pub fn checked_align_up(addr: usize, align: usize) -> Option<usize> {
+    kassert!(align.is_power_of_two(), "alignment must be a power of 2");
+    addr.checked_add(align - 1).map(|addr| addr & !(align - 1))
+}
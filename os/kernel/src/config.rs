@@ -0,0 +1,22 @@
+/// Board-specific configuration for the kernel.
+pub struct BoardConfig {
+    /// The GPIO pin driving the heartbeat LED, or `None` to disable the
+    /// heartbeat.
+    pub heartbeat: Option<u8>,
+    /// Whether the hardware watchdog is armed at boot.
+    pub watchdog: bool,
+    /// Whether console output is styled with ANSI escape sequences at boot.
+    /// The shell's `color` command changes this at run time.
+    pub color: bool,
+    /// The size in pixels of the framebuffer console output is mirrored to
+    /// over HDMI, or `None` to use only the UART.
+    pub screen: Option<(u32, u32)>,
+}
+
+/// The configuration for the board the kernel is built for.
+pub const BOARD: BoardConfig = BoardConfig {
+    heartbeat: Some(16),
+    watchdog: true,
+    color: true,
+    screen: Some((1024, 768)),
+};
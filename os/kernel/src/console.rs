@@ -1,30 +1,86 @@
 use std::io;
 use std::fmt;
+use std::mem;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 
-use pi::uart::MiniUart;
+use pi::ring::RingBuffer;
 
+use fbcon::Screen;
+use hw::{self, Uart};
+use hw::timer::current_time_us;
+use irq;
+use klog::KLOG;
+use line::MAX_LINE;
 use mutex::Mutex;
+use self::input::Key;
+use self::style::Style;
+
+/// How the console delivers input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Bytes are delivered as they arrive and aren't echoed. Programs that
+    /// handle keys themselves, like the shell's line editor or a pager, use
+    /// this.
+    Raw,
+    /// Input is delivered a line at a time. Bytes are echoed as they are
+    /// typed, Backspace erases the last one and Ctrl-U the whole line, and
+    /// nothing can be read until Enter completes the line, which is then
+    /// read ending in `\n`.
+    Cooked,
+}
+
+/// The line being typed or read in cooked mode.
+struct CookedLine {
+    bytes: [u8; MAX_LINE],
+    len: usize,
+    /// The number of bytes of the completed line already read, or `None`
+    /// while the line is still being typed.
+    read: Option<usize>,
+    /// The line was completed by `\r`, so a `\n` right after it is part of
+    /// the same Enter.
+    after_cr: bool,
+}
+
+const BELL: u8 = 7;
 
 /// A global singleton allowing read/write access to the console.
+///
+/// Input comes from the UART and is delivered according to the console's
+/// `Mode`, which starts out cooked. Output goes to the UART and, once one is
+/// attached, is mirrored to a framebuffer `Screen`.
 pub struct Console {
-    inner: Option<MiniUart>
+    inner: Option<Uart>,
+    screen: Option<Screen<'static>>,
+    mode: Mode,
+    cooked: CookedLine,
 }
 
 impl Console {
     /// Creates a new instance of `Console`.
     const fn new() -> Console {
-        Console { inner:None }
+        Console {
+            inner: None,
+            screen: None,
+            mode: Mode::Cooked,
+            cooked: CookedLine { bytes: [0; MAX_LINE], len: 0, read: None, after_cr: false },
+        }
+    }
+
+    /// Mirrors all further output to `screen`, replacing any screen already
+    /// attached.
+    pub fn attach_screen(&mut self, screen: Screen<'static>) {
+        self.screen = Some(screen);
     }
 
     /// Initializes the console if it's not already initialized.
     #[inline]
     fn initialize(&mut self) {
-        self.inner = Some(MiniUart::new())
+        self.inner = Some(Uart::new())
     }
 
-    /// Returns a mutable borrow to the inner `MiniUart`, initializing it as
+    /// Returns a mutable borrow to the inner `Uart`, initializing it as
     /// needed.
-    fn inner(&mut self) -> &mut MiniUart {
+    fn inner(&mut self) -> &mut Uart {
         if self.inner.is_none() {
             self.initialize();
         }
@@ -32,26 +88,219 @@ impl Console {
         self.inner.as_mut().unwrap()
     }
 
-    /// Reads a byte from the UART device, blocking until a byte is available.
+    /// Switches the UART to interrupt-driven reads: from now on the IRQ
+    /// handler buffers the bytes it receives, so none are lost while the
+    /// reader isn't running, and the console reads from that buffer. The IRQ
+    /// handler must pass UART interrupts to `hw::handle_uart_irq()`.
+    #[cfg(target_arch = "aarch64")]
+    pub fn enable_rx_interrupt(&mut self) {
+        self.inner().enable_rx_interrupt();
+    }
+
+    /// Returns the input mode.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Switches to the input mode `mode` and returns the mode it replaces, so
+    /// that it can be restored. Cooked input that hasn't been read yet is
+    /// discarded.
+    pub fn set_mode(&mut self, mode: Mode) -> Mode {
+        self.cooked.len = 0;
+        self.cooked.read = None;
+        mem::replace(&mut self.mode, mode)
+    }
+
+    /// Returns `true` if there is at least one byte ready to be read. In
+    /// cooked mode, this processes the bytes typed so far, and a byte is ready
+    /// once a line has been completed. This method does not block.
+    pub fn has_byte(&mut self) -> bool {
+        match self.mode {
+            Mode::Raw => self.inner().has_byte(),
+            Mode::Cooked => {
+                while self.cooked.read.is_none() && self.inner().has_byte() {
+                    let byte = self.inner().read_byte();
+                    self.cook(byte);
+                }
+
+                self.cooked.read.is_some()
+            }
+        }
+    }
+
+    /// Reads a byte, blocking until one is available.
     pub fn read_byte(&mut self) -> u8 {
-        self.inner().read_byte()
+        match self.mode {
+            Mode::Raw => self.inner().read_byte(),
+            Mode::Cooked => {
+                while self.cooked.read.is_none() {
+                    let byte = self.inner().read_byte();
+                    self.cook(byte);
+                }
+
+                self.next_cooked()
+            }
+        }
+    }
+
+    /// Processes `byte`, typed in cooked mode.
+    fn cook(&mut self, byte: u8) {
+        let after_cr = mem::replace(&mut self.cooked.after_cr, byte == b'\r');
+        match Key::from_byte(byte) {
+            Key::Enter if byte == b'\n' && after_cr => {  }
+            Key::Enter => {
+                // `bytes` always has room for the newline.
+                self.cooked.bytes[self.cooked.len] = b'\n';
+                self.cooked.len += 1;
+                self.cooked.read = Some(0);
+                self.write_byte(b'\r');
+                self.write_byte(b'\n');
+            }
+            Key::Backspace => self.erase(1),
+            Key::Ctrl(b'u') => {
+                let len = self.cooked.len;
+                self.erase(len);
+            }
+            Key::Char(_) | Key::Tab if self.cooked.len + 1 < MAX_LINE => {
+                self.cooked.bytes[self.cooked.len] = byte;
+                self.cooked.len += 1;
+                self.write_byte(byte);
+            }
+            _ => self.write_byte(BELL),
+        }
+    }
+
+    /// Erases the last `count` bytes of the line being typed in cooked mode,
+    /// from the line and from the terminal.
+    fn erase(&mut self, count: usize) {
+        if self.cooked.len == 0 {
+            self.write_byte(BELL);
+            return;
+        }
+
+        for _ in 0..count.min(self.cooked.len) {
+            self.cooked.len -= 1;
+            for &byte in b"\x08 \x08" {
+                self.write_byte(byte);
+            }
+        }
+    }
+
+    /// Returns the next byte of the completed cooked line. Once all of it has
+    /// been read, the next line can be typed.
+    fn next_cooked(&mut self) -> u8 {
+        let read = self.cooked.read.expect("a cooked line is complete");
+        let byte = self.cooked.bytes[read];
+        if read + 1 == self.cooked.len {
+            self.cooked.len = 0;
+            self.cooked.read = None;
+        } else {
+            self.cooked.read = Some(read + 1);
+        }
+
+        byte
     }
 
     /// Writes the byte `byte` to the UART device.
     pub fn write_byte(&mut self, byte: u8) {
-        self.inner().write_byte(byte)
+        self.inner().write_byte(byte);
+        if let Some(ref mut screen) = self.screen {
+            screen.write_byte(byte);
+        }
+    }
+
+    /// Writes everything queued in `deferred` to the UART device, followed by
+    /// a note if any of it was dropped since the last call.
+    pub fn flush_deferred(&mut self, deferred: &Deferred) {
+        while let Some(byte) = deferred.ring.pop() {
+            self.write_byte(byte);
+        }
+
+        let dropped = deferred.ring.dropped();
+        let reported = deferred.reported.swap(dropped, Ordering::Relaxed);
+        if dropped != reported {
+            let _ = fmt::Write::write_fmt(self, format_args!(
+                "[console: {} bytes of interrupt output dropped]\n", dropped.wrapping_sub(reported)));
+        }
+    }
+}
+
+/// Output written in interrupt context, queued until the interrupted code
+/// writes it to the console.
+///
+/// Interrupt handlers are the producers and `Console::flush_deferred()` is the
+/// consumer. Only one handler runs at a time, and only the holder of the
+/// console lock has a `Console`, so there is one of each. Output that doesn't
+/// fit is dropped.
+pub struct Deferred {
+    ring: RingBuffer,
+    /// The value of `ring.dropped()` last reported by the consumer.
+    reported: AtomicUsize,
+}
+
+impl Deferred {
+    /// Returns an empty queue.
+    pub const fn new() -> Deferred {
+        Deferred { ring: RingBuffer::new(), reported: ATOMIC_USIZE_INIT }
+    }
+
+    /// Returns `true` if nothing is queued.
+    pub fn is_empty(&self) -> bool {
+        self.ring.is_empty()
+    }
+
+    /// Queues `args`, translating newlines like the UART does.
+    pub fn write_fmt(&self, args: fmt::Arguments) {
+        let _ = fmt::Write::write_fmt(&mut DeferredWriter(self), args);
+    }
+}
+
+/// Adapts a `&Deferred` to `fmt::Write`.
+struct DeferredWriter<'a>(&'a Deferred);
+
+impl<'a> fmt::Write for DeferredWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if byte == b'\n' {
+                self.0.ring.push(b'\r');
+            }
+
+            self.0.ring.push(byte);
+        }
+
+        Ok(())
     }
 }
 
 impl io::Read for Console {
+    /// In cooked mode, reads block until a line is complete and return no
+    /// more than the rest of that line.
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner().read(buf)
+        match self.mode {
+            Mode::Raw => self.inner().read(buf),
+            Mode::Cooked if buf.is_empty() => Ok(0),
+            Mode::Cooked => {
+                buf[0] = self.read_byte();
+                let mut read = 1;
+                while read < buf.len() && self.cooked.read.is_some() {
+                    buf[read] = self.next_cooked();
+                    read += 1;
+                }
+
+                Ok(read)
+            }
+        }
     }
 }
 
 impl io::Write for Console {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.inner().write(buf)
+        let written = self.inner().write(buf)?;
+        if let Some(ref mut screen) = self.screen {
+            screen.write(&buf[..written]);
+        }
+
+        Ok(written)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -61,21 +310,398 @@ impl io::Write for Console {
 
 impl fmt::Write for Console {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        self.inner().write_str(s)
+        self.inner().write_str(s)?;
+        if let Some(ref mut screen) = self.screen {
+            screen.write(s.as_bytes());
+        }
+
+        Ok(())
+    }
+}
+
+/// ANSI colors and text attributes for console output, so that every part of
+/// the kernel styles its output the same way.
+///
+/// Styling is off until `set_enabled()` turns it on, for terminals that don't
+/// understand the escape sequences. While it is off, styled values are written
+/// as they are.
+///
+/// ```rust,ignore
+/// kprintln!("{}", style::paint(Style::Red, "error: no such pin"));
+/// ```
+pub mod style {
+    use std::fmt;
+    use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+
+    /// Whether styles are written.
+    static ENABLED: AtomicBool = ATOMIC_BOOL_INIT;
+
+    /// A style: an SGR (Select Graphic Rendition) attribute or color.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum Style {
+        Bold,
+        /// Faint text, for secondary information like timestamps.
+        Dim,
+        Red,
+        Green,
+        Yellow,
+        Blue,
+        Cyan,
+    }
+
+    impl Style {
+        /// Returns the SGR parameter that selects this style.
+        pub fn code(&self) -> u8 {
+            match *self {
+                Style::Bold => 1,
+                Style::Dim => 2,
+                Style::Red => 31,
+                Style::Green => 32,
+                Style::Yellow => 33,
+                Style::Blue => 34,
+                Style::Cyan => 36,
+            }
+        }
+
+        /// Returns `value` in this style, whether or not styling is enabled.
+        pub fn apply<T: fmt::Display>(self, value: T) -> Styled<T> {
+            Styled { style: Some(self), value }
+        }
+    }
+
+    /// A value that is written in a style, followed by a reset to the default
+    /// style, or as it is if it has no style.
+    #[derive(Debug, Copy, Clone)]
+    pub struct Styled<T> {
+        style: Option<Style>,
+        value: T,
+    }
+
+    impl<T: fmt::Display> fmt::Display for Styled<T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.style {
+                Some(style) => write!(f, "\x1b[{}m{}\x1b[0m", style.code(), self.value),
+                None => self.value.fmt(f),
+            }
+        }
+    }
+
+    /// Returns `true` if styling is enabled.
+    pub fn enabled() -> bool {
+        ENABLED.load(Ordering::Relaxed)
+    }
+
+    /// Enables or disables styling.
+    pub fn set_enabled(enabled: bool) {
+        ENABLED.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns `value` in `style` if styling is enabled, and as it is
+    /// otherwise.
+    pub fn paint<T: fmt::Display>(style: Style, value: T) -> Styled<T> {
+        Styled { style: if enabled() { Some(style) } else { None }, value }
+    }
+}
+
+/// Decoding the bytes a terminal sends into key presses, so that the shell and
+/// full-screen programs deal with keys instead of escape sequences.
+///
+/// A lone `ESC` can't be told apart from the start of a sequence until the
+/// next byte arrives, so readers give up on a sequence that stops arriving
+/// for `ESC_TIMEOUT_US`; see `Decoder::flush()`.
+pub mod input {
+    /// The escape byte that starts terminal control sequences.
+    pub const ESC: u8 = 0x1B;
+
+    /// How long a reader waits for the rest of an escape sequence before
+    /// giving up on it, in microseconds. Terminals send sequences all at
+    /// once, so this only expires after the Escape key itself.
+    pub const ESC_TIMEOUT_US: u64 = 50_000;
+
+    /// The direction of an arrow key.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum Arrow {
+        Up,
+        Down,
+        Left,
+        Right,
+    }
+
+    /// A key press.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum Key {
+        /// A byte that isn't a control character: printable ASCII, or part of
+        /// a UTF-8 sequence.
+        Char(u8),
+        /// Return, sent as `\r` or `\n`.
+        Enter,
+        /// Backspace, sent as `^H` or `DEL`.
+        Backspace,
+        Tab,
+        /// The Escape key: an `ESC` that nothing followed in time.
+        Escape,
+        CtrlC,
+        /// Any other control character, as the lowercase letter typed with
+        /// Ctrl: `Ctrl(b'a')` for `0x01`. `0x00` and `0x1C..0x1F` are given
+        /// as the punctuation that sends them, like `Ctrl(b'@')`.
+        Ctrl(u8),
+        Arrow(Arrow),
+        Home,
+        End,
+        Delete,
+        /// An escape sequence that isn't understood. It is consumed whole.
+        Unknown,
+    }
+
+    impl Key {
+        /// Returns the key that the byte `byte` sends on its own.
+        pub fn from_byte(byte: u8) -> Key {
+            match byte {
+                b'\r' | b'\n' => Key::Enter,
+                0x08 | 0x7F => Key::Backspace,
+                b'\t' => Key::Tab,
+                ESC => Key::Escape,
+                0x03 => Key::CtrlC,
+                0x01...0x1A => Key::Ctrl(byte - 1 + b'a'),
+                0x00...0x1F => Key::Ctrl(byte + b'@'),
+                _ => Key::Char(byte),
+            }
+        }
+    }
+
+    /// Where `Decoder` is in an escape sequence.
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+    enum State {
+        Ground,
+        /// After `ESC`.
+        Escape,
+        /// After `ESC [` (CSI) or `ESC O` (SS3), which arrow keys are sent
+        /// with depending on the terminal's mode.
+        Sequence,
+    }
+
+    /// Turns the bytes a terminal sends into `Key`s, one byte at a time.
+    #[derive(Debug)]
+    pub struct Decoder {
+        state: State,
+        /// The first numeric parameter of the sequence being decoded.
+        param: u32,
+        /// Set once the first parameter has ended.
+        param_done: bool,
+    }
+
+    impl Decoder {
+        /// Returns a decoder that isn't in the middle of an escape sequence.
+        pub fn new() -> Decoder {
+            Decoder { state: State::Ground, param: 0, param_done: false }
+        }
+
+        /// Returns `true` if the decoder is in the middle of an escape
+        /// sequence.
+        pub fn is_pending(&self) -> bool {
+            self.state != State::Ground
+        }
+
+        /// Gives up on the escape sequence being decoded, if any, and
+        /// returns the key it stands for: `Key::Escape` if only `ESC` has
+        /// been fed, and `Key::Unknown` otherwise.
+        pub fn flush(&mut self) -> Option<Key> {
+            let key = match self.state {
+                State::Ground => None,
+                State::Escape => Some(Key::Escape),
+                State::Sequence => Some(Key::Unknown),
+            };
+
+            self.state = State::Ground;
+            key
+        }
+
+        /// Feeds `byte` to the decoder. Returns the key it completes, if any.
+        pub fn feed(&mut self, byte: u8) -> Option<Key> {
+            match self.state {
+                State::Ground if byte == ESC => {
+                    self.state = State::Escape;
+                    None
+                }
+                State::Ground => Some(Key::from_byte(byte)),
+                State::Escape if byte == b'[' || byte == b'O' => {
+                    self.state = State::Sequence;
+                    self.param = 0;
+                    self.param_done = false;
+                    None
+                }
+                State::Escape => {
+                    self.state = State::Ground;
+                    Some(Key::Unknown)
+                }
+                // Parameters and intermediates; the sequence ends with a byte
+                // in 0x40..=0x7E. Only the first parameter matters.
+                State::Sequence if byte >= 0x20 && byte < 0x40 => {
+                    if byte >= b'0' && byte <= b'9' && !self.param_done {
+                        self.param = self.param.saturating_mul(10).saturating_add((byte - b'0') as u32);
+                    } else {
+                        self.param_done = true;
+                    }
+
+                    None
+                }
+                State::Sequence => {
+                    self.state = State::Ground;
+                    Some(match (byte, self.param) {
+                        (b'A', _) => Key::Arrow(Arrow::Up),
+                        (b'B', _) => Key::Arrow(Arrow::Down),
+                        (b'C', _) => Key::Arrow(Arrow::Right),
+                        (b'D', _) => Key::Arrow(Arrow::Left),
+                        (b'H', _) => Key::Home,
+                        (b'F', _) => Key::End,
+                        (b'~', 1) | (b'~', 7) => Key::Home,
+                        (b'~', 4) | (b'~', 8) => Key::End,
+                        (b'~', 3) => Key::Delete,
+                        _ => Key::Unknown,
+                    })
+                }
+            }
+        }
     }
 }
 
 /// Global `Console` singleton.
-pub static CONSOLE: Mutex<Console> = Mutex::new(Console::new());
+///
+/// Commands run with the console locked for as long as they take, so its lock
+/// leaves IRQs unmasked: the shell can still be preempted and the watchdog
+/// still fed. Interrupt handlers never take it.
+pub static CONSOLE: Mutex<Console> = Mutex::unmasked(Console::new());
+
+/// Output from `kprint!` in interrupt context, or with IRQs masked while the
+/// console is locked, waiting to be written to `CONSOLE`.
+pub static DEFERRED: Deferred = Deferred::new();
 
-/// Internal function called by the `kprint[ln]!` macros.
+/// Internal function called by the `kprint[ln]!` macros. The output is
+/// appended to the kernel log, then written to the console. In interrupt
+/// context, it is queued in `DEFERRED` instead of waiting for the console
+/// lock, which the interrupted code may hold. So it is with IRQs masked, as in
+/// exception handlers, if the console is locked: its holder can't run to
+/// unlock it.
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use std::fmt::Write;
-    let mut console = CONSOLE.lock();
+    let _ = KLOG.lock().write_fmt(args);
+    if irq::in_handler() {
+        DEFERRED.write_fmt(args);
+        return;
+    }
+
+    let mut console = match CONSOLE.try_lock() {
+        Some(console) => console,
+        None if hw::irq::is_masked() => {
+            DEFERRED.write_fmt(args);
+            return;
+        }
+        None => CONSOLE.lock(),
+    };
+
+    console.flush_deferred(&DEFERRED);
     console.write_fmt(args).unwrap();
 }
 
+/// Writes `args` through a new handle to the UART without taking the console
+/// lock, which the caller may already hold. Used to report failures.
+#[doc(hidden)]
+pub fn _emergency_print(args: fmt::Arguments) {
+    use std::fmt::Write;
+    let _ = Uart::new().write_fmt(args);
+}
+
+/// An assertion operand, formatted as `{:#x?}` if it's an integer and as
+/// `{:?}` otherwise.
+#[doc(hidden)]
+pub struct Operand<'a, T: 'a + ?Sized>(pub &'a T);
+
+impl<'a, T: fmt::Debug + ?Sized> fmt::Debug for Operand<'a, T> {
+    default fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+macro hex_operands($($t:ty),*) {
+    $(impl<'a> fmt::Debug for Operand<'a, $t> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "{:#x?}", self.0)
+        }
+    })*
+}
+
+hex_operands!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// Reports a failed kernel assertion of `expr` at `file:line` through the
+/// emergency console, then panics.
+#[doc(hidden)]
+pub fn _assert_failed(expr: fmt::Arguments, message: Option<fmt::Arguments>,
+                      file: &'static str, line: u32) -> ! {
+    _emergency_print(format_args!("kernel assertion failed on core {} at {}:{}: {}\n",
+                                  hw::core_id(), file, line, expr));
+    if let Some(message) = message {
+        _emergency_print(format_args!("  note: {}\n", message));
+    }
+
+    panic!("kernel assertion failed at {}:{}", file, line)
+}
+
+/// Like `assert!`, but reports a failure with the expression, file, line and
+/// core through the emergency console before panicking.
+pub macro kassert {
+    ($cond:expr) => (
+        if !$cond {
+            _assert_failed(format_args!("`{}`", stringify!($cond)), None, file!(), line!())
+        }
+    ),
+    ($cond:expr, $($arg:tt)+) => (
+        if !$cond {
+            _assert_failed(format_args!("`{}`", stringify!($cond)),
+                           Some(format_args!($($arg)+)), file!(), line!())
+        }
+    )
+}
+
+/// Like `assert_eq!`, but reports a failure like `kassert!` does, including
+/// both operands. Integer operands are printed in hex.
+pub macro kassert_eq {
+    ($left:expr, $right:expr) => (
+        match (&$left, &$right) {
+            (left, right) => if !(*left == *right) {
+                _assert_failed(format_args!("`{} == {}`\n   left: {:?}\n  right: {:?}",
+                                            stringify!($left), stringify!($right),
+                                            Operand(left), Operand(right)),
+                               None, file!(), line!())
+            }
+        }
+    ),
+    ($left:expr, $right:expr, $($arg:tt)+) => (
+        match (&$left, &$right) {
+            (left, right) => if !(*left == *right) {
+                _assert_failed(format_args!("`{} == {}`\n   left: {:?}\n  right: {:?}",
+                                            stringify!($left), stringify!($right),
+                                            Operand(left), Operand(right)),
+                               Some(format_args!($($arg)+)), file!(), line!())
+            }
+        }
+    )
+}
+
+/// Like `kassert!`, but only checked in debug builds.
+pub macro debug_kassert($($arg:tt)*) {
+    if cfg!(debug_assertions) {
+        kassert!($($arg)*);
+    }
+}
+
+/// Like `kassert_eq!`, but only checked in debug builds.
+pub macro debug_kassert_eq($($arg:tt)*) {
+    if cfg!(debug_assertions) {
+        kassert_eq!($($arg)*);
+    }
+}
+
 /// Like `println!`, but for kernel-space.
 pub macro kprintln {
     () => (kprint!("\n")),
@@ -87,3 +713,128 @@ pub macro kprintln {
 pub macro kprint($($arg:tt)*) {
     _print(format_args!($($arg)*))
 }
+
+/// How important a log message is, from least to most.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+/// Every level, from least to most important.
+pub const LEVELS: [Level; 5] = [Level::Trace, Level::Debug, Level::Info, Level::Warn, Level::Error];
+
+impl Level {
+    /// Returns the level's name, as accepted by `from_name()`.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+
+    /// Returns the level named `name`, if there is one.
+    pub fn from_name(name: &str) -> Option<Level> {
+        LEVELS.iter().cloned().find(|level| level.name() == name)
+    }
+
+    /// Returns the tag messages at this level are prefixed with.
+    fn tag(&self) -> &'static str {
+        match *self {
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Info => "INFO ",
+            Level::Warn => "WARN ",
+            Level::Error => "ERROR",
+        }
+    }
+
+    /// Returns the style the tag is written in.
+    fn style(&self) -> Style {
+        match *self {
+            Level::Trace => Style::Dim,
+            Level::Debug => Style::Blue,
+            Level::Info => Style::Green,
+            Level::Warn => Style::Yellow,
+            Level::Error => Style::Red,
+        }
+    }
+}
+
+/// The least important level that is logged, as a `Level` cast to `usize`.
+static LOG_LEVEL: AtomicUsize = AtomicUsize::new(Level::Info as usize);
+
+/// Returns the least important level that is logged.
+pub fn log_level() -> Level {
+    LEVELS[LOG_LEVEL.load(Ordering::Relaxed)]
+}
+
+/// Logs messages at `level` and more important levels from now on.
+pub fn set_log_level(level: Level) {
+    LOG_LEVEL.store(level as usize, Ordering::Relaxed);
+}
+
+/// Writes the prefix of a message logged at `level`, `time_us` microseconds
+/// after boot: the time in seconds, then the level's tag, as in
+/// `[    1.000042] INFO  `.
+pub fn write_log_prefix<W: fmt::Write>(w: &mut W, level: Level, time_us: u64) -> fmt::Result {
+    write!(w, "{} {} ",
+           style::paint(Style::Dim, format_args!("[{:5}.{:06}]", time_us / 1_000_000, time_us % 1_000_000)),
+           style::paint(level.style(), level.tag()))
+}
+
+/// Internal function called by the logging macros.
+#[doc(hidden)]
+pub fn _log(level: Level, args: fmt::Arguments) {
+    if level < log_level() {
+        return;
+    }
+
+    struct Prefix(Level, u64);
+
+    impl fmt::Display for Prefix {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write_log_prefix(f, self.0, self.1)
+        }
+    }
+
+    _print(format_args!("{}{}\n", Prefix(level, current_time_us()), args));
+}
+
+/// Logs a message at `level` with a timestamp and the level's tag, if
+/// messages at `level` are being logged. Like `kprintln!`, the message is
+/// formatted with `format_args!`.
+pub macro log($level:expr, $($arg:tt)+) {
+    _log($level, format_args!($($arg)+))
+}
+
+/// Logs a message at `Level::Trace`.
+pub macro trace($($arg:tt)+) {
+    _log(Level::Trace, format_args!($($arg)+))
+}
+
+/// Logs a message at `Level::Debug`.
+pub macro debug($($arg:tt)+) {
+    _log(Level::Debug, format_args!($($arg)+))
+}
+
+/// Logs a message at `Level::Info`.
+pub macro info($($arg:tt)+) {
+    _log(Level::Info, format_args!($($arg)+))
+}
+
+/// Logs a message at `Level::Warn`.
+pub macro warn($($arg:tt)+) {
+    _log(Level::Warn, format_args!($($arg)+))
+}
+
+/// Logs a message at `Level::Error`.
+pub macro error($($arg:tt)+) {
+    _log(Level::Error, format_args!($($arg)+))
+}
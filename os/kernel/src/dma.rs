@@ -0,0 +1,125 @@
+//! Buffers shared with the GPU's firmware and the DMA engine.
+//!
+//! Both address memory through the VideoCore bus, where ARM physical memory is
+//! aliased at `0xC0000000` past the L2 cache. While the MMU is off the ARM
+//! doesn't cache memory either, so the two agree on a buffer's contents. The
+//! buffers come from a pool of `POOL_SIZE` bytes reserved from the frame
+//! allocator at boot, so that once the MMU is on, the pool can be mapped
+//! uncached as a whole.
+
+use std::{ptr, slice};
+
+use pi::dma::memory_bus_address;
+
+use frames::{FrameAllocator, PAGE_SIZE};
+use mutex::Mutex;
+
+/// The size of the pool in bytes. It is aligned to its size, which makes it a
+/// single 2MiB block for the MMU.
+pub const POOL_SIZE: usize = 2 * 1024 * 1024;
+
+/// A pool of memory that DMA buffers are allocated from.
+pub struct Pool(Mutex<Option<FrameAllocator<'static>>>);
+
+/// The kernel's DMA pool.
+pub static POOL: Pool = Pool::uninitialized();
+
+impl Pool {
+    /// Returns a pool with no memory. Allocations fail until it is
+    /// initialized.
+    pub const fn uninitialized() -> Pool {
+        Pool(Mutex::new(None))
+    }
+
+    /// Initializes the pool to hand out the frames `frames` manages.
+    pub fn initialize_with(&self, frames: FrameAllocator<'static>) {
+        *self.0.lock() = Some(frames);
+    }
+
+    /// Returns the start and end of the pool's memory, or `None` if it isn't
+    /// initialized.
+    pub fn region(&self) -> Option<(usize, usize)> {
+        self.0.lock().as_ref().map(|frames| frames.region())
+    }
+
+    /// Allocates a zeroed buffer of `len` bytes starting at a multiple of
+    /// `align` bytes. Buffers are whole pages, so `align` is at least a page.
+    /// Returns `None` if the pool has no room or isn't initialized.
+    pub fn alloc(&'static self, len: usize, align: usize) -> Option<DmaBuffer> {
+        let pages = ((len + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+        let addr = self.0.lock().as_mut()?.alloc_contiguous(pages, align)?;
+        unsafe { ptr::write_bytes(addr as *mut u8, 0, pages * PAGE_SIZE) };
+        Some(DmaBuffer { pool: self, addr, len, pages })
+    }
+}
+
+/// A buffer from a DMA pool, returned to it when dropped.
+pub struct DmaBuffer {
+    pool: &'static Pool,
+    addr: usize,
+    len: usize,
+    pages: usize,
+}
+
+impl DmaBuffer {
+    /// Returns the ARM physical address of the buffer.
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// Returns the uncached VideoCore bus address of the buffer, which the
+    /// firmware and the DMA engine use.
+    pub fn bus_address(&self) -> u32 {
+        memory_bus_address(self.addr)
+    }
+
+    /// Returns the length of the buffer in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns a pointer to the start of the buffer.
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.addr as *mut u8
+    }
+
+    /// Returns the buffer's contents.
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.addr as *const u8, self.len) }
+    }
+
+    /// Returns the buffer's contents for writing.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.addr as *mut u8, self.len) }
+    }
+}
+
+impl Drop for DmaBuffer {
+    fn drop(&mut self) {
+        let mut guard = self.pool.0.lock();
+        let frames = guard.as_mut().expect("DMA pool uninitialized");
+        for page in 0..self.pages {
+            frames.free(self.addr + page * PAGE_SIZE);
+        }
+    }
+}
+
+/// Reserves the kernel's DMA pool from the frame allocator.
+///
+/// # Panics
+///
+/// Panics if there aren't `POOL_SIZE` bytes of free frames.
+#[cfg(target_arch = "aarch64")]
+pub fn initialize() {
+    use frames::{self, in_region};
+
+    let start = frames::alloc_contiguous(POOL_SIZE / PAGE_SIZE, POOL_SIZE)
+        .expect("no frames for the DMA pool");
+    POOL.initialize_with(unsafe { in_region(start, start + POOL_SIZE) });
+}
+
+/// Allocates a zeroed buffer of `len` bytes aligned to `align` bytes from the
+/// kernel's DMA pool; see `Pool::alloc()`.
+pub fn alloc(len: usize, align: usize) -> Option<DmaBuffer> {
+    POOL.alloc(len, align)
+}
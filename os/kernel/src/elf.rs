@@ -0,0 +1,266 @@
+//! Parsing and loading AArch64 ELF executables.
+//!
+//! Without an MMU every process shares the physical address space, so an
+//! executable can't be linked to run at a fixed address: it must be position
+//! independent (`ET_DYN`, as `-pie` links it). It is loaded wherever its
+//! frames were allocated, and its `R_AARCH64_RELATIVE` relocations, the only
+//! kind a static PIE has, are applied for that address.
+
+use std::io;
+
+/// The bytes every ELF file starts with.
+pub const MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+
+/// `e_type` of a position-independent executable.
+pub const ET_DYN: u16 = 3;
+
+/// `e_machine` of AArch64.
+pub const EM_AARCH64: u16 = 183;
+
+/// `p_type` of a segment that is loaded into memory.
+pub const PT_LOAD: u32 = 1;
+
+/// `p_type` of the segment holding the dynamic section.
+pub const PT_DYNAMIC: u32 = 2;
+
+/// Dynamic section tags: the end of the section, and the address, size and
+/// entry size of the relocation table.
+pub const DT_NULL: u64 = 0;
+pub const DT_RELA: u64 = 7;
+pub const DT_RELASZ: u64 = 8;
+pub const DT_RELAENT: u64 = 9;
+
+/// Relocation types: none, and "the load bias plus the addend".
+pub const R_AARCH64_NONE: u32 = 0;
+pub const R_AARCH64_RELATIVE: u32 = 1027;
+
+/// The sizes of the ELF header, a program header, a dynamic section entry and
+/// a relocation.
+const HEADER_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const DYN_SIZE: usize = 16;
+const RELA_SIZE: usize = 24;
+
+/// A segment of an executable, from its program header.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// Where the segment's contents start in the file.
+    pub offset: u64,
+    /// The address the segment is linked at.
+    pub vaddr: u64,
+    /// How many bytes of the segment are in the file.
+    pub file_size: u64,
+    /// How many bytes the segment takes in memory. The bytes past
+    /// `file_size` are zeroed.
+    pub mem_size: u64,
+}
+
+/// A parsed executable, borrowing the file's contents.
+#[derive(Debug)]
+pub struct Elf<'a> {
+    data: &'a [u8],
+    /// The address of the entry point, as linked.
+    pub entry: u64,
+    /// The `PT_LOAD` segments.
+    pub segments: Vec<Segment>,
+    /// The `PT_DYNAMIC` segment, if there is one.
+    pub dynamic: Option<Segment>,
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Returns the `size` bytes of `data` at `at`, failing if they aren't all
+/// in `data`.
+fn bytes(data: &[u8], at: usize, size: usize) -> io::Result<&[u8]> {
+    at.checked_add(size)
+        .and_then(|end| data.get(at..end))
+        .ok_or(invalid("ELF file is truncated"))
+}
+
+fn read_u16(data: &[u8], at: usize) -> io::Result<u16> {
+    let bytes = bytes(data, at, 2)?;
+    Ok(bytes[0] as u16 | (bytes[1] as u16) << 8)
+}
+
+fn read_u32(data: &[u8], at: usize) -> io::Result<u32> {
+    let bytes = bytes(data, at, 4)?;
+    Ok((0..4).fold(0, |value, i| value | (bytes[i] as u32) << (i * 8)))
+}
+
+fn read_u64(data: &[u8], at: usize) -> io::Result<u64> {
+    let bytes = bytes(data, at, 8)?;
+    Ok((0..8).fold(0, |value, i| value | (bytes[i] as u64) << (i * 8)))
+}
+
+fn write_u64(data: &mut [u8], at: usize, value: u64) -> io::Result<()> {
+    let bytes = at.checked_add(8)
+        .and_then(move |end| data.get_mut(at..end))
+        .ok_or(invalid("relocation is outside the image"))?;
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (value >> (i * 8)) as u8;
+    }
+
+    Ok(())
+}
+
+impl<'a> Elf<'a> {
+    /// Parses the ELF header and program headers of `data`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if `data` isn't a little-endian,
+    /// 64-bit, position-independent AArch64 executable, or if its headers or
+    /// segments don't fit in it.
+    pub fn parse(data: &'a [u8]) -> io::Result<Elf<'a>> {
+        if data.len() < HEADER_SIZE || data[..4] != MAGIC {
+            return Err(invalid("not an ELF file"));
+        } else if data[4] != 2 || data[5] != 1 {
+            return Err(invalid("not a little-endian 64-bit ELF file"));
+        } else if read_u16(data, 18)? != EM_AARCH64 {
+            return Err(invalid("not an AArch64 executable"));
+        } else if read_u16(data, 16)? != ET_DYN {
+            return Err(invalid("not a position-independent executable"));
+        }
+
+        let entry = read_u64(data, 24)?;
+        let phoff = read_u64(data, 32)? as usize;
+        let phentsize = read_u16(data, 54)? as usize;
+        let phnum = read_u16(data, 56)? as usize;
+        if phentsize < PHDR_SIZE {
+            return Err(invalid("program headers are too small"));
+        }
+
+        let mut segments = Vec::new();
+        let mut dynamic = None;
+        for i in 0..phnum {
+            let at = phoff.checked_add(i * phentsize).ok_or(invalid("ELF file is truncated"))?;
+            let kind = read_u32(data, at)?;
+            let segment = Segment {
+                offset: read_u64(data, at + 8)?,
+                vaddr: read_u64(data, at + 16)?,
+                file_size: read_u64(data, at + 32)?,
+                mem_size: read_u64(data, at + 40)?,
+            };
+
+            if kind != PT_LOAD && kind != PT_DYNAMIC {
+                continue;
+            } else if segment.file_size > segment.mem_size
+                || segment.offset.checked_add(segment.file_size)
+                    .map_or(true, |end| end > data.len() as u64)
+                || segment.vaddr.checked_add(segment.mem_size).is_none()
+            {
+                return Err(invalid("segment doesn't fit in the file"));
+            }
+
+            match kind {
+                PT_LOAD => segments.push(segment),
+                _ => dynamic = Some(segment),
+            }
+        }
+
+        let elf = Elf { data, entry, segments, dynamic };
+        let (start, end) = elf.span().ok_or(invalid("executable has no loadable segments"))?;
+        if entry < start || entry >= end {
+            return Err(invalid("entry point is outside the executable"));
+        }
+
+        Ok(elf)
+    }
+
+    /// Returns the lowest and one past the highest address the loadable
+    /// segments are linked at, or `None` if there are none.
+    pub fn span(&self) -> Option<(u64, u64)> {
+        let start = self.segments.iter().map(|segment| segment.vaddr).min()?;
+        let end = self.segments.iter().map(|segment| segment.vaddr + segment.mem_size).max()?;
+        Some((start, end))
+    }
+
+    /// Loads the executable into `image`, which is at address `base` and must
+    /// hold at least the span of the loadable segments: the segments are
+    /// copied to their offsets from the start of the span, the rest of
+    /// `image` is zeroed, and the relocations are applied for `base`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if the dynamic section or the
+    /// relocations it names are outside the image, or if there is a
+    /// relocation of a type other than `R_AARCH64_RELATIVE`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `image` is smaller than the span.
+    pub fn load(&self, image: &mut [u8], base: u64) -> io::Result<()> {
+        let (start, end) = self.span().expect("a parsed ELF file has loadable segments");
+        assert!(image.len() as u64 >= end - start, "image is smaller than the executable");
+
+        for byte in image.iter_mut() {
+            *byte = 0;
+        }
+
+        for segment in self.segments.iter() {
+            let to = (segment.vaddr - start) as usize;
+            let from = segment.offset as usize;
+            let size = segment.file_size as usize;
+            image[to..to + size].copy_from_slice(&self.data[from..from + size]);
+        }
+
+        match self.dynamic {
+            Some(dynamic) => relocate(image, start, base, dynamic),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Applies the relocations named by the dynamic section, `dynamic`, of the
+/// executable loaded into `image`, which is linked at `start` and loaded at
+/// `base`.
+fn relocate(image: &mut [u8], start: u64, base: u64, dynamic: Segment) -> io::Result<()> {
+    let offset = |address: u64| -> io::Result<usize> {
+        address.checked_sub(start).map(|offset| offset as usize)
+            .ok_or(invalid("address is outside the image"))
+    };
+
+    let (mut rela, mut rela_size, mut rela_entry) = (None, 0, RELA_SIZE as u64);
+    let mut at = offset(dynamic.vaddr)?;
+    loop {
+        let tag = read_u64(image, at)?;
+        let value = read_u64(image, at + 8)?;
+        match tag {
+            DT_NULL => break,
+            DT_RELA => rela = Some(value),
+            DT_RELASZ => rela_size = value,
+            DT_RELAENT => rela_entry = value,
+            _ => {}
+        }
+
+        at += DYN_SIZE;
+    }
+
+    let rela = match rela {
+        Some(rela) => offset(rela)?,
+        None => return Ok(()),
+    };
+
+    if (rela_entry as usize) < RELA_SIZE {
+        return Err(invalid("relocations are too small"));
+    }
+
+    let bias = base.wrapping_sub(start);
+    for i in 0..(rela_size / rela_entry) as usize {
+        let at = (i as u64).checked_mul(rela_entry)
+            .and_then(|offset| (rela as u64).checked_add(offset))
+            .ok_or(invalid("relocation is outside the image"))? as usize;
+        let target = read_u64(image, at)?;
+        let kind = read_u64(image, at + 8)? as u32;
+        let addend = read_u64(image, at + 16)?;
+        match kind {
+            R_AARCH64_NONE => {}
+            R_AARCH64_RELATIVE => write_u64(image, offset(target)?, bias.wrapping_add(addend))?,
+            _ => return Err(invalid("unsupported relocation type")),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,241 @@
+//! Shell variables: a small, fixed-capacity map from names to values, and the
+//! `$NAME` expansion the shell applies to each line before parsing it.
+
+use std::fmt;
+use std::str;
+
+use mutex::Mutex;
+
+/// The maximum number of variables that can be set at once.
+pub const MAX_VARS: usize = 16;
+
+/// The longest variable name, in bytes.
+pub const MAX_NAME: usize = 16;
+
+/// The longest variable value, in bytes.
+pub const MAX_VALUE: usize = 128;
+
+/// Ways setting or expanding variables can fail.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The name isn't a letter or `_` followed by letters, digits and `_`, or
+    /// is longer than `MAX_NAME` bytes.
+    InvalidName,
+    /// The value is longer than `MAX_VALUE` bytes.
+    ValueTooLong,
+    /// `MAX_VARS` variables are already set.
+    Full,
+    /// The expanded line doesn't fit in the buffer it is expanded into.
+    LineTooLong,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidName => write!(f, "invalid variable name"),
+            Error::ValueTooLong => write!(f, "value longer than {} bytes", MAX_VALUE),
+            Error::Full => write!(f, "too many variables"),
+            Error::LineTooLong => write!(f, "line too long"),
+        }
+    }
+}
+
+/// A variable.
+#[derive(Copy, Clone)]
+struct Var {
+    name: [u8; MAX_NAME],
+    name_len: usize,
+    value: [u8; MAX_VALUE],
+    value_len: usize,
+}
+
+/// An unused slot in the variable table.
+const UNSET: Var = Var { name: [0; MAX_NAME], name_len: 0, value: [0; MAX_VALUE], value_len: 0 };
+
+impl Var {
+    fn name(&self) -> &str {
+        str::from_utf8(&self.name[..self.name_len]).expect("names are ASCII")
+    }
+
+    fn value(&self) -> &str {
+        str::from_utf8(&self.value[..self.value_len]).expect("copied from a str")
+    }
+}
+
+/// Returns `true` if `byte` may start a variable name.
+fn is_name_start(byte: u8) -> bool {
+    (byte as char).is_ascii_alphabetic() || byte == b'_'
+}
+
+/// Returns `true` if `byte` may follow the first byte of a variable name.
+fn is_name_byte(byte: u8) -> bool {
+    (byte as char).is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// Returns `true` if `name` is a valid variable name.
+pub fn is_valid_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    !bytes.is_empty() && bytes.len() <= MAX_NAME
+        && is_name_start(bytes[0])
+        && bytes.iter().all(|&byte| is_name_byte(byte))
+}
+
+/// A fixed-capacity set of variables, in the order they were first set.
+pub struct Vars {
+    vars: [Var; MAX_VARS],
+    len: usize,
+}
+
+impl Vars {
+    /// Returns a new, empty set of variables.
+    pub const fn new() -> Vars {
+        Vars { vars: [UNSET; MAX_VARS], len: 0 }
+    }
+
+    /// Returns the number of variables set.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no variables are set.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the value of the variable `name`, if it is set.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars[..self.len].iter()
+            .find(|var| var.name() == name)
+            .map(|var| var.value())
+    }
+
+    /// Sets the variable `name` to `value`, replacing its value if it is
+    /// already set.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidName` if `name` isn't a valid name,
+    /// `Error::ValueTooLong` if `value` is longer than `MAX_VALUE` bytes, or
+    /// `Error::Full` if `name` isn't set and `MAX_VARS` variables are.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), Error> {
+        if !is_valid_name(name) {
+            return Err(Error::InvalidName);
+        }
+
+        if value.len() > MAX_VALUE {
+            return Err(Error::ValueTooLong);
+        }
+
+        let index = match self.vars[..self.len].iter().position(|var| var.name() == name) {
+            Some(index) => index,
+            None if self.len == MAX_VARS => return Err(Error::Full),
+            None => {
+                let var = &mut self.vars[self.len];
+                var.name[..name.len()].copy_from_slice(name.as_bytes());
+                var.name_len = name.len();
+                self.len += 1;
+                self.len - 1
+            }
+        };
+
+        let var = &mut self.vars[index];
+        var.value[..value.len()].copy_from_slice(value.as_bytes());
+        var.value_len = value.len();
+        Ok(())
+    }
+
+    /// Unsets the variable `name`. Returns `true` if it was set.
+    pub fn unset(&mut self, name: &str) -> bool {
+        let index = match self.vars[..self.len].iter().position(|var| var.name() == name) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        for i in index + 1..self.len {
+            self.vars[i - 1] = self.vars[i];
+        }
+
+        self.len -= 1;
+        true
+    }
+
+    /// Returns an iterator over the name and value of every variable set.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.vars[..self.len].iter().map(|var| (var.name(), var.value()))
+    }
+
+    /// Copies `line` into `out`, replacing each `$NAME` outside of single
+    /// quotes with the value of `NAME`, or with nothing if it isn't set.
+    /// Returns the length of the expanded line.
+    ///
+    /// Values are escaped so that the shell's parser reads them back as they
+    /// are: a value containing spaces or quotes stays part of one argument. A
+    /// `$` that isn't followed by a name, or that is escaped with a
+    /// backslash, is copied unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::LineTooLong` if the expanded line doesn't fit in
+    /// `out`.
+    pub fn expand(&self, line: &str, out: &mut [u8]) -> Result<usize, Error> {
+        let bytes = line.as_bytes();
+        let mut len = 0;
+        let mut quote = None;
+        let mut i = 0;
+        while i < bytes.len() {
+            let byte = bytes[i];
+            match (quote, byte) {
+                (Some(b'\''), b'\'') => quote = None,
+                (Some(b'\''), _) => {  }
+                // Copy escapes whole so that an escaped `$` isn't expanded.
+                (_, b'\\') if i + 1 < bytes.len() => {
+                    push(out, &mut len, byte)?;
+                    i += 1;
+                }
+                (None, b'"') | (None, b'\'') => quote = Some(byte),
+                (Some(b'"'), b'"') => quote = None,
+                (_, b'$') if i + 1 < bytes.len() && is_name_start(bytes[i + 1]) => {
+                    let end = bytes[i + 1..].iter()
+                        .position(|&byte| !is_name_byte(byte))
+                        .map_or(bytes.len(), |n| i + 1 + n);
+                    let value = self.get(&line[i + 1..end]).unwrap_or("");
+                    for &byte in value.as_bytes() {
+                        let special = match quote {
+                            None => byte == b' ' || byte == b'"' || byte == b'\'' || byte == b'\\',
+                            _ => byte == b'"' || byte == b'\\',
+                        };
+
+                        if special {
+                            push(out, &mut len, b'\\')?;
+                        }
+
+                        push(out, &mut len, byte)?;
+                    }
+
+                    i = end;
+                    continue;
+                }
+                _ => {  }
+            }
+
+            push(out, &mut len, bytes[i])?;
+            i += 1;
+        }
+
+        Ok(len)
+    }
+}
+
+/// Appends `byte` to the first `*len` bytes of `out`.
+fn push(out: &mut [u8], len: &mut usize, byte: u8) -> Result<(), Error> {
+    match out.get_mut(*len) {
+        Some(slot) => *slot = byte,
+        None => return Err(Error::LineTooLong),
+    }
+
+    *len += 1;
+    Ok(())
+}
+
+/// The shell's variables.
+pub static VARS: Mutex<Vars> = Mutex::new(Vars::new());
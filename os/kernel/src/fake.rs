@@ -0,0 +1,353 @@
+//! Host stand-ins for the hardware the kernel touches directly.
+//!
+//! These are only compiled when the kernel is _not_ built for the Raspberry
+//! Pi, which is the case when running `cargo test` on a desktop. Each type
+//! mirrors the surface of the `pi` driver it replaces. All state is kept per
+//! thread so that tests running in parallel don't observe each other.
+
+use std::io;
+use std::fmt;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+
+use pi::Error;
+use pi::framebuffer::Info;
+
+thread_local! {
+    static UART_INPUT: RefCell<VecDeque<u8>> = RefCell::new(VecDeque::new());
+    static UART_OUTPUT: RefCell<Vec<u8>> = RefCell::new(Vec::new());
+    static CLOCK: Cell<u64> = Cell::new(0);
+    static COOKIE: Cell<u64> = Cell::new(0);
+}
+
+/// An in-memory UART with the same interface as `pi::uart::MiniUart`.
+///
+/// Bytes queued with `fake::push_input()` are returned by reads; bytes written
+/// are accumulated and can be retrieved with `fake::take_output()`.
+pub struct FakeUart {
+    timeout: Option<u32>,
+}
+
+impl FakeUart {
+    /// Returns a new `FakeUart` with no read timeout.
+    pub fn new() -> FakeUart {
+        FakeUart { timeout: None }
+    }
+
+    /// Set the read timeout to `milliseconds` milliseconds.
+    pub fn set_read_timeout(&mut self, milliseconds: u32) {
+        self.timeout = Some(milliseconds);
+    }
+
+    /// Appends `byte` to the output buffer.
+    pub fn write_byte(&mut self, byte: u8) {
+        UART_OUTPUT.with(|output| output.borrow_mut().push(byte));
+    }
+
+    /// Returns `true` if there is at least one queued input byte.
+    pub fn has_byte(&self) -> bool {
+        UART_INPUT.with(|input| !input.borrow().is_empty())
+    }
+
+    /// Returns `Ok(())` if a byte is queued. If no byte is queued and a read
+    /// timeout is set, the fake clock is advanced by the timeout and
+    /// `Err(Error::Timeout)` is returned.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no byte is queued and no timeout is set: on the host, such a
+    /// call would block forever.
+    pub fn wait_for_byte(&self) -> Result<(), Error> {
+        if self.has_byte() {
+            return Ok(());
+        }
+
+        match self.timeout {
+            Some(ms) => {
+                timer::advance(ms as u64 * 1000);
+                Err(Error::Timeout)
+            }
+            None => panic!("FakeUart::wait_for_byte(): no input queued"),
+        }
+    }
+
+    /// Pops the next queued input byte.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no input is queued.
+    pub fn read_byte(&mut self) -> u8 {
+        UART_INPUT.with(|input| input.borrow_mut().pop_front())
+            .expect("FakeUart::read_byte(): no input queued")
+    }
+}
+
+impl fmt::Write for FakeUart {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+
+            self.write_byte(byte);
+        }
+
+        Ok(())
+    }
+}
+
+impl io::Read for FakeUart {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.wait_for_byte().is_err() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut,
+                                      "Timeout waiting for data"));
+        }
+
+        let mut bytes_read = 0;
+        while self.has_byte() && bytes_read < buf.len() {
+            buf[bytes_read] = self.read_byte();
+            bytes_read += 1;
+        }
+
+        Ok(bytes_read)
+    }
+}
+
+impl io::Write for FakeUart {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            self.write_byte(byte);
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Queues `bytes` to be read from any `FakeUart` on this thread.
+pub fn push_input(bytes: &[u8]) {
+    UART_INPUT.with(|input| input.borrow_mut().extend(bytes.iter().cloned()));
+}
+
+/// Removes and returns everything written to any `FakeUart` on this thread.
+pub fn take_output() -> Vec<u8> {
+    UART_OUTPUT.with(|output| ::std::mem::replace(&mut *output.borrow_mut(), Vec::new()))
+}
+
+/// Returns 0: host tests run as if on the first core.
+pub fn core_id() -> u64 {
+    0
+}
+
+/// IRQ masking with the same interface as `hw::irq`. There are no interrupts
+/// on the host; whether they would be masked is tracked per thread.
+pub mod irq {
+    use std::cell::Cell;
+
+    thread_local! {
+        static MASKED: Cell<bool> = Cell::new(false);
+    }
+
+    /// Masks IRQs. Returns 1 if they were already masked and 0 otherwise.
+    pub fn save_and_disable() -> u64 {
+        MASKED.with(|masked| masked.replace(true) as u64)
+    }
+
+    /// Restores the state `saved` returned by `save_and_disable()`.
+    pub fn restore(saved: u64) {
+        MASKED.with(|masked| masked.set(saved != 0));
+    }
+
+    /// Returns `true` if IRQs are masked.
+    pub fn is_masked() -> bool {
+        MASKED.with(|masked| masked.get())
+    }
+}
+
+/// A fake clock with the same interface as `pi::timer`.
+///
+/// Time only moves when `set()` or `advance()` is called, or when one of the
+/// sleep functions is called, which advance the clock by the requested amount
+/// and return immediately.
+pub mod timer {
+    use std::time::Duration;
+
+    use super::CLOCK;
+
+    /// Returns the current fake time.
+    pub fn current_time() -> Duration {
+        let us = current_time_us();
+        Duration::new(us / 1_000_000, (us % 1_000_000) as u32 * 1000)
+    }
+
+    /// Returns the current fake time in microseconds.
+    pub fn current_time_us() -> u64 {
+        CLOCK.with(|clock| clock.get())
+    }
+
+    /// Sets the fake time to `us` microseconds.
+    pub fn set(us: u64) {
+        CLOCK.with(|clock| clock.set(us));
+    }
+
+    /// Advances the fake time by `us` microseconds.
+    pub fn advance(us: u64) {
+        CLOCK.with(|clock| clock.set(clock.get() + us));
+    }
+
+    /// Advances the fake time by `duration`, rounded down to a microsecond.
+    pub fn spin_sleep(duration: Duration) {
+        advance(duration.as_secs() * 1_000_000 + (duration.subsec_nanos() / 1000) as u64);
+    }
+
+    /// Advances the fake time by `us` microseconds.
+    pub fn spin_sleep_us(us: u64) {
+        advance(us);
+    }
+
+    /// Advances the fake time by `ms` milliseconds.
+    pub fn spin_sleep_ms(ms: u64) {
+        advance(ms * 1000);
+    }
+}
+
+/// In-memory GPIO pins with the same interface as `hw::gpio`.
+///
+/// Every pin starts as a low input. A pin reads back the level last written
+/// to it, whatever its function.
+pub mod gpio {
+    use std::cell::RefCell;
+
+    pub use pi::gpio::{Function, MAX_PIN};
+    use pi::{Error, Result};
+
+    thread_local! {
+        static PINS: RefCell<[(Function, bool); MAX_PIN as usize + 1]> =
+            RefCell::new([(Function::Input, false); MAX_PIN as usize + 1]);
+    }
+
+    /// Runs `f` on pin `pin`'s function and level.
+    fn with_pin<T, F: FnOnce(&mut (Function, bool)) -> T>(pin: u8, f: F) -> Result<T> {
+        if pin > MAX_PIN {
+            return Err(Error::InvalidPin(pin));
+        }
+
+        Ok(PINS.with(|pins| f(&mut pins.borrow_mut()[pin as usize])))
+    }
+
+    /// Selects `function` for pin `pin`.
+    pub fn select(pin: u8, function: Function) -> Result<()> {
+        with_pin(pin, |state| state.0 = function)
+    }
+
+    /// Returns the function currently selected for pin `pin`.
+    pub fn function(pin: u8) -> Result<Function> {
+        with_pin(pin, |state| state.0)
+    }
+
+    /// Sets pin `pin`'s level to `level`.
+    pub fn write(pin: u8, level: bool) -> Result<()> {
+        with_pin(pin, |state| state.1 = level)
+    }
+
+    /// Returns pin `pin`'s level.
+    pub fn read(pin: u8) -> Result<bool> {
+        with_pin(pin, |state| state.1)
+    }
+}
+
+/// A watchdog with the same interface as `pi::pm::Watchdog` that never fires.
+pub struct FakeWatchdog {
+    timeout_ms: Option<u32>,
+}
+
+impl FakeWatchdog {
+    /// The longest timeout the watchdog supports, in milliseconds.
+    pub const MAX_TIMEOUT_MS: u32 = 15999;
+
+    /// Returns a new, stopped `FakeWatchdog`.
+    pub fn new() -> FakeWatchdog {
+        FakeWatchdog { timeout_ms: None }
+    }
+
+    /// Starts (or restarts) the watchdog with a timeout of `timeout_ms`.
+    pub fn start(&mut self, timeout_ms: u32) {
+        self.timeout_ms = Some(timeout_ms);
+    }
+
+    /// Restarts the watchdog with the timeout it was last started with.
+    pub fn pet(&mut self) {  }
+
+    /// Stops the watchdog.
+    pub fn stop(&mut self) {
+        self.timeout_ms = None;
+    }
+
+    /// Returns the timeout the watchdog was last started with, or 0 if it is
+    /// stopped.
+    pub fn remaining_ms(&self) -> u32 {
+        self.timeout_ms.unwrap_or(0)
+    }
+}
+
+/// A framebuffer with the same interface as `pi::framebuffer::Framebuffer`.
+/// There is no display, so none can be allocated.
+pub struct FakeFramebuffer {
+    info: Info,
+}
+
+impl FakeFramebuffer {
+    /// Fails with `Error::MailboxFailed`, as the firmware does when no display
+    /// is connected.
+    pub fn new(_width: u32, _height: u32) -> Result<FakeFramebuffer, Error> {
+        Err(Error::MailboxFailed)
+    }
+
+    /// Returns the framebuffer's geometry and location.
+    pub fn info(&self) -> Info {
+        self.info
+    }
+
+    /// Returns the framebuffer's pixels.
+    pub fn into_pixels(self) -> &'static mut [u32] {
+        &mut []
+    }
+}
+
+/// Stands in for `pi::pm::reboot()`. There is no board to reset, so this
+/// panics.
+pub fn reboot() -> ! {
+    panic!("reboot requested")
+}
+
+/// Stands in for `pi::pm::halt()`. There is no core to stop, so this panics.
+pub fn halt() -> ! {
+    panic!("halt requested")
+}
+
+/// Stands in for `pi::quiesce()`. There are no peripherals to reset.
+pub fn quiesce() {  }
+
+/// Stands in for `hw::relocate()`. There is no image to jump to, so this
+/// panics.
+pub fn relocate(_: usize, _: usize, _: usize, _: usize) -> ! {
+    panic!("relocation requested")
+}
+
+/// A reboot cookie with the same interface as `hw::cookie`.
+pub mod cookie {
+    use super::COOKIE;
+
+    /// Returns the current cookie value.
+    pub fn read() -> u64 {
+        COOKIE.with(|cookie| cookie.get())
+    }
+
+    /// Sets the cookie value to `value`.
+    pub fn write(value: u64) {
+        COOKIE.with(|cookie| cookie.set(value));
+    }
+}
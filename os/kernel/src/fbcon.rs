@@ -0,0 +1,485 @@
+//! A text console drawn on a framebuffer, so that console output can be read
+//! on an HDMI display as well as over the UART.
+//!
+//! `Screen` renders bytes the way a terminal would. Printable ASCII is drawn
+//! in 8x16 cells using an 8x8 font with each row doubled, the text scrolls up
+//! once it reaches the bottom, and the escape sequences the kernel writes
+//! (colors, erasing a line, moving the cursor) are interpreted, not drawn.
+//! Other UTF-8 characters are drawn as `?`.
+
+use std::fmt;
+
+use hw::PixelOrder;
+
+/// The size of a character cell in pixels.
+pub const CELL_WIDTH: usize = 8;
+pub const CELL_HEIGHT: usize = 16;
+
+/// The height of the underline marking the cursor, in pixels.
+const CURSOR_HEIGHT: usize = 2;
+
+/// The distance between tab stops, in columns.
+const TAB_WIDTH: usize = 8;
+
+/// The most parameters of an escape sequence that are kept. Later ones are
+/// ignored.
+const MAX_PARAMS: usize = 4;
+
+const ESC: u8 = 0x1B;
+
+/// The ANSI colors, as `(r, g, b)`, in SGR order: black, red, green, yellow,
+/// blue, magenta, cyan and white.
+const COLORS: [(u8, u8, u8); 8] = [
+    (0x00, 0x00, 0x00), (0xAA, 0x00, 0x00), (0x00, 0xAA, 0x00), (0xAA, 0x55, 0x00),
+    (0x00, 0x00, 0xAA), (0xAA, 0x00, 0xAA), (0x00, 0xAA, 0xAA), (0xAA, 0xAA, 0xAA),
+];
+
+/// The colors bold text is drawn in.
+const BRIGHT_COLORS: [(u8, u8, u8); 8] = [
+    (0x55, 0x55, 0x55), (0xFF, 0x55, 0x55), (0x55, 0xFF, 0x55), (0xFF, 0xFF, 0x55),
+    (0x55, 0x55, 0xFF), (0xFF, 0x55, 0xFF), (0x55, 0xFF, 0xFF), (0xFF, 0xFF, 0xFF),
+];
+
+/// The colors text is drawn in after an SGR reset, as indices into `COLORS`.
+const DEFAULT_FG: usize = 7;
+const DEFAULT_BG: usize = 0;
+
+/// Where the parser is in an escape sequence.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Escape {
+    /// Not in one.
+    Ground,
+    /// After `ESC`.
+    Escape,
+    /// After `ESC [`, reading parameters until the final byte.
+    Csi,
+}
+
+/// A grid of character cells drawn on a framebuffer, with a cursor where the
+/// next character goes.
+pub struct Screen<'a> {
+    pixels: &'a mut [u32],
+    /// The distance between the starts of consecutive pixel rows, in pixels.
+    stride: usize,
+    order: PixelOrder,
+    columns: usize,
+    rows: usize,
+    col: usize,
+    row: usize,
+    /// A character was drawn in the last column. The cursor stays there, and
+    /// the next character is drawn at the start of the next line.
+    wrap_pending: bool,
+    fg: usize,
+    bg: usize,
+    bold: bool,
+    dim: bool,
+    escape: Escape,
+    params: [u16; MAX_PARAMS],
+    param_count: usize,
+    /// The number of continuation bytes left in the UTF-8 character being
+    /// skipped.
+    continuation: u8,
+}
+
+impl<'a> Screen<'a> {
+    /// Returns a cleared screen over the `width` by `height` pixel image in
+    /// `pixels`, whose rows start `stride` pixels apart. Partial cells at the
+    /// right and bottom edges are left unused.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pixels` is smaller than the image or the image is smaller
+    /// than one cell.
+    pub fn new(pixels: &'a mut [u32], width: usize, height: usize, stride: usize,
+               order: PixelOrder) -> Screen<'a> {
+        assert!(width <= stride && pixels.len() >= stride * height, "pixels don't fit the image");
+        assert!(width >= CELL_WIDTH && height >= CELL_HEIGHT, "image smaller than a cell");
+
+        let mut screen = Screen {
+            pixels, stride, order,
+            columns: width / CELL_WIDTH,
+            rows: height / CELL_HEIGHT,
+            col: 0,
+            row: 0,
+            wrap_pending: false,
+            fg: DEFAULT_FG,
+            bg: DEFAULT_BG,
+            bold: false,
+            dim: false,
+            escape: Escape::Ground,
+            params: [0; MAX_PARAMS],
+            param_count: 0,
+            continuation: 0,
+        };
+
+        let rows = screen.rows;
+        screen.clear_rows(0, rows);
+        screen.toggle_cursor();
+        screen
+    }
+
+    /// Returns the number of columns of text.
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    /// Returns the number of rows of text.
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the cursor position as `(column, row)`.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.col, self.row)
+    }
+
+    /// Writes `byte` at the cursor.
+    pub fn write_byte(&mut self, byte: u8) {
+        self.toggle_cursor();
+        self.feed(byte);
+        self.toggle_cursor();
+    }
+
+    /// Writes `bytes` at the cursor.
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.toggle_cursor();
+        for &byte in bytes {
+            self.feed(byte);
+        }
+
+        self.toggle_cursor();
+    }
+
+    /// Interprets `byte`. The cursor must be hidden.
+    fn feed(&mut self, byte: u8) {
+        match self.escape {
+            Escape::Ground => self.feed_ground(byte),
+            Escape::Escape if byte == b'[' => {
+                self.escape = Escape::Csi;
+                self.params = [0; MAX_PARAMS];
+                self.param_count = 0;
+            }
+            Escape::Escape => self.escape = Escape::Ground,
+            Escape::Csi => match byte {
+                b'0'...b'9' => {
+                    if let Some(param) = self.params.get_mut(self.param_count) {
+                        *param = param.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                    }
+                }
+                b';' => self.param_count += 1,
+                0x40...0x7E => {
+                    self.escape = Escape::Ground;
+                    let count = (self.param_count + 1).min(MAX_PARAMS);
+                    let params = self.params;
+                    self.dispatch(byte, &params[..count]);
+                }
+                // Private markers and intermediate bytes: nothing the
+                // kernel writes uses them.
+                _ => {  }
+            },
+        }
+    }
+
+    fn feed_ground(&mut self, byte: u8) {
+        if self.continuation > 0 && byte & 0xC0 == 0x80 {
+            self.continuation -= 1;
+            return;
+        }
+
+        self.continuation = 0;
+        let (col, row) = (self.col, self.row);
+        match byte {
+            ESC => self.escape = Escape::Escape,
+            b'\n' => self.newline(),
+            b'\r' => self.move_to(0, row),
+            0x08 => self.move_to(col.saturating_sub(1), row),
+            b'\t' => self.move_to((col / TAB_WIDTH + 1) * TAB_WIDTH, row),
+            0x20...0x7E => self.put(byte),
+            0xC0...0xF7 => {
+                self.continuation = match byte {
+                    0xC0...0xDF => 1,
+                    0xE0...0xEF => 2,
+                    _ => 3,
+                };
+
+                self.put(b'?');
+            }
+            _ => {  }
+        }
+    }
+
+    /// Runs the CSI sequence ending in `command` with parameters `params`.
+    fn dispatch(&mut self, command: u8, params: &[u16]) {
+        // The parameter at `i`, where 0 and missing parameters mean 1.
+        let count = |i: usize| params.get(i).map_or(1, |&n| n.max(1) as usize);
+        let (col, row) = (self.col, self.row);
+        let (columns, rows) = (self.columns, self.rows);
+        match command {
+            b'm' => for &param in params {
+                self.select_graphic_rendition(param);
+            },
+            b'K' => match params[0] {
+                0 => self.clear_cells(row, col, columns),
+                1 => self.clear_cells(row, 0, col + 1),
+                _ => self.clear_cells(row, 0, columns),
+            },
+            b'J' => match params[0] {
+                0 => {
+                    self.clear_cells(row, col, columns);
+                    self.clear_rows(row + 1, rows);
+                }
+                1 => {
+                    self.clear_rows(0, row);
+                    self.clear_cells(row, 0, col + 1);
+                }
+                _ => self.clear_rows(0, rows),
+            },
+            b'A' => self.move_to(col, row.saturating_sub(count(0))),
+            b'B' => self.move_to(col, row + count(0)),
+            b'C' => self.move_to(col + count(0), row),
+            b'D' => self.move_to(col.saturating_sub(count(0)), row),
+            b'H' | b'f' => self.move_to(count(1) - 1, count(0) - 1),
+            _ => {  }
+        }
+    }
+
+    /// Applies the SGR parameter `param`.
+    fn select_graphic_rendition(&mut self, param: u16) {
+        match param {
+            0 => {
+                self.fg = DEFAULT_FG;
+                self.bg = DEFAULT_BG;
+                self.bold = false;
+                self.dim = false;
+            }
+            1 => self.bold = true,
+            2 => self.dim = true,
+            22 => {
+                self.bold = false;
+                self.dim = false;
+            }
+            30...37 => self.fg = (param - 30) as usize,
+            39 => self.fg = DEFAULT_FG,
+            40...47 => self.bg = (param - 40) as usize,
+            49 => self.bg = DEFAULT_BG,
+            _ => {  }
+        }
+    }
+
+    /// Moves the cursor to `(col, row)`, clamped to the screen.
+    fn move_to(&mut self, col: usize, row: usize) {
+        self.col = col.min(self.columns - 1);
+        self.row = row.min(self.rows - 1);
+        self.wrap_pending = false;
+    }
+
+    /// Moves the cursor to the start of the next line, scrolling if it is on
+    /// the last.
+    fn newline(&mut self) {
+        self.col = 0;
+        self.wrap_pending = false;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    /// Draws the printable ASCII character `byte` at the cursor and advances
+    /// it.
+    fn put(&mut self, byte: u8) {
+        if self.wrap_pending {
+            self.newline();
+        }
+
+        self.draw_glyph(byte);
+        if self.col + 1 < self.columns {
+            self.col += 1;
+        } else {
+            self.wrap_pending = true;
+        }
+    }
+
+    /// Returns the pixel for the color at `index`.
+    fn pixel(&self, index: usize, bold: bool, dim: bool) -> u32 {
+        let (mut r, mut g, mut b) = if bold { BRIGHT_COLORS[index] } else { COLORS[index] };
+        if dim {
+            r /= 2;
+            g /= 2;
+            b /= 2;
+        }
+
+        self.order.pack(r, g, b)
+    }
+
+    /// Returns the index of the first pixel of the cell at `(col, row)`.
+    fn cell_start(&self, col: usize, row: usize) -> usize {
+        row * CELL_HEIGHT * self.stride + col * CELL_WIDTH
+    }
+
+    fn draw_glyph(&mut self, byte: u8) {
+        let glyph = &FONT[(byte - 0x20) as usize];
+        let fg = self.pixel(self.fg, self.bold, self.dim);
+        let bg = self.pixel(self.bg, false, false);
+        let start = self.cell_start(self.col, self.row);
+        for y in 0..CELL_HEIGHT {
+            let bits = glyph[y / 2];
+            let line = start + y * self.stride;
+            for x in 0..CELL_WIDTH {
+                self.pixels[line + x] = if bits & (1 << x) != 0 { fg } else { bg };
+            }
+        }
+    }
+
+    /// Clears the cells in columns `from..to` of `row` to the background
+    /// color.
+    fn clear_cells(&mut self, row: usize, from: usize, to: usize) {
+        let bg = self.pixel(self.bg, false, false);
+        let (start, end) = (self.cell_start(from, row), self.cell_start(to, row));
+        for y in 0..CELL_HEIGHT {
+            let offset = y * self.stride;
+            for pixel in self.pixels[start + offset..end + offset].iter_mut() {
+                *pixel = bg;
+            }
+        }
+    }
+
+    /// Clears rows `from..to` to the background color.
+    fn clear_rows(&mut self, from: usize, to: usize) {
+        let columns = self.columns;
+        for row in from..to {
+            self.clear_cells(row, 0, columns);
+        }
+    }
+
+    /// Moves every row up one, dropping the first, and clears the last.
+    fn scroll(&mut self) {
+        let line = CELL_HEIGHT * self.stride;
+        for i in line..self.rows * line {
+            self.pixels[i - line] = self.pixels[i];
+        }
+
+        let (columns, last) = (self.columns, self.rows - 1);
+        self.clear_cells(last, 0, columns);
+    }
+
+    /// Shows the cursor if it is hidden, and hides it if it is shown, by
+    /// inverting an underline in its cell.
+    fn toggle_cursor(&mut self) {
+        let start = self.cell_start(self.col, self.row);
+        for y in CELL_HEIGHT - CURSOR_HEIGHT..CELL_HEIGHT {
+            let line = start + y * self.stride;
+            for pixel in self.pixels[line..line + CELL_WIDTH].iter_mut() {
+                *pixel ^= 0x00FFFFFF;
+            }
+        }
+    }
+}
+
+impl<'a> fmt::Write for Screen<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// The glyphs for `' '` through `'~'`, from the public domain font8x8 by
+/// Daniel Hepper. Each byte is a row, top first, with the leftmost pixel in
+/// the least significant bit.
+const FONT: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x18, 0x3C, 0x3C, 0x18, 0x18, 0x00, 0x18, 0x00], // !
+    [0x36, 0x36, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x36, 0x36, 0x7F, 0x36, 0x7F, 0x36, 0x36, 0x00], // #
+    [0x0C, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x0C, 0x00], // $
+    [0x00, 0x63, 0x33, 0x18, 0x0C, 0x66, 0x63, 0x00], // %
+    [0x1C, 0x36, 0x1C, 0x6E, 0x3B, 0x33, 0x6E, 0x00], // &
+    [0x06, 0x06, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x18, 0x0C, 0x06, 0x06, 0x06, 0x0C, 0x18, 0x00], // (
+    [0x06, 0x0C, 0x18, 0x18, 0x18, 0x0C, 0x06, 0x00], // )
+    [0x00, 0x66, 0x3C, 0xFF, 0x3C, 0x66, 0x00, 0x00], // *
+    [0x00, 0x0C, 0x0C, 0x3F, 0x0C, 0x0C, 0x00, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ,
+    [0x00, 0x00, 0x00, 0x3F, 0x00, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // .
+    [0x60, 0x30, 0x18, 0x0C, 0x06, 0x03, 0x01, 0x00], // /
+    [0x3E, 0x63, 0x73, 0x7B, 0x6F, 0x67, 0x3E, 0x00], // 0
+    [0x0C, 0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x3F, 0x00], // 1
+    [0x1E, 0x33, 0x30, 0x1C, 0x06, 0x33, 0x3F, 0x00], // 2
+    [0x1E, 0x33, 0x30, 0x1C, 0x30, 0x33, 0x1E, 0x00], // 3
+    [0x38, 0x3C, 0x36, 0x33, 0x7F, 0x30, 0x78, 0x00], // 4
+    [0x3F, 0x03, 0x1F, 0x30, 0x30, 0x33, 0x1E, 0x00], // 5
+    [0x1C, 0x06, 0x03, 0x1F, 0x33, 0x33, 0x1E, 0x00], // 6
+    [0x3F, 0x33, 0x30, 0x18, 0x0C, 0x0C, 0x0C, 0x00], // 7
+    [0x1E, 0x33, 0x33, 0x1E, 0x33, 0x33, 0x1E, 0x00], // 8
+    [0x1E, 0x33, 0x33, 0x3E, 0x30, 0x18, 0x0E, 0x00], // 9
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x00], // :
+    [0x00, 0x0C, 0x0C, 0x00, 0x00, 0x0C, 0x0C, 0x06], // ;
+    [0x18, 0x0C, 0x06, 0x03, 0x06, 0x0C, 0x18, 0x00], // <
+    [0x00, 0x00, 0x3F, 0x00, 0x00, 0x3F, 0x00, 0x00], // =
+    [0x06, 0x0C, 0x18, 0x30, 0x18, 0x0C, 0x06, 0x00], // >
+    [0x1E, 0x33, 0x30, 0x18, 0x0C, 0x00, 0x0C, 0x00], // ?
+    [0x3E, 0x63, 0x7B, 0x7B, 0x7B, 0x03, 0x1E, 0x00], // @
+    [0x0C, 0x1E, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x00], // A
+    [0x3F, 0x66, 0x66, 0x3E, 0x66, 0x66, 0x3F, 0x00], // B
+    [0x3C, 0x66, 0x03, 0x03, 0x03, 0x66, 0x3C, 0x00], // C
+    [0x1F, 0x36, 0x66, 0x66, 0x66, 0x36, 0x1F, 0x00], // D
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x46, 0x7F, 0x00], // E
+    [0x7F, 0x46, 0x16, 0x1E, 0x16, 0x06, 0x0F, 0x00], // F
+    [0x3C, 0x66, 0x03, 0x03, 0x73, 0x66, 0x7C, 0x00], // G
+    [0x33, 0x33, 0x33, 0x3F, 0x33, 0x33, 0x33, 0x00], // H
+    [0x1E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // I
+    [0x78, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E, 0x00], // J
+    [0x67, 0x66, 0x36, 0x1E, 0x36, 0x66, 0x67, 0x00], // K
+    [0x0F, 0x06, 0x06, 0x06, 0x46, 0x66, 0x7F, 0x00], // L
+    [0x63, 0x77, 0x7F, 0x7F, 0x6B, 0x63, 0x63, 0x00], // M
+    [0x63, 0x67, 0x6F, 0x7B, 0x73, 0x63, 0x63, 0x00], // N
+    [0x1C, 0x36, 0x63, 0x63, 0x63, 0x36, 0x1C, 0x00], // O
+    [0x3F, 0x66, 0x66, 0x3E, 0x06, 0x06, 0x0F, 0x00], // P
+    [0x1E, 0x33, 0x33, 0x33, 0x3B, 0x1E, 0x38, 0x00], // Q
+    [0x3F, 0x66, 0x66, 0x3E, 0x36, 0x66, 0x67, 0x00], // R
+    [0x1E, 0x33, 0x07, 0x0E, 0x38, 0x33, 0x1E, 0x00], // S
+    [0x3F, 0x2D, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // T
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x33, 0x3F, 0x00], // U
+    [0x33, 0x33, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // V
+    [0x63, 0x63, 0x63, 0x6B, 0x7F, 0x77, 0x63, 0x00], // W
+    [0x63, 0x63, 0x36, 0x1C, 0x1C, 0x36, 0x63, 0x00], // X
+    [0x33, 0x33, 0x33, 0x1E, 0x0C, 0x0C, 0x1E, 0x00], // Y
+    [0x7F, 0x63, 0x31, 0x18, 0x4C, 0x66, 0x7F, 0x00], // Z
+    [0x1E, 0x06, 0x06, 0x06, 0x06, 0x06, 0x1E, 0x00], // [
+    [0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x40, 0x00], // \
+    [0x1E, 0x18, 0x18, 0x18, 0x18, 0x18, 0x1E, 0x00], // ]
+    [0x08, 0x1C, 0x36, 0x63, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF], // _
+    [0x0C, 0x0C, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00], // `
+    [0x00, 0x00, 0x1E, 0x30, 0x3E, 0x33, 0x6E, 0x00], // a
+    [0x07, 0x06, 0x06, 0x3E, 0x66, 0x66, 0x3B, 0x00], // b
+    [0x00, 0x00, 0x1E, 0x33, 0x03, 0x33, 0x1E, 0x00], // c
+    [0x38, 0x30, 0x30, 0x3E, 0x33, 0x33, 0x6E, 0x00], // d
+    [0x00, 0x00, 0x1E, 0x33, 0x3F, 0x03, 0x1E, 0x00], // e
+    [0x1C, 0x36, 0x06, 0x0F, 0x06, 0x06, 0x0F, 0x00], // f
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x1F], // g
+    [0x07, 0x06, 0x36, 0x6E, 0x66, 0x66, 0x67, 0x00], // h
+    [0x0C, 0x00, 0x0E, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // i
+    [0x30, 0x00, 0x30, 0x30, 0x30, 0x33, 0x33, 0x1E], // j
+    [0x07, 0x06, 0x66, 0x36, 0x1E, 0x36, 0x67, 0x00], // k
+    [0x0E, 0x0C, 0x0C, 0x0C, 0x0C, 0x0C, 0x1E, 0x00], // l
+    [0x00, 0x00, 0x33, 0x7F, 0x7F, 0x6B, 0x63, 0x00], // m
+    [0x00, 0x00, 0x1F, 0x33, 0x33, 0x33, 0x33, 0x00], // n
+    [0x00, 0x00, 0x1E, 0x33, 0x33, 0x33, 0x1E, 0x00], // o
+    [0x00, 0x00, 0x3B, 0x66, 0x66, 0x3E, 0x06, 0x0F], // p
+    [0x00, 0x00, 0x6E, 0x33, 0x33, 0x3E, 0x30, 0x78], // q
+    [0x00, 0x00, 0x3B, 0x6E, 0x66, 0x06, 0x0F, 0x00], // r
+    [0x00, 0x00, 0x3E, 0x03, 0x1E, 0x30, 0x1F, 0x00], // s
+    [0x08, 0x0C, 0x3E, 0x0C, 0x0C, 0x2C, 0x18, 0x00], // t
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x33, 0x6E, 0x00], // u
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x1E, 0x0C, 0x00], // v
+    [0x00, 0x00, 0x63, 0x6B, 0x7F, 0x7F, 0x36, 0x00], // w
+    [0x00, 0x00, 0x63, 0x36, 0x1C, 0x36, 0x63, 0x00], // x
+    [0x00, 0x00, 0x33, 0x33, 0x33, 0x3E, 0x30, 0x1F], // y
+    [0x00, 0x00, 0x3F, 0x19, 0x0C, 0x26, 0x3F, 0x00], // z
+    [0x38, 0x0C, 0x0C, 0x07, 0x0C, 0x0C, 0x38, 0x00], // {
+    [0x18, 0x18, 0x18, 0x00, 0x18, 0x18, 0x18, 0x00], // |
+    [0x07, 0x0C, 0x0C, 0x38, 0x0C, 0x0C, 0x07, 0x00], // }
+    [0x6E, 0x3B, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ~
+];
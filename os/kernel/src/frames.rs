@@ -0,0 +1,207 @@
+//! The physical frame allocator: hands out memory a 4KiB page at a time, for
+//! page tables and DMA buffers, which need whole pages rather than heap bytes.
+//!
+//! The frames are the memory past the heap. Which of them are in use is kept
+//! in a bitmap stored in the first frames of the region itself.
+
+use std::slice;
+
+use allocator::util::{align_down, align_up};
+use console::kassert;
+use mutex::Mutex;
+
+/// The size of a frame in bytes.
+pub const PAGE_SIZE: usize = 4096;
+
+/// The number of bits in a bitmap word.
+const WORD_BITS: usize = 64;
+
+/// A bitmap allocator over a run of frames.
+pub struct FrameAllocator<'a> {
+    /// The address of the first frame.
+    base: usize,
+    /// The number of frames.
+    frames: usize,
+    /// Bit `i` is set while frame `i` is allocated.
+    bitmap: &'a mut [u64],
+    free: usize,
+}
+
+impl<'a> FrameAllocator<'a> {
+    /// Returns an allocator over the `frames` frames from `base`, with all of
+    /// them free, keeping track of them in `bitmap`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `base` isn't page aligned or `bitmap` is too small.
+    pub fn new(base: usize, frames: usize, bitmap: &'a mut [u64]) -> FrameAllocator<'a> {
+        kassert!(base % PAGE_SIZE == 0, "frames must be page aligned");
+        kassert!(bitmap.len() * WORD_BITS >= frames, "bitmap too small");
+        for word in bitmap.iter_mut() {
+            *word = 0;
+        }
+
+        FrameAllocator { base, frames, bitmap, free: frames }
+    }
+
+    /// Returns the address of the first frame.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Returns the number of frames managed.
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Returns the start and end of the frames managed.
+    pub fn region(&self) -> (usize, usize) {
+        (self.base, self.base + self.frames * PAGE_SIZE)
+    }
+
+    /// Returns the number of frames not allocated.
+    pub fn free_frames(&self) -> usize {
+        self.free
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / WORD_BITS] & (1 << (frame % WORD_BITS)) != 0
+    }
+
+    fn set_used(&mut self, frame: usize, used: bool) {
+        let bit = 1 << (frame % WORD_BITS);
+        if used {
+            self.bitmap[frame / WORD_BITS] |= bit;
+        } else {
+            self.bitmap[frame / WORD_BITS] &= !bit;
+        }
+    }
+
+    /// Allocates a frame and returns its address, or `None` if every frame
+    /// is in use.
+    pub fn alloc(&mut self) -> Option<usize> {
+        let word = self.bitmap.iter().position(|&word| word != !0)?;
+        let frame = word * WORD_BITS + (!self.bitmap[word]).trailing_zeros() as usize;
+        if frame >= self.frames {
+            return None;
+        }
+
+        self.set_used(frame, true);
+        self.free -= 1;
+        Some(self.base + frame * PAGE_SIZE)
+    }
+
+    /// Allocates `count` consecutive frames starting at an address that is a
+    /// multiple of `align` bytes, and returns that address. Returns `None` if
+    /// there is no such run of free frames.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of 2.
+    pub fn alloc_contiguous(&mut self, count: usize, align: usize) -> Option<usize> {
+        let align = align.max(PAGE_SIZE);
+        let mut start = align_up(self.base, align);
+        loop {
+            let first = (start - self.base) / PAGE_SIZE;
+            if count == 0 || first.checked_add(count)? > self.frames {
+                return None;
+            }
+
+            let last_used = (first..first + count).rev().find(|&frame| self.is_used(frame));
+            match last_used {
+                Some(used) => start = align_up(self.base + (used + 1) * PAGE_SIZE, align),
+                None => {
+                    for frame in first..first + count {
+                        self.set_used(frame, true);
+                    }
+
+                    self.free -= count;
+                    return Some(start);
+                }
+            }
+        }
+    }
+
+    /// Frees the frame at `addr`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `addr` isn't the address of an allocated frame.
+    pub fn free(&mut self, addr: usize) {
+        let frame = addr.wrapping_sub(self.base) / PAGE_SIZE;
+        kassert!(addr >= self.base && frame < self.frames && addr % PAGE_SIZE == 0,
+                 "{:#x} is not a frame", addr);
+        kassert!(self.is_used(frame), "frame {:#x} freed twice", addr);
+
+        self.set_used(frame, false);
+        self.free += 1;
+    }
+}
+
+/// Returns an allocator over the frames from `start` to `end`, with its
+/// bitmap in the first of them.
+///
+/// # Safety
+///
+/// The memory from `start` to `end` must be unused for as long as the
+/// allocator lives.
+pub unsafe fn in_region(start: usize, end: usize) -> FrameAllocator<'static> {
+    let start = align_up(start, PAGE_SIZE);
+    let total = align_down(end, PAGE_SIZE).saturating_sub(start) / PAGE_SIZE;
+    let words = (total + WORD_BITS - 1) / WORD_BITS;
+    let bitmap_frames = (words * 8 + PAGE_SIZE - 1) / PAGE_SIZE;
+
+    let bitmap = slice::from_raw_parts_mut(start as *mut u64, words);
+    let frames = total.saturating_sub(bitmap_frames);
+    FrameAllocator::new(start + bitmap_frames * PAGE_SIZE, frames, bitmap)
+}
+
+/// The kernel's frame allocator.
+static FRAMES: Mutex<Option<FrameAllocator<'static>>> = Mutex::new(None);
+
+/// Initializes the frame allocator with the memory past the heap.
+///
+/// # Panics
+///
+/// Panics if the ATAGs describe no memory past the kernel.
+#[cfg(target_arch = "aarch64")]
+pub fn initialize() {
+    use allocator::{self, HEAP_SIZE};
+
+    let (start, end) = allocator::memory_map().expect("no memory for frames");
+    let start = start.saturating_add(HEAP_SIZE).min(end);
+    *FRAMES.lock() = Some(unsafe { in_region(start, end) });
+}
+
+/// Allocates a frame and returns its address, or `None` if there are none
+/// left or the frame allocator isn't initialized.
+pub fn alloc_frame() -> Option<usize> {
+    FRAMES.lock().as_mut().and_then(|frames| frames.alloc())
+}
+
+/// Allocates `count` consecutive frames starting at a multiple of `align`
+/// bytes and returns their address; see `FrameAllocator::alloc_contiguous()`.
+pub fn alloc_contiguous(count: usize, align: usize) -> Option<usize> {
+    FRAMES.lock().as_mut().and_then(|frames| frames.alloc_contiguous(count, align))
+}
+
+/// Frees the frame at `addr`.
+///
+/// # Panics
+///
+/// Panics if `addr` isn't the address of an allocated frame.
+pub fn free_frame(addr: usize) {
+    FRAMES.lock().as_mut().expect("frame allocator uninitialized").free(addr);
+}
+
+/// Returns the start and end of the frames the frame allocator manages, or
+/// `None` if it isn't initialized.
+pub fn region() -> Option<(usize, usize)> {
+    FRAMES.lock().as_ref().map(|frames| frames.region())
+}
+
+/// Returns the number of free frames and the number of frames in all, or
+/// `None` if the frame allocator isn't initialized.
+pub fn usage() -> Option<(usize, usize)> {
+    FRAMES.lock().as_ref().map(|frames| (frames.free_frames(), frames.frames()))
+}
@@ -0,0 +1,323 @@
+//! The kernel's virtual file system: a table of file systems mounted at
+//! paths. Paths are resolved against the mount with the longest matching
+//! prefix, and the rest of the path is passed on to that file system. The
+//! first FAT12, FAT16 or FAT32 partition of the SD card is mounted at `/` at
+//! boot, and an in-memory file system at `/tmp`.
+
+#[cfg(target_arch = "aarch64")]
+pub mod sd;
+pub mod node;
+pub mod tmpfs;
+
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use fat32::traits::{self, BlockDevice};
+use fat32::vfat::{self, FatType, Report, Shared, VFat};
+
+use mutex::Mutex;
+
+pub use self::node::{File, Dir, Entry, DirIter};
+pub use self::tmpfs::TmpFs;
+
+/// A file system that can be mounted.
+#[derive(Clone)]
+pub enum Backend {
+    Fat32(Shared<VFat>),
+    Tmpfs(TmpFs),
+}
+
+impl Backend {
+    /// The name of the kind of file system, as `mount` lists it.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            Backend::Fat32(ref vfat) => match vfat.borrow().fat_type() {
+                FatType::Fat12 => "fat12",
+                FatType::Fat16 => "fat16",
+                FatType::Fat32 => "fat32",
+            },
+            Backend::Tmpfs(_) => "tmpfs",
+        }
+    }
+
+    /// Writes everything the file system has cached back to its device.
+    pub fn sync(&self) -> io::Result<()> {
+        match *self {
+            Backend::Fat32(ref vfat) => vfat.borrow_mut().sync(),
+            Backend::Tmpfs(_) => Ok(()),
+        }
+    }
+
+    /// Checks the file system for inconsistencies, repairing the trivial ones
+    /// if `repair` is `true`. See `VFat::check()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` for a tmpfs, which has nothing to check,
+    /// besides the errors of `VFat::check()`.
+    pub fn check(&self, repair: bool) -> io::Result<Report> {
+        match *self {
+            Backend::Fat32(ref vfat) => VFat::check(vfat, repair),
+            Backend::Tmpfs(_) => {
+                Err(io::Error::new(io::ErrorKind::Other, "only FAT file systems can be checked"))
+            }
+        }
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Entry> {
+        match *self {
+            Backend::Fat32(ref vfat) => Ok(Entry::from(traits::FileSystem::open(vfat, path)?)),
+            Backend::Tmpfs(ref tmpfs) => Ok(Entry::from(traits::FileSystem::open(tmpfs, path)?)),
+        }
+    }
+
+    fn create_file(&self, path: &Path) -> io::Result<File> {
+        match *self {
+            Backend::Fat32(ref vfat) => {
+                Ok(File::Fat32(traits::FileSystem::create_file(vfat, path)?))
+            }
+            Backend::Tmpfs(ref tmpfs) => {
+                Ok(File::Tmpfs(traits::FileSystem::create_file(tmpfs, path)?))
+            }
+        }
+    }
+
+    fn create_dir(&self, path: &Path, parents: bool) -> io::Result<Dir> {
+        match *self {
+            Backend::Fat32(ref vfat) => {
+                Ok(Dir::Fat32(traits::FileSystem::create_dir(vfat, path, parents)?))
+            }
+            Backend::Tmpfs(ref tmpfs) => {
+                Ok(Dir::Tmpfs(traits::FileSystem::create_dir(tmpfs, path, parents)?))
+            }
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        match *self {
+            Backend::Fat32(ref vfat) => traits::FileSystem::rename(vfat, from, to),
+            Backend::Tmpfs(ref tmpfs) => traits::FileSystem::rename(tmpfs, from, to),
+        }
+    }
+
+    fn remove(&self, path: &Path, children: bool) -> io::Result<()> {
+        match *self {
+            Backend::Fat32(ref vfat) => traits::FileSystem::remove(vfat, path, children),
+            Backend::Tmpfs(ref tmpfs) => traits::FileSystem::remove(tmpfs, path, children),
+        }
+    }
+}
+
+/// A file system and the path it is mounted at.
+struct Mount {
+    path: PathBuf,
+    backend: Backend,
+}
+
+/// The mount table. Every operation resolves its path to a mounted file
+/// system first, and fails with `NotFound` if none is mounted there.
+pub struct FileSystem(Mutex<Option<Vec<Mount>>>);
+
+/// Returns `path` with `.` and `..` components resolved, without looking at
+/// any file system. `..` at the root stays at the root.
+///
+/// # Errors
+///
+/// Returns an error of `InvalidInput` if `path` isn't absolute.
+pub fn normalize(path: &Path) -> io::Result<PathBuf> {
+    if !path.is_absolute() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "path is not absolute"));
+    }
+
+    let mut normalized = PathBuf::from("/");
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => normalized.push(name),
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            _ => continue,
+        }
+    }
+
+    Ok(normalized)
+}
+
+impl FileSystem {
+    /// Returns a mount table with nothing mounted. Every operation fails
+    /// with `NotFound` until something is.
+    pub const fn uninitialized() -> FileSystem {
+        FileSystem(Mutex::new(None))
+    }
+
+    /// Mounts the first FAT partition of the SD card at `/`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the card can't be initialized or holds no valid
+    /// FAT partition.
+    #[cfg(target_arch = "aarch64")]
+    pub fn initialize(&self) -> Result<(), vfat::Error> {
+        self.initialize_with(sd::Sd::new()?)
+    }
+
+    /// Mounts the first FAT partition of `device` at `/`, replacing whatever
+    /// was mounted there before.
+    pub fn initialize_with<T>(&self, device: T) -> Result<(), vfat::Error>
+        where T: BlockDevice + 'static
+    {
+        let backend = Backend::Fat32(VFat::from(device)?);
+        let _ = self.unmount("/");
+        self.mount("/", backend).expect("nothing is mounted at /");
+        Ok(())
+    }
+
+    /// Mounts `backend` at `path`. The directory at `path`, if there is one,
+    /// is hidden until `backend` is unmounted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `path` isn't absolute and of
+    /// `AlreadyExists` if something is already mounted at `path`.
+    pub fn mount<P: AsRef<Path>>(&self, path: P, backend: Backend) -> io::Result<()> {
+        let path = normalize(path.as_ref())?;
+        let mut mounts = self.0.lock();
+        let mounts = mounts.get_or_insert_with(Vec::new);
+        if mounts.iter().any(|mount| mount.path == path) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "already mounted"));
+        }
+
+        mounts.push(Mount { path, backend });
+        Ok(())
+    }
+
+    /// Unmounts the file system mounted at `path` and returns it, after
+    /// writing back what it has cached.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `path` isn't absolute and of
+    /// `NotFound` if nothing is mounted at `path`.
+    pub fn unmount<P: AsRef<Path>>(&self, path: P) -> io::Result<Backend> {
+        let path = normalize(path.as_ref())?;
+        let backend = {
+            let mut mounts = self.0.lock();
+            let mounts = mounts.get_or_insert_with(Vec::new);
+            match mounts.iter().position(|mount| mount.path == path) {
+                Some(i) => mounts.remove(i).backend,
+                None => return Err(io::Error::new(io::ErrorKind::NotFound, "nothing is mounted")),
+            }
+        };
+
+        backend.sync()?;
+        Ok(backend)
+    }
+
+    /// Returns `true` if a file system is mounted at `/`.
+    pub fn is_mounted(&self) -> bool {
+        self.0.lock().as_ref().map_or(false, |mounts| {
+            mounts.iter().any(|mount| mount.path == Path::new("/"))
+        })
+    }
+
+    /// Returns the paths file systems are mounted at and their kinds, in the
+    /// order they were mounted.
+    pub fn mounts(&self) -> Vec<(PathBuf, &'static str)> {
+        match *self.0.lock() {
+            Some(ref mounts) => {
+                mounts.iter().map(|mount| (mount.path.clone(), mount.backend.kind())).collect()
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Writes everything every mounted file system has cached back to its
+    /// device. Stops at the first error.
+    pub fn sync(&self) -> io::Result<()> {
+        let backends: Vec<Backend> = match *self.0.lock() {
+            Some(ref mounts) => mounts.iter().map(|mount| mount.backend.clone()).collect(),
+            None => Vec::new(),
+        };
+
+        for backend in backends {
+            backend.sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// Checks the file system `path` is on; see `Backend::check()`.
+    pub fn check<P: AsRef<Path>>(&self, path: P, repair: bool) -> io::Result<Report> {
+        let (backend, _, _) = self.resolve(path.as_ref())?;
+        backend.check(repair)
+    }
+
+    /// Returns the file system `path` is on, the path it is mounted at, and
+    /// `path` relative to the file system's root. The lock is only held while
+    /// the mount is found, so that a file system operation doesn't mask IRQs
+    /// throughout.
+    fn resolve(&self, path: &Path) -> io::Result<(Backend, PathBuf, PathBuf)> {
+        let path = normalize(path)?;
+        let mounts = self.0.lock();
+        let mount = mounts.as_ref()
+            .and_then(|mounts| {
+                mounts.iter()
+                    .filter(|mount| path.starts_with(&mount.path))
+                    .max_by_key(|mount| mount.path.components().count())
+            })
+            .ok_or(io::Error::new(io::ErrorKind::NotFound, "no file system is mounted"))?;
+
+        let mut relative = PathBuf::from("/");
+        for component in path.components().skip(mount.path.components().count()) {
+            relative.push(component.as_os_str());
+        }
+
+        Ok((mount.backend.clone(), mount.path.clone(), relative))
+    }
+}
+
+impl<'a> traits::FileSystem for &'a FileSystem {
+    type File = File;
+    type Dir = Dir;
+    type Entry = Entry;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        let (backend, _, path) = self.resolve(path.as_ref())?;
+        backend.open(&path)
+    }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let (backend, _, path) = self.resolve(path.as_ref())?;
+        backend.create_file(&path)
+    }
+
+    fn create_dir<P>(self, path: P, parents: bool) -> io::Result<Self::Dir>
+        where P: AsRef<Path>
+    {
+        let (backend, _, path) = self.resolve(path.as_ref())?;
+        backend.create_dir(&path, parents)
+    }
+
+    /// Renames `from` to `to`, which must be on the same file system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `Other` if `from` and `to` are on different file
+    /// systems, besides the errors of the file system they are on.
+    fn rename<P, Q>(self, from: P, to: Q) -> io::Result<()>
+        where P: AsRef<Path>, Q: AsRef<Path>
+    {
+        let (backend, from_mount, from) = self.resolve(from.as_ref())?;
+        let (_, to_mount, to) = self.resolve(to.as_ref())?;
+        if from_mount != to_mount {
+            return Err(io::Error::new(io::ErrorKind::Other, "can't rename across file systems"));
+        }
+
+        backend.rename(&from, &to)
+    }
+
+    fn remove<P: AsRef<Path>>(self, path: P, children: bool) -> io::Result<()> {
+        let (backend, _, path) = self.resolve(path.as_ref())?;
+        backend.remove(&path, children)
+    }
+}
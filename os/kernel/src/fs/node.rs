@@ -0,0 +1,207 @@
+//! The files, directories and entries of the mounted file systems, whichever
+//! backend they come from.
+
+use std::io::{self, SeekFrom};
+
+use fat32::traits::{self, Dir as DirTrait};
+use fat32::vfat::{self, Metadata};
+
+use fs::tmpfs;
+
+/// A file on a mounted file system.
+#[derive(Debug)]
+pub enum File {
+    Fat32(vfat::File),
+    Tmpfs(tmpfs::File),
+}
+
+/// A directory on a mounted file system.
+#[derive(Debug)]
+pub enum Dir {
+    Fat32(vfat::Dir),
+    Tmpfs(tmpfs::Dir),
+}
+
+/// An entry of a directory on a mounted file system.
+#[derive(Debug)]
+pub enum Entry {
+    File(File),
+    Dir(Dir),
+}
+
+/// An iterator over the entries of a `Dir`.
+pub enum DirIter {
+    Fat32(<vfat::Dir as DirTrait>::Iter),
+    Tmpfs(tmpfs::DirIter),
+}
+
+impl From<vfat::Entry> for Entry {
+    fn from(entry: vfat::Entry) -> Entry {
+        match entry {
+            vfat::Entry::File(file) => Entry::File(File::Fat32(file)),
+            vfat::Entry::Dir(dir) => Entry::Dir(Dir::Fat32(dir)),
+        }
+    }
+}
+
+impl From<tmpfs::Entry> for Entry {
+    fn from(entry: tmpfs::Entry) -> Entry {
+        match entry {
+            tmpfs::Entry::File(file) => Entry::File(File::Tmpfs(file)),
+            tmpfs::Entry::Dir(dir) => Entry::Dir(Dir::Tmpfs(dir)),
+        }
+    }
+}
+
+impl File {
+    pub fn name(&self) -> &str {
+        match *self {
+            File::Fat32(ref file) => file.name(),
+            File::Tmpfs(ref file) => file.name(),
+        }
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        match *self {
+            File::Fat32(ref file) => file.metadata(),
+            File::Tmpfs(ref file) => file.metadata(),
+        }
+    }
+}
+
+impl traits::File for File {
+    fn sync(&mut self) -> io::Result<()> {
+        match *self {
+            File::Fat32(ref mut file) => traits::File::sync(file),
+            File::Tmpfs(ref mut file) => traits::File::sync(file),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match *self {
+            File::Fat32(ref file) => traits::File::size(file),
+            File::Tmpfs(ref file) => traits::File::size(file),
+        }
+    }
+}
+
+impl io::Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            File::Fat32(ref mut file) => io::Read::read(file, buf),
+            File::Tmpfs(ref mut file) => io::Read::read(file, buf),
+        }
+    }
+}
+
+impl io::Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            File::Fat32(ref mut file) => io::Write::write(file, buf),
+            File::Tmpfs(ref mut file) => io::Write::write(file, buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            File::Fat32(ref mut file) => io::Write::flush(file),
+            File::Tmpfs(ref mut file) => io::Write::flush(file),
+        }
+    }
+}
+
+impl io::Seek for File {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match *self {
+            File::Fat32(ref mut file) => io::Seek::seek(file, pos),
+            File::Tmpfs(ref mut file) => io::Seek::seek(file, pos),
+        }
+    }
+}
+
+impl Dir {
+    pub fn name(&self) -> &str {
+        match *self {
+            Dir::Fat32(ref dir) => dir.name(),
+            Dir::Tmpfs(ref dir) => dir.name(),
+        }
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        match *self {
+            Dir::Fat32(ref dir) => dir.metadata(),
+            Dir::Tmpfs(ref dir) => dir.metadata(),
+        }
+    }
+}
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = DirIter;
+
+    fn entries(&self) -> io::Result<DirIter> {
+        match *self {
+            Dir::Fat32(ref dir) => Ok(DirIter::Fat32(dir.entries()?)),
+            Dir::Tmpfs(ref dir) => Ok(DirIter::Tmpfs(dir.entries()?)),
+        }
+    }
+}
+
+impl Iterator for DirIter {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        match *self {
+            DirIter::Fat32(ref mut iter) => iter.next().map(Entry::from),
+            DirIter::Tmpfs(ref mut iter) => iter.next().map(Entry::from),
+        }
+    }
+}
+
+impl traits::Entry for Entry {
+    type File = File;
+    type Dir = Dir;
+    type Metadata = Metadata;
+
+    fn name(&self) -> &str {
+        match *self {
+            Entry::File(ref file) => file.name(),
+            Entry::Dir(ref dir) => dir.name(),
+        }
+    }
+
+    fn metadata(&self) -> &Metadata {
+        match *self {
+            Entry::File(ref file) => file.metadata(),
+            Entry::Dir(ref dir) => dir.metadata(),
+        }
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        match *self {
+            Entry::File(ref file) => Some(file),
+            Entry::Dir(_) => None,
+        }
+    }
+
+    fn as_dir(&self) -> Option<&Dir> {
+        match *self {
+            Entry::File(_) => None,
+            Entry::Dir(ref dir) => Some(dir),
+        }
+    }
+
+    fn into_file(self) -> Option<File> {
+        match self {
+            Entry::File(file) => Some(file),
+            Entry::Dir(_) => None,
+        }
+    }
+
+    fn into_dir(self) -> Option<Dir> {
+        match self {
+            Entry::File(_) => None,
+            Entry::Dir(dir) => Some(dir),
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! The SD card as a block device for the file system.
+//!
+//! The driver retries failed reads itself. When the card stops responding
+//! altogether, it is identified again and the read is tried once more before
+//! the error reaches the file system.
+
+use std::io;
+
+use fat32::traits::BlockDevice;
+use pi::emmc::{self, Emmc};
+
+/// The SD card in the Pi's slot.
+pub struct Sd(Emmc);
+
+impl Sd {
+    /// Initializes the controller and the card in the slot.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no card or it doesn't respond.
+    pub fn new() -> io::Result<Sd> {
+        Emmc::new().map(Sd).map_err(to_io_error)
+    }
+}
+
+/// Converts an error from the SD card driver into an I/O error.
+fn to_io_error(error: ::pi::Error) -> io::Error {
+    match error {
+        ::pi::Error::Timeout => io::Error::new(io::ErrorKind::TimedOut, "the SD card timed out"),
+        ::pi::Error::InvalidSector(_) => {
+            io::Error::new(io::ErrorKind::InvalidInput, "sector can't be addressed")
+        }
+        ::pi::Error::UnsupportedCard => {
+            io::Error::new(io::ErrorKind::Other, "the SD card isn't supported")
+        }
+        ::pi::Error::CardLost => {
+            io::Error::new(io::ErrorKind::TimedOut, "the SD card stopped responding")
+        }
+        _ => io::Error::new(io::ErrorKind::Other, "the SD card failed a command"),
+    }
+}
+
+impl BlockDevice for Sd {
+    fn sector_size(&self) -> u64 {
+        emmc::BlockDevice::sector_size(&self.0)
+    }
+
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        match emmc::BlockDevice::read_sector(&mut self.0, n, buf) {
+            Err(::pi::Error::CardLost) => {
+                self.0.reinit().map_err(to_io_error)?;
+                emmc::BlockDevice::read_sector(&mut self.0, n, buf).map_err(to_io_error)
+            }
+            result => result.map_err(to_io_error),
+        }
+    }
+
+    /// The driver can't write to the card yet.
+    fn write_sector(&mut self, _n: u64, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "the SD card is read only"))
+    }
+}
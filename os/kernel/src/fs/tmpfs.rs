@@ -0,0 +1,463 @@
+//! An in-memory file system. Its files and directories live on the kernel's
+//! heap and are lost at reboot; it is mounted at `/tmp` as scratch space.
+//!
+//! Entries carry the same `Metadata` as FAT entries, so that the VFS hands
+//! out one kind of metadata whichever file system an entry is on. As there is
+//! no clock to read, every entry is stamped with the FAT epoch.
+
+use std::cmp::min;
+use std::io::{self, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::vec;
+
+use fat32::traits::{self, FileSystem};
+use fat32::vfat::{Attributes, Metadata, Shared, Timestamp};
+
+/// The contents of a file, shared by every handle to it.
+#[derive(Debug)]
+struct FileNode {
+    data: Vec<u8>,
+    metadata: Metadata,
+}
+
+/// The entries of a directory, in the order they were created.
+#[derive(Debug)]
+struct DirNode {
+    entries: Vec<(String, Node)>,
+    metadata: Metadata,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    File(Shared<FileNode>),
+    Dir(Shared<DirNode>),
+}
+
+/// An in-memory file system. Clones refer to the same file system.
+#[derive(Debug, Clone)]
+pub struct TmpFs {
+    root: Shared<DirNode>,
+}
+
+/// A file on a `TmpFs`. A file that is removed while it is open stays
+/// readable and writable through its handles until they are dropped.
+#[derive(Debug)]
+pub struct File {
+    node: Shared<FileNode>,
+    name: String,
+    metadata: Metadata,
+    /// The offset reads and writes continue from.
+    position: u64,
+}
+
+/// A directory on a `TmpFs`.
+#[derive(Debug)]
+pub struct Dir {
+    node: Shared<DirNode>,
+    name: String,
+    metadata: Metadata,
+}
+
+/// An entry of a directory on a `TmpFs`.
+#[derive(Debug)]
+pub enum Entry {
+    File(File),
+    Dir(Dir),
+}
+
+/// An iterator over the entries of a `Dir`, as they were when `entries()`
+/// was called.
+#[derive(Debug)]
+pub struct DirIter(vec::IntoIter<Entry>);
+
+fn metadata(attributes: u8) -> Metadata {
+    Metadata {
+        attributes: Attributes::new(attributes),
+        created: Timestamp::EPOCH,
+        accessed: Timestamp::EPOCH,
+        modified: Timestamp::EPOCH,
+    }
+}
+
+impl Node {
+    /// Returns a handle to the node, named `name`.
+    fn entry(&self, name: &str) -> Entry {
+        match *self {
+            Node::File(ref node) => Entry::File(File {
+                node: node.clone(),
+                name: name.to_string(),
+                metadata: node.borrow().metadata.clone(),
+                position: 0,
+            }),
+            Node::Dir(ref node) => Entry::Dir(Dir {
+                node: node.clone(),
+                name: name.to_string(),
+                metadata: node.borrow().metadata.clone(),
+            }),
+        }
+    }
+}
+
+impl DirNode {
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|&(ref entry, _)| entry == name)
+    }
+}
+
+impl TmpFs {
+    /// Returns an empty file system.
+    pub fn new() -> TmpFs {
+        let root = DirNode { entries: Vec::new(), metadata: metadata(Attributes::DIRECTORY) };
+        TmpFs { root: Shared::new(root) }
+    }
+
+    /// Returns the node at `path` and its name. The root directory's name is
+    /// empty.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `path` isn't absolute or passes
+    /// through a file, and of `NotFound` if there is no entry at `path`.
+    fn node(&self, path: &Path) -> io::Result<(String, Node)> {
+        if !path.is_absolute() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "path is not absolute"));
+        }
+
+        // The directories leading to the current node, so that `..` can go
+        // back up.
+        let mut nodes = vec![(String::new(), Node::Dir(self.root.clone()))];
+        for component in path.components() {
+            match component {
+                Component::ParentDir => {
+                    if nodes.len() > 1 {
+                        nodes.pop();
+                    }
+                }
+                Component::Normal(name) => {
+                    let name = name.to_str().unwrap_or("");
+                    let node = match nodes.last() {
+                        Some(&(_, Node::Dir(ref dir))) => {
+                            let dir = dir.borrow();
+                            let node = dir.position(name).map(|i| dir.entries[i].1.clone());
+                            node
+                        }
+                        _ => {
+                            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                                      "path component is not a directory"));
+                        }
+                    };
+
+                    let node = node.ok_or(
+                        io::Error::new(io::ErrorKind::NotFound, "no such file or directory"))?;
+                    nodes.push((name.to_string(), node));
+                }
+                _ => continue,
+            }
+        }
+
+        Ok(nodes.pop().expect("the root directory is never popped"))
+    }
+
+    /// Returns the directory that will hold the entry at `path` and the name
+    /// of the entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if `path` isn't absolute, has no
+    /// final name, or its parent isn't an existing directory.
+    fn parent_of(&self, path: &Path) -> io::Result<(Shared<DirNode>, String)> {
+        if !path.is_absolute() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "path is not absolute"));
+        }
+
+        let name = path.file_name().and_then(|name| name.to_str()).ok_or(
+            io::Error::new(io::ErrorKind::InvalidInput, "path has no valid final name"))?;
+        let parent = path.parent().expect("an absolute path with a name has a parent");
+        match self.node(parent) {
+            Ok((_, Node::Dir(dir))) => Ok((dir, name.to_string())),
+            Ok((_, Node::File(_))) => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, "parent is not a directory"))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, "parent does not exist"))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Adds `node` to the directory that will hold `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `parent_of()`, and an error of `AlreadyExists`
+    /// if there is an entry at `path`.
+    fn insert(&self, path: &Path, node: Node) -> io::Result<Entry> {
+        let (dir, name) = self.parent_of(path)?;
+        let mut dir = dir.borrow_mut();
+        if dir.position(&name).is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+        }
+
+        let entry = node.entry(&name);
+        dir.entries.push((name, node));
+        Ok(entry)
+    }
+
+    /// Removes the entry at `path` from its directory and returns it.
+    fn unlink(&self, path: &Path) -> io::Result<Node> {
+        let (dir, name) = self.parent_of(path)?;
+        let mut dir = dir.borrow_mut();
+        match dir.position(&name) {
+            Some(i) => Ok(dir.entries.remove(i).1),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "no such file or directory")),
+        }
+    }
+}
+
+impl<'a> traits::FileSystem for &'a TmpFs {
+    type File = File;
+    type Dir = Dir;
+    type Entry = Entry;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        let (name, node) = self.node(path.as_ref())?;
+        Ok(node.entry(&name))
+    }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let node = FileNode { data: Vec::new(), metadata: metadata(Attributes::ARCHIVE) };
+        match self.insert(path.as_ref(), Node::File(Shared::new(node)))? {
+            Entry::File(file) => Ok(file),
+            Entry::Dir(_) => unreachable!("a file node was inserted"),
+        }
+    }
+
+    fn create_dir<P>(self, path: P, parents: bool) -> io::Result<Self::Dir>
+        where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        if parents {
+            let mut ancestor = PathBuf::from("/");
+            let parent = path.parent().unwrap_or(path);
+            for component in parent.components() {
+                ancestor.push(component.as_os_str());
+                match self.open(&ancestor) {
+                    Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                        self.create_dir(&ancestor, false)?;
+                    }
+                    _ => continue,
+                }
+            }
+        }
+
+        let node = DirNode { entries: Vec::new(), metadata: metadata(Attributes::DIRECTORY) };
+        match self.insert(path, Node::Dir(Shared::new(node)))? {
+            Entry::Dir(dir) => Ok(dir),
+            Entry::File(_) => unreachable!("a directory node was inserted"),
+        }
+    }
+
+    fn rename<P, Q>(self, from: P, to: Q) -> io::Result<()>
+        where P: AsRef<Path>, Q: AsRef<Path>
+    {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        if !from.is_absolute() || !to.is_absolute() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "path is not absolute"));
+        }
+
+        let (name, node) = self.node(from)?;
+        if name.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "can't rename the root directory"));
+        }
+
+        if let Node::Dir(_) = node {
+            if to.starts_with(from) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "can't move a directory into itself"));
+            }
+        }
+
+        let (dir, name) = self.parent_of(to)?;
+        if dir.borrow().position(&name).is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+        }
+
+        let node = self.unlink(from)?;
+        dir.borrow_mut().entries.push((name, node));
+        Ok(())
+    }
+
+    fn remove<P: AsRef<Path>>(self, path: P, children: bool) -> io::Result<()> {
+        let path = path.as_ref();
+        match self.node(path)? {
+            (_, Node::Dir(ref dir)) if !children && !dir.borrow().entries.is_empty() => {
+                return Err(io::Error::new(io::ErrorKind::Other, "directory is not empty"));
+            }
+            (ref name, _) if name.is_empty() => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "can't remove the root directory"));
+            }
+            _ => {}
+        }
+
+        self.unlink(path).map(|_| ())
+    }
+}
+
+impl File {
+    /// The name of the file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
+impl traits::File for File {
+    /// Does nothing: there is no device to write back to.
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.node.borrow().data.len() as u64
+    }
+}
+
+impl io::Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let node = self.node.borrow();
+        let start = min(self.position, node.data.len() as u64) as usize;
+        let len = min(buf.len(), node.data.len() - start);
+        buf[..len].copy_from_slice(&node.data[start..start + len]);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+impl io::Write for File {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut node = self.node.borrow_mut();
+        let start = self.position as usize;
+        let end = start + buf.len();
+        if node.data.len() < end {
+            node.data.resize(end, 0);
+        }
+
+        node.data[start..end].copy_from_slice(buf);
+        self.position = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for File {
+    /// Seeks to the offset `pos` in the file, which may be at most the
+    /// file's size.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidInput` if seeking would place the position
+    /// before the start or past the end of the file.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let size = self.node.borrow().data.len() as i64;
+        let position = match pos {
+            SeekFrom::Start(offset) => min(offset, i64::max_value() as u64) as i64,
+            SeekFrom::End(offset) => size.saturating_add(offset),
+            SeekFrom::Current(offset) => (self.position as i64).saturating_add(offset),
+        };
+
+        if position < 0 || position > size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek out of bounds"));
+        }
+
+        self.position = position as u64;
+        Ok(self.position)
+    }
+}
+
+impl Dir {
+    /// The name of the directory.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = DirIter;
+
+    fn entries(&self) -> io::Result<DirIter> {
+        let node = self.node.borrow();
+        let entries: Vec<Entry> = node.entries.iter()
+            .map(|&(ref name, ref node)| node.entry(name))
+            .collect();
+        Ok(DirIter(entries.into_iter()))
+    }
+}
+
+impl Iterator for DirIter {
+    type Item = Entry;
+
+    fn next(&mut self) -> Option<Entry> {
+        self.0.next()
+    }
+}
+
+impl traits::Entry for Entry {
+    type File = File;
+    type Dir = Dir;
+    type Metadata = Metadata;
+
+    fn name(&self) -> &str {
+        match *self {
+            Entry::File(ref file) => file.name(),
+            Entry::Dir(ref dir) => dir.name(),
+        }
+    }
+
+    fn metadata(&self) -> &Metadata {
+        match *self {
+            Entry::File(ref file) => file.metadata(),
+            Entry::Dir(ref dir) => dir.metadata(),
+        }
+    }
+
+    fn as_file(&self) -> Option<&File> {
+        match *self {
+            Entry::File(ref file) => Some(file),
+            Entry::Dir(_) => None,
+        }
+    }
+
+    fn as_dir(&self) -> Option<&Dir> {
+        match *self {
+            Entry::File(_) => None,
+            Entry::Dir(ref dir) => Some(dir),
+        }
+    }
+
+    fn into_file(self) -> Option<File> {
+        match self {
+            Entry::File(file) => Some(file),
+            Entry::Dir(_) => None,
+        }
+    }
+
+    fn into_dir(self) -> Option<Dir> {
+        match self {
+            Entry::File(_) => None,
+            Entry::Dir(dir) => Some(dir),
+        }
+    }
+}
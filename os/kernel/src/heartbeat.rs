@@ -0,0 +1,95 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use pi::gpio::{Gpio, Output};
+use pi::led::{Pattern, Step};
+
+use mutex::Mutex;
+use config::BOARD;
+use hw::timer;
+use syscall;
+
+/// The heartbeat pattern as `(level, duration)` steps with durations in
+/// microseconds: on 50ms, off 100ms, on 50ms, off 800ms.
+pub const PATTERN: [Step; 4] = [
+    (true, 50_000),
+    (false, 100_000),
+    (true, 50_000),
+    (false, 800_000),
+];
+
+/// How often the heartbeat thread advances the pattern, in milliseconds.
+const TICK_PERIOD_MS: u32 = 10;
+
+/// The double-blink heartbeat state machine: `PATTERN`, repeated.
+///
+/// The state machine is advanced with `tick(now)`, which returns the level the
+/// LED should be set to whenever that level changes.
+pub struct Heartbeat(Pattern);
+
+impl Heartbeat {
+    /// Returns a new heartbeat that starts its pattern on the first `tick()`.
+    pub fn new() -> Heartbeat {
+        Heartbeat(Pattern::new(&PATTERN))
+    }
+
+    /// Advances the pattern to time `now` in microseconds. Returns `Some` of
+    /// the new LED level if the level should change and `None` otherwise.
+    ///
+    /// If `now` is more than a full pattern period past the current step's
+    /// deadline, the pattern restarts at `now`.
+    pub fn tick(&mut self, now: u64) -> Option<bool> {
+        self.0.tick(now)
+    }
+}
+
+/// The global heartbeat and the LED it drives.
+static HEARTBEAT: Mutex<Option<(Heartbeat, Gpio<Output>)>> = Mutex::new(None);
+
+/// Set once the heartbeat has been suppressed by a panic.
+static SUPPRESSED: AtomicBool = AtomicBool::new(false);
+
+/// Starts the heartbeat on the LED at GPIO pin `pin`. The LED blinks once
+/// `run()` runs in a thread of its own.
+///
+/// # Errors
+///
+/// Returns `Err(())` if `pin` isn't a GPIO pin.
+pub fn start(pin: u8) -> Result<(), ()> {
+    let led = Gpio::new(pin).map_err(|_| ())?.into_output();
+    *HEARTBEAT.lock() = Some((Heartbeat::new(), led));
+    Ok(())
+}
+
+/// The heartbeat thread: advances the global heartbeat every
+/// `TICK_PERIOD_MS` milliseconds, forever, sleeping in between.
+pub extern "C" fn run() -> u32 {
+    loop {
+        tick(timer::current_time_us());
+        let _ = syscall::sleep(TICK_PERIOD_MS);
+    }
+}
+
+/// Advances the global heartbeat to time `now`.
+fn tick(now: u64) {
+    if SUPPRESSED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    if let Some((ref mut heartbeat, ref mut led)) = *HEARTBEAT.lock() {
+        if let Some(level) = heartbeat.tick(now) {
+            led.write(level);
+        }
+    }
+}
+
+/// Stops the heartbeat and turns its LED solid on so that a panic is
+/// distinguishable from a running kernel.
+///
+/// This function does not take the heartbeat lock, so it is safe to call from
+/// the panic handler.
+pub fn suppress() {
+    SUPPRESSED.store(true, Ordering::Relaxed);
+    if let Some(Ok(gpio)) = BOARD.heartbeat.map(Gpio::new) {
+        gpio.into_output().set();
+    }
+}
@@ -0,0 +1,98 @@
+//! Replacing the running kernel with one received over the console, so that a
+//! kernel under development can be reloaded without reflashing the SD card.
+//!
+//! The new image can't be received straight into place, since the running
+//! kernel is there. It is received into a staging area instead. `jump()` then
+//! copies it to `LOAD_ADDR` using a small trampoline from `init.S`, which runs
+//! from a copy placed past the staged image, and branches to it.
+
+use std::io;
+
+use xmodem::{trailer, Progress, Xmodem};
+
+use hw::{self, Uart, Watchdog};
+use watchdog;
+
+/// Where kernels are linked to run, and where the new image is copied to.
+pub const LOAD_ADDR: usize = 0x80000;
+
+/// Where the new image is received.
+pub const STAGING_ADDR: usize = 0x100_0000;
+
+/// The largest image that can be received: up to the staging area, so that the
+/// copy to `LOAD_ADDR` never overwrites the part of the image still to copy.
+pub const STAGING_CAPACITY: usize = STAGING_ADDR - LOAD_ADDR;
+
+/// The UART read timeout during a transfer in milliseconds. After each
+/// timeout, the receiver asks the sender to send the packet again.
+const READ_TIMEOUT_MS: u32 = 750;
+
+/// How long the new kernel has to start up and stop the watchdog before the
+/// board is reset, in milliseconds.
+const HANDOFF_WATCHDOG_MS: u32 = 15_000;
+
+/// Why receiving a new kernel failed.
+#[derive(Debug)]
+pub enum Error {
+    /// The XMODEM transfer failed.
+    Transfer(io::Error),
+    /// The received image doesn't match its trailer.
+    Verification(trailer::Error),
+}
+
+/// Returns the length of the image in the `received` bytes of a transfer. If
+/// `verify` is set, the transfer must end with a trailer that the image
+/// matches; otherwise the whole transfer, padding included, is the image.
+pub fn image_len(received: &[u8], verify: bool) -> Result<usize, trailer::Error> {
+    if verify {
+        trailer::verify(received)
+    } else {
+        Ok(received.len())
+    }
+}
+
+/// Returns the address the trampoline is copied to for a `len` byte image: the
+/// first 16-byte aligned address past the staged image.
+pub fn trampoline_addr(len: usize) -> usize {
+    (STAGING_ADDR + len + 15) & !15
+}
+
+/// Receives an image over the UART into the staging area and returns its
+/// length. `progress` is called as the transfer proceeds. If `verify` is set,
+/// the image must end with a trailer, and the sender is told whether it
+/// matched.
+///
+/// The caller must hold the console lock for the duration so that nothing else
+/// uses the UART.
+pub fn receive(verify: bool, progress: fn(Progress)) -> Result<usize, Error> {
+    let mut uart = Uart::new();
+    uart.set_read_timeout(READ_TIMEOUT_MS);
+
+    let storage = unsafe {
+        ::std::slice::from_raw_parts_mut(STAGING_ADDR as *mut u8, STAGING_CAPACITY)
+    };
+
+    let received = {
+        let mut receiver = Xmodem::new_with_progress(&mut uart, progress);
+        receiver.set_crc(true);
+        receiver.receive_all(&mut storage[..]).map_err(Error::Transfer)?
+    };
+
+    let result = image_len(&storage[..received], verify);
+    if verify {
+        let _ = trailer::write_verdict(&mut uart, result.is_ok());
+    }
+
+    result.map_err(Error::Verification)
+}
+
+/// Copies the `len` byte image in the staging area to `LOAD_ADDR` and jumps
+/// to it. The peripherals are handed over as the firmware left them, and the
+/// watchdog is armed so that a new kernel that hangs before taking it over
+/// resets the board.
+pub fn jump(len: usize) -> ! {
+    watchdog::disable();
+    hw::quiesce();
+    Watchdog::new().start(HANDOFF_WATCHDOG_MS);
+    hw::relocate(STAGING_ADDR, LOAD_ADDR, len, trampoline_addr(len))
+}
@@ -0,0 +1,192 @@
+//! The hardware the kernel touches directly.
+//!
+//! Kernel modules use the types and modules re-exported here instead of naming
+//! the `pi` drivers themselves. On the Raspberry Pi these are the real drivers;
+//! on any other target (`cargo test` on a desktop) they are the in-memory
+//! stand-ins from `fake`, which lets the pure-logic parts of the kernel be unit
+//! tested on the host.
+
+#[cfg(target_arch = "aarch64")]
+pub use pi::uart::MiniUart as Uart;
+#[cfg(target_arch = "aarch64")]
+pub use pi::timer;
+#[cfg(target_arch = "aarch64")]
+pub use pi::pm::Watchdog;
+#[cfg(target_arch = "aarch64")]
+pub use pi::pm::{halt, reboot};
+#[cfg(target_arch = "aarch64")]
+pub use pi::quiesce;
+#[cfg(target_arch = "aarch64")]
+pub use pi::framebuffer::Framebuffer;
+
+#[cfg(not(target_arch = "aarch64"))]
+pub use fake::gpio;
+#[cfg(not(target_arch = "aarch64"))]
+pub use fake::FakeUart as Uart;
+#[cfg(not(target_arch = "aarch64"))]
+pub use fake::timer;
+#[cfg(not(target_arch = "aarch64"))]
+pub use fake::FakeWatchdog as Watchdog;
+#[cfg(not(target_arch = "aarch64"))]
+pub use fake::{halt, reboot, quiesce, relocate};
+#[cfg(not(target_arch = "aarch64"))]
+pub use fake::cookie;
+#[cfg(not(target_arch = "aarch64"))]
+pub use fake::core_id;
+#[cfg(not(target_arch = "aarch64"))]
+pub use fake::irq;
+#[cfg(not(target_arch = "aarch64"))]
+pub use fake::FakeFramebuffer as Framebuffer;
+
+pub use pi::framebuffer::PixelOrder;
+
+/// Returns the number of the core the caller is running on, from the affinity
+/// level 0 field of `MPIDR_EL1`.
+#[cfg(target_arch = "aarch64")]
+pub fn core_id() -> u64 {
+    let mpidr: u64;
+    unsafe { asm!("mrs $0, mpidr_el1" : "=r"(mpidr) : : : "volatile"); }
+    mpidr & 0xFF
+}
+
+/// The mini UART's share of the IRQ handler: if its interrupt is pending,
+/// moves the bytes it received into the buffer the console reads from.
+#[cfg(target_arch = "aarch64")]
+pub fn handle_uart_irq() {
+    use pi::interrupt::{Controller, Interrupt};
+
+    if Controller::new().is_pending(Interrupt::Aux) {
+        pi::uart::handle_irq();
+    }
+}
+
+/// The ACT LED's pin on the firmware's GPIO expander. On the Pi 3 the LED
+/// isn't wired to a GPIO pin the ARM can drive.
+#[cfg(target_arch = "aarch64")]
+const ACT_LED_PIN: u32 = 130;
+
+/// Turns the ACT LED on or off, through the mailbox.
+#[cfg(target_arch = "aarch64")]
+pub fn set_act_led(on: bool) {
+    use pi::mailbox::{self, Message, TAG_SET_GPIO_STATE};
+
+    let mut message = Message::new();
+    message.push(TAG_SET_GPIO_STATE, &[ACT_LED_PIN, on as u32], 2);
+    let _ = mailbox::call(&mut message);
+}
+
+/// Masking IRQs on the calling core, through the I bit of `DAIF`.
+#[cfg(target_arch = "aarch64")]
+pub mod irq {
+    /// The I bit of `DAIF`: IRQs are masked while it is set.
+    const DAIF_I: u64 = 1 << 7;
+
+    /// Masks IRQs on the calling core. Returns the previous `DAIF` value, to
+    /// be passed to `restore()`.
+    pub fn save_and_disable() -> u64 {
+        let daif: u64;
+        unsafe { asm!("mrs $0, daif; msr daifset, #2" : "=r"(daif) : : "memory" : "volatile"); }
+        daif
+    }
+
+    /// Restores the `DAIF` value `saved` returned by `save_and_disable()`,
+    /// unmasking IRQs if they were unmasked then.
+    pub fn restore(saved: u64) {
+        unsafe { asm!("msr daif, $0" : : "r"(saved) : "memory" : "volatile"); }
+    }
+
+    /// Returns `true` if IRQs are masked on the calling core.
+    pub fn is_masked() -> bool {
+        let daif: u64;
+        unsafe { asm!("mrs $0, daif" : "=r"(daif) : : : "volatile"); }
+        daif & DAIF_I != 0
+    }
+}
+
+/// The trampoline in `init.S` that copies an image into place and branches
+/// to it.
+#[cfg(target_arch = "aarch64")]
+extern "C" {
+    static _relocate: u8;
+    static _relocate_end: u8;
+}
+
+/// Copies `len` bytes from `from` to `to` and branches to `to`. The copying is
+/// done by the trampoline in `init.S`, which is first copied to `trampoline`,
+/// an address outside of both ranges, so that it can't overwrite itself.
+#[cfg(target_arch = "aarch64")]
+pub fn relocate(from: usize, to: usize, len: usize, trampoline: usize) -> ! {
+    unsafe {
+        let start = &_relocate as *const u8;
+        let size = &_relocate_end as *const u8 as usize - start as usize;
+        ::std::ptr::copy_nonoverlapping(start, trampoline as *mut u8, size);
+        asm!("br $3"
+             : : "{x0}"(from), "{x1}"(to), "{x2}"(len), "r"(trampoline)
+             : "memory" : "volatile");
+        loop { asm!("wfe" :::: "volatile") }
+    }
+}
+
+/// A word of RAM that survives a warm reset.
+///
+/// The cookie lives in low memory above the ATAGS the firmware places at
+/// `0x100` and far below the kernel's stack, which grows down from `0x80000`.
+/// Neither the firmware nor the kernel's start-up code clears it.
+#[cfg(target_arch = "aarch64")]
+pub mod cookie {
+    use std::ptr;
+
+    /// The address of the cookie.
+    const COOKIE_ADDR: usize = 0x7000;
+
+    /// Returns the current cookie value.
+    pub fn read() -> u64 {
+        unsafe { ptr::read_volatile(COOKIE_ADDR as *const u64) }
+    }
+
+    /// Sets the cookie value to `value`.
+    pub fn write(value: u64) {
+        unsafe { ptr::write_volatile(COOKIE_ADDR as *mut u64, value) }
+    }
+}
+
+/// Pin-at-a-time access to the GPIO pins for code, like the shell, that picks
+/// pins at run time rather than holding typed `pi::gpio::Gpio` handles.
+#[cfg(target_arch = "aarch64")]
+pub mod gpio {
+    pub use pi::gpio::Function;
+
+    use pi::gpio::{Gpio, GpioBank};
+    use pi::Result;
+
+    /// Selects `function` for pin `pin`.
+    pub fn select(pin: u8, function: Function) -> Result<()> {
+        Gpio::new(pin)?.into_alt(function);
+        Ok(())
+    }
+
+    /// Returns the function currently selected for pin `pin`.
+    pub fn function(pin: u8) -> Result<Function> {
+        Ok(Gpio::new(pin)?.function())
+    }
+
+    /// Sets pin `pin`'s output level to `level`. The pin only drives the
+    /// level while it is an output.
+    pub fn write(pin: u8, level: bool) -> Result<()> {
+        Gpio::new(pin)?;
+        let mut bank = GpioBank::new(pin / 32)?;
+        if level {
+            bank.set(1 << (pin % 32));
+        } else {
+            bank.clear(1 << (pin % 32));
+        }
+
+        Ok(())
+    }
+
+    /// Returns pin `pin`'s level, whatever function is selected for it.
+    pub fn read(pin: u8) -> Result<bool> {
+        Gpio::new(pin)?;
+        Ok(GpioBank::new(pin / 32)?.read_levels() & (1 << (pin % 32)) != 0)
+    }
+}
@@ -0,0 +1,36 @@
+//! Interrupt context: whether the calling core is running an interrupt
+//! handler, so that work that might wait on the code it interrupted, like
+//! writing to the console, can be deferred instead.
+
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use hw;
+
+/// The number of cores.
+const CORES: usize = 4;
+
+/// How deeply each core is nested in interrupt handlers.
+static DEPTH: [AtomicUsize; CORES] = [ATOMIC_USIZE_INIT, ATOMIC_USIZE_INIT,
+                                      ATOMIC_USIZE_INIT, ATOMIC_USIZE_INIT];
+
+/// Returns the calling core's nesting depth.
+fn depth() -> &'static AtomicUsize {
+    &DEPTH[hw::core_id() as usize % CORES]
+}
+
+/// Marks the start of an interrupt handler on the calling core. The code that
+/// dispatches interrupts calls this before running a handler and `exit()`
+/// after it returns.
+pub fn enter() {
+    depth().fetch_add(1, Ordering::Relaxed);
+}
+
+/// Marks the end of an interrupt handler started with `enter()`.
+pub fn exit() {
+    depth().fetch_sub(1, Ordering::Relaxed);
+}
+
+/// Returns `true` if the calling core is running an interrupt handler.
+pub fn in_handler() -> bool {
+    depth().load(Ordering::Relaxed) != 0
+}
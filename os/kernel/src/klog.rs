@@ -0,0 +1,92 @@
+//! The kernel log: everything written with `kprint!` and the logging macros,
+//! kept in RAM so that output that scrolled by, or that was written before
+//! anyone was watching the console, can be replayed with `dmesg`.
+
+use std::fmt;
+
+use mutex::Mutex;
+
+/// The number of bytes of output the kernel log keeps.
+pub const LOG_SIZE: usize = 16 * 1024;
+
+/// A ring of the most recent `LOG_SIZE` bytes written to it. Once full, each
+/// write overwrites the oldest bytes.
+pub struct LogBuffer {
+    bytes: [u8; LOG_SIZE],
+    /// The number of bytes ever written.
+    written: u64,
+}
+
+impl LogBuffer {
+    /// Returns an empty log.
+    pub const fn new() -> LogBuffer {
+        LogBuffer { bytes: [0; LOG_SIZE], written: 0 }
+    }
+
+    /// Returns the number of bytes the log holds.
+    pub fn len(&self) -> usize {
+        if self.written < LOG_SIZE as u64 { self.written as usize } else { LOG_SIZE }
+    }
+
+    /// Returns `true` if the log holds nothing.
+    pub fn is_empty(&self) -> bool {
+        self.written == 0
+    }
+
+    /// Returns the number of bytes that have been overwritten.
+    pub fn overwritten(&self) -> u64 {
+        self.written - self.len() as u64
+    }
+
+    /// Appends `bytes`.
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.bytes[(self.written % LOG_SIZE as u64) as usize] = byte;
+            self.written += 1;
+        }
+    }
+
+    /// Forgets everything written.
+    pub fn clear(&mut self) {
+        self.written = 0;
+    }
+
+    /// Returns the log's contents, oldest first, in two parts: the second
+    /// follows the first.
+    pub fn contents(&self) -> (&[u8], &[u8]) {
+        let start = (self.written % LOG_SIZE as u64) as usize;
+        if self.written <= LOG_SIZE as u64 {
+            (&self.bytes[..start], &[])
+        } else {
+            (&self.bytes[start..], &self.bytes[..start])
+        }
+    }
+
+    /// Returns the lines in the log, oldest first, in two parts like
+    /// `contents()`. If the oldest line has been partly overwritten, what
+    /// remains of it is left out.
+    pub fn lines(&self) -> (&[u8], &[u8]) {
+        let (first, second) = self.contents();
+        if self.overwritten() == 0 {
+            return (first, second);
+        }
+
+        match first.iter().position(|&byte| byte == b'\n') {
+            Some(i) => (&first[i + 1..], second),
+            None => {
+                let skip = second.iter().position(|&byte| byte == b'\n').map_or(second.len(), |i| i + 1);
+                (&[], &second[skip..])
+            }
+        }
+    }
+}
+
+impl fmt::Write for LogBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// The kernel log.
+pub static KLOG: Mutex<LogBuffer> = Mutex::new(LogBuffer::new());
@@ -8,33 +8,162 @@
 #![feature(attr_literals)]
 #![feature(never_type)]
 #![feature(ptr_internals)]
+#![feature(conservative_impl_trait)]
+#![feature(slice_patterns)]
+#![feature(specialization)]
+#![feature(i128_type)]
+#![feature(alloc, allocator_api, global_allocator)]
 
 extern crate pi;
 extern crate stack_vec;
+extern crate xmodem;
+extern crate fat32;
 
+#[cfg(test)]
+mod tests;
+#[cfg(not(target_arch = "aarch64"))]
+pub mod fake;
+
+#[cfg(target_arch = "aarch64")]
 pub mod lang_items;
+pub mod hw;
+pub mod irq;
 pub mod mutex;
 pub mod console;
+pub mod klog;
+pub mod fbcon;
+pub mod line;
+pub mod env;
 pub mod shell;
+pub mod config;
+pub mod timer;
+pub mod heartbeat;
+pub mod watchdog;
+pub mod hotload;
+pub mod panic;
+pub mod allocator;
+pub mod frames;
+pub mod dma;
+pub mod memory;
+pub mod fs;
+pub mod elf;
+pub mod process;
+pub mod traps;
+pub mod scheduler;
+pub mod syscall;
 
 use pi::uart::MiniUart;
 use shell::shell;
-use console::{kprint, kprintln, CONSOLE};
+use console::{kprint, kprintln, style, info, warn, CONSOLE};
 use pi::gpio::Gpio;
+use config::BOARD;
+use fbcon::Screen;
+use hw::Framebuffer;
 
 use std::fmt::Write;
 
+use allocator::Allocator;
+use fs::{Backend, FileSystem, TmpFs};
+use process::Process;
+use scheduler::Scheduler;
+
+/// The kernel's heap. It is only the global allocator on the Pi; on the host,
+/// tests allocate from the host's heap and this one is never initialized.
+#[cfg_attr(target_arch = "aarch64", global_allocator)]
+pub static ALLOCATOR: Allocator = Allocator::uninitialized();
+
+/// The file systems the kernel has mounted. The first FAT partition of the
+/// SD card is mounted at `/` at boot.
+pub static FILESYSTEM: FileSystem = FileSystem::uninitialized();
+
+/// The kernel's scheduler. `kmain` adds the shell and the heartbeat to it as
+/// kernel threads, then starts it.
+pub static SCHEDULER: Scheduler = Scheduler::uninitialized();
+
+/// The shell's kernel thread. It runs `/boot/init.sh` first, if there is one.
+extern "C" fn run_shell() -> u32 {
+    shell::run_init_script();
+    loop {
+        shell("->");
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn kmain() {
     //let mut uart = MiniUart::new();
     //uart.set_read_timeout(100000);
+    #[cfg(target_arch = "aarch64")]
+    ALLOCATOR.initialize();
+    #[cfg(target_arch = "aarch64")]
+    frames::initialize();
+    #[cfg(target_arch = "aarch64")]
+    dma::initialize();
+
+    style::set_enabled(BOARD.color);
     kprintln!("OS,OS,OS");
-    let mut ready_led = Gpio::new(16).into_output();
-    ready_led.set();
-    shell("->");
-    //loop {
-    //    let temp = uart.read_byte();
-    //    uart.write_byte(temp);
-    //    uart.write_str("<-");
-    //}
+    if watchdog::init().is_err() {
+        warn!("failed to start the watchdog service");
+    } else if BOARD.watchdog {
+        watchdog::enable();
+    }
+
+    if watchdog::last_reboot() == watchdog::RebootReason::Watchdog {
+        info!("the previous boot was reset by the watchdog");
+    }
+
+    let heartbeat = match BOARD.heartbeat {
+        Some(pin) => {
+            let started = heartbeat::start(pin).is_ok();
+            if !started {
+                warn!("failed to start the heartbeat");
+            }
+
+            started
+        }
+        None => {
+            Gpio::new(16).expect("GPIO 16 exists").into_output().set();
+            false
+        }
+    };
+
+    #[cfg(target_arch = "aarch64")]
+    match FILESYSTEM.initialize() {
+        Ok(()) => info!("mounted the SD card's FAT partition at /"),
+        Err(e) => warn!("no file system: {:?}", e),
+    }
+
+    if FILESYSTEM.mount("/tmp", Backend::Tmpfs(TmpFs::new())).is_err() {
+        warn!("failed to mount a tmpfs at /tmp");
+    }
+
+    if let Some((width, height)) = BOARD.screen {
+        match Framebuffer::new(width, height) {
+            Ok(framebuffer) => {
+                let info = framebuffer.info();
+                memory::set_framebuffer(info.base, info.base + info.size);
+                let screen = Screen::new(framebuffer.into_pixels(), info.width as usize,
+                                         info.height as usize, info.pitch as usize / 4, info.order);
+                let (columns, rows) = (screen.columns(), screen.rows());
+                CONSOLE.lock().attach_screen(screen);
+                info!("console mirrored to a {}x{} character display", columns, rows);
+            }
+            Err(e) => warn!("no framebuffer console: {}", e),
+        }
+    }
+
+    let shell = Process::kernel_thread(run_shell).expect("a stack for the shell");
+    SCHEDULER.add(shell);
+    if heartbeat {
+        match Process::kernel_thread(heartbeat::run) {
+            Some(process) => {
+                SCHEDULER.add(process);
+            }
+            None => warn!("no stack for the heartbeat"),
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    CONSOLE.lock().enable_rx_interrupt();
+    #[cfg(target_arch = "aarch64")]
+    SCHEDULER.start();
 }
@@ -1,6 +1,9 @@
 #[lang = "eh_personality"] pub extern fn eh_personality() {}
 
-#[lang = "panic_fmt"] #[no_mangle] pub extern fn panic_fmt() -> ! { loop{} }
+#[lang = "panic_fmt"] #[no_mangle]
+pub extern fn panic_fmt(msg: ::std::fmt::Arguments, file: &'static str, line: u32, col: u32) -> ! {
+    ::panic::handle(msg, file, line, col)
+}
 
 #[no_mangle]
 pub unsafe extern fn memcpy(dest: *mut u8, src: *const u8, n: usize) -> *mut u8 {
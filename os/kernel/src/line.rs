@@ -0,0 +1,293 @@
+//! Line editing support for the shell: the line being edited, previously
+//! entered lines, and word completion. The keys a terminal sends are decoded
+//! by `console::input`.
+
+use stack_vec::StackVec;
+
+/// The longest line the shell accepts, in bytes.
+pub const MAX_LINE: usize = 512;
+
+/// The number of lines `History` storage conventionally holds.
+pub const HISTORY_LEN: usize = 16;
+
+/// A line stored in `History`.
+#[derive(Copy, Clone)]
+pub struct Entry {
+    bytes: [u8; MAX_LINE],
+    len: usize,
+}
+
+impl Entry {
+    /// An entry holding the empty line, for initializing `History` storage.
+    pub const EMPTY: Entry = Entry { bytes: [0; MAX_LINE], len: 0 };
+
+    /// Returns the line this entry holds.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+/// The most recently entered lines, as many as its storage holds. Once full,
+/// entering a line forgets the oldest.
+pub struct History<'a> {
+    entries: StackVec<'a, Entry>,
+}
+
+impl<'a> History<'a> {
+    /// Returns an empty history that remembers up to `storage.len()` lines.
+    pub fn new(storage: &'a mut [Entry]) -> History<'a> {
+        History { entries: StackVec::new(storage) }
+    }
+
+    /// Returns the number of lines remembered.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if no lines are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remembers `line`, truncated to `MAX_LINE` bytes. Blank lines and
+    /// repeats of the most recent line are not remembered.
+    pub fn push(&mut self, line: &[u8]) {
+        let line = &line[..line.len().min(MAX_LINE)];
+        if line.iter().all(|&b| b == b' ') || self.get(0) == Some(line) {
+            return;
+        }
+
+        if self.entries.capacity() == 0 {
+            return;
+        }
+
+        if self.entries.is_full() {
+            let entries = self.entries.as_mut_slice();
+            for i in 1..entries.len() {
+                entries[i - 1] = entries[i];
+            }
+
+            self.entries.pop();
+        }
+
+        let mut entry = Entry { bytes: [0; MAX_LINE], len: line.len() };
+        entry.bytes[..line.len()].copy_from_slice(line);
+        let _ = self.entries.push(entry);
+    }
+
+    /// Returns the line entered `age` lines ago, where 0 is the most recent,
+    /// if it is remembered.
+    pub fn get(&self, age: usize) -> Option<&[u8]> {
+        let len = self.entries.len();
+        if age >= len {
+            return None;
+        }
+
+        Some(self.entries[len - 1 - age].as_bytes())
+    }
+}
+
+/// The result of completing a word against a set of candidates.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Completion<'a> {
+    /// The number of candidates the word is a prefix of.
+    pub matches: usize,
+    /// The longest prefix shared by every match, or `""` if there are none.
+    /// The word can always be extended to this.
+    pub common: &'a str,
+}
+
+/// Completes `word` against `candidates`.
+pub fn complete<'a, I: IntoIterator<Item = &'a str>>(word: &str, candidates: I) -> Completion<'a> {
+    let mut completion = Completion { matches: 0, common: "" };
+    for candidate in candidates.into_iter().filter(|c| c.starts_with(word)) {
+        completion.common = if completion.matches == 0 {
+            candidate
+        } else {
+            let shared = completion.common.bytes().zip(candidate.bytes())
+                .take_while(|&(a, b)| a == b)
+                .count();
+            &completion.common[..shared]
+        };
+
+        completion.matches += 1;
+    }
+
+    completion
+}
+
+/// A line being edited: its bytes and a cursor that edits happen at. Each
+/// editing method returns `false`, leaving the line as it was, if the edit
+/// isn't possible.
+pub struct Editor<'a> {
+    buf: StackVec<'a, u8>,
+    cursor: usize,
+}
+
+impl<'a> Editor<'a> {
+    /// Returns an empty line that can grow to `storage.len()` bytes.
+    pub fn new(storage: &'a mut [u8]) -> Editor<'a> {
+        Editor { buf: StackVec::new(storage), cursor: 0 }
+    }
+
+    /// Returns the line.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.buf.as_slice()
+    }
+
+    /// Returns the line, consuming the editor. The line is returned mutably
+    /// so that it can be parsed in place.
+    pub fn into_bytes(self) -> &'a mut [u8] {
+        self.buf.into_slice()
+    }
+
+    /// Returns the length of the line in bytes.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns `true` if the line is empty.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Returns the position of the cursor: the number of bytes before it.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replaces the line with `line`, truncated to fit, and moves the cursor
+    /// to its end.
+    pub fn replace(&mut self, line: &[u8]) {
+        self.buf.truncate(0);
+        for &byte in line {
+            if self.buf.push(byte).is_err() {
+                break;
+            }
+        }
+
+        self.cursor = self.buf.len();
+    }
+
+    /// Inserts `byte` before the cursor.
+    pub fn insert(&mut self, byte: u8) -> bool {
+        if self.buf.push(byte).is_err() {
+            return false;
+        }
+
+        let cursor = self.cursor;
+        let bytes = self.buf.as_mut_slice();
+        for i in (cursor + 1..bytes.len()).rev() {
+            bytes[i] = bytes[i - 1];
+        }
+
+        bytes[cursor] = byte;
+        self.cursor += 1;
+        true
+    }
+
+    /// Removes the `count` bytes starting at `start`.
+    fn remove(&mut self, start: usize, count: usize) {
+        let len = self.buf.len();
+        {
+            let bytes = self.buf.as_mut_slice();
+            for i in start..len - count {
+                bytes[i] = bytes[i + count];
+            }
+        }
+
+        self.buf.truncate(len - count);
+        if self.cursor > start {
+            self.cursor = if self.cursor >= start + count { self.cursor - count } else { start };
+        }
+    }
+
+    /// Deletes the byte before the cursor.
+    pub fn backspace(&mut self) -> bool {
+        if self.cursor == 0 {
+            return false;
+        }
+
+        let at = self.cursor - 1;
+        self.remove(at, 1);
+        true
+    }
+
+    /// Deletes the byte under the cursor.
+    pub fn delete(&mut self) -> bool {
+        if self.cursor == self.buf.len() {
+            return false;
+        }
+
+        let at = self.cursor;
+        self.remove(at, 1);
+        true
+    }
+
+    /// Moves the cursor one byte left.
+    pub fn left(&mut self) -> bool {
+        if self.cursor == 0 {
+            return false;
+        }
+
+        self.cursor -= 1;
+        true
+    }
+
+    /// Moves the cursor one byte right.
+    pub fn right(&mut self) -> bool {
+        if self.cursor == self.buf.len() {
+            return false;
+        }
+
+        self.cursor += 1;
+        true
+    }
+
+    /// Moves the cursor to the start of the line.
+    pub fn home(&mut self) -> bool {
+        self.cursor = 0;
+        true
+    }
+
+    /// Moves the cursor to the end of the line.
+    pub fn end(&mut self) -> bool {
+        self.cursor = self.buf.len();
+        true
+    }
+
+    /// Deletes everything before the cursor.
+    pub fn kill_to_start(&mut self) -> bool {
+        let count = self.cursor;
+        self.remove(0, count);
+        true
+    }
+
+    /// Deletes everything from the cursor on.
+    pub fn kill_to_end(&mut self) -> bool {
+        let cursor = self.cursor;
+        self.buf.truncate(cursor);
+        true
+    }
+
+    /// Deletes the word before the cursor and any spaces after it.
+    pub fn kill_word(&mut self) -> bool {
+        let bytes = self.buf.as_slice();
+        let mut start = self.cursor;
+        while start > 0 && bytes[start - 1] == b' ' {
+            start -= 1;
+        }
+
+        while start > 0 && bytes[start - 1] != b' ' {
+            start -= 1;
+        }
+
+        if start == self.cursor {
+            return false;
+        }
+
+        let count = self.cursor - start;
+        self.remove(start, count);
+        true
+    }
+}
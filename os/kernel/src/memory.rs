@@ -0,0 +1,174 @@
+//! The layout of physical memory: where the kernel, its allocators and the
+//! devices are.
+//!
+//! There are no page tables yet: the MMU is off, so every address is physical
+//! and the permissions listed are the ones each region is meant to have.
+
+use std::fmt;
+
+use pi::atags::{ATAG_BASE, MAX_SIZE};
+use pi::common::IO_BASE;
+
+use allocator::HOTLOAD_END;
+use dma;
+use frames;
+use hotload::{LOAD_ADDR, STAGING_ADDR};
+use mutex::Mutex;
+
+/// The lowest address the boot stack, which grows down from `LOAD_ADDR`, is
+/// expected to reach. Nothing enforces it.
+pub const STACK_LIMIT: usize = 0x8000;
+
+/// The end of the peripherals at `IO_BASE`.
+const IO_END: usize = 0x4000_0000;
+
+/// The ARM local peripherals: the core timers, mailboxes and interrupt
+/// routing.
+const LOCAL_BASE: usize = 0x4000_0000;
+const LOCAL_END: usize = 0x4004_0000;
+
+/// How the CPU is meant to access a region.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// Ordinary memory, cacheable once the MMU is on.
+    Normal,
+    /// Memory shared with the GPU or the DMA engine.
+    Uncached,
+    /// Device registers.
+    Device,
+}
+
+/// A region's permissions. Every region can be read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Perms {
+    pub write: bool,
+    pub execute: bool,
+    pub kind: Kind,
+}
+
+const R: Perms = Perms { write: false, execute: false, kind: Kind::Normal };
+const RW: Perms = Perms { write: true, execute: false, kind: Kind::Normal };
+const RX: Perms = Perms { write: false, execute: true, kind: Kind::Normal };
+const UNCACHED: Perms = Perms { write: true, execute: false, kind: Kind::Uncached };
+const DEVICE: Perms = Perms { write: true, execute: false, kind: Kind::Device };
+
+impl fmt::Display for Perms {
+    /// Writes the permissions as in `ls`, followed by the kind unless it is
+    /// normal: `r-x`, `rw- device`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let write = if self.write { "w" } else { "-" };
+        let execute = if self.execute { "x" } else { "-" };
+        write!(f, "r{}{}", write, execute)?;
+        match self.kind {
+            Kind::Normal => Ok(()),
+            Kind::Uncached => write!(f, " uncached"),
+            Kind::Device => write!(f, " device"),
+        }
+    }
+}
+
+/// A named range of addresses, from `start` up to `end`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub name: &'static str,
+    pub start: usize,
+    pub end: usize,
+    pub perms: Perms,
+}
+
+impl Region {
+    pub fn new(name: &'static str, start: usize, end: usize, perms: Perms) -> Region {
+        Region { name, start, end, perms }
+    }
+
+    /// Returns the size of the region in bytes.
+    pub fn size(&self) -> usize {
+        self.end - self.start
+    }
+}
+
+/// The framebuffer's memory, once one is allocated.
+static FRAMEBUFFER: Mutex<Option<(usize, usize)>> = Mutex::new(None);
+
+/// Records that the framebuffer occupies the memory from `start` to `end`.
+pub fn set_framebuffer(start: usize, end: usize) {
+    *FRAMEBUFFER.lock() = Some((start, end));
+}
+
+/// Returns the kernel binary's sections, from the symbols in `layout.ld`.
+#[cfg(target_arch = "aarch64")]
+fn sections() -> Vec<Region> {
+    extern "C" {
+        static __text_start: u8;
+        static __text_end: u8;
+        static __rodata_start: u8;
+        static __rodata_end: u8;
+        static __data_start: u8;
+        static __data_end: u8;
+        static __bss_start: u8;
+        static __bss_end: u8;
+    }
+
+    let addr = |symbol: &u8| symbol as *const u8 as usize;
+    unsafe {
+        vec![
+            Region::new("kernel text", addr(&__text_start), addr(&__text_end), RX),
+            Region::new("kernel rodata", addr(&__rodata_start), addr(&__rodata_end), R),
+            Region::new("kernel data", addr(&__data_start), addr(&__data_end), RW),
+            Region::new("kernel bss", addr(&__bss_start), addr(&__bss_end), RW),
+        ]
+    }
+}
+
+/// The kernel isn't linked with `layout.ld` on the host.
+#[cfg(not(target_arch = "aarch64"))]
+fn sections() -> Vec<Region> {
+    Vec::new()
+}
+
+/// Returns the regions of memory the kernel knows about, sorted by start
+/// address. Regions may nest: the DMA pool is carved from the frames.
+pub fn map() -> Vec<Region> {
+    let mut regions = vec![
+        Region::new("ATAGs", ATAG_BASE, ATAG_BASE + MAX_SIZE, R),
+        Region::new("boot stack", STACK_LIMIT, LOAD_ADDR, RW),
+        Region::new("hotload staging", STAGING_ADDR, HOTLOAD_END, RW),
+        Region::new("peripherals", IO_BASE, IO_END, DEVICE),
+        Region::new("local peripherals", LOCAL_BASE, LOCAL_END, DEVICE),
+    ];
+
+    regions.extend(sections());
+    let dynamic = [
+        ("heap", ::ALLOCATOR.region(), RW),
+        ("frames", frames::region(), RW),
+        ("DMA pool", dma::POOL.region(), UNCACHED),
+        ("framebuffer", *FRAMEBUFFER.lock(), UNCACHED),
+    ];
+
+    for &(name, region, perms) in dynamic.iter() {
+        if let Some((start, end)) = region {
+            regions.push(Region::new(name, start, end, perms));
+        }
+    }
+
+    regions.sort_by_key(|region| region.start);
+    regions
+}
+
+/// Writes `regions` as a table, one region per line.
+pub fn write_map<W: fmt::Write>(w: &mut W, regions: &[Region]) -> fmt::Result {
+    writeln!(w, "{:<10}  {:<10}  {:>9}  {:<12}  {}", "start", "end", "size", "perms", "region")?;
+    for region in regions {
+        let (size, unit) = match region.size() {
+            size if size >= 1 << 20 => (size >> 20, "MiB"),
+            size if size >= 1 << 10 => (size >> 10, "KiB"),
+            size => (size, "B"),
+        };
+
+        let perms = format!("{}", region.perms);
+        writeln!(w, "{:#010x}  {:#010x}  {:>5} {:<3}  {:<12}  {}",
+                 region.start, region.end, size, unit, perms, region.name)?;
+    }
+
+    Ok(())
+}
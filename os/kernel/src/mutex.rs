@@ -2,18 +2,29 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::cell::UnsafeCell;
 use std::ops::{DerefMut, Deref, Drop};
 
+use hw::irq;
+
 #[repr(align(32))]
 pub struct Mutex<T> {
     data: UnsafeCell<T>,
     #[allow(unused)]
     lock: AtomicBool,
+    /// Whether IRQs are masked while the lock is held.
+    masks_irqs: bool,
 }
 
 unsafe impl<T: Send> Send for Mutex<T> { }
 unsafe impl<T: Send> Sync for Mutex<T> { }
 
+/// A locked `Mutex`. Unless the mutex was made with `Mutex::unmasked()`, IRQs
+/// are masked on the locking core for as long as the guard lives, so that an
+/// interrupt handler can't spin forever on a lock held by the code it
+/// interrupted. Guards for nested locks must be dropped in the reverse of the
+/// order they were taken in, or IRQs are unmasked too early.
 pub struct MutexGuard<'a, T: 'a> {
-    lock: &'a Mutex<T>
+    lock: &'a Mutex<T>,
+    /// The IRQ mask state from before the lock was taken, if it masked them.
+    irq: Option<u64>,
 }
 
 impl<'a, T> !Send for MutexGuard<'a, T> { }
@@ -23,25 +34,78 @@ impl<T> Mutex<T> {
     pub const fn new(val: T) -> Mutex<T> {
         Mutex {
             lock: AtomicBool::new(false),
-            data: UnsafeCell::new(val)
+            data: UnsafeCell::new(val),
+            masks_irqs: true,
+        }
+    }
+
+    /// Returns a mutex that leaves IRQs as they are while it is held, so that
+    /// the code holding it can still be preempted. Neither interrupt handlers
+    /// nor code running with IRQs masked may wait for it, since the holder
+    /// can't run again to release it; they may only `try_lock()` it.
+    pub const fn unmasked(val: T) -> Mutex<T> {
+        Mutex {
+            lock: AtomicBool::new(false),
+            data: UnsafeCell::new(val),
+            masks_irqs: false,
         }
     }
 }
 
 impl<T> Mutex<T> {
-    // Once MMU/cache is enabled, do the right thing here. For now, we don't
-    // need any real synchronization.
-    #[inline(never)]
+    /// Waits for the lock and takes it.
     pub fn lock(&self) -> MutexGuard<T> {
-        // Wait until we can "aquire" the lock, then "acquire" it.
-        while self.lock.load(Ordering::Relaxed) { }
+        loop {
+            if let Some(guard) = self.try_lock() {
+                return guard;
+            }
+        }
+    }
+
+    /// Takes the lock if it is free. Returns `None` if it is held.
+    #[cfg(target_arch = "aarch64")]
+    #[inline(never)]
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        // Once MMU/cache is enabled, do the right thing here. For now, we
+        // don't need any real synchronization: with IRQs masked, nothing else
+        // runs on the core between the check and the store.
+        let irq = irq::save_and_disable();
+        if self.lock.load(Ordering::Relaxed) {
+            irq::restore(irq);
+            return None;
+        }
+
         self.lock.store(true, Ordering::Relaxed);
+        Some(self.guard(irq))
+    }
+
+    /// Takes the lock if it is free. Returns `None` if it is held.
+    #[cfg(not(target_arch = "aarch64"))]
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        // On the host, tests run on many threads at once, so the lock must be
+        // real.
+        let irq = irq::save_and_disable();
+        if self.lock.compare_and_swap(false, true, Ordering::Acquire) {
+            irq::restore(irq);
+            return None;
+        }
 
-        MutexGuard { lock: &self }
+        Some(self.guard(irq))
+    }
+
+    /// Returns a guard for the lock just taken with IRQs masked, `irq` being
+    /// the mask state from before. Unmasked mutexes restore it right away.
+    fn guard(&self, irq: u64) -> MutexGuard<T> {
+        if self.masks_irqs {
+            return MutexGuard { lock: &self, irq: Some(irq) };
+        }
+
+        irq::restore(irq);
+        MutexGuard { lock: &self, irq: None }
     }
 
     fn unlock(&self) {
-        self.lock.store(false, Ordering::Relaxed);
+        self.lock.store(false, Ordering::Release);
     }
 }
 
@@ -61,6 +125,9 @@ impl<'a, T: 'a> DerefMut for MutexGuard<'a, T> {
 
 impl<'a, T: 'a> Drop for MutexGuard<'a, T> {
     fn drop(&mut self) {
-        self.lock.unlock()
+        self.lock.unlock();
+        if let Some(irq) = self.irq {
+            irq::restore(irq);
+        }
     }
 }
@@ -0,0 +1,139 @@
+//! What the kernel does when it panics: report the panic over the UART, then
+//! blink the ACT LED until the board is reset.
+//!
+//! The report is written through a new handle to the UART instead of through
+//! `CONSOLE`, whose lock the panicking code may hold, and IRQs are masked so
+//! that nothing else runs on the core. A panic while reporting a panic skips
+//! the report and goes straight to blinking. If the watchdog is armed, it
+//! resets the board once nothing pets it.
+
+use std::fmt;
+
+use pi::led::Step;
+
+/// How long an SOS dot, dash and the gap between them are, in microseconds.
+const DOT: u64 = 150_000;
+const DASH: u64 = 450_000;
+const GAP: u64 = 150_000;
+
+/// How long the LED stays off between repetitions of `PATTERN`.
+const PAUSE: u64 = 1_050_000;
+
+/// The panic blink pattern as `(level, duration)` steps: SOS, three dots,
+/// three dashes and three dots, then a pause. It can't be mistaken for the
+/// heartbeat or for the firmware's blink codes.
+pub const PATTERN: [Step; 18] = [
+    (true, DOT), (false, GAP), (true, DOT), (false, GAP), (true, DOT), (false, GAP),
+    (true, DASH), (false, GAP), (true, DASH), (false, GAP), (true, DASH), (false, GAP),
+    (true, DOT), (false, GAP), (true, DOT), (false, GAP), (true, DOT), (false, PAUSE),
+];
+
+/// A snapshot of a core's registers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Registers {
+    /// The general purpose registers `x0` to `x30`.
+    pub x: [u64; 31],
+    pub sp: u64,
+    /// The exception level the core is running at.
+    pub el: u64,
+    /// The `DAIF` exception mask bits.
+    pub daif: u64,
+}
+
+impl Registers {
+    /// Returns the calling core's registers as they are on entry to this
+    /// function. One register holds the address the snapshot is written to.
+    #[cfg(target_arch = "aarch64")]
+    #[inline(always)]
+    pub fn capture() -> Registers {
+        let mut registers = Registers { x: [0; 31], sp: 0, el: 0, daif: 0 };
+        unsafe {
+            asm!("stp x0, x1, [$0, #0]
+                  stp x2, x3, [$0, #16]
+                  stp x4, x5, [$0, #32]
+                  stp x6, x7, [$0, #48]
+                  stp x8, x9, [$0, #64]
+                  stp x10, x11, [$0, #80]
+                  stp x12, x13, [$0, #96]
+                  stp x14, x15, [$0, #112]
+                  stp x16, x17, [$0, #128]
+                  stp x18, x19, [$0, #144]
+                  stp x20, x21, [$0, #160]
+                  stp x22, x23, [$0, #176]
+                  stp x24, x25, [$0, #192]
+                  stp x26, x27, [$0, #208]
+                  stp x28, x29, [$0, #224]
+                  str x30, [$0, #240]"
+                 : : "r"(registers.x.as_mut_ptr()) : "memory" : "volatile");
+            asm!("mov $0, sp" : "=r"(registers.sp) : : : "volatile");
+            asm!("mrs $0, CurrentEL" : "=r"(registers.el) : : : "volatile");
+            asm!("mrs $0, daif" : "=r"(registers.daif) : : : "volatile");
+        }
+
+        registers.el = (registers.el >> 2) & 0b11;
+        registers
+    }
+}
+
+impl fmt::Display for Registers {
+    /// Writes the general purpose registers four to a line, then the stack
+    /// pointer, exception level and `DAIF`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, value) in self.x.iter().enumerate() {
+            let separator = match i % 4 {
+                0 => "",
+                _ => "  ",
+            };
+
+            write!(f, "{}x{:02}: {:#018x}", separator, i, value)?;
+            if i % 4 == 3 || i == self.x.len() - 1 {
+                writeln!(f, "")?;
+            }
+        }
+
+        writeln!(f, " sp: {:#018x}  el: {}  daif: {:#x}", self.sp, self.el, self.daif)
+    }
+}
+
+/// Writes the report for a panic on core `core` with the message `msg` at
+/// `file:line:col` to `w`, followed by the registers if there are any.
+pub fn write_report<W: fmt::Write>(w: &mut W, msg: fmt::Arguments, file: &str, line: u32,
+                                   col: u32, core: u64, registers: Option<&Registers>)
+                                   -> fmt::Result {
+    writeln!(w, "\n!!! kernel panic on core {} at {}:{}:{}", core, file, line, col)?;
+    writeln!(w, "    {}", msg)?;
+    if let Some(registers) = registers {
+        write!(w, "{}", registers)?;
+    }
+
+    Ok(())
+}
+
+/// Handles a panic: reports it over the UART and blinks `PATTERN` on the ACT
+/// LED forever. The heartbeat is stopped first so that it doesn't blink too.
+#[cfg(target_arch = "aarch64")]
+pub fn handle(msg: fmt::Arguments, file: &'static str, line: u32, col: u32) -> ! {
+    use std::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
+
+    use heartbeat;
+    use hw::{self, irq, Uart};
+    use hw::timer::spin_sleep_us;
+
+    /// Set once a panic is being handled.
+    static PANICKING: AtomicBool = ATOMIC_BOOL_INIT;
+
+    let registers = Registers::capture();
+    irq::save_and_disable();
+    if !PANICKING.swap(true, Ordering::SeqCst) {
+        heartbeat::suppress();
+        let _ = write_report(&mut Uart::new(), msg, file, line, col, hw::core_id(),
+                             Some(&registers));
+    }
+
+    loop {
+        for &(on, duration) in PATTERN.iter() {
+            hw::set_act_led(on);
+            spin_sleep_us(duration);
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! Processes: threads of execution the kernel can stop and resume.
+//!
+//! A process owns a stack of its own, allocated from the frame allocator, and
+//! the registers it was stopped with, saved in a `TrapFrame`. A process loaded
+//! from an executable also owns the frames its image was loaded into. Its
+//! `State` says whether it is running, ready to run, or waiting for something
+//! to happen first.
+
+mod pages;
+mod process;
+mod stack;
+mod state;
+mod trap_frame;
+
+pub use self::pages::Pages;
+pub use self::process::{Process, Id, MAX_IMAGE_SIZE, USER_SPSR};
+pub use self::stack::{Stack, STACK_SIZE};
+pub use self::state::{State, EventPollFn};
+pub use self::trap_frame::TrapFrame;
@@ -0,0 +1,82 @@
+use std::{fmt, slice};
+
+use frames::PAGE_SIZE;
+
+/// A run of consecutive frames owned by a process, returned to the frame
+/// allocator when dropped.
+pub struct Pages {
+    base: usize,
+    count: usize,
+}
+
+impl Pages {
+    /// Allocates `count` consecutive frames. Returns `None` if there aren't
+    /// that many consecutive free frames or the frame allocator isn't
+    /// initialized.
+    pub fn new(count: usize) -> Option<Pages> {
+        Some(Pages { base: alloc(count)?, count })
+    }
+
+    /// Returns the address of the first frame.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Returns the size of the frames in bytes.
+    pub fn size(&self) -> usize {
+        self.count * PAGE_SIZE
+    }
+
+    /// Returns `true` if the `len` bytes at `addr` all lie in the frames.
+    pub fn contains(&self, addr: usize, len: usize) -> bool {
+        addr >= self.base
+            && addr.checked_add(len).map_or(false, |end| end <= self.base + self.size())
+    }
+
+    /// Returns the frames' contents for writing.
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.base as *mut u8, self.size()) }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn alloc(count: usize) -> Option<usize> {
+    use frames;
+
+    frames::alloc_contiguous(count, PAGE_SIZE)
+}
+
+#[cfg(target_arch = "aarch64")]
+fn free(base: usize, count: usize) {
+    use frames;
+
+    for page in 0..count {
+        frames::free_frame(base + page * PAGE_SIZE);
+    }
+}
+
+// The frame allocator is never initialized on the host, so tests get their
+// frames from the host's heap instead.
+#[cfg(not(target_arch = "aarch64"))]
+fn alloc(count: usize) -> Option<usize> {
+    let pages = vec![0u64; count * PAGE_SIZE / 8].into_boxed_slice();
+    Some(Box::into_raw(pages) as *mut u64 as usize)
+}
+
+#[cfg(not(target_arch = "aarch64"))]
+fn free(base: usize, count: usize) {
+    let pages = unsafe { slice::from_raw_parts_mut(base as *mut u64, count * PAGE_SIZE / 8) };
+    drop(unsafe { Box::from_raw(pages) });
+}
+
+impl Drop for Pages {
+    fn drop(&mut self) {
+        free(self.base, self.count);
+    }
+}
+
+impl fmt::Debug for Pages {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Pages({:#x}..{:#x})", self.base, self.base + self.size())
+    }
+}
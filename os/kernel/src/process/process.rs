@@ -0,0 +1,148 @@
+use std::{io, mem};
+
+use elf::Elf;
+use frames::PAGE_SIZE;
+use process::{Pages, Stack, State, TrapFrame};
+use syscall;
+
+/// A process ID. IDs are handed out by the scheduler, from 1.
+pub type Id = u64;
+
+/// The processor state kernel threads start with: EL1 using `SP_EL0`, with
+/// IRQs unmasked and debug exceptions, SErrors and FIQs masked.
+const KERNEL_THREAD_SPSR: u64 = 0b1101 << 6 | 0b0100;
+
+/// The processor state user programs start with: EL0, with IRQs unmasked and
+/// debug exceptions, SErrors and FIQs masked.
+pub const USER_SPSR: u64 = 0b1101 << 6;
+
+/// The most memory an executable's loadable segments may take.
+pub const MAX_IMAGE_SIZE: usize = 64 * 1024 * 1024;
+
+/// A process: its saved registers, its stack, its state, and the image it was
+/// loaded from, if any.
+#[derive(Debug)]
+pub struct Process {
+    /// The registers the process resumes with.
+    pub trap_frame: Box<TrapFrame>,
+    pub stack: Stack,
+    /// The frames the process's executable was loaded into. Kernel threads
+    /// run from the kernel's image and have none.
+    pub image: Option<Pages>,
+    pub state: State,
+    /// The ID of the process that was running when this one was added to the
+    /// scheduler, if any.
+    pub parent: Option<Id>,
+    /// The IDs of the process's children that haven't been waited for, and
+    /// the exit codes of those that have exited.
+    pub children: Vec<(Id, Option<u32>)>,
+}
+
+impl Process {
+    /// Returns a new process, ready to run, with a stack of its own and every
+    /// register zeroed but its stack pointer, which points at the top of the
+    /// stack. Its entry point and processor state are left to the caller.
+    ///
+    /// Returns `None` if no stack could be allocated.
+    pub fn new() -> Option<Process> {
+        let stack = Stack::new()?;
+        let mut trap_frame = Box::new(TrapFrame::zeroed());
+        trap_frame.sp = stack.top() as u64;
+        Some(Process {
+            trap_frame,
+            stack,
+            image: None,
+            state: State::Ready,
+            parent: None,
+            children: Vec::new(),
+        })
+    }
+
+    /// Returns a new process that runs `entry` in EL1, the kernel's
+    /// exception level, on a stack of its own. If `entry` returns, the
+    /// process exits with the value it returned.
+    ///
+    /// Returns `None` if no stack could be allocated.
+    pub fn kernel_thread(entry: extern "C" fn() -> u32) -> Option<Process> {
+        let mut process = Process::new()?;
+        process.trap_frame.elr = entry as usize as u64;
+        process.trap_frame.x[30] = exit_on_return as usize as u64;
+        process.trap_frame.spsr = KERNEL_THREAD_SPSR;
+        Some(process)
+    }
+
+    /// Returns a new process that runs the ELF executable `executable` in
+    /// EL0, loaded into frames of its own, on a stack of its own.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of `InvalidData` if `executable` isn't a valid
+    /// position-independent AArch64 executable (see `Elf::parse()`) or its
+    /// segments take more than `MAX_IMAGE_SIZE` bytes, and of
+    /// `Other` if no frames could be allocated for its image or its stack.
+    pub fn load(executable: &[u8]) -> io::Result<Process> {
+        let elf = Elf::parse(executable)?;
+        let (start, end) = elf.span().expect("a parsed ELF file has loadable segments");
+        let pages = match end.checked_sub(start) {
+            Some(size) if size <= MAX_IMAGE_SIZE as u64 => {
+                (size as usize + PAGE_SIZE - 1) / PAGE_SIZE
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "executable is too large")),
+        };
+        let out_of_memory = || io::Error::new(io::ErrorKind::Other, "out of memory");
+        let mut image = Pages::new(pages).ok_or_else(out_of_memory)?;
+        let base = image.base() as u64;
+        elf.load(image.as_mut_slice(), base)?;
+
+        let mut process = Process::new().ok_or_else(out_of_memory)?;
+        process.trap_frame.elr = base + (elf.entry - start);
+        process.trap_frame.spsr = USER_SPSR;
+        process.image = Some(image);
+        Ok(process)
+    }
+
+    /// Returns the process's ID, which is kept in its `TPIDR_EL0`. A process
+    /// that hasn't been added to a scheduler has ID 0.
+    pub fn id(&self) -> Id {
+        self.trap_frame.tpidr
+    }
+
+    /// Returns `true` if the process may pass the `len` bytes at `addr` to a
+    /// syscall. Kernel threads may pass any memory, as it is all the
+    /// kernel's; user programs only memory in their image or on their stack.
+    pub fn may_access(&self, addr: usize, len: usize) -> bool {
+        match self.image {
+            None => true,
+            Some(ref image) => image.contains(addr, len) || self.stack.contains(addr, len),
+        }
+    }
+
+    /// Returns `true` if the process is ready to run. A waiting process's
+    /// poll function is called to find out; if it returns `true` the process
+    /// is marked ready.
+    pub fn is_ready(&mut self) -> bool {
+        match self.state {
+            State::Ready => return true,
+            State::Running | State::Dead(_) => return false,
+            State::Waiting(_) => {}
+        }
+
+        let mut poll = match mem::replace(&mut self.state, State::Ready) {
+            State::Waiting(poll) => poll,
+            _ => unreachable!("the process is waiting"),
+        };
+
+        if poll(self) {
+            true
+        } else {
+            self.state = State::Waiting(poll);
+            false
+        }
+    }
+}
+
+/// Where a kernel thread's entry function returns to, with the value it
+/// returned in `x0`: exits with that value as the exit code.
+extern "C" fn exit_on_return(code: u32) -> ! {
+    syscall::exit(code)
+}
@@ -0,0 +1,42 @@
+use std::fmt;
+
+use frames::PAGE_SIZE;
+use process::Pages;
+
+/// The size of a process's stack in bytes.
+pub const STACK_SIZE: usize = 1024 * 1024;
+
+/// A process's stack: `STACK_SIZE` bytes of consecutive frames, returned to
+/// the frame allocator when dropped.
+pub struct Stack(Pages);
+
+impl Stack {
+    /// Allocates a stack from the frame allocator. Returns `None` if there
+    /// aren't enough consecutive free frames or the frame allocator isn't
+    /// initialized.
+    pub fn new() -> Option<Stack> {
+        Pages::new(STACK_SIZE / PAGE_SIZE).map(Stack)
+    }
+
+    /// Returns the lowest address of the stack.
+    pub fn bottom(&self) -> usize {
+        self.0.base()
+    }
+
+    /// Returns the address just past the stack, which its stack pointer
+    /// starts at, as the stack grows down.
+    pub fn top(&self) -> usize {
+        self.bottom() + STACK_SIZE
+    }
+
+    /// Returns `true` if the `len` bytes at `addr` all lie on the stack.
+    pub fn contains(&self, addr: usize, len: usize) -> bool {
+        self.0.contains(addr, len)
+    }
+}
+
+impl fmt::Debug for Stack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Stack({:#x}..{:#x})", self.bottom(), self.top())
+    }
+}
@@ -0,0 +1,31 @@
+use std::fmt;
+
+use process::Process;
+
+/// A function polled to find out whether a waiting process can run again. It
+/// is passed the process, and returns `true` once the process is ready.
+pub type EventPollFn = Box<FnMut(&mut Process) -> bool + Send>;
+
+/// Where a process is in its life.
+pub enum State {
+    /// The process is ready to be run.
+    Ready,
+    /// The process is waiting for an event; it is ready once the function
+    /// returns `true`.
+    Waiting(EventPollFn),
+    /// The process is running.
+    Running,
+    /// The process has exited with the exit code. It never runs again.
+    Dead(u32),
+}
+
+impl fmt::Debug for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            State::Ready => write!(f, "Ready"),
+            State::Waiting(_) => write!(f, "Waiting"),
+            State::Running => write!(f, "Running"),
+            State::Dead(code) => write!(f, "Dead({})", code),
+        }
+    }
+}
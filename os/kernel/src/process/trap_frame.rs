@@ -0,0 +1,47 @@
+use std::fmt;
+
+/// The registers of a process, saved when it is stopped by an exception and
+/// restored when it resumes.
+///
+/// The layout is fixed: the exception vectors in `init.S` build the frame on
+/// the stack field by field, in this order, and restore the registers from it
+/// when returning from the exception.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct TrapFrame {
+    /// The address execution resumes at, from `ELR_ELx`.
+    pub elr: u64,
+    /// The processor state to resume with, from `SPSR_ELx`.
+    pub spsr: u64,
+    /// The stack pointer processes run with, `SP_EL0`.
+    pub sp: u64,
+    /// The thread ID register, `TPIDR_EL0`, which holds the process's ID.
+    pub tpidr: u64,
+    /// The SIMD and floating point registers, `q0` to `q31`.
+    pub q: [u128; 32],
+    /// The general purpose registers, `x0` to `x30`.
+    pub x: [u64; 31],
+    /// Pads the frame to a multiple of 16 bytes, as the stack must be.
+    pub reserved: u64,
+}
+
+impl TrapFrame {
+    /// Returns a frame with every register zeroed.
+    pub fn zeroed() -> TrapFrame {
+        TrapFrame { elr: 0, spsr: 0, sp: 0, tpidr: 0, q: [0; 32], x: [0; 31], reserved: 0 }
+    }
+}
+
+impl fmt::Debug for TrapFrame {
+    /// Writes the special registers and the general purpose ones; the SIMD
+    /// registers are left out.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TrapFrame")
+            .field("elr", &self.elr)
+            .field("spsr", &self.spsr)
+            .field("sp", &self.sp)
+            .field("tpidr", &self.tpidr)
+            .field("x", &&self.x[..])
+            .finish()
+    }
+}
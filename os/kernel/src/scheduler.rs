@@ -0,0 +1,286 @@
+//! Round-robin scheduling of processes.
+//!
+//! Processes run in turn for a time slice of `TICK_US` microseconds each. The
+//! end of each slice is a periodic timer event, run from the timer interrupt,
+//! after which the IRQ handler calls `Scheduler::switch()`, which saves the
+//! process's registers, moves it to the back of the queue and resumes the
+//! first process that is ready to run. A process that exits is removed from
+//! the queue once it has been switched from, and its exit code is passed on
+//! to its parent, the process that was running when it was added, for
+//! `wait()`.
+
+use std::collections::VecDeque;
+#[cfg(target_arch = "aarch64")]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use mutex::Mutex;
+use process::{Id, Process, State, TrapFrame};
+use {timer, watchdog};
+
+/// The time slice of a process in microseconds.
+pub const TICK_US: u32 = 10_000;
+
+/// Set by the time slice event, and cleared by `slice_ended()`.
+#[cfg(target_arch = "aarch64")]
+static SLICE_ENDED: AtomicBool = AtomicBool::new(false);
+
+/// The scheduler's liveness flag, once `start()` has registered it. It is
+/// touched whenever the scheduler switches processes or waits for one to be
+/// ready, so the watchdog resets the board if the time slices stop ending.
+static ALIVE: Mutex<Option<watchdog::Flag>> = Mutex::new(None);
+
+/// The processes a scheduler runs.
+struct Queue {
+    /// The running process, if there is one, is at the front.
+    processes: VecDeque<Process>,
+    /// The ID of the running process.
+    current: Option<Id>,
+    /// The ID handed out last.
+    last_id: Option<Id>,
+}
+
+impl Queue {
+    fn new() -> Queue {
+        Queue { processes: VecDeque::new(), current: None, last_id: None }
+    }
+
+    /// Returns the process `id` if it is in the queue.
+    fn get_mut(&mut self, id: Id) -> Option<&mut Process> {
+        self.processes.iter_mut().find(|process| process.id() == id)
+    }
+
+    /// Gives `process` the next ID and adds it to the back of the queue, as a
+    /// child of the running process if there is one. Returns the ID, or
+    /// `None` if IDs have run out.
+    fn add(&mut self, mut process: Process) -> Option<Id> {
+        let id = match self.last_id {
+            Some(id) => id.checked_add(1)?,
+            None => 1,
+        };
+
+        process.trap_frame.tpidr = id;
+        process.parent = self.current;
+        if let Some(parent) = self.current.and_then(|current| self.get_mut(current)) {
+            parent.children.push((id, None));
+        }
+
+        self.processes.push_back(process);
+        self.last_id = Some(id);
+        Some(id)
+    }
+
+    /// Saves `tf` as the registers of the running process, if there is one,
+    /// and moves it to the back of the queue in state `new_state`.
+    fn schedule_out(&mut self, new_state: State, tf: &TrapFrame) {
+        if self.current.take().is_none() {
+            return;
+        }
+
+        let mut process = self.processes.pop_front().expect("the running process is queued");
+        *process.trap_frame = *tf;
+        process.state = new_state;
+        self.processes.push_back(process);
+    }
+
+    /// Removes the dead processes from the queue, freeing their stacks, and
+    /// records their exit codes in their parents' lists of children.
+    fn reap(&mut self) {
+        loop {
+            let index = self.processes.iter().position(|process| match process.state {
+                State::Dead(_) => true,
+                _ => false,
+            });
+
+            let process = match index.and_then(|index| self.processes.remove(index)) {
+                Some(process) => process,
+                None => return,
+            };
+
+            let code = match process.state {
+                State::Dead(code) => code,
+                _ => unreachable!("the process is dead"),
+            };
+
+            if let Some(parent) = process.parent.and_then(|parent| self.get_mut(parent)) {
+                for child in parent.children.iter_mut().filter(|child| child.0 == process.id()) {
+                    child.1 = Some(code);
+                }
+            }
+        }
+    }
+
+    /// Resumes the first process in the queue that is ready to run: moves it
+    /// to the front, marks it running and copies its registers into `tf`.
+    /// Returns its ID, or `None` if no process is ready.
+    fn switch_to(&mut self, tf: &mut TrapFrame) -> Option<Id> {
+        let index = self.processes.iter_mut().position(|process| process.is_ready())?;
+        let mut process = self.processes.remove(index).expect("index is in the queue");
+        process.state = State::Running;
+        *tf = *process.trap_frame;
+
+        let id = process.id();
+        self.processes.push_front(process);
+        self.current = Some(id);
+        Some(id)
+    }
+}
+
+/// A round-robin scheduler. Its queue is behind the IRQ-masking mutex, so the
+/// timer IRQ can't switch processes while another operation is under way.
+pub struct Scheduler(Mutex<Option<Queue>>);
+
+impl Scheduler {
+    /// Returns a scheduler with no processes.
+    pub const fn uninitialized() -> Scheduler {
+        Scheduler(Mutex::new(None))
+    }
+
+    /// Adds `process` to the back of the queue and returns the ID it was
+    /// given, or `None` if IDs have run out.
+    pub fn add(&self, process: Process) -> Option<Id> {
+        self.0.lock().get_or_insert_with(Queue::new).add(process)
+    }
+
+    /// Returns the ID of the running process, or `None` if no process is
+    /// running.
+    pub fn current(&self) -> Option<Id> {
+        self.0.lock().as_ref().and_then(|queue| queue.current)
+    }
+
+    /// Returns the number of processes in the queue, running or not.
+    pub fn len(&self) -> usize {
+        self.0.lock().as_ref().map_or(0, |queue| queue.processes.len())
+    }
+
+    /// Returns `true` if `id` is a child of the running process that hasn't
+    /// been waited for.
+    pub fn is_child(&self, id: Id) -> bool {
+        let mut queue = self.0.lock();
+        let queue = match queue.as_mut() {
+            Some(queue) => queue,
+            None => return false,
+        };
+
+        match queue.current.and_then(|current| queue.get_mut(current)) {
+            Some(process) => process.children.iter().any(|child| child.0 == id),
+            None => false,
+        }
+    }
+
+    /// Returns `true` if the running process may pass the `len` bytes at
+    /// `addr` to a syscall (see `Process::may_access()`), and `false` if no
+    /// process is running.
+    pub fn may_access(&self, addr: usize, len: usize) -> bool {
+        let mut queue = self.0.lock();
+        let queue = match queue.as_mut() {
+            Some(queue) => queue,
+            None => return false,
+        };
+
+        match queue.current.and_then(|current| queue.get_mut(current)) {
+            Some(process) => process.may_access(addr, len),
+            None => false,
+        }
+    }
+
+    /// Returns the exit code of `id`, a child of the running process, if it
+    /// has exited and hasn't been waited for.
+    pub fn exit_code(&self, id: Id) -> Option<u32> {
+        let mut queue = self.0.lock();
+        let queue = queue.as_mut()?;
+        let parent = queue.current.and_then(|current| queue.get_mut(current))?;
+        parent.children.iter().find(|child| child.0 == id).and_then(|child| child.1)
+    }
+
+    /// Switches from the running process, leaving it in state `new_state`, to
+    /// the next process that is ready to run. `tf` holds the registers of the
+    /// running process on entry and those of the next on return. Returns the
+    /// ID of the next process. If `new_state` is `Dead`, the process is
+    /// removed from the queue and its stack freed: `tf` is all that is left
+    /// of it, and it is overwritten. Its exit code is left for its parent.
+    ///
+    /// If no process is ready, spins until one is. This is only called from
+    /// exception handlers, where IRQs are masked, so the timer interrupt
+    /// can't be taken while spinning: the timer events are run from here
+    /// instead.
+    pub fn switch(&self, new_state: State, tf: &mut TrapFrame) -> Id {
+        {
+            let mut queue = self.0.lock();
+            let queue = queue.get_or_insert_with(Queue::new);
+            queue.schedule_out(new_state, tf);
+            queue.reap();
+        }
+
+        loop {
+            if let Some(flag) = *ALIVE.lock() {
+                watchdog::touch(flag);
+            }
+
+            if let Some(id) = self.0.lock().as_mut().and_then(|queue| queue.switch_to(tf)) {
+                return id;
+            }
+
+            timer::poll();
+        }
+    }
+
+    /// Registers the event that ends each time slice, starts the timer
+    /// interrupt that runs it and runs the first process that is ready. Never
+    /// returns: the kernel's boot context is abandoned, and its stack becomes
+    /// the stack exceptions are taken on.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no process is ready to run.
+    #[cfg(target_arch = "aarch64")]
+    pub fn start(&self) -> ! {
+        use std::mem::size_of;
+
+        extern "C" {
+            static _start: u8;
+        }
+
+        let mut tf = Box::new(TrapFrame::zeroed());
+        self.0.lock().as_mut().and_then(|queue| queue.switch_to(&mut tf))
+            .expect("no process to start");
+
+        *ALIVE.lock() = watchdog::register("scheduler");
+        timer::every(::hw::timer::from_micros(TICK_US as u64), end_slice)
+            .expect("room for the time slice event");
+        timer::start();
+
+        // Copy the frame to the top of the boot stack, which `_start` starts
+        // at, and return from it as if from an exception. The copy advances
+        // its source and count, so it works on copies of the inputs.
+        unsafe {
+            let stack_top = &_start as *const u8 as usize;
+            asm!("mov   x2, $0
+                  mov   x5, $2
+                  mov   sp, $1
+                  sub   sp, sp, x5
+                  mov   x1, sp
+              1:  ldp   x3, x4, [x2], #16
+                  stp   x3, x4, [x1], #16
+                  subs  x5, x5, #16
+                  b.ne  1b
+                  b     context_restore"
+                 : : "r"(&*tf as *const TrapFrame), "r"(stack_top), "r"(size_of::<TrapFrame>())
+                 : "x1", "x2", "x3", "x4", "x5", "memory" : "volatile");
+        }
+
+        unreachable!("context_restore returns to the first process")
+    }
+}
+
+/// Periodic event: ends the running process's time slice.
+#[cfg(target_arch = "aarch64")]
+fn end_slice(_: u64) {
+    SLICE_ENDED.store(true, Ordering::SeqCst);
+}
+
+/// Returns `true` if a time slice has ended since the last call. The IRQ
+/// handler switches processes if it has.
+#[cfg(target_arch = "aarch64")]
+pub fn slice_ended() -> bool {
+    SLICE_ENDED.swap(false, Ordering::SeqCst)
+}
@@ -1,129 +1,2296 @@
 use stack_vec::StackVec;
-use console::{kprint, kprintln, CONSOLE};
+use console::{kprint, kprintln, Console, Mode, CONSOLE, DEFERRED};
+use console::style::{self, Style};
+use console::{log_level, set_log_level, Level};
+use line::{self, Editor, History, Entry, HISTORY_LEN, MAX_LINE};
+use console::input::{Arrow, Decoder, Key, ESC_TIMEOUT_US};
+use hw::gpio::{self, Function};
+use hw::irq;
+use hw::timer::{current_time, current_time_us};
+use allocator;
+use env;
+use hotload;
+use klog::KLOG;
+use memory;
+use syscall;
+use timer;
+use mutex::Mutex;
+use fs;
+use {FILESYSTEM, SCHEDULER};
+use process::Id;
+use watchdog;
+use xmodem::Progress;
 use std::str;
-use std::io::Write;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use fat32::traits::{Dir, Entry as EntryTrait, File, FileSystem, Metadata, Timestamp};
+use std::fmt::{self, Write};
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::mem;
+use std::time::Duration;
 
-/// Error type for `Command` parse failures.
+/// Error type for `CommandLine` parse failures.
 #[derive(Debug)]
-enum Error {
+pub enum Error {
     Empty,
-    TooManyArgs
+    TooManyArgs,
+    /// A quote was opened and never closed.
+    UnterminatedQuote,
 }
 
-/// A structure representing a single shell command.
-struct Command<'a> {
-    args: StackVec<'a, &'a str>
+/// A structure representing a single line entered at the shell: a command
+/// name followed by its arguments.
+#[derive(Debug)]
+pub struct CommandLine<'a> {
+    args: StackVec<'a, &'a str>,
+    background: bool,
 }
 
-impl<'a> Command<'a> {
-    /// Parse a command from a string `s` using `buf` as storage for the
+impl<'a> CommandLine<'a> {
+    /// Parse a command line from a string `s` using `buf` as storage for the
     /// arguments.
     ///
+    /// Arguments are separated by spaces. Spaces inside single or double
+    /// quotes don't separate arguments, and outside of quotes a backslash
+    /// makes the character after it literal. Inside double quotes, a
+    /// backslash only escapes `"`, `\` and `$`; inside single quotes, nothing but
+    /// the closing quote is special. Quotes and escaping backslashes are
+    /// removed by rewriting `s` in place, so `echo "two  words"` has the
+    /// single argument `two  words`. A final, unquoted `&` isn't an argument:
+    /// it marks the line as one to run in the background.
+    ///
     /// # Errors
     ///
     /// If `s` contains no arguments, returns `Error::Empty`. If there are more
-    /// arguments than `buf` can hold, returns `Error::TooManyArgs`.
-    fn parse(s: &'a str, buf: &'a mut [&'a str]) -> Result<Command<'a>, Error> {
+    /// arguments than `buf` can hold, returns `Error::TooManyArgs`. If a quote
+    /// isn't closed, returns `Error::UnterminatedQuote`.
+    pub fn parse(s: &'a mut str, buf: &'a mut [&'a str]) -> Result<CommandLine<'a>, Error> {
         let mut args = StackVec::new(buf);
-        for arg in s.split(' ').filter(|a| !a.is_empty()) {
+
+        // Only ASCII bytes are removed, so each argument is valid UTF-8.
+        let mut rest: &'a mut [u8] = unsafe { s.as_bytes_mut() };
+        let mut background = false;
+        loop {
+            // Checked before unquoting so that a quoted `"&"` isn't a marker.
+            let ampersand = first_word(rest) == b"&";
+            let (len, end) = match unquote(rest)? {
+                Some(found) => found,
+                None => break,
+            };
+
+            background = ampersand;
+            let (word, tail) = mem::replace(&mut rest, &mut []).split_at_mut(end);
+            let arg = unsafe { str::from_utf8_unchecked(&word[..len]) };
             args.push(arg).map_err(|_| Error::TooManyArgs)?;
+            rest = tail;
+        }
+
+        if background {
+            args.pop();
         }
 
         if args.is_empty() {
             return Err(Error::Empty);
         }
 
-        Ok(Command { args })
+        Ok(CommandLine { args, background })
+    }
+
+    /// Returns `true` if the line ended with a bare `&`, asking for the
+    /// command to run in the background. The `&` isn't one of the arguments.
+    pub fn is_background(&self) -> bool {
+        self.background
     }
 
     /// Returns this command's path. This is equivalent to the first argument.
-    fn path(&self) -> &str {
+    pub fn path(&self) -> &str {
         self.args[0]
     }
 
-    fn execute(&self) -> bool {
-        match self.path() {
-            "echo" => handle_echo(&self.args[1..]),
-            path => kprintln!("Unknown command: {}", path)
+    /// Returns this command's arguments, excluding the path.
+    pub fn args(&self) -> &[&'a str] {
+        &self.args[1..]
+    }
+
+    /// Runs the command named by the path with the remaining arguments,
+    /// writing its output to `console`. If the arguments are malformed, the
+    /// command's usage is written too.
+    ///
+    /// # Errors
+    ///
+    /// Returns the command's failure, or `Failure::Reported` if there is no
+    /// such command.
+    pub fn execute(&self, console: &mut Console) -> Result<(), Failure> {
+        let command = match find(self.path()) {
+            Some(command) => command,
+            None => return fail(console, format_args!("Unknown command: {}; type 'help' for a list",
+                                                     self.path())),
+        };
+
+        let result = command.run(console, self.args());
+        if result == Err(Failure::Usage) {
+            writeln!(console, "usage: {}", command.help())?;
         }
-        true
+
+        result
     }
 }
 
-fn handle_echo(args: &[&str]) {
-    let len = args.len();
+/// The maximum number of arguments on a line, including the command name.
+pub const MAX_ARGS: usize = 64;
 
-    if len > 0 {
-        for s in args[..len - 1].iter() {
-            kprint!("{} ", s);
+/// Expands the variables in `line`, parses it, and runs the command on it,
+/// writing output and errors to `console`. A blank line runs nothing and
+/// succeeds.
+///
+/// # Errors
+///
+/// Returns `Failure::Reported` if the line can't be expanded or parsed, and
+/// the command's failure if it fails.
+pub fn run_line(console: &mut Console, line: &str) -> Result<(), Failure> {
+    let mut expanded = [0u8; MAX_LINE];
+    let len = match env::VARS.lock().expand(line, &mut expanded) {
+        Ok(len) => len,
+        Err(error) => return fail(console, format_args!("error: {}", error)),
+    };
+
+    let line = str::from_utf8_mut(&mut expanded[..len]).expect("expanded from a str");
+    let mut storage: [&str; MAX_ARGS] = [""; MAX_ARGS];
+    match CommandLine::parse(line, &mut storage) {
+        Ok(ref command) if command.is_background() => start_job(console, command),
+        Ok(command) => command.execute(console),
+        Err(Error::Empty) => Ok(()),
+        Err(Error::TooManyArgs) => fail(console, format_args!("error: too many arguments")),
+        Err(Error::UnterminatedQuote) => fail(console, format_args!("error: unterminated quote")),
+    }
+}
+
+/// Runs each line of `script` as if it had been entered at the shell,
+/// writing output to `console`. Lines whose first non-space character is `#`
+/// are comments and are skipped, as are blank lines.
+///
+/// # Errors
+///
+/// If a line fails, returns its number, counting from 1. If `stop_on_error`
+/// is `true`, no lines after it are run; otherwise the rest of the script
+/// still runs and the first failure is returned.
+pub fn run_script(console: &mut Console, script: &str, stop_on_error: bool) -> Result<(), usize> {
+    let mut first_failure = None;
+    for (i, line) in script.lines().enumerate() {
+        if line.trim_left().starts_with('#') {
+            continue;
+        }
+
+        if run_line(console, line).is_err() {
+            first_failure = first_failure.or(Some(i + 1));
+            if stop_on_error {
+                break;
+            }
         }
-        kprintln!("{}", args[len - 1]);
     }
-}   
 
-const BELL: u8 = 7;
-const BACKSPACE: u8 = 8;
-const DELETE: u8 = 127;
+    match first_failure {
+        Some(line) => Err(line),
+        None => Ok(()),
+    }
+}
 
-/// Starts a shell using `prefix` as the prefix for each line. This function
-/// never returns: it is perpetually in a shell loop.
-pub fn shell(prefix: &str)  {
-    
-    loop {
-        let mut buf_storage = [0u8; 512];
-        let mut buf = StackVec::new(&mut buf_storage);
+/// The script the shell thread runs when the kernel starts, if there is one.
+pub const INIT_SCRIPT: &str = "/boot/init.sh";
 
-        kprint!("{}", prefix);
+/// How deeply scripts may source other scripts, so that a script that sources
+/// itself fails instead of overflowing the stack.
+const MAX_SOURCE_DEPTH: usize = 8;
 
-        loop {
-            let byte = CONSOLE.lock().read_byte();
-            // the end of the cmd
-            if byte == b'\r' || byte == b'\n' {
-                kprintln!("this is my FIRST OS!!!!SOS!!!!");
-                let mut command_storage: [&str;64] = ["";64];
-                let result = Command::parse(
-                    str::from_utf8(buf.into_slice()).unwrap(),
-                    &mut command_storage
-                );
-                kprint!("\n");
-                match result {
-                    Err(Error::TooManyArgs) => {
-                        kprintln!("error: too many arguments");
-                    },
-                    Err(Error::Empty) => {
-                        // No command, ignore.
-                    },
-                    Ok(command) => {
-                        if !command.execute() {
-                            return;
-                        }
-                    },
+/// How deeply the script being run is nested in other scripts.
+static SOURCE_DEPTH: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Runs the script in the file at `path`, relative to the working directory,
+/// with `run_script()`, writing output and errors to `console`.
+///
+/// # Errors
+///
+/// Returns `Failure::Reported` if the file can't be read or a line of it
+/// fails.
+pub fn source(console: &mut Console, path: &str, stop_on_error: bool) -> Result<(), Failure> {
+    let mut file = match open_entry(console, "source", path)?.into_file() {
+        Some(file) => file,
+        None => return fail(console, format_args!("source: {}: is a directory", path)),
+    };
+
+    let mut script = vec![0; file.size() as usize];
+    if let Err(e) = file.read_exact(&mut script) {
+        return fail(console, format_args!("source: {}: {}", path, Reason(&e)));
+    }
+
+    let script = match String::from_utf8(script) {
+        Ok(script) => script,
+        Err(_) => return fail(console, format_args!("source: {}: not a text file", path)),
+    };
+
+    if SOURCE_DEPTH.fetch_add(1, Ordering::SeqCst) >= MAX_SOURCE_DEPTH {
+        SOURCE_DEPTH.fetch_sub(1, Ordering::SeqCst);
+        return fail(console, format_args!("source: {}: scripts are nested too deeply", path));
+    }
+
+    let result = run_script(console, &script, stop_on_error);
+    SOURCE_DEPTH.fetch_sub(1, Ordering::SeqCst);
+    match result {
+        Ok(()) => Ok(()),
+        Err(line) => fail(console, format_args!("source: {}: line {} failed", path, line)),
+    }
+}
+
+/// Runs `INIT_SCRIPT` with `source()`, if it exists, with output and errors
+/// written to the console. Failures don't stop the rest of the script.
+pub fn run_init_script() {
+    if (&FILESYSTEM).open(INIT_SCRIPT).is_err() {
+        return;
+    }
+
+    let mut console = CONSOLE.lock();
+    console.set_mode(Mode::Cooked);
+    // Failures have already been reported.
+    let _ = source(&mut console, INIT_SCRIPT, false);
+}
+
+/// Returns the first argument in `bytes` as written, quotes and all.
+fn first_word(bytes: &[u8]) -> &[u8] {
+    let start = bytes.iter().position(|&byte| byte != b' ').unwrap_or(bytes.len());
+    let end = bytes[start..].iter().position(|&byte| byte == b' ').map_or(bytes.len(), |n| start + n);
+    &bytes[start..end]
+}
+
+/// Unquotes the first argument in `bytes`, moving it to the front of `bytes`.
+/// Returns its unquoted length and the index just past it in `bytes`, or
+/// `None` if there are only spaces left.
+fn unquote(bytes: &mut [u8]) -> Result<Option<(usize, usize)>, Error> {
+    let mut read = match bytes.iter().position(|&byte| byte != b' ') {
+        Some(start) => start,
+        None => return Ok(None),
+    };
+
+    let mut len = 0;
+    let mut quote = None;
+    while read < bytes.len() {
+        let byte = bytes[read];
+        read += 1;
+
+        let literal = match (quote, byte) {
+            (None, b' ') => break,
+            (None, b'"') | (None, b'\'') => {
+                quote = Some(byte);
+                None
+            }
+            (Some(open), _) if byte == open => {
+                quote = None;
+                None
+            }
+            (Some(b'\''), _) => Some(byte),
+            (_, b'\\') if read < bytes.len()
+                && (quote.is_none() || b"\"\\$".contains(&bytes[read])) => {
+                read += 1;
+                Some(bytes[read - 1])
+            }
+            _ => Some(byte),
+        };
+
+        // The unquoted argument never outgrows what has been read.
+        if let Some(literal) = literal {
+            bytes[len] = literal;
+            len += 1;
+        }
+    }
+
+    if quote.is_some() {
+        return Err(Error::UnterminatedQuote);
+    }
+
+    Ok(Some((len, read)))
+}
+
+/// Why a command failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// The command's arguments were malformed. The shell prints its usage.
+    Usage,
+    /// The command failed and has already said why.
+    Reported,
+    /// Writing the command's output failed.
+    Output,
+}
+
+impl From<fmt::Error> for Failure {
+    fn from(_: fmt::Error) -> Failure {
+        Failure::Output
+    }
+}
+
+/// A command that can be run from the shell.
+///
+/// The shell's own commands are built in; other subsystems make theirs
+/// available with `register()`. Commands run with the console locked, so they
+/// must write through the console they are passed rather than with `kprint!`.
+pub trait Command: Sync {
+    /// Returns the name the command is run by.
+    fn name(&self) -> &'static str;
+
+    /// Returns a one-line summary of how the command is used, such as
+    /// `peek [-8|-16|-32|-64] <addr> [count]`.
+    fn help(&self) -> &'static str;
+
+    /// Returns a one-line description of what the command does, for `help`.
+    fn summary(&self) -> &'static str;
+
+    /// Returns a longer description of the command's arguments and behavior,
+    /// printed by `help <command>`. Empty by default.
+    fn details(&self) -> &'static str {
+        ""
+    }
+
+    /// Runs the command with `args`, the arguments following its name,
+    /// writing any output to `console`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Failure::Usage` if `args` are malformed, or
+    /// `Failure::Reported` once the command has written why it failed.
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure>;
+}
+
+/// The maximum number of commands that can be registered with `register()`,
+/// in addition to the built-in ones.
+pub const MAX_COMMANDS: usize = 16;
+
+/// The built-in commands, sorted by name.
+static BUILTINS: &[&Command] = &[
+    &Cat, &Cd, &Color, &Cp, &Crash, &Dmesg, &Echo, &Fg, &Free, &Fsck, &Gpio, &Halt, &Help,
+    &Jobs, &LogLevel, &Ls, &Memmap, &Mkdir, &Mount, &Mv, &Peek, &Poke, &Pwd, &Reboot, &Rm, &Run,
+    &Set, &Source, &Stat, &Touch, &Unset, &Uptime, &Watchdog, &XmodemRecv, &Xxd,
+];
+
+/// The commands registered with `register()`, in the order they were.
+static REGISTERED: Mutex<[Option<&'static Command>; MAX_COMMANDS]> = Mutex::new([None; MAX_COMMANDS]);
+
+/// Makes `command` available from the shell.
+///
+/// # Errors
+///
+/// Returns `Err(())` if a command with the same name already exists or
+/// `MAX_COMMANDS` commands are already registered.
+pub fn register(command: &'static Command) -> Result<(), ()> {
+    let mut registered = REGISTERED.lock();
+    let taken = BUILTINS.iter().cloned()
+        .chain(registered.iter().filter_map(|slot| *slot))
+        .any(|existing| existing.name() == command.name());
+    if taken {
+        return Err(());
+    }
+
+    match registered.iter_mut().find(|slot| slot.is_none()) {
+        Some(slot) => {
+            *slot = Some(command);
+            Ok(())
+        }
+        None => Err(()),
+    }
+}
+
+/// An iterator over every command the shell can run: the built-in commands,
+/// then the registered ones.
+pub struct Commands {
+    registered: [Option<&'static Command>; MAX_COMMANDS],
+    next: usize,
+}
+
+impl Iterator for Commands {
+    type Item = &'static Command;
+
+    fn next(&mut self) -> Option<&'static Command> {
+        while self.next < BUILTINS.len() + MAX_COMMANDS {
+            let i = self.next;
+            self.next += 1;
+            let command = if i < BUILTINS.len() {
+                Some(BUILTINS[i])
+            } else {
+                self.registered[i - BUILTINS.len()]
+            };
+
+            if command.is_some() {
+                return command;
+            }
+        }
+
+        None
+    }
+}
+
+/// Returns an iterator over every command the shell can run. Commands
+/// registered after this is called aren't included.
+pub fn commands() -> Commands {
+    Commands { registered: *REGISTERED.lock(), next: 0 }
+}
+
+/// Returns the command named `name`, if there is one.
+pub fn find(name: &str) -> Option<&'static Command> {
+    commands().find(|command| command.name() == name)
+}
+
+struct Help;
+
+impl Command for Help {
+    fn name(&self) -> &'static str { "help" }
+    fn help(&self) -> &'static str { "help [command]" }
+    fn summary(&self) -> &'static str { "list the commands, or describe one" }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        match args {
+            &[] => {
+                let width = commands().map(|command| command.name().len()).max().unwrap_or(0);
+                for command in commands() {
+                    writeln!(console, "  {:w$}  {}", command.name(), command.summary(), w = width)?;
                 }
 
-                break;
+                Ok(())
             }
-            // store the cmd to the buffer
-            else {
-                let mut console = CONSOLE.lock();
-                if byte == BACKSPACE || byte == DELETE {
-                    // Handle backspace and delete to erase a single character
-                    if buf.pop() == None {
-                        console.write_byte(BELL);
+            &[name] => match find(name) {
+                Some(command) => {
+                    writeln!(console, "usage: {}", command.help())?;
+                    writeln!(console, "{}", command.summary())?;
+                    if !command.details().is_empty() {
+                        writeln!(console, "\n{}", command.details())?;
                     }
-                    else {
-                        console.write(&[BACKSPACE, b' ', BACKSPACE]).expect("write to delte the terminal");
-                    }
-                } else if byte < 32 || byte == 255 {
-                    //Discard non-printable characters and send an alert
-                    console.write_byte(BELL);
-                }  else {
-                    if buf.push(byte).is_err() {
-                        console.write_byte(BELL);
-                    } else {
-                        console.write_byte(byte);
+
+                    Ok(())
+                }
+                None => fail(console, format_args!("help: no command named '{}'", name)),
+            },
+            _ => Err(Failure::Usage),
+        }
+    }
+}
+
+/// Writes `args`, in red, and a newline to `console`, then fails with
+/// `Failure::Reported`.
+fn fail<T>(console: &mut Console, args: fmt::Arguments) -> Result<T, Failure> {
+    writeln!(console, "{}", style::paint(Style::Red, args))?;
+    Err(Failure::Reported)
+}
+
+struct Color;
+
+impl Command for Color {
+    fn name(&self) -> &'static str { "color" }
+    fn help(&self) -> &'static str { "color [on|off]" }
+    fn summary(&self) -> &'static str { "show or set whether output is colored" }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        match args {
+            &[] => {
+                writeln!(console, "color: {}", if style::enabled() { "on" } else { "off" })?;
+            }
+            &["on"] => style::set_enabled(true),
+            &["off"] => style::set_enabled(false),
+            _ => return Err(Failure::Usage),
+        }
+
+        Ok(())
+    }
+}
+
+struct LogLevel;
+
+impl Command for LogLevel {
+    fn name(&self) -> &'static str { "loglevel" }
+    fn help(&self) -> &'static str { "loglevel [trace|debug|info|warn|error]" }
+    fn summary(&self) -> &'static str { "show or set the least important level logged" }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        match args {
+            &[] => writeln!(console, "loglevel: {}", log_level().name())?,
+            &[name] => match Level::from_name(name) {
+                Some(level) => set_log_level(level),
+                None => return Err(Failure::Usage),
+            },
+            _ => return Err(Failure::Usage),
+        }
+
+        Ok(())
+    }
+}
+
+struct Dmesg;
+
+impl Command for Dmesg {
+    fn name(&self) -> &'static str { "dmesg" }
+    fn help(&self) -> &'static str { "dmesg [-c]" }
+    fn summary(&self) -> &'static str { "print the kernel log" }
+
+    fn details(&self) -> &'static str {
+        "The kernel log holds the most recent output of kprint! and the logging\n\
+         macros. With -c, the log is cleared after it is printed."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let clear = match args {
+            &[] => false,
+            &["-c"] => true,
+            _ => return Err(Failure::Usage),
+        };
+
+        let mut log = KLOG.lock();
+        {
+            let (first, second) = log.lines();
+            for &byte in first.iter().chain(second.iter()) {
+                if byte == b'\n' {
+                    console.write_byte(b'\r');
+                }
+
+                console.write_byte(byte);
+            }
+        }
+
+        if clear {
+            log.clear();
+        }
+
+        Ok(())
+    }
+}
+
+struct Echo;
+
+impl Command for Echo {
+    fn name(&self) -> &'static str { "echo" }
+    fn help(&self) -> &'static str { "echo [arg...]" }
+    fn summary(&self) -> &'static str { "print the arguments" }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let len = args.len();
+        if len > 0 {
+            for s in args[..len - 1].iter() {
+                write!(console, "{} ", s)?;
+            }
+            writeln!(console, "{}", args[len - 1])?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Free;
+
+impl Command for Free {
+    fn name(&self) -> &'static str { "free" }
+    fn help(&self) -> &'static str { "free" }
+    fn summary(&self) -> &'static str { "show how much of the heap is in use" }
+
+    fn details(&self) -> &'static str {
+        "Prints the heap's size, the bytes in use now and at most, the bytes\n\
+         allocated and freed since boot, and the number of allocations of each\n\
+         power-of-two size class."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if !args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        match allocator::stats() {
+            Some(stats) => Ok(write!(console, "{}", stats)?),
+            None => fail(console, format_args!("the heap isn't initialized")),
+        }
+    }
+}
+
+struct Fsck;
+
+impl Command for Fsck {
+    fn name(&self) -> &'static str { "fsck" }
+    fn help(&self) -> &'static str { "fsck [-r] [path]" }
+    fn summary(&self) -> &'static str { "check a file system for inconsistencies" }
+
+    fn details(&self) -> &'static str {
+        "Checks the FAT file system holding path, / by default, for entries\n\
+         naming invalid clusters, broken and cross-linked chains, files whose\n\
+         sizes don't match their chains, lost clusters and a wrong free count.\n\
+         -r frees lost clusters, trims chains longer than their files and\n\
+         recounts the free clusters; nothing else is repaired."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let (flags, paths) = split_flags(args, "r")?;
+        let path = match paths {
+            &[] => "/",
+            &[path] => path,
+            _ => return Err(Failure::Usage),
+        };
+
+        let report = match FILESYSTEM.check(absolute_path(path), flags.contains(&'r')) {
+            Ok(report) => report,
+            Err(e) => return fail(console, format_args!("fsck: {}: {}", path, Reason(&e))),
+        };
+
+        for problem in report.problems.iter() {
+            let repaired = report.repaired > 0 && problem.is_trivial();
+            writeln!(console, "{}{}", problem, if repaired { " (repaired)" } else { "" })?;
+        }
+
+        writeln!(console, "{} directories, {} files, {} clusters used, {} free",
+                 report.dirs, report.files, report.used, report.free)?;
+        match report.problems.len() {
+            0 => Ok(()),
+            _ if report.is_clean() => Ok(writeln!(console, "every problem was repaired")?),
+            n => fail(console, format_args!("fsck: {} of {} problems are unrepaired",
+                                            n - report.repaired, n)),
+        }
+    }
+}
+
+struct Memmap;
+
+impl Command for Memmap {
+    fn name(&self) -> &'static str { "memmap" }
+    fn help(&self) -> &'static str { "memmap" }
+    fn summary(&self) -> &'static str { "show the layout of physical memory" }
+
+    fn details(&self) -> &'static str {
+        "Lists the kernel's sections, the heap, the frames, the DMA pool, the\n\
+         framebuffer and the device registers, with their addresses, sizes and\n\
+         intended permissions."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if !args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        Ok(memory::write_map(console, &memory::map())?)
+    }
+}
+
+struct Mount;
+
+impl Command for Mount {
+    fn name(&self) -> &'static str { "mount" }
+    fn help(&self) -> &'static str { "mount" }
+    fn summary(&self) -> &'static str { "list the mounted file systems" }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if !args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        let mounts = FILESYSTEM.mounts();
+        if mounts.is_empty() {
+            return fail(console, format_args!("mount: nothing is mounted"));
+        }
+
+        for (path, kind) in mounts {
+            writeln!(console, "{} on {}", kind, path.display())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The shell's current working directory, which relative paths are resolved
+/// against. `None` is the root directory.
+static CWD: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Returns the shell's current working directory.
+pub fn cwd() -> PathBuf {
+    CWD.lock().clone().unwrap_or_else(|| PathBuf::from("/"))
+}
+
+/// Returns `path` made absolute against the current working directory, with
+/// its `.` and `..` components resolved.
+pub fn absolute_path(path: &str) -> PathBuf {
+    fs::normalize(&cwd().join(path)).expect("path was joined onto an absolute path")
+}
+
+/// Describes a file system error the way the file commands report it.
+struct Reason<'a>(&'a io::Error);
+
+impl<'a> fmt::Display for Reason<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0.kind() {
+            io::ErrorKind::NotFound => write!(f, "no such file or directory"),
+            io::ErrorKind::AlreadyExists => write!(f, "file exists"),
+            io::ErrorKind::PermissionDenied => write!(f, "read-only file system"),
+            _ => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Opens the entry at `path`, relative to the current working directory. On
+/// failure, reports `command: path: reason`.
+fn open_entry(console: &mut Console, command: &str, path: &str) -> Result<fs::Entry, Failure> {
+    match (&FILESYSTEM).open(absolute_path(path)) {
+        Ok(entry) => Ok(entry),
+        Err(e) => fail(console, format_args!("{}: {}: {}", command, path, Reason(&e))),
+    }
+}
+
+/// Checks that the parent of `path`, the absolute form of `arg`, is a
+/// directory, so that creating `path` fails with a clearer message than the
+/// file system's.
+fn check_parent(console: &mut Console, command: &str, arg: &str, path: &Path) -> Result<(), Failure> {
+    let parent = path.parent().unwrap_or(path);
+    match (&FILESYSTEM).open(parent) {
+        Ok(ref entry) if entry.is_dir() => Ok(()),
+        Ok(_) => fail(console, format_args!("{}: {}: {} is not a directory",
+                                            command, arg, parent.display())),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            fail(console, format_args!("{}: {}: no such directory {}",
+                                       command, arg, parent.display()))
+        }
+        Err(e) => fail(console, format_args!("{}: {}: {}", command, arg, Reason(&e))),
+    }
+}
+
+/// Returns the path `mv` and `cp` write `from` to: `to` itself, or `from`'s
+/// name inside `to` if `to` is a directory.
+fn destination(from: &Path, to: &str) -> PathBuf {
+    let to = absolute_path(to);
+    match ((&FILESYSTEM).open(&to), from.file_name()) {
+        (Ok(ref entry), Some(name)) if entry.is_dir() => to.join(name),
+        _ => to,
+    }
+}
+
+/// Splits the leading flags, like `-a -l` or `-al`, off of `args`. Every
+/// flag must be one of the characters of `allowed`.
+fn split_flags<'a, 'b>(args: &'a [&'b str], allowed: &str) -> Result<(Vec<char>, &'a [&'b str]), Failure> {
+    let mut flags = Vec::new();
+    let mut rest = args;
+    while let Some((&arg, tail)) = rest.split_first() {
+        if arg.len() < 2 || !arg.starts_with('-') {
+            break;
+        }
+
+        for flag in arg[1..].chars() {
+            if !allowed.contains(flag) {
+                return Err(Failure::Usage);
+            }
+
+            flags.push(flag);
+        }
+
+        rest = tail;
+    }
+
+    Ok((flags, rest))
+}
+
+/// Returns `true` if `ls` leaves `entry` out unless given `-a`.
+fn is_hidden(entry: &fs::Entry) -> bool {
+    entry.name().starts_with('.') || entry.metadata().hidden()
+}
+
+/// Writes the attributes of `entry` as flags: `d` for a directory, `r` for
+/// read only, `h` for hidden, `s` for system and `a` for archive, or `-` for
+/// each that isn't set.
+fn write_attributes(console: &mut Console, entry: &fs::Entry) -> fmt::Result {
+    let metadata = entry.metadata();
+    let flags = [
+        (entry.is_dir(), 'd'),
+        (metadata.read_only(), 'r'),
+        (metadata.hidden(), 'h'),
+        (metadata.system(), 's'),
+        (metadata.archive(), 'a'),
+    ];
+
+    for &(set, flag) in flags.iter() {
+        console.write_char(if set { flag } else { '-' })?;
+    }
+
+    Ok(())
+}
+
+/// Writes the line `ls` shows for `entry`. Directories have a trailing `/`.
+fn write_ls_entry(console: &mut Console, entry: &fs::Entry, long: bool) -> fmt::Result {
+    let suffix = if entry.is_dir() { "/" } else { "" };
+    if long {
+        let size = entry.as_file().map_or(0, |file| file.size());
+        write_attributes(console, entry)?;
+        writeln!(console, " {} {:>10} {}{}", entry.metadata().modified().date_time(), size,
+                 entry.name(), suffix)
+    } else {
+        writeln!(console, "{}{}", entry.name(), suffix)
+    }
+}
+
+struct Cat;
+
+impl Command for Cat {
+    fn name(&self) -> &'static str { "cat" }
+    fn help(&self) -> &'static str { "cat <path...>" }
+    fn summary(&self) -> &'static str { "print the contents of files" }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        for &path in args {
+            let mut file = match open_entry(console, "cat", path)?.into_file() {
+                Some(file) => file,
+                None => return fail(console, format_args!("cat: {}: is a directory", path)),
+            };
+
+            let mut buf = [0u8; 512];
+            loop {
+                let n = match file.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(e) => return fail(console, format_args!("cat: {}: {}", path, Reason(&e))),
+                };
+
+                for &byte in &buf[..n] {
+                    if byte == b'\n' {
+                        console.write_byte(b'\r');
                     }
-                }     
+
+                    console.write_byte(byte);
+                }
             }
         }
+
+        Ok(())
+    }
+}
+
+struct Cd;
+
+impl Command for Cd {
+    fn name(&self) -> &'static str { "cd" }
+    fn help(&self) -> &'static str { "cd [dir]" }
+    fn summary(&self) -> &'static str { "change the working directory" }
+
+    fn details(&self) -> &'static str {
+        "Changes to '/' if no directory is given. Paths that don't start with\n\
+         '/' are relative to the working directory; '.' is the directory\n\
+         itself and '..' its parent."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let path = match args {
+            &[] => "/",
+            &[path] => path,
+            _ => return Err(Failure::Usage),
+        };
+
+        if !open_entry(console, "cd", path)?.is_dir() {
+            return fail(console, format_args!("cd: {}: not a directory", path));
+        }
+
+        *CWD.lock() = Some(absolute_path(path));
+        Ok(())
+    }
+}
+
+struct Cp;
+
+impl Command for Cp {
+    fn name(&self) -> &'static str { "cp" }
+    fn help(&self) -> &'static str { "cp <from> <to>" }
+    fn summary(&self) -> &'static str { "copy a file" }
+
+    fn details(&self) -> &'static str {
+        "If 'to' is a directory, the copy is made inside it under the name of\n\
+         'from'. An existing file at the destination is replaced unless it is\n\
+         read only."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let (from_arg, to_arg) = match args {
+            &[from, to] => (from, to),
+            _ => return Err(Failure::Usage),
+        };
+
+        let mut source = match open_entry(console, "cp", from_arg)?.into_file() {
+            Some(file) => file,
+            None => return fail(console, format_args!("cp: {}: is a directory", from_arg)),
+        };
+
+        let from = absolute_path(from_arg);
+        let to = destination(&from, to_arg);
+        let same = match (from.to_str(), to.to_str()) {
+            (Some(from), Some(to)) => from.eq_ignore_ascii_case(to),
+            _ => false,
+        };
+
+        if same {
+            return fail(console, format_args!("cp: {} and {} are the same file", from_arg, to_arg));
+        }
+
+        match (&FILESYSTEM).open(&to) {
+            Ok(ref entry) if entry.is_dir() => {
+                return fail(console, format_args!("cp: {}: is a directory", to.display()));
+            }
+            Ok(ref entry) if entry.metadata().read_only() => {
+                return fail(console, format_args!("cp: {}: file is read only", to.display()));
+            }
+            Ok(_) => {
+                if let Err(e) = (&FILESYSTEM).remove(&to, false) {
+                    return fail(console, format_args!("cp: {}: {}", to.display(), Reason(&e)));
+                }
+            }
+            Err(_) => check_parent(console, "cp", to_arg, &to)?,
+        }
+
+        let result = (&FILESYSTEM).create_file(&to).and_then(|mut file| {
+            io::copy(&mut source, &mut file)?;
+            io::Write::flush(&mut file)
+        });
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => fail(console, format_args!("cp: {}: {}", to.display(), Reason(&e))),
+        }
+    }
+}
+
+struct Ls;
+
+impl Command for Ls {
+    fn name(&self) -> &'static str { "ls" }
+    fn help(&self) -> &'static str { "ls [-a] [-l] [path...]" }
+    fn summary(&self) -> &'static str { "list the entries of directories" }
+
+    fn details(&self) -> &'static str {
+        "Lists the working directory if no path is given. -a includes the\n\
+         entries whose names start with '.' or that are marked hidden. -l shows\n\
+         each entry's attributes, 'd' for a directory, 'r' for read only, 'h'\n\
+         for hidden, 's' for system and 'a' for archive, its modification time\n\
+         and its size. Directories are listed with a trailing '/'."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let (flags, paths) = split_flags(args, "al")?;
+        let (all, long) = (flags.contains(&'a'), flags.contains(&'l'));
+        let paths = if paths.is_empty() { &["."][..] } else { paths };
+        let mut result = Ok(());
+        for (i, &path) in paths.iter().enumerate() {
+            let entry = match open_entry(console, "ls", path) {
+                Ok(entry) => entry,
+                Err(failure) => {
+                    result = Err(failure);
+                    continue;
+                }
+            };
+
+            let dir = match entry.as_dir() {
+                Some(dir) => dir,
+                None => {
+                    write_ls_entry(console, &entry, long)?;
+                    continue;
+                }
+            };
+
+            if paths.len() > 1 {
+                if i > 0 {
+                    writeln!(console)?;
+                }
+
+                writeln!(console, "{}:", path)?;
+            }
+
+            match dir.entries() {
+                Ok(entries) => {
+                    for entry in entries.filter(|entry| all || !is_hidden(entry)) {
+                        write_ls_entry(console, &entry, long)?;
+                    }
+                }
+                Err(e) => result = fail(console, format_args!("ls: {}: {}", path, Reason(&e))),
+            }
+        }
+
+        result
+    }
+}
+
+struct Mkdir;
+
+impl Command for Mkdir {
+    fn name(&self) -> &'static str { "mkdir" }
+    fn help(&self) -> &'static str { "mkdir [-p] <dir...>" }
+    fn summary(&self) -> &'static str { "create directories" }
+
+    fn details(&self) -> &'static str {
+        "-p creates missing parent directories as well, and doesn't fail if\n\
+         the directory already exists."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let (flags, paths) = split_flags(args, "p")?;
+        let parents = flags.contains(&'p');
+        if paths.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        for &arg in paths {
+            let path = absolute_path(arg);
+            if !parents {
+                check_parent(console, "mkdir", arg, &path)?;
+            }
+
+            if let Err(e) = (&FILESYSTEM).create_dir(&path, parents) {
+                return fail(console, format_args!("mkdir: {}: {}", arg, Reason(&e)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Mv;
+
+impl Command for Mv {
+    fn name(&self) -> &'static str { "mv" }
+    fn help(&self) -> &'static str { "mv <from> <to>" }
+    fn summary(&self) -> &'static str { "move or rename a file or directory" }
+
+    fn details(&self) -> &'static str {
+        "If 'to' is a directory, 'from' is moved inside it. Nothing is\n\
+         replaced: 'to' must not exist otherwise. Both must be on the same\n\
+         file system."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let (from_arg, to_arg) = match args {
+            &[from, to] => (from, to),
+            _ => return Err(Failure::Usage),
+        };
+
+        open_entry(console, "mv", from_arg)?;
+        let from = absolute_path(from_arg);
+        let to = destination(&from, to_arg);
+        if (&FILESYSTEM).open(&to).is_err() {
+            check_parent(console, "mv", to_arg, &to)?;
+        }
+
+        match (&FILESYSTEM).rename(&from, &to) {
+            Ok(()) => Ok(()),
+            Err(e) => fail(console, format_args!("mv: {}: {}", to.display(), Reason(&e))),
+        }
+    }
+}
+
+struct Pwd;
+
+impl Command for Pwd {
+    fn name(&self) -> &'static str { "pwd" }
+    fn help(&self) -> &'static str { "pwd" }
+    fn summary(&self) -> &'static str { "print the working directory" }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if !args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        writeln!(console, "{}", cwd().display())?;
+        Ok(())
+    }
+}
+
+struct Rm;
+
+impl Command for Rm {
+    fn name(&self) -> &'static str { "rm" }
+    fn help(&self) -> &'static str { "rm [-r] [-f] <path...>" }
+    fn summary(&self) -> &'static str { "remove files and directories" }
+
+    fn details(&self) -> &'static str {
+        "-r removes directories and everything in them. -f removes entries\n\
+         marked read only as well."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let (flags, paths) = split_flags(args, "rf")?;
+        let (recursive, force) = (flags.contains(&'r'), flags.contains(&'f'));
+        if paths.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        for &arg in paths {
+            let entry = open_entry(console, "rm", arg)?;
+            let path = absolute_path(arg);
+            if path.parent().is_none() {
+                return fail(console, format_args!("rm: {}: can't remove the root directory", arg));
+            } else if entry.is_dir() && !recursive {
+                return fail(console, format_args!("rm: {}: is a directory", arg));
+            } else if entry.metadata().read_only() && !force {
+                return fail(console, format_args!("rm: {}: file is read only", arg));
+            }
+
+            drop(entry);
+            if let Err(e) = (&FILESYSTEM).remove(&path, recursive) {
+                return fail(console, format_args!("rm: {}: {}", arg, Reason(&e)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Run;
+
+impl Command for Run {
+    fn name(&self) -> &'static str { "run" }
+    fn help(&self) -> &'static str { "run <path>" }
+    fn summary(&self) -> &'static str { "run a program and wait for it to exit" }
+
+    fn details(&self) -> &'static str {
+        "Loads the position-independent AArch64 ELF executable at <path>\n\
+         into a process of its own, runs it in EL0, and waits for it to\n\
+         exit. Fails if it exits with a nonzero code. With a final '&', it\n\
+         runs in the background instead: see 'jobs' and 'fg'."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let arg = match args {
+            &[arg] => arg,
+            _ => return Err(Failure::Usage),
+        };
+
+        let path = absolute_path(arg);
+        let path = path.to_str().expect("the path was made from a str");
+        let exited = syscall::spawn(path).and_then(wait_for_child);
+        match exited {
+            Ok(0) => Ok(()),
+            Ok(code) => fail(console, format_args!("run: {}: exited with code {}", arg, code)),
+            Err(e) => fail(console, format_args!("run: {}: {}", arg, e)),
+        }
+    }
+}
+
+/// A program started in the background with `run <path> &`.
+struct Job {
+    pid: Id,
+    /// The path as it was given to `run`.
+    path: String,
+}
+
+/// The background jobs that haven't been waited for, oldest first.
+static JOBS: Mutex<Option<Vec<Job>>> = Mutex::new(None);
+
+/// Starts the program the background job line `command` runs without waiting
+/// for it, and writes its ID to `console`. Only `run <path> &` can be started
+/// in the background: the built-in commands run in the shell itself.
+fn start_job(console: &mut Console, command: &CommandLine) -> Result<(), Failure> {
+    if command.path() != Run.name() {
+        return fail(console, format_args!("error: only programs can run in the background; \
+                                           use 'run <path> &'"));
+    }
+
+    let arg = match command.args() {
+        &[arg] => arg,
+        _ => {
+            writeln!(console, "usage: {} &", Run.help())?;
+            return Err(Failure::Usage);
+        }
+    };
+
+    let path = absolute_path(arg);
+    let path = path.to_str().expect("the path was made from a str");
+    match syscall::spawn(path) {
+        Ok(pid) => {
+            JOBS.lock().get_or_insert_with(Vec::new).push(Job { pid, path: arg.to_string() });
+            Ok(writeln!(console, "[{}] {}", pid, arg)?)
+        }
+        Err(e) => fail(console, format_args!("run: {}: {}", arg, e)),
+    }
+}
+
+/// Waits for the child process `pid` to exit and returns its exit code. The
+/// shell's liveness flag is suspended meanwhile, as the program may run for
+/// as long as it likes.
+fn wait_for_child(pid: Id) -> Result<u32, syscall::Error> {
+    let alive = *ALIVE.lock();
+    if let Some(flag) = alive {
+        watchdog::suspend(flag);
+    }
+
+    let exited = syscall::wait(pid);
+    if let Some(flag) = alive {
+        watchdog::resume(flag);
+    }
+
+    exited
+}
+
+/// Waits for the background job `pid` and forgets it. Returns its exit code.
+fn wait_for_job(pid: Id) -> Result<u32, syscall::Error> {
+    let exited = wait_for_child(pid);
+    if let Some(ref mut jobs) = *JOBS.lock() {
+        jobs.retain(|job| job.pid != pid);
+    }
+
+    exited
+}
+
+struct Jobs;
+
+impl Command for Jobs {
+    fn name(&self) -> &'static str { "jobs" }
+    fn help(&self) -> &'static str { "jobs" }
+    fn summary(&self) -> &'static str { "list the programs running in the background" }
+
+    fn details(&self) -> &'static str {
+        "Lists the programs started with 'run <path> &' by ID. Those that have\n\
+         exited are listed once more, with their exit codes, and then\n\
+         forgotten."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if !args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        let jobs: Vec<(Id, String)> = match *JOBS.lock() {
+            Some(ref jobs) => jobs.iter().map(|job| (job.pid, job.path.clone())).collect(),
+            None => Vec::new(),
+        };
+
+        for (pid, path) in jobs {
+            match SCHEDULER.exit_code(pid) {
+                Some(code) => {
+                    // It has exited, so this doesn't wait.
+                    let _ = wait_for_job(pid);
+                    writeln!(console, "[{}] done, exit code {}  {}", pid, code, path)?;
+                }
+                None => writeln!(console, "[{}] running  {}", pid, path)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Fg;
+
+impl Command for Fg {
+    fn name(&self) -> &'static str { "fg" }
+    fn help(&self) -> &'static str { "fg [id]" }
+    fn summary(&self) -> &'static str { "wait for a background program to exit" }
+
+    fn details(&self) -> &'static str {
+        "Waits for the background job with the ID 'jobs' lists, the newest\n\
+         by default, to exit. Fails if it exits with a nonzero code."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let newest = JOBS.lock().as_ref().and_then(|jobs| jobs.last().map(|job| job.pid));
+        let pid = match args {
+            &[] => match newest {
+                Some(pid) => pid,
+                None => return fail(console, format_args!("fg: no background jobs")),
+            },
+            &[arg] => match arg.parse::<Id>() {
+                Ok(pid) if JOBS.lock().as_ref().map_or(false, |jobs| {
+                    jobs.iter().any(|job| job.pid == pid)
+                }) => pid,
+                _ => return fail(console, format_args!("fg: {}: no such job", arg)),
+            },
+            _ => return Err(Failure::Usage),
+        };
+
+        match wait_for_job(pid) {
+            Ok(0) => Ok(()),
+            Ok(code) => fail(console, format_args!("fg: [{}]: exited with code {}", pid, code)),
+            Err(e) => fail(console, format_args!("fg: [{}]: {}", pid, e)),
+        }
+    }
+}
+
+struct Source;
+
+impl Command for Source {
+    fn name(&self) -> &'static str { "source" }
+    fn help(&self) -> &'static str { "source [-e] <path>" }
+    fn summary(&self) -> &'static str { "run the commands in a file" }
+
+    fn details(&self) -> &'static str {
+        "Runs each line of the file at <path> as if it had been entered at the\n\
+         shell. Blank lines and lines starting with # are skipped. With -e,\n\
+         the script stops at the first line that fails. At startup, the shell\n\
+         runs /boot/init.sh this way if it exists."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let (flags, paths) = split_flags(args, "e")?;
+        match paths {
+            &[path] => source(console, path, flags.contains(&'e')),
+            _ => Err(Failure::Usage),
+        }
+    }
+}
+
+struct Stat;
+
+impl Command for Stat {
+    fn name(&self) -> &'static str { "stat" }
+    fn help(&self) -> &'static str { "stat <path...>" }
+    fn summary(&self) -> &'static str { "describe files and directories" }
+
+    fn details(&self) -> &'static str {
+        "Shows each entry's type, size, attributes and timestamps. FAT32 only\n\
+         records the date of the last access."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        for (i, &path) in args.iter().enumerate() {
+            let entry = open_entry(console, "stat", path)?;
+            let metadata = entry.metadata();
+            if i > 0 {
+                writeln!(console)?;
+            }
+
+            writeln!(console, "      path: {}", absolute_path(path).display())?;
+            match entry.as_file() {
+                Some(file) => writeln!(console, "      type: file, {} bytes", file.size())?,
+                None => writeln!(console, "      type: directory")?,
+            }
+
+            write!(console, "attributes: ")?;
+            write_attributes(console, &entry)?;
+            writeln!(console, "\n  modified: {}", metadata.modified().date_time())?;
+            writeln!(console, "   created: {}", metadata.created().date_time())?;
+            let accessed = metadata.accessed().date_time();
+            writeln!(console, "  accessed: {}-{:02}-{:02}",
+                     accessed.year, accessed.month, accessed.day)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Touch;
+
+impl Command for Touch {
+    fn name(&self) -> &'static str { "touch" }
+    fn help(&self) -> &'static str { "touch <path...>" }
+    fn summary(&self) -> &'static str { "create empty files" }
+
+    fn details(&self) -> &'static str {
+        "Files that already exist are left as they are."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        for &arg in args {
+            let path = absolute_path(arg);
+            if (&FILESYSTEM).open(&path).is_ok() {
+                continue;
+            }
+
+            check_parent(console, "touch", arg, &path)?;
+            if let Err(e) = (&FILESYSTEM).create_file(&path) {
+                return fail(console, format_args!("touch: {}: {}", arg, Reason(&e)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Set;
+
+impl Command for Set {
+    fn name(&self) -> &'static str { "set" }
+    fn help(&self) -> &'static str { "set [NAME=value...]" }
+    fn summary(&self) -> &'static str { "set shell variables, or list them" }
+
+    fn details(&self) -> &'static str {
+        "With no arguments, lists every variable. $NAME in a command line is\n\
+         replaced by the variable's value, except inside single quotes."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let mut vars = env::VARS.lock();
+        if args.is_empty() {
+            for (name, value) in vars.iter() {
+                writeln!(console, "{}={}", name, value)?;
+            }
+
+            return Ok(());
+        }
+
+        if args.iter().any(|arg| !arg.contains('=')) {
+            return Err(Failure::Usage);
+        }
+
+        for arg in args {
+            let (name, value) = arg.split_at(arg.find('=').expect("checked above"));
+            if let Err(error) = vars.set(name, &value[1..]) {
+                return fail(console, format_args!("set: {}: {}", name, error));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Unset;
+
+impl Command for Unset {
+    fn name(&self) -> &'static str { "unset" }
+    fn help(&self) -> &'static str { "unset NAME..." }
+    fn summary(&self) -> &'static str { "remove shell variables" }
+
+    fn run(&self, _: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        let mut vars = env::VARS.lock();
+        for name in args {
+            vars.unset(name);
+        }
+
+        Ok(())
+    }
+}
+
+/// Feeds `decoder` the bytes `next_byte` returns until they complete a key,
+/// and returns it. `next_byte` returns `None` if no byte is available yet;
+/// `keep_alive()` is called while waiting. An escape sequence that stops
+/// arriving for `ESC_TIMEOUT_US` is given up on.
+fn wait_for_key<F: FnMut() -> Option<u8>>(decoder: &mut Decoder, mut next_byte: F) -> Key {
+    let mut last_byte = current_time_us();
+    loop {
+        match next_byte() {
+            Some(byte) => {
+                last_byte = current_time_us();
+                if let Some(key) = decoder.feed(byte) {
+                    return key;
+                }
+            }
+            None => {
+                if decoder.is_pending()
+                    && current_time_us().wrapping_sub(last_byte) >= ESC_TIMEOUT_US {
+                    if let Some(key) = decoder.flush() {
+                        return key;
+                    }
+                }
+
+                keep_alive();
+            }
+        }
+    }
+}
+
+/// Blocks until a key is pressed on the console and returns it, writing
+/// deferred interrupt output while waiting. The console is only locked while
+/// it is checked for input.
+fn read_line_key(decoder: &mut Decoder) -> Key {
+    wait_for_key(decoder, || {
+        let mut console = CONSOLE.lock();
+        console.flush_deferred(&DEFERRED);
+        if console.has_byte() { Some(console.read_byte()) } else { None }
+    })
+}
+
+/// Blocks until a key is pressed on `console`, which the caller has already
+/// locked, and returns it. The console is in raw mode while waiting. Commands
+/// that wait for input, such as a pager waiting for a key, use this.
+pub fn read_key(console: &mut Console) -> Key {
+    let mut decoder = Decoder::new();
+    let mode = console.set_mode(Mode::Raw);
+    let key = wait_for_key(&mut decoder, || {
+        if console.has_byte() { Some(console.read_byte()) } else { None }
+    });
+    console.set_mode(mode);
+    key
+}
+
+/// The shell's liveness flag, once `shell()` has registered it. It is touched
+/// by `keep_alive()` and suspended while the shell waits for a program, so
+/// the watchdog resets the board if the shell stops taking input.
+static ALIVE: Mutex<Option<watchdog::Flag>> = Mutex::new(None);
+
+/// Touches the shell's liveness flag, then sleeps for a moment if IRQs are
+/// masked, as they are while a lock other than the console's is held. The
+/// timer interrupt can't be taken then, so the scheduler runs the timer
+/// events, and with them the watchdog, while it has no process to run. Code
+/// that keeps the shell busy for long, like waiting for input, calls this
+/// regularly.
+fn keep_alive() {
+    touch_alive();
+    if irq::is_masked() {
+        let _ = syscall::sleep(1);
+    }
+}
+
+/// The progress callback of XMODEM transfers. Like `keep_alive()`, but never
+/// gives up the CPU: the sender doesn't wait once a packet is acknowledged,
+/// and the mini UART only holds 8 bytes, so bytes would be lost while the
+/// shell isn't running. The timer events that are due are run instead, if
+/// IRQs are masked.
+pub fn transfer_progress(_: Progress) {
+    touch_alive();
+    if irq::is_masked() {
+        timer::poll();
+    }
+}
+
+/// Touches the shell's liveness flag, if it has been registered.
+fn touch_alive() {
+    if let Some(flag) = *ALIVE.lock() {
+        watchdog::touch(flag);
+    }
+}
+
+struct Watchdog;
+
+impl Command for Watchdog {
+    fn name(&self) -> &'static str { "watchdog" }
+    fn help(&self) -> &'static str { "watchdog [on|off|status]" }
+    fn summary(&self) -> &'static str { "control the hardware watchdog" }
+
+    fn details(&self) -> &'static str {
+        "'status', the default, shows whether the watchdog is armed. 'on' arms\n\
+         it and 'off' disarms it."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        match args {
+            &["on"] => watchdog::enable(),
+            &["off"] => watchdog::disable(),
+            &["status"] | &[] => watchdog::write_status(console)?,
+            _ => return Err(Failure::Usage),
+        }
+
+        Ok(())
+    }
+}
+
+struct Reboot;
+
+impl Command for Reboot {
+    fn name(&self) -> &'static str { "reboot" }
+    fn help(&self) -> &'static str { "reboot" }
+    fn summary(&self) -> &'static str { "reset the board" }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if !args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        writeln!(console, "rebooting...")?;
+        watchdog::reboot();
+    }
+}
+
+struct Halt;
+
+impl Command for Halt {
+    fn name(&self) -> &'static str { "halt" }
+    fn help(&self) -> &'static str { "halt" }
+    fn summary(&self) -> &'static str { "stop the kernel until the board is power cycled" }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if !args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        writeln!(console, "halted; power cycle the board to restart")?;
+        watchdog::halt();
+    }
+}
+
+struct Crash;
+
+impl Command for Crash {
+    fn name(&self) -> &'static str { "crash" }
+    fn help(&self) -> &'static str { "crash panic --yes" }
+    fn summary(&self) -> &'static str { "panic the kernel on purpose" }
+
+    fn details(&self) -> &'static str {
+        "Panics, so that the panic report and the SOS pattern on the ACT LED\n\
+         can be checked. The kernel stops until the board is reset, so --yes\n\
+         is required."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        match args {
+            &["panic", "--yes"] => panic!("crash requested from the shell"),
+            &["panic"] => fail(console, format_args!("crash: this stops the kernel; add --yes \
+                                                      to go ahead")),
+            _ => Err(Failure::Usage),
+        }
+    }
+}
+
+/// Writes `uptime` as days, hours, minutes, seconds and microseconds, as in
+/// `up 2 days, 03:04:05.000006`.
+pub fn write_uptime<W: fmt::Write>(w: &mut W, uptime: Duration) -> fmt::Result {
+    let secs = uptime.as_secs();
+    let days = secs / 86_400;
+    write!(w, "up {} day{}, {:02}:{:02}:{:02}.{:06}", days, if days == 1 { "" } else { "s" },
+           secs / 3600 % 24, secs / 60 % 60, secs % 60, uptime.subsec_nanos() / 1000)
+}
+
+struct Uptime;
+
+impl Command for Uptime {
+    fn name(&self) -> &'static str { "uptime" }
+    fn help(&self) -> &'static str { "uptime" }
+    fn summary(&self) -> &'static str { "show the time since the board was reset" }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        if !args.is_empty() {
+            return Err(Failure::Usage);
+        }
+
+        write_uptime(console, current_time())?;
+        console.write_str("\n")?;
+        Ok(())
+    }
+}
+
+/// The width of the memory accesses made by `peek` and `poke`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Width {
+    Byte = 1,
+    Half = 2,
+    Word = 4,
+    Double = 8,
+}
+
+impl Width {
+    /// Parses a width flag: `-8`, `-16`, `-32` or `-64`, in bits.
+    pub fn from_flag(flag: &str) -> Option<Width> {
+        match flag {
+            "-8" => Some(Width::Byte),
+            "-16" => Some(Width::Half),
+            "-32" => Some(Width::Word),
+            "-64" => Some(Width::Double),
+            _ => None,
+        }
+    }
+
+    /// Returns the width in bytes.
+    pub fn bytes(&self) -> usize {
+        *self as usize
+    }
+
+    /// Returns the largest value an access of this width can write.
+    pub fn max_value(&self) -> u64 {
+        match *self {
+            Width::Double => u64::max_value(),
+            width => (1 << (width.bytes() * 8)) - 1,
+        }
+    }
+
+    /// Performs a volatile read of this width from `addr`.
+    unsafe fn read(&self, addr: usize) -> u64 {
+        match *self {
+            Width::Byte => ptr::read_volatile(addr as *const u8) as u64,
+            Width::Half => ptr::read_volatile(addr as *const u16) as u64,
+            Width::Word => ptr::read_volatile(addr as *const u32) as u64,
+            Width::Double => ptr::read_volatile(addr as *const u64),
+        }
+    }
+
+    /// Performs a volatile write of this width of `value` to `addr`.
+    unsafe fn write(&self, addr: usize, value: u64) {
+        match *self {
+            Width::Byte => ptr::write_volatile(addr as *mut u8, value as u8),
+            Width::Half => ptr::write_volatile(addr as *mut u16, value as u16),
+            Width::Word => ptr::write_volatile(addr as *mut u32, value as u32),
+            Width::Double => ptr::write_volatile(addr as *mut u64, value),
+        }
+    }
+}
+
+/// Parses a number written in hexadecimal with a `0x` prefix, in binary with
+/// a `0b` prefix, or in decimal. Underscores may separate digits.
+pub fn parse_number(s: &str) -> Option<u64> {
+    let (digits, radix) = if s.starts_with("0x") || s.starts_with("0X") {
+        (&s[2..], 16)
+    } else if s.starts_with("0b") || s.starts_with("0B") {
+        (&s[2..], 2)
+    } else {
+        (s, 10)
+    };
+
+    let mut value: u64 = 0;
+    let mut any = false;
+    for c in digits.chars().filter(|&c| c != '_') {
+        let digit = c.to_digit(radix)? as u64;
+        value = value.checked_mul(radix as u64)?.checked_add(digit)?;
+        any = true;
+    }
+
+    if any { Some(value) } else { None }
+}
+
+/// Splits the optional width flag off the front of `args`. The width is 32
+/// bits if there is no flag.
+fn split_width<'a, 'b>(args: &'a [&'b str]) -> (Width, &'a [&'b str]) {
+    match args.first().and_then(|flag| Width::from_flag(flag)) {
+        Some(width) => (width, &args[1..]),
+        None => (Width::Word, args),
+    }
+}
+
+/// Parses `arg` as an address suitably aligned for `width`, reporting an
+/// error for `command` if it isn't one.
+fn parse_address(console: &mut Console, command: &str, arg: &str, width: Width) -> Result<usize, Failure> {
+    match parse_number(arg) {
+        Some(addr) if addr as usize % width.bytes() != 0 => {
+            fail(console, format_args!("{}: {:#x} isn't {}-byte aligned", command, addr, width.bytes()))
+        }
+        Some(addr) => Ok(addr as usize),
+        None => fail(console, format_args!("{}: bad address '{}'", command, arg)),
+    }
+}
+
+struct Peek;
+
+impl Command for Peek {
+    fn name(&self) -> &'static str { "peek" }
+    fn help(&self) -> &'static str { "peek [-8|-16|-32|-64] <addr> [count]" }
+    fn summary(&self) -> &'static str { "read memory" }
+
+    fn details(&self) -> &'static str {
+        "Reads 'count' values, 1 by default, starting at 'addr'. The flag gives\n\
+         the width of each read in bits, 32 by default, and 'addr' must be\n\
+         aligned to it. Numbers are decimal, hex with 0x, or binary with 0b."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let (width, args) = split_width(args);
+        let (addr, count) = match args {
+            &[addr] => (addr, "1"),
+            &[addr, count] => (addr, count),
+            _ => return Err(Failure::Usage),
+        };
+
+        let addr = parse_address(console, "peek", addr, width)?;
+        let count = match parse_number(count) {
+            Some(count) => count as usize,
+            None => return fail(console, format_args!("peek: bad count '{}'", count)),
+        };
+
+        for i in 0..count {
+            let at = addr.wrapping_add(i * width.bytes());
+            let value = unsafe { width.read(at) };
+            writeln!(console, "{:#010x}: {:#0w$x}", at, value, w = width.bytes() * 2 + 2)?;
+        }
+
+        Ok(())
+    }
+}
+
+struct Poke;
+
+impl Command for Poke {
+    fn name(&self) -> &'static str { "poke" }
+    fn help(&self) -> &'static str { "poke [-8|-16|-32|-64] <addr> <value>" }
+    fn summary(&self) -> &'static str { "write memory" }
+
+    fn details(&self) -> &'static str {
+        "Writes 'value' to 'addr'. The flag gives the width of the write in bits,\n\
+         32 by default, and 'addr' must be aligned to it. Numbers are decimal,\n\
+         hex with 0x, or binary with 0b."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let (width, args) = split_width(args);
+        let (addr, value) = match args {
+            &[addr, value] => (addr, value),
+            _ => return Err(Failure::Usage),
+        };
+
+        let addr = parse_address(console, "poke", addr, width)?;
+        match parse_number(value) {
+            Some(value) if value <= width.max_value() => unsafe { width.write(addr, value) },
+            Some(_) => return fail(console, format_args!("poke: {} doesn't fit in {} bits",
+                                                         value, width.bytes() * 8)),
+            None => return fail(console, format_args!("poke: bad value '{}'", value)),
+        }
+
+        Ok(())
+    }
+}
+
+/// The number of bytes on each line of a hexdump.
+pub const HEXDUMP_WIDTH: usize = 16;
+
+/// The number of lines `xxd` prints before waiting for a key.
+const PAGE_LINES: usize = 16;
+
+/// Writes one line of a canonical hexdump of `bytes`, at most
+/// `HEXDUMP_WIDTH` of them, which start at `offset`: the offset, each byte in
+/// hexadecimal, and the printable bytes as ASCII.
+///
+/// ```text
+/// 00080000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a        |Hello, world!.|
+/// ```
+pub fn write_hexdump_line<W: fmt::Write>(w: &mut W, offset: usize, bytes: &[u8]) -> fmt::Result {
+    write!(w, "{:08x} ", offset)?;
+    for i in 0..HEXDUMP_WIDTH {
+        if i == HEXDUMP_WIDTH / 2 {
+            w.write_str(" ")?;
+        }
+
+        match bytes.get(i) {
+            Some(byte) => write!(w, " {:02x}", byte)?,
+            None => w.write_str("   ")?,
+        }
+    }
+
+    w.write_str("  |")?;
+    for &byte in bytes.iter().take(HEXDUMP_WIDTH) {
+        w.write_char(if byte >= 0x20 && byte < 0x7F { byte as char } else { '.' })?;
+    }
+
+    w.write_str("|\n")
+}
+
+struct Xxd;
+
+impl Command for Xxd {
+    fn name(&self) -> &'static str { "xxd" }
+    fn help(&self) -> &'static str { "xxd <addr> <len> | <path> [len]" }
+    fn summary(&self) -> &'static str { "print memory or a file as a hexdump" }
+
+    fn details(&self) -> &'static str {
+        "Dumps <len> bytes of memory at <addr>, or the first [len] bytes of the\n\
+         file at <path>, all of it by default. A path that is also a number,\n\
+         like 10, has to be written as ./10.\n\
+         \n\
+         Pauses after each page: Enter shows one more line, q or Ctrl-C stops,\n\
+         and any other key shows the next page."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let (target, len) = match args {
+            &[target] => (target, None),
+            &[target, len] => (target, Some(len)),
+            _ => return Err(Failure::Usage),
+        };
+
+        let len = match len.map(parse_number) {
+            Some(Some(len)) => Some(len as usize),
+            Some(None) => return fail(console, format_args!("xxd: bad length '{}'", len.unwrap())),
+            None => None,
+        };
+
+        if let Some(addr) = parse_number(target) {
+            let len = match len {
+                Some(len) => len,
+                None => return Err(Failure::Usage),
+            };
+
+            let addr = addr as usize;
+            return write_hexdump(console, target, addr, len, |offset, line| {
+                let at = addr.wrapping_add(offset);
+                for (i, byte) in line.iter_mut().enumerate() {
+                    *byte = unsafe { Width::Byte.read(at.wrapping_add(i)) } as u8;
+                }
+
+                Ok(())
+            });
+        }
+
+        let mut file = match open_entry(console, "xxd", target)?.into_file() {
+            Some(file) => file,
+            None => return fail(console, format_args!("xxd: {}: is a directory", target)),
+        };
+
+        let size = file.size() as usize;
+        let len = len.map_or(size, |len| len.min(size));
+        write_hexdump(console, target, 0, len, |_, line| file.read_exact(line))
+    }
+}
+
+/// Writes `len` bytes as a hexdump to `console`, pausing after each page as
+/// `xxd` does. `read(offset, line)` fills `line` with the bytes at `offset`,
+/// which are labeled `base + offset`. If it fails, the failure is reported as
+/// one reading `name`.
+fn write_hexdump<F>(console: &mut Console, name: &str, base: usize, len: usize, mut read: F)
+    -> Result<(), Failure>
+    where F: FnMut(usize, &mut [u8]) -> io::Result<()>
+{
+    let mut line = [0u8; HEXDUMP_WIDTH];
+    let mut lines_left = PAGE_LINES;
+    let mut offset = 0;
+    while offset < len {
+        if lines_left == 0 {
+            console.write_str("-- more --")?;
+            let key = read_key(console);
+            console.write_str("\r")?;
+            write_bytes(console, ERASE_TO_END);
+            lines_left = match key {
+                Key::Char(b'q') | Key::Char(b'Q') | Key::CtrlC => break,
+                Key::Enter => 1,
+                _ => PAGE_LINES,
+            };
+        }
+
+        let count = (len - offset).min(HEXDUMP_WIDTH);
+        if let Err(e) = read(offset, &mut line[..count]) {
+            return fail(console, format_args!("xxd: {}: {}", name, Reason(&e)));
+        }
+
+        write_hexdump_line(console, base.wrapping_add(offset), &line[..count])?;
+        offset += count;
+        lines_left -= 1;
+    }
+
+    Ok(())
+}
+
+/// Parses the name of a pin function as the `gpio` command takes it: `in`,
+/// `out`, or `alt0` through `alt5`.
+pub fn parse_function(name: &str) -> Option<Function> {
+    match name {
+        "in" => Some(Function::Input),
+        "out" => Some(Function::Output),
+        "alt0" => Some(Function::Alt0),
+        "alt1" => Some(Function::Alt1),
+        "alt2" => Some(Function::Alt2),
+        "alt3" => Some(Function::Alt3),
+        "alt4" => Some(Function::Alt4),
+        "alt5" => Some(Function::Alt5),
+        _ => None,
+    }
+}
+
+/// Returns the name `parse_function()` parses as `function`.
+fn function_name(function: Function) -> &'static str {
+    match function {
+        Function::Input => "in",
+        Function::Output => "out",
+        Function::Alt0 => "alt0",
+        Function::Alt1 => "alt1",
+        Function::Alt2 => "alt2",
+        Function::Alt3 => "alt3",
+        Function::Alt4 => "alt4",
+        Function::Alt5 => "alt5",
+    }
+}
+
+struct Gpio;
+
+impl Command for Gpio {
+    fn name(&self) -> &'static str { "gpio" }
+    fn help(&self) -> &'static str { "gpio <pin> [out|in|set|clear|read|alt<N>]" }
+    fn summary(&self) -> &'static str { "configure, drive or read a GPIO pin" }
+
+    fn details(&self) -> &'static str {
+        "With no operation, prints the pin's function and level. 'set' and\n\
+         'clear' change the level the pin drives once it is an output; they\n\
+         don't select 'out' themselves."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let (pin, op) = match args {
+            &[pin] => (pin, "read"),
+            &[pin, op] => (pin, op),
+            _ => return Err(Failure::Usage),
+        };
+
+        let pin = match parse_number(pin) {
+            Some(number) if number <= u8::max_value() as u64 => number as u8,
+            _ => return fail(console, format_args!("gpio: bad pin '{}'", pin)),
+        };
+
+        let result = match op {
+            "set" => gpio::write(pin, true),
+            "clear" => gpio::write(pin, false),
+            "read" => match (gpio::function(pin), gpio::read(pin)) {
+                (Ok(function), Ok(level)) => {
+                    writeln!(console, "gpio {}: {}, {}", pin, function_name(function),
+                             if level { "high" } else { "low" })?;
+                    Ok(())
+                }
+                (Err(error), _) | (_, Err(error)) => Err(error),
+            },
+            op => match parse_function(op) {
+                Some(function) => gpio::select(pin, function),
+                None => return fail(console, format_args!("gpio: unknown operation '{}'", op)),
+            },
+        };
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(error) => fail(console, format_args!("gpio: {}", error)),
+        }
+    }
+}
+
+struct XmodemRecv;
+
+impl Command for XmodemRecv {
+    fn name(&self) -> &'static str { "xmodem-recv" }
+    fn help(&self) -> &'static str { "xmodem-recv [-v]" }
+    fn summary(&self) -> &'static str { "receive a kernel over XMODEM and run it" }
+
+    fn details(&self) -> &'static str {
+        "Send the kernel from the host once the shell is waiting for it. With\n\
+         -v, the transfer must end with a CRC-32 trailer, and the kernel only\n\
+         runs if it matches. ttywrite always sends one and waits to hear\n\
+         whether it matched, so use -v with it."
+    }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        let verify = match args {
+            &[] => false,
+            &["-v"] => true,
+            _ => return Err(Failure::Usage),
+        };
+
+        writeln!(console, "xmodem-recv: waiting for up to {} bytes at {:#x}",
+                 hotload::STAGING_CAPACITY, hotload::STAGING_ADDR)?;
+
+        let len = match hotload::receive(verify, transfer_progress) {
+            Ok(len) => len,
+            Err(hotload::Error::Transfer(error)) => {
+                return fail(console, format_args!("xmodem-recv: transfer failed: {:?}", error));
+            }
+            Err(hotload::Error::Verification(error)) => {
+                return fail(console, format_args!("xmodem-recv: bad image: {}", error));
+            }
+        };
+
+        writeln!(console, "xmodem-recv: received {} bytes; jumping to {:#x}", len,
+                 hotload::LOAD_ADDR)?;
+        hotload::jump(len);
+    }
+}
+
+const BELL: u8 = 7;
+/// Erases from the cursor to the end of the line.
+const ERASE_TO_END: &[u8] = b"\x1b[K";
+
+/// Writes `bytes` to `console` as they are.
+fn write_bytes(console: &mut Console, bytes: &[u8]) {
+    for &byte in bytes {
+        console.write_byte(byte);
+    }
+}
+
+/// Writes the prompt `prefix` to `console`.
+fn write_prompt(console: &mut Console, prefix: &str) {
+    let _ = write!(console, "{}", style::paint(Style::Green, prefix));
+}
+
+/// Redraws the line being edited after `prefix` and puts the cursor back
+/// where it belongs.
+fn redraw(prefix: &str, editor: &Editor) {
+    let mut console = CONSOLE.lock();
+    console.write_byte(b'\r');
+    write_prompt(&mut console, prefix);
+    write_bytes(&mut console, editor.as_bytes());
+    write_bytes(&mut console, ERASE_TO_END);
+
+    let behind = editor.len() - editor.cursor();
+    if behind > 0 {
+        let _ = write!(console, "\x1b[{}D", behind);
+    }
+}
+
+/// Returns what the last word of `line` can be completed to, and the part of
+/// it that is being completed. The first word is completed to a command name.
+/// Any other word is a path, relative to the working directory: the part
+/// after its last `/` is completed to the name of an entry of the directory
+/// before it, with a `/` after directories. Hidden entries are only
+/// candidates once a `.` has been typed.
+pub fn completion_candidates(line: &str) -> (&str, Vec<String>) {
+    let word_start = line.rfind(' ').map_or(0, |i| i + 1);
+    if word_start == 0 {
+        return (line, commands().map(|command| command.name().to_string()).collect());
+    }
+
+    let word = &line[word_start..];
+    let (dir, name) = word.split_at(word.rfind('/').map_or(0, |i| i + 1));
+    let mut candidates = Vec::new();
+    let entry = match (&FILESYSTEM).open(absolute_path(if dir.is_empty() { "." } else { dir })) {
+        Ok(entry) => entry,
+        Err(_) => return (name, candidates),
+    };
+
+    if let Some(Ok(entries)) = entry.as_dir().map(|dir| dir.entries()) {
+        for entry in entries {
+            if entry.name() == "." || entry.name() == ".."
+                || (is_hidden(&entry) && !name.starts_with('.')) {
+                continue;
+            }
+
+            let slash = if entry.is_dir() { "/" } else { "" };
+            candidates.push(format!("{}{}", entry.name(), slash));
+        }
+    }
+
+    (name, candidates)
+}
+
+/// Completes the word being typed in `editor`, which is displayed after
+/// `prefix`, as `completion_candidates()` does. A unique match is completed
+/// and followed by a space, unless it is a directory. Several matches are
+/// completed as far as they agree; if that adds nothing, they are listed and
+/// the line is redrawn below them. Nothing is completed unless the cursor is
+/// at the end of the line.
+fn complete_word(prefix: &str, editor: &mut Editor) {
+    let (word, candidates) = match str::from_utf8(editor.as_bytes()) {
+        Ok(line) if editor.cursor() == line.len() => {
+            let (word, candidates) = completion_candidates(line);
+            (word.to_string(), candidates)
+        }
+        _ => {
+            CONSOLE.lock().write_byte(BELL);
+            return;
+        }
+    };
+
+    let completion = line::complete(&word, candidates.iter().map(|candidate| candidate.as_str()));
+    let typed = word.len();
+    if completion.matches == 0 || (completion.matches > 1 && completion.common.len() == typed) {
+        let mut console = CONSOLE.lock();
+        console.write_byte(BELL);
+        if completion.matches > 1 {
+            write_bytes(&mut console, b"\n");
+            for candidate in candidates.iter().filter(|candidate| candidate.starts_with(&word)) {
+                let _ = write!(console, "{}  ", candidate);
+            }
+
+            write_bytes(&mut console, b"\n");
+            write_prompt(&mut console, prefix);
+            write_bytes(&mut console, editor.as_bytes());
+        }
+
+        return;
+    }
+
+    for &byte in &completion.common.as_bytes()[typed..] {
+        if !editor.insert(byte) {
+            break;
+        }
+    }
+
+    if completion.matches == 1 && !completion.common.ends_with('/') {
+        editor.insert(b' ');
+    }
+
+    redraw(prefix, editor);
+}
+
+/// Starts a shell using `prefix` as the prefix for each line. This function
+/// never returns: it is perpetually in a shell loop.
+///
+/// The shell edits lines itself with the console in raw mode, and runs
+/// commands with it in cooked mode. It registers the watchdog liveness flag
+/// `shell`, which is touched while it waits for input.
+///
+/// Lines are edited with the arrow keys, Home and End, Backspace and Delete,
+/// and the Emacs-style control keys Ctrl-A, Ctrl-E, Ctrl-K, Ctrl-U and
+/// Ctrl-W. Up and down recall the previous and next of the last
+/// `HISTORY_LEN` lines entered, and Tab completes command names and paths.
+pub fn shell(prefix: &str)  {
+    {
+        let mut alive = ALIVE.lock();
+        if alive.is_none() {
+            *alive = watchdog::register("shell");
+        }
+    }
+
+    let mut history_storage = [Entry::EMPTY; HISTORY_LEN];
+    let mut history = History::new(&mut history_storage);
+
+    loop {
+        let mut buf_storage = [0u8; MAX_LINE];
+        let mut editor = Editor::new(&mut buf_storage);
+        let mut decoder = Decoder::new();
+        // How many lines back the line being edited was recalled from.
+        let mut recalled: Option<usize> = None;
+
+        {
+            let mut console = CONSOLE.lock();
+            console.set_mode(Mode::Raw);
+            write_prompt(&mut console, prefix);
+        }
+
+        loop {
+            let edited = match read_line_key(&mut decoder) {
+                // the end of the cmd
+                Key::Enter => break,
+                Key::Tab => {
+                    complete_word(prefix, &mut editor);
+                    continue;
+                }
+                Key::Arrow(Arrow::Up) => match history.get(recalled.map_or(0, |age| age + 1)) {
+                    Some(line) => {
+                        recalled = Some(recalled.map_or(0, |age| age + 1));
+                        editor.replace(line);
+                        true
+                    }
+                    None => false,
+                },
+                Key::Arrow(Arrow::Down) => match recalled {
+                    Some(0) => {
+                        recalled = None;
+                        editor.replace(&[]);
+                        true
+                    }
+                    Some(age) => {
+                        recalled = Some(age - 1);
+                        editor.replace(history.get(age - 1).unwrap_or(&[]));
+                        true
+                    }
+                    None => false,
+                },
+                Key::Arrow(Arrow::Left) => editor.left(),
+                Key::Arrow(Arrow::Right) => editor.right(),
+                Key::Home | Key::Ctrl(b'a') => editor.home(),
+                Key::End | Key::Ctrl(b'e') => editor.end(),
+                Key::Backspace => editor.backspace(),
+                Key::Delete => editor.delete(),
+                Key::Ctrl(b'k') => editor.kill_to_end(),
+                Key::Ctrl(b'u') => editor.kill_to_start(),
+                Key::Ctrl(b'w') => editor.kill_word(),
+                // Typing at the end of the line only needs the byte echoed.
+                Key::Char(byte) if byte != 255 => {
+                    let at_end = editor.cursor() == editor.len();
+                    let inserted = editor.insert(byte);
+                    if inserted && at_end {
+                        CONSOLE.lock().write_byte(byte);
+                        continue;
+                    }
+
+                    inserted
+                }
+                //Discard other keys and send an alert
+                _ => false,
+            };
+
+            if edited {
+                redraw(prefix, &editor);
+            } else {
+                CONSOLE.lock().write_byte(BELL);
+            }
+        }
+
+        kprintln!("this is my FIRST OS!!!!SOS!!!!");
+        history.push(editor.as_bytes());
+        let line = str::from_utf8(editor.into_bytes()).unwrap();
+        kprint!("\n");
+        let mut console = CONSOLE.lock();
+        console.set_mode(Mode::Cooked);
+        // Failures have already been reported.
+        let _ = run_line(&mut console, line);
     }
 }
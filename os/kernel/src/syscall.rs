@@ -0,0 +1,305 @@
+//! System calls: requests a process makes of the kernel with `svc #n`, where
+//! `n` is the number of the syscall.
+//!
+//! Arguments are passed in `x0` to `x6`, and results are returned in the same
+//! registers. `x7` is 0 on return if the syscall succeeded, and the code of an
+//! `Error` if it didn't. A syscall that has to wait for something parks the
+//! process in the `Waiting` state, and returns once it is ready again.
+
+use std::{fmt, io, slice, str};
+use std::io::Read;
+use std::time::Duration;
+
+use fat32::traits::{Entry, File, FileSystem};
+
+use hw::timer;
+use process::{Id, Process, State, TrapFrame};
+use {FILESYSTEM, SCHEDULER};
+
+/// `sleep(ms)`: sleeps for at least `ms` milliseconds and returns the number
+/// of milliseconds that actually passed.
+pub const SYS_SLEEP: u16 = 1;
+
+/// `exit(code)`: ends the process with exit code `code`. Doesn't return.
+pub const SYS_EXIT: u16 = 2;
+
+/// `wait(pid)`: waits for the child process `pid` to exit and returns its
+/// exit code.
+pub const SYS_WAIT: u16 = 3;
+
+/// `spawn(path, len)`: loads the ELF executable at the absolute path of `len`
+/// bytes at `path` into a new child process and returns its ID.
+pub const SYS_SPAWN: u16 = 4;
+
+/// Why a syscall failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// There is no syscall with the number the process asked for.
+    NoSuchSyscall = 1,
+    /// There is no such process, none the caller may wait for, or process
+    /// IDs have run out.
+    NoSuchProcess = 2,
+    /// An argument was malformed, like a path that isn't UTF-8 or absolute,
+    /// or pointed at memory that isn't the caller's.
+    InvalidArgument = 3,
+    /// There is no file at the path.
+    NoSuchFile = 4,
+    /// The file isn't an executable that can be loaded.
+    BadExecutable = 5,
+    /// Reading the file failed, or there was no memory for the process.
+    Io = 6,
+    /// `x7` held a code that isn't any other error's.
+    Unknown = 0xFFFF,
+}
+
+impl Error {
+    /// Returns the result of a syscall that returned `value` with error code
+    /// `code`.
+    pub fn result(value: u64, code: u64) -> Result<u64, Error> {
+        match code {
+            0 => Ok(value),
+            1 => Err(Error::NoSuchSyscall),
+            2 => Err(Error::NoSuchProcess),
+            3 => Err(Error::InvalidArgument),
+            4 => Err(Error::NoSuchFile),
+            5 => Err(Error::BadExecutable),
+            6 => Err(Error::Io),
+            _ => Err(Error::Unknown),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        match error.kind() {
+            io::ErrorKind::NotFound => Error::NoSuchFile,
+            io::ErrorKind::InvalidData => Error::BadExecutable,
+            io::ErrorKind::InvalidInput => Error::InvalidArgument,
+            _ => Error::Io,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Error::NoSuchSyscall => "no such syscall",
+            Error::NoSuchProcess => "no such process",
+            Error::InvalidArgument => "invalid argument",
+            Error::NoSuchFile => "no such file",
+            Error::BadExecutable => "not an executable",
+            Error::Io => "I/O error",
+            Error::Unknown => "unknown error",
+        })
+    }
+}
+
+/// Handles syscall `num` from the running process, whose registers are in
+/// `tf`. The syscall's results are left in `tf`.
+pub fn handle_syscall(num: u16, tf: &mut TrapFrame) {
+    match num {
+        SYS_SLEEP => sys_sleep(tf.x[0] as u32, tf),
+        SYS_EXIT => sys_exit(tf.x[0] as u32, tf),
+        SYS_WAIT => sys_wait(tf.x[0], tf),
+        SYS_SPAWN => sys_spawn(tf.x[0] as usize, tf.x[1] as usize, tf),
+        _ => tf.x[7] = Error::NoSuchSyscall as u64,
+    }
+}
+
+/// Parks the running process until `ms` milliseconds have passed.
+fn sys_sleep(ms: u32, tf: &mut TrapFrame) {
+    SCHEDULER.switch(sleeping(ms), tf);
+}
+
+/// Ends the running process with exit code `code` and switches to the next.
+fn sys_exit(code: u32, tf: &mut TrapFrame) {
+    SCHEDULER.switch(State::Dead(code), tf);
+}
+
+/// Parks the running process until its child `pid` exits. Fails with
+/// `NoSuchProcess` if `pid` isn't one of its children still to be waited for.
+fn sys_wait(pid: Id, tf: &mut TrapFrame) {
+    if !SCHEDULER.is_child(pid) {
+        tf.x[7] = Error::NoSuchProcess as u64;
+        return;
+    }
+
+    SCHEDULER.switch(waiting_for(pid), tf);
+}
+
+/// Loads the executable at the path of `len` bytes at `path` into a new
+/// process, a child of the running one, and schedules it. Its ID is returned
+/// in `x0`. Fails with `InvalidArgument` if the path isn't memory the
+/// running process may pass (see `Process::may_access()`).
+fn sys_spawn(path: usize, len: usize, tf: &mut TrapFrame) {
+    if !SCHEDULER.may_access(path, len) {
+        tf.x[7] = Error::InvalidArgument as u64;
+        return;
+    }
+
+    // Without an MMU the caller's memory is the kernel's to read, once it is
+    // known to be the caller's.
+    let path = unsafe { slice::from_raw_parts(path as *const u8, len) };
+    let spawned = str::from_utf8(path).map_err(|_| Error::InvalidArgument)
+        .and_then(load)
+        .and_then(|process| SCHEDULER.add(process).ok_or(Error::NoSuchProcess));
+    match spawned {
+        Ok(pid) => {
+            tf.x[0] = pid;
+            tf.x[7] = 0;
+        }
+        Err(error) => tf.x[7] = error as u64,
+    }
+}
+
+/// Reads the ELF executable at the absolute path `path` and loads it into a
+/// new process, ready to run in EL0 but not yet scheduled.
+///
+/// # Errors
+///
+/// Returns `NoSuchFile` if there is no file at `path`, `BadExecutable` if it
+/// isn't an executable that can be loaded, `InvalidArgument` if `path` isn't
+/// absolute, and `Io` if reading it fails or there is no memory for the
+/// process.
+pub fn load(path: &str) -> Result<Process, Error> {
+    let mut file = (&FILESYSTEM).open(path)?.into_file().ok_or(Error::NoSuchFile)?;
+    let mut executable = vec![0; file.size() as usize];
+    file.read_exact(&mut executable)?;
+    Ok(Process::load(&executable)?)
+}
+
+/// Returns the state of a process waiting for its child `pid` to exit. The
+/// process is ready once the child has exited, with the child's exit code as
+/// the result of its `wait()`; the child is then forgotten.
+pub fn waiting_for(pid: Id) -> State {
+    State::Waiting(Box::new(move |process| {
+        let exited = process.children.iter().find(|child| child.0 == pid).and_then(|child| child.1);
+        let code = match exited {
+            Some(code) => code,
+            None => return false,
+        };
+
+        process.children.retain(|child| child.0 != pid);
+        process.trap_frame.x[0] = code as u64;
+        process.trap_frame.x[7] = 0;
+        true
+    }))
+}
+
+/// Returns the state of a process sleeping for `ms` milliseconds from now.
+/// The process is ready once they have passed, with the number of
+/// milliseconds that actually did as the result of its `sleep()`.
+pub fn sleeping(ms: u32) -> State {
+    let start = timer::current_time();
+    let end = start + Duration::from_millis(ms as u64);
+    State::Waiting(Box::new(move |process| {
+        let now = timer::current_time();
+        if now < end {
+            return false;
+        }
+
+        let elapsed = now - start;
+        let ms = elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64;
+        process.trap_frame.x[0] = ms;
+        process.trap_frame.x[7] = 0;
+        true
+    }))
+}
+
+/// Sleeps for at least `ms` milliseconds, letting other processes run in the
+/// meantime. Returns the number of milliseconds that actually passed.
+#[cfg(target_arch = "aarch64")]
+pub fn sleep(ms: u32) -> Result<u32, Error> {
+    let (elapsed, code): (u64, u64);
+    unsafe {
+        asm!("svc 1"
+             : "={x0}"(elapsed), "={x7}"(code)
+             : "{x0}"(ms as u64)
+             : "memory"
+             : "volatile");
+    }
+
+    Error::result(elapsed, code).map(|elapsed| elapsed as u32)
+}
+
+/// Stands in for `sleep()` on the host, where there are no exceptions:
+/// advances the fake clock by `ms` milliseconds.
+#[cfg(not(target_arch = "aarch64"))]
+pub fn sleep(ms: u32) -> Result<u32, Error> {
+    timer::spin_sleep_ms(ms as u64);
+    Ok(ms)
+}
+
+/// Ends the calling process with exit code `code`. Its stack is freed once
+/// the kernel has switched to another process.
+#[cfg(target_arch = "aarch64")]
+pub fn exit(code: u32) -> ! {
+    unsafe {
+        asm!("svc 2" : : "{x0}"(code as u64) : "memory" : "volatile");
+    }
+
+    unreachable!("exit returned")
+}
+
+/// Stands in for `exit()` on the host, where there are no processes to end:
+/// panics.
+#[cfg(not(target_arch = "aarch64"))]
+pub fn exit(code: u32) -> ! {
+    panic!("exit({}) outside of a process", code)
+}
+
+/// Waits for the child process `pid` to exit, letting other processes run in
+/// the meantime, and returns its exit code.
+///
+/// # Errors
+///
+/// Returns `NoSuchProcess` if `pid` isn't a child of the calling process or
+/// has already been waited for.
+#[cfg(target_arch = "aarch64")]
+pub fn wait(pid: Id) -> Result<u32, Error> {
+    let (code, error): (u64, u64);
+    unsafe {
+        asm!("svc 3"
+             : "={x0}"(code), "={x7}"(error)
+             : "{x0}"(pid)
+             : "memory"
+             : "volatile");
+    }
+
+    Error::result(code, error).map(|code| code as u32)
+}
+
+/// Stands in for `wait()` on the host, where there are no processes to wait
+/// for: fails with `NoSuchProcess`.
+#[cfg(not(target_arch = "aarch64"))]
+pub fn wait(_pid: Id) -> Result<u32, Error> {
+    Err(Error::NoSuchProcess)
+}
+
+/// Loads the ELF executable at the absolute path `path` into a new child
+/// process and schedules it. Returns the child's ID, to `wait()` for.
+///
+/// # Errors
+///
+/// See `load()`.
+#[cfg(target_arch = "aarch64")]
+pub fn spawn(path: &str) -> Result<Id, Error> {
+    let (pid, error): (u64, u64);
+    unsafe {
+        asm!("svc 4"
+             : "={x0}"(pid), "={x7}"(error)
+             : "{x0}"(path.as_ptr() as u64), "{x1}"(path.len() as u64)
+             : "memory"
+             : "volatile");
+    }
+
+    Error::result(pid, error)
+}
+
+/// Stands in for `spawn()` on the host, where nothing runs the processes the
+/// scheduler holds: loads the executable and adds it to the scheduler.
+#[cfg(not(target_arch = "aarch64"))]
+pub fn spawn(path: &str) -> Result<Id, Error> {
+    let process = load(path)?;
+    SCHEDULER.add(process).ok_or(Error::NoSuchProcess)
+}
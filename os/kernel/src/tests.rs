@@ -0,0 +1,2195 @@
+use std::{io, str};
+use std::collections::VecDeque;
+use std::time::Duration;
+use std::fmt::Write;
+use std::heap::{Alloc, AllocErr, Layout};
+
+use fake;
+use console;
+use console::{kprint, kprintln, kassert, kassert_eq, debug_kassert, Console, Deferred, Mode, CONSOLE};
+use console::style::{self, Style};
+use console::{Level, LEVELS, DEFERRED};
+use shell::{self, Command, CommandLine, Error, Failure, Width};
+use line::{self, Editor, History, Entry, MAX_LINE};
+use console::input::{Arrow, Decoder, Key};
+use mutex::Mutex;
+use hw::irq;
+use klog::{LogBuffer, LOG_SIZE};
+use fbcon::{Screen, CELL_WIDTH, CELL_HEIGHT};
+use hw::PixelOrder;
+use env::{self, Vars, MAX_VARS};
+use hw::gpio::Function;
+use timer::{Events, MAX_EVENTS};
+use heartbeat::{self, Heartbeat, PATTERN};
+use watchdog::{Liveness, RebootReason, MAX_FLAGS};
+use hotload::{self, LOAD_ADDR, STAGING_ADDR, STAGING_CAPACITY};
+use panic::{self, Registers};
+use xmodem::{Progress, Xmodem};
+use xmodem::trailer::{self, Trailer};
+use allocator::{self, bin, bump, Allocator, Stats, HOTLOAD_END};
+use allocator::util::{align_down, align_up, checked_align_up};
+use allocator::canary::{self, CANARY, CANARY_SIZE, POISON};
+use pi::atags::Atags;
+use frames::{self, FrameAllocator, PAGE_SIZE};
+use dma::{self, Pool};
+use memory;
+use fs::{self, Backend, FileSystem, TmpFs};
+use fat32::traits::{Dir as DirTrait, Entry as EntryTrait, FileSystem as FileSystemTrait};
+use fat32::vfat::VFat;
+use elf::{self, Elf};
+use process::{Id, Process, State, TrapFrame, STACK_SIZE, USER_SPSR};
+use scheduler::Scheduler;
+use syscall;
+use traps::{self, Syndrome};
+
+macro expect_variant($e:expr, $variant:pat) {
+    match $e {
+        $variant => {  },
+        ref o => panic!("expected '{}' but found '{:?}'", stringify!($variant), o)
+    }
+}
+
+#[test]
+fn command_parse_splits_on_spaces() {
+    let mut line = String::from("  echo hello   world ");
+    let mut storage = [""; 8];
+    let command = CommandLine::parse(&mut line, &mut storage).expect("parses");
+    assert_eq!(command.path(), "echo");
+    assert_eq!(command.args(), &["hello", "world"]);
+}
+
+#[test]
+fn command_parse_errors() {
+    let mut storage = [""; 8];
+    expect_variant!(CommandLine::parse(&mut String::from(""), &mut storage), Err(Error::Empty));
+
+    let mut storage = [""; 8];
+    expect_variant!(CommandLine::parse(&mut String::from("     "), &mut storage), Err(Error::Empty));
+
+    let mut storage = [""; 2];
+    expect_variant!(CommandLine::parse(&mut String::from("a b c"), &mut storage), Err(Error::TooManyArgs));
+
+    let mut storage = [""; 8];
+    expect_variant!(CommandLine::parse(&mut String::from("echo \"a b"), &mut storage),
+                    Err(Error::UnterminatedQuote));
+
+    let mut storage = [""; 8];
+    expect_variant!(CommandLine::parse(&mut String::from("echo 'a\\'"), &mut storage), Ok(_));
+}
+
+/// Parses `line` and returns its arguments, including the path.
+fn parse_args(line: &str) -> Vec<String> {
+    let mut line = line.to_string();
+    let mut storage = [""; 8];
+    let command = CommandLine::parse(&mut line, &mut storage).expect("parses");
+    let mut args = vec![command.path().to_string()];
+    args.extend(command.args().iter().map(|arg| arg.to_string()));
+    args
+}
+
+#[test]
+fn command_parse_unquotes() {
+    assert_eq!(parse_args("echo \"hello world\""), ["echo", "hello world"]);
+    assert_eq!(parse_args("echo 'two  words' x"), ["echo", "two  words", "x"]);
+    assert_eq!(parse_args("echo a\\ b"), ["echo", "a b"]);
+    assert_eq!(parse_args("echo pre\"mid dle\"post"), ["echo", "premid dlepost"]);
+    assert_eq!(parse_args("echo \"\" ''"), ["echo", "", ""]);
+    assert_eq!(parse_args("echo \"say \\\"hi\\\" \\n\""), ["echo", "say \"hi\" \\n"]);
+    assert_eq!(parse_args("echo 'it\\'"), ["echo", "it\\"]);
+    assert_eq!(parse_args("echo \"\\$x\" '\\$x'"), ["echo", "$x", "\\$x"]);
+    assert_eq!(parse_args("echo \"it's\""), ["echo", "it's"]);
+    assert_eq!(parse_args("echo \\'q\\' trailing\\"), ["echo", "'q'", "trailing\\"]);
+    assert_eq!(parse_args("echo caf\u{e9} \"\u{e9}\u{e8}\""), ["echo", "caf\u{e9}", "\u{e9}\u{e8}"]);
+}
+
+#[test]
+fn command_parse_recognizes_background_lines() {
+    let mut line = String::from("sleep 5 &  ");
+    let mut storage = [""; 8];
+    let command = CommandLine::parse(&mut line, &mut storage).expect("parses");
+    assert!(command.is_background());
+    assert_eq!(command.args(), &["5"]);
+
+    let mut line = String::from("echo \"&\" a&");
+    let mut storage = [""; 8];
+    let command = CommandLine::parse(&mut line, &mut storage).expect("parses");
+    assert!(!command.is_background());
+    assert_eq!(command.args(), &["&", "a&"]);
+
+    let mut line = String::from("echo '&'");
+    let mut storage = [""; 8];
+    assert!(!CommandLine::parse(&mut line, &mut storage).expect("parses").is_background());
+
+    let mut storage = [""; 8];
+    expect_variant!(CommandLine::parse(&mut String::from(" & "), &mut storage), Err(Error::Empty));
+
+    fake::take_output();
+    assert_eq!(shell::run_line(&mut CONSOLE.lock(), "echo hi &"), Err(Failure::Reported));
+    let output = String::from_utf8(fake::take_output()).unwrap();
+    assert!(output.starts_with("error: only programs can run in the background"));
+}
+
+fn decode(bytes: &[u8]) -> Vec<Key> {
+    let mut decoder = Decoder::new();
+    bytes.iter().filter_map(|&b| decoder.feed(b)).collect()
+}
+
+#[test]
+fn decoder_recognizes_arrow_keys() {
+    assert_eq!(decode(b"a\x1b[Ab\x1bOB\x1b[C\x1b[D"),
+               vec![Key::Char(b'a'), Key::Arrow(Arrow::Up), Key::Char(b'b'), Key::Arrow(Arrow::Down),
+                    Key::Arrow(Arrow::Right), Key::Arrow(Arrow::Left)]);
+    assert_eq!(decode(b"\x1b[1;5Z\x1bx!"), vec![Key::Unknown, Key::Unknown, Key::Char(b'!')]);
+    assert_eq!(decode(b"\x1b["), vec![]);
+}
+
+#[test]
+fn decoder_names_control_keys() {
+    assert_eq!(decode(b"\r\n\x08\x7f\t\x03\x01\x17\x00\x1f\xc3"),
+               vec![Key::Enter, Key::Enter, Key::Backspace, Key::Backspace, Key::Tab, Key::CtrlC,
+                    Key::Ctrl(b'a'), Key::Ctrl(b'w'), Key::Ctrl(b'@'), Key::Ctrl(b'_'),
+                    Key::Char(0xC3)]);
+}
+
+#[test]
+fn decoder_flushes_unfinished_sequences() {
+    let mut decoder = Decoder::new();
+    assert!(!decoder.is_pending());
+    assert_eq!(decoder.flush(), None);
+
+    assert_eq!(decoder.feed(0x1B), None);
+    assert!(decoder.is_pending());
+    assert_eq!(decoder.flush(), Some(Key::Escape));
+    assert!(!decoder.is_pending());
+
+    assert_eq!(decoder.feed(0x1B), None);
+    assert_eq!(decoder.feed(b'['), None);
+    assert_eq!(decoder.feed(b'1'), None);
+    assert_eq!(decoder.flush(), Some(Key::Unknown));
+    assert_eq!(decoder.feed(b'A'), Some(Key::Char(b'A')));
+}
+
+#[test]
+fn decoder_recognizes_editing_keys() {
+    assert_eq!(decode(b"\x1b[H\x1b[F\x1bOH\x1bOF"), vec![Key::Home, Key::End, Key::Home, Key::End]);
+    assert_eq!(decode(b"\x1b[1~\x1b[7~\x1b[4~\x1b[8~\x1b[3~"),
+               vec![Key::Home, Key::Home, Key::End, Key::End, Key::Delete]);
+    assert_eq!(decode(b"\x1b[3;5~\x1b[13~\x1b[2~"), vec![Key::Delete, Key::Unknown, Key::Unknown]);
+}
+
+/// Applies `edit` to an editor holding `line` with the cursor at `cursor`
+/// and returns whether it succeeded, the resulting line, and the cursor.
+fn edit<F: FnOnce(&mut Editor) -> bool>(line: &str, cursor: usize, edit: F) -> (bool, String, usize) {
+    let mut storage = [0u8; 16];
+    let mut editor = Editor::new(&mut storage);
+    editor.replace(line.as_bytes());
+    for _ in cursor..line.len() {
+        editor.left();
+    }
+
+    let done = edit(&mut editor);
+    (done, String::from_utf8(editor.as_bytes().to_vec()).unwrap(), editor.cursor())
+}
+
+#[test]
+fn editor_inserts_and_deletes_at_the_cursor() {
+    assert_eq!(edit("helo", 3, |e| e.insert(b'l')), (true, "hello".into(), 4));
+    assert_eq!(edit("hello", 0, |e| e.insert(b'>')), (true, ">hello".into(), 1));
+    assert_eq!(edit("hello", 2, |e| e.backspace()), (true, "hllo".into(), 1));
+    assert_eq!(edit("hello", 0, |e| e.backspace()), (false, "hello".into(), 0));
+    assert_eq!(edit("hello", 2, |e| e.delete()), (true, "helo".into(), 2));
+    assert_eq!(edit("hello", 5, |e| e.delete()), (false, "hello".into(), 5));
+    assert_eq!(edit("0123456789abcdef", 3, |e| e.insert(b'!')), (false, "0123456789abcdef".into(), 3));
+}
+
+#[test]
+fn editor_moves_the_cursor() {
+    assert_eq!(edit("abc", 0, |e| e.left()), (false, "abc".into(), 0));
+    assert_eq!(edit("abc", 1, |e| e.right()), (true, "abc".into(), 2));
+    assert_eq!(edit("abc", 3, |e| e.right()), (false, "abc".into(), 3));
+    assert_eq!(edit("abc", 2, |e| e.home()), (true, "abc".into(), 0));
+    assert_eq!(edit("abc", 1, |e| e.end()), (true, "abc".into(), 3));
+}
+
+#[test]
+fn editor_kills_text() {
+    assert_eq!(edit("echo hi there", 7, |e| e.kill_to_start()), (true, " there".into(), 0));
+    assert_eq!(edit("echo hi there", 7, |e| e.kill_to_end()), (true, "echo hi".into(), 7));
+    assert_eq!(edit("echo hi  there", 9, |e| e.kill_word()), (true, "echo there".into(), 5));
+    assert_eq!(edit("echo hi there", 6, |e| e.kill_word()), (true, "echo i there".into(), 5));
+    assert_eq!(edit("   echo", 0, |e| e.kill_word()), (false, "   echo".into(), 0));
+}
+
+#[test]
+fn history_keeps_the_most_recent_lines() {
+    let mut storage = [Entry::EMPTY; 3];
+    let mut history = History::new(&mut storage);
+    assert_eq!(history.get(0), None);
+
+    for line in &["one", "two", "two", "   ", "", "three", "four"] {
+        history.push(line.as_bytes());
+    }
+
+    assert_eq!(history.len(), 3);
+    assert_eq!(history.get(0), Some(&b"four"[..]));
+    assert_eq!(history.get(1), Some(&b"three"[..]));
+    assert_eq!(history.get(2), Some(&b"two"[..]));
+    assert_eq!(history.get(3), None);
+
+    history.push(&[b'x'; MAX_LINE + 10]);
+    assert_eq!(history.get(0).map(|l| l.len()), Some(MAX_LINE));
+}
+
+#[test]
+fn completion_extends_to_the_shared_prefix() {
+    let names = ["echo", "exit", "export", "reboot"];
+    let complete = |word| line::complete(word, names.iter().cloned());
+
+    assert_eq!(complete("r").matches, 1);
+    assert_eq!(complete("r").common, "reboot");
+    assert_eq!(complete("ex").matches, 2);
+    assert_eq!(complete("ex").common, "ex");
+    assert_eq!(complete("exp").common, "export");
+    assert_eq!(complete("e").common, "e");
+    assert_eq!(complete("").matches, 4);
+    assert_eq!(complete("z").matches, 0);
+    assert_eq!(complete("z").common, "");
+}
+
+#[test]
+fn shell_parses_numbers() {
+    assert_eq!(shell::parse_number("0"), Some(0));
+    assert_eq!(shell::parse_number("4096"), Some(4096));
+    assert_eq!(shell::parse_number("0x3F20_0000"), Some(0x3F20_0000));
+    assert_eq!(shell::parse_number("0b101"), Some(5));
+    assert_eq!(shell::parse_number("0xffffffffffffffff"), Some(u64::max_value()));
+    assert_eq!(shell::parse_number("0x1_0000_0000_0000_0000"), None);
+    assert_eq!(shell::parse_number("0x"), None);
+    assert_eq!(shell::parse_number("12a"), None);
+    assert_eq!(shell::parse_number(""), None);
+}
+
+#[test]
+fn shell_width_flags() {
+    assert_eq!(Width::from_flag("-8"), Some(Width::Byte));
+    assert_eq!(Width::from_flag("-64"), Some(Width::Double));
+    assert_eq!(Width::from_flag("-12"), None);
+    assert_eq!(Width::Half.bytes(), 2);
+    assert_eq!(Width::Word.max_value(), 0xFFFF_FFFF);
+    assert_eq!(Width::Double.max_value(), u64::max_value());
+}
+
+#[test]
+fn shell_gpio_functions() {
+    assert_eq!(shell::parse_function("in"), Some(Function::Input));
+    assert_eq!(shell::parse_function("out"), Some(Function::Output));
+    assert_eq!(shell::parse_function("alt0"), Some(Function::Alt0));
+    assert_eq!(shell::parse_function("alt5"), Some(Function::Alt5));
+    assert_eq!(shell::parse_function("alt6"), None);
+    assert_eq!(shell::parse_function("set"), None);
+}
+
+struct Twice;
+
+impl Command for Twice {
+    fn name(&self) -> &'static str { "twice" }
+    fn help(&self) -> &'static str { "twice <word>" }
+    fn summary(&self) -> &'static str { "print a word twice" }
+
+    fn run(&self, console: &mut Console, args: &[&str]) -> Result<(), Failure> {
+        match args {
+            &[word] => Ok(write!(console, "{}{}", word, word)?),
+            _ => Err(Failure::Usage),
+        }
+    }
+}
+
+#[test]
+fn shell_registers_commands() {
+    assert!(shell::find("twice").is_none());
+    shell::register(&Twice).expect("registers");
+    assert!(shell::register(&Twice).is_err());
+    assert_eq!(shell::find("twice").map(|command| command.help()), Some("twice <word>"));
+    assert!(shell::commands().any(|command| command.name() == "echo"));
+
+    fake::take_output();
+    let twice = shell::find("twice").unwrap();
+    assert_eq!(twice.run(&mut CONSOLE.lock(), &["ab"]), Ok(()));
+    assert_eq!(twice.run(&mut CONSOLE.lock(), &[]), Err(Failure::Usage));
+    assert_eq!(fake::take_output(), b"abab");
+}
+
+#[test]
+fn help_lists_and_describes_commands() {
+    let help = shell::find("help").expect("help is built in");
+    fake::take_output();
+    assert_eq!(help.run(&mut CONSOLE.lock(), &[]), Ok(()));
+    let output = String::from_utf8(fake::take_output()).unwrap();
+    assert!(output.starts_with("  cat          print the contents of files\r\n"));
+    assert!(output.contains("  color        show or set whether output is colored\r\n"));
+    assert!(output.contains("  echo         print the arguments\r\n"));
+    assert!(output.contains("  watchdog     control the hardware watchdog\r\n"));
+    assert!(output.contains("  help         list the commands, or describe one\r\n"));
+
+    assert_eq!(help.run(&mut CONSOLE.lock(), &["halt"]), Ok(()));
+    assert_eq!(str::from_utf8(&fake::take_output()).unwrap(),
+               "usage: halt\r\nstop the kernel until the board is power cycled\r\n");
+
+    assert_eq!(help.run(&mut CONSOLE.lock(), &["xxd"]), Ok(()));
+    let output = String::from_utf8(fake::take_output()).unwrap();
+    assert!(output.starts_with("usage: xxd <addr> <len> | <path> [len]\r\n\
+                                print memory or a file as a hexdump\r\n\r\nDumps"));
+
+    assert_eq!(help.run(&mut CONSOLE.lock(), &["nope"]), Err(Failure::Reported));
+    assert_eq!(help.run(&mut CONSOLE.lock(), &["a", "b"]), Err(Failure::Usage));
+}
+
+#[test]
+fn crash_needs_confirmation() {
+    let crash = shell::find("crash").expect("crash is built in");
+    fake::take_output();
+    assert_eq!(crash.run(&mut CONSOLE.lock(), &["panic"]), Err(Failure::Reported));
+    assert!(String::from_utf8(fake::take_output()).unwrap().contains("add --yes"));
+    assert_eq!(crash.run(&mut CONSOLE.lock(), &["--yes"]), Err(Failure::Usage));
+
+    let panicked = ::std::panic::catch_unwind(|| {
+        let _ = shell::run_line(&mut CONSOLE.lock(), "crash panic --yes");
+    });
+    assert!(panicked.is_err());
+}
+
+#[test]
+fn styles_wrap_values_in_sgr_sequences() {
+    assert_eq!(format!("{}", Style::Red.apply("error")), "\x1b[31merror\x1b[0m");
+    assert_eq!(format!("{}", Style::Dim.apply(format_args!("[{:>4}]", 12))), "\x1b[2m[  12]\x1b[0m");
+    assert_eq!(Style::Bold.code(), 1);
+
+    // Styling is off unless the kernel turns it on at boot.
+    assert!(!style::enabled());
+    assert_eq!(format!("{}", style::paint(Style::Green, "->")), "->");
+
+    let color = shell::find("color").expect("color is built in");
+    fake::take_output();
+    assert_eq!(color.run(&mut CONSOLE.lock(), &[]), Ok(()));
+    assert_eq!(fake::take_output(), b"color: off\r\n");
+    assert_eq!(color.run(&mut CONSOLE.lock(), &["blue"]), Err(Failure::Usage));
+}
+
+#[test]
+fn log_levels_and_prefixes() {
+    for &level in LEVELS.iter() {
+        assert_eq!(Level::from_name(level.name()), Some(level));
+    }
+
+    assert_eq!(Level::from_name("INFO"), None);
+    assert!(Level::Trace < Level::Debug && Level::Warn < Level::Error);
+
+    let mut prefix = String::new();
+    console::write_log_prefix(&mut prefix, Level::Info, 1_000_042).unwrap();
+    assert_eq!(prefix, "[    1.000042] INFO  ");
+
+    let mut prefix = String::new();
+    console::write_log_prefix(&mut prefix, Level::Error, 123_456_789_000).unwrap();
+    assert_eq!(prefix, "[123456.789000] ERROR ");
+
+    let loglevel = shell::find("loglevel").expect("loglevel is built in");
+    fake::take_output();
+    assert_eq!(loglevel.run(&mut CONSOLE.lock(), &[]), Ok(()));
+    assert_eq!(fake::take_output(), b"loglevel: info\r\n");
+    assert_eq!(loglevel.run(&mut CONSOLE.lock(), &["loud"]), Err(Failure::Usage));
+}
+
+#[test]
+fn shell_builtins_write_to_the_console() {
+    fake::take_output();
+    let echo = shell::find("echo").expect("echo is built in");
+    assert_eq!(echo.run(&mut CONSOLE.lock(), &["hello", "world"]), Ok(()));
+    assert_eq!(str::from_utf8(&fake::take_output()).unwrap(), "hello world\r\n");
+
+    let gpio = shell::find("gpio").expect("gpio is built in");
+    assert_eq!(gpio.run(&mut CONSOLE.lock(), &["21", "out"]), Ok(()));
+    assert_eq!(gpio.run(&mut CONSOLE.lock(), &["21", "set"]), Ok(()));
+    assert_eq!(gpio.run(&mut CONSOLE.lock(), &["21"]), Ok(()));
+    assert_eq!(str::from_utf8(&fake::take_output()).unwrap(), "gpio 21: out, high\r\n");
+
+    assert_eq!(gpio.run(&mut CONSOLE.lock(), &["54", "out"]), Err(Failure::Reported));
+    assert_eq!(gpio.run(&mut CONSOLE.lock(), &["21", "up"]), Err(Failure::Reported));
+    assert_eq!(gpio.run(&mut CONSOLE.lock(), &[]), Err(Failure::Usage));
+}
+
+const SCRIPT: &str = "# set up\r\necho one\r\n\n  # indented comment\nbogus\necho 'two words'\n";
+
+#[test]
+fn shell_runs_scripts() {
+    fake::take_output();
+    assert_eq!(shell::run_script(&mut CONSOLE.lock(), SCRIPT, false), Err(5));
+    assert_eq!(str::from_utf8(&fake::take_output()).unwrap(),
+               "one\r\nUnknown command: bogus; type 'help' for a list\r\ntwo words\r\n");
+
+    assert_eq!(shell::run_script(&mut CONSOLE.lock(), SCRIPT, true), Err(5));
+    assert_eq!(str::from_utf8(&fake::take_output()).unwrap(),
+               "one\r\nUnknown command: bogus; type 'help' for a list\r\n");
+
+    assert_eq!(shell::run_script(&mut CONSOLE.lock(), "echo \"open\necho ok", true), Err(1));
+    assert_eq!(str::from_utf8(&fake::take_output()).unwrap(), "error: unterminated quote\r\n");
+
+    assert_eq!(shell::run_script(&mut CONSOLE.lock(), "\n# only comments\n", true), Ok(()));
+    assert!(fake::take_output().is_empty());
+}
+
+/// Formats `uptime` with `shell::write_uptime()`.
+fn uptime(uptime: Duration) -> String {
+    let mut s = String::new();
+    shell::write_uptime(&mut s, uptime).unwrap();
+    s
+}
+
+#[test]
+fn shell_formats_uptime() {
+    assert_eq!(uptime(Duration::new(0, 0)), "up 0 days, 00:00:00.000000");
+    assert_eq!(uptime(Duration::new(86_400 + 59, 999_999_999)), "up 1 day, 00:00:59.999999");
+    assert_eq!(uptime(Duration::new(2 * 86_400 + 3 * 3600 + 4 * 60 + 5, 6_000)),
+               "up 2 days, 03:04:05.000006");
+
+    fake::timer::set(90_061_000_001);
+    fake::take_output();
+    let command = shell::find("uptime").expect("uptime is built in");
+    assert_eq!(command.run(&mut CONSOLE.lock(), &[]), Ok(()));
+    assert_eq!(fake::take_output(), b"up 1 day, 01:01:01.000001\r\n");
+}
+
+#[test]
+fn hexdump_lines_are_canonical() {
+    let mut line = String::new();
+    shell::write_hexdump_line(&mut line, 0x80000, b"Hello, world!\n").unwrap();
+    assert_eq!(line, "00080000  48 65 6c 6c 6f 2c 20 77  6f 72 6c 64 21 0a        |Hello, world!.|\n");
+
+    let mut line = String::new();
+    let bytes: Vec<u8> = (0x7Eu8..0x8E).collect();
+    shell::write_hexdump_line(&mut line, 0x10, &bytes).unwrap();
+    assert_eq!(line, "00000010  7e 7f 80 81 82 83 84 85  86 87 88 89 8a 8b 8c 8d  |~...............|\n");
+
+    let mut line = String::new();
+    shell::write_hexdump_line(&mut line, 0, b"").unwrap();
+    assert_eq!(line, format!("00000000 {:49}  ||\n", ""));
+}
+
+#[test]
+fn xxd_pages_through_memory() {
+    let bytes: Vec<u8> = (0..1024).map(|i| b'a' + (i % 26) as u8).collect();
+    let addr = format!("{:#x}", bytes.as_ptr() as usize);
+    let xxd = shell::find("xxd").expect("xxd is built in");
+
+    fake::take_output();
+    assert_eq!(xxd.run(&mut CONSOLE.lock(), &[&addr, "20"]), Ok(()));
+    let output = String::from_utf8(fake::take_output()).unwrap();
+    assert_eq!(output.lines().count(), 2);
+    assert!(output.ends_with(&format!("71 72 73 74{:39}|qrst|\r\n", "")));
+
+    // 16 lines fill the first page; `q` stops before the rest.
+    fake::push_input(b"q");
+    assert_eq!(xxd.run(&mut CONSOLE.lock(), &[&addr, "1024"]), Ok(()));
+    let output = String::from_utf8(fake::take_output()).unwrap();
+    assert!(output.ends_with("-- more --\r\x1b[K"));
+    assert_eq!(output.matches('|').count(), 2 * 16);
+
+    assert_eq!(xxd.run(&mut CONSOLE.lock(), &["kernel.img", "16"]), Err(Failure::Reported));
+    assert_eq!(xxd.run(&mut CONSOLE.lock(), &[&addr]), Err(Failure::Usage));
+    assert_eq!(xxd.run(&mut CONSOLE.lock(), &[&addr, "x"]), Err(Failure::Reported));
+}
+
+#[test]
+fn vars_set_get_and_unset() {
+    let mut vars = Vars::new();
+    assert!(vars.is_empty());
+    vars.set("KADDR", "0x80000").unwrap();
+    vars.set("_x1", "").unwrap();
+    vars.set("KADDR", "0x90000").unwrap();
+    assert_eq!(vars.len(), 2);
+    assert_eq!(vars.get("KADDR"), Some("0x90000"));
+    assert_eq!(vars.get("_x1"), Some(""));
+    assert_eq!(vars.get("kaddr"), None);
+    assert_eq!(vars.iter().collect::<Vec<_>>(), [("KADDR", "0x90000"), ("_x1", "")]);
+
+    assert!(vars.unset("KADDR"));
+    assert!(!vars.unset("KADDR"));
+    assert_eq!(vars.get("KADDR"), None);
+    assert_eq!(vars.get("_x1"), Some(""));
+}
+
+#[test]
+fn vars_reject_bad_names_and_overflow() {
+    let mut vars = Vars::new();
+    assert_eq!(vars.set("", "v"), Err(env::Error::InvalidName));
+    assert_eq!(vars.set("1A", "v"), Err(env::Error::InvalidName));
+    assert_eq!(vars.set("A-B", "v"), Err(env::Error::InvalidName));
+    assert_eq!(vars.set("A_VERY_LONG_NAME_X", "v"), Err(env::Error::InvalidName));
+    assert_eq!(vars.set("A", &"v".repeat(env::MAX_VALUE + 1)), Err(env::Error::ValueTooLong));
+
+    for i in 0..MAX_VARS {
+        vars.set(&format!("V{}", i), "v").unwrap();
+    }
+
+    assert_eq!(vars.set("ONE_MORE", "v"), Err(env::Error::Full));
+    assert_eq!(vars.set("V0", "replaced"), Ok(()));
+}
+
+/// Expands `line` with `vars` and returns the result.
+fn expand(vars: &Vars, line: &str) -> Result<String, env::Error> {
+    let mut out = [0u8; 64];
+    vars.expand(line, &mut out).map(|len| str::from_utf8(&out[..len]).unwrap().to_string())
+}
+
+#[test]
+fn vars_expand_outside_single_quotes() {
+    let mut vars = Vars::new();
+    vars.set("A", "0x80000").unwrap();
+    vars.set("MSG", "two \"quoted\" words").unwrap();
+
+    assert_eq!(expand(&vars, "peek $A 4").unwrap(), "peek 0x80000 4");
+    assert_eq!(expand(&vars, "echo $A$A-$UNSET.").unwrap(), "echo 0x800000x80000-.");
+    assert_eq!(expand(&vars, "echo '$A' \\$A $ $1").unwrap(), "echo '$A' \\$A $ $1");
+    assert_eq!(expand(&vars, "echo $MSG").unwrap(), "echo two\\ \\\"quoted\\\"\\ words");
+    assert_eq!(expand(&vars, "echo \"$MSG\"").unwrap(), "echo \"two \\\"quoted\\\" words\"");
+    assert_eq!(expand(&vars, "echo $MSG $MSG $MSG"), Err(env::Error::LineTooLong));
+}
+
+#[test]
+fn shell_expands_variables() {
+    fake::take_output();
+    let script = "set GREETING=\"hello  world\" TARGET=there\n\
+                  echo $GREETING '$GREETING' \"<$GREETING>\" \\$TARGET\n\
+                  unset GREETING\n\
+                  echo [$GREETING]\n\
+                  set 1X=bad";
+    assert_eq!(shell::run_script(&mut CONSOLE.lock(), script, false), Err(5));
+    assert_eq!(str::from_utf8(&fake::take_output()).unwrap(),
+               "hello  world $GREETING <hello  world> $TARGET\r\n[]\r\n\
+                set: 1X: invalid variable name\r\n");
+    assert_eq!(env::VARS.lock().get("TARGET"), Some("there"));
+}
+
+#[test]
+fn fake_gpio_remembers_pins() {
+    assert_eq!(fake::gpio::function(16), Ok(Function::Input));
+    fake::gpio::select(16, Function::Output).unwrap();
+    fake::gpio::write(16, true).unwrap();
+    assert_eq!(fake::gpio::function(16), Ok(Function::Output));
+    assert_eq!(fake::gpio::read(16), Ok(true));
+    assert_eq!(fake::gpio::read(21), Ok(false));
+    assert_eq!(fake::gpio::select(54, Function::Output), Err(::pi::Error::InvalidPin(54)));
+}
+
+#[test]
+fn console_translates_newlines() {
+    fake::take_output();
+    kprint!("{}-{}", 1, 2);
+    kprintln!("!");
+    kprintln!();
+    assert_eq!(str::from_utf8(&fake::take_output()).unwrap(), "1-2!\r\n\r\n");
+}
+
+/// Runs `f`, which must panic, and returns what it wrote to the fake UART.
+fn failure_output<F: FnOnce() + ::std::panic::UnwindSafe>(f: F) -> String {
+    fake::take_output();
+    assert!(::std::panic::catch_unwind(f).is_err(), "expected a panic");
+    String::from_utf8(fake::take_output()).unwrap()
+}
+
+#[test]
+fn kassert_passes_silently() {
+    fake::take_output();
+    kassert!(1 + 1 == 2);
+    kassert_eq!(0x10u32, 16, "never {}", "shown");
+    debug_kassert!(true);
+    assert!(fake::take_output().is_empty());
+}
+
+#[test]
+fn kassert_reports_expression_and_location() {
+    let output = failure_output(|| kassert!(1 > 2, "checking {}", "order"));
+    assert!(output.starts_with("kernel assertion failed on core 0 at src/tests.rs:"), "{}", output);
+    assert!(output.contains(": `1 > 2`\r\n"), "{}", output);
+    assert!(output.ends_with("  note: checking order\r\n"), "{}", output);
+}
+
+#[test]
+fn kassert_eq_reports_integers_in_hex() {
+    let base: u32 = 0x3F20_0000;
+    let output = failure_output(|| kassert_eq!(base + 4, 0x3F20_0008));
+    assert!(output.contains(": `base + 4 == 0x3F20_0008`\r\n"), "{}", output);
+    assert!(output.contains("   left: 0x3f200004\r\n"), "{}", output);
+    assert!(output.ends_with("  right: 0x3f200008\r\n"), "{}", output);
+}
+
+#[test]
+fn kassert_eq_reports_other_values_with_debug() {
+    let output = failure_output(|| kassert_eq!("ok", "no", "status of {}", 2));
+    assert!(output.contains("   left: \"ok\"\r\n  right: \"no\"\r\n"), "{}", output);
+    assert!(output.ends_with("  note: status of 2\r\n"), "{}", output);
+}
+
+#[test]
+fn console_reads_fake_input() {
+    fake::push_input(b"ok");
+    let mut console = CONSOLE.lock();
+    let mode = console.set_mode(Mode::Raw);
+    assert_eq!(console.read_byte(), b'o');
+    assert_eq!(console.read_byte(), b'k');
+    console.set_mode(mode);
+}
+
+#[test]
+fn fake_uart_read_times_out() {
+    fake::timer::set(0);
+    let mut uart = fake::FakeUart::new();
+    uart.set_read_timeout(25);
+    assert_eq!(uart.wait_for_byte(), Err(::pi::Error::Timeout));
+    assert_eq!(fake::timer::current_time_us(), 25_000);
+}
+
+#[test]
+fn fake_timer_advances_on_sleep() {
+    fake::timer::set(1000);
+    fake::timer::spin_sleep_ms(2);
+    fake::timer::spin_sleep_us(5);
+    fake::timer::spin_sleep(Duration::new(1, 7_000));
+    assert_eq!(fake::timer::current_time_us(), 1_003_012);
+    assert_eq!(fake::timer::current_time(), Duration::new(1, 3_012_000));
+}
+
+#[test]
+fn mutex_guards_data() {
+    let mutex = Mutex::new(0);
+    *mutex.lock() += 41;
+    *mutex.lock() += 1;
+    assert_eq!(*mutex.lock(), 42);
+}
+
+#[test]
+fn heartbeat_follows_pattern_exactly() {
+    let mut heartbeat = Heartbeat::new();
+    let mut now = 0;
+    assert_eq!(heartbeat.tick(now), Some(true));
+
+    let mut levels = vec![];
+    for step in 0..8 {
+        now += PATTERN[step % PATTERN.len()].1;
+        assert_eq!(heartbeat.tick(now - 1), None);
+        levels.push(heartbeat.tick(now).expect("level changes"));
+    }
+
+    assert_eq!(levels, [false, true, false, true, false, true, false, true]);
+}
+
+#[test]
+fn heartbeat_restarts_when_late() {
+    let mut heartbeat = Heartbeat::new();
+    assert_eq!(heartbeat.tick(0), Some(true));
+    assert_eq!(heartbeat.tick(60_000), Some(false));
+    assert_eq!(heartbeat.tick(10_000_000), Some(true));
+    assert_eq!(heartbeat.tick(10_049_999), None);
+    assert_eq!(heartbeat.tick(10_050_000), Some(false));
+}
+
+#[test]
+fn heartbeat_rejects_missing_pin() {
+    assert_eq!(heartbeat::start(::pi::gpio::MAX_PIN + 1), Err(()));
+}
+
+thread_local!(static FIRED: ::std::cell::Cell<usize> = ::std::cell::Cell::new(0));
+
+fn count_event(_: u64) {
+    FIRED.with(|fired| fired.set(fired.get() + 1));
+}
+
+#[test]
+fn periodic_events_fire_once_per_period() {
+    let mut events = Events::new();
+    events.every(0, 100, count_event).expect("room for event");
+
+    events.poll(99);
+    assert_eq!(FIRED.with(|f| f.get()), 0);
+    events.poll(100);
+    events.poll(150);
+    assert_eq!(FIRED.with(|f| f.get()), 1);
+    events.poll(1_000);
+    events.poll(1_050);
+    assert_eq!(FIRED.with(|f| f.get()), 2);
+    events.poll(1_100);
+    assert_eq!(FIRED.with(|f| f.get()), 3);
+}
+
+#[test]
+fn one_shot_events_fire_once_in_order() {
+    thread_local!(static ORDER: ::std::cell::RefCell<Vec<u64>> = ::std::cell::RefCell::new(Vec::new()));
+    fn first(_: u64) { ORDER.with(|o| o.borrow_mut().push(1)); }
+    fn second(_: u64) { ORDER.with(|o| o.borrow_mut().push(2)); }
+    fn third(_: u64) { ORDER.with(|o| o.borrow_mut().push(3)); }
+
+    let mut events = Events::new();
+    events.after(0, 300, third).expect("room for event");
+    events.after(0, 100, first).expect("room for event");
+    events.after(0, 300, second).expect("room for event");
+    events.every(0, 1000, count_event).expect("room for event");
+    assert_eq!(events.len(), 4);
+    assert_eq!(events.next_due(), Some(100));
+
+    events.poll(100);
+    events.poll(299);
+    assert_eq!(ORDER.with(|o| o.borrow().clone()), vec![1]);
+    events.poll(300);
+    assert_eq!(ORDER.with(|o| o.borrow().clone()), vec![1, 3, 2]);
+    events.poll(10_000);
+    assert_eq!(ORDER.with(|o| o.borrow().clone()), vec![1, 3, 2]);
+
+    assert_eq!(events.len(), 1, "only the periodic event is left");
+    assert_eq!(events.next_due(), Some(11_000));
+}
+
+#[test]
+fn periodic_event_table_fills() {
+    let mut events = Events::new();
+    for _ in 0..MAX_EVENTS {
+        events.every(0, 10, count_event).expect("room for event");
+    }
+
+    assert!(events.every(0, 10, count_event).is_err());
+}
+
+#[test]
+fn liveness_requires_every_flag() {
+    let mut liveness = Liveness::new();
+    assert!(liveness.check(), "no flags is trivially alive");
+
+    let shell = liveness.register("shell").expect("room for flag");
+    let sched = liveness.register("scheduler").expect("room for flag");
+    assert!(liveness.check(), "flags start out touched");
+    assert!(!liveness.check(), "flags are cleared by check");
+
+    liveness.touch(shell);
+    assert!(!liveness.is_touched(sched));
+    assert!(!liveness.check());
+
+    liveness.touch(sched);
+    liveness.touch(shell);
+    liveness.touch(shell);
+    assert!(liveness.check());
+
+    let names: Vec<_> = liveness.flags().map(|(_, name)| name).collect();
+    assert_eq!(names, ["shell", "scheduler"]);
+}
+
+#[test]
+fn liveness_excuses_suspended_flags() {
+    let mut liveness = Liveness::new();
+    let shell = liveness.register("shell").expect("room for flag");
+    let sched = liveness.register("scheduler").expect("room for flag");
+    liveness.check();
+
+    liveness.suspend(shell);
+    liveness.touch(sched);
+    assert!(liveness.is_suspended(shell));
+    assert!(liveness.check(), "a suspended flag needn't be touched");
+    assert!(!liveness.check(), "the others still must be");
+
+    liveness.resume(shell);
+    liveness.touch(sched);
+    assert!(!liveness.is_suspended(shell));
+    assert!(liveness.check(), "a resumed flag starts out touched");
+    liveness.touch(sched);
+    assert!(!liveness.check(), "and must be touched again after");
+}
+
+#[test]
+fn liveness_capacity() {
+    let mut liveness = Liveness::new();
+    for _ in 0..MAX_FLAGS {
+        liveness.register("flag").expect("room for flag");
+    }
+
+    assert!(liveness.register("one too many").is_none());
+    assert!(liveness.check());
+}
+
+#[test]
+fn reboot_reason_cookie_round_trips() {
+    let armed = RebootReason::cookie(true);
+    let disarmed = RebootReason::cookie(false);
+    assert_eq!(RebootReason::from_cookie(armed), RebootReason::Watchdog);
+    assert_eq!(RebootReason::from_cookie(disarmed), RebootReason::Clean);
+    assert_eq!(RebootReason::from_cookie(0), RebootReason::PowerOn);
+    assert_eq!(RebootReason::from_cookie(armed ^ (1 << 40)), RebootReason::PowerOn);
+    assert_eq!(RebootReason::from_cookie(!0), RebootReason::PowerOn);
+}
+
+#[test]
+fn hotload_image_len_checks_the_trailer() {
+    let mut received = vec![0xA5u8; 300];
+    received.resize(384, 0);
+    received.extend_from_slice(&Trailer::for_image(&[0xA5; 300]).to_packet()[..]);
+
+    assert_eq!(hotload::image_len(&received, true), Ok(300));
+    assert_eq!(hotload::image_len(&received, false), Ok(512));
+
+    received[7] ^= 1;
+    match hotload::image_len(&received, true) {
+        Err(trailer::Error::Crc { .. }) => {  }
+        result => panic!("damaged image verified: {:?}", result),
+    }
+
+    assert_eq!(hotload::image_len(&received[..384], true), Err(trailer::Error::Missing));
+}
+
+#[test]
+fn hotload_staging_stays_clear_of_the_copy() {
+    assert!(LOAD_ADDR + STAGING_CAPACITY <= STAGING_ADDR);
+    assert_eq!(hotload::trampoline_addr(0), STAGING_ADDR);
+    assert_eq!(hotload::trampoline_addr(1), STAGING_ADDR + 16);
+    assert_eq!(hotload::trampoline_addr(STAGING_CAPACITY) % 16, 0);
+    assert!(hotload::trampoline_addr(300) >= STAGING_ADDR + 300);
+}
+
+/// How long the mini UART takes to receive a byte at 115200 baud, in
+/// microseconds.
+const BYTE_US: u64 = 87;
+
+/// The depth of the mini UART's receive FIFO.
+const FIFO_DEPTH: usize = 8;
+
+/// A mini UART receiving `input` into its 8-byte FIFO, one byte every
+/// `BYTE_US` of the fake clock. A read takes the oldest byte in the FIFO,
+/// waiting for the next to arrive if it's empty. Bytes that arrive while it's
+/// full, as when the reader has been descheduled, are lost.
+struct FifoUart {
+    input: VecDeque<u8>,
+    fifo: VecDeque<u8>,
+    /// When the FIFO was last filled.
+    last_us: u64,
+    dropped: usize,
+}
+
+impl FifoUart {
+    fn new(input: &[u8]) -> FifoUart {
+        FifoUart {
+            input: input.iter().cloned().collect(),
+            fifo: VecDeque::new(),
+            last_us: fake::timer::current_time_us(),
+            dropped: 0,
+        }
+    }
+}
+
+impl io::Read for FifoUart {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let now = fake::timer::current_time_us();
+        let arrived = ((now - self.last_us) / BYTE_US) as usize;
+        self.last_us = now;
+        for _ in 0..arrived.max(self.fifo.is_empty() as usize) {
+            match self.input.pop_front() {
+                Some(byte) if self.fifo.len() < FIFO_DEPTH => self.fifo.push_back(byte),
+                Some(_) => self.dropped += 1,
+                None => break,
+            }
+        }
+
+        match self.fifo.pop_front() {
+            Some(byte) => {
+                buf[0] = byte;
+                Ok(1)
+            }
+            None => Err(io::Error::new(io::ErrorKind::TimedOut, "nothing more is sent")),
+        }
+    }
+}
+
+impl io::Write for FifoUart {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns what an XMODEM sender sends for `data` to a receiver that asks for
+/// CRCs and acknowledges every packet.
+fn xmodem_stream(data: &[u8]) -> Vec<u8> {
+    struct Receiver {
+        replies: VecDeque<u8>,
+        sent: Vec<u8>,
+    }
+
+    impl io::Read for Receiver {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            buf[0] = self.replies.pop_front().expect("the sender waits for no more replies");
+            Ok(1)
+        }
+    }
+
+    impl io::Write for Receiver {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.sent.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const ACK: u8 = 0x06;
+    const NAK: u8 = 0x15;
+    let mut replies = vec![b'C'];
+    replies.extend(vec![ACK; (data.len() + 127) / 128]);
+    replies.extend(&[NAK, ACK]);
+    let mut receiver = Receiver { replies: replies.into_iter().collect(), sent: vec![] };
+    Xmodem::transmit(data, &mut receiver).expect("every packet is acknowledged");
+    receiver.sent
+}
+
+#[test]
+fn transfers_keep_up_with_the_uart_fifo() {
+    let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+    let stream = xmodem_stream(&data);
+    let receive = |progress: fn(Progress)| {
+        let mut uart = FifoUart::new(&stream);
+        let mut received = vec![];
+        let result = {
+            let mut receiver = Xmodem::new_with_progress(&mut uart, progress);
+            receiver.set_crc(true);
+            receiver.receive_all(&mut received)
+        };
+
+        (result.map(|_| received), uart.dropped)
+    };
+
+    // A receiver that sleeps between packets misses the start of the next.
+    let (result, dropped) = receive(|_| {
+        let _ = syscall::sleep(1);
+    });
+    assert!(dropped > 0);
+    assert!(result.is_err());
+
+    let (result, dropped) = receive(shell::transfer_progress);
+    assert_eq!(dropped, 0);
+    let received = result.expect("the transfer succeeds");
+    assert_eq!(&received[..data.len()], &data[..]);
+}
+
+#[test]
+fn mutex_masks_irqs_while_locked() {
+    let outer = Mutex::new(1);
+    let inner = Mutex::new(2);
+    assert!(!irq::is_masked());
+    {
+        let _outer = outer.lock();
+        assert!(irq::is_masked());
+        {
+            let _inner = inner.lock();
+            assert!(irq::is_masked());
+        }
+
+        assert!(irq::is_masked(), "still masked for the outer lock");
+    }
+
+    assert!(!irq::is_masked());
+}
+
+#[test]
+fn console_lock_leaves_irqs_unmasked() {
+    let mut console = CONSOLE.lock();
+    assert!(!irq::is_masked(), "commands run with the console locked stay preemptible");
+
+    // With IRQs masked, as in an exception handler, printing can't wait for
+    // the console to be unlocked.
+    let saved = irq::save_and_disable();
+    kprint!("printed with IRQs masked");
+    irq::restore(saved);
+
+    fake::take_output();
+    console.flush_deferred(&DEFERRED);
+    let output = String::from_utf8(fake::take_output()).unwrap();
+    assert!(output.contains("printed with IRQs masked"), "{:?}", output);
+}
+
+#[test]
+fn console_modes_cook_or_pass_through_input() {
+    use std::io::Read;
+
+    let mut console = CONSOLE.lock();
+    assert_eq!(console.set_mode(Mode::Cooked), Mode::Cooked);
+    fake::take_output();
+
+    fake::push_input(b"lx\x7fs \x15ls -l");
+    assert!(!console.has_byte());
+    assert_eq!(fake::take_output(), b"lx\x08 \x08s \x08 \x08\x08 \x08\x08 \x08ls -l");
+
+    fake::push_input(b"\r\nok\n");
+    let mut buf = [0; 4];
+    assert_eq!(console.read(&mut buf).unwrap(), 4);
+    assert_eq!(&buf, b"ls -");
+    assert_eq!(console.read(&mut buf).unwrap(), 2);
+    assert_eq!(&buf[..2], b"l\n");
+    assert_eq!(console.read_byte(), b'o');
+    assert_eq!(fake::take_output(), b"\r\nok\r\n");
+
+    // The rest of a cooked line is dropped when switching to raw mode.
+    assert_eq!(console.set_mode(Mode::Raw), Mode::Cooked);
+    assert!(!console.has_byte());
+    fake::push_input(b"\x7fq");
+    assert_eq!(console.read_byte(), 0x7F);
+    assert_eq!(console.read_byte(), b'q');
+    assert_eq!(fake::take_output(), b"");
+
+    console.set_mode(Mode::Cooked);
+}
+
+#[test]
+fn deferred_output_is_flushed_to_the_console() {
+    let deferred = Deferred::new();
+    deferred.write_fmt(format_args!("irq {}\n", 7));
+    assert!(!deferred.is_empty());
+
+    fake::take_output();
+    CONSOLE.lock().flush_deferred(&deferred);
+    assert_eq!(fake::take_output(), b"irq 7\r\n");
+    assert!(deferred.is_empty());
+
+    for _ in 0..300 {
+        deferred.write_fmt(format_args!("x"));
+    }
+
+    CONSOLE.lock().flush_deferred(&deferred);
+    let output = String::from_utf8(fake::take_output()).unwrap();
+    assert!(output.ends_with("[console: 44 bytes of interrupt output dropped]\r\n"));
+
+    CONSOLE.lock().flush_deferred(&deferred);
+    assert_eq!(fake::take_output(), b"");
+}
+
+#[test]
+fn log_buffer_keeps_the_newest_lines() {
+    let mut log = LogBuffer::new();
+    assert!(log.is_empty());
+    log.write(b"one\ntwo\n");
+    assert_eq!(log.contents(), (&b"one\ntwo\n"[..], &b""[..]));
+    assert_eq!(log.lines(), log.contents());
+
+    let line = [b'x'; 99];
+    for _ in 0..LOG_SIZE / 100 + 1 {
+        log.write(&line);
+        log.write(b"\n");
+    }
+
+    assert_eq!(log.len(), LOG_SIZE);
+    assert_eq!(log.overwritten(), 8 + (LOG_SIZE / 100 + 1) as u64 * 100 - LOG_SIZE as u64);
+
+    let (first, second) = log.contents();
+    assert_eq!(first.len() + second.len(), LOG_SIZE);
+    assert_eq!(second.last(), Some(&b'\n'));
+
+    // Only whole lines are left once the oldest has been overwritten.
+    let (first, second) = log.lines();
+    let lines: Vec<u8> = first.iter().chain(second.iter()).cloned().collect();
+    assert_eq!(lines.len() % 100, 0);
+    assert!(lines.split(|&byte| byte == b'\n').all(|l| l.is_empty() || l == &line[..]));
+
+    log.clear();
+    assert!(log.is_empty());
+    assert_eq!(log.contents(), (&b""[..], &b""[..]));
+}
+
+#[test]
+fn dmesg_replays_printed_output() {
+    kprintln!("dmesg test {}", 0xD3);
+    let dmesg = shell::find("dmesg").expect("dmesg is built in");
+    fake::take_output();
+    assert_eq!(dmesg.run(&mut CONSOLE.lock(), &[]), Ok(()));
+    let output = String::from_utf8(fake::take_output()).unwrap();
+    assert!(output.contains("dmesg test 211\r\n"));
+    assert_eq!(dmesg.run(&mut CONSOLE.lock(), &["-x"]), Err(Failure::Usage));
+}
+
+/// Returns the pixels of the cell at `(col, row)` of an image whose rows start
+/// `stride` pixels apart.
+fn cell_pixels(pixels: &[u32], stride: usize, col: usize, row: usize) -> Vec<u32> {
+    let start = row * CELL_HEIGHT * stride + col * CELL_WIDTH;
+    (0..CELL_HEIGHT)
+        .flat_map(|y| pixels[start + y * stride..start + y * stride + CELL_WIDTH].to_vec())
+        .collect()
+}
+
+#[test]
+fn screen_draws_glyphs_and_the_cursor() {
+    let (white, black, inverted) = (0xAAAAAA, 0x000000, 0xFFFFFF);
+    let mut pixels = vec![0x123456; 40 * 32];
+    {
+        let mut screen = Screen::new(&mut pixels, 36, 32, 40, PixelOrder::Rgb);
+        assert_eq!((screen.columns(), screen.rows()), (4, 2));
+        screen.write(b"A");
+        assert_eq!(screen.cursor(), (1, 0));
+    }
+
+    // The first row of 'A' is 0x0C, and each font row is drawn twice.
+    let cell = cell_pixels(&pixels, 40, 0, 0);
+    let expected = [black, black, white, white, black, black, black, black];
+    assert_eq!(&cell[..8], &expected);
+    assert_eq!(&cell[8..16], &expected);
+
+    // The cursor underlines the next cell; the partial column is untouched.
+    let cursor = cell_pixels(&pixels, 40, 1, 0);
+    assert!(cursor[..14 * 8].iter().all(|&p| p == black));
+    assert!(cursor[14 * 8..].iter().all(|&p| p == inverted));
+    assert_eq!(pixels[32], 0x123456);
+}
+
+#[test]
+fn screen_wraps_and_scrolls() {
+    let mut pixels = vec![0; 16 * 32];
+    let mut expected = vec![0; 16 * 32];
+    {
+        let mut screen = Screen::new(&mut pixels, 16, 32, 16, PixelOrder::Bgr);
+        screen.write(b"ab");
+        assert_eq!(screen.cursor(), (1, 0));
+        screen.write(b"cd");
+        assert_eq!(screen.cursor(), (1, 1));
+        screen.write(b"\n");
+        assert_eq!(screen.cursor(), (0, 1));
+    }
+
+    {
+        let mut screen = Screen::new(&mut expected, 16, 32, 16, PixelOrder::Bgr);
+        screen.write(b"cd\n");
+    }
+
+    assert_eq!(pixels, expected);
+}
+
+#[test]
+fn screen_interprets_escape_sequences() {
+    let mut pixels = vec![0; 64 * 32];
+    {
+        let mut screen = Screen::new(&mut pixels, 64, 32, 64, PixelOrder::Bgr);
+        screen.write(b"abc\x1b[2D");
+        assert_eq!(screen.cursor(), (1, 0));
+        screen.write(b"\x1b[2;5H");
+        assert_eq!(screen.cursor(), (4, 1));
+        screen.write(b"\x1b[9;99H\r\t");
+        assert_eq!(screen.cursor(), (7, 1));
+        screen.write("\x1b[Hé!".as_bytes());
+        assert_eq!(screen.cursor(), (2, 0));
+        write!(screen, "{}", Style::Red.apply("X")).unwrap();
+        assert_eq!(screen.cursor(), (3, 0));
+    }
+
+    // 'X' starts with 0x63: red pixels in columns 0, 1, 5 and 6.
+    let red = PixelOrder::Bgr.pack(0xAA, 0, 0);
+    let cell = cell_pixels(&pixels, 64, 2, 0);
+    assert_eq!(&cell[..8], &[red, red, 0, 0, 0, red, red, 0]);
+
+    // '?' replaced 'a', and erasing to the end of the line cleared 'c'.
+    let mut pixels = vec![0; 64 * 32];
+    let mut expected = vec![0; 64 * 32];
+    Screen::new(&mut pixels, 64, 32, 64, PixelOrder::Bgr).write("éb\x1b[Kc\x1b[D\x1b[K".as_bytes());
+    Screen::new(&mut expected, 64, 32, 64, PixelOrder::Bgr).write(b"?b");
+    assert_eq!(pixels, expected);
+}
+
+#[test]
+fn panic_pattern_blinks_sos() {
+    let marks: Vec<u64> = panic::PATTERN.iter()
+        .filter(|&&(on, _)| on)
+        .map(|&(_, duration)| duration)
+        .collect();
+    assert_eq!(marks.len(), 9);
+    assert!(marks[..3].iter().chain(&marks[6..]).all(|&dot| dot * 3 == marks[3]));
+    assert!(marks[3..6].iter().all(|&dash| dash == marks[3]));
+    assert_eq!(panic::PATTERN.last().map(|&(on, _)| on), Some(false));
+}
+
+#[test]
+fn panic_report_names_the_location_and_registers() {
+    let mut x = [0; 31];
+    for (i, value) in x.iter_mut().enumerate() {
+        *value = i as u64;
+    }
+
+    let registers = Registers { x, sp: 0x7fff0, el: 1, daif: 0x3c0 };
+    let mut report = String::new();
+    panic::write_report(&mut report, format_args!("index {} out of range", 9),
+                        "src/shell.rs", 42, 7, 0, Some(&registers)).unwrap();
+
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 12);
+    assert_eq!(lines[1], "!!! kernel panic on core 0 at src/shell.rs:42:7");
+    assert_eq!(lines[2], "    index 9 out of range");
+    assert_eq!(lines[3], "x00: 0x0000000000000000  x01: 0x0000000000000001  \
+                          x02: 0x0000000000000002  x03: 0x0000000000000003");
+    assert!(lines[10].starts_with("x28: ") && lines[10].ends_with("x30: 0x000000000000001e"));
+    assert_eq!(lines[11], " sp: 0x000000000007fff0  el: 1  daif: 0x3c0");
+
+    let mut short = String::new();
+    panic::write_report(&mut short, format_args!("oops"), "a.rs", 1, 2, 3, None).unwrap();
+    assert_eq!(short, "\n!!! kernel panic on core 3 at a.rs:1:2\n    oops\n");
+}
+
+#[test]
+fn alignment_helpers_round_to_powers_of_two() {
+    assert_eq!(align_down(0x1234, 0x100), 0x1200);
+    assert_eq!(align_down(0x1200, 0x100), 0x1200);
+    assert_eq!(align_up(0x1201, 0x100), 0x1300);
+    assert_eq!(align_up(0x1200, 0x100), 0x1200);
+    assert_eq!(align_up(0, 8), 0);
+    assert_eq!(checked_align_up(usize::max_value() - 2, 4), None);
+    assert_eq!(checked_align_up(usize::max_value() - 3, 4), Some(usize::max_value() - 3));
+}
+
+#[test]
+#[should_panic]
+fn alignment_helpers_reject_other_alignments() {
+    align_up(0x1000, 24);
+}
+
+fn layout(size: usize, align: usize) -> Layout {
+    Layout::from_size_align(size, align).unwrap()
+}
+
+#[test]
+fn bump_allocator_aligns_and_exhausts() {
+    let mut bump = bump::Allocator::new(0x1001, 0x1100);
+    assert_eq!(bump.alloc(layout(3, 1)).unwrap() as usize, 0x1001);
+    assert_eq!(bump.alloc(layout(16, 16)).unwrap() as usize, 0x1010);
+    assert_eq!(bump.alloc(layout(8, 8)).unwrap() as usize, 0x1020);
+
+    // Freeing leaks the memory, so the rest is carved off after it.
+    let ptr = bump.alloc(layout(0x40, 0x40)).unwrap();
+    assert_eq!(ptr as usize, 0x1040);
+    bump.dealloc(ptr, layout(0x40, 0x40));
+    assert_eq!(bump.alloc(layout(0x40, 0x40)).unwrap() as usize, 0x1080);
+    expect_variant!(bump.alloc(layout(0x41, 1)), Err(AllocErr::Exhausted { .. }));
+    assert_eq!(bump.alloc(layout(0x40, 1)).unwrap() as usize, 0x10C0);
+    expect_variant!(bump.alloc(layout(1, 1)), Err(AllocErr::Exhausted { .. }));
+
+    let mut top = bump::Allocator::new(usize::max_value() - 8, usize::max_value());
+    expect_variant!(top.alloc(layout(4, 16)), Err(AllocErr::Exhausted { .. }));
+}
+
+#[test]
+fn heap_range_skips_the_kernel_and_hotload() {
+    let mem = |start: u32, size: u32| {
+        vec![5, 0x54410001, 0, 4096, 0, 4, 0x54410002, size, start, 0, 0]
+    };
+    assert_eq!(allocator::heap_range(Atags::new(&mem(0, 0x3B000000)), 0x9_0001),
+               Some((HOTLOAD_END, 0x3B000000)));
+    assert_eq!(allocator::heap_range(Atags::new(&mem(0, 0x3B000000)), 0x2000_0003),
+               Some((0x2000_0010, 0x3B000000)));
+    assert_eq!(allocator::heap_range(Atags::new(&mem(0x3000_0000, 0x1000)), 0x9_0000),
+               Some((0x3000_0000, 0x3000_1000)));
+    assert_eq!(allocator::heap_range(Atags::new(&mem(0, HOTLOAD_END as u32)), 0x9_0000), None);
+    assert_eq!(allocator::heap_range(Atags::new(&[0, 0]), 0x9_0000), None);
+}
+
+/// Returns a bin allocator over `len` bytes of `buffer` starting `offset`
+/// bytes past a 4KiB boundary, and the address of that boundary.
+fn bin_allocator(buffer: &mut Vec<u64>, offset: usize, len: usize) -> (bin::Allocator, usize) {
+    let base = align_up(buffer.as_mut_ptr() as usize, 4096);
+    assert!(base + offset + len <= buffer.as_ptr() as usize + buffer.len() * 8);
+    (bin::Allocator::new(base + offset, base + offset + len), base)
+}
+
+#[test]
+fn bin_allocator_maps_layouts_to_size_classes() {
+    assert_eq!(bin::bin_for(&layout(1, 1)), Some(0));
+    assert_eq!(bin::bin_for(&layout(8, 8)), Some(0));
+    assert_eq!(bin::bin_for(&layout(9, 1)), Some(1));
+    assert_eq!(bin::bin_for(&layout(8, 64)), Some(3));
+    assert_eq!(bin::bin_for(&layout(4096, 8)), Some(9));
+    assert_eq!(bin::bin_for(&layout(bin::block_size(bin::NUM_BINS - 1), 8)),
+               Some(bin::NUM_BINS - 1));
+    assert_eq!(bin::bin_for(&layout(bin::block_size(bin::NUM_BINS - 1) + 1, 8)), None);
+}
+
+#[test]
+fn bin_allocator_reuses_freed_blocks() {
+    let mut buffer = vec![0u64; 2048];
+    let (mut heap, base) = bin_allocator(&mut buffer, 0, 4096);
+
+    let a = heap.alloc(layout(24, 8)).unwrap();
+    let b = heap.alloc(layout(32, 8)).unwrap();
+    assert_eq!((a as usize, b as usize), (base, base + 32));
+
+    // A layout of the same class gets the freed block back, newest first.
+    heap.dealloc(a, layout(24, 8));
+    heap.dealloc(b, layout(32, 8));
+    assert_eq!(heap.alloc(layout(17, 4)).unwrap(), b);
+    assert_eq!(heap.alloc(layout(32, 32)).unwrap(), a);
+    assert_eq!(heap.alloc(layout(32, 8)).unwrap() as usize, base + 64);
+
+    // Freed blocks of other classes aren't used.
+    let c = heap.alloc(layout(8, 8)).unwrap();
+    heap.dealloc(c, layout(8, 8));
+    assert_eq!(heap.alloc(layout(16, 8)).unwrap() as usize, base + 112);
+}
+
+#[test]
+fn bin_allocator_aligns_and_keeps_the_gaps() {
+    let mut buffer = vec![0u64; 2048];
+    let (mut heap, base) = bin_allocator(&mut buffer, 8, 4088);
+
+    let page = heap.alloc(layout(100, 256)).unwrap();
+    assert_eq!(page as usize, base + 256);
+
+    // The gap before it is split into blocks of 8, 16, 32, 64 and 128 bytes.
+    assert_eq!(heap.alloc(layout(8, 8)).unwrap() as usize, base + 8);
+    assert_eq!(heap.alloc(layout(16, 16)).unwrap() as usize, base + 16);
+    assert_eq!(heap.alloc(layout(128, 8)).unwrap() as usize, base + 128);
+    assert_eq!(heap.alloc(layout(64, 64)).unwrap() as usize, base + 64);
+    assert_eq!(heap.alloc(layout(32, 8)).unwrap() as usize, base + 32);
+    assert_eq!(heap.alloc(layout(8, 8)).unwrap() as usize, base + 512);
+}
+
+#[test]
+fn bin_allocator_splits_larger_blocks_once_exhausted() {
+    let mut buffer = vec![0u64; 2048];
+    let (mut heap, base) = bin_allocator(&mut buffer, 0, 1024);
+
+    let all = heap.alloc(layout(1024, 8)).unwrap();
+    expect_variant!(heap.alloc(layout(8, 8)), Err(AllocErr::Exhausted { .. }));
+
+    heap.dealloc(all, layout(1024, 8));
+    assert_eq!(heap.alloc(layout(100, 8)).unwrap() as usize, base);
+    assert_eq!(heap.alloc(layout(512, 8)).unwrap() as usize, base + 512);
+    assert_eq!(heap.alloc(layout(256, 8)).unwrap() as usize, base + 256);
+    assert_eq!(heap.alloc(layout(128, 8)).unwrap() as usize, base + 128);
+    expect_variant!(heap.alloc(layout(8, 8)), Err(AllocErr::Exhausted { .. }));
+
+    let huge = layout(bin::block_size(bin::NUM_BINS - 1) + 1, 8);
+    expect_variant!(heap.alloc(huge), Err(AllocErr::Exhausted { .. }));
+}
+
+#[test]
+fn allocator_counts_allocations() {
+    let mut buffer = vec![0u64; 2048];
+    let start = align_up(buffer.as_mut_ptr() as usize, 8);
+    let heap = Allocator::uninitialized();
+    assert_eq!(heap.stats(), None);
+
+    heap.initialize_with(start, start + 4096);
+    let mut handle = &heap;
+    unsafe {
+        let a = handle.alloc(layout(100, 8)).unwrap();
+        let b = handle.alloc(layout(8, 8)).unwrap();
+        handle.dealloc(a, layout(100, 8));
+        handle.alloc(layout(20, 4)).unwrap();
+        handle.dealloc(b, layout(8, 8));
+        expect_variant!(handle.alloc(layout(8192, 8)), Err(AllocErr::Exhausted { .. }));
+    }
+
+    let stats = heap.stats().unwrap();
+    assert_eq!((stats.heap_size, stats.allocated, stats.freed), (4096, 128, 108));
+    assert_eq!((stats.in_use(), stats.peak), (20, 108));
+    assert_eq!((stats.allocations, stats.frees), (3, 2));
+    assert_eq!(&stats.classes[..5], &[1, 0, 1, 0, 1]);
+    assert_eq!(stats.classes[5..].iter().sum::<u64>(), 0);
+}
+
+#[test]
+fn free_prints_allocator_stats() {
+    let mut stats = Stats::new(1 << 20);
+    stats.record_alloc(&layout(100, 8));
+    stats.record_alloc(&layout(8, 8));
+    stats.record_dealloc(&layout(8, 8));
+    assert_eq!(stats.to_string(),
+               "heap:           1048576 bytes\n\
+                in use:             100 bytes (peak 108)\n\
+                allocated:          108 bytes in 2 allocations\n\
+                freed:                8 bytes in 1 frees\n\
+                \x20      class  allocations\n\
+                \x20          8  1\n\
+                \x20        128  1\n");
+
+    // The host's heap isn't the kernel's, which is never initialized.
+    fake::take_output();
+    let command = shell::find("free").expect("free is built in");
+    assert_eq!(command.run(&mut CONSOLE.lock(), &[]), Err(Failure::Reported));
+    assert!(str::from_utf8(&fake::take_output()).unwrap().contains("the heap isn't initialized"));
+    assert_eq!(command.run(&mut CONSOLE.lock(), &["-h"]), Err(Failure::Usage));
+}
+
+#[test]
+fn canaries_surround_allocations() {
+    let (block, offset) = canary::padded(&layout(10, 4)).unwrap();
+    assert_eq!((block.size(), block.align(), offset),
+               (CANARY_SIZE + 10 + CANARY_SIZE, 4, CANARY_SIZE));
+    let (block, offset) = canary::padded(&layout(10, 64)).unwrap();
+    assert_eq!((block.size(), block.align(), offset), (64 + 10 + CANARY_SIZE, 64, 64));
+
+    let mut bytes = [0u8; 64];
+    let start = bytes.as_mut_ptr();
+    unsafe {
+        let ptr = canary::guard(start, CANARY_SIZE, 10);
+        assert_eq!(ptr as usize, start as usize + CANARY_SIZE);
+        *ptr.offset(9) = 0xFF;
+        assert_eq!(canary::check(start, CANARY_SIZE, 10), Ok(()));
+
+        *ptr.offset(10) = 0;
+        assert_eq!(canary::check(start, CANARY_SIZE, 10), Err(ptr as usize + 10));
+        *ptr.offset(-1) = 0;
+        assert_eq!(canary::check(start, CANARY_SIZE, 10), Err(ptr as usize - 1));
+
+        canary::poison(start, 42);
+    }
+
+    assert!(bytes[..42].iter().all(|&byte| byte == POISON));
+    assert_eq!(bytes[42], 0);
+}
+
+#[test]
+#[cfg(feature = "heap_canaries")]
+#[should_panic(expected = "heap corruption")]
+fn canaries_catch_overruns() {
+    let mut buffer = vec![0u64; 2048];
+    let start = align_up(buffer.as_mut_ptr() as usize, 8);
+    let heap = Allocator::uninitialized();
+    heap.initialize_with(start, start + 4096);
+
+    let mut handle = &heap;
+    unsafe {
+        let ptr = handle.alloc(layout(12, 4)).unwrap();
+        ::std::ptr::write_bytes(ptr, 0, 13);
+        handle.dealloc(ptr, layout(12, 4));
+    }
+}
+
+#[test]
+fn frame_allocator_hands_out_pages() {
+    let mut bitmap = [0u64; 2];
+    let mut frames = FrameAllocator::new(0x10_0000, 70, &mut bitmap);
+    assert_eq!(frames.alloc(), Some(0x10_0000));
+    assert_eq!(frames.alloc(), Some(0x10_1000));
+    frames.free(0x10_0000);
+    assert_eq!(frames.alloc(), Some(0x10_0000));
+    assert_eq!(frames.free_frames(), 68);
+
+    for _ in 0..68 {
+        assert!(frames.alloc().is_some());
+    }
+
+    assert_eq!(frames.alloc(), None);
+    frames.free(0x10_0000 + 69 * PAGE_SIZE);
+    assert_eq!(frames.alloc(), Some(0x10_0000 + 69 * PAGE_SIZE));
+}
+
+#[test]
+fn frame_allocator_finds_aligned_runs() {
+    let mut bitmap = [0u64; 1];
+    let mut frames = FrameAllocator::new(0x10_1000, 32, &mut bitmap);
+    assert_eq!(frames.alloc_contiguous(2, 0x4000), Some(0x10_4000));
+    assert_eq!(frames.alloc_contiguous(3, 1), Some(0x10_1000));
+    assert_eq!(frames.alloc_contiguous(4, 0x4000), Some(0x10_8000));
+    assert_eq!(frames.alloc(), Some(0x10_6000));
+    assert_eq!(frames.free_frames(), 32 - 10);
+
+    // Runs skip over allocated frames, and must fit in what's left.
+    frames.free(0x10_2000);
+    assert_eq!(frames.alloc_contiguous(2, 1), Some(0x10_c000));
+    assert_eq!(frames.alloc_contiguous(32, 1), None);
+    assert_eq!(frames.alloc_contiguous(0, 1), None);
+    assert_eq!(frames.alloc_contiguous(1, 0x10_0000), None);
+}
+
+#[test]
+fn frame_allocator_catches_double_frees() {
+    let output = failure_output(|| {
+        let mut bitmap = [0u64; 1];
+        let mut frames = FrameAllocator::new(0, 8, &mut bitmap);
+        let frame = frames.alloc().unwrap();
+        frames.free(frame);
+        frames.free(frame);
+    });
+
+    assert!(output.ends_with("  note: frame 0x0 freed twice\r\n"), "{}", output);
+}
+
+#[test]
+fn frame_allocator_keeps_its_bitmap_in_the_region() {
+    let mut buffer = vec![0u64; 3 * 512];
+    let start = buffer.as_mut_ptr() as usize;
+    let end = start + buffer.len() * 8;
+    let frames = unsafe { frames::in_region(start, end) };
+
+    let first = align_up(start, PAGE_SIZE);
+    assert_eq!(frames.base(), first + PAGE_SIZE);
+    assert_eq!(frames.frames(), (end - first) / PAGE_SIZE - 1);
+    assert_eq!(frames::usage(), None);
+}
+
+#[test]
+fn dma_buffers_come_from_their_pool() {
+    static TEST_POOL: Pool = Pool::uninitialized();
+    assert!(TEST_POOL.alloc(16, 1).is_none());
+    assert!(dma::alloc(16, 1).is_none());
+
+    // The pool's memory must outlive the test, as the kernel's does.
+    let mut memory = vec![0xFFu64; 6 * 512];
+    let start = memory.as_mut_ptr() as usize;
+    ::std::mem::forget(memory);
+    TEST_POOL.initialize_with(unsafe { frames::in_region(start, start + 6 * PAGE_SIZE) });
+    let (base, end) = TEST_POOL.region().unwrap();
+    assert_eq!(base, align_up(start, PAGE_SIZE) + PAGE_SIZE);
+
+    let mut buffer = TEST_POOL.alloc(PAGE_SIZE + 1, 1).unwrap();
+    assert_eq!((buffer.addr(), buffer.len()), (base, PAGE_SIZE + 1));
+    assert_eq!(buffer.bus_address(), 0xC0000000 | base as u32);
+    assert!(buffer.as_slice().iter().all(|&byte| byte == 0));
+    buffer.as_mut_slice()[PAGE_SIZE] = 7;
+
+    let second = TEST_POOL.alloc(1, 1).unwrap();
+    assert_eq!(second.addr(), base + 2 * PAGE_SIZE);
+    assert!(base + 3 * PAGE_SIZE <= end);
+    drop(buffer);
+    assert_eq!(TEST_POOL.alloc(2 * PAGE_SIZE, 1).map(|buffer| buffer.addr()), Some(base));
+    assert!(TEST_POOL.alloc(end - base, 1).is_none());
+}
+
+#[test]
+fn memory_map_lists_regions_in_order() {
+    memory::set_framebuffer(0x3C10_0000, 0x3C4B_0000);
+    let map = memory::map();
+    assert!(map.windows(2).all(|pair| pair[0].start <= pair[1].start));
+
+    let names: Vec<&str> = map.iter().map(|region| region.name).collect();
+    assert_eq!(names, ["ATAGs", "boot stack", "hotload staging", "framebuffer", "peripherals",
+                       "local peripherals"]);
+    assert_eq!((map[2].start, map[2].end, map[2].size()),
+               (STAGING_ADDR, HOTLOAD_END, HOTLOAD_END - STAGING_ADDR));
+
+    let mut table = String::new();
+    memory::write_map(&mut table, &map[..]).unwrap();
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines[0], "start       end              size  perms         region");
+    assert_eq!(lines[1], "0x00000100  0x00004100     16 KiB  r--           ATAGs");
+    assert_eq!(lines[4], "0x3c100000  0x3c4b0000      3 MiB  rw- uncached  framebuffer");
+    assert_eq!(lines[5], "0x3f000000  0x40000000     16 MiB  rw- device    peripherals");
+}
+
+#[test]
+fn trap_frame_layout_matches_the_vectors() {
+    use std::mem::size_of;
+
+    let frame = TrapFrame::zeroed();
+    let base = &frame as *const TrapFrame as usize;
+    assert_eq!(size_of::<TrapFrame>(), 800);
+    assert_eq!(size_of::<TrapFrame>() % 16, 0);
+    assert_eq!(&frame.sp as *const u64 as usize - base, 16);
+    assert_eq!(&frame.q as *const _ as usize - base, 32);
+    assert_eq!(&frame.x as *const _ as usize - base, 32 + 32 * 16);
+    assert_eq!(&frame.x[30] as *const u64 as usize - base, 32 + 32 * 16 + 30 * 8);
+}
+
+#[test]
+fn kernel_threads_start_at_their_entry_on_their_own_stack() {
+    extern "C" fn entry() -> u32 {
+        0
+    }
+
+    let process = Process::kernel_thread(entry).expect("a stack");
+    assert_eq!(process.stack.top() - process.stack.bottom(), STACK_SIZE);
+    assert_eq!(process.trap_frame.sp, process.stack.top() as u64);
+    assert_eq!(process.trap_frame.elr, entry as usize as u64);
+    assert!(process.trap_frame.x[30] != 0);
+
+    // EL1 on SP_EL0, with IRQs unmasked.
+    assert_eq!(process.trap_frame.spsr & 0b1111, 0b0100);
+    assert_eq!(process.trap_frame.spsr & 1 << 7, 0);
+    assert_eq!(process.id(), 0);
+    assert_eq!(format!("{:?}", process.state), "Ready");
+}
+
+#[test]
+fn waiting_processes_are_ready_once_polled_true() {
+    let mut process = Process::new().expect("a stack");
+    process.state = State::Waiting(Box::new(|process| {
+        process.trap_frame.x[0] += 1;
+        process.trap_frame.x[0] == 2
+    }));
+
+    assert!(!process.is_ready());
+    assert_eq!(format!("{:?}", process.state), "Waiting");
+    assert!(process.is_ready());
+    assert!(process.is_ready());
+    assert_eq!(process.trap_frame.x[0], 2);
+
+    process.state = State::Running;
+    assert!(!process.is_ready());
+}
+
+#[test]
+fn scheduler_switches_round_robin() {
+    let scheduler = Scheduler::uninitialized();
+    let ids: Vec<Id> = (1..4).map(|i| {
+        let mut process = Process::new().expect("a stack");
+        process.trap_frame.elr = 0x1000 * i;
+        scheduler.add(process).expect("an ID")
+    }).collect();
+    assert_eq!(ids, [1, 2, 3]);
+    assert_eq!(scheduler.current(), None);
+
+    // With nothing running, the first switch only resumes the first process.
+    let mut tf = TrapFrame::zeroed();
+    assert_eq!(scheduler.switch(State::Ready, &mut tf), 1);
+    assert_eq!((tf.elr, tf.tpidr), (0x1000, 1));
+
+    tf.x[0] = 7;
+    assert_eq!(scheduler.switch(State::Ready, &mut tf), 2);
+    assert_eq!((tf.elr, tf.x[0]), (0x2000, 0));
+    assert_eq!(scheduler.switch(State::Waiting(Box::new(|_| false)), &mut tf), 3);
+    assert_eq!(scheduler.switch(State::Ready, &mut tf), 1);
+    assert_eq!((tf.elr, tf.x[0]), (0x1000, 7));
+
+    // Process 2 is skipped while it waits.
+    assert_eq!(scheduler.switch(State::Ready, &mut tf), 3);
+    assert_eq!(scheduler.current(), Some(3));
+    assert_eq!(scheduler.len(), 3);
+}
+
+#[test]
+fn parents_wait_for_their_children_to_exit() {
+    let scheduler = Scheduler::uninitialized();
+    scheduler.add(Process::new().expect("a stack")).expect("an ID");
+    let mut tf = TrapFrame::zeroed();
+    assert_eq!(scheduler.switch(State::Ready, &mut tf), 1);
+
+    // Processes added while process 1 runs are its children.
+    for _ in 0..2 {
+        scheduler.add(Process::new().expect("a stack")).expect("an ID");
+    }
+    assert!(scheduler.is_child(2) && scheduler.is_child(3));
+    assert!(!scheduler.is_child(1));
+
+    tf.x[0] = 2;
+    assert_eq!(scheduler.switch(syscall::waiting_for(2), &mut tf), 2);
+    assert_eq!(scheduler.switch(State::Dead(3), &mut tf), 3);
+    assert_eq!(scheduler.len(), 2);
+
+    // The parent wakes with the exit code, and the child is forgotten.
+    assert_eq!(scheduler.switch(State::Ready, &mut tf), 1);
+    assert_eq!((tf.x[0], tf.x[7]), (3, 0));
+    assert!(!scheduler.is_child(2) && scheduler.is_child(3));
+    assert_eq!(format!("{:?}", State::Dead(3)), "Dead(3)");
+}
+
+#[test]
+fn syndromes_decode_the_exception_class() {
+    assert_eq!(Syndrome::from(0x5600_0001), Syndrome::Svc(1));
+    assert_eq!(Syndrome::from(0xF200_03E8), Syndrome::Brk(1000));
+    assert_eq!(Syndrome::from(0x9600_0021), Syndrome::DataAbort);
+    assert_eq!(Syndrome::from(0x8600_000F), Syndrome::InstructionAbort);
+    assert_eq!(Syndrome::from(0x0200_0000), Syndrome::Other(0));
+}
+
+#[test]
+fn faulting_programs_exit_with_the_signal_they_would_raise() {
+    assert_eq!(traps::fault_exit_code(Syndrome::DataAbort), 139);
+    assert_eq!(traps::fault_exit_code(Syndrome::InstructionAbort), 139);
+    assert_eq!(traps::fault_exit_code(Syndrome::Brk(0)), 133);
+    assert_eq!(traps::fault_exit_code(Syndrome::Other(0)), 132);
+}
+
+#[test]
+fn sleeping_processes_wake_with_the_time_slept() {
+    fake::timer::set(5_000_000);
+    let mut process = Process::new().expect("a stack");
+    process.trap_frame.x[7] = 9;
+    process.state = syscall::sleeping(20);
+
+    fake::timer::advance(19_999);
+    assert!(!process.is_ready());
+    fake::timer::advance(5_001);
+    assert!(process.is_ready());
+    assert_eq!((process.trap_frame.x[0], process.trap_frame.x[7]), (25, 0));
+}
+
+#[test]
+fn unknown_syscalls_fail() {
+    let mut tf = TrapFrame::zeroed();
+    syscall::handle_syscall(0x1234, &mut tf);
+    assert_eq!(syscall::Error::result(tf.x[0], tf.x[7]), Err(syscall::Error::NoSuchSyscall));
+    assert_eq!(syscall::Error::result(3, 0), Ok(3));
+    assert_eq!(syscall::wait(1), Err(syscall::Error::NoSuchProcess));
+    assert_eq!(syscall::sleep(7), Ok(7));
+}
+
+/// Returns a position-independent AArch64 executable linked at 0: a `ret` at
+/// its entry point, 0xB0, a pointer to it at 0xB8 with the relocation that
+/// fixes it up, and 0xE8 bytes of BSS.
+fn pie_executable() -> Vec<u8> {
+    fn put(data: &mut [u8], at: usize, value: u64, size: usize) {
+        for i in 0..size {
+            data[at + i] = (value >> (i * 8)) as u8;
+        }
+    }
+
+    let mut data = vec![0u8; 0x118];
+    data[..8].copy_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1, 1, 0]);
+    put(&mut data, 16, elf::ET_DYN as u64, 2);
+    put(&mut data, 18, elf::EM_AARCH64 as u64, 2);
+    put(&mut data, 24, 0xB0, 8);
+    put(&mut data, 32, 64, 8);
+    put(&mut data, 54, 56, 2);
+    put(&mut data, 56, 2, 2);
+
+    // The PT_LOAD segment covers the whole file, and the PT_DYNAMIC one the
+    // dynamic section at 0xC0.
+    let segments = [(elf::PT_LOAD, 0, 0x118, 0x200), (elf::PT_DYNAMIC, 0xC0, 0x40, 0x40)];
+    for (i, &(kind, offset, file_size, mem_size)) in segments.iter().enumerate() {
+        let at = 64 + i * 56;
+        put(&mut data, at, kind as u64, 4);
+        put(&mut data, at + 8, offset, 8);
+        put(&mut data, at + 16, offset, 8);
+        put(&mut data, at + 32, file_size, 8);
+        put(&mut data, at + 40, mem_size, 8);
+    }
+
+    put(&mut data, 0xB0, 0xD65F03C0, 4);
+    let dynamic = [(elf::DT_RELA, 0x100), (elf::DT_RELASZ, 24), (elf::DT_RELAENT, 24)];
+    for (i, &(tag, value)) in dynamic.iter().enumerate() {
+        put(&mut data, 0xC0 + i * 16, tag, 8);
+        put(&mut data, 0xC8 + i * 16, value, 8);
+    }
+
+    put(&mut data, 0x100, 0xB8, 8);
+    put(&mut data, 0x108, elf::R_AARCH64_RELATIVE as u64, 8);
+    put(&mut data, 0x110, 0xB0, 8);
+    data
+}
+
+fn read_u64(data: &[u8], at: usize) -> u64 {
+    (0..8).fold(0, |value, i| value | (data[at + i] as u64) << (i * 8))
+}
+
+#[test]
+fn executables_load_at_any_address() {
+    let executable = pie_executable();
+    let elf = Elf::parse(&executable).unwrap();
+    assert_eq!((elf.entry, elf.segments.len()), (0xB0, 1));
+    assert_eq!(elf.span(), Some((0, 0x200)));
+
+    let mut image = vec![0xAA; 0x200];
+    elf.load(&mut image, 0x40_0000).unwrap();
+    assert_eq!(&image[..0xB8], &executable[..0xB8]);
+    assert_eq!(&image[0xC0..0x118], &executable[0xC0..]);
+    assert_eq!(read_u64(&image, 0xB8), 0x40_00B0);
+    assert!(image[0x118..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn processes_start_at_the_entry_of_their_executable() {
+    let mut process = Process::load(&pie_executable()).unwrap();
+    let base = process.image.as_ref().expect("an image").base() as u64;
+    assert_eq!(process.trap_frame.elr, base + 0xB0);
+    assert_eq!(process.trap_frame.spsr, USER_SPSR);
+    assert_eq!(process.trap_frame.sp as usize, process.stack.top());
+
+    let image = process.image.as_mut().expect("an image").as_mut_slice();
+    assert_eq!(image.len(), PAGE_SIZE);
+    assert_eq!(read_u64(image, 0xB8), base + 0xB0);
+}
+
+#[test]
+fn programs_may_only_pass_their_own_memory() {
+    let process = Process::load(&pie_executable()).unwrap();
+    let image = process.image.as_ref().expect("an image").base();
+    assert!(process.may_access(image, PAGE_SIZE));
+    assert!(process.may_access(process.stack.top() - 16, 16));
+    assert!(!process.may_access(image, PAGE_SIZE + 1));
+    assert!(!process.may_access(image - 1, 2));
+    assert!(!process.may_access(process.stack.top() - 16, 17));
+    assert!(!process.may_access(image, usize::max_value()));
+
+    // Kernel threads share the kernel's memory.
+    assert!(Process::new().expect("a stack").may_access(0x8_0000, 16));
+}
+
+#[test]
+fn malformed_executables_are_rejected() {
+    use std::io;
+
+    let invalid = |data: &[u8]| Elf::parse(data).unwrap_err().kind();
+    let executable = pie_executable();
+    assert_eq!(invalid(&executable[..0x40]), io::ErrorKind::InvalidData);
+    assert_eq!(invalid(b"#!/bin/sh\n"), io::ErrorKind::InvalidData);
+
+    let mut modified = executable.clone();
+    modified[16] = 2;
+    assert_eq!(Process::load(&modified).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let mut modified = executable.clone();
+    modified[18] = 62;
+    assert_eq!(invalid(&modified), io::ErrorKind::InvalidData);
+
+    // Sizes and offsets that would overflow are rejected, not wrapped.
+    let mut modified = executable.clone();
+    modified[64 + 40..64 + 48].copy_from_slice(&[0xF0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+    assert_eq!(Process::load(&modified).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    let mut modified = executable.clone();
+    modified[32..40].copy_from_slice(&[0xFF; 8]);
+    assert_eq!(invalid(&modified), io::ErrorKind::InvalidData);
+    let mut modified = executable.clone();
+    modified[0xC8..0xD0].copy_from_slice(&[0xFF; 8]);
+    assert!(Elf::parse(&modified).unwrap().load(&mut vec![0; 0x200], 0).is_err());
+
+    // Only relative relocations are supported.
+    let mut modified = executable.clone();
+    modified[0x108] = 1;
+    let elf = Elf::parse(&modified).unwrap();
+    assert!(elf.load(&mut vec![0; 0x200], 0).is_err());
+
+    assert_eq!(syscall::Error::from(io::Error::new(io::ErrorKind::InvalidData, "bad")),
+               syscall::Error::BadExecutable);
+    assert_eq!(syscall::Error::BadExecutable.to_string(), "not an executable");
+}
+
+/// Returns a nine sector disk: an MBR, then a FAT32 partition with one
+/// sector per cluster, holding `/HELLO.TXT` and four free clusters.
+fn fat32_image() -> Vec<u8> {
+    let mut image = vec![0u8; 9 * 512];
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    // The partition starts at sector 1 and fills the disk.
+    put(&mut image, 446 + 4, &[0x0C]);
+    put(&mut image, 446 + 8, &[1, 0, 0, 0, 8, 0, 0, 0]);
+    put(&mut image, 510, &[0x55, 0xAA]);
+
+    // 512 byte sectors, 1 per cluster, 1 reserved, 1 FAT of 1 sector, 8
+    // sectors in all, the root directory at cluster 2.
+    put(&mut image, 512 + 11, &[0x00, 0x02, 1, 1, 0, 1]);
+    put(&mut image, 512 + 32, &[8, 0, 0, 0]);
+    put(&mut image, 512 + 36, &[1, 0, 0, 0]);
+    put(&mut image, 512 + 44, &[2, 0, 0, 0]);
+    put(&mut image, 512 + 510, &[0x55, 0xAA]);
+
+    // Clusters 2 and 3 are each a whole chain.
+    put(&mut image, 2 * 512 + 8, &[0xFF, 0xFF, 0xFF, 0x0F, 0xFF, 0xFF, 0xFF, 0x0F]);
+
+    let mut entry = [0u8; 32];
+    entry[..11].copy_from_slice(b"HELLO   TXT");
+    entry[11] = 0x20;
+    entry[26] = 3;
+    entry[28] = 5;
+    put(&mut image, 3 * 512, &entry);
+    put(&mut image, 4 * 512, b"hello");
+    image
+}
+
+#[test]
+fn filesystem_mounts_a_fat32_partition() {
+    use std::io::{self, Read};
+
+    static TEST_FILESYSTEM: FileSystem = FileSystem::uninitialized();
+    assert!(!TEST_FILESYSTEM.is_mounted());
+    let error = (&TEST_FILESYSTEM).open("/").err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    assert!(TEST_FILESYSTEM.initialize_with(io::Cursor::new(vec![0u8; 1024])).is_err());
+
+    TEST_FILESYSTEM.initialize_with(io::Cursor::new(fat32_image())).unwrap();
+    assert!(TEST_FILESYSTEM.is_mounted());
+
+    let root = (&TEST_FILESYSTEM).open_dir("/").unwrap();
+    let names: Vec<String> = root.entries().unwrap().map(|e| e.name().to_string()).collect();
+    assert_eq!(names, ["HELLO.TXT"]);
+
+    let mut contents = String::new();
+    let mut file = (&TEST_FILESYSTEM).open_file("/hello.txt").unwrap();
+    file.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "hello");
+}
+
+#[test]
+fn filesystem_normalizes_paths() {
+    use std::path::{Path, PathBuf};
+
+    let normalized = |path: &str| fs::normalize(Path::new(path)).unwrap();
+    assert_eq!(normalized("/"), PathBuf::from("/"));
+    assert_eq!(normalized("/a/./b//c/"), PathBuf::from("/a/b/c"));
+    assert_eq!(normalized("/a/../../b/.."), PathBuf::from("/"));
+    assert!(fs::normalize(Path::new("a/b")).is_err());
+}
+
+#[test]
+fn filesystem_resolves_paths_to_mounts() {
+    use std::io::{self, Read};
+    use std::path::PathBuf;
+
+    static TEST_FILESYSTEM: FileSystem = FileSystem::uninitialized();
+    let mount = || Backend::Fat32(VFat::from(io::Cursor::new(fat32_image())).unwrap());
+    TEST_FILESYSTEM.initialize_with(io::Cursor::new(fat32_image())).unwrap();
+    TEST_FILESYSTEM.mount("/mnt/card", mount()).unwrap();
+    let e = TEST_FILESYSTEM.mount("/mnt/./card/", mount()).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+    assert_eq!(TEST_FILESYSTEM.mounts(), vec![(PathBuf::from("/"), "fat32"),
+                                              (PathBuf::from("/mnt/card"), "fat32")]);
+
+    // The longest mount that is a prefix of a path wins, and `..` leaves it.
+    (&TEST_FILESYSTEM).create_file("/mnt/card/new.txt").unwrap();
+    assert!((&TEST_FILESYSTEM).open("/new.txt").is_err());
+    let names = |path: &str| -> Vec<String> {
+        let dir = (&TEST_FILESYSTEM).open_dir(path).unwrap();
+        dir.entries().unwrap().map(|e| e.name().to_string()).collect()
+    };
+
+    assert_eq!(names("/mnt/card"), ["HELLO.TXT", "new.txt"]);
+    assert_eq!(names("/mnt/card/../.."), ["HELLO.TXT"]);
+
+    let mut contents = String::new();
+    let mut file = (&TEST_FILESYSTEM).open_file("/mnt/card/hello.txt").unwrap();
+    file.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "hello");
+
+    let e = (&TEST_FILESYSTEM).rename("/mnt/card/new.txt", "/new.txt").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+    (&TEST_FILESYSTEM).rename("/mnt/card/new.txt", "/mnt/card/renamed.txt").unwrap();
+    assert_eq!(names("/mnt/card"), ["HELLO.TXT", "renamed.txt"]);
+
+    // Once unmounted, the path is on the root file system again.
+    TEST_FILESYSTEM.unmount("/mnt/card").unwrap();
+    let e = TEST_FILESYSTEM.unmount("/mnt/card").err().unwrap();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    let e = (&TEST_FILESYSTEM).open("/mnt/card/hello.txt").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::NotFound);
+    assert!(TEST_FILESYSTEM.is_mounted());
+}
+
+#[test]
+fn filesystem_mounts_a_tmpfs() {
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+    use std::path::PathBuf;
+    use fat32::traits::File;
+
+    // A tmpfs needs no device, so it can be the only file system.
+    static TEST_FILESYSTEM: FileSystem = FileSystem::uninitialized();
+    TEST_FILESYSTEM.mount("/", Backend::Tmpfs(TmpFs::new())).unwrap();
+    TEST_FILESYSTEM.mount("/tmp", Backend::Tmpfs(TmpFs::new())).unwrap();
+    assert_eq!(TEST_FILESYSTEM.mounts(), vec![(PathBuf::from("/"), "tmpfs"),
+                                              (PathBuf::from("/tmp"), "tmpfs")]);
+
+    let names = |path: &str| -> Vec<String> {
+        let dir = (&TEST_FILESYSTEM).open_dir(path).unwrap();
+        dir.entries().unwrap().map(|e| e.name().to_string()).collect()
+    };
+
+    (&TEST_FILESYSTEM).create_dir("/tmp/a/b", true).unwrap();
+    let mut file = (&TEST_FILESYSTEM).create_file("/tmp/a/notes.txt").unwrap();
+    file.write_all(b"hello world").unwrap();
+    assert_eq!(file.size(), 11);
+    file.seek(SeekFrom::Start(6)).unwrap();
+    file.write_all(b"there!").unwrap();
+    assert!(file.seek(SeekFrom::End(1)).is_err());
+    drop(file);
+
+    assert_eq!(names("/"), Vec::<String>::new());
+    assert_eq!(names("/tmp"), ["a"]);
+    assert_eq!(names("/tmp/a/b/.."), ["b", "notes.txt"]);
+    let entry = (&TEST_FILESYSTEM).open("/tmp/a/notes.txt").unwrap();
+    assert!(entry.metadata().attributes.archive());
+    assert!((&TEST_FILESYSTEM).open("/tmp/a/b").unwrap().is_dir());
+
+    let mut contents = String::new();
+    let mut file = (&TEST_FILESYSTEM).open_file("/tmp/a/notes.txt").unwrap();
+    file.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "hello there!");
+
+    let e = (&TEST_FILESYSTEM).create_file("/tmp/a/notes.txt").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+    let e = (&TEST_FILESYSTEM).create_file("/tmp/none/x").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = (&TEST_FILESYSTEM).open("/tmp/a/notes.txt/x").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    // Renames stay on the tmpfs, and a directory can't move into itself.
+    (&TEST_FILESYSTEM).rename("/tmp/a/notes.txt", "/tmp/a/b/moved.txt").unwrap();
+    assert_eq!(names("/tmp/a"), ["b"]);
+    assert_eq!(names("/tmp/a/b"), ["moved.txt"]);
+    let e = (&TEST_FILESYSTEM).rename("/tmp/a", "/tmp/a/b/a").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = (&TEST_FILESYSTEM).rename("/tmp/a/b/moved.txt", "/moved.txt").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+
+    // A removed file stays readable through the handles that are open.
+    let e = (&TEST_FILESYSTEM).remove("/tmp/a", false).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+    (&TEST_FILESYSTEM).remove("/tmp/a", true).unwrap();
+    assert_eq!(names("/tmp"), Vec::<String>::new());
+    file.seek(SeekFrom::Start(0)).unwrap();
+    contents.clear();
+    file.read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "hello there!");
+
+    assert!((&TEST_FILESYSTEM).remove("/tmp", true).is_err());
+    let e = TEST_FILESYSTEM.check("/tmp/a", false).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+    TEST_FILESYSTEM.unmount("/tmp").unwrap();
+    TEST_FILESYSTEM.unmount("/").unwrap();
+    assert!(!TEST_FILESYSTEM.is_mounted());
+}
+
+#[test]
+fn shell_navigates_and_edits_the_file_system() {
+    use std::io::{self, Write};
+    use std::path::PathBuf;
+    use FILESYSTEM;
+
+    // The only test to use the kernel's file system and working directory.
+    FILESYSTEM.initialize_with(io::Cursor::new(fat32_image())).unwrap();
+    (&FILESYSTEM).create_dir("/docs", false).unwrap();
+    let mut file = (&FILESYSTEM).create_file("/docs/notes.txt").unwrap();
+    file.write_all(b"one\ntwo\n").unwrap();
+    drop(file);
+
+    let run = |line: &str| -> (Result<(), Failure>, String) {
+        fake::take_output();
+        let result = shell::run_line(&mut CONSOLE.lock(), line);
+        (result, String::from_utf8(fake::take_output()).unwrap())
+    };
+
+    assert_eq!(run("pwd"), (Ok(()), "/\r\n".to_string()));
+    assert_eq!(run("ls"), (Ok(()), "HELLO.TXT\r\ndocs/\r\n".to_string()));
+    assert_eq!(run("cd docs"), (Ok(()), String::new()));
+    assert_eq!(shell::cwd(), PathBuf::from("/docs"));
+    assert_eq!(run("pwd"), (Ok(()), "/docs\r\n".to_string()));
+    assert_eq!(run("ls -a"), (Ok(()), "./\r\n../\r\nnotes.txt\r\n".to_string()));
+    assert_eq!(run("cat notes.txt ../hello.txt"), (Ok(()), "one\r\ntwo\r\nhello".to_string()));
+
+    // `-l` shows the attributes, modification time and size.
+    assert_eq!(run("ls -l /docs/notes.txt").1,
+               "----a 1980-01-01 00:00:00          8 notes.txt\r\n");
+    assert_eq!(run("ls -al ..").1.lines().nth(1).unwrap(),
+               "d---- 1980-01-01 00:00:00          0 docs/");
+    let (result, output) = run("ls / .");
+    assert_eq!(result, Ok(()));
+    assert_eq!(output, "/:\r\nHELLO.TXT\r\ndocs/\r\n\r\n.:\r\nnotes.txt\r\n");
+
+    let (result, output) = run("stat notes.txt");
+    assert_eq!(result, Ok(()));
+    assert!(output.starts_with("      path: /docs/notes.txt\r\n      type: file, 8 bytes\r\n\
+                                attributes: ----a\r\n"));
+    assert!(output.ends_with("  accessed: 1980-01-01\r\n"));
+
+    // `..` at the root stays there, and failures leave the directory alone.
+    assert_eq!(run("cd ../../.."), (Ok(()), String::new()));
+    assert_eq!(shell::cwd(), PathBuf::from("/"));
+    assert_eq!(run("cd hello.txt").0, Err(Failure::Reported));
+    assert!(run("cd nothing").1.contains("cd: nothing: "));
+    assert!(run("cat docs").1.contains("cat: docs: is a directory"));
+    assert_eq!(run("ls -x").0, Err(Failure::Usage));
+    assert_eq!(shell::cwd(), PathBuf::from("/"));
+    assert_eq!(run("cd /docs/"), (Ok(()), String::new()));
+    assert_eq!(run("cd"), (Ok(()), String::new()));
+    assert_eq!(shell::cwd(), PathBuf::from("/"));
+
+    // Files and directories can be created, copied, moved and removed.
+    assert_eq!(run("touch empty.txt empty.txt"), (Ok(()), String::new()));
+    assert_eq!(run("cp docs/notes.txt copy.txt"), (Ok(()), String::new()));
+    assert_eq!(run("cat copy.txt").1, "one\r\ntwo\r\n");
+    assert_eq!(run("mv copy.txt docs"), (Ok(()), String::new()));
+    assert_eq!(run("mv docs/copy.txt docs/moved.txt"), (Ok(()), String::new()));
+    assert_eq!(run("ls docs").1, "notes.txt\r\nmoved.txt\r\n");
+    assert_eq!(run("rm docs/moved.txt empty.txt"), (Ok(()), String::new()));
+    assert_eq!(run("mkdir -p deep/er"), (Ok(()), String::new()));
+    assert_eq!(run("ls deep").1, "er/\r\n");
+    assert_eq!(run("rm -r deep"), (Ok(()), String::new()));
+    assert_eq!(run("ls").1, "HELLO.TXT\r\ndocs/\r\n");
+
+    // Failures say why.
+    let failure = |line: &str| {
+        let (result, output) = run(line);
+        assert_eq!(result, Err(Failure::Reported));
+        output
+    };
+
+    assert!(failure("mkdir docs").contains("mkdir: docs: file exists"));
+    assert!(failure("mkdir a/b").contains("mkdir: a/b: no such directory /a"));
+    assert!(failure("touch hello.txt/x").contains("touch: hello.txt/x: /hello.txt is not a directory"));
+    assert!(failure("cp hello.txt HELLO.TXT").contains("are the same file"));
+    assert!(failure("cp nothing x").contains("cp: nothing: no such file or directory"));
+    assert!(failure("cp docs x").contains("cp: docs: is a directory"));
+    assert!(failure("mv hello.txt docs/notes.txt").contains("mv: /docs/notes.txt: file exists"));
+    assert!(failure("rm docs").contains("rm: docs: is a directory"));
+    assert!(failure("rm /").contains("rm: /: can't remove the root directory"));
+    assert!(failure("run nothing").contains("run: nothing: no such file"));
+    assert!(failure("run hello.txt").contains("run: hello.txt: not an executable"));
+
+    // Programs run in the background until they are waited for.
+    let mut file = (&FILESYSTEM).create_file("/prog").unwrap();
+    file.write_all(&pie_executable()).unwrap();
+    drop(file);
+    let (result, output) = run("run prog &");
+    assert_eq!(result, Ok(()));
+    assert!(output.starts_with('[') && output.ends_with("] prog\r\n"));
+    let pid = &output[1..output.find(']').unwrap()];
+    assert_eq!(run("jobs").1, format!("[{}] running  prog\r\n", pid));
+    assert!(failure("fg 0").contains("fg: 0: no such job"));
+    assert!(failure("run hello.txt &").contains("run: hello.txt: not an executable"));
+    assert_eq!(run("run &").0, Err(Failure::Usage));
+    // Nothing runs processes on the host, so waiting fails, but still forgets the job.
+    assert!(failure(&format!("fg {}", pid)).contains("no such process"));
+    assert_eq!(run("jobs"), (Ok(()), String::new()));
+    assert!(failure("fg").contains("fg: no background jobs"));
+    assert_eq!(run("rm prog"), (Ok(()), String::new()));
+
+    // Tab completes command names, then paths relative to the working directory.
+    let candidates = |line| {
+        let (word, mut candidates) = shell::completion_candidates(line);
+        candidates.retain(|candidate| candidate.starts_with(word));
+        (word.to_string(), candidates)
+    };
+    assert_eq!(candidates("xx"), ("xx".to_string(), vec!["xxd".to_string()]));
+    assert_eq!(candidates("cat d"), ("d".to_string(), vec!["docs/".to_string()]));
+    assert_eq!(candidates("cat docs/n"), ("n".to_string(), vec!["notes.txt".to_string()]));
+    assert_eq!(candidates("cat /docs/").1, ["notes.txt"]);
+    assert_eq!(candidates("cat nothing/").1, Vec::<String>::new());
+    assert_eq!(run("cd docs"), (Ok(()), String::new()));
+    assert_eq!(candidates("ls ../H").1, ["HELLO.TXT"]);
+    assert_eq!(candidates("ls ").1, ["notes.txt"]);
+    assert_eq!(run("cd /"), (Ok(()), String::new()));
+
+    // Files are dumped from the start, all of them unless a length is given.
+    let (result, output) = run("xxd docs/notes.txt");
+    assert_eq!(result, Ok(()));
+    assert_eq!(output, format!("00000000  6f 6e 65 0a 74 77 6f 0a{:27}|one.two.|\r\n", ""));
+    assert_eq!(run("xxd /docs/notes.txt 3").1, format!("00000000  6f 6e 65{:42}|one|\r\n", ""));
+    assert!(failure("xxd docs").contains("xxd: docs: is a directory"));
+    assert!(failure("xxd nothing 4").contains("xxd: nothing: "));
+
+    // Scripts are run from files, and may source each other, but not forever.
+    let mut file = (&FILESYSTEM).create_file("/init.sh").unwrap();
+    file.write_all(SCRIPT.as_bytes()).unwrap();
+    drop(file);
+    let (result, output) = run("source init.sh");
+    assert_eq!(result, Err(Failure::Reported));
+    assert!(output.starts_with("one\r\nUnknown command: bogus"));
+    assert!(output.ends_with("two words\r\nsource: init.sh: line 5 failed\r\n"));
+    assert!(!run("source -e /init.sh").1.contains("two words"));
+    let mut file = (&FILESYSTEM).create_file("/loop.sh").unwrap();
+    file.write_all(b"source loop.sh\n").unwrap();
+    drop(file);
+    assert!(failure("source loop.sh").contains("source: loop.sh: scripts are nested too deeply"));
+    assert!(failure("source docs").contains("source: docs: is a directory"));
+    assert_eq!(run("source").0, Err(Failure::Usage));
+    assert_eq!(run("rm init.sh loop.sh"), (Ok(()), String::new()));
+    assert_eq!(run("rm").0, Err(Failure::Usage));
+    assert_eq!(run("ls -R").0, Err(Failure::Usage));
+    assert_eq!(run("ls -a docs").1, "./\r\n../\r\nnotes.txt\r\n");
+
+    // Nothing above left the file system inconsistent.
+    assert_eq!(run("fsck").1, "2 directories, 2 files, 4 clusters used, 2 free\r\n");
+    assert_eq!(run("fsck / /").0, Err(Failure::Usage));
+}
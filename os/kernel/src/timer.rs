@@ -0,0 +1,214 @@
+use std::time::Duration;
+
+use mutex::Mutex;
+use hw::timer::current_time_us;
+
+/// The maximum number of events that can be registered at once.
+pub const MAX_EVENTS: usize = 8;
+
+/// How often the timer interrupt runs the global events, in microseconds: an
+/// event runs up to this long after it is due.
+pub const RESOLUTION_US: u32 = 1_000;
+
+/// A callback run by an event. It is passed the current time in
+/// microseconds.
+pub type EventFn = fn(u64);
+
+/// A registered event.
+#[derive(Copy, Clone)]
+struct Event {
+    /// How often the event repeats in microseconds, or 0 if it runs once.
+    period: u64,
+    next: u64,
+    f: EventFn,
+}
+
+/// An unused slot in the event table.
+const EMPTY: Event = Event { period: 0, next: 0, f: noop };
+
+/// A fixed-capacity table of one-shot and periodic events, kept sorted by
+/// when each is next due so that polling only looks at the events that are
+/// due.
+///
+/// The kernel has a single, global table that is driven by `poll()`. Once
+/// `start()` has run, the timer interrupt calls `poll()` every `RESOLUTION_US`
+/// microseconds.
+pub struct Events {
+    events: [Event; MAX_EVENTS],
+    len: usize,
+}
+
+impl Events {
+    /// Returns a new, empty event table.
+    pub const fn new() -> Events {
+        Events { events: [EMPTY; MAX_EVENTS], len: 0 }
+    }
+
+    /// Returns the number of registered events.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no events are registered.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the time at which the earliest event is due, if there are any
+    /// events.
+    pub fn next_due(&self) -> Option<u64> {
+        self.events[..self.len].first().map(|event| event.next)
+    }
+
+    /// Inserts `event` after every event due no later than it, so that events
+    /// due at the same time run in the order they were registered.
+    fn insert(&mut self, event: Event) -> Result<(), ()> {
+        if self.len == MAX_EVENTS {
+            return Err(());
+        }
+
+        let at = self.events[..self.len].iter()
+            .position(|e| e.next > event.next)
+            .unwrap_or(self.len);
+
+        for i in (at..self.len).rev() {
+            self.events[i + 1] = self.events[i];
+        }
+
+        self.events[at] = event;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes and returns the earliest event if it is due at time `now`.
+    fn pop_due(&mut self, now: u64) -> Option<Event> {
+        if self.len == 0 || self.events[0].next > now {
+            return None;
+        }
+
+        let event = self.events[0];
+        for i in 1..self.len {
+            self.events[i - 1] = self.events[i];
+        }
+
+        self.len -= 1;
+        Some(event)
+    }
+
+    /// Registers `f` to be called once, `delay` microseconds after `now`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(())` if the table is full.
+    pub fn after(&mut self, now: u64, delay: u64, f: EventFn) -> Result<(), ()> {
+        self.insert(Event { period: 0, next: now.saturating_add(delay), f })
+    }
+
+    /// Registers `f` to be called every `period` microseconds, starting
+    /// `period` microseconds after `now`. A `period` of 0 is treated as 1.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(())` if the table is full.
+    pub fn every(&mut self, now: u64, period: u64, f: EventFn) -> Result<(), ()> {
+        let period = if period == 0 { 1 } else { period };
+        self.insert(Event { period, next: now.saturating_add(period), f })
+    }
+
+    /// Collects every event due at time `now` into `due`, rescheduling each
+    /// periodic event for its next period and dropping each one-shot event.
+    /// Returns the number of events collected.
+    ///
+    /// An event that is overdue by more than one period is run once, not once
+    /// per missed period.
+    fn collect_due(&mut self, now: u64, due: &mut [EventFn; MAX_EVENTS]) -> usize {
+        let mut count = 0;
+        while count < MAX_EVENTS {
+            let mut event = match self.pop_due(now) {
+                Some(event) => event,
+                None => break,
+            };
+
+            due[count] = event.f;
+            count += 1;
+            if event.period != 0 {
+                event.next += event.period;
+                if event.next <= now {
+                    event.next = now + event.period;
+                }
+
+                // There is room: the event was just removed.
+                let _ = self.insert(event);
+            }
+        }
+
+        count
+    }
+
+    /// Runs every event that is due at time `now`.
+    pub fn poll(&mut self, now: u64) {
+        let mut due: [EventFn; MAX_EVENTS] = [noop; MAX_EVENTS];
+        let count = self.collect_due(now, &mut due);
+        for f in due[..count].iter() {
+            f(now);
+        }
+    }
+}
+
+fn noop(_: u64) {  }
+
+/// Returns `duration` in whole microseconds.
+fn micros(duration: Duration) -> u64 {
+    duration.as_secs()
+        .saturating_mul(1_000_000)
+        .saturating_add((duration.subsec_nanos() / 1000) as u64)
+}
+
+/// The global event table.
+static EVENTS: Mutex<Events> = Mutex::new(Events::new());
+
+/// Registers `f` to be called once, after `delay`.
+///
+/// # Errors
+///
+/// Returns `Err(())` if `MAX_EVENTS` events are already registered.
+pub fn after(delay: Duration, f: EventFn) -> Result<(), ()> {
+    EVENTS.lock().after(current_time_us(), micros(delay), f)
+}
+
+/// Registers `f` to be called every `period`.
+///
+/// # Errors
+///
+/// Returns `Err(())` if `MAX_EVENTS` events are already registered.
+pub fn every(period: Duration, f: EventFn) -> Result<(), ()> {
+    EVENTS.lock().every(current_time_us(), micros(period), f)
+}
+
+/// Runs every registered event that is due.
+pub fn poll() {
+    let now = current_time_us();
+    let mut due: [EventFn; MAX_EVENTS] = [noop; MAX_EVENTS];
+
+    // Callbacks run without the table locked so that they may register events.
+    let count = EVENTS.lock().collect_due(now, &mut due);
+    for f in due[..count].iter() {
+        f(now);
+    }
+}
+
+/// Starts the timer interrupt that runs `poll()` every `RESOLUTION_US`
+/// microseconds. The events run in interrupt context, with IRQs masked.
+#[cfg(target_arch = "aarch64")]
+pub fn start() {
+    ::hw::timer::set_tick_handler(tick);
+    ::hw::timer::tick_in(RESOLUTION_US);
+}
+
+/// The timer interrupt's handler: requests the next tick and runs the events
+/// that are due.
+#[cfg(target_arch = "aarch64")]
+fn tick() {
+    ::hw::timer::tick_in(RESOLUTION_US);
+    poll();
+}
@@ -0,0 +1,132 @@
+//! Exceptions: the kernel's side of the vectors in `init.S`.
+//!
+//! Every exception saves the registers of what it interrupted as a
+//! `TrapFrame` on the exception stack and calls `handle_exception()` with it.
+//! Whatever the frame holds when the handler returns is what execution
+//! resumes with, which is how the scheduler switches processes.
+
+use std::fmt;
+
+#[cfg(target_arch = "aarch64")]
+use process::TrapFrame;
+
+/// Where an exception was taken from, as the vector that took it says.
+#[repr(u16)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// The kernel's exception level, running on `SP_EL0`, as processes do.
+    CurrentSpEl0 = 0,
+    /// The kernel's exception level, running on its own stack.
+    CurrentSpElx = 1,
+    LowerAArch64 = 2,
+    LowerAArch32 = 3,
+}
+
+/// The kind of an exception.
+#[repr(u16)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Kind {
+    Synchronous = 0,
+    Irq = 1,
+    Fiq = 2,
+    SError = 3,
+}
+
+/// The source and kind of an exception, as the vector that took it passes
+/// them to `handle_exception()`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Info {
+    pub source: Source,
+    pub kind: Kind,
+}
+
+impl fmt::Display for Info {
+    /// Writes the kind and source, as in `Irq from CurrentSpEl0`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} from {:?}", self.kind, self.source)
+    }
+}
+
+/// The cause of a synchronous exception, decoded from the exception class
+/// and instruction specific syndrome fields of `ESR_ELx`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Syndrome {
+    /// `svc #n` in AArch64: a syscall.
+    Svc(u16),
+    /// `brk #n` in AArch64.
+    Brk(u16),
+    InstructionAbort,
+    DataAbort,
+    /// Any other exception class.
+    Other(u8),
+}
+
+impl From<u32> for Syndrome {
+    fn from(esr: u32) -> Syndrome {
+        let iss = (esr & 0xFFFF) as u16;
+        match (esr >> 26) as u8 {
+            0b010101 => Syndrome::Svc(iss),
+            0b111100 => Syndrome::Brk(iss),
+            0b100000 | 0b100001 => Syndrome::InstructionAbort,
+            0b100100 | 0b100101 => Syndrome::DataAbort,
+            class => Syndrome::Other(class),
+        }
+    }
+}
+
+/// Returns the exit code of a user program killed for the synchronous
+/// exception `syndrome`: 128 plus the number of the Unix signal it would have
+/// raised, as shells report them. `brk` is a trap (`SIGTRAP`), an abort a
+/// segmentation fault (`SIGSEGV`) and anything else an illegal instruction
+/// (`SIGILL`).
+pub fn fault_exit_code(syndrome: Syndrome) -> u32 {
+    match syndrome {
+        Syndrome::Brk(_) => 128 + 5,
+        Syndrome::InstructionAbort | Syndrome::DataAbort => 128 + 11,
+        _ => 128 + 4,
+    }
+}
+
+/// Handles the exception `info`, with syndrome `esr`, that interrupted the
+/// context saved in `tf`.
+///
+/// IRQs are handled as bytes received by the UART, which are buffered for the
+/// console, and timer ticks, which run the due timer events and, at the end of
+/// a time slice, switch to the next process. `svc` instructions are handled as
+/// syscalls. Any other synchronous exception a user program takes kills it,
+/// with the exit code `fault_exit_code()` gives. Any other exception is a
+/// bug, and panics.
+#[cfg(target_arch = "aarch64")]
+#[no_mangle]
+pub extern "C" fn handle_exception(info: Info, esr: u32, tf: &mut TrapFrame) {
+    use console::warn;
+    use hw::{self, timer};
+    use irq;
+    use process::State;
+    use scheduler;
+    use syscall;
+    use SCHEDULER;
+
+    match info.kind {
+        Kind::Irq => {
+            irq::enter();
+            hw::handle_uart_irq();
+            if timer::handle_irq() && scheduler::slice_ended() {
+                SCHEDULER.switch(State::Ready, tf);
+            }
+            irq::exit();
+        }
+        Kind::Synchronous => match Syndrome::from(esr) {
+            Syndrome::Svc(num) => syscall::handle_syscall(num, tf),
+            syndrome if info.source == Source::LowerAArch64 => {
+                warn!("killed process {}: {:?} at ELR {:#x}", tf.tpidr, syndrome, tf.elr);
+                SCHEDULER.switch(State::Dead(fault_exit_code(syndrome)), tf);
+            }
+            syndrome => {
+                panic!("unexpected {:?} ({}): ESR {:#010x}, ELR {:#x}", syndrome, info, esr, tf.elr)
+            }
+        },
+        _ => panic!("unexpected {} exception: ESR {:#010x}, ELR {:#x}", info, esr, tf.elr),
+    }
+}
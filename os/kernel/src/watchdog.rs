@@ -0,0 +1,276 @@
+use std::fmt;
+use std::time::Duration;
+
+use hw::{self, cookie, Watchdog};
+use mutex::Mutex;
+use timer;
+
+/// The hardware watchdog timeout in milliseconds.
+pub const TIMEOUT_MS: u32 = 15000;
+
+/// How often the watchdog service checks liveness and kicks the hardware
+/// watchdog, in milliseconds.
+const KICK_PERIOD_MS: u64 = 1000;
+
+/// The maximum number of liveness flags that can be registered.
+pub const MAX_FLAGS: usize = 16;
+
+/// The upper half of every reboot cookie written by the kernel.
+const COOKIE_MAGIC: u64 = 0x57444f47 << 32;
+
+/// Cookie value: the watchdog was armed.
+const COOKIE_ARMED: u64 = COOKIE_MAGIC | 1;
+
+/// Cookie value: the watchdog was not armed.
+const COOKIE_DISARMED: u64 = COOKIE_MAGIC | 2;
+
+/// Why the board last came up.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RebootReason {
+    /// The reboot cookie was never written: the board was power cycled.
+    PowerOn,
+    /// The previous boot left the watchdog armed and never disarmed it: the
+    /// hardware watchdog fired.
+    Watchdog,
+    /// The previous boot ended with the watchdog disarmed.
+    Clean,
+}
+
+impl RebootReason {
+    /// Decodes the reboot cookie `cookie` left behind by the previous boot.
+    pub fn from_cookie(cookie: u64) -> RebootReason {
+        match cookie {
+            COOKIE_ARMED => RebootReason::Watchdog,
+            COOKIE_DISARMED => RebootReason::Clean,
+            _ => RebootReason::PowerOn,
+        }
+    }
+
+    /// Returns the reboot cookie to leave behind when the watchdog is `armed`.
+    pub fn cookie(armed: bool) -> u64 {
+        if armed { COOKIE_ARMED } else { COOKIE_DISARMED }
+    }
+}
+
+/// A registered liveness flag.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Flag(usize);
+
+/// A set of named liveness flags.
+///
+/// Each subsystem that must keep running registers a flag and touches it
+/// regularly. The set is alive if every registered flag has been touched since
+/// the last call to `check()`, or is suspended: a subsystem that is
+/// legitimately parked for a while suspends its flag until it runs again.
+pub struct Liveness {
+    names: [&'static str; MAX_FLAGS],
+    registered: usize,
+    touched: u32,
+    suspended: u32,
+}
+
+impl Liveness {
+    /// Returns an empty set of flags.
+    pub const fn new() -> Liveness {
+        Liveness { names: [""; MAX_FLAGS], registered: 0, touched: 0, suspended: 0 }
+    }
+
+    /// Registers a new flag named `name`. The flag starts out touched.
+    ///
+    /// Returns `None` if `MAX_FLAGS` flags are already registered.
+    pub fn register(&mut self, name: &'static str) -> Option<Flag> {
+        if self.registered == MAX_FLAGS {
+            return None;
+        }
+
+        let flag = Flag(self.registered);
+        self.names[flag.0] = name;
+        self.registered += 1;
+        self.touch(flag);
+        Some(flag)
+    }
+
+    /// Marks `flag` as touched.
+    pub fn touch(&mut self, flag: Flag) {
+        self.touched |= 1 << flag.0;
+    }
+
+    /// Returns `true` if `flag` has been touched since the last `check()`.
+    pub fn is_touched(&self, flag: Flag) -> bool {
+        self.touched & (1 << flag.0) != 0
+    }
+
+    /// Stops `check()` requiring `flag` to be touched until it is resumed.
+    pub fn suspend(&mut self, flag: Flag) {
+        self.suspended |= 1 << flag.0;
+    }
+
+    /// Requires `flag` to be touched again, starting out touched.
+    pub fn resume(&mut self, flag: Flag) {
+        self.suspended &= !(1 << flag.0);
+        self.touch(flag);
+    }
+
+    /// Returns `true` if `flag` is suspended.
+    pub fn is_suspended(&self, flag: Flag) -> bool {
+        self.suspended & (1 << flag.0) != 0
+    }
+
+    /// Returns `true` if every registered flag has been touched since the last
+    /// call to `check()` or is suspended, then clears all flags.
+    pub fn check(&mut self) -> bool {
+        let all = ((1u64 << self.registered) - 1) as u32;
+        let alive = (self.touched | self.suspended) & all == all;
+        self.touched = 0;
+        alive
+    }
+
+    /// Returns an iterator over the registered flags and their names.
+    pub fn flags<'a>(&'a self) -> impl Iterator<Item = (Flag, &'static str)> + 'a {
+        self.names[..self.registered].iter().enumerate().map(|(i, &n)| (Flag(i), n))
+    }
+}
+
+/// The state of the watchdog service.
+struct Service {
+    liveness: Liveness,
+    watchdog: Option<Watchdog>,
+    last_reboot: RebootReason,
+    kicks: u64,
+    stalled: bool,
+}
+
+static SERVICE: Mutex<Service> = Mutex::new(Service {
+    liveness: Liveness::new(),
+    watchdog: None,
+    last_reboot: RebootReason::PowerOn,
+    kicks: 0,
+    stalled: false,
+});
+
+/// Initializes the watchdog service: records why the board last came up and
+/// registers the periodic kick. The hardware watchdog is not armed until
+/// `enable()` is called; if the bootloader left it armed to catch a kernel
+/// that hangs while starting up, it is stopped here.
+///
+/// # Errors
+///
+/// Returns `Err(())` if the periodic kick event could not be registered.
+pub fn init() -> Result<(), ()> {
+    Watchdog::new().stop();
+    {
+        let mut service = SERVICE.lock();
+        service.last_reboot = RebootReason::from_cookie(cookie::read());
+        cookie::write(RebootReason::cookie(false));
+    }
+
+    timer::every(Duration::from_millis(KICK_PERIOD_MS), kick)
+}
+
+/// Returns why the board last came up, as determined by `init()`.
+pub fn last_reboot() -> RebootReason {
+    SERVICE.lock().last_reboot
+}
+
+/// Registers a liveness flag named `name`. Once the watchdog is enabled, the
+/// flag must be touched at least once every second, or the hardware watchdog
+/// will be allowed to reset the board.
+pub fn register(name: &'static str) -> Option<Flag> {
+    SERVICE.lock().liveness.register(name)
+}
+
+/// Marks `flag` as touched.
+pub fn touch(flag: Flag) {
+    SERVICE.lock().liveness.touch(flag);
+}
+
+/// Stops requiring `flag` to be touched until `resume()` is called, for a
+/// subsystem that is about to wait for longer than a second.
+pub fn suspend(flag: Flag) {
+    SERVICE.lock().liveness.suspend(flag);
+}
+
+/// Requires `flag` to be touched again after `suspend()`.
+pub fn resume(flag: Flag) {
+    SERVICE.lock().liveness.resume(flag);
+}
+
+/// Arms the hardware watchdog with a timeout of `TIMEOUT_MS`.
+pub fn enable() {
+    let mut service = SERVICE.lock();
+    let mut watchdog = Watchdog::new();
+    watchdog.start(TIMEOUT_MS);
+    service.watchdog = Some(watchdog);
+    service.stalled = false;
+    cookie::write(RebootReason::cookie(true));
+}
+
+/// Stops the hardware watchdog.
+pub fn disable() {
+    let mut service = SERVICE.lock();
+    if let Some(mut watchdog) = service.watchdog.take() {
+        watchdog.stop();
+    }
+
+    cookie::write(RebootReason::cookie(false));
+}
+
+/// Resets the board. The reboot is recorded as clean, not as the watchdog
+/// firing.
+pub fn reboot() -> ! {
+    disable();
+    hw::reboot()
+}
+
+/// Stops the kernel for good, with the watchdog disabled so that it doesn't
+/// reset the board.
+pub fn halt() -> ! {
+    disable();
+    hw::halt()
+}
+
+/// Periodic event: kicks the hardware watchdog if every liveness flag has been
+/// touched since the last kick.
+fn kick(_: u64) {
+    let mut service = SERVICE.lock();
+    let service = &mut *service;
+    let alive = service.liveness.check();
+    if let Some(ref mut watchdog) = service.watchdog {
+        if alive {
+            service.kicks += 1;
+            watchdog.pet();
+        } else {
+            service.stalled = true;
+        }
+    }
+}
+
+/// Writes a human-readable summary of the watchdog service to `w`.
+pub fn write_status<W: fmt::Write>(w: &mut W) -> fmt::Result {
+    let service = SERVICE.lock();
+    match service.watchdog {
+        Some(ref watchdog) => {
+            writeln!(w, "watchdog: armed, {} ms remaining", watchdog.remaining_ms())?;
+        }
+        None => writeln!(w, "watchdog: disarmed")?,
+    }
+
+    writeln!(w, "kicks: {}", service.kicks)?;
+    if service.stalled {
+        writeln!(w, "stall detected: a liveness check failed since arming")?;
+    }
+
+    writeln!(w, "last reboot: {:?}", service.last_reboot)?;
+    for (flag, name) in service.liveness.flags() {
+        let state = if service.liveness.is_suspended(flag) {
+            "suspended"
+        } else if service.liveness.is_touched(flag) {
+            "alive"
+        } else {
+            "waiting"
+        };
+        writeln!(w, "  {}: {}", name, state)?;
+    }
+
+    Ok(())
+}
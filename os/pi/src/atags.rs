@@ -0,0 +1,150 @@
+//! The ATAG list: how the firmware describes the machine to the kernel.
+//!
+//! Unless a device tree is enabled in `config.txt`, the firmware writes a list
+//! of tags at `ATAG_BASE` before starting the kernel. Each tag is a header of
+//! two words, the tag's size in words (header included) and its type, followed
+//! by its data. The list ends with a `NONE` tag.
+//!
+//! ```rust,ignore
+//! let memory = Atags::get().filter_map(Atag::mem).next();
+//! ```
+
+use core::{slice, str};
+
+/// The address of the ATAG list.
+pub const ATAG_BASE: usize = 0x100;
+
+/// An upper bound on the size of the list in bytes, so that a list that lost
+/// its `NONE` tag isn't followed through the rest of memory.
+pub const MAX_SIZE: usize = 0x4000;
+
+/// Tag types.
+const TAG_NONE: u32 = 0x00000000;
+const TAG_CORE: u32 = 0x54410001;
+const TAG_MEM: u32 = 0x54410002;
+const TAG_CMDLINE: u32 = 0x54410009;
+
+/// The `CORE` tag, which starts the list.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Core {
+    /// Bit 0 is set if the root device is mounted read-only.
+    pub flags: u32,
+    pub page_size: u32,
+    pub root_dev: u32,
+}
+
+/// A `MEM` tag: a region of physical memory the kernel may use.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Mem {
+    /// The size of the region in bytes.
+    pub size: u32,
+    /// The physical address the region starts at.
+    pub start: u32,
+}
+
+/// A decoded tag.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Atag<'a> {
+    Core(Core),
+    Mem(Mem),
+    /// The kernel command line. Anything after invalid UTF-8 is dropped.
+    Cmd(&'a str),
+    /// The `NONE` tag that ends the list. `Atags` stops before it.
+    None,
+    /// A tag of the given type that isn't decoded, or is too short to be.
+    Unknown(u32),
+}
+
+impl<'a> Atag<'a> {
+    /// Decodes the tag of type `tag` with the data words `data`.
+    pub fn decode(tag: u32, data: &'a [u32]) -> Atag<'a> {
+        match tag {
+            TAG_NONE => Atag::None,
+            // A `CORE` tag with no data describes no root device.
+            TAG_CORE if data.is_empty() => {
+                Atag::Core(Core { flags: 0, page_size: 0, root_dev: 0 })
+            }
+            TAG_CORE if data.len() >= 3 => {
+                Atag::Core(Core { flags: data[0], page_size: data[1], root_dev: data[2] })
+            }
+            TAG_MEM if data.len() >= 2 => Atag::Mem(Mem { size: data[0], start: data[1] }),
+            TAG_CMDLINE => {
+                let bytes = unsafe {
+                    slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * 4)
+                };
+                let bytes = &bytes[..bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len())];
+                let valid = match str::from_utf8(bytes) {
+                    Ok(_) => bytes.len(),
+                    Err(e) => e.valid_up_to(),
+                };
+
+                Atag::Cmd(str::from_utf8(&bytes[..valid]).unwrap_or(""))
+            }
+            _ => Atag::Unknown(tag),
+        }
+    }
+
+    /// Returns the `CORE` tag's contents if this is one.
+    pub fn core(self) -> Option<Core> {
+        match self {
+            Atag::Core(core) => Some(core),
+            _ => None,
+        }
+    }
+
+    /// Returns the `MEM` tag's contents if this is one.
+    pub fn mem(self) -> Option<Mem> {
+        match self {
+            Atag::Mem(mem) => Some(mem),
+            _ => None,
+        }
+    }
+
+    /// Returns the command line if this is a `CMDLINE` tag.
+    pub fn cmd(self) -> Option<&'a str> {
+        match self {
+            Atag::Cmd(cmd) => Some(cmd),
+            _ => None,
+        }
+    }
+}
+
+/// An iterator over the tags of an ATAG list, up to its `NONE` tag.
+pub struct Atags<'a> {
+    words: &'a [u32],
+}
+
+impl Atags<'static> {
+    /// Returns the list the firmware wrote at `ATAG_BASE`.
+    pub fn get() -> Atags<'static> {
+        Atags::new(unsafe { slice::from_raw_parts(ATAG_BASE as *const u32, MAX_SIZE / 4) })
+    }
+}
+
+impl<'a> Atags<'a> {
+    /// Returns an iterator over the list in `words`. Iteration also stops
+    /// early at a tag whose size is impossible.
+    pub fn new(words: &'a [u32]) -> Atags<'a> {
+        Atags { words }
+    }
+}
+
+impl<'a> Iterator for Atags<'a> {
+    type Item = Atag<'a>;
+
+    fn next(&mut self) -> Option<Atag<'a>> {
+        if self.words.len() < 2 {
+            return None;
+        }
+
+        let (size, tag) = (self.words[0] as usize, self.words[1]);
+        if tag == TAG_NONE || size < 2 || size > self.words.len() {
+            self.words = &[];
+            return None;
+        }
+
+        let (current, rest) = self.words.split_at(size);
+        self.words = rest;
+        Some(Atag::decode(tag, &current[2..]))
+    }
+}
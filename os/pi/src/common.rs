@@ -5,3 +5,55 @@ pub const IO_BASE: usize = 0x3F000000;
 pub macro states($($name:ident),*) {
     $(pub enum $name {  })*
 }
+
+/// Declares a `#[repr(C)]` register block named `$name` whose fields are each
+/// preceded by their byte offset from the start of the block as given in the
+/// datasheet, and whose total size is `$size`. The offsets are checked by
+/// `assert_offsets!` when testing. The block and its fields are visible to the
+/// whole crate so that tests can drive drivers against a block in memory.
+///
+/// ```rust,ignore
+/// register_layout! {
+///     struct Registers(0x08) {
+///         0x00 => DATA: Volatile<u32>,
+///         0x04 => STATUS: ReadVolatile<u32>,
+///     }
+/// }
+/// ```
+pub macro register_layout(
+    $(#[$attr:meta])*
+    struct $name:ident($size:expr) {
+        $($offset:expr => $field:ident: $ty:ty),* $(,)*
+    }
+) {
+    $(#[$attr])*
+    #[repr(C)]
+    #[allow(non_snake_case)]
+    pub(crate) struct $name {
+        $(pub(crate) $field: $ty),*
+    }
+
+    assert_offsets!($name($size) { $($field => $offset),* });
+}
+
+/// Generates a test asserting that each `$field` of the struct `$name` lies
+/// at byte offset `$offset` and that `$name` is `$size` bytes long. A
+/// mismatch fails with a message naming the field and both offsets.
+pub macro assert_offsets($name:ident($size:expr) { $($field:ident => $offset:expr),* $(,)* }) {
+    #[cfg(test)]
+    #[test]
+    fn register_offsets() {
+        let block: $name = unsafe { ::core::mem::zeroed() };
+        let base = &block as *const $name as usize;
+
+        $({
+            let offset = &block.$field as *const _ as usize - base;
+            assert!(offset == $offset, "{}::{}.{} is at offset {:#x}; the datasheet puts it at {:#x}",
+                    module_path!(), stringify!($name), stringify!($field), offset, $offset);
+        })*
+
+        let size = ::core::mem::size_of::<$name>();
+        assert!(size == $size, "{}::{} is {:#x} bytes long; the datasheet says {:#x}",
+                module_path!(), stringify!($name), size, $size);
+    }
+}
@@ -0,0 +1,139 @@
+//! Sub-microsecond delays using the ARM PMU's cycle counter.
+//!
+//! The system timer in `timer` ticks at 1MHz, too coarsely for the few-
+//! hundred-nanosecond delays 1-Wire and similar protocols need. The core's
+//! cycle counter (`PMCCNTR_EL0`) ticks once per CPU cycle instead. The CPU
+//! clock changes with the firmware's frequency scaling, so `calibrate()`
+//! measures it against the system timer; until then, `DEFAULT_CYCLES_PER_US`
+//! is assumed. The cycle counter only exists on the Raspberry Pi, so off it
+//! only the conversions are available.
+//!
+//! ```rust,ignore
+//! cycles::enable();
+//! cycles::calibrate();
+//! cycles::spin_sleep_ns(480);
+//! ```
+
+#[cfg(target_arch = "aarch64")]
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+#[cfg(target_arch = "aarch64")]
+use timer;
+
+/// Cycles per microsecond at the Pi 3's nominal 1.2GHz CPU clock.
+pub const DEFAULT_CYCLES_PER_US: u64 = 1200;
+
+/// How long `calibrate()` measures the cycle counter for, in microseconds.
+#[cfg(target_arch = "aarch64")]
+const CALIBRATION_US: u64 = 1000;
+
+/// `PMCR_EL0` bits: enable the counters and reset the cycle counter.
+#[cfg(target_arch = "aarch64")]
+const PMCR_E: u64 = 1;
+#[cfg(target_arch = "aarch64")]
+const PMCR_C: u64 = 1 << 2;
+
+/// The `PMCNTENSET_EL0` bit enabling the cycle counter.
+#[cfg(target_arch = "aarch64")]
+const PMCNTENSET_C: u64 = 1 << 31;
+
+/// The measured cycles per microsecond, or 0 if `calibrate()` hasn't run.
+#[cfg(target_arch = "aarch64")]
+static CYCLES_PER_US: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// The system registers behind the cycle counter.
+#[cfg(target_arch = "aarch64")]
+mod sysreg {
+    pub fn pmcr() -> u64 {
+        let value: u64;
+        unsafe { asm!("mrs $0, pmcr_el0" : "=r"(value) : : : "volatile"); }
+        value
+    }
+
+    pub fn set_pmcr(value: u64) {
+        unsafe { asm!("msr pmcr_el0, $0; isb" : : "r"(value) : "memory" : "volatile"); }
+    }
+
+    pub fn set_pmcntenset(value: u64) {
+        unsafe { asm!("msr pmcntenset_el0, $0; isb" : : "r"(value) : "memory" : "volatile"); }
+    }
+
+    pub fn pmccntr() -> u64 {
+        let value: u64;
+        unsafe { asm!("mrs $0, pmccntr_el0" : "=r"(value) : : : "volatile"); }
+        value
+    }
+}
+
+/// Resets and starts the calling core's cycle counter. Each core has its own
+/// counter, and it must be enabled before any other function here is used.
+#[cfg(target_arch = "aarch64")]
+pub fn enable() {
+    sysreg::set_pmcr(sysreg::pmcr() | PMCR_E | PMCR_C);
+    sysreg::set_pmcntenset(PMCNTENSET_C);
+}
+
+/// Returns the calling core's cycle count.
+#[cfg(target_arch = "aarch64")]
+pub fn count() -> u64 {
+    sysreg::pmccntr()
+}
+
+/// Measures the CPU clock against the system timer and uses the result for
+/// `spin_sleep_ns()`. Returns the measured cycles per microsecond. Takes
+/// about a millisecond.
+#[cfg(target_arch = "aarch64")]
+pub fn calibrate() -> u64 {
+    // Start on a tick boundary so that the whole interval is measured.
+    let tick = timer::current_time_us();
+    while timer::current_time_us() == tick {  }
+
+    let start_us = timer::current_time_us();
+    let start = count();
+    while timer::current_time_us().wrapping_sub(start_us) < CALIBRATION_US {  }
+    let elapsed = count().wrapping_sub(start);
+
+    let rate = cycles_per_us(elapsed, CALIBRATION_US);
+    CYCLES_PER_US.store(rate as usize, Ordering::Relaxed);
+    rate
+}
+
+/// Returns the cycles per microsecond in effect: the last value measured by
+/// `calibrate()`, or `DEFAULT_CYCLES_PER_US`.
+#[cfg(target_arch = "aarch64")]
+pub fn rate() -> u64 {
+    match CYCLES_PER_US.load(Ordering::Relaxed) {
+        0 => DEFAULT_CYCLES_PER_US,
+        rate => rate as u64,
+    }
+}
+
+/// Returns the rate at which `cycles` cycles elapsed in `us` microseconds,
+/// rounded to the nearest cycle per microsecond and at least 1.
+pub fn cycles_per_us(cycles: u64, us: u64) -> u64 {
+    let rate = (cycles + us / 2) / us;
+    if rate == 0 { 1 } else { rate }
+}
+
+/// Converts `ns` nanoseconds to cycles at `cycles_per_us`, rounding up so
+/// that a delay is never shorter than asked for. Delays too long to count
+/// saturate rather than wrap.
+pub fn ns_to_cycles(ns: u64, cycles_per_us: u64) -> u64 {
+    let product = ns.saturating_mul(cycles_per_us);
+    product / 1000 + (product % 1000 != 0) as u64
+}
+
+/// Spins for at least `cycles` CPU cycles.
+#[cfg(target_arch = "aarch64")]
+pub fn spin_cycles(cycles: u64) {
+    let start = count();
+    while count().wrapping_sub(start) < cycles {  }
+}
+
+/// Spins for at least `ns` nanoseconds, as measured by the cycle counter.
+/// Accurate to a few tens of cycles once `calibrate()` has run; interrupts
+/// taken while spinning lengthen the delay.
+#[cfg(target_arch = "aarch64")]
+pub fn spin_sleep_ns(ns: u64) {
+    spin_cycles(ns_to_cycles(ns, rate()));
+}
@@ -0,0 +1,199 @@
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use common::{IO_BASE, register_layout};
+use error::{Error, Result};
+use quiesce;
+use volatile::prelude::*;
+use volatile::{Volatile, ReadVolatile};
+
+/// The base address of DMA channel 0's registers. Channel `n`'s registers are
+/// `CHANNEL_STRIDE * n` bytes further on.
+const DMA_BASE: usize = IO_BASE + 0x7000;
+
+/// The distance between consecutive channels' registers.
+const CHANNEL_STRIDE: usize = 0x100;
+
+/// The `DMA_ENABLE` register: one bit per channel.
+const DMA_ENABLE: *mut Volatile<u32> = (IO_BASE + 0x7FF0) as *mut Volatile<u32>;
+
+/// The largest full-featured channel. Channels 7 to 14 are "lite" channels
+/// with shorter transfers and are not supported.
+pub const MAX_CHANNEL: u8 = 6;
+
+/// The VideoCore bus address of the peripherals at `IO_BASE`.
+const BUS_IO_BASE: u32 = 0x7E000000;
+
+/// The VideoCore bus alias of ARM physical memory that bypasses the L2 cache.
+const BUS_UNCACHED_MEMORY: u32 = 0xC0000000;
+
+/// `CS` bits: reset the channel, start it, it has ended, and it has failed.
+const CS_RESET: u32 = 1 << 31;
+const CS_ACTIVE: u32 = 1;
+const CS_END: u32 = 1 << 1;
+const CS_ERROR: u32 = 1 << 8;
+
+/// `TI` bits: wait for write responses, pace writes by a peripheral's DREQ,
+/// increment the source address, and the field naming that peripheral.
+const TI_WAIT_RESP: u32 = 1 << 3;
+const TI_DEST_DREQ: u32 = 1 << 6;
+const TI_SRC_INC: u32 = 1 << 8;
+const TI_PERMAP: (u32, u32) = (16, 5);
+
+/// `DEBUG` bits recording read, FIFO and AXI errors. Writing them clears them.
+const DEBUG_ERRORS: u32 = 0b111;
+
+register_layout! {
+    struct Registers(0x24) {
+        0x00 => CS: Volatile<u32>, // Control and status.
+        0x04 => CONBLK_AD: Volatile<u32>, // Control block address.
+        0x08 => TI: ReadVolatile<u32>, // Transfer information.
+        0x0C => SOURCE_AD: ReadVolatile<u32>, // Source address.
+        0x10 => DEST_AD: ReadVolatile<u32>, // Destination address.
+        0x14 => TXFR_LEN: ReadVolatile<u32>, // Transfer length.
+        0x18 => STRIDE: ReadVolatile<u32>, // 2D stride.
+        0x1C => NEXTCONBK: ReadVolatile<u32>, // Next control block address.
+        0x20 => DEBUG: Volatile<u32>, // Debug.
+    }
+}
+
+/// A DMA control block, describing one transfer. The controller reads it
+/// from memory, so it must stay put until the transfer is over.
+#[repr(C, align(32))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ControlBlock {
+    pub transfer_info: u32,
+    pub source: u32,
+    pub dest: u32,
+    pub length: u32,
+    pub stride: u32,
+    pub next: u32,
+    _reserved: [u32; 2],
+}
+
+impl ControlBlock {
+    /// Returns a control block copying `src` to the peripheral register at
+    /// ARM physical address `register`, one write each time the peripheral
+    /// with DREQ number `dreq` asks for data.
+    pub fn to_peripheral(src: &[u8], register: usize, dreq: u8) -> ControlBlock {
+        let mut transfer_info = TI_WAIT_RESP | TI_DEST_DREQ | TI_SRC_INC;
+        transfer_info |= ((dreq as u32) & ((1 << TI_PERMAP.1) - 1)) << TI_PERMAP.0;
+        ControlBlock {
+            transfer_info,
+            source: memory_bus_address(src.as_ptr() as usize),
+            dest: peripheral_bus_address(register),
+            length: src.len() as u32,
+            stride: 0,
+            next: 0,
+            _reserved: [0; 2],
+        }
+    }
+}
+
+/// Returns the bus address the DMA controller uses for the peripheral at ARM
+/// physical address `address`.
+pub fn peripheral_bus_address(address: usize) -> u32 {
+    (address - IO_BASE) as u32 + BUS_IO_BASE
+}
+
+/// Returns the uncached bus address the DMA controller uses for ARM physical
+/// memory address `address`.
+pub fn memory_bus_address(address: usize) -> u32 {
+    address as u32 | BUS_UNCACHED_MEMORY
+}
+
+/// The channels handed out by `Channel::new()`, one bit per channel.
+static CLAIMED: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// A full-featured DMA channel.
+///
+/// The controller reads memory without going through the ARM's caches. The
+/// kernel runs with the MMU and data cache off, so this is coherent as long
+/// as that stays true.
+pub struct Channel {
+    number: u8,
+    registers: &'static mut Registers,
+}
+
+impl Channel {
+    /// Returns DMA channel `number`, enabled and reset.
+    ///
+    /// The firmware uses some channels itself; channel 5 is conventionally
+    /// free on the Pi 3.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidDmaChannel` if `number` is larger than
+    /// `MAX_CHANNEL` or has already been handed out.
+    pub fn new(number: u8) -> Result<Channel> {
+        if number > MAX_CHANNEL {
+            return Err(Error::InvalidDmaChannel(number));
+        }
+
+        let bit = 1 << number;
+        if CLAIMED.fetch_or(bit, Ordering::SeqCst) & bit != 0 {
+            return Err(Error::InvalidDmaChannel(number));
+        }
+
+        let registers = unsafe {
+            (*DMA_ENABLE).set_bits(bit as u32);
+            &mut *((DMA_BASE + CHANNEL_STRIDE * number as usize) as *mut Registers)
+        };
+
+        registers.CS.write(CS_RESET);
+
+        // If the hook table is full, channels are simply left as they are.
+        let _ = quiesce::register(shutdown);
+
+        Ok(Channel { number, registers })
+    }
+
+    /// Returns this channel's number.
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    /// Runs the transfer described by `block` and spins until it has
+    /// finished, so the CPU does no other work meanwhile.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::DmaFailed` if the controller reports an error. The
+    /// channel is reset and can be used again.
+    pub fn run(&mut self, block: &ControlBlock) -> Result<()> {
+        self.registers.CS.write(CS_END);
+        self.registers.DEBUG.write(DEBUG_ERRORS);
+        self.registers.CONBLK_AD.write(memory_bus_address(block as *const _ as usize));
+        self.registers.CS.write(CS_ACTIVE);
+
+        while self.registers.CS.has_mask(CS_ACTIVE) {
+            if self.registers.CS.has_mask(CS_ERROR) {
+                self.registers.CS.write(CS_RESET);
+                return Err(Error::DmaFailed(self.number));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Channel {
+    fn drop(&mut self) {
+        self.registers.CS.write(CS_RESET);
+        CLAIMED.fetch_and(!(1 << self.number), Ordering::SeqCst);
+    }
+}
+
+/// Shutdown hook registered by `Channel::new()`: resets every channel that
+/// has been handed out, aborting any transfer in progress.
+fn shutdown() {
+    let claimed = CLAIMED.load(Ordering::SeqCst);
+    for number in 0..(MAX_CHANNEL as usize + 1) {
+        if claimed & (1 << number) != 0 {
+            let registers = unsafe {
+                &mut *((DMA_BASE + CHANNEL_STRIDE * number) as *mut Registers)
+            };
+
+            registers.CS.write(CS_RESET);
+        }
+    }
+}
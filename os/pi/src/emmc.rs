@@ -0,0 +1,541 @@
+//! The EMMC controller, driving the SD card the Pi boots from.
+//!
+//! `Emmc::new()` resets the controller and brings the card up the way the SD
+//! specification describes: `CMD0` to reset it, `CMD8` to check its voltage,
+//! `ACMD41` until it has powered up, then `CMD2`, `CMD3` and `CMD7` to give it
+//! an address and select it. Sectors are then read one at a time with `CMD17`
+//! or several at a time with `CMD18`. Transfers are polled; there is no DMA.
+//!
+//! A read that fails is abandoned, stopping the card with `CMD12` if it is
+//! still sending, and retried, waiting longer before each retry. If the card
+//! doesn't answer `CMD13` while the read is abandoned, the read fails with
+//! `Error::CardLost` and `Emmc::reinit()` identifies the card again.
+//!
+//! The firmware leaves GPIO pins 48 to 53 routed to the controller, so the
+//! driver doesn't claim them.
+//!
+//! ```rust,ignore
+//! let mut sd = Emmc::new()?;
+//! let mut sector = [0u8; 512];
+//! sd.read_sector(0, &mut sector)?;
+//! ```
+
+use core::time::Duration;
+
+use common::{IO_BASE, register_layout};
+use error::{Error, Result};
+use timer::{self, Deadline};
+use volatile::prelude::*;
+use volatile::{Volatile, ReadVolatile, Reserved};
+
+/// The base address of the EMMC registers.
+const EMMC_BASE: usize = IO_BASE + 0x300000;
+
+/// The size of a sector in bytes.
+pub const SECTOR_SIZE: usize = 512;
+
+/// The frequency of the clock the controller divides down, in Hz.
+pub const BASE_CLOCK: u32 = 41_666_666;
+
+/// The card clock while the card is identified, and afterwards, in Hz.
+const IDENTIFY_CLOCK: u32 = 400_000;
+const TRANSFER_CLOCK: u32 = 25_000_000;
+
+/// How long a command, a block of data and the card's power up may take, in
+/// milliseconds.
+const COMMAND_TIMEOUT: u64 = 100;
+const DATA_TIMEOUT: u64 = 500;
+const POWER_UP_TIMEOUT: u64 = 1000;
+
+/// How many times a read is attempted before its error is returned, and how
+/// long to wait before the first retry, in milliseconds. The wait doubles
+/// with each retry.
+pub const READ_ATTEMPTS: u32 = 3;
+const RETRY_BACKOFF: u64 = 5;
+
+/// `CONTROL1` bits: enable the internal clock, it is stable, enable the card
+/// clock, the divisor fields, the data timeout field, reset the host, and
+/// reset the command and data lines.
+const C1_CLK_INTLEN: u32 = 1;
+const C1_CLK_STABLE: u32 = 1 << 1;
+const C1_CLK_EN: u32 = 1 << 2;
+const C1_CLK_FREQ_MS2: (u32, u32) = (6, 2);
+const C1_CLK_FREQ8: (u32, u32) = (8, 8);
+const C1_DATA_TOUNIT: (u32, u32) = (16, 4);
+const C1_SRST_HC: u32 = 1 << 24;
+const C1_SRST_CMD: u32 = 1 << 25;
+const C1_SRST_DATA: u32 = 1 << 26;
+
+/// The largest data timeout exponent: the controller waits `2^(13 + n)` clock
+/// cycles.
+const DATA_TOUNIT_MAX: u32 = 0xE;
+
+/// `STATUS` bits: the command and data lines are in use.
+const STATUS_CMD_INHIBIT: u32 = 1;
+const STATUS_DAT_INHIBIT: u32 = 1 << 1;
+
+/// `INTERRUPT` bits: a command finished, a transfer finished, a block is
+/// ready to read, and the summary of the error bits above it.
+const INT_CMD_DONE: u32 = 1;
+const INT_DATA_DONE: u32 = 1 << 1;
+const INT_READ_RDY: u32 = 1 << 5;
+const INT_ERR: u32 = 1 << 15;
+const INT_ERRORS: u32 = 0xFFFF0000 | INT_ERR;
+
+/// `CMDTM` bits: count blocks, send `CMD12` when they're done, read from the
+/// card, transfer several blocks, the response type field, check the
+/// response's CRC and index, a data transfer follows, and the command index.
+const TM_BLKCNT_EN: u32 = 1 << 1;
+const TM_AUTO_CMD12: u32 = 1 << 2;
+const TM_DAT_DIR_READ: u32 = 1 << 4;
+const TM_MULTI_BLOCK: u32 = 1 << 5;
+const CMD_RSPNS_TYPE: u32 = 16;
+const CMD_CRCCHK_EN: u32 = 1 << 19;
+const CMD_IXCHK_EN: u32 = 1 << 20;
+const CMD_ISDATA: u32 = 1 << 21;
+const CMD_INDEX: u32 = 24;
+
+/// The `CMD8` argument: 2.7-3.6V and a check pattern the card echoes.
+const IF_COND: u32 = 0x1AA;
+
+/// `ACMD41` argument and response bits: the host supports high capacity
+/// cards, the 3.2-3.4V window, the card has powered up, and it is high
+/// capacity.
+const OCR_HCS: u32 = 1 << 30;
+const OCR_VOLTAGE: u32 = 0x00FF8000;
+const OCR_POWERED_UP: u32 = 1 << 31;
+const OCR_CCS: u32 = 1 << 30;
+
+register_layout! {
+    struct Registers(0x100) {
+        0x00 => ARG2: Volatile<u32>,
+        0x04 => BLKSIZECNT: Volatile<u32>,
+        0x08 => ARG1: Volatile<u32>,
+        0x0C => CMDTM: Volatile<u32>,
+        0x10 => RESP: [ReadVolatile<u32>; 4],
+        0x20 => DATA: ReadVolatile<u32>,
+        0x24 => STATUS: ReadVolatile<u32>,
+        0x28 => CONTROL0: Volatile<u32>,
+        0x2C => CONTROL1: Volatile<u32>,
+        0x30 => INTERRUPT: Volatile<u32>,
+        0x34 => IRPT_MASK: Volatile<u32>,
+        0x38 => IRPT_EN: Volatile<u32>,
+        0x3C => CONTROL2: Volatile<u32>,
+        0x40 => __r0: [Reserved<u32>; 47],
+        0xFC => SLOTISR_VER: ReadVolatile<u32>,
+    }
+}
+
+/// The kind of response a command gets.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Response {
+    None,
+    /// A 136-bit response, such as `CMD2`'s card identification.
+    R136,
+    /// A 48-bit response.
+    R48,
+    /// A 48-bit response without a valid CRC or index, such as `ACMD41`'s
+    /// operating conditions.
+    R48Unchecked,
+    /// A 48-bit response, after which the card holds the data line while it
+    /// is busy.
+    R48Busy,
+}
+
+/// An SD command: its index, the response it gets and how its data moves.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Command {
+    pub index: u8,
+    pub response: Response,
+    /// Whether the card sends data blocks after the response.
+    pub reads: bool,
+    /// Whether several blocks are sent, ended by an automatic `CMD12`.
+    pub multi_block: bool,
+}
+
+impl Command {
+    const fn new(index: u8, response: Response) -> Command {
+        Command { index, response, reads: false, multi_block: false }
+    }
+
+    const fn read(index: u8, multi_block: bool) -> Command {
+        Command { index, response: Response::R48, reads: true, multi_block }
+    }
+
+    /// Returns the value of `CMDTM` that issues this command.
+    pub fn cmdtm(&self) -> u32 {
+        let mut cmdtm = (self.index as u32) << CMD_INDEX;
+        cmdtm |= match self.response {
+            Response::None => 0,
+            Response::R136 => 1 << CMD_RSPNS_TYPE | CMD_CRCCHK_EN,
+            Response::R48 => 2 << CMD_RSPNS_TYPE | CMD_CRCCHK_EN | CMD_IXCHK_EN,
+            Response::R48Unchecked => 2 << CMD_RSPNS_TYPE,
+            Response::R48Busy => 3 << CMD_RSPNS_TYPE | CMD_CRCCHK_EN | CMD_IXCHK_EN,
+        };
+
+        if self.reads {
+            cmdtm |= CMD_ISDATA | TM_DAT_DIR_READ;
+        }
+
+        if self.multi_block {
+            cmdtm |= TM_MULTI_BLOCK | TM_BLKCNT_EN | TM_AUTO_CMD12;
+        }
+
+        cmdtm
+    }
+}
+
+/// The commands the driver issues. `APP_CMD` precedes each application
+/// specific (`ACMD`) command.
+pub const GO_IDLE_STATE: Command = Command::new(0, Response::None);
+pub const ALL_SEND_CID: Command = Command::new(2, Response::R136);
+pub const SEND_RELATIVE_ADDR: Command = Command::new(3, Response::R48);
+pub const SELECT_CARD: Command = Command::new(7, Response::R48Busy);
+pub const SEND_IF_COND: Command = Command::new(8, Response::R48);
+pub const STOP_TRANSMISSION: Command = Command::new(12, Response::R48Busy);
+pub const SEND_STATUS: Command = Command::new(13, Response::R48);
+pub const SET_BLOCKLEN: Command = Command::new(16, Response::R48);
+pub const READ_SINGLE_BLOCK: Command = Command::read(17, false);
+pub const READ_MULTIPLE_BLOCK: Command = Command::read(18, true);
+pub const APP_CMD: Command = Command::new(55, Response::R48);
+pub const SD_SEND_OP_COND: Command = Command::new(41, Response::R48Unchecked);
+
+/// Returns the divisor that brings `BASE_CLOCK` down to at most `target` Hz.
+/// The card clock is `BASE_CLOCK / (2 * divisor)`, or `BASE_CLOCK` for 0.
+pub fn clock_divisor(target: u32) -> u32 {
+    if target >= BASE_CLOCK {
+        return 0;
+    }
+
+    let divisor = (BASE_CLOCK + 2 * target - 1) / (2 * target);
+    divisor.min(0x3FF)
+}
+
+/// Returns `CONTROL1`'s divisor fields set to `divisor`, which is 10 bits:
+/// the low 8 go in `CLK_FREQ8` and the high 2 in `CLK_FREQ_MS2`.
+pub(crate) fn divisor_bits(divisor: u32) -> u32 {
+    let low = divisor & ((1 << C1_CLK_FREQ8.1) - 1);
+    let high = (divisor >> C1_CLK_FREQ8.1) & ((1 << C1_CLK_FREQ_MS2.1) - 1);
+    low << C1_CLK_FREQ8.0 | high << C1_CLK_FREQ_MS2.0
+}
+
+/// Returns the address a read of sector `sector` names. High capacity cards
+/// are addressed in sectors and standard capacity cards in bytes.
+pub fn sector_address(sector: u64, high_capacity: bool) -> Result<u32> {
+    let address = if high_capacity { sector } else { sector * SECTOR_SIZE as u64 };
+    if address > ::core::u32::MAX as u64 {
+        return Err(Error::InvalidSector(sector));
+    }
+
+    Ok(address as u32)
+}
+
+/// The states of the card the driver tells apart, from the `CURRENT_STATE`
+/// field of its status.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CardState {
+    /// Selected and waiting for a command.
+    Transfer,
+    /// Sending data blocks.
+    Data,
+    Other(u8),
+}
+
+/// Returns the state of the card from the card status `status`, the response
+/// of `SEND_STATUS` and most other commands.
+pub fn card_state(status: u32) -> CardState {
+    match (status >> 9) & 0xF {
+        4 => CardState::Transfer,
+        5 => CardState::Data,
+        state => CardState::Other(state as u8),
+    }
+}
+
+/// Returns how long to wait before retry `retry` of a failed read, counting
+/// from 0, in milliseconds.
+pub fn retry_backoff(retry: u32) -> u64 {
+    RETRY_BACKOFF << retry.min(16)
+}
+
+/// A device that is read in sectors.
+pub trait BlockDevice {
+    /// The size of a sector in bytes.
+    fn sector_size(&self) -> u64 {
+        SECTOR_SIZE as u64
+    }
+
+    /// Reads sector `n` into `buf`. `self.sector_size()` or `buf.len()`
+    /// bytes, whichever is less, are read. Returns the number of bytes read.
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> Result<usize>;
+}
+
+/// An initialized SD card.
+pub struct Emmc {
+    registers: &'static mut Registers,
+    /// The card's relative address, which `SELECT_CARD` names it by.
+    rca: u32,
+    high_capacity: bool,
+}
+
+impl Emmc {
+    /// Resets the controller and initializes the card in the slot.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Timeout` if the controller or the card doesn't respond,
+    /// `Error::SdCommand(n)` if the card reports an error for command `n`, and
+    /// `Error::UnsupportedCard` if the card isn't a version 2 SD card that
+    /// runs at 3.3V.
+    pub fn new() -> Result<Emmc> {
+        let registers = unsafe { &mut *(EMMC_BASE as *mut Registers) };
+        let mut emmc = Emmc { registers, rca: 0, high_capacity: false };
+        emmc.reset()?;
+        emmc.identify()?;
+        Ok(emmc)
+    }
+
+    /// Resets the controller and identifies the card again, as `new()` does.
+    /// A card that stopped responding, for instance after a brown out or
+    /// being reseated, can be used again after this succeeds.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of `new()`.
+    pub fn reinit(&mut self) -> Result<()> {
+        self.rca = 0;
+        self.high_capacity = false;
+        self.reset()?;
+        self.identify()
+    }
+
+    /// Returns `true` if the card addresses sectors rather than bytes: it is
+    /// an SDHC or SDXC card.
+    pub fn high_capacity(&self) -> bool {
+        self.high_capacity
+    }
+
+    /// Reads `buf.len() / SECTOR_SIZE` sectors starting at `start` into `buf`
+    /// with one `READ_MULTIPLE_BLOCK`. Returns the number of bytes read.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidSector` if the sectors can't be addressed,
+    /// `Error::CardLost` if the card stopped responding, and the error of the
+    /// last attempt if `READ_ATTEMPTS` attempts failed.
+    pub fn read_sectors(&mut self, start: u64, buf: &mut [u8]) -> Result<usize> {
+        let count = buf.len() / SECTOR_SIZE;
+        match count {
+            0 => Ok(0),
+            1 => self.read_retrying(READ_SINGLE_BLOCK, start, &mut buf[..SECTOR_SIZE]),
+            _ => self.read_retrying(READ_MULTIPLE_BLOCK, start, &mut buf[..count * SECTOR_SIZE]),
+        }
+    }
+
+    /// Resets the host, turns its clock on at the identification frequency
+    /// and masks its interrupts, which are polled instead.
+    fn reset(&mut self) -> Result<()> {
+        self.registers.CONTROL0.write(0);
+        self.registers.CONTROL1.set_bits(C1_SRST_HC);
+        wait_until(COMMAND_TIMEOUT, || !self.registers.CONTROL1.has_mask(C1_SRST_HC))?;
+
+        self.registers.CONTROL1.write_field(C1_DATA_TOUNIT.0, C1_DATA_TOUNIT.1, DATA_TOUNIT_MAX);
+        self.set_clock(IDENTIFY_CLOCK)?;
+
+        self.registers.IRPT_EN.write(0);
+        self.registers.IRPT_MASK.write(0xFFFFFFFF);
+        self.registers.INTERRUPT.write(0xFFFFFFFF);
+        Ok(())
+    }
+
+    /// Switches the card clock to at most `target` Hz.
+    fn set_clock(&mut self, target: u32) -> Result<()> {
+        wait_until(COMMAND_TIMEOUT, || {
+            !self.registers.STATUS.has_mask(STATUS_CMD_INHIBIT | STATUS_DAT_INHIBIT)
+        })?;
+
+        self.registers.CONTROL1.clear_bits(C1_CLK_EN);
+        let bits = divisor_bits(clock_divisor(target));
+        self.registers.CONTROL1.update(|control| {
+            let fields = (((1 << C1_CLK_FREQ8.1) - 1) << C1_CLK_FREQ8.0)
+                | (((1 << C1_CLK_FREQ_MS2.1) - 1) << C1_CLK_FREQ_MS2.0);
+            (control & !fields) | bits | C1_CLK_INTLEN
+        });
+
+        wait_until(COMMAND_TIMEOUT, || self.registers.CONTROL1.has_mask(C1_CLK_STABLE))?;
+        self.registers.CONTROL1.set_bits(C1_CLK_EN);
+        Ok(())
+    }
+
+    /// Takes the card from idle to selected, learning its address and
+    /// capacity on the way.
+    fn identify(&mut self) -> Result<()> {
+        self.command(GO_IDLE_STATE, 0)?;
+
+        // Version 1 cards don't answer `CMD8`.
+        if self.command(SEND_IF_COND, IF_COND)? & 0xFFF != IF_COND {
+            return Err(Error::UnsupportedCard);
+        }
+
+        let deadline = Deadline::after(Duration::from_millis(POWER_UP_TIMEOUT));
+        let ocr = loop {
+            self.command(APP_CMD, 0)?;
+            let ocr = self.command(SD_SEND_OP_COND, OCR_HCS | OCR_VOLTAGE)?;
+            if ocr & OCR_POWERED_UP != 0 {
+                break ocr;
+            }
+
+            if deadline.expired() {
+                return Err(Error::Timeout);
+            }
+
+            timer::spin_sleep_ms(10);
+        };
+
+        if ocr & OCR_VOLTAGE == 0 {
+            return Err(Error::UnsupportedCard);
+        }
+
+        self.high_capacity = ocr & OCR_CCS != 0;
+        self.command(ALL_SEND_CID, 0)?;
+        self.rca = self.command(SEND_RELATIVE_ADDR, 0)? & 0xFFFF0000;
+        let rca = self.rca;
+        self.command(SELECT_CARD, rca)?;
+
+        if !self.high_capacity {
+            self.command(SET_BLOCKLEN, SECTOR_SIZE as u32)?;
+        }
+
+        self.set_clock(TRANSFER_CLOCK)
+    }
+
+    /// Issues `command` with the argument `arg` and waits for it to finish.
+    /// Returns the first word of the response.
+    fn command(&mut self, command: Command, arg: u32) -> Result<u32> {
+        wait_until(COMMAND_TIMEOUT, || !self.registers.STATUS.has_mask(STATUS_CMD_INHIBIT))?;
+
+        self.registers.INTERRUPT.write(0xFFFFFFFF);
+        self.registers.ARG1.write(arg);
+        self.registers.CMDTM.write(command.cmdtm());
+        self.wait_interrupt(INT_CMD_DONE, COMMAND_TIMEOUT, command.index)?;
+        Ok(self.registers.RESP[0].read())
+    }
+
+    /// Reads like `read_blocks()`, abandoning a failed attempt with `abort()`
+    /// and retrying it up to `READ_ATTEMPTS` times in all.
+    fn read_retrying(&mut self, command: Command, start: u64, buf: &mut [u8]) -> Result<usize> {
+        let mut retry = 0;
+        loop {
+            let error = match self.read_blocks(command, start, buf) {
+                Ok(read) => return Ok(read),
+                Err(error @ Error::InvalidSector(_)) => return Err(error),
+                Err(error) => error,
+            };
+
+            if self.abort().is_err() {
+                return Err(Error::CardLost);
+            }
+
+            if retry + 1 >= READ_ATTEMPTS {
+                return Err(error);
+            }
+
+            timer::spin_sleep_ms(retry_backoff(retry));
+            retry += 1;
+        }
+    }
+
+    /// Abandons a failed transfer: resets the controller's command and data
+    /// lines and, if the card is still sending blocks, stops it with
+    /// `STOP_TRANSMISSION`. The card's state is read with `SEND_STATUS` first,
+    /// as a card that isn't sending ignores `STOP_TRANSMISSION`; an error here
+    /// means the card isn't answering at all.
+    fn abort(&mut self) -> Result<()> {
+        let lines = C1_SRST_CMD | C1_SRST_DATA;
+        self.registers.CONTROL1.set_bits(lines);
+        wait_until(COMMAND_TIMEOUT, || self.registers.CONTROL1.read() & lines == 0)?;
+        self.registers.INTERRUPT.write(0xFFFFFFFF);
+
+        let rca = self.rca;
+        if card_state(self.command(SEND_STATUS, rca)?) == CardState::Data {
+            self.command(STOP_TRANSMISSION, 0)?;
+            wait_until(DATA_TIMEOUT, || !self.registers.STATUS.has_mask(STATUS_DAT_INHIBIT))?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads `buf.len() / SECTOR_SIZE` blocks starting at sector `start`
+    /// with `command`.
+    fn read_blocks(&mut self, command: Command, start: u64, buf: &mut [u8]) -> Result<usize> {
+        let address = sector_address(start, self.high_capacity)?;
+        let count = buf.len() / SECTOR_SIZE;
+
+        wait_until(COMMAND_TIMEOUT, || !self.registers.STATUS.has_mask(STATUS_DAT_INHIBIT))?;
+        self.registers.BLKSIZECNT.write((count as u32) << 16 | SECTOR_SIZE as u32);
+        self.command(command, address)?;
+
+        for block in buf.chunks_mut(SECTOR_SIZE) {
+            self.wait_interrupt(INT_READ_RDY, DATA_TIMEOUT, command.index)?;
+            for word in block.chunks_mut(4) {
+                let value = self.registers.DATA.read();
+                word.copy_from_slice(&[value as u8, (value >> 8) as u8,
+                                       (value >> 16) as u8, (value >> 24) as u8]);
+            }
+        }
+
+        self.wait_interrupt(INT_DATA_DONE, DATA_TIMEOUT, command.index)?;
+        Ok(count * SECTOR_SIZE)
+    }
+
+    /// Waits for the `INTERRUPT` bits in `mask` and acknowledges them. The
+    /// error bits are acknowledged and reported as a failure of the command
+    /// with index `index`. Gives up after `timeout` milliseconds.
+    fn wait_interrupt(&mut self, mask: u32, timeout: u64, index: u8) -> Result<()> {
+        let deadline = Deadline::after(Duration::from_millis(timeout));
+        loop {
+            let interrupt = self.registers.INTERRUPT.read();
+            if interrupt & INT_ERRORS != 0 {
+                self.registers.INTERRUPT.write(interrupt);
+                return Err(Error::SdCommand(index));
+            }
+
+            if interrupt & mask == mask {
+                self.registers.INTERRUPT.write(mask);
+                return Ok(());
+            }
+
+            if deadline.expired() {
+                return Err(Error::Timeout);
+            }
+        }
+    }
+}
+
+impl BlockDevice for Emmc {
+    /// Reads sector `n`, retrying as `read_sectors()` does.
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> Result<usize> {
+        if buf.len() >= SECTOR_SIZE {
+            return self.read_retrying(READ_SINGLE_BLOCK, n, &mut buf[..SECTOR_SIZE]);
+        }
+
+        // Reads are a whole sector long, so a shorter `buf` is filled from
+        // a copy.
+        let mut sector = [0u8; SECTOR_SIZE];
+        self.read_retrying(READ_SINGLE_BLOCK, n, &mut sector)?;
+        let len = buf.len();
+        buf.copy_from_slice(&sector[..len]);
+        Ok(len)
+    }
+}
+
+/// Spins until `done` returns `true`, for at most `timeout` milliseconds.
+fn wait_until<F: FnMut() -> bool>(timeout: u64, mut done: F) -> Result<()> {
+    let deadline = Deadline::after(Duration::from_millis(timeout));
+    while !done() {
+        if deadline.expired() {
+            return Err(Error::Timeout);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use core::fmt;
+
+/// The ways a `pi` driver operation can fail.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The GPIO pin number is larger than `gpio::MAX_PIN`.
+    InvalidPin(u8),
+    /// The GPIO bank number is at least `gpio::BANKS`.
+    InvalidBank(u8),
+    /// The UART can't produce the baud rate.
+    InvalidBaud(u32),
+    /// The operation didn't complete before its timeout expired.
+    Timeout,
+    /// `quiesce::MAX_HOOKS` shutdown hooks are already registered.
+    TooManyHooks,
+    /// The mini UART only supports one stop bit.
+    UnsupportedStopBits,
+    /// A UART self-test sent `.0` and read back `.1`.
+    LoopbackMismatch(u8, u8),
+    /// The DMA channel doesn't exist, isn't supported, or is already in use.
+    InvalidDmaChannel(u8),
+    /// A transfer on the DMA channel failed.
+    DmaFailed(u8),
+    /// The I2C address doesn't fit in 7 bits.
+    InvalidI2cAddress(u8),
+    /// The I2C device at the address didn't acknowledge its address or a
+    /// byte written to it.
+    I2cNack(u8),
+    /// The GPU firmware didn't process a mailbox request.
+    MailboxFailed,
+    /// The SD card reported an error for the command with index `.0`.
+    SdCommand(u8),
+    /// The SD card isn't a version 2 card that runs at 3.3V.
+    UnsupportedCard,
+    /// The sector is beyond what the SD card can address.
+    InvalidSector(u64),
+    /// The SD card stopped responding. `Emmc::reinit()` may bring it back.
+    CardLost,
+}
+
+/// A non-blocking operation couldn't proceed without waiting.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WouldBlock;
+
+/// A `Result` whose error type is `pi::Error`.
+pub type Result<T> = ::core::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::InvalidPin(pin) => write!(f, "GPIO pin {} doesn't exist", pin),
+            Error::InvalidBank(bank) => write!(f, "GPIO bank {} doesn't exist", bank),
+            Error::InvalidBaud(baud) => write!(f, "the UART can't run at {} baud", baud),
+            Error::Timeout => write!(f, "timed out"),
+            Error::TooManyHooks => write!(f, "too many shutdown hooks"),
+            Error::UnsupportedStopBits => write!(f, "the mini UART only supports one stop bit"),
+            Error::LoopbackMismatch(sent, received) => {
+                write!(f, "UART loopback sent {:#04x} but read back {:#04x}", sent, received)
+            }
+            Error::InvalidDmaChannel(channel) => write!(f, "DMA channel {} isn't available", channel),
+            Error::DmaFailed(channel) => write!(f, "DMA channel {} reported an error", channel),
+            Error::InvalidI2cAddress(address) => write!(f, "{:#x} isn't a 7-bit I2C address", address),
+            Error::I2cNack(address) => write!(f, "I2C device {:#04x} didn't acknowledge", address),
+            Error::MailboxFailed => write!(f, "the GPU firmware rejected a mailbox request"),
+            Error::SdCommand(index) => write!(f, "the SD card failed command {}", index),
+            Error::UnsupportedCard => write!(f, "the SD card isn't supported"),
+            Error::InvalidSector(sector) => write!(f, "sector {} can't be addressed", sector),
+            Error::CardLost => write!(f, "the SD card stopped responding"),
+        }
+    }
+}
+
+impl fmt::Display for WouldBlock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "operation would block")
+    }
+}
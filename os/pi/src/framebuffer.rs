@@ -0,0 +1,142 @@
+//! A linear framebuffer allocated by the GPU's firmware, which scans it out to
+//! the HDMI port.
+//!
+//! Pixels are 32 bits wide. Whether the red or the blue channel is in the low
+//! byte is up to the firmware; `PixelOrder::pack()` builds pixels either way.
+
+use core::slice;
+
+use error::{Error, Result};
+use mailbox::{self, Message};
+
+/// Property tags used to set the framebuffer up.
+const TAG_ALLOCATE_BUFFER: u32 = 0x00040001;
+const TAG_GET_PITCH: u32 = 0x00040008;
+const TAG_SET_PHYSICAL_SIZE: u32 = 0x00048003;
+const TAG_SET_VIRTUAL_SIZE: u32 = 0x00048004;
+const TAG_SET_DEPTH: u32 = 0x00048005;
+const TAG_SET_PIXEL_ORDER: u32 = 0x00048006;
+
+/// The number of bits per pixel.
+pub const DEPTH: u32 = 32;
+
+/// The alignment asked of the framebuffer's address.
+const BUFFER_ALIGN: u32 = 4096;
+
+/// The bits of a VideoCore bus address that are the ARM physical address.
+const BUS_ADDRESS_MASK: u32 = 0x3FFFFFFF;
+
+/// Which color channel is in the low byte of a pixel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelOrder {
+    /// Blue in the low byte, then green, then red.
+    Bgr,
+    /// Red in the low byte, then green, then blue.
+    Rgb,
+}
+
+impl PixelOrder {
+    /// Returns the pixel showing the color `(r, g, b)`.
+    pub fn pack(&self, r: u8, g: u8, b: u8) -> u32 {
+        let (low, high) = match *self {
+            PixelOrder::Bgr => (b, r),
+            PixelOrder::Rgb => (r, b),
+        };
+
+        (high as u32) << 16 | (g as u32) << 8 | low as u32
+    }
+}
+
+/// The geometry and location of an allocated framebuffer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Info {
+    /// The width in pixels.
+    pub width: u32,
+    /// The height in pixels.
+    pub height: u32,
+    /// The distance between the starts of consecutive rows, in bytes.
+    pub pitch: u32,
+    pub order: PixelOrder,
+    /// The ARM physical address of the first pixel.
+    pub base: usize,
+    /// The size of the framebuffer in bytes.
+    pub size: usize,
+}
+
+/// A property message asking for a `width` by `height` framebuffer, and where
+/// the answers to it will be.
+pub(crate) struct Request {
+    pub message: Message,
+    size: usize,
+    order: usize,
+    buffer: usize,
+    pitch: usize,
+}
+
+impl Request {
+    /// Returns the request for a `width` by `height` framebuffer with
+    /// `DEPTH` bits per pixel, red first if the firmware allows it.
+    pub fn new(width: u32, height: u32) -> Request {
+        let mut message = Message::new();
+        let size = message.push(TAG_SET_PHYSICAL_SIZE, &[width, height], 2);
+        message.push(TAG_SET_VIRTUAL_SIZE, &[width, height], 2);
+        message.push(TAG_SET_DEPTH, &[DEPTH], 1);
+        let order = message.push(TAG_SET_PIXEL_ORDER, &[1], 1);
+        let buffer = message.push(TAG_ALLOCATE_BUFFER, &[BUFFER_ALIGN], 2);
+        let pitch = message.push(TAG_GET_PITCH, &[], 1);
+        Request { message, size, order, buffer, pitch }
+    }
+
+    /// Reads the framebuffer the firmware allocated out of its response.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::MailboxFailed` if no framebuffer was allocated.
+    pub fn info(&self) -> Result<Info> {
+        let message = &self.message;
+        let (base, size) = (message.value(self.buffer), message.value(self.buffer + 1));
+        if base == 0 || size == 0 {
+            return Err(Error::MailboxFailed);
+        }
+
+        Ok(Info {
+            width: message.value(self.size),
+            height: message.value(self.size + 1),
+            pitch: message.value(self.pitch),
+            order: if message.value(self.order) == 1 { PixelOrder::Rgb } else { PixelOrder::Bgr },
+            base: (base & BUS_ADDRESS_MASK) as usize,
+            size: size as usize,
+        })
+    }
+}
+
+/// A framebuffer being scanned out to HDMI.
+pub struct Framebuffer {
+    info: Info,
+}
+
+impl Framebuffer {
+    /// Asks the firmware for a `width` by `height` framebuffer. The firmware
+    /// may pick a different size; see `info()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::MailboxFailed` if the firmware can't provide one.
+    pub fn new(width: u32, height: u32) -> Result<Framebuffer> {
+        let mut request = Request::new(width, height);
+        mailbox::call(&mut request.message)?;
+        Ok(Framebuffer { info: request.info()? })
+    }
+
+    /// Returns the framebuffer's geometry and location.
+    pub fn info(&self) -> Info {
+        self.info
+    }
+
+    /// Returns the framebuffer's pixels, row by row, with `pitch / 4` pixels
+    /// from the start of one row to the start of the next. The framebuffer
+    /// stays allocated for good, so the pixels can be kept.
+    pub fn into_pixels(self) -> &'static mut [u32] {
+        unsafe { slice::from_raw_parts_mut(self.info.base as *mut u32, self.info.size / 4) }
+    }
+}
@@ -0,0 +1,196 @@
+//! The per-core ARM generic timer.
+//!
+//! Each core has its own physical timer (`CNTP`), programmed through system
+//! registers, which counts at `frequency()` Hz independently of the BCM
+//! system timer in `timer`. Its interrupt is routed to the core by the local
+//! interrupt controller at `0x4000_0040` rather than the peripheral
+//! interrupt controller in `interrupt`.
+//!
+//! A core can only program its own timer, so every function here acts on the
+//! timer of the core it's called on. The timer's system registers only exist
+//! on the Raspberry Pi, so off it only the conversion from microseconds to
+//! counts is available.
+
+#[cfg(any(target_arch = "aarch64", test))]
+use common::register_layout;
+#[cfg(target_arch = "aarch64")]
+use quiesce;
+#[cfg(any(target_arch = "aarch64", test))]
+use volatile::prelude::*;
+#[cfg(any(target_arch = "aarch64", test))]
+use volatile::{Volatile, ReadVolatile};
+
+/// The base address of the local interrupt controller's per-core timer,
+/// mailbox and interrupt source registers.
+#[cfg(target_arch = "aarch64")]
+const LOCAL_INT_BASE: usize = 0x4000_0040;
+
+/// The number of cores.
+pub const CORES: usize = 4;
+
+/// `CNTP_CTL_EL0` bits: the timer is enabled, its interrupt is masked, and
+/// its condition is met.
+#[cfg(target_arch = "aarch64")]
+const CTL_ENABLE: u64 = 1;
+#[cfg(target_arch = "aarch64")]
+const CTL_IMASK: u64 = 1 << 1;
+#[cfg(target_arch = "aarch64")]
+const CTL_ISTATUS: u64 = 1 << 2;
+
+/// The bit for the non-secure physical timer (`CNTPNSIRQ`) in a core's
+/// timer interrupt control and interrupt source registers. The firmware
+/// leaves the cores in non-secure state.
+#[cfg(any(target_arch = "aarch64", test))]
+const CNTPNSIRQ: u32 = 1 << 1;
+
+#[cfg(any(target_arch = "aarch64", test))]
+register_layout! {
+    struct Registers(0x40) {
+        0x00 => TIMER_INT_CTL: [Volatile<u32>; CORES],
+        0x10 => MAILBOX_INT_CTL: [Volatile<u32>; CORES],
+        0x20 => IRQ_SOURCE: [ReadVolatile<u32>; CORES],
+        0x30 => FIQ_SOURCE: [ReadVolatile<u32>; CORES],
+    }
+}
+
+/// The system registers behind the timer.
+#[cfg(target_arch = "aarch64")]
+mod sysreg {
+    pub fn mpidr() -> u64 {
+        let value: u64;
+        unsafe { asm!("mrs $0, mpidr_el1" : "=r"(value) : : : "volatile"); }
+        value
+    }
+
+    pub fn cntfrq() -> u64 {
+        let value: u64;
+        unsafe { asm!("mrs $0, cntfrq_el0" : "=r"(value) : : : "volatile"); }
+        value
+    }
+
+    pub fn cntpct() -> u64 {
+        let value: u64;
+        unsafe { asm!("isb; mrs $0, cntpct_el0" : "=r"(value) : : "memory" : "volatile"); }
+        value
+    }
+
+    pub fn cntp_ctl() -> u64 {
+        let value: u64;
+        unsafe { asm!("mrs $0, cntp_ctl_el0" : "=r"(value) : : : "volatile"); }
+        value
+    }
+
+    pub fn set_cntp_ctl(value: u64) {
+        unsafe { asm!("msr cntp_ctl_el0, $0; isb" : : "r"(value) : "memory" : "volatile"); }
+    }
+
+    pub fn set_cntp_tval(value: u64) {
+        unsafe { asm!("msr cntp_tval_el0, $0; isb" : : "r"(value) : "memory" : "volatile"); }
+    }
+}
+
+/// Returns the number of the core the caller is running on.
+#[cfg(target_arch = "aarch64")]
+pub fn core_id() -> usize {
+    (sysreg::mpidr() & 0b11) as usize
+}
+
+/// Returns the frequency the timer counts at, in Hz, as set by the firmware:
+/// 19.2MHz on the Pi 3.
+#[cfg(target_arch = "aarch64")]
+pub fn frequency() -> u64 {
+    sysreg::cntfrq()
+}
+
+/// Returns the current count. Counts are the same on every core.
+#[cfg(target_arch = "aarch64")]
+pub fn counter() -> u64 {
+    sysreg::cntpct()
+}
+
+/// Converts `us` microseconds to counts at `frequency` Hz, saturating at the
+/// largest value `CNTP_TVAL_EL0` can hold.
+pub fn us_to_ticks(us: u32, frequency: u64) -> u32 {
+    let ticks = us as u64 * frequency / 1_000_000;
+    if ticks > i32::max_value() as u64 {
+        i32::max_value() as u32
+    } else {
+        ticks as u32
+    }
+}
+
+/// Routes core `core`'s timer interrupt to its IRQ line in `registers` if
+/// `enable` is `true`, or stops routing it otherwise. Other interrupt sources
+/// are unaffected.
+#[cfg(any(target_arch = "aarch64", test))]
+pub(crate) fn route_irq(registers: &mut Registers, core: usize, enable: bool) {
+    let control = registers.TIMER_INT_CTL[core].read();
+    if enable {
+        registers.TIMER_INT_CTL[core].write(control | CNTPNSIRQ);
+    } else {
+        registers.TIMER_INT_CTL[core].write(control & !CNTPNSIRQ);
+    }
+}
+
+/// The calling core's generic timer.
+#[cfg(target_arch = "aarch64")]
+pub struct GenericTimer {
+    core: usize,
+    registers: &'static mut Registers,
+}
+
+#[cfg(target_arch = "aarch64")]
+impl GenericTimer {
+    /// Returns a handle to the calling core's timer. The handle must not be
+    /// moved to another core.
+    pub fn new() -> GenericTimer {
+        GenericTimer {
+            core: core_id(),
+            registers: unsafe { &mut *(LOCAL_INT_BASE as *mut Registers) },
+        }
+    }
+
+    /// Returns the number of the core whose timer this is.
+    pub fn core(&self) -> usize {
+        self.core
+    }
+
+    /// Starts the timer so that it fires `us` microseconds from now,
+    /// replacing any tick already requested, and routes its interrupt to
+    /// this core's IRQ line. The timer keeps its interrupt raised until it is
+    /// given a new tick or stopped.
+    pub fn tick_in(&mut self, us: u32) {
+        sysreg::set_cntp_tval(us_to_ticks(us, frequency()) as u64);
+        sysreg::set_cntp_ctl(CTL_ENABLE);
+        route_irq(self.registers, self.core, true);
+
+        // If the hook table is full, the timer is simply left running.
+        let _ = quiesce::register(shutdown);
+    }
+
+    /// Returns `true` if the tick requested with `tick_in()` has arrived.
+    pub fn has_fired(&self) -> bool {
+        let control = sysreg::cntp_ctl();
+        control & CTL_ENABLE != 0 && control & CTL_ISTATUS != 0
+    }
+
+    /// Returns `true` if this core's timer interrupt is pending at the local
+    /// interrupt controller.
+    pub fn is_pending(&self) -> bool {
+        self.registers.IRQ_SOURCE[self.core].has_mask(CNTPNSIRQ)
+    }
+
+    /// Stops the timer, lowering its interrupt, and stops routing the
+    /// interrupt to this core.
+    pub fn stop(&mut self) {
+        sysreg::set_cntp_ctl(CTL_IMASK);
+        route_irq(self.registers, self.core, false);
+    }
+}
+
+/// Shutdown hook registered by `GenericTimer::tick_in()`: stops the calling
+/// core's timer.
+#[cfg(target_arch = "aarch64")]
+fn shutdown() {
+    GenericTimer::new().stop();
+}
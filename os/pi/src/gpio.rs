@@ -1,11 +1,19 @@
 use core::marker::PhantomData;
+use core::sync::atomic::{spin_loop_hint, AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
 
-use common::{IO_BASE, states};
+use common::{IO_BASE, states, register_layout};
+use error::{Error, Result};
+use quiesce;
+#[cfg(feature = "timer")]
+use core::time::Duration;
+#[cfg(feature = "timer")]
+use timer::Deadline;
 use volatile::prelude::*;
 use volatile::{Volatile, WriteVolatile, ReadVolatile, Reserved};
 
 /// An alternative GPIO function.
 #[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Function {
     Input = 0b000,
     Output = 0b001,
@@ -17,38 +25,74 @@ pub enum Function {
     Alt5 = 0b010
 }
 
-#[repr(C)]
-#[allow(non_snake_case)]
-struct Registers {
-    FSEL: [Volatile<u32>; 6],
-    __r0: Reserved<u32>,
-    SET: [WriteVolatile<u32>; 2],
-    __r1: Reserved<u32>,
-    CLR: [WriteVolatile<u32>; 2],
-    __r2: Reserved<u32>,
-    LEV: [ReadVolatile<u32>; 2],
-    __r3: Reserved<u32>,
-    EDS: [Volatile<u32>; 2],
-    __r4: Reserved<u32>,
-    REN: [Volatile<u32>; 2],
-    __r5: Reserved<u32>,
-    FEN: [Volatile<u32>; 2],
-    __r6: Reserved<u32>,
-    HEN: [Volatile<u32>; 2],
-    __r7: Reserved<u32>,
-    LEN: [Volatile<u32>; 2],
-    __r8: Reserved<u32>,
-    AREN: [Volatile<u32>; 2],
-    __r9: Reserved<u32>,
-    AFEN: [Volatile<u32>; 2],
-    __r10: Reserved<u32>,
-    PUD: Volatile<u32>,
-    PUDCLK: [Volatile<u32>; 2],
+impl Function {
+    /// Returns the function selected by the 3-bit `GPFSELn` field value
+    /// `bits`. Only the low 3 bits of `bits` are used.
+    pub fn from_bits(bits: u32) -> Function {
+        match bits & 0b111 {
+            0b000 => Function::Input,
+            0b001 => Function::Output,
+            0b100 => Function::Alt0,
+            0b101 => Function::Alt1,
+            0b110 => Function::Alt2,
+            0b111 => Function::Alt3,
+            0b011 => Function::Alt4,
+            _ => Function::Alt5,
+        }
+    }
+}
+
+register_layout! {
+    struct Registers(0xA0) {
+        0x00 => FSEL: [Volatile<u32>; 6],
+        0x18 => __r0: Reserved<u32>,
+        0x1C => SET: [WriteVolatile<u32>; 2],
+        0x24 => __r1: Reserved<u32>,
+        0x28 => CLR: [WriteVolatile<u32>; 2],
+        0x30 => __r2: Reserved<u32>,
+        0x34 => LEV: [ReadVolatile<u32>; 2],
+        0x3C => __r3: Reserved<u32>,
+        0x40 => EDS: [Volatile<u32>; 2],
+        0x48 => __r4: Reserved<u32>,
+        0x4C => REN: [Volatile<u32>; 2],
+        0x54 => __r5: Reserved<u32>,
+        0x58 => FEN: [Volatile<u32>; 2],
+        0x60 => __r6: Reserved<u32>,
+        0x64 => HEN: [Volatile<u32>; 2],
+        0x6C => __r7: Reserved<u32>,
+        0x70 => LEN: [Volatile<u32>; 2],
+        0x78 => __r8: Reserved<u32>,
+        0x7C => AREN: [Volatile<u32>; 2],
+        0x84 => __r9: Reserved<u32>,
+        0x88 => AFEN: [Volatile<u32>; 2],
+        0x90 => __r10: Reserved<u32>,
+        0x94 => PUD: Volatile<u32>,
+        0x98 => PUDCLK: [Volatile<u32>; 2],
+    }
+}
+
+/// The level changes that `Gpio<Input>::enable_edge_detect()` records as
+/// events.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// The pull resistor on an input pin, as the value of the `GPPUD` control
+/// field.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pull {
+    None = 0b00,
+    Down = 0b01,
+    Up = 0b10,
 }
 
 /// Possible states for a GPIO pin.
 states! {
-    Uninitialized, Input, Output, Alt
+    Uninitialized, Input, Output, Alt, OpenDrain
 }
 
 /// A GPIP pin in state `State`.
@@ -64,9 +108,95 @@ pub struct Gpio<State> {
     _state: PhantomData<State>
 }
 
+/// The largest GPIO pin number.
+pub const MAX_PIN: u8 = 53;
+
 /// The base address of the `GPIO` registers.
 const GPIO_BASE: usize = IO_BASE + 0x200000;
 
+/// The width in bits of each pin's function select field in `GPFSELn`.
+const FSEL_WIDTH: u32 = 3;
+
+/// The number of pins whose function select fields share one `GPFSELn`.
+const FSEL_PINS: u8 = 10;
+
+/// The number of pins sharing one `GPSETn`, `GPCLRn` or `GPLEVn` register.
+const BANK_PINS: u8 = 32;
+
+/// The number of cycles the pull-up/down control signal must be held before
+/// and after it is clocked into a pin.
+const PUD_SETUP_CYCLES: u32 = 150;
+
+/// The pins carrying the mini UART's transmit and receive lines, TXD1 and
+/// RXD1, in `Function::Alt5`.
+pub const TXD1: u8 = 14;
+pub const RXD1: u8 = 15;
+
+/// The pins carrying the mini UART's flow control lines, CTS1 and RTS1, in
+/// `Function::Alt5`.
+pub const CTS1: u8 = 16;
+pub const RTS1: u8 = 17;
+
+/// The same pins carrying the PL011's lines, TXD0 and RXD0, in
+/// `Function::Alt0`.
+pub const TXD0: u8 = 14;
+pub const RXD0: u8 = 15;
+
+/// The pins whose function has been set since boot, one bit per pin laid out
+/// as in `GPSETn`.
+static CLAIMED: [AtomicUsize; 2] = [ATOMIC_USIZE_INIT, ATOMIC_USIZE_INIT];
+
+/// Shutdown hook registered by the first `into_alt()`. See `shutdown_registers()`.
+fn shutdown() {
+    let claimed = [CLAIMED[0].load(Ordering::SeqCst) as u32,
+                   CLAIMED[1].load(Ordering::SeqCst) as u32];
+    let registers = unsafe { &mut *(GPIO_BASE as *mut Registers) };
+    let returned = shutdown_registers(registers, claimed);
+    for bank in 0..2 {
+        CLAIMED[bank].fetch_and(!(returned[bank] as usize), Ordering::SeqCst);
+    }
+}
+
+/// Disables rising edge, falling edge, high, low and asynchronous edge event
+/// detection on every pin, clears any pending events, and returns the pins in
+/// `claimed` (one bit per pin, laid out as in `GPSETn`) to inputs, except for
+/// `TXD1` and `RXD1` (which are also `TXD0` and `RXD0`) so that the console
+/// keeps working. Returns the pins that were returned to inputs.
+pub(crate) fn shutdown_registers(registers: &mut Registers, claimed: [u32; 2]) -> [u32; 2] {
+    for bank in 0..2 {
+        registers.REN[bank].write(0);
+        registers.FEN[bank].write(0);
+        registers.HEN[bank].write(0);
+        registers.LEN[bank].write(0);
+        registers.AREN[bank].write(0);
+        registers.AFEN[bank].write(0);
+        // Event status bits are cleared by writing 1s.
+        registers.EDS[bank].write(!0);
+    }
+
+    let mut returned = [0u32; 2];
+    for pin in 0..(MAX_PIN + 1) {
+        let (bank, bit) = ((pin / BANK_PINS) as usize, (pin % BANK_PINS) as u32);
+        if claimed[bank] & (1 << bit) == 0 || pin == TXD1 || pin == RXD1 {
+            continue;
+        }
+
+        let shift = (pin % FSEL_PINS) as u32 * FSEL_WIDTH;
+        registers.FSEL[(pin / FSEL_PINS) as usize]
+            .write_field(shift, FSEL_WIDTH, Function::Input as u32);
+        returned[bank] |= 1 << bit;
+    }
+
+    returned
+}
+
+/// Spins for at least `cycles` CPU cycles.
+fn wait_cycles(cycles: u32) {
+    for _ in 0..cycles {
+        spin_loop_hint();
+    }
+}
+
 impl<T> Gpio<T> {
     /// Transitions `self` to state `S`, consuming `self` and returning a new
     /// `Gpio` instance in state `S`. This method should _never_ be exposed to
@@ -79,21 +209,76 @@ impl<T> Gpio<T> {
             _state: PhantomData
         }
     }
+
+    /// Returns this pin's number.
+    pub fn pin(&self) -> u8 {
+        self.pin
+    }
+
+    /// Reads back the function currently selected for this pin. Another
+    /// `Gpio` for the same pin may have changed it since `self` was created.
+    pub fn function(&self) -> Function {
+        let (register_index, shift) = self.fsel();
+        Function::from_bits(self.registers.FSEL[register_index].read_field(shift, FSEL_WIDTH))
+    }
+
+    /// Selects `function` for this pin and records the pin as claimed so that
+    /// `quiesce()` returns it to an input.
+    fn select(&mut self, function: Function) {
+        self.write_fsel(function);
+
+        // If the hook table is full, pins are simply left as they are.
+        let (bank, bit) = self.bank();
+        CLAIMED[bank].fetch_or(1 << bit, Ordering::SeqCst);
+        let _ = quiesce::register(shutdown);
+    }
+
+    /// Selects `function` for this pin without recording a claim.
+    #[inline(always)]
+    fn write_fsel(&mut self, function: Function) {
+        let (register_index, shift) = self.fsel();
+        self.registers.FSEL[register_index].write_field(shift, FSEL_WIDTH, function as u32);
+    }
+
+    /// Returns the index of the `GPFSELn` register holding this pin's
+    /// function select field, and the field's shift.
+    #[inline(always)]
+    fn fsel(&self) -> (usize, u32) {
+        ((self.pin / FSEL_PINS) as usize, (self.pin % FSEL_PINS) as u32 * FSEL_WIDTH)
+    }
+
+    /// Returns the index of the `GPSETn`, `GPCLRn` and `GPLEVn` registers
+    /// holding this pin's bit, and the bit's position in them.
+    #[inline(always)]
+    fn bank(&self) -> (usize, u32) {
+        ((self.pin / BANK_PINS) as usize, (self.pin % BANK_PINS) as u32)
+    }
 }
 
 impl Gpio<Uninitialized> {
     /// Returns a new `GPIO` structure for pin number `pin`.
     ///
-    /// # Panics
+    /// # Errors
     ///
-    /// Panics if `pin` > `53`.
-    pub fn new(pin: u8) -> Gpio<Uninitialized> {
-        if pin > 53 {
-            panic!("Gpio::new(): pin {} exceeds maximum of 53", pin);
+    /// Returns `Error::InvalidPin` if `pin` > `MAX_PIN`.
+    pub fn new(pin: u8) -> Result<Gpio<Uninitialized>> {
+        if pin > MAX_PIN {
+            return Err(Error::InvalidPin(pin));
         }
 
+        Ok(unsafe { Gpio::new_unchecked(pin) })
+    }
+
+    /// Returns a new `GPIO` structure for pin number `pin` without checking
+    /// that the pin exists.
+    ///
+    /// # Safety
+    ///
+    /// `pin` must be at most `MAX_PIN`. Larger pins index past the end of the
+    /// GPIO register block.
+    pub unsafe fn new_unchecked(pin: u8) -> Gpio<Uninitialized> {
         Gpio {
-            registers: unsafe { &mut *(GPIO_BASE as *mut Registers) },
+            registers: &mut *(GPIO_BASE as *mut Registers),
             pin: pin,
             _state: PhantomData
         }
@@ -101,16 +286,8 @@ impl Gpio<Uninitialized> {
 
     /// Enables the alternative function `function` for `self`. Consumes self
     /// and returns a `Gpio` structure in the `Alt` state.
-    pub fn into_alt(self, function: Function) -> Gpio<Alt> {
-        let register_index: usize = (self.pin / 10) as usize;
-        let shift: usize = (self.pin as usize - register_index * 10) * 3;
-
-        {
-            let register: &mut Volatile<u32> = &mut self.registers.FSEL[register_index];
-            let value: u32 = register.read();
-            register.write(value & !(0b111 << shift) | ((function as u32) << shift));
-        }
-
+    pub fn into_alt(mut self, function: Function) -> Gpio<Alt> {
+        self.select(function);
         self.transition()
     }
 
@@ -125,33 +302,257 @@ impl Gpio<Uninitialized> {
     pub fn into_input(self) -> Gpio<Input> {
         self.into_alt(Function::Input).transition()
     }
+
+    /// Sets this pin up as an emulated _open-drain_ pin, initially released.
+    /// Consumes self and returns a `Gpio` structure in the `OpenDrain` state.
+    ///
+    /// The pin is never driven high: it is pulled low by making it an output
+    /// whose level is cleared, and released by making it an input, so the
+    /// line needs a pull-up.
+    pub fn into_open_drain(self) -> Gpio<OpenDrain> {
+        let mut pin: Gpio<OpenDrain> = self.into_alt(Function::Input).transition();
+        let (register_index, shift) = pin.bank();
+        pin.registers.CLR[register_index].write(1 << shift);
+        pin
+    }
+}
+
+impl Gpio<Alt> {
+    /// Switches this pin to the alternative function `function`.
+    pub fn into_alt(mut self, function: Function) -> Gpio<Alt> {
+        self.select(function);
+        self
+    }
+
+    /// Releases this pin from its alternative function, making it an _input_
+    /// pin.
+    pub fn into_input(mut self) -> Gpio<Input> {
+        self.select(Function::Input);
+        self.transition()
+    }
+
+    /// Releases this pin from its alternative function, making it an _output_
+    /// pin.
+    pub fn into_output(mut self) -> Gpio<Output> {
+        self.select(Function::Output);
+        self.transition()
+    }
 }
 
 impl Gpio<Output> {
     /// Sets (turns on) the pin.
     pub fn set(&mut self) {
-        let register_index: usize = (self.pin / 32) as usize;
-        let shift: usize = self.pin as usize - register_index * 32;
-
+        let (register_index, shift) = self.bank();
         self.registers.SET[register_index].write(1 << shift);
     }
 
     /// Clears (turns off) the pin.
     pub fn clear(&mut self) {
-        let register_index: usize = (self.pin / 32) as usize;
-        let shift: usize = self.pin as usize - register_index * 32;
-
+        let (register_index, shift) = self.bank();
         self.registers.CLR[register_index].write(1 << shift);
     }
+
+    /// Sets the pin if `level` is `true` and clears it otherwise.
+    pub fn write(&mut self, level: bool) {
+        if level {
+            self.set();
+        } else {
+            self.clear();
+        }
+    }
+
+    /// Returns `true` if the pin is set. The level is read back from
+    /// `GPLEVn`, so a pin held low externally reads as cleared.
+    pub fn is_set(&self) -> bool {
+        let (register_index, shift) = self.bank();
+        self.registers.LEV[register_index].read_field(shift, 1) == 1
+    }
+
+    /// Inverts the pin's level as read back by `is_set()`.
+    pub fn toggle(&mut self) {
+        let level = self.is_set();
+        self.write(!level);
+    }
+}
+
+impl Gpio<OpenDrain> {
+    /// Pulls the line low.
+    pub fn pull_low(&mut self) {
+        self.write_fsel(Function::Output);
+    }
+
+    /// Stops pulling the line low, letting its pull-up or another device set
+    /// its level.
+    pub fn release(&mut self) {
+        self.write_fsel(Function::Input);
+    }
+
+    /// Returns `true` if the line is high: released by this pin and by every
+    /// other device on it.
+    pub fn is_high(&self) -> bool {
+        let (register_index, shift) = self.bank();
+        self.registers.LEV[register_index].read_field(shift, 1) == 1
+    }
 }
 
 impl Gpio<Input> {
     /// Reads the pin's value. Returns `true` if the level is high and `false`
     /// if the level is low.
-    pub fn level(&mut self) -> bool {
-        let register_index: usize = (self.pin / 32) as usize;
-        let shift: usize = self.pin as usize - register_index * 32;
+    pub fn level(&self) -> bool {
+        let (register_index, shift) = self.bank();
+        self.registers.LEV[register_index].read_field(shift, 1) == 1
+    }
+
+    /// Starts recording `edge` level changes on this pin in its event status
+    /// bit, replacing any edges recorded before, and clears any pending event.
+    /// Detection is synchronous: pulses shorter than two clock cycles are
+    /// missed.
+    ///
+    /// Events on pins 0 to 31 raise `interrupt::Interrupt::Gpio0` and on pins
+    /// 32 to 53 `Gpio1`, once those are enabled in the interrupt controller.
+    /// The handler must call `clear_event()` or the interrupt stays raised.
+    pub fn enable_edge_detect(&mut self, edge: Edge) {
+        let (bank, bit) = self.bank();
+        let (rising, falling) = match edge {
+            Edge::Rising => (true, false),
+            Edge::Falling => (false, true),
+            Edge::Both => (true, true),
+        };
+
+        self.registers.REN[bank].write_field(bit, 1, rising as u32);
+        self.registers.FEN[bank].write_field(bit, 1, falling as u32);
+        self.clear_event();
+    }
+
+    /// Sets the pin's pull resistor to `pull`. The setting survives a reset;
+    /// it isn't undone by `quiesce()`.
+    pub fn set_pull(&mut self, pull: Pull) {
+        let (bank, bit) = self.bank();
+        self.registers.PUD.write(pull as u32);
+        wait_cycles(PUD_SETUP_CYCLES);
+        self.registers.PUDCLK[bank].write(1 << bit);
+        wait_cycles(PUD_SETUP_CYCLES);
+        self.registers.PUD.write(Pull::None as u32);
+        self.registers.PUDCLK[bank].write(0);
+    }
+
+    /// Stops recording edges on this pin and clears any pending event.
+    pub fn disable_edge_detect(&mut self) {
+        let (bank, bit) = self.bank();
+        self.registers.REN[bank].clear_bits(1 << bit);
+        self.registers.FEN[bank].clear_bits(1 << bit);
+        self.clear_event();
+    }
+
+    /// Returns `true` if a detected edge has been recorded since the event
+    /// was last cleared.
+    pub fn has_event(&self) -> bool {
+        let (bank, bit) = self.bank();
+        self.registers.EDS[bank].read_field(bit, 1) == 1
+    }
+
+    /// Blocks until an `edge` level change happens on this pin. If `timeout`
+    /// is `Some(ms)`, this method blocks for at most `ms` milliseconds.
+    /// Otherwise, it blocks indefinitely. Edges before the call are ignored.
+    ///
+    /// Edge detection is left disabled afterwards, replacing any set up with
+    /// `enable_edge_detect()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Err(Error::Timeout)` if the timeout expired before an edge.
+    #[cfg(feature = "timer")]
+    pub fn wait_for_edge(&mut self, edge: Edge, timeout: Option<u32>) -> Result<()> {
+        let deadline = timeout.map(|ms| Deadline::after(Duration::from_millis(ms as u64)));
+        self.enable_edge_detect(edge);
+
+        let mut result = Ok(());
+        while !self.has_event() {
+            if let Some(ref deadline) = deadline {
+                if deadline.expired() {
+                    result = Err(Error::Timeout);
+                    break;
+                }
+            }
+        }
+
+        self.disable_edge_detect();
+        result
+    }
+
+    /// Clears this pin's recorded event. Other pins' events are unaffected.
+    pub fn clear_event(&mut self) {
+        let (bank, bit) = self.bank();
+        // Event status bits are cleared by writing 1s; 0s are ignored.
+        self.registers.EDS[bank].write(1 << bit);
+    }
+}
+
+/// The number of pin banks. See `GpioBank`.
+pub const BANKS: u8 = 2;
+
+/// A bank of up to 32 GPIO pins driven or read together: bank 0 holds pins 0
+/// to 31 and bank 1 pins 32 to `MAX_PIN`. Bit `n` of a mask is pin
+/// `32 * bank + n`; bits for pins that don't exist are ignored.
+///
+/// A `GpioBank` doesn't change pin functions. Set pins up as outputs or
+/// inputs with `Gpio` first; setting or clearing a pin that isn't an output
+/// only takes effect once it becomes one.
+pub struct GpioBank {
+    bank: usize,
+    registers: &'static mut Registers,
+}
+
+impl GpioBank {
+    /// Returns pin bank `bank`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidBank` if `bank` >= `BANKS`.
+    pub fn new(bank: u8) -> Result<GpioBank> {
+        if bank >= BANKS {
+            return Err(Error::InvalidBank(bank));
+        }
+
+        Ok(GpioBank {
+            bank: bank as usize,
+            registers: unsafe { &mut *(GPIO_BASE as *mut Registers) },
+        })
+    }
+
+    /// Returns the mask of pins that exist in this bank.
+    pub fn pins(&self) -> u32 {
+        bank_pins(self.bank)
+    }
 
-        self.registers.LEV[register_index].has_mask(1 << shift)
+    /// Sets every output pin in `mask` with a single `GPSETn` write.
+    pub fn set(&mut self, mask: u32) {
+        self.registers.SET[self.bank].write(mask & self.pins());
     }
+
+    /// Clears every output pin in `mask` with a single `GPCLRn` write.
+    pub fn clear(&mut self, mask: u32) {
+        self.registers.CLR[self.bank].write(mask & self.pins());
+    }
+
+    /// Drives the output pins in `mask` to the matching bits of `levels`:
+    /// pins whose bit is 1 are set and then those whose bit is 0 cleared, so
+    /// the two groups change one write apart.
+    pub fn write(&mut self, mask: u32, levels: u32) {
+        self.set(mask & levels);
+        self.clear(mask & !levels);
+    }
+
+    /// Returns the level of every pin in the bank from a single `GPLEVn`
+    /// read, one bit per pin.
+    pub fn read_levels(&self) -> u32 {
+        self.registers.LEV[self.bank].read() & self.pins()
+    }
+}
+
+/// Returns the mask of pins that exist in bank `bank`.
+pub(crate) fn bank_pins(bank: usize) -> u32 {
+    let first = bank as u32 * BANK_PINS as u32;
+    let count = (MAX_PIN as u32 + 1).saturating_sub(first);
+    if count >= 32 { !0 } else { (1 << count) - 1 }
 }
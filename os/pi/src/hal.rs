@@ -0,0 +1,117 @@
+//! `embedded-hal` trait implementations for the drivers, so that drivers
+//! written against `embedded-hal` can run on the Pi. Each implementation is
+//! only built with the feature of the driver it is for.
+//!
+//! None of these operations can fail, so their error types are `!`.
+
+#[cfg(feature = "gpio")]
+use embedded_hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin, ToggleableOutputPin};
+#[cfg(feature = "timer")]
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+#[cfg(feature = "uart")]
+use embedded_hal::serial;
+#[cfg(feature = "uart")]
+use nb;
+
+#[cfg(feature = "gpio")]
+use gpio::{Gpio, Input, Output};
+#[cfg(feature = "timer")]
+use timer;
+#[cfg(feature = "uart")]
+use uart::MiniUart;
+
+#[cfg(feature = "uart")]
+impl serial::Read<u8> for MiniUart {
+    type Error = !;
+
+    fn read(&mut self) -> nb::Result<u8, !> {
+        self.try_read_byte().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+#[cfg(feature = "uart")]
+impl serial::Write<u8> for MiniUart {
+    type Error = !;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), !> {
+        self.try_write_byte(byte).map_err(|_| nb::Error::WouldBlock)
+    }
+
+    /// Completes once every byte written so far has been transmitted.
+    fn flush(&mut self) -> nb::Result<(), !> {
+        if self.is_idle() { Ok(()) } else { Err(nb::Error::WouldBlock) }
+    }
+}
+
+#[cfg(feature = "gpio")]
+impl OutputPin for Gpio<Output> {
+    type Error = !;
+
+    fn set_low(&mut self) -> Result<(), !> {
+        self.clear();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), !> {
+        self.set();
+        Ok(())
+    }
+}
+
+/// The level is read back from the pin, as with `Gpio::is_set()`.
+#[cfg(feature = "gpio")]
+impl StatefulOutputPin for Gpio<Output> {
+    fn is_set_high(&self) -> Result<bool, !> {
+        Ok(self.is_set())
+    }
+
+    fn is_set_low(&self) -> Result<bool, !> {
+        Ok(!self.is_set())
+    }
+}
+
+#[cfg(feature = "gpio")]
+impl ToggleableOutputPin for Gpio<Output> {
+    type Error = !;
+
+    fn toggle(&mut self) -> Result<(), !> {
+        Gpio::toggle(self);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "gpio")]
+impl InputPin for Gpio<Input> {
+    type Error = !;
+
+    fn is_high(&self) -> Result<bool, !> {
+        Ok(self.level())
+    }
+
+    fn is_low(&self) -> Result<bool, !> {
+        Ok(!self.level())
+    }
+}
+
+/// Delays that spin on the system timer.
+#[cfg(feature = "timer")]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Delay;
+
+#[cfg(feature = "timer")]
+macro delays($($t:ty),*) {
+    $(impl DelayUs<$t> for Delay {
+        fn delay_us(&mut self, us: $t) {
+            timer::spin_sleep_us(us as u64);
+        }
+    }
+
+    impl DelayMs<$t> for Delay {
+        fn delay_ms(&mut self, ms: $t) {
+            timer::spin_sleep_ms(ms as u64);
+        }
+    })*
+}
+
+#[cfg(feature = "timer")]
+delays!(u8, u16, u32, u64);
@@ -0,0 +1,76 @@
+use common::{IO_BASE, register_layout};
+use volatile::prelude::*;
+use volatile::{Volatile, ReadVolatile};
+
+/// The base address of the interrupt controller's registers.
+const INT_BASE: usize = IO_BASE + 0xB200;
+
+/// A peripheral interrupt, numbered as in the BCM2837 documentation's IRQ
+/// table. IRQs 0 to 31 are in bank 0 of the pending, enable and disable
+/// registers and 32 to 63 in bank 1.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Interrupt {
+    Timer1 = 1,
+    Timer3 = 3,
+    Usb = 9,
+    Aux = 29,
+    Gpio0 = 49,
+    Gpio1 = 50,
+    Gpio2 = 51,
+    Gpio3 = 52,
+    Uart = 57,
+}
+
+impl Interrupt {
+    /// Returns the register bank holding this interrupt's bit and the bit's
+    /// position in it.
+    pub fn bank(&self) -> (usize, u32) {
+        let irq = *self as u32;
+        ((irq / 32) as usize, irq % 32)
+    }
+}
+
+register_layout! {
+    struct Registers(0x28) {
+        0x00 => IRQ_BASIC_PENDING: ReadVolatile<u32>,
+        0x04 => IRQ_PENDING: [ReadVolatile<u32>; 2],
+        0x0C => FIQ_CONTROL: Volatile<u32>,
+        0x10 => ENABLE_IRQS: [Volatile<u32>; 2],
+        0x18 => ENABLE_BASIC_IRQS: Volatile<u32>,
+        0x1C => DISABLE_IRQS: [Volatile<u32>; 2],
+        0x24 => DISABLE_BASIC_IRQS: Volatile<u32>,
+    }
+}
+
+/// An interrupt controller. Used to enable and disable interrupts as well as
+/// to detect which interrupts are pending.
+pub struct Controller {
+    registers: &'static mut Registers
+}
+
+impl Controller {
+    /// Returns a new handle to the interrupt controller.
+    pub fn new() -> Controller {
+        Controller {
+            registers: unsafe { &mut *(INT_BASE as *mut Registers) },
+        }
+    }
+
+    /// Enables the interrupt `int`. Other interrupts are unaffected.
+    pub fn enable(&mut self, int: Interrupt) {
+        let (bank, bit) = int.bank();
+        self.registers.ENABLE_IRQS[bank].write(1 << bit);
+    }
+
+    /// Disables the interrupt `int`. Other interrupts are unaffected.
+    pub fn disable(&mut self, int: Interrupt) {
+        let (bank, bit) = int.bank();
+        self.registers.DISABLE_IRQS[bank].write(1 << bit);
+    }
+
+    /// Returns `true` if `int` is pending. Returns `false` otherwise.
+    pub fn is_pending(&self, int: Interrupt) -> bool {
+        let (bank, bit) = int.bank();
+        self.registers.IRQ_PENDING[bank].read_field(bit, 1) == 1
+    }
+}
@@ -0,0 +1,128 @@
+use gpio::{Gpio, Output};
+use error::Result;
+
+/// One step of a blink pattern: the LED level and how long to hold it, in
+/// microseconds.
+pub type Step = (bool, u64);
+
+/// A repeating blink pattern and the position within it.
+///
+/// The pattern is advanced with `tick(now)`, which returns the level the LED
+/// should be set to whenever that level changes. Nothing about a `Pattern`
+/// depends on the hardware, so it can be advanced from a timer interrupt or
+/// cooperatively from a polling loop.
+pub struct Pattern {
+    steps: &'static [Step],
+    step: usize,
+    deadline: Option<u64>,
+}
+
+impl Pattern {
+    /// Returns a pattern cycling through `steps` that starts on the first
+    /// `tick()`. An empty pattern never changes the LED's level.
+    pub fn new(steps: &'static [Step]) -> Pattern {
+        Pattern { steps, step: 0, deadline: None }
+    }
+
+    /// Returns the steps this pattern cycles through.
+    pub fn steps(&self) -> &'static [Step] {
+        self.steps
+    }
+
+    /// Returns the length of one full repetition of the pattern in
+    /// microseconds.
+    pub fn period(&self) -> u64 {
+        self.steps.iter().fold(0, |period: u64, &(_, duration)| period.saturating_add(duration))
+    }
+
+    /// Advances the pattern to time `now` in microseconds. Returns `Some` of
+    /// the new LED level if the level should change and `None` otherwise.
+    ///
+    /// If `now` is more than a full period past the current step's deadline,
+    /// the pattern restarts at `now`.
+    pub fn tick(&mut self, now: u64) -> Option<bool> {
+        if self.steps.is_empty() {
+            return None;
+        }
+
+        match self.deadline {
+            // A deadline that saturated at the end of time never passes.
+            Some(deadline) if now < deadline || deadline == u64::max_value() => return None,
+            Some(deadline) if now - deadline < self.period() => {
+                let mut deadline = deadline;
+                while now >= deadline && deadline != u64::max_value() {
+                    self.step = (self.step + 1) % self.steps.len();
+                    deadline = deadline.saturating_add(self.steps[self.step].1);
+                }
+
+                self.deadline = Some(deadline);
+            }
+            _ => {
+                self.step = 0;
+                self.deadline = Some(now.saturating_add(self.steps[0].1));
+            }
+        }
+
+        Some(self.steps[self.step].0)
+    }
+}
+
+/// An LED on a GPIO output pin that is either held at a level or plays a
+/// blink `Pattern`.
+pub struct Led {
+    gpio: Gpio<Output>,
+    pattern: Option<Pattern>,
+}
+
+impl Led {
+    /// Returns an LED driven by GPIO pin `pin`, initially off.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidPin` if `pin` isn't a GPIO pin.
+    pub fn new(pin: u8) -> Result<Led> {
+        let mut led = Led { gpio: Gpio::new(pin)?.into_output(), pattern: None };
+        led.off();
+        Ok(led)
+    }
+
+    /// Stops any pattern and turns the LED on.
+    pub fn on(&mut self) {
+        self.pattern = None;
+        self.gpio.set();
+    }
+
+    /// Stops any pattern and turns the LED off.
+    pub fn off(&mut self) {
+        self.pattern = None;
+        self.gpio.clear();
+    }
+
+    /// Starts playing the blink pattern `steps`. If `steps` is already
+    /// playing, it continues where it is rather than restarting.
+    ///
+    /// The pattern only advances when `tick()` is called.
+    pub fn pattern(&mut self, steps: &'static [Step]) {
+        let playing = match self.pattern {
+            Some(ref pattern) => pattern.steps().as_ptr() == steps.as_ptr()
+                && pattern.steps().len() == steps.len(),
+            None => false,
+        };
+
+        if !playing {
+            self.pattern = Some(Pattern::new(steps));
+        }
+    }
+
+    /// Advances the current pattern, if any, to time `now` in microseconds.
+    pub fn tick(&mut self, now: u64) {
+        let level = match self.pattern {
+            Some(ref mut pattern) => pattern.tick(now),
+            None => None,
+        };
+
+        if let Some(level) = level {
+            self.gpio.write(level);
+        }
+    }
+}
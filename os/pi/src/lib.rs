@@ -6,13 +6,60 @@
 #![feature(attr_literals)]
 #![feature(never_type)]
 
-#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 
-#[cfg(feature = "std")]
+// Drivers report bad input through `Error` rather than panicking.
+#![cfg_attr(all(feature = "cargo-clippy", not(test)), deny(option_unwrap_used, result_unwrap_used))]
+
+#[cfg(any(test, feature = "std"))]
 extern crate core;
 extern crate volatile;
+#[cfg(feature = "hal")]
+extern crate embedded_hal;
+#[cfg(feature = "hal")]
+extern crate nb;
+
+#[cfg(test)]
+mod tests;
+mod error;
 
+pub mod common;
+pub mod quiesce;
+pub mod ring;
+#[cfg(feature = "timer")]
 pub mod timer;
+#[cfg(feature = "timer")]
+pub mod cycles;
+#[cfg(feature = "uart")]
 pub mod uart;
+#[cfg(feature = "uart")]
+pub mod pl011;
+#[cfg(feature = "gpio")]
 pub mod gpio;
-pub mod common;
+#[cfg(feature = "pm")]
+pub mod pm;
+#[cfg(feature = "interrupt")]
+pub mod interrupt;
+#[cfg(feature = "led")]
+pub mod led;
+#[cfg(feature = "dma")]
+pub mod dma;
+#[cfg(feature = "soft_i2c")]
+pub mod soft_i2c;
+#[cfg(feature = "soft_spi")]
+pub mod soft_spi;
+#[cfg(feature = "generic_timer")]
+pub mod generic_timer;
+#[cfg(feature = "mailbox")]
+pub mod mailbox;
+#[cfg(feature = "framebuffer")]
+pub mod framebuffer;
+#[cfg(feature = "atags")]
+pub mod atags;
+#[cfg(feature = "emmc")]
+pub mod emmc;
+#[cfg(feature = "hal")]
+pub mod hal;
+
+pub use error::{Error, Result, WouldBlock};
+pub use quiesce::quiesce;
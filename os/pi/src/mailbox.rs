@@ -0,0 +1,163 @@
+//! The VideoCore mailbox: how the ARM asks the GPU's firmware for the things
+//! the firmware manages, like the framebuffer.
+//!
+//! Requests use the property interface. A `Message` holds a sequence of tags,
+//! each asking for or setting one value, and `call()` hands it to the
+//! firmware, which writes its answers over the request in place.
+//!
+//! ```rust,ignore
+//! let mut message = Message::new();
+//! let at = message.push(TAG_GET_ARM_MEMORY, &[], 2);
+//! mailbox::call(&mut message)?;
+//! let (base, size) = (message.value(at), message.value(at + 1));
+//! ```
+
+use common::{IO_BASE, register_layout};
+use error::{Error, Result};
+use volatile::prelude::*;
+use volatile::{ReadVolatile, Reserved, Volatile, WriteVolatile};
+
+/// The base address of the mailbox registers.
+const MAILBOX_BASE: usize = IO_BASE + 0xB880;
+
+/// The VideoCore bus alias of ARM physical memory that bypasses the L2 cache.
+const BUS_UNCACHED_MEMORY: u32 = 0xC0000000;
+
+/// `STATUS` bits: the mailbox to the firmware is full, and the mailbox from
+/// it is empty.
+const STATUS_FULL: u32 = 1 << 31;
+const STATUS_EMPTY: u32 = 1 << 30;
+
+/// The channel property messages are exchanged on.
+pub const PROPERTY_CHANNEL: u8 = 8;
+
+/// Message codes: a request, and a response to a request that succeeded.
+const CODE_REQUEST: u32 = 0;
+const CODE_SUCCESS: u32 = 0x80000000;
+
+/// The number of words a `Message` can hold, headers included.
+pub const MESSAGE_WORDS: usize = 64;
+
+/// Tag: returns the base address and size of the ARM's share of memory.
+pub const TAG_GET_ARM_MEMORY: u32 = 0x00010005;
+
+/// Tag: sets the level of a pin on the firmware's GPIO expander, given its
+/// number (from 128) and the level.
+pub const TAG_SET_GPIO_STATE: u32 = 0x00038041;
+
+register_layout! {
+    struct Registers(0x24) {
+        0x00 => READ: ReadVolatile<u32>,
+        0x04 => __r0: [Reserved<u32>; 3],
+        0x10 => PEEK: ReadVolatile<u32>,
+        0x14 => SENDER: ReadVolatile<u32>,
+        0x18 => STATUS: ReadVolatile<u32>,
+        0x1C => CONFIG: Volatile<u32>,
+        0x20 => WRITE: WriteVolatile<u32>,
+    }
+}
+
+/// A property message. The firmware reads it from memory, so it is aligned
+/// as the mailbox requires: the low four bits of its address carry the
+/// channel.
+#[repr(C, align(16))]
+pub struct Message {
+    words: [u32; MESSAGE_WORDS],
+    /// The number of words used, not counting the end tag.
+    len: usize,
+}
+
+impl Message {
+    /// Returns a request with no tags.
+    pub fn new() -> Message {
+        let mut words = [0; MESSAGE_WORDS];
+        words[1] = CODE_REQUEST;
+        Message { words, len: 2 }
+    }
+
+    /// Appends the tag `tag` with the request values `values` and room for a
+    /// response of `response_words` words. Returns the index of the tag's
+    /// first value, where its response can be read with `value()` once the
+    /// message has been sent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag doesn't fit in the message.
+    pub fn push(&mut self, tag: u32, values: &[u32], response_words: usize) -> usize {
+        let size = values.len().max(response_words);
+        assert!(self.len + 3 + size + 1 <= MESSAGE_WORDS, "mailbox message is full");
+
+        let at = self.len + 3;
+        self.words[self.len] = tag;
+        self.words[self.len + 1] = (size * 4) as u32;
+        self.words[self.len + 2] = 0;
+        self.words[at..at + values.len()].copy_from_slice(values);
+        for word in self.words[at + values.len()..at + size].iter_mut() {
+            *word = 0;
+        }
+
+        self.len = at + size;
+        at
+    }
+
+    /// Returns the word at index `at`.
+    pub fn value(&self, at: usize) -> u32 {
+        self.words[at]
+    }
+
+    /// Sets the word at index `at`, as the firmware does when it responds.
+    #[cfg(test)]
+    pub(crate) fn set_value(&mut self, at: usize, value: u32) {
+        self.words[at] = value;
+    }
+
+    /// Returns the message as sent: the header, the tags and the end tag.
+    pub fn words(&self) -> &[u32] {
+        &self.words[..self.len + 1]
+    }
+
+    /// Returns `true` if the firmware processed every tag.
+    pub fn succeeded(&self) -> bool {
+        self.words[1] == CODE_SUCCESS
+    }
+
+    /// Writes the total size and the end tag, and marks the message as a
+    /// request.
+    fn finish(&mut self) {
+        self.words[0] = ((self.len + 1) * 4) as u32;
+        self.words[1] = CODE_REQUEST;
+        self.words[self.len] = 0;
+    }
+}
+
+/// Sends `message` to the firmware on the property channel and waits for the
+/// response, which replaces the request in `message`.
+///
+/// # Errors
+///
+/// Returns `Error::MailboxFailed` if the firmware couldn't process the
+/// message.
+pub fn call(message: &mut Message) -> Result<()> {
+    message.finish();
+
+    let registers = unsafe { &mut *(MAILBOX_BASE as *mut Registers) };
+    let address = message.words.as_ptr() as usize as u32 | BUS_UNCACHED_MEMORY;
+    let request = address | PROPERTY_CHANNEL as u32;
+
+    while registers.STATUS.has_mask(STATUS_FULL) {  }
+    registers.WRITE.write(request);
+
+    // Responses to other channels' requests are skipped.
+    loop {
+        while registers.STATUS.has_mask(STATUS_EMPTY) {  }
+        if registers.READ.read() == request {
+            break;
+        }
+    }
+
+    if message.succeeded() {
+        Ok(())
+    } else {
+        Err(Error::MailboxFailed)
+    }
+}
@@ -0,0 +1,485 @@
+use core::fmt;
+use core::time::Duration;
+
+use volatile::prelude::*;
+use volatile::{Volatile, ReadVolatile, WriteVolatile, Reserved};
+
+use timer::{self, Deadline};
+use common::{IO_BASE, register_layout};
+use gpio::{Gpio, Function, TXD0, RXD0};
+use quiesce;
+use uart::{DataBits, StopBits};
+use error::{Error, Result};
+#[cfg(feature = "dma")]
+use dma::{Channel, ControlBlock};
+
+/// The base address of the PL011 (`UART0`) registers.
+const UART0_BASE: usize = IO_BASE + 0x201000;
+
+/// The clock the PL011's baud rate is derived from, in Hz. This is the
+/// firmware's default `init_uart_clock`; a `config.txt` that changes it must
+/// change this too.
+const UART_CLOCK: u32 = 48_000_000;
+
+/// The divisors producing `DEFAULT_BAUD`: `baud_divisor(DEFAULT_BAUD)`.
+const DEFAULT_DIVISOR: (u16, u8) = (26, 3);
+
+/// `UART_DR` field holding the byte read or written.
+const DR_DATA: (u32, u32) = (0, 8);
+
+/// `UART_IBRD` and `UART_FBRD` fields holding the integer and fractional
+/// parts of the baud rate divisor.
+const IBRD_DIVISOR: (u32, u32) = (0, 16);
+const FBRD_DIVISOR: (u32, u32) = (0, 6);
+
+/// `UART_LCRH` bit holding the transmit line low.
+const LCRH_BRK: u32 = 1;
+
+/// `UART_LCRH` bits: two stop bits, FIFOs enabled, and the word length field.
+const LCRH_STP2: u32 = 1 << 3;
+const LCRH_FEN: u32 = 1 << 4;
+const LCRH_WLEN: (u32, u32) = (5, 2);
+
+/// `UART_CR` bits enabling the UART, its transmitter and its receiver.
+const CR_UARTEN: u32 = 1;
+const CR_TXE: u32 = 1 << 8;
+const CR_RXE: u32 = 1 << 9;
+
+/// `UART_CR` bit feeding the transmitter straight into the receiver.
+const CR_LBE: u32 = 1 << 7;
+
+/// The bytes `self_test()` sends: alternating bits both ways, all zeroes, all
+/// ones, and the line ending the console sends.
+const SELF_TEST_PATTERN: [u8; 6] = [0x55, 0xAA, 0x00, 0xFF, b'\r', b'\n'];
+
+/// How long `self_test()` waits for each byte to come back, in microseconds.
+/// A byte takes about a millisecond at 9600 baud.
+const SELF_TEST_TIMEOUT_US: u64 = 10_000;
+
+/// `UART_RIS` and `UART_ICR` bit for the break error interrupt, which is
+/// raised whenever a break is received whether or not it's masked.
+const INT_BREAK: u32 = 1 << 9;
+
+/// `UART_DMACR` bit letting the transmit FIFO request data from the DMA
+/// controller.
+#[cfg(feature = "dma")]
+const DMACR_TXDMAE: u32 = 1 << 1;
+
+/// The DREQ number of the PL011's transmit FIFO.
+#[cfg(feature = "dma")]
+const DREQ_UART_TX: u8 = 12;
+
+/// Value for `UART_ICR` clearing every interrupt.
+const ICR_CLEAR_ALL: u32 = 0x7FF;
+
+/// Bit fields of the `UART_FR` register.
+#[repr(u8)]
+enum Flag {
+    Busy = 1 << 3,
+    RxEmpty = 1 << 4,
+    TxFull = 1 << 5,
+}
+
+register_layout! {
+    struct Registers(0x4C) {
+        0x00 => DR: Volatile<u32>, // Data.
+        0x04 => RSRECR: Volatile<u32>, // Receive status and error clear.
+        0x08 => __r0: [Reserved<u32>; 4],
+        0x18 => FR: ReadVolatile<u32>, // Flags.
+        0x1C => __r1: Reserved<u32>,
+        0x20 => ILPR: Reserved<u32>, // IrDA, not used.
+        0x24 => IBRD: Volatile<u32>, // Integer baud rate divisor.
+        0x28 => FBRD: Volatile<u32>, // Fractional baud rate divisor.
+        0x2C => LCRH: Volatile<u32>, // Line control.
+        0x30 => CR: Volatile<u32>, // Control.
+        0x34 => IFLS: Volatile<u32>, // Interrupt FIFO level select.
+        0x38 => IMSC: Volatile<u32>, // Interrupt mask set and clear.
+        0x3C => RIS: ReadVolatile<u32>, // Raw interrupt status.
+        0x40 => MIS: ReadVolatile<u32>, // Masked interrupt status.
+        0x44 => ICR: WriteVolatile<u32>, // Interrupt clear.
+        0x48 => DMACR: Volatile<u32>, // DMA control.
+    }
+}
+
+/// The Raspberry Pi's PL011 UART, `UART0`.
+///
+/// On the Pi 3 `UART0` drives the Bluetooth module unless `config.txt`
+/// disables Bluetooth (`dtoverlay=pi3-disable-bt`). Only then can it carry
+/// the console on GPIO pins 14 and 15.
+pub struct Pl011 {
+    registers: &'static mut Registers,
+    timeout: Option<u32>,
+    #[cfg(feature = "dma")]
+    dma: Option<Channel>,
+}
+
+impl Pl011 {
+    /// Initializes `UART0` for 8 data bits, one stop bit and no parity at
+    /// `DEFAULT_BAUD`, with both FIFOs enabled, and sets GPIO pins 14 and 15
+    /// to alternative function 0 (TXD0/RXD0).
+    ///
+    /// By default, reads will never time out. To set a read timeout, use
+    /// `set_read_timeout()`.
+    pub fn new() -> Pl011 {
+        Pl011::init(DEFAULT_DIVISOR, DataBits::Eight, StopBits::One)
+    }
+
+    /// Initializes `UART0` like `new()`, but at the rate nearest `baud` (see
+    /// `baud_divisor()`) with `data_bits` data bits and `stop_bits` stop bits.
+    /// Nothing is changed if an error is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidBaud` if the PL011 can't produce `baud`.
+    pub fn with_config(baud: u32, data_bits: DataBits, stop_bits: StopBits) -> Result<Pl011> {
+        let divisor = baud_divisor(baud)?;
+        Ok(Pl011::init(divisor, data_bits, stop_bits))
+    }
+
+    /// Initializes `UART0` with the integer and fractional baud rate divisors
+    /// `divisor`. See `new()`.
+    fn init(divisor: (u16, u8), data_bits: DataBits, stop_bits: StopBits) -> Pl011 {
+        let registers = unsafe { &mut *(UART0_BASE as *mut Registers) };
+
+        // The line settings may only change while the UART is disabled and
+        // idle. Disabling the FIFOs flushes them.
+        registers.CR.write(0);
+        while registers.FR.has_mask(Flag::Busy as u32) {
+            continue
+        }
+        registers.LCRH.write(0);
+
+        registers.ICR.write(ICR_CLEAR_ALL);
+        registers.IMSC.write(0);
+        registers.IBRD.write_field(IBRD_DIVISOR.0, IBRD_DIVISOR.1, divisor.0 as u32);
+        registers.FBRD.write_field(FBRD_DIVISOR.0, FBRD_DIVISOR.1, divisor.1 as u32);
+
+        // `LCRH` must be written after the divisors for them to take effect.
+        let mut lcrh = LCRH_FEN;
+        if stop_bits == StopBits::Two {
+            lcrh |= LCRH_STP2;
+        }
+        registers.LCRH.write(lcrh);
+        registers.LCRH.write_field(LCRH_WLEN.0, LCRH_WLEN.1, word_length(data_bits));
+
+        unsafe {
+            Gpio::new_unchecked(TXD0).into_alt(Function::Alt0);
+            Gpio::new_unchecked(RXD0).into_alt(Function::Alt0);
+        }
+
+        registers.CR.write(CR_UARTEN | CR_TXE | CR_RXE);
+
+        // If the hook table is full, the UART is simply left running.
+        let _ = quiesce::register(shutdown);
+
+        Pl011 {
+            registers,
+            timeout: None,
+            #[cfg(feature = "dma")]
+            dma: None,
+        }
+    }
+
+    /// Set the read timeout to `milliseconds` milliseconds.
+    pub fn set_read_timeout(&mut self, milliseconds: u32) {
+        self.timeout = Some(milliseconds);
+    }
+
+    /// Blocks until every byte written so far has been transmitted.
+    pub fn wait_for_idle(&self) {
+        while self.registers.FR.has_mask(Flag::Busy as u32) {
+            continue
+        }
+    }
+
+    /// Write the byte `byte`. This method blocks until there is space available
+    /// in the output FIFO.
+    pub fn write_byte(&mut self, byte: u8) {
+        while self.registers.FR.has_mask(Flag::TxFull as u32) {
+            continue
+        }
+
+        // A plain write: reading DR, as a read-modify-write would, pops a
+        // byte from the receive FIFO and latches its error flags.
+        self.registers.DR.write(byte as u32);
+    }
+
+    /// Returns `true` if there is at least one byte ready to be read. If this
+    /// method returns `true`, a subsequent call to `read_byte` is guaranteed to
+    /// return immediately. This method does not block.
+    pub fn has_byte(&self) -> bool {
+        !self.registers.FR.has_mask(Flag::RxEmpty as u32)
+    }
+
+    /// Blocks until there is a byte ready to read. If a read timeout is set,
+    /// this method blocks for at most that amount of time. Otherwise, this
+    /// method blocks indefinitely until there is a byte to read.
+    ///
+    /// Returns `Ok(())` if a byte is ready to read. Returns `Err(Error::Timeout)`
+    /// if the timeout expired while waiting for a byte to be ready.
+    pub fn wait_for_byte(&self) -> Result<()> {
+        let deadline = self.timeout.map(|ms| Deadline::after(Duration::from_millis(ms as u64)));
+
+        while !self.has_byte() {
+            if let Some(ref deadline) = deadline {
+                if deadline.expired() {
+                    return Err(Error::Timeout);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Hands DMA channel `channel` to the UART for `write_all_dma()` to use,
+    /// returning the channel it had before, if any.
+    #[cfg(feature = "dma")]
+    pub fn attach_dma(&mut self, channel: Channel) -> Option<Channel> {
+        self.dma.replace(channel)
+    }
+
+    /// Writes all of `buf`. If a DMA channel has been attached with
+    /// `attach_dma()`, the DMA controller feeds the transmit FIFO while the
+    /// CPU spins until it has finished; otherwise, or without the `dma`
+    /// feature, this is `write_byte()` in a loop. Either way the caller is
+    /// stalled for as long as sending `buf` takes. Unlike `fmt::Write`, no
+    /// `\r` is added before `\n`.
+    ///
+    /// The kernel doesn't use this: its console is on the mini UART, which
+    /// has no DREQ line to pace the DMA controller by.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::DmaFailed` if the DMA controller reports an error. How
+    /// much of `buf` was sent is then unknown.
+    pub fn write_all_dma(&mut self, buf: &[u8]) -> Result<()> {
+        #[cfg(feature = "dma")]
+        {
+            if let Some(ref mut channel) = self.dma {
+                let block = ControlBlock::to_peripheral(buf, &self.registers.DR as *const _ as usize,
+                                                        DREQ_UART_TX);
+                self.registers.DMACR.set_bits(DMACR_TXDMAE);
+                let result = channel.run(&block);
+                self.registers.DMACR.clear_bits(DMACR_TXDMAE);
+                return result;
+            }
+        }
+
+        for &byte in buf {
+            self.write_byte(byte);
+        }
+
+        Ok(())
+    }
+
+    /// Holds the transmit line low for `duration_ms` milliseconds once every
+    /// byte written so far has been sent, signalling a break to the other end.
+    pub fn send_break(&mut self, duration_ms: u64) {
+        self.wait_for_idle();
+        self.registers.LCRH.set_bits(LCRH_BRK);
+        timer::spin_sleep_ms(duration_ms);
+        self.registers.LCRH.clear_bits(LCRH_BRK);
+    }
+
+    /// Returns `true` if a break has been received since the last call. A
+    /// break also leaves a `0x00` byte in the receive FIFO, so a reader that
+    /// sees `0x00` can call this to tell the two apart.
+    pub fn take_break(&mut self) -> bool {
+        let received = self.registers.RIS.has_mask(INT_BREAK);
+        if received {
+            self.registers.ICR.write(INT_BREAK);
+        }
+
+        received
+    }
+
+    /// Connects the transmitter to the receiver inside the UART: bytes
+    /// written are read back instead of going out on TXD0, and RXD0 is
+    /// ignored.
+    pub fn enable_loopback(&mut self) {
+        self.wait_for_idle();
+        self.registers.CR.set_bits(CR_LBE);
+    }
+
+    /// Undoes `enable_loopback()`.
+    pub fn disable_loopback(&mut self) {
+        self.wait_for_idle();
+        self.registers.CR.clear_bits(CR_LBE);
+    }
+
+    /// Checks the UART itself by sending a fixed pattern in loopback mode and
+    /// reading it back. Any unread input is discarded first. Loopback is left
+    /// as it was found.
+    ///
+    /// Because the pattern never leaves the chip, a pass says nothing about
+    /// the wiring; a failure means the UART or its configuration is broken.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::LoopbackMismatch` if a byte comes back changed, and
+    /// `Error::Timeout` if one doesn't come back at all.
+    pub fn self_test(&mut self) -> Result<()> {
+        let looped = self.registers.CR.has_mask(CR_LBE);
+        self.enable_loopback();
+        while self.has_byte() {
+            self.read_byte();
+        }
+
+        let result = check_echo(&SELF_TEST_PATTERN, |byte| {
+            self.write_byte(byte);
+            let deadline = Deadline::after(timer::from_micros(SELF_TEST_TIMEOUT_US));
+            while !self.has_byte() {
+                if deadline.expired() {
+                    return None;
+                }
+            }
+
+            Some(self.read_byte())
+        });
+
+        if !looped {
+            self.disable_loopback();
+        }
+
+        result
+    }
+
+    /// Reads a byte. Blocks indefinitely until a byte is ready to be read.
+    /// Framing, parity and overrun errors are discarded with the byte's
+    /// error bits; a received break reads as `0x00`. See `take_break()`.
+    pub fn read_byte(&mut self) -> u8 {
+        while !self.has_byte() {
+            continue
+        }
+
+        self.registers.DR.read_field(DR_DATA.0, DR_DATA.1) as u8
+    }
+}
+
+/// Sends each byte of `pattern` through `echo`, which returns the byte that
+/// came back or `None` if none did, and checks that it came back unchanged.
+///
+/// # Errors
+///
+/// Returns `Error::LoopbackMismatch` for the first byte that came back
+/// changed, and `Error::Timeout` for the first that didn't come back.
+pub(crate) fn check_echo<F: FnMut(u8) -> Option<u8>>(pattern: &[u8], mut echo: F) -> Result<()> {
+    for &sent in pattern {
+        match echo(sent) {
+            Some(received) if received == sent => continue,
+            Some(received) => return Err(Error::LoopbackMismatch(sent, received)),
+            None => return Err(Error::Timeout),
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the `UART_LCRH` word length field for `data_bits`.
+fn word_length(data_bits: DataBits) -> u32 {
+    match data_bits {
+        DataBits::Seven => 0b10,
+        DataBits::Eight => 0b11,
+    }
+}
+
+/// Shutdown hook registered by `Pl011::new()`. See `shutdown_registers()`.
+fn shutdown() {
+    shutdown_registers(unsafe { &mut *(UART0_BASE as *mut Registers) });
+}
+
+/// Waits for the UART to finish sending if it's enabled, then disables it,
+/// masks and clears its interrupts, and flushes its FIFOs. The line settings
+/// and baud rate are left as they are.
+pub(crate) fn shutdown_registers(registers: &mut Registers) {
+    if registers.CR.has_mask(CR_UARTEN) {
+        while registers.FR.has_mask(Flag::Busy as u32) {
+            continue
+        }
+    }
+
+    registers.CR.write(0);
+    registers.IMSC.write(0);
+    registers.ICR.write(ICR_CLEAR_ALL);
+    registers.LCRH.clear_bits(LCRH_FEN);
+}
+
+/// Returns the integer and fractional divisors for `UART_IBRD` and
+/// `UART_FBRD` giving the rate nearest `baud`: the PL011 runs at
+/// `UART_CLOCK / (16 * (ibrd + fbrd / 64))` baud.
+///
+/// # Errors
+///
+/// Returns `Error::InvalidBaud` if `baud` is zero, faster than the PL011 can
+/// run, or too slow for a 16-bit integer divisor.
+pub fn baud_divisor(baud: u32) -> Result<(u16, u8)> {
+    if baud == 0 {
+        return Err(Error::InvalidBaud(baud));
+    }
+
+    // The divisor in 64ths, rounded to the nearest.
+    let sixty_fourths = (UART_CLOCK as u64 * 4 + baud as u64 / 2) / baud as u64;
+    let integer = sixty_fourths >> 6;
+    if integer == 0 || integer > u16::max_value() as u64 {
+        return Err(Error::InvalidBaud(baud));
+    }
+
+    Ok((integer as u16, (sixty_fourths & 0x3F) as u8))
+}
+
+impl fmt::Write for Pl011 {
+    /// Writes a string to the UART. For any \n character, a \r is
+    /// automatically written preceding it.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &byte in s.as_bytes() {
+            if byte == b'\n' {
+                self.write_byte(b'\r');
+            }
+
+            self.write_byte(byte);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+mod pl011_io {
+    use std::io;
+    use super::Pl011;
+
+    impl io::Read for Pl011 {
+        /// Waits up to the read timeout for the first byte, then reads as many
+        /// bytes as are ready, up to `buf.len()`.
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.wait_for_byte().is_err() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "Timeout waiting for data"));
+            }
+
+            let mut bytes_read: usize = 0;
+            while self.has_byte() && bytes_read < buf.len() {
+                buf[bytes_read] = self.read_byte();
+                bytes_read += 1;
+            }
+
+            Ok(bytes_read)
+        }
+    }
+
+    impl io::Write for Pl011 {
+        /// Writes all of `buf` to the transmit FIFO.
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for &byte in buf {
+                self.write_byte(byte);
+            }
+
+            Ok(buf.len())
+        }
+
+        /// Waits for the transmit FIFO to drain.
+        fn flush(&mut self) -> io::Result<()> {
+            self.wait_for_idle();
+            Ok(())
+        }
+    }
+}
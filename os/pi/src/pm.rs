@@ -0,0 +1,146 @@
+use core::time::Duration;
+
+use common::{IO_BASE, register_layout};
+use quiesce;
+use volatile::prelude::*;
+use volatile::{Volatile, Reserved};
+
+/// The base address for the power management (`PM`) registers.
+const PM_REG_BASE: usize = IO_BASE + 0x100000;
+
+/// Every write to a `PM` register must include this password.
+const PM_PASSWORD: u32 = 0x5a000000;
+
+/// Mask clearing the `WRCFG` field of `RSTC`.
+const PM_RSTC_WRCFG_CLR: u32 = 0xffffffcf;
+
+/// `WRCFG` value requesting a full reset when the watchdog expires.
+const PM_RSTC_WRCFG_FULL_RESET: u32 = 0x00000020;
+
+/// `RSTC` value that stops a running watchdog.
+const PM_RSTC_RESET: u32 = 0x00000102;
+
+/// The `WDOG` counter is 20 bits wide.
+const PM_WDOG_TIME_MASK: u32 = 0x000fffff;
+
+/// The `WDOG` counter ticks at 65536 Hz.
+const PM_WDOG_TICKS_PER_SEC: u64 = 65536;
+
+/// The watchdog timeout `reboot()` uses, in ticks: long enough for the
+/// register writes to land.
+const PM_REBOOT_TICKS: u32 = 10;
+
+register_layout! {
+    struct Registers(0x28) {
+        0x00 => __r0: [Reserved<u32>; 7],
+        0x1C => RSTC: Volatile<u32>,
+        0x20 => RSTS: Volatile<u32>,
+        0x24 => WDOG: Volatile<u32>,
+    }
+}
+
+/// Converts `ms` milliseconds to watchdog ticks, saturating at the largest
+/// value the `WDOG` counter can hold.
+pub fn ms_to_ticks(ms: u32) -> u32 {
+    let ticks = (ms as u64 * PM_WDOG_TICKS_PER_SEC) / 1000;
+    if ticks > PM_WDOG_TIME_MASK as u64 {
+        PM_WDOG_TIME_MASK
+    } else {
+        ticks as u32
+    }
+}
+
+/// Converts `ticks` watchdog ticks to milliseconds.
+pub fn ticks_to_ms(ticks: u32) -> u32 {
+    ((ticks as u64 * 1000) / PM_WDOG_TICKS_PER_SEC) as u32
+}
+
+/// Loads `ticks` into the `WDOG` counter in `registers` and configures the
+/// watchdog to reset the board when it reaches zero.
+pub(crate) fn arm_registers(registers: &mut Registers, ticks: u32) {
+    registers.WDOG.write(PM_PASSWORD | (ticks & PM_WDOG_TIME_MASK));
+    let rstc = registers.RSTC.read() & PM_RSTC_WRCFG_CLR;
+    registers.RSTC.write(PM_PASSWORD | rstc | PM_RSTC_WRCFG_FULL_RESET);
+}
+
+/// The Raspberry Pi's hardware watchdog.
+///
+/// Once started, the watchdog resets the board when its timeout expires unless
+/// it is restarted (kicked) or stopped first.
+pub struct Watchdog {
+    registers: &'static mut Registers,
+    timeout_ms: u32,
+}
+
+impl Watchdog {
+    /// The longest timeout the watchdog supports, in milliseconds.
+    pub const MAX_TIMEOUT_MS: u32 = 15999;
+
+    /// Returns a new instance of `Watchdog`.
+    pub fn new() -> Watchdog {
+        Watchdog {
+            registers: unsafe { &mut *(PM_REG_BASE as *mut Registers) },
+            timeout_ms: 0,
+        }
+    }
+
+    /// Starts (or restarts) the watchdog with a timeout of `timeout_ms`
+    /// milliseconds. Timeouts longer than `MAX_TIMEOUT_MS` are truncated.
+    pub fn start(&mut self, timeout_ms: u32) {
+        self.timeout_ms = timeout_ms;
+        arm_registers(self.registers, ms_to_ticks(timeout_ms));
+    }
+
+    /// Restarts the watchdog with the timeout it was last started with.
+    pub fn pet(&mut self) {
+        let timeout_ms = self.timeout_ms;
+        self.start(timeout_ms);
+    }
+
+    /// Stops the watchdog.
+    pub fn stop(&mut self) {
+        self.registers.RSTC.write(PM_PASSWORD | PM_RSTC_RESET);
+    }
+
+    /// Returns the number of milliseconds left before the watchdog expires.
+    pub fn remaining_ms(&self) -> u32 {
+        ticks_to_ms(self.registers.WDOG.read() & PM_WDOG_TIME_MASK)
+    }
+}
+
+/// Starts the watchdog with a timeout of `timeout`, truncated to
+/// `Watchdog::MAX_TIMEOUT_MS`, and returns it so that it can be petted.
+pub fn start_watchdog(timeout: Duration) -> Watchdog {
+    let ms = timeout.as_secs()
+        .saturating_mul(1000)
+        .saturating_add((timeout.subsec_nanos() / 1_000_000) as u64);
+
+    let mut watchdog = Watchdog::new();
+    watchdog.start(if ms > u32::max_value() as u64 { u32::max_value() } else { ms as u32 });
+    watchdog
+}
+
+/// Quiesces the drivers (see `quiesce()`) and resets the board by letting
+/// the watchdog expire almost immediately.
+pub fn reboot() -> ! {
+    quiesce::quiesce();
+    arm_registers(unsafe { &mut *(PM_REG_BASE as *mut Registers) }, PM_REBOOT_TICKS);
+    loop {  }
+}
+
+/// Quiesces the drivers (see `quiesce()`) and stops the calling core in a
+/// low-power wait for interrupt, forever. Only a reset or power cycle
+/// restarts the board, so the watchdog must not be running.
+#[cfg(target_arch = "aarch64")]
+pub fn halt() -> ! {
+    quiesce::quiesce();
+    loop {
+        wait_for_interrupt();
+    }
+}
+
+/// Puts the calling core to sleep until an interrupt arrives.
+#[cfg(target_arch = "aarch64")]
+fn wait_for_interrupt() {
+    unsafe { asm!("wfi" : : : "memory" : "volatile"); }
+}
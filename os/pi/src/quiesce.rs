@@ -0,0 +1,75 @@
+//! Returning peripherals to their power-on state before control leaves the
+//! running binary.
+//!
+//! Drivers register a shutdown hook with `register()` when they are first
+//! initialized. `quiesce()` runs the hooks in the reverse of the order they
+//! were registered, so a driver that was set up on top of another (the mini
+//! UART on top of its GPIO pins) is shut down first. Hooks are kept in a fixed
+//! table, so registration works before any allocator exists.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT};
+
+use error::{Error, Result};
+
+/// A driver shutdown hook.
+pub type Hook = fn();
+
+/// The most hooks that can be registered.
+pub const MAX_HOOKS: usize = 8;
+
+/// The registered hooks. Entries below `COUNT` are initialized.
+static mut HOOKS: [Option<Hook>; MAX_HOOKS] = [None; MAX_HOOKS];
+
+/// The number of registered hooks.
+static COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Set while `quiesce()` is running.
+static RUNNING: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Registers `hook` to be run by `quiesce()`. Registering a hook that is
+/// already registered does nothing.
+///
+/// Drivers register their hooks while the board is brought up on a single
+/// core, so registration isn't synchronized against concurrent registration.
+///
+/// # Errors
+///
+/// Returns `Error::TooManyHooks` if `MAX_HOOKS` hooks are already registered.
+pub fn register(hook: Hook) -> Result<()> {
+    let count = COUNT.load(Ordering::SeqCst);
+    let hooks = unsafe { &mut HOOKS };
+    if hooks[..count].iter().any(|&registered| registered == Some(hook)) {
+        return Ok(());
+    }
+
+    if count == MAX_HOOKS {
+        return Err(Error::TooManyHooks);
+    }
+
+    hooks[count] = Some(hook);
+    COUNT.store(count + 1, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Returns the number of registered hooks.
+pub fn registered() -> usize {
+    COUNT.load(Ordering::SeqCst)
+}
+
+/// Runs every registered hook, most recently registered first. Hooks stay
+/// registered. A hook that calls `quiesce()` itself returns immediately.
+pub fn quiesce() {
+    if RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let count = COUNT.load(Ordering::SeqCst);
+    let hooks = unsafe { &HOOKS };
+    for hook in hooks[..count].iter().rev() {
+        if let Some(hook) = *hook {
+            hook();
+        }
+    }
+
+    RUNNING.store(false, Ordering::SeqCst);
+}
@@ -0,0 +1,89 @@
+//! A fixed-size byte queue shared between an interrupt handler and the code
+//! it interrupts.
+//!
+//! A `RingBuffer` has exactly one producer and one consumer: the producer
+//! only calls `push()` and the consumer only calls `pop()`. Neither side
+//! takes a lock, so the producer may be an IRQ handler that interrupts the
+//! consumer at any point. The storage is inline, so a buffer can live in a
+//! `static` before any allocator exists.
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of bytes a `RingBuffer` holds. A power of two, so the wrapping
+/// counters below stay consistent when they overflow.
+pub const CAPACITY: usize = 256;
+
+/// A single-producer, single-consumer queue of up to `CAPACITY` bytes.
+pub struct RingBuffer {
+    bytes: UnsafeCell<[u8; CAPACITY]>,
+    /// Count of bytes ever pushed. Only the producer writes it.
+    head: AtomicUsize,
+    /// Count of bytes ever popped. Only the consumer writes it.
+    tail: AtomicUsize,
+    /// Count of bytes dropped because the buffer was full.
+    dropped: AtomicUsize,
+}
+
+// The producer only writes slots the consumer has released and the consumer
+// only reads slots the producer has published, so sharing is sound as long
+// as there is one of each.
+unsafe impl Sync for RingBuffer {  }
+
+impl RingBuffer {
+    /// Returns an empty buffer.
+    pub const fn new() -> RingBuffer {
+        RingBuffer {
+            bytes: UnsafeCell::new([0; CAPACITY]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Appends `byte`. Returns `false`, and counts the byte as dropped, if the
+    /// buffer is full. Must only be called by the producer.
+    pub fn push(&self, byte: u8) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head.wrapping_sub(tail) == CAPACITY {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        unsafe { (*self.bytes.get())[head % CAPACITY] = byte; }
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Removes and returns the oldest byte, or `None` if the buffer is empty.
+    /// Must only be called by the consumer.
+    pub fn pop(&self) -> Option<u8> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        let byte = unsafe { (*self.bytes.get())[tail % CAPACITY] };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Some(byte)
+    }
+
+    /// Returns the number of bytes waiting to be popped.
+    pub fn len(&self) -> usize {
+        // Reading `tail` first means `head` can't be seen behind it.
+        let tail = self.tail.load(Ordering::Acquire);
+        self.head.load(Ordering::Acquire).wrapping_sub(tail)
+    }
+
+    /// Returns `true` if there are no bytes waiting to be popped.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of bytes dropped so far because the buffer was full.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,270 @@
+//! An I2C master bit-banged over two GPIO pins.
+//!
+//! Both lines are emulated open-drain (see `Gpio::into_open_drain()`), so
+//! they need pull-ups: the Pi's GPIO 2 and 3 have 1.8kΩ pull-ups on the
+//! board, and other pins can use `Gpio::set_pull(Pull::Up)` for short wires
+//! at low speed. Devices may stretch the clock by holding SCL low.
+
+use gpio::{Gpio, OpenDrain};
+use error::{Error, Result};
+use timer;
+
+/// Half a clock period at the standard-mode 100kHz, in microseconds.
+pub const STANDARD_HALF_PERIOD_US: u64 = 5;
+
+/// How long a device may stretch the clock before the transfer is abandoned,
+/// in microseconds.
+pub const STRETCH_TIMEOUT_US: u64 = 10_000;
+
+/// The largest 7-bit I2C address.
+pub const MAX_ADDRESS: u8 = 0x7F;
+
+/// An open-drain line: pulled low or released, and read back.
+pub trait Line {
+    /// Pulls the line low.
+    fn pull_low(&mut self);
+
+    /// Releases the line so that it floats high unless another device pulls
+    /// it low.
+    fn release(&mut self);
+
+    /// Returns `true` if the line is high.
+    fn is_high(&self) -> bool;
+}
+
+impl Line for Gpio<OpenDrain> {
+    fn pull_low(&mut self) {
+        Gpio::pull_low(self)
+    }
+
+    fn release(&mut self) {
+        Gpio::release(self)
+    }
+
+    fn is_high(&self) -> bool {
+        Gpio::is_high(self)
+    }
+}
+
+/// The clock a `SoftI2c` paces itself with: a microsecond counter and a way
+/// to wait.
+pub struct Clock {
+    /// Returns the current time in microseconds.
+    pub now: fn() -> u64,
+    /// Waits for the given number of microseconds.
+    pub delay: fn(u64),
+}
+
+/// The system timer.
+pub const SYSTEM_CLOCK: Clock = Clock {
+    now: timer::current_time_us,
+    delay: timer::spin_sleep_us,
+};
+
+/// A bit-banged I2C master on lines `scl` and `sda`.
+pub struct SoftI2c<L: Line = Gpio<OpenDrain>> {
+    scl: L,
+    sda: L,
+    clock: Clock,
+    half_period_us: u64,
+}
+
+impl SoftI2c {
+    /// Returns a 100kHz I2C master with SCL on GPIO pin `scl` and SDA on GPIO
+    /// pin `sda`, both released.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidPin` if either pin isn't a GPIO pin.
+    pub fn new(scl: u8, sda: u8) -> Result<SoftI2c> {
+        let scl = Gpio::new(scl)?.into_open_drain();
+        let sda = Gpio::new(sda)?.into_open_drain();
+        Ok(SoftI2c::with_lines(scl, sda, SYSTEM_CLOCK))
+    }
+}
+
+impl<L: Line> SoftI2c<L> {
+    /// Returns a 100kHz I2C master on lines `scl` and `sda` paced by `clock`.
+    /// Both lines are released.
+    pub fn with_lines(mut scl: L, mut sda: L, clock: Clock) -> SoftI2c<L> {
+        scl.release();
+        sda.release();
+        SoftI2c { scl, sda, clock, half_period_us: STANDARD_HALF_PERIOD_US }
+    }
+
+    /// Sets half the clock period to `half_period_us` microseconds. Longer
+    /// periods help with long wires and weak pull-ups.
+    pub fn set_half_period(&mut self, half_period_us: u64) {
+        self.half_period_us = half_period_us;
+    }
+
+    /// Writes `bytes` to the device at 7-bit address `address`.
+    ///
+    /// # Errors
+    ///
+    /// See `write_read()`.
+    pub fn write(&mut self, address: u8, bytes: &[u8]) -> Result<()> {
+        self.write_read(address, bytes, &mut [])
+    }
+
+    /// Reads `buf.len()` bytes from the device at 7-bit address `address`.
+    ///
+    /// # Errors
+    ///
+    /// See `write_read()`.
+    pub fn read(&mut self, address: u8, buf: &mut [u8]) -> Result<()> {
+        self.write_read(address, &[], buf)
+    }
+
+    /// Writes `bytes` to the device at 7-bit address `address`, then, without
+    /// releasing the bus, reads `buf.len()` bytes back from it. This is how
+    /// most devices' registers are read: write the register number, then
+    /// read its contents. Either part is skipped if it's empty.
+    ///
+    /// A stop condition ends the transfer whether or not it succeeds.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidI2cAddress` if `address` > `MAX_ADDRESS`,
+    /// `Error::I2cNack` if the device doesn't acknowledge its address or a
+    /// byte written to it, and `Error::Timeout` if it stretches the clock for
+    /// longer than `STRETCH_TIMEOUT_US`.
+    pub fn write_read(&mut self, address: u8, bytes: &[u8], buf: &mut [u8]) -> Result<()> {
+        if address > MAX_ADDRESS {
+            return Err(Error::InvalidI2cAddress(address));
+        }
+
+        let result = self.transfer(address, bytes, buf);
+        let stopped = self.stop();
+        result.and(stopped)
+    }
+
+    /// Performs `write_read()` up to, but not including, the stop condition.
+    fn transfer(&mut self, address: u8, bytes: &[u8], buf: &mut [u8]) -> Result<()> {
+        self.start()?;
+        if !bytes.is_empty() || buf.is_empty() {
+            self.write_address(address, false)?;
+            for &byte in bytes {
+                if !self.write_byte(byte)? {
+                    return Err(Error::I2cNack(address));
+                }
+            }
+
+            if buf.is_empty() {
+                return Ok(());
+            }
+
+            self.start()?;
+        }
+
+        self.write_address(address, true)?;
+        let last = buf.len() - 1;
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = self.read_byte(i != last)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends `address` with the read bit set if `read` is `true`.
+    fn write_address(&mut self, address: u8, read: bool) -> Result<()> {
+        if !self.write_byte(address << 1 | read as u8)? {
+            return Err(Error::I2cNack(address));
+        }
+
+        Ok(())
+    }
+
+    /// Waits half a clock period.
+    fn delay(&self) {
+        (self.clock.delay)(self.half_period_us);
+    }
+
+    /// Releases SCL and waits for it to go high, allowing for clock
+    /// stretching.
+    fn release_scl(&mut self) -> Result<()> {
+        self.scl.release();
+        let start = (self.clock.now)();
+        while !self.scl.is_high() {
+            if (self.clock.now)().saturating_sub(start) > STRETCH_TIMEOUT_US {
+                return Err(Error::Timeout);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sends a start condition, or a repeated start in the middle of a
+    /// transfer: SDA falls while SCL is high. Leaves SCL low.
+    fn start(&mut self) -> Result<()> {
+        self.sda.release();
+        self.delay();
+        self.release_scl()?;
+        self.delay();
+        self.sda.pull_low();
+        self.delay();
+        self.scl.pull_low();
+        Ok(())
+    }
+
+    /// Sends a stop condition: SDA rises while SCL is high. Leaves both lines
+    /// released.
+    fn stop(&mut self) -> Result<()> {
+        self.sda.pull_low();
+        self.delay();
+        let released = self.release_scl();
+        self.delay();
+        self.sda.release();
+        self.delay();
+        released
+    }
+
+    /// Sends the bit `bit` on one clock pulse.
+    fn write_bit(&mut self, bit: bool) -> Result<()> {
+        if bit {
+            self.sda.release();
+        } else {
+            self.sda.pull_low();
+        }
+
+        self.delay();
+        self.release_scl()?;
+        self.delay();
+        self.scl.pull_low();
+        Ok(())
+    }
+
+    /// Samples SDA on one clock pulse with SDA released.
+    fn read_bit(&mut self) -> Result<bool> {
+        self.sda.release();
+        self.delay();
+        self.release_scl()?;
+        self.delay();
+        let bit = self.sda.is_high();
+        self.scl.pull_low();
+        Ok(bit)
+    }
+
+    /// Sends `byte`, most significant bit first, and returns `true` if the
+    /// device acknowledged it.
+    fn write_byte(&mut self, byte: u8) -> Result<bool> {
+        for i in (0..8).rev() {
+            self.write_bit(byte & (1 << i) != 0)?;
+        }
+
+        // An acknowledgement is the device holding SDA low.
+        Ok(!self.read_bit()?)
+    }
+
+    /// Receives a byte, most significant bit first, and acknowledges it if
+    /// `ack` is `true`. The last byte of a read isn't acknowledged.
+    fn read_byte(&mut self, ack: bool) -> Result<u8> {
+        let mut byte = 0;
+        for _ in 0..8 {
+            byte = byte << 1 | self.read_bit()? as u8;
+        }
+
+        self.write_bit(!ack)?;
+        Ok(byte)
+    }
+}
@@ -0,0 +1,184 @@
+//! An SPI master bit-banged over four GPIO pins.
+//!
+//! `SoftSpi` drives SCLK, MOSI and an active-low chip select, and samples
+//! MISO, in any of the four SPI modes. Bytes are sent most significant bit
+//! first.
+
+use gpio::{Gpio, Input, Output};
+use error::Result;
+use timer;
+
+/// Half a clock period by default, in microseconds: a 100kHz clock, slow
+/// enough for SD cards during initialization.
+pub const DEFAULT_HALF_PERIOD_US: u64 = 5;
+
+/// The clock polarity and phase.
+///
+/// | mode    | SCLK idles | MOSI and MISO are sampled on |
+/// |---------|------------|------------------------------|
+/// | `Mode0` | low        | the rising edge              |
+/// | `Mode1` | low        | the falling edge             |
+/// | `Mode2` | high       | the falling edge             |
+/// | `Mode3` | high       | the rising edge              |
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mode {
+    Mode0,
+    Mode1,
+    Mode2,
+    Mode3,
+}
+
+impl Mode {
+    /// Returns CPOL: `true` if SCLK idles high.
+    pub fn cpol(&self) -> bool {
+        *self == Mode::Mode2 || *self == Mode::Mode3
+    }
+
+    /// Returns CPHA: `true` if data is sampled on the second (trailing) edge
+    /// of each clock pulse rather than the first.
+    pub fn cpha(&self) -> bool {
+        *self == Mode::Mode1 || *self == Mode::Mode3
+    }
+}
+
+/// A pin driven by `SoftSpi`.
+pub trait OutputPin {
+    /// Drives the pin high if `level` is `true` and low otherwise.
+    fn write(&mut self, level: bool);
+}
+
+/// A pin sampled by `SoftSpi`.
+pub trait InputPin {
+    /// Returns `true` if the pin is high.
+    fn is_high(&mut self) -> bool;
+}
+
+impl OutputPin for Gpio<Output> {
+    fn write(&mut self, level: bool) {
+        Gpio::write(self, level)
+    }
+}
+
+impl InputPin for Gpio<Input> {
+    fn is_high(&mut self) -> bool {
+        self.level()
+    }
+}
+
+/// A bit-banged SPI master.
+pub struct SoftSpi<O: OutputPin = Gpio<Output>, I: InputPin = Gpio<Input>> {
+    sclk: O,
+    mosi: O,
+    miso: I,
+    cs: O,
+    mode: Mode,
+    half_period_us: u64,
+    delay: fn(u64),
+}
+
+impl SoftSpi {
+    /// Returns an SPI master in mode `mode` on the given GPIO pins, with chip
+    /// select deasserted and SCLK idle.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidPin` if any pin isn't a GPIO pin.
+    pub fn new(sclk: u8, mosi: u8, miso: u8, cs: u8, mode: Mode) -> Result<SoftSpi> {
+        let sclk = Gpio::new(sclk)?.into_output();
+        let mosi = Gpio::new(mosi)?.into_output();
+        let miso = Gpio::new(miso)?.into_input();
+        let cs = Gpio::new(cs)?.into_output();
+        Ok(SoftSpi::with_pins(sclk, mosi, miso, cs, mode, timer::spin_sleep_us))
+    }
+}
+
+impl<O: OutputPin, I: InputPin> SoftSpi<O, I> {
+    /// Returns an SPI master in mode `mode` on the given pins that waits with
+    /// `delay`, which is passed a number of microseconds. Chip select is
+    /// deasserted and SCLK left idle.
+    pub fn with_pins(sclk: O, mosi: O, miso: I, cs: O, mode: Mode, delay: fn(u64)) -> SoftSpi<O, I> {
+        let mut spi = SoftSpi {
+            sclk, mosi, miso, cs, mode, delay,
+            half_period_us: DEFAULT_HALF_PERIOD_US,
+        };
+
+        spi.cs.write(true);
+        spi.sclk.write(mode.cpol());
+        spi
+    }
+
+    /// Sets half the clock period to `half_period_us` microseconds.
+    pub fn set_half_period(&mut self, half_period_us: u64) {
+        self.half_period_us = half_period_us;
+    }
+
+    /// Switches to mode `mode`. Takes effect with the next transfer.
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.sclk.write(mode.cpol());
+    }
+
+    /// Asserts chip select, exchanges every byte of `buf` with the device,
+    /// replacing each with the byte received while it was sent, and deasserts
+    /// chip select.
+    pub fn transfer(&mut self, buf: &mut [u8]) {
+        self.cs.write(false);
+        self.delay();
+        for byte in buf.iter_mut() {
+            *byte = self.transfer_byte(*byte);
+        }
+
+        self.cs.write(true);
+        self.delay();
+    }
+
+    /// Like `transfer()`, but sends `bytes` and discards what is received.
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.cs.write(false);
+        self.delay();
+        for &byte in bytes {
+            self.transfer_byte(byte);
+        }
+
+        self.cs.write(true);
+        self.delay();
+    }
+
+    /// Waits half a clock period.
+    fn delay(&self) {
+        (self.delay)(self.half_period_us);
+    }
+
+    /// Sends `out` and returns the byte received at the same time. Chip
+    /// select must already be asserted.
+    fn transfer_byte(&mut self, out: u8) -> u8 {
+        let idle = self.mode.cpol();
+        let mut received = 0;
+        for i in (0..8).rev() {
+            let bit = out & (1 << i) != 0;
+            let sampled = if self.mode.cpha() {
+                // Shift out on the leading edge, sample on the trailing one.
+                self.sclk.write(!idle);
+                self.mosi.write(bit);
+                self.delay();
+                let sampled = self.miso.is_high();
+                self.sclk.write(idle);
+                self.delay();
+                sampled
+            } else {
+                // Data is set up before the leading edge, which samples it.
+                self.mosi.write(bit);
+                self.delay();
+                self.sclk.write(!idle);
+                self.delay();
+                let sampled = self.miso.is_high();
+                self.sclk.write(idle);
+                sampled
+            };
+
+            received = received << 1 | sampled as u8;
+        }
+
+        received
+    }
+}
@@ -0,0 +1,1059 @@
+#[cfg(any(feature = "gpio", feature = "timer", feature = "dma", feature = "generic_timer", feature = "pm"))]
+use core::mem;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+#[cfg(any(feature = "gpio", feature = "uart", feature = "timer", feature = "generic_timer", feature = "pm"))]
+use volatile::prelude::*;
+
+use error::{Error, WouldBlock};
+use quiesce::{self, MAX_HOOKS};
+use ring::{RingBuffer, CAPACITY};
+#[cfg(feature = "gpio")]
+use gpio::{self, Function, Gpio, GpioBank, MAX_PIN};
+#[cfg(feature = "led")]
+use led::{Led, Pattern, Step};
+#[cfg(feature = "uart")]
+use pl011;
+#[cfg(feature = "dma")]
+use dma::{self, ControlBlock};
+#[cfg(feature = "interrupt")]
+use interrupt::Interrupt;
+#[cfg(feature = "timer")]
+use timer::{self, Compare, Deadline};
+#[cfg(feature = "timer")]
+use core::time::Duration;
+#[cfg(feature = "timer")]
+use cycles;
+#[cfg(feature = "generic_timer")]
+use generic_timer;
+#[cfg(feature = "pm")]
+use pm;
+#[cfg(feature = "soft_i2c")]
+use soft_i2c::{self, SoftI2c, Line, Clock};
+#[cfg(feature = "soft_spi")]
+use soft_spi::{self, SoftSpi, Mode, OutputPin, InputPin};
+#[cfg(any(feature = "soft_i2c", feature = "soft_spi"))]
+use std::cell::RefCell;
+#[cfg(any(feature = "soft_i2c", feature = "soft_spi"))]
+use std::rc::Rc;
+#[cfg(feature = "mailbox")]
+use mailbox::{self, Message};
+#[cfg(feature = "framebuffer")]
+use framebuffer::{self, PixelOrder};
+#[cfg(feature = "atags")]
+use atags::{Atag, Atags, Core, Mem};
+#[cfg(feature = "emmc")]
+use emmc::{self, Command, Response};
+#[cfg(feature = "uart")]
+use uart::{self, baud_divisor, MiniUart, DataBits, StopBits, DEFAULT_BAUD};
+
+#[test]
+#[cfg(feature = "gpio")]
+fn gpio_rejects_missing_pins() {
+    for &pin in &[MAX_PIN + 1, 64, 255] {
+        match Gpio::new(pin) {
+            Err(e) => assert_eq!(e, Error::InvalidPin(pin)),
+            Ok(_) => panic!("pin {} was accepted", pin),
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "gpio")]
+fn gpio_functions_round_trip() {
+    let functions = [Function::Input, Function::Output, Function::Alt0, Function::Alt1,
+                     Function::Alt2, Function::Alt3, Function::Alt4, Function::Alt5];
+    for &function in &functions {
+        assert_eq!(Function::from_bits(function as u32), function);
+        assert_eq!(Function::from_bits(function as u32 | 0b1000), function);
+    }
+
+    for bits in 0..8 {
+        assert_eq!(Function::from_bits(bits) as u32, bits);
+    }
+}
+
+#[test]
+#[cfg(feature = "gpio")]
+fn gpio_banks_cover_every_pin_once() {
+    assert_eq!(gpio::bank_pins(0), !0);
+    assert_eq!(gpio::bank_pins(1), (1 << 22) - 1);
+    assert_eq!(gpio::bank_pins(0).count_ones() + gpio::bank_pins(1).count_ones(),
+               MAX_PIN as u32 + 1);
+
+    assert_eq!(GpioBank::new(2).err(), Some(Error::InvalidBank(2)));
+    assert_eq!(GpioBank::new(255).err(), Some(Error::InvalidBank(255)));
+}
+
+#[test]
+#[cfg(feature = "led")]
+fn led_rejects_missing_pins() {
+    match Led::new(MAX_PIN + 1) {
+        Err(e) => assert_eq!(e, Error::InvalidPin(MAX_PIN + 1)),
+        Ok(_) => panic!("pin {} was accepted", MAX_PIN + 1),
+    }
+}
+
+#[test]
+#[cfg(feature = "uart")]
+fn baud_divisors() {
+    assert_eq!(baud_divisor(115200), Ok(270));
+    assert_eq!(baud_divisor(230400), Ok(135));
+    assert_eq!(baud_divisor(921600), Ok(33));
+    assert_eq!(baud_divisor(31_250_000), Ok(0));
+    assert_eq!(baud_divisor(477), Ok(65513));
+}
+
+#[test]
+#[cfg(feature = "uart")]
+fn default_divisor_matches_default_baud() {
+    assert_eq!(baud_divisor(DEFAULT_BAUD), Ok(270));
+    assert_eq!(baud_divisor(9600), Ok(3254));
+    assert_eq!(baud_divisor(57600), Ok(542));
+}
+
+#[test]
+#[cfg(feature = "uart")]
+fn uart_config_is_validated_first() {
+    match MiniUart::with_config(9600, DataBits::Eight, StopBits::Two) {
+        Err(e) => assert_eq!(e, Error::UnsupportedStopBits),
+        Ok(_) => panic!("two stop bits were accepted"),
+    }
+
+    match MiniUart::with_config(0, DataBits::Seven, StopBits::One) {
+        Err(e) => assert_eq!(e, Error::InvalidBaud(0)),
+        Ok(_) => panic!("0 baud was accepted"),
+    }
+}
+
+#[test]
+#[cfg(feature = "uart")]
+fn unproducible_bauds_are_rejected() {
+    for &baud in &[0, 1, 476, 62_500_001, u32::max_value()] {
+        assert_eq!(baud_divisor(baud), Err(Error::InvalidBaud(baud)));
+    }
+}
+
+#[test]
+#[cfg(feature = "led")]
+fn empty_pattern_never_changes_level() {
+    const EMPTY: [Step; 0] = [];
+    let mut pattern = Pattern::new(&EMPTY);
+    assert_eq!(pattern.period(), 0);
+    for &now in &[0, 1, 1_000_000, u64::max_value()] {
+        assert_eq!(pattern.tick(now), None);
+    }
+}
+
+#[test]
+#[cfg(feature = "led")]
+fn long_pattern_saturates() {
+    const LONG: [Step; 2] = [(true, u64::max_value()), (false, u64::max_value())];
+    let mut pattern = Pattern::new(&LONG);
+    assert_eq!(pattern.period(), u64::max_value());
+    assert_eq!(pattern.tick(1), Some(true));
+    assert_eq!(pattern.tick(u64::max_value() - 1), None);
+    assert_eq!(pattern.tick(u64::max_value()), None);
+}
+
+#[test]
+fn errors_display() {
+    assert_eq!(Error::InvalidPin(60).to_string(), "GPIO pin 60 doesn't exist");
+    assert_eq!(Error::InvalidBaud(7).to_string(), "the UART can't run at 7 baud");
+    assert_eq!(Error::Timeout.to_string(), "timed out");
+    assert_eq!(Error::UnsupportedStopBits.to_string(), "the mini UART only supports one stop bit");
+    assert_eq!(WouldBlock.to_string(), "operation would block");
+    assert_eq!(Error::LoopbackMismatch(0x55, 0x5).to_string(),
+               "UART loopback sent 0x55 but read back 0x05");
+    assert_eq!(Error::I2cNack(0x50).to_string(), "I2C device 0x50 didn't acknowledge");
+    assert_eq!(Error::SdCommand(17).to_string(), "the SD card failed command 17");
+}
+
+/// The hooks run so far, as digits: hook `n` appends `n`.
+static HOOK_LOG: AtomicUsize = ATOMIC_USIZE_INIT;
+
+fn log_hook(n: usize) {
+    let log = HOOK_LOG.load(Ordering::SeqCst);
+    HOOK_LOG.store(log * 10 + n, Ordering::SeqCst);
+}
+
+fn hook_1() { log_hook(1) }
+fn hook_2() { log_hook(2) }
+fn hook_3() { log_hook(3) }
+fn spare_hook() {  }
+fn nested_hook() { quiesce::quiesce() }
+
+// This is the only test that touches the hook table, which is global.
+#[test]
+fn quiesce_runs_hooks_in_reverse_once_each() {
+    assert_eq!(quiesce::register(hook_1), Ok(()));
+    assert_eq!(quiesce::register(hook_2), Ok(()));
+    assert_eq!(quiesce::register(nested_hook), Ok(()));
+    assert_eq!(quiesce::register(hook_3), Ok(()));
+    assert_eq!(quiesce::register(hook_1), Ok(()));
+    assert_eq!(quiesce::registered(), 4);
+
+    quiesce::quiesce();
+    assert_eq!(HOOK_LOG.load(Ordering::SeqCst), 321);
+    quiesce::quiesce();
+    assert_eq!(HOOK_LOG.load(Ordering::SeqCst), 321_321);
+
+    let hooks: [fn(); 4] = [spare_hook, || {}, || log_hook(9), || log_hook(8)];
+    for &hook in &hooks {
+        assert_eq!(quiesce::register(hook), Ok(()));
+    }
+
+    assert_eq!(quiesce::registered(), MAX_HOOKS);
+    assert_eq!(quiesce::register(|| log_hook(7)), Err(Error::TooManyHooks));
+    assert_eq!(quiesce::register(spare_hook), Ok(()));
+}
+
+#[test]
+#[cfg(feature = "gpio")]
+fn gpio_shutdown_returns_claimed_pins_to_inputs() {
+    let mut registers: gpio::Registers = unsafe { mem::zeroed() };
+    for fsel in registers.FSEL.iter_mut() {
+        fsel.write(0b001_001_001_001_001_001_001_001_001_001);
+    }
+
+    for bank in 0..2 {
+        registers.REN[bank].write(!0);
+        registers.AFEN[bank].write(0x10);
+        registers.EDS[bank].write(0);
+    }
+
+    let claimed = [1 << 14 | 1 << 15 | 1 << 16 | 1 << 31, 1 << (47 - 32) | 1 << (53 - 32)];
+    let returned = gpio::shutdown_registers(&mut registers, claimed);
+    assert_eq!(returned, [1 << 16 | 1 << 31, 1 << (47 - 32) | 1 << (53 - 32)]);
+
+    // Pins 14 and 15 carry the console and keep their function.
+    assert_eq!(registers.FSEL[1].read(), 0b001_001_001_000_001_001_001_001_001_001);
+    assert_eq!(registers.FSEL[3].read(), 0b001_001_001_001_001_001_001_001_000_001);
+    assert_eq!(registers.FSEL[4].read(), 0b001_001_000_001_001_001_001_001_001_001);
+    assert_eq!(registers.FSEL[5].read(), 0b001_001_001_001_001_001_000_001_001_001);
+    for &i in &[0, 2] {
+        assert_eq!(registers.FSEL[i].read(), 0b001_001_001_001_001_001_001_001_001_001);
+    }
+
+    for bank in 0..2 {
+        assert_eq!(registers.REN[bank].read(), 0);
+        assert_eq!(registers.AFEN[bank].read(), 0);
+        assert_eq!(registers.EDS[bank].read(), !0);
+    }
+}
+
+#[test]
+#[cfg(feature = "uart")]
+fn uart_shutdown_disables_receiver_and_transmitter() {
+    let mut registers: uart::Registers = unsafe { mem::zeroed() };
+    registers.CNTL.write(0b11 | 1 << 4);
+    registers.IER.write(0b11);
+    registers.LSR.write(1 << 6);
+    registers.BAUD.write(270);
+
+    uart::shutdown_registers(&mut registers);
+    assert_eq!(registers.CNTL.read(), 1 << 4);
+    assert_eq!(registers.IER.read(), 0);
+    assert_eq!(registers.IIR.read(), 0b110);
+    assert_eq!(registers.BAUD.read(), 270);
+}
+
+#[test]
+#[cfg(feature = "uart")]
+fn uart_shutdown_disables_flow_control() {
+    let mut registers: uart::Registers = unsafe { mem::zeroed() };
+    registers.CNTL.write(0b1111 | 0b11 << 6);
+    registers.LSR.write(1 << 6);
+
+    uart::shutdown_registers(&mut registers);
+    assert_eq!(registers.CNTL.read(), 0);
+}
+
+#[test]
+#[cfg(feature = "uart")]
+fn uart_shutdown_skips_flush_when_transmitter_is_off() {
+    let mut registers: uart::Registers = unsafe { mem::zeroed() };
+    registers.CNTL.write(0b01);
+
+    uart::shutdown_registers(&mut registers);
+    assert_eq!(registers.CNTL.read(), 0);
+}
+
+#[test]
+fn ring_buffer_is_first_in_first_out() {
+    let ring = RingBuffer::new();
+    assert!(ring.is_empty());
+    assert_eq!(ring.pop(), None);
+
+    for round in 0..3 {
+        for byte in 0..200 {
+            assert!(ring.push(byte));
+        }
+
+        assert_eq!(ring.len(), 200);
+        for byte in 0..200 {
+            assert_eq!(ring.pop(), Some(byte), "round {}", round);
+        }
+
+        assert_eq!(ring.pop(), None);
+    }
+
+    assert_eq!(ring.dropped(), 0);
+}
+
+#[test]
+fn full_ring_buffer_drops_new_bytes() {
+    let ring = RingBuffer::new();
+    for i in 0..CAPACITY {
+        assert!(ring.push(i as u8));
+    }
+
+    assert!(!ring.push(0xAA));
+    assert!(!ring.push(0xBB));
+    assert_eq!(ring.dropped(), 2);
+    assert_eq!(ring.len(), CAPACITY);
+
+    assert_eq!(ring.pop(), Some(0));
+    assert!(ring.push(0xCC));
+    for i in 1..CAPACITY {
+        assert_eq!(ring.pop(), Some(i as u8));
+    }
+
+    assert_eq!(ring.pop(), Some(0xCC));
+    assert!(ring.is_empty());
+}
+
+#[test]
+#[cfg(feature = "uart")]
+fn uart_irq_moves_at_most_a_fifo_of_bytes() {
+    let mut registers: uart::Registers = unsafe { mem::zeroed() };
+    let ring = RingBuffer::new();
+    assert_eq!(uart::drain_rx_fifo(&mut registers, &ring), 0);
+    assert!(ring.is_empty());
+
+    // A mock FIFO never empties, so the drain has to stop on its own.
+    registers.LSR.write(1);
+    registers.IO.write(0x1_41);
+    assert_eq!(uart::drain_rx_fifo(&mut registers, &ring), 8);
+    assert_eq!(ring.len(), 8);
+    while let Some(byte) = ring.pop() {
+        assert_eq!(byte, 0x41);
+    }
+}
+
+#[test]
+#[cfg(feature = "uart")]
+fn pl011_divisors() {
+    assert_eq!(pl011::baud_divisor(DEFAULT_BAUD), Ok((26, 3)));
+    assert_eq!(pl011::baud_divisor(9600), Ok((312, 32)));
+    assert_eq!(pl011::baud_divisor(230400), Ok((13, 1)));
+    assert_eq!(pl011::baud_divisor(3_000_000), Ok((1, 0)));
+    assert_eq!(pl011::baud_divisor(0), Err(Error::InvalidBaud(0)));
+    assert_eq!(pl011::baud_divisor(4_000_000), Err(Error::InvalidBaud(4_000_000)));
+    assert_eq!(pl011::baud_divisor(40), Err(Error::InvalidBaud(40)));
+}
+
+#[test]
+#[cfg(feature = "uart")]
+fn pl011_shutdown_disables_the_uart() {
+    let mut registers: pl011::Registers = unsafe { mem::zeroed() };
+    registers.CR.write(1 | 1 << 8 | 1 << 9);
+    registers.IMSC.write(1 << 4);
+    registers.LCRH.write(0b11 << 5 | 1 << 4);
+    registers.IBRD.write(26);
+
+    pl011::shutdown_registers(&mut registers);
+    assert_eq!(registers.CR.read(), 0);
+    assert_eq!(registers.IMSC.read(), 0);
+    assert_eq!(registers.LCRH.read(), 0b11 << 5);
+    assert_eq!(registers.IBRD.read(), 26);
+}
+
+#[test]
+#[cfg(feature = "uart")]
+fn loopback_echo_is_checked_byte_by_byte() {
+    let pattern = [0x55, 0xAA, 0x00];
+    let mut sent = vec![];
+    assert_eq!(pl011::check_echo(&pattern, |byte| { sent.push(byte); Some(byte) }), Ok(()));
+    assert_eq!(sent, pattern);
+
+    let drop_high_bit = |byte: u8| Some(byte & 0x7F);
+    assert_eq!(pl011::check_echo(&pattern, drop_high_bit), Err(Error::LoopbackMismatch(0xAA, 0x2A)));
+
+    let mut sent = vec![];
+    let lost = pl011::check_echo(&pattern, |byte| { sent.push(byte); None });
+    assert_eq!(lost, Err(Error::Timeout));
+    assert_eq!(sent, [0x55]);
+}
+
+#[test]
+#[cfg(feature = "dma")]
+fn dma_control_block_targets_the_peripheral_bus() {
+    assert_eq!(mem::align_of::<ControlBlock>(), 32);
+    assert_eq!(mem::size_of::<ControlBlock>(), 32);
+
+    let data = [0u8; 40];
+    let block = ControlBlock::to_peripheral(&data, 0x3F201000, 12);
+    assert_eq!(block.transfer_info, 12 << 16 | 1 << 8 | 1 << 6 | 1 << 3);
+    assert_eq!(block.source, data.as_ptr() as u32 | 0xC0000000);
+    assert_eq!(block.dest, 0x7E201000);
+    assert_eq!(block.length, 40);
+    assert_eq!((block.stride, block.next), (0, 0));
+
+    assert_eq!(dma::peripheral_bus_address(0x3F215040), 0x7E215040);
+    assert_eq!(dma::memory_bus_address(0x80000), 0xC0080000);
+}
+
+#[test]
+#[cfg(feature = "dma")]
+fn dma_rejects_lite_channels() {
+    for &channel in &[7, 14, 15, 200] {
+        assert_eq!(dma::Channel::new(channel).err(), Some(Error::InvalidDmaChannel(channel)));
+    }
+}
+
+#[test]
+#[cfg(feature = "interrupt")]
+fn interrupts_map_to_register_banks() {
+    assert_eq!(Interrupt::Timer1.bank(), (0, 1));
+    assert_eq!(Interrupt::Aux.bank(), (0, 29));
+    assert_eq!(Interrupt::Gpio0.bank(), (1, 17));
+    assert_eq!(Interrupt::Uart.bank(), (1, 25));
+}
+
+/// What a `MockDevice` is doing.
+#[cfg(feature = "soft_i2c")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Phase {
+    Idle,
+    Address,
+    Write,
+    Read,
+}
+
+/// An I2C device with eight byte-wide registers: the first byte written
+/// after its address selects a register, later bytes are written to
+/// consecutive registers, and reads return consecutive registers.
+#[cfg(feature = "soft_i2c")]
+struct MockDevice {
+    address: u8,
+    registers: [u8; 8],
+    pointer: usize,
+    phase: Phase,
+    bits: u8,
+    byte: u8,
+    selected: bool,
+    master_acked: bool,
+    sda_low: bool,
+    stretching: bool,
+}
+
+/// Both bus lines, as driven by the master and the device.
+#[cfg(feature = "soft_i2c")]
+struct MockBus {
+    master_scl_low: bool,
+    master_sda_low: bool,
+    scl: bool,
+    sda: bool,
+    device: MockDevice,
+}
+
+#[cfg(feature = "soft_i2c")]
+impl MockBus {
+    fn new(address: u8) -> Rc<RefCell<MockBus>> {
+        Rc::new(RefCell::new(MockBus {
+            master_scl_low: false,
+            master_sda_low: false,
+            scl: true,
+            sda: true,
+            device: MockDevice {
+                address,
+                registers: [0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17],
+                pointer: 0,
+                phase: Phase::Idle,
+                bits: 0,
+                byte: 0,
+                selected: false,
+                master_acked: false,
+                sda_low: false,
+                stretching: false,
+            },
+        }))
+    }
+
+    fn wires(&self) -> (bool, bool) {
+        (!(self.master_scl_low || self.device.stretching), !(self.master_sda_low || self.device.sda_low))
+    }
+
+    /// Lets the device react to the master's latest change to the lines.
+    fn settle(&mut self) {
+        let (scl, sda) = self.wires();
+        {
+            let device = &mut self.device;
+            if self.scl && scl && self.sda != sda {
+                // SDA changing while SCL is high is a start or a stop.
+                device.phase = if sda { Phase::Idle } else { Phase::Address };
+                device.bits = 0;
+                device.byte = 0;
+                device.sda_low = false;
+            } else if !self.scl && scl {
+                device.rise(sda);
+            } else if self.scl && !scl {
+                device.fall();
+            }
+        }
+
+        let (scl, sda) = self.wires();
+        self.scl = scl;
+        self.sda = sda;
+    }
+}
+
+#[cfg(feature = "soft_i2c")]
+impl MockDevice {
+    fn drive(&mut self, bit: u8) {
+        self.sda_low = self.registers[self.pointer] & (1 << bit) == 0;
+    }
+
+    fn rise(&mut self, sda: bool) {
+        match self.phase {
+            Phase::Address | Phase::Write if self.bits < 8 => self.byte = self.byte << 1 | sda as u8,
+            Phase::Read if self.bits == 8 => self.master_acked = !sda,
+            _ => {  }
+        }
+
+        if self.phase != Phase::Idle {
+            self.bits += 1;
+        }
+    }
+
+    fn fall(&mut self) {
+        match (self.phase, self.bits) {
+            (Phase::Address, 8) if self.byte >> 1 == self.address => self.sda_low = true,
+            (Phase::Address, 8) => self.phase = Phase::Idle,
+            (Phase::Address, 9) => {
+                self.sda_low = false;
+                self.bits = 0;
+                if self.byte & 1 == 1 {
+                    self.phase = Phase::Read;
+                    self.drive(7);
+                } else {
+                    self.phase = Phase::Write;
+                    self.selected = false;
+                }
+
+                self.byte = 0;
+            }
+            (Phase::Write, 8) => {
+                self.sda_low = true;
+                if self.selected {
+                    self.registers[self.pointer] = self.byte;
+                    self.pointer += 1;
+                } else {
+                    self.pointer = self.byte as usize;
+                    self.selected = true;
+                }
+            }
+            (Phase::Write, 9) => {
+                self.sda_low = false;
+                self.bits = 0;
+                self.byte = 0;
+            }
+            (Phase::Read, bits) if bits < 8 => self.drive(7 - bits),
+            (Phase::Read, 8) => self.sda_low = false,
+            (Phase::Read, 9) if self.master_acked => {
+                self.pointer += 1;
+                self.bits = 0;
+                self.drive(7);
+            }
+            (Phase::Read, 9) => {
+                self.phase = Phase::Idle;
+                self.sda_low = false;
+            }
+            _ => {  }
+        }
+    }
+}
+
+/// One of the two lines of a `MockBus`, as seen by the master.
+#[cfg(feature = "soft_i2c")]
+struct MockLine {
+    bus: Rc<RefCell<MockBus>>,
+    scl: bool,
+}
+
+#[cfg(feature = "soft_i2c")]
+impl MockLine {
+    fn set_low(&mut self, low: bool) {
+        let mut bus = self.bus.borrow_mut();
+        if self.scl {
+            bus.master_scl_low = low;
+        } else {
+            bus.master_sda_low = low;
+        }
+
+        bus.settle();
+    }
+}
+
+#[cfg(feature = "soft_i2c")]
+impl Line for MockLine {
+    fn pull_low(&mut self) {
+        self.set_low(true)
+    }
+
+    fn release(&mut self) {
+        self.set_low(false)
+    }
+
+    fn is_high(&self) -> bool {
+        let bus = self.bus.borrow();
+        if self.scl { bus.scl } else { bus.sda }
+    }
+}
+
+/// Microseconds on the mock clock: every reading advances it by one.
+#[cfg(feature = "soft_i2c")]
+static MOCK_NOW: AtomicUsize = ATOMIC_USIZE_INIT;
+
+#[cfg(feature = "soft_i2c")]
+fn mock_i2c(bus: &Rc<RefCell<MockBus>>) -> SoftI2c<MockLine> {
+    let clock = Clock {
+        now: || MOCK_NOW.fetch_add(1, Ordering::SeqCst) as u64,
+        delay: |_| {  },
+    };
+
+    let scl = MockLine { bus: bus.clone(), scl: true };
+    let sda = MockLine { bus: bus.clone(), scl: false };
+    SoftI2c::with_lines(scl, sda, clock)
+}
+
+#[test]
+#[cfg(feature = "soft_i2c")]
+fn soft_i2c_reads_registers() {
+    let bus = MockBus::new(0x50);
+    let mut i2c = mock_i2c(&bus);
+
+    let mut buf = [0u8; 3];
+    assert_eq!(i2c.write_read(0x50, &[2], &mut buf), Ok(()));
+    assert_eq!(buf, [0x12, 0x13, 0x14]);
+
+    let mut buf = [0u8; 2];
+    assert_eq!(i2c.read(0x50, &mut buf), Ok(()));
+    assert_eq!(buf, [0x14, 0x15]);
+
+    let bus = bus.borrow();
+    assert_eq!((bus.scl, bus.sda), (true, true));
+    assert_eq!(bus.device.phase, Phase::Idle);
+}
+
+#[test]
+#[cfg(feature = "soft_i2c")]
+fn soft_i2c_writes_registers() {
+    let bus = MockBus::new(0x50);
+    let mut i2c = mock_i2c(&bus);
+
+    assert_eq!(i2c.write(0x50, &[5, 0xAA, 0x55]), Ok(()));
+    assert_eq!(bus.borrow().device.registers, [0x10, 0x11, 0x12, 0x13, 0x14, 0xAA, 0x55, 0x17]);
+}
+
+#[test]
+#[cfg(feature = "soft_i2c")]
+fn soft_i2c_reports_missing_devices() {
+    let bus = MockBus::new(0x50);
+    let mut i2c = mock_i2c(&bus);
+
+    let mut buf = [0u8; 1];
+    assert_eq!(i2c.write_read(0x51, &[0], &mut buf), Err(Error::I2cNack(0x51)));
+    assert_eq!(i2c.read(0x51, &mut buf), Err(Error::I2cNack(0x51)));
+    assert_eq!(i2c.write(0x80, &[0]), Err(Error::InvalidI2cAddress(0x80)));
+    assert_eq!(soft_i2c::MAX_ADDRESS, 0x7F);
+
+    // The bus is released after a failed transfer.
+    let bus = bus.borrow();
+    assert_eq!((bus.scl, bus.sda), (true, true));
+}
+
+#[test]
+#[cfg(feature = "soft_i2c")]
+fn soft_i2c_gives_up_on_endless_clock_stretching() {
+    let bus = MockBus::new(0x50);
+    let mut i2c = mock_i2c(&bus);
+    bus.borrow_mut().device.stretching = true;
+    bus.borrow_mut().settle();
+
+    assert_eq!(i2c.write(0x50, &[0]), Err(Error::Timeout));
+}
+
+/// An SPI device in a given mode that records the bytes it receives and
+/// answers with `response`, then `0xFF`s.
+#[cfg(feature = "soft_spi")]
+struct MockSpiBus {
+    mode: Mode,
+    sclk: bool,
+    mosi: bool,
+    cs: bool,
+    miso: bool,
+    bits: usize,
+    shift: u8,
+    response: Vec<u8>,
+    received: Vec<u8>,
+}
+
+#[cfg(feature = "soft_spi")]
+impl MockSpiBus {
+    fn new(mode: Mode, response: &[u8]) -> Rc<RefCell<MockSpiBus>> {
+        Rc::new(RefCell::new(MockSpiBus {
+            mode,
+            sclk: mode.cpol(),
+            mosi: false,
+            cs: true,
+            miso: true,
+            bits: 0,
+            shift: 0,
+            response: response.to_vec(),
+            received: vec![],
+        }))
+    }
+
+    fn sample(&mut self) {
+        self.shift = self.shift << 1 | self.mosi as u8;
+        self.bits += 1;
+        if self.bits % 8 == 0 {
+            self.received.push(self.shift);
+        }
+    }
+
+    fn drive(&mut self) {
+        let byte = self.response.get(self.bits / 8).cloned().unwrap_or(0xFF);
+        self.miso = byte & (0x80 >> (self.bits % 8)) != 0;
+    }
+
+    fn set_cs(&mut self, level: bool) {
+        if self.cs && !level {
+            self.bits = 0;
+            if !self.mode.cpha() {
+                self.drive();
+            }
+        }
+
+        self.cs = level;
+    }
+
+    fn set_sclk(&mut self, level: bool) {
+        if self.cs || level == self.sclk {
+            self.sclk = level;
+            return;
+        }
+
+        self.sclk = level;
+        let leading = level != self.mode.cpol();
+        match (leading, self.mode.cpha()) {
+            (true, false) | (false, true) => self.sample(),
+            (false, false) | (true, true) => self.drive(),
+        }
+    }
+}
+
+/// Which of the master's output lines a `MockSpiPin` drives.
+#[cfg(feature = "soft_spi")]
+#[derive(Copy, Clone)]
+enum SpiLine {
+    Sclk,
+    Mosi,
+    Cs,
+}
+
+#[cfg(feature = "soft_spi")]
+struct MockSpiPin(Rc<RefCell<MockSpiBus>>, SpiLine);
+
+#[cfg(feature = "soft_spi")]
+impl OutputPin for MockSpiPin {
+    fn write(&mut self, level: bool) {
+        let mut bus = self.0.borrow_mut();
+        match self.1 {
+            SpiLine::Sclk => bus.set_sclk(level),
+            SpiLine::Mosi => bus.mosi = level,
+            SpiLine::Cs => bus.set_cs(level),
+        }
+    }
+}
+
+/// The master's view of a `MockSpiBus`'s MISO line.
+#[cfg(feature = "soft_spi")]
+struct MockMiso(Rc<RefCell<MockSpiBus>>);
+
+#[cfg(feature = "soft_spi")]
+impl InputPin for MockMiso {
+    fn is_high(&mut self) -> bool {
+        self.0.borrow().miso
+    }
+}
+
+#[test]
+#[cfg(feature = "soft_spi")]
+fn soft_spi_exchanges_bytes_in_every_mode() {
+    for &mode in &[Mode::Mode0, Mode::Mode1, Mode::Mode2, Mode::Mode3] {
+        let bus = MockSpiBus::new(mode, &[0x5A, 0xC3]);
+        let pin = |line| MockSpiPin(bus.clone(), line);
+        let miso = MockMiso(bus.clone());
+        let mut spi = SoftSpi::with_pins(pin(SpiLine::Sclk), pin(SpiLine::Mosi), miso,
+                                         pin(SpiLine::Cs), mode, |_| {  });
+
+        let mut buf = [0xA5, 0x3C, 0x81];
+        spi.transfer(&mut buf);
+        assert_eq!(buf, [0x5A, 0xC3, 0xFF], "{:?}", mode);
+
+        let bus = bus.borrow();
+        assert_eq!(bus.received, [0xA5, 0x3C, 0x81], "{:?}", mode);
+        assert_eq!((bus.cs, bus.sclk), (true, mode.cpol()), "{:?}", mode);
+    }
+
+    assert_eq!(soft_spi::DEFAULT_HALF_PERIOD_US, 5);
+}
+
+#[test]
+#[cfg(feature = "timer")]
+fn timer_compare_matches_relative_to_the_counter() {
+    let mut registers: timer::Registers = unsafe { mem::zeroed() };
+    timer::arm_compare(&mut registers, Compare::C1, 1000);
+    assert_eq!(registers.COMPARE[1].read(), 1000);
+    assert_eq!(registers.CS.read(), 1 << 1);
+
+    registers.CS.write(0);
+    assert!(!timer::acknowledge_match(&mut registers, Compare::C3));
+    assert_eq!(registers.CS.read(), 0);
+
+    registers.CS.write(1 << 3);
+    assert!(timer::acknowledge_match(&mut registers, Compare::C3));
+    assert_eq!(Compare::C3.interrupt(), Interrupt::Timer3);
+}
+
+#[test]
+#[cfg(feature = "generic_timer")]
+fn generic_timer_ticks_saturate() {
+    assert_eq!(generic_timer::us_to_ticks(0, 19_200_000), 0);
+    assert_eq!(generic_timer::us_to_ticks(1000, 19_200_000), 19_200);
+    assert_eq!(generic_timer::us_to_ticks(u32::max_value(), 19_200_000), i32::max_value() as u32);
+}
+
+#[test]
+#[cfg(feature = "generic_timer")]
+fn generic_timer_routes_only_its_core() {
+    let mut registers: generic_timer::Registers = unsafe { mem::zeroed() };
+    registers.TIMER_INT_CTL[2].write(0b1000);
+
+    generic_timer::route_irq(&mut registers, 2, true);
+    assert_eq!(registers.TIMER_INT_CTL[2].read(), 0b1010);
+    assert_eq!(registers.TIMER_INT_CTL[1].read(), 0);
+
+    generic_timer::route_irq(&mut registers, 2, false);
+    assert_eq!(registers.TIMER_INT_CTL[2].read(), 0b1000);
+}
+
+#[test]
+#[cfg(feature = "timer")]
+fn timer_micros_round_trip() {
+    assert_eq!(timer::from_micros(1_000_001), Duration::new(1, 1000));
+    assert_eq!(timer::as_micros(Duration::new(1, 1999)), 1_000_001);
+    assert_eq!(timer::as_micros(Duration::new(u64::max_value(), 0)), u64::max_value());
+}
+
+#[test]
+#[cfg(feature = "timer")]
+fn deadline_expires_after_timeout() {
+    let start = Duration::from_millis(5);
+    let deadline = Deadline::at(start, Duration::from_millis(10));
+    assert!(!deadline.expired_at(start));
+    assert_eq!(deadline.remaining_at(start), Duration::from_millis(10));
+    assert!(!deadline.expired_at(Duration::from_millis(15)));
+    assert!(deadline.expired_at(Duration::from_millis(16)));
+    assert_eq!(deadline.remaining_at(Duration::from_millis(16)), Duration::new(0, 0));
+
+    let forever = Deadline::at(start, Duration::new(u64::max_value(), 0));
+    assert!(!forever.expired_at(Duration::new(u64::max_value() - 1, 0)));
+}
+
+#[test]
+#[cfg(feature = "pm")]
+fn watchdog_arms_a_full_reset() {
+    let mut registers: pm::Registers = unsafe { mem::zeroed() };
+    registers.RSTC.write(0x30 | 0x1);
+    pm::arm_registers(&mut registers, pm::ms_to_ticks(1000));
+    assert_eq!(registers.WDOG.read(), 0x5a000000 | 65536);
+    assert_eq!(registers.RSTC.read(), 0x5a000000 | 0x20 | 0x1);
+
+    pm::arm_registers(&mut registers, !0);
+    assert_eq!(registers.WDOG.read(), 0x5a000000 | 0xfffff);
+}
+
+#[test]
+#[cfg(feature = "timer")]
+fn cycle_conversions_round_safely() {
+    assert_eq!(cycles::cycles_per_us(1_199_600, 1000), 1200);
+    assert_eq!(cycles::cycles_per_us(1_199_400, 1000), 1199);
+    assert_eq!(cycles::cycles_per_us(0, 1000), 1);
+
+    assert_eq!(cycles::ns_to_cycles(0, 1200), 0);
+    assert_eq!(cycles::ns_to_cycles(480, 1200), 576);
+    assert_eq!(cycles::ns_to_cycles(1, 1200), 2);
+    assert_eq!(cycles::ns_to_cycles(u64::max_value(), 1200), u64::max_value() / 1000 + 1);
+}
+
+#[test]
+#[cfg(feature = "mailbox")]
+fn mailbox_messages_are_laid_out_as_tags() {
+    let mut message = Message::new();
+    assert_eq!(message.push(mailbox::TAG_GET_ARM_MEMORY, &[], 2), 5);
+    assert_eq!(message.push(0x48003, &[640, 480], 2), 10);
+    assert_eq!(message.push(0x48005, &[32, 1, 2], 1), 15);
+    assert_eq!(message.words(), &[0, 0,
+                                  0x10005, 8, 0, 0, 0,
+                                  0x48003, 8, 0, 640, 480,
+                                  0x48005, 12, 0, 32, 1, 2,
+                                  0][..]);
+    assert!(!message.succeeded());
+
+    message.set_value(1, 0x80000000);
+    assert!(message.succeeded());
+}
+
+#[test]
+#[cfg(feature = "framebuffer")]
+fn framebuffer_reads_the_firmware_response() {
+    assert_eq!(PixelOrder::Rgb.pack(0x11, 0x22, 0x33), 0x332211);
+    assert_eq!(PixelOrder::Bgr.pack(0x11, 0x22, 0x33), 0x112233);
+
+    let mut request = framebuffer::Request::new(1024, 768);
+    assert_eq!(request.info(), Err(Error::MailboxFailed));
+
+    // The firmware's answers, at the value offsets `Request::new` pushed.
+    request.message.set_value(5, 800);
+    request.message.set_value(6, 600);
+    request.message.set_value(19, 0);
+    request.message.set_value(23, 0xC3E00000);
+    request.message.set_value(24, 800 * 600 * 4);
+    request.message.set_value(28, 3200);
+
+    let info = request.info().expect("a buffer was allocated");
+    assert_eq!((info.width, info.height, info.pitch), (800, 600, 3200));
+    assert_eq!(info.order, PixelOrder::Bgr);
+    assert_eq!((info.base, info.size), (0x03E00000, 800 * 600 * 4));
+}
+
+#[test]
+#[cfg(feature = "atags")]
+fn atags_decode_the_firmware_list() {
+    // "a=1 b" and a NUL, packed into little-endian words.
+    let cmdline = [0x20313D61, 0x00000062];
+    let mut words = vec![5, 0x54410001, 1, 4096, 0xFF];
+    words.extend_from_slice(&[4, 0x54410002, 0x3B000000, 0]);
+    words.extend_from_slice(&[4, 0x54410009]);
+    words.extend_from_slice(&cmdline);
+    words.extend_from_slice(&[3, 0x41000403, 7]);
+    words.extend_from_slice(&[2, 0, 5, 0x54410002]);
+
+    let tags: Vec<_> = Atags::new(&words).collect();
+    assert_eq!(tags, vec![
+        Atag::Core(Core { flags: 1, page_size: 4096, root_dev: 0xFF }),
+        Atag::Mem(Mem { size: 0x3B000000, start: 0 }),
+        Atag::Cmd("a=1 b"),
+        Atag::Unknown(0x41000403),
+    ]);
+
+    assert_eq!(Atags::new(&words).filter_map(Atag::mem).next().map(|m| m.size), Some(0x3B000000));
+    assert_eq!(Atags::new(&words).filter_map(Atag::cmd).next(), Some("a=1 b"));
+    assert_eq!(Atag::decode(0, &[]), Atag::None);
+    assert_eq!(Atag::decode(0x54410002, &[1]), Atag::Unknown(0x54410002));
+}
+
+#[test]
+#[cfg(feature = "atags")]
+fn atags_stop_at_impossible_sizes() {
+    assert_eq!(Atags::new(&[]).count(), 0);
+    assert_eq!(Atags::new(&[2, 0x54410001, 1, 0x54410002]).count(), 1);
+    assert_eq!(Atags::new(&[2, 0x54410001, 9, 0x54410002, 0, 0]).count(), 1);
+}
+
+#[test]
+#[cfg(feature = "emmc")]
+fn emmc_commands_encode_their_response_and_data() {
+    assert_eq!(emmc::GO_IDLE_STATE.cmdtm(), 0);
+    assert_eq!(emmc::ALL_SEND_CID.cmdtm(), 2 << 24 | 1 << 16 | 1 << 19);
+    assert_eq!(emmc::SEND_IF_COND.cmdtm(), 8 << 24 | 2 << 16 | 1 << 19 | 1 << 20);
+    assert_eq!(emmc::SELECT_CARD.cmdtm(), 7 << 24 | 3 << 16 | 1 << 19 | 1 << 20);
+    assert_eq!(emmc::SD_SEND_OP_COND.cmdtm(), 41 << 24 | 2 << 16);
+    assert_eq!(emmc::READ_SINGLE_BLOCK.cmdtm(),
+               17 << 24 | 2 << 16 | 1 << 19 | 1 << 20 | 1 << 21 | 1 << 4);
+    assert_eq!(emmc::READ_MULTIPLE_BLOCK.cmdtm(),
+               18 << 24 | 2 << 16 | 1 << 19 | 1 << 20 | 1 << 21 | 1 << 4 | 1 << 5 | 1 << 2 | 1 << 1);
+    assert_eq!(emmc::STOP_TRANSMISSION.cmdtm(), 12 << 24 | 3 << 16 | 1 << 19 | 1 << 20);
+    assert_eq!(emmc::SEND_STATUS.cmdtm(), 13 << 24 | 2 << 16 | 1 << 19 | 1 << 20);
+
+    let command = Command { index: 55, response: Response::R48, reads: false, multi_block: false };
+    assert_eq!(command, emmc::APP_CMD);
+}
+
+#[test]
+#[cfg(feature = "emmc")]
+fn emmc_clock_divisors() {
+    assert_eq!(emmc::clock_divisor(400_000), 53);
+    assert_eq!(emmc::clock_divisor(25_000_000), 1);
+    assert_eq!(emmc::clock_divisor(emmc::BASE_CLOCK), 0);
+    assert_eq!(emmc::clock_divisor(1), 0x3FF);
+
+    // The card clock never exceeds the target.
+    for &target in [100_000, 400_000, 1_000_000, 20_000_000].iter() {
+        let divisor = emmc::clock_divisor(target);
+        assert!(emmc::BASE_CLOCK / (2 * divisor) <= target);
+    }
+
+    assert_eq!(emmc::divisor_bits(53), 53 << 8);
+    assert_eq!(emmc::divisor_bits(0x3FF), 0xFF << 8 | 0b11 << 6);
+}
+
+#[test]
+#[cfg(feature = "emmc")]
+fn emmc_sector_addresses() {
+    assert_eq!(emmc::sector_address(3, true), Ok(3));
+    assert_eq!(emmc::sector_address(3, false), Ok(3 * 512));
+    assert_eq!(emmc::sector_address(1 << 32, true), Err(Error::InvalidSector(1 << 32)));
+    assert_eq!(emmc::sector_address(1 << 23, false), Err(Error::InvalidSector(1 << 23)));
+    assert_eq!(emmc::sector_address((1 << 23) - 1, false), Ok(0xFFFFFE00));
+}
+
+#[test]
+#[cfg(feature = "emmc")]
+fn emmc_recovery() {
+    use emmc::CardState;
+
+    assert_eq!(emmc::card_state(4 << 9 | 1 << 8), CardState::Transfer);
+    assert_eq!(emmc::card_state(5 << 9), CardState::Data);
+    assert_eq!(emmc::card_state(7 << 9 | 1 << 22), CardState::Other(7));
+
+    // Each retry waits twice as long as the one before.
+    assert_eq!(emmc::retry_backoff(0), 5);
+    assert_eq!(emmc::retry_backoff(1), 10);
+    assert_eq!(emmc::retry_backoff(2), 20);
+    assert!(emmc::retry_backoff(100) > 0);
+}
+
+#[test]
+#[cfg(all(feature = "hal", feature = "uart"))]
+fn drivers_implement_embedded_hal() {
+    use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+    use embedded_hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin, ToggleableOutputPin};
+    use embedded_hal::serial;
+    use gpio::{Input, Output};
+    use hal::Delay;
+
+    fn serial<T: serial::Read<u8> + serial::Write<u8>>() {}
+    fn output<T: OutputPin + StatefulOutputPin + ToggleableOutputPin>() {}
+    fn input<T: InputPin>() {}
+    fn delay<T: DelayMs<u32> + DelayUs<u32> + DelayMs<u8> + DelayUs<u64>>() {}
+
+    serial::<MiniUart>();
+    output::<Gpio<Output>>();
+    input::<Gpio<Input>>();
+    delay::<Delay>();
+}
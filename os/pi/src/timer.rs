@@ -1,19 +1,50 @@
-use common::IO_BASE;
+use core::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use core::mem;
+use core::time::Duration;
+
+use common::{IO_BASE, register_layout};
+use interrupt::{Controller, Interrupt};
+use quiesce;
 use volatile::prelude::*;
 use volatile::{Volatile, ReadVolatile};
 
 /// The base address for the ARM system timer registers.
 const TIMER_REG_BASE: usize = IO_BASE + 0x3000;
 
-#[repr(C)]
-#[allow(non_snake_case)]
-struct Registers {
-    CS: Volatile<u32>,
-    CLO: ReadVolatile<u32>,
-    CHI: ReadVolatile<u32>,
-    COMPARE: [Volatile<u32>; 4]
+register_layout! {
+    struct Registers(0x1C) {
+        0x00 => CS: Volatile<u32>,
+        0x04 => CLO: ReadVolatile<u32>,
+        0x08 => CHI: ReadVolatile<u32>,
+        0x0C => COMPARE: [Volatile<u32>; 4],
+    }
+}
+
+/// A system timer compare channel free for the ARM to use. The GPU uses
+/// channels 0 and 2.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compare {
+    C1 = 1,
+    C3 = 3,
+}
+
+impl Compare {
+    /// Returns the interrupt raised when this channel matches.
+    pub fn interrupt(&self) -> Interrupt {
+        match *self {
+            Compare::C1 => Interrupt::Timer1,
+            Compare::C3 => Interrupt::Timer3,
+        }
+    }
 }
 
+/// A callback run by `handle_irq()` when a tick requested with `tick_in()`
+/// arrives.
+pub type TickHandler = fn();
+
+/// The registered `TickHandler` as a `usize`, or 0 for none.
+static TICK_HANDLER: AtomicUsize = ATOMIC_USIZE_INIT;
+
 /// The Raspberry Pi ARM system timer.
 pub struct Timer {
     registers: &'static mut Registers
@@ -33,17 +64,119 @@ impl Timer {
         return (self.registers.CHI.read() as u64) << 32
                 | (self.registers.CLO.read() as u64);
     }
+
+    /// Sets compare channel `channel` to match `us` microseconds from now and
+    /// clears any match already pending on it. The match raises
+    /// `channel.interrupt()` if that is enabled in the interrupt controller.
+    pub fn set_compare(&mut self, channel: Compare, us: u32) {
+        arm_compare(self.registers, channel, us);
+    }
+
+    /// Returns `true` if compare channel `channel` has matched since it was
+    /// last acknowledged.
+    pub fn has_matched(&self, channel: Compare) -> bool {
+        self.registers.CS.has_mask(1 << channel as u32)
+    }
+
+    /// Acknowledges a match on compare channel `channel`, lowering its
+    /// interrupt. Returns `true` if a match was pending.
+    pub fn acknowledge(&mut self, channel: Compare) -> bool {
+        acknowledge_match(self.registers, channel)
+    }
+}
+
+/// Sets `channel` in `registers` to match `us` microseconds after the current
+/// counter value, then clears any pending match on it.
+pub(crate) fn arm_compare(registers: &mut Registers, channel: Compare, us: u32) {
+    let target = registers.CLO.read().wrapping_add(us);
+    registers.COMPARE[channel as usize].write(target);
+    registers.CS.write(1 << channel as u32);
+}
+
+/// Clears a pending match on `channel` in `registers`, returning `true` if
+/// there was one. Match bits are cleared by writing 1s; 0s are ignored.
+pub(crate) fn acknowledge_match(registers: &mut Registers, channel: Compare) -> bool {
+    let matched = registers.CS.has_mask(1 << channel as u32);
+    if matched {
+        registers.CS.write(1 << channel as u32);
+    }
+
+    matched
+}
+
+/// Sets the callback `handle_irq()` runs for each tick.
+pub fn set_tick_handler(handler: TickHandler) {
+    TICK_HANDLER.store(handler as usize, Ordering::SeqCst);
+}
+
+/// Requests a tick `us` microseconds from now on compare channel 1, replacing
+/// any tick already requested, and enables its interrupt. An interrupt
+/// handler must call `handle_irq()` for the tick to run the handler set with
+/// `set_tick_handler()`; to tick periodically, the handler calls `tick_in()`
+/// again.
+pub fn tick_in(us: u32) {
+    Timer::new().set_compare(Compare::C1, us);
+    Controller::new().enable(Compare::C1.interrupt());
+
+    // If the hook table is full, the tick is simply left armed.
+    let _ = quiesce::register(shutdown);
+}
+
+/// The system timer's share of the IRQ handler for `Interrupt::Timer1`.
+/// Acknowledges a tick requested with `tick_in()` and runs the tick handler.
+/// Returns `true` if there was a tick.
+pub fn handle_irq() -> bool {
+    if !Timer::new().acknowledge(Compare::C1) {
+        return false;
+    }
+
+    let handler = TICK_HANDLER.load(Ordering::SeqCst);
+    if handler != 0 {
+        let handler: TickHandler = unsafe { mem::transmute(handler) };
+        handler();
+    }
+
+    true
+}
+
+/// Shutdown hook registered by `tick_in()`: disables and acknowledges the
+/// tick interrupt.
+fn shutdown() {
+    Controller::new().disable(Compare::C1.interrupt());
+    Timer::new().acknowledge(Compare::C1);
+}
+
+/// Returns `us` microseconds as a `Duration`.
+pub fn from_micros(us: u64) -> Duration {
+    Duration::new(us / 1_000_000, (us % 1_000_000) as u32 * 1000)
 }
 
-/// Returns the current time in microseconds.
-pub fn current_time() -> u64 {
+/// Returns `duration` in whole microseconds, saturating at `u64::MAX`.
+pub fn as_micros(duration: Duration) -> u64 {
+    duration.as_secs()
+        .saturating_mul(1_000_000)
+        .saturating_add((duration.subsec_nanos() / 1000) as u64)
+}
+
+/// Returns the time since the timer started counting.
+pub fn current_time() -> Duration {
+    from_micros(current_time_us())
+}
+
+/// Returns the time since the timer started counting in microseconds.
+pub fn current_time_us() -> u64 {
     Timer::new().read()
 }
 
+/// Spins until `duration` has passed.
+pub fn spin_sleep(duration: Duration) {
+    spin_sleep_us(as_micros(duration));
+}
+
 /// Spins until `us` microseconds have passed.
 pub fn spin_sleep_us(us: u64) {
     let timer = Timer::new();
-    let target: u64 = timer.read() + us;
+    let target: u64 = timer.read().saturating_add(us);
 
     while timer.read() <= target {
         // Spin...
@@ -52,5 +185,54 @@ pub fn spin_sleep_us(us: u64) {
 
 /// Spins until `ms` milliseconds have passed.
 pub fn spin_sleep_ms(ms: u64) {
-    spin_sleep_us(ms * 1000);
+    spin_sleep_us(ms.saturating_mul(1000));
+}
+
+/// A point in time after which a wait should give up.
+///
+/// ```rust,ignore
+/// let deadline = Deadline::after(Duration::from_millis(10));
+/// while !ready() {
+///     if deadline.expired() {
+///         return Err(Error::Timeout);
+///     }
+/// }
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Deadline {
+    end: Duration,
+}
+
+impl Deadline {
+    /// Returns a deadline `timeout` from now.
+    pub fn after(timeout: Duration) -> Deadline {
+        Deadline::at(current_time(), timeout)
+    }
+
+    /// Returns a deadline `timeout` after `now`.
+    pub fn at(now: Duration, timeout: Duration) -> Deadline {
+        let end = now.checked_add(timeout).unwrap_or(Duration::new(u64::max_value(), 0));
+        Deadline { end }
+    }
+
+    /// Returns `true` if the deadline has passed.
+    pub fn expired(&self) -> bool {
+        self.expired_at(current_time())
+    }
+
+    /// Returns the time left until the deadline, or zero if it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.remaining_at(current_time())
+    }
+
+    /// Returns `true` if the deadline has passed at time `now`.
+    pub fn expired_at(&self, now: Duration) -> bool {
+        now > self.end
+    }
+
+    /// Returns the time left until the deadline at time `now`, or zero if it
+    /// has passed.
+    pub fn remaining_at(&self, now: Duration) -> Duration {
+        self.end.checked_sub(now).unwrap_or(Duration::new(0, 0))
+    }
 }
@@ -1,39 +1,121 @@
 use core::fmt;
+use core::time::Duration;
+use core::sync::atomic::{AtomicBool, Ordering, ATOMIC_BOOL_INIT};
 
 use volatile::prelude::*;
 use volatile::{Volatile, ReadVolatile, Reserved};
 
-use timer;
-use common::IO_BASE;
-use gpio::{Gpio, Function};
+use timer::{self, Deadline};
+use common::{IO_BASE, register_layout};
+use gpio::{Gpio, Function, TXD1, RXD1, CTS1, RTS1};
+use quiesce;
+use interrupt::{Controller, Interrupt};
+use ring::RingBuffer;
+use error::{Error, Result, WouldBlock};
+
+pub use pl011::Pl011;
 
 /// The base address for the `MU` registers.
 const MU_REG_BASE: usize = IO_BASE + 0x215040;
 
 /// The `AUXENB` register from page 9 of the BCM2837 documentation.
-const AUX_ENABLES: *mut Volatile<u8> = (IO_BASE + 0x215004) as *mut Volatile<u8>;
+const AUX_ENABLES: *mut Volatile<u32> = (IO_BASE + 0x215004) as *mut Volatile<u32>;
+
+/// `AUXENB` bit enabling the mini UART. The register's other bits enable the
+/// SPI modules and must be left as they are.
+const AUX_ENABLE_MINI_UART: u32 = 1;
+
+/// `AUX_MU_IO_REG` field holding the byte read or written.
+const IO_DATA: (u32, u32) = (0, 8);
+
+/// `AUX_MU_LCR_REG` field selecting the data size. See `DataBits`.
+const LCR_DATA_SIZE: (u32, u32) = (0, 2);
+
+/// `AUX_MU_LCR_REG` bit holding the transmit line low.
+const LCR_BREAK: u32 = 1 << 6;
+
+/// `AUX_MU_CNTL_REG` bits enabling the receiver and transmitter.
+const CNTL_RX_ENABLE: u32 = 1;
+const CNTL_TX_ENABLE: u32 = 1 << 1;
+
+/// `AUX_MU_CNTL_REG` bits enabling receive flow control on RTS and transmit
+/// flow control on CTS, and making both lines active low.
+const CNTL_RX_AUTO_FLOW: u32 = 1 << 2;
+const CNTL_TX_AUTO_FLOW: u32 = 1 << 3;
+const CNTL_RTS_ACTIVE_LOW: u32 = 1 << 6;
+const CNTL_CTS_ACTIVE_LOW: u32 = 1 << 7;
+const CNTL_AUTO_FLOW: u32 = CNTL_RX_AUTO_FLOW | CNTL_TX_AUTO_FLOW
+    | CNTL_RTS_ACTIVE_LOW | CNTL_CTS_ACTIVE_LOW;
+
+/// `AUX_MU_BAUD_REG` field holding the baud rate divisor.
+const BAUD_DIVISOR: (u32, u32) = (0, 16);
+
+/// `AUX_MU_IER_REG` bits enabling the receive interrupt. Bit 0 enables it;
+/// the datasheet marks bits 2 and 3 as don't-care, but the errata notes that
+/// no interrupts are raised unless they are set.
+const IER_RX_INTERRUPT: u32 = 0b1101;
+
+/// The depth of the mini UART's receive FIFO.
+const RX_FIFO_DEPTH: usize = 8;
+
+/// The clock the mini UART's baud rate is derived from, in Hz.
+const SYSTEM_CLOCK: u32 = 250_000_000;
+
+/// Value for `AUX_MU_IIR_REG` clearing the receive and transmit FIFOs.
+const IIR_CLEAR_FIFOS: u32 = 0b110;
+
+/// The baud rate `new()` sets.
+pub const DEFAULT_BAUD: u32 = 115200;
+
+/// The divisor producing `DEFAULT_BAUD`: `baud_divisor(DEFAULT_BAUD)`.
+const DEFAULT_DIVISOR: u16 = 270;
+
+/// The number of data bits in each character, as the value of the
+/// `AUX_MU_LCR_REG` data size field.
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DataBits {
+    Seven = 0b00,
+    Eight = 0b11,
+}
+
+/// The number of stop bits after each character. The mini UART only
+/// supports `One`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/// Bytes received by `handle_irq()` and not yet read.
+static RX_BUFFER: RingBuffer = RingBuffer::new();
+
+/// Set while the receive interrupt is enabled. Reads then come from
+/// `RX_BUFFER` instead of the receive FIFO.
+static RX_INTERRUPTS: AtomicBool = ATOMIC_BOOL_INIT;
 
 /// Enum representing bit fields of the `AUX_MU_LSR_REG` register.
 #[repr(u8)]
 enum LsrStatus {
     DataReady = 1,
     TxAvailable = 1 << 5,
+    TxIdle = 1 << 6,
 }
 
-#[repr(C)]
-#[allow(non_snake_case)]
-struct Registers {
-    IO: Volatile<u32>, // IO read/write.
-    IER: Volatile<u32>, // Interrupt enable.
-    IIR: Volatile<u32>, // Interrupt status.
-    LCR: Volatile<u32>, // Line data format control.
-    MCR: Volatile<u32>, // Controls modem signals.
-    LSR: Volatile<u32>, // Data status.
-    MSR: ReadVolatile<u32>, // Modem status.
-    SCRATCH: Reserved<u32>, // Scratch register, not used.
-    CNTL: Volatile<u32>, // Control, provides access to additional features.
-    STAT: ReadVolatile<u32>, // miniUART status.
-    BAUD: Volatile<u32>, // Baud rate.
+register_layout! {
+    struct Registers(0x2C) {
+        0x00 => IO: Volatile<u32>, // IO read/write.
+        0x04 => IER: Volatile<u32>, // Interrupt enable.
+        0x08 => IIR: Volatile<u32>, // Interrupt status.
+        0x0C => LCR: Volatile<u32>, // Line data format control.
+        0x10 => MCR: Volatile<u32>, // Controls modem signals.
+        0x14 => LSR: Volatile<u32>, // Data status.
+        0x18 => MSR: ReadVolatile<u32>, // Modem status.
+        0x1C => SCRATCH: Reserved<u32>, // Scratch register, not used.
+        0x20 => CNTL: Volatile<u32>, // Control, provides access to additional features.
+        0x24 => STAT: ReadVolatile<u32>, // miniUART status.
+        0x28 => BAUD: Volatile<u32>, // Baud rate.
+    }
 }
 
 /// The Raspberry Pi's "mini UART".
@@ -51,23 +133,52 @@ impl MiniUart {
     /// By default, reads will never time out. To set a read timeout, use
     /// `set_read_timeout()`.
     pub fn new() -> MiniUart {
+        MiniUart::init(DEFAULT_DIVISOR, DataBits::Eight)
+    }
+
+    /// Initializes the mini UART like `new()`, but at the rate nearest `baud`
+    /// (see `baud_divisor()`) with `data_bits` data bits and `stop_bits` stop
+    /// bits. Nothing is changed if an error is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidBaud` if the mini UART can't produce `baud`, and
+    /// `Error::UnsupportedStopBits` for anything but `StopBits::One`.
+    pub fn with_config(baud: u32, data_bits: DataBits, stop_bits: StopBits) -> Result<MiniUart> {
+        if stop_bits != StopBits::One {
+            return Err(Error::UnsupportedStopBits);
+        }
+
+        let divisor = baud_divisor(baud)?;
+        Ok(MiniUart::init(divisor, data_bits))
+    }
+
+    /// Initializes the mini UART with baud rate divisor `divisor` and
+    /// `data_bits` data bits. See `new()`.
+    fn init(divisor: u16, data_bits: DataBits) -> MiniUart {
         let registers = unsafe {
             // Enable the mini UART as an auxiliary device.
-            (*AUX_ENABLES).or_mask(1);
+            (*AUX_ENABLES).set_bits(AUX_ENABLE_MINI_UART);
             &mut *(MU_REG_BASE as *mut Registers)
         };
 
-        // FIXME: Implement remaining mini UART initialization.
-        registers.LCR.write(0x3); // Enable 8-bit mode.
+        registers.LCR.write_field(LCR_DATA_SIZE.0, LCR_DATA_SIZE.1, data_bits as u32);
 
         // The baud register is (system_clock_rate / (8 * desired_baud) - 1)
         // For 115200, this is 270.
-        registers.BAUD.write(270);
+        registers.BAUD.write_field(BAUD_DIVISOR.0, BAUD_DIVISOR.1, divisor as u32);
 
-        Gpio::new(14).into_alt(Function::Alt5);
-        Gpio::new(15).into_alt(Function::Alt5);
+        unsafe {
+            Gpio::new_unchecked(TXD1).into_alt(Function::Alt5);
+            Gpio::new_unchecked(RXD1).into_alt(Function::Alt5);
+        }
+
+        // Written whole, so that flow control bits the firmware or a
+        // bootloader left set don't hold up the transmitter.
+        registers.CNTL.write(CNTL_RX_ENABLE | CNTL_TX_ENABLE);
 
-        registers.CNTL.write(0x3); // Enable RX/TX.
+        // If the hook table is full, the mini UART is simply left running.
+        let _ = quiesce::register(shutdown);
 
         MiniUart { registers, timeout: None }
     }
@@ -77,6 +188,109 @@ impl MiniUart {
         self.timeout = Some(milliseconds);
     }
 
+    /// Sets the baud rate divisor to `divisor`. The mini UART runs at
+    /// `system_clock_rate / (8 * (divisor + 1))` baud; `new()` sets 270.
+    ///
+    /// Bytes still in the transmit FIFO are sent at the new rate, so callers
+    /// switching rates mid-conversation should let the FIFO drain first.
+    pub fn set_baud_divisor(&mut self, divisor: u16) {
+        self.registers.BAUD.write_field(BAUD_DIVISOR.0, BAUD_DIVISOR.1, divisor as u32);
+    }
+
+    /// Sets the baud rate to the rate nearest `baud` that the mini UART can
+    /// produce. See `baud_divisor()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidBaud` if no divisor produces a rate near `baud`.
+    pub fn set_baud(&mut self, baud: u32) -> Result<()> {
+        let divisor = baud_divisor(baud)?;
+        self.set_baud_divisor(divisor);
+        Ok(())
+    }
+
+    /// Enables or disables RTS/CTS hardware flow control. While enabled, the
+    /// mini UART deasserts RTS when its receive FIFO is nearly full and only
+    /// transmits while CTS is asserted; both lines are active low.
+    ///
+    /// Enabling puts GPIO pins 16 (CTS1) and 17 (RTS1) into alternative
+    /// function 5, so they can't be used for anything else, such as a status
+    /// LED, at the same time. Disabling returns them to inputs.
+    pub fn set_flow_control(&mut self, enabled: bool) {
+        if enabled {
+            unsafe {
+                Gpio::new_unchecked(CTS1).into_alt(Function::Alt5);
+                Gpio::new_unchecked(RTS1).into_alt(Function::Alt5);
+            }
+
+            self.registers.CNTL.set_bits(CNTL_AUTO_FLOW);
+        } else {
+            self.registers.CNTL.clear_bits(CNTL_AUTO_FLOW);
+            unsafe {
+                Gpio::new_unchecked(CTS1).into_input();
+                Gpio::new_unchecked(RTS1).into_input();
+            }
+        }
+    }
+
+    /// Returns `true` if hardware flow control is enabled.
+    pub fn flow_control(&self) -> bool {
+        self.registers.CNTL.has_mask(CNTL_RX_AUTO_FLOW | CNTL_TX_AUTO_FLOW)
+    }
+
+    /// Switches reads to interrupt-driven mode: the mini UART raises an
+    /// interrupt whenever it receives a byte, the IRQ handler moves the byte
+    /// into a buffer with `handle_irq()`, and reads take bytes from that
+    /// buffer. Up to `ring::CAPACITY` bytes can be buffered; bytes arriving
+    /// while it's full are dropped and counted by `rx_dropped()`.
+    ///
+    /// The caller must route IRQs to a handler that calls `handle_irq()` and
+    /// unmask them; until then nothing is read.
+    pub fn enable_rx_interrupt(&mut self) {
+        RX_INTERRUPTS.store(true, Ordering::SeqCst);
+        self.registers.IER.set_bits(IER_RX_INTERRUPT);
+        Controller::new().enable(Interrupt::Aux);
+    }
+
+    /// Switches reads back to polling the receive FIFO. Bytes still in the
+    /// buffer are read first.
+    pub fn disable_rx_interrupt(&mut self) {
+        self.registers.IER.clear_bits(IER_RX_INTERRUPT);
+        Controller::new().disable(Interrupt::Aux);
+        RX_INTERRUPTS.store(false, Ordering::SeqCst);
+    }
+
+    /// Returns the number of received bytes dropped because the interrupt
+    /// buffer was full.
+    pub fn rx_dropped(&self) -> usize {
+        RX_BUFFER.dropped()
+    }
+
+    /// Holds the transmit line low for `duration_ms` milliseconds once every
+    /// byte written so far has been sent, signalling a break to the other end.
+    ///
+    /// The mini UART can't detect breaks it receives: they read as `0x00`
+    /// bytes. Use the PL011 to detect them.
+    pub fn send_break(&mut self, duration_ms: u64) {
+        self.wait_for_idle();
+        self.registers.LCR.set_bits(LCR_BREAK);
+        timer::spin_sleep_ms(duration_ms);
+        self.registers.LCR.clear_bits(LCR_BREAK);
+    }
+
+    /// Returns `true` if every byte written so far has been transmitted. This
+    /// method does not block.
+    pub fn is_idle(&self) -> bool {
+        self.registers.LSR.has_mask(LsrStatus::TxIdle as u32)
+    }
+
+    /// Blocks until every byte written so far has been transmitted.
+    pub fn wait_for_idle(&self) {
+        while !self.is_idle() {
+            continue
+        }
+    }
+
 /*     ///Write char
     pub fn write_str(&mut self, str: &String) {
         let bytes: &[u8] = str.as_bytes();
@@ -88,37 +302,49 @@ impl MiniUart {
     /// Write the byte `byte`. This method blocks until there is space available
     /// in the output FIFO.
     pub fn write_byte(&mut self, byte: u8) {
-         // Wait until the transmit FIFO can accept at least one byte.
-        while !self.registers.LSR.has_mask(LsrStatus::TxAvailable as u32) {
+        while self.try_write_byte(byte).is_err() {
             continue
         }
+    }
+
+    /// Writes the byte `byte` if the output FIFO has space for it. Returns
+    /// `Err(WouldBlock)`, and writes nothing, if it's full. This method does
+    /// not block.
+    pub fn try_write_byte(&mut self, byte: u8) -> ::core::result::Result<(), WouldBlock> {
+        if !self.registers.LSR.has_mask(LsrStatus::TxAvailable as u32) {
+            return Err(WouldBlock);
+        }
 
+        // A plain write: reading AUX_MU_IO, as a read-modify-write would,
+        // pops a byte from the receive FIFO.
         self.registers.IO.write(byte as u32);
+        Ok(())
     }
 
     /// Returns `true` if there is at least one byte ready to be read. If this
     /// method returns `true`, a subsequent call to `read_byte` is guaranteed to
     /// return immediately. This method does not block.
     pub fn has_byte(&self) -> bool {
-        self.registers.LSR.has_mask(LsrStatus::DataReady as u32)
+        !RX_BUFFER.is_empty() || (!RX_INTERRUPTS.load(Ordering::SeqCst)
+            && self.registers.LSR.has_mask(LsrStatus::DataReady as u32))
     }
 
     /// Blocks until there is a byte ready to read. If a read timeout is set,
     /// this method blocks for at most that amount of time. Otherwise, this
     /// method blocks indefinitely until there is a byte to read.
     ///
-    /// Returns `Ok(())` if a byte is ready to read. Returns `Err(())` if the
-    /// timeout expired while waiting for a byte to be ready. If this method
-    /// returns `Ok(())`, a subsequent call to `read_byte` is guaranteed to
-    /// return immediately.
-    pub fn wait_for_byte(&self) -> Result<(), ()> {
-        let start_time: u64 = timer::current_time();
+    /// Returns `Ok(())` if a byte is ready to read. Returns `Err(Error::Timeout)`
+    /// if the timeout expired while waiting for a byte to be ready. If this
+    /// method returns `Ok(())`, a subsequent call to `read_byte` is guaranteed
+    /// to return immediately.
+    pub fn wait_for_byte(&self) -> Result<()> {
+        let deadline = self.timeout.map(|ms| Deadline::after(Duration::from_millis(ms as u64)));
 
         while !self.has_byte() {
             // Check for timeout.
-            if let Some(duration) = self.timeout {
-                if timer::current_time() > start_time + (duration as u64) * 1000 {
-                    return Err(());
+            if let Some(ref deadline) = deadline {
+                if deadline.expired() {
+                    return Err(Error::Timeout);
                 }
             }
         }
@@ -128,12 +354,110 @@ impl MiniUart {
 
     /// Reads a byte. Blocks indefinitely until a byte is ready to be read.
     pub fn read_byte(&mut self) -> u8 {
-        while !self.has_byte() {
+        loop {
+            if let Some(byte) = self.try_read_byte() {
+                return byte;
+            }
+        }
+    }
+
+    /// Reads a byte if one is ready and returns `None` otherwise. This method
+    /// does not block.
+    pub fn try_read_byte(&mut self) -> Option<u8> {
+        if let Some(byte) = RX_BUFFER.pop() {
+            return Some(byte);
+        }
+
+        if RX_INTERRUPTS.load(Ordering::SeqCst)
+            || !self.registers.LSR.has_mask(LsrStatus::DataReady as u32) {
+            return None;
+        }
+
+        Some(self.registers.IO.read_field(IO_DATA.0, IO_DATA.1) as u8)
+    }
+
+    /// Reads as many bytes as are ready, up to `buf.len()`, into `buf` and
+    /// returns the number read. This method does not block.
+    pub fn read_buffered(&mut self, buf: &mut [u8]) -> usize {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.try_read_byte() {
+                Some(byte) => buf[read] = byte,
+                None => break,
+            }
+
+            read += 1;
+        }
+
+        read
+    }
+}
+
+/// Moves every byte in the mini UART's receive FIFO into the buffer read by
+/// `MiniUart` in interrupt-driven mode. This is the mini UART's share of the
+/// IRQ handler for `Interrupt::Aux`; reading the FIFO dry clears the interrupt.
+///
+/// Returns the number of bytes moved. Must not be called from anywhere but
+/// the IRQ handler, which is the buffer's only producer.
+pub fn handle_irq() -> usize {
+    drain_rx_fifo(unsafe { &mut *(MU_REG_BASE as *mut Registers) }, &RX_BUFFER)
+}
+
+/// Moves the bytes in `registers`' receive FIFO into `buffer`, stopping after
+/// a FIFO's worth so that a stuck status bit can't wedge the IRQ handler.
+pub(crate) fn drain_rx_fifo(registers: &mut Registers, buffer: &RingBuffer) -> usize {
+    let mut moved = 0;
+    while moved < RX_FIFO_DEPTH && registers.LSR.has_mask(LsrStatus::DataReady as u32) {
+        buffer.push(registers.IO.read_field(IO_DATA.0, IO_DATA.1) as u8);
+        moved += 1;
+    }
+
+    moved
+}
+
+/// Shutdown hook registered by `MiniUart::new()`. See `shutdown_registers()`.
+fn shutdown() {
+    shutdown_registers(unsafe { &mut *(MU_REG_BASE as *mut Registers) });
+    Controller::new().disable(Interrupt::Aux);
+    RX_INTERRUPTS.store(false, Ordering::SeqCst);
+}
+
+/// Waits for the transmitter to send everything it holds if it's enabled,
+/// then disables the receiver, the transmitter, flow control and interrupts,
+/// and clears both FIFOs. The line settings and baud rate are left as they
+/// are.
+pub(crate) fn shutdown_registers(registers: &mut Registers) {
+    if registers.CNTL.has_mask(CNTL_TX_ENABLE) {
+        while !registers.LSR.has_mask(LsrStatus::TxIdle as u32) {
             continue
         }
+    }
+
+    registers.CNTL.clear_bits(CNTL_RX_ENABLE | CNTL_TX_ENABLE | CNTL_AUTO_FLOW);
+    registers.IER.write(0);
+    registers.IIR.write(IIR_CLEAR_FIFOS);
+}
 
-        (self.registers.IO.read() & 0xFF) as u8
+/// Returns the value for `AUX_MU_BAUD_REG` giving the rate nearest `baud`:
+/// the mini UART runs at `SYSTEM_CLOCK / (8 * (divisor + 1))` baud.
+///
+/// # Errors
+///
+/// Returns `Error::InvalidBaud` if `baud` is zero, faster than the mini UART
+/// can run, or too slow for a 16-bit divisor.
+pub fn baud_divisor(baud: u32) -> Result<u16> {
+    if baud == 0 {
+        return Err(Error::InvalidBaud(baud));
     }
+
+    let clock = SYSTEM_CLOCK as u64;
+    let baud64 = baud as u64;
+    let ticks = (clock + 4 * baud64) / (8 * baud64);
+    if ticks == 0 || ticks - 1 > u16::max_value() as u64 {
+        return Err(Error::InvalidBaud(baud));
+    }
+
+    Ok((ticks - 1) as u16)
 }
 
 // FIXME: Implement `fmt::Write` for `MiniUart`. A b'\r' byte should be written
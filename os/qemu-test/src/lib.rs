@@ -0,0 +1,16 @@
+//! Host-side harness for testing the kernel under QEMU.
+//!
+//! `Qemu::launch()` boots a kernel image on an emulated Raspberry Pi 3 with
+//! the mini UART connected to a `Transport`, and a `Session` sends input to it
+//! and waits for expected output. Output is matched line by line after
+//! `normalize()` strips the parts that change from run to run.
+
+#[cfg(test)]
+mod tests;
+mod transport;
+mod qemu;
+mod session;
+
+pub use transport::{Transport, PipeTransport};
+pub use qemu::{Qemu, QEMU, qemu_available, qemu_args, kernel_image};
+pub use session::{Session, Error, normalize};
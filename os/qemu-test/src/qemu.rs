@@ -0,0 +1,95 @@
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use transport::PipeTransport;
+
+/// The QEMU binary that is run.
+pub const QEMU: &str = "qemu-system-aarch64";
+
+/// Returns `true` if `QEMU` can be run.
+pub fn qemu_available() -> bool {
+    Command::new(QEMU)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .unwrap_or(false)
+}
+
+/// Returns the arguments that boot `kernel` on an emulated Raspberry Pi 3 with
+/// the mini UART on standard input and output.
+///
+/// QEMU attaches its first serial port to the PL011 UART and its second to
+/// the mini UART, which is the one the kernel uses, so the first is discarded.
+pub fn qemu_args(kernel: &Path) -> Vec<OsString> {
+    let mut args: Vec<OsString> = ["-M", "raspi3b", "-display", "none",
+                                   "-serial", "null", "-serial", "stdio", "-kernel"]
+        .iter()
+        .map(OsString::from)
+        .collect();
+    args.push(kernel.as_os_str().to_owned());
+    args
+}
+
+/// Returns the path of the kernel image to boot: `$KERNEL_IMAGE` if it is
+/// set, and otherwise the kernel in `os/kernel`, after running `make` there.
+///
+/// # Errors
+///
+/// Returns a description of the problem if `make` can't be run or fails.
+pub fn kernel_image() -> Result<PathBuf, String> {
+    if let Some(image) = env::var_os("KERNEL_IMAGE") {
+        return Ok(PathBuf::from(image));
+    }
+
+    let kernel_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../kernel");
+    let status = Command::new("make").arg("-C").arg(&kernel_dir).status()
+        .map_err(|e| format!("couldn't run make: {}", e))?;
+    if !status.success() {
+        return Err(format!("building the kernel failed: make {}", status));
+    }
+
+    Ok(kernel_dir.join("build/kernel.elf"))
+}
+
+/// A running QEMU process. The process is killed when this is dropped.
+pub struct Qemu {
+    child: Child,
+}
+
+impl Qemu {
+    /// Boots `kernel` under QEMU and returns the process and a transport
+    /// connected to the mini UART.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if QEMU can't be started.
+    pub fn launch(kernel: &Path) -> io::Result<(Qemu, PipeTransport<ChildStdin>)> {
+        let mut child = Command::new(QEMU)
+            .args(qemu_args(kernel))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+
+        let stdin = child.stdin.take().expect("piped stdin");
+        let stdout = child.stdout.take().expect("piped stdout");
+        Ok((Qemu { child }, PipeTransport::new(stdin, stdout)))
+    }
+
+    /// Returns `true` if QEMU is still running.
+    pub fn is_running(&mut self) -> bool {
+        self.child.try_wait().map(|status| status.is_none()).unwrap_or(false)
+    }
+}
+
+impl Drop for Qemu {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
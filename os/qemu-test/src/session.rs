@@ -0,0 +1,201 @@
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use transport::Transport;
+
+/// The log level names `normalize()` strips from the start of a line.
+const LEVELS: [&str; 5] = ["TRACE", "DEBUG", "INFO", "WARN", "ERROR"];
+
+/// The ways waiting for output can fail.
+#[derive(Debug)]
+pub enum Error {
+    /// The pattern didn't appear in time. Holds the pattern and the
+    /// normalized output that arrived while waiting.
+    Timeout(String, String),
+    /// The guest's output ended before the pattern appeared. Holds the same
+    /// as `Timeout`.
+    Closed(String, String),
+    /// Talking to the guest failed.
+    Io(io::Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Timeout(ref pattern, ref output) => {
+                write!(f, "timed out waiting for {:?}; output was:\n{}", pattern, output)
+            }
+            Error::Closed(ref pattern, ref output) => {
+                write!(f, "output ended before {:?}; output was:\n{}", pattern, output)
+            }
+            Error::Io(ref error) => write!(f, "I/O error: {}", error),
+        }
+    }
+}
+
+/// Returns `line` without the parts that vary between runs or don't show on a
+/// terminal: ANSI escape sequences and other control characters, a leading
+/// `[ seconds.micros ]` timestamp, a leading log level such as `INFO:` or
+/// `[WARN]`, and surrounding whitespace. Backspaces erase the character before
+/// them, as they would on a terminal.
+pub fn normalize(line: &str) -> String {
+    let mut text = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                // Skip `ESC [ params letter`, or just the escaped character.
+                if chars.peek() == Some(&'[') {
+                    chars.next();
+                    for c in chars.by_ref() {
+                        if c.is_ascii_alphabetic() {
+                            break;
+                        }
+                    }
+                } else {
+                    chars.next();
+                }
+            }
+            '\x08' => { text.pop(); }
+            c if c.is_control() => {  }
+            c => text.push(c),
+        }
+    }
+
+    let mut rest = text.trim();
+    if rest.starts_with('[') {
+        if let Some(end) = rest.find(']') {
+            let inside = rest[1..end].trim();
+            if !inside.is_empty() && inside.chars().all(|c| c.is_ascii_digit() || c == '.') {
+                rest = rest[end + 1..].trim();
+            }
+        }
+    }
+
+    for level in LEVELS.iter() {
+        let bracketed = format!("[{}]", level);
+        let colon = format!("{}:", level);
+        if rest.starts_with(&bracketed) {
+            rest = rest[bracketed.len()..].trim();
+            break;
+        } else if rest.starts_with(&colon) {
+            rest = rest[colon.len()..].trim();
+            break;
+        }
+    }
+
+    rest.to_string()
+}
+
+/// A conversation with the guest over a `Transport`.
+pub struct Session<T> {
+    transport: T,
+    /// Output received but not yet consumed by `expect()`.
+    pending: String,
+}
+
+impl<T: Transport> Session<T> {
+    /// Returns a session over `transport`.
+    pub fn new(transport: T) -> Session<T> {
+        Session { transport, pending: String::new() }
+    }
+
+    /// Returns the transport this session talks over.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Sends `line` followed by a carriage return, as typed at a terminal.
+    pub fn send_line(&mut self, line: &str) -> io::Result<()> {
+        self.transport.send(line.as_bytes())?;
+        self.transport.send(b"\r")
+    }
+
+    /// Waits at most `timeout` for a line whose normalized text contains
+    /// `pattern`, and returns that normalized line. The line may be the last,
+    /// unfinished one, so that prompts can be matched. Output up to and
+    /// including the matched line is consumed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Timeout` or `Error::Closed` holding the output seen
+    /// while waiting if the pattern doesn't appear, and `Error::Io` if
+    /// receiving fails.
+    pub fn expect(&mut self, pattern: &str, timeout: Duration) -> Result<String, Error> {
+        self.wait_for(pattern, timeout, true, |line| line.contains(pattern))
+    }
+
+    /// Like `expect()`, but waits for a finished line whose normalized text is
+    /// exactly `text`. Use this for command output, which would otherwise
+    /// match the echo of the command itself.
+    pub fn expect_line(&mut self, text: &str, timeout: Duration) -> Result<String, Error> {
+        self.wait_for(text, timeout, false, |line| line == text)
+    }
+
+    /// Waits at most `timeout` for a normalized line for which `matches`
+    /// returns `true`, considering the unfinished last line if `partial`.
+    /// `pattern` describes what is being waited for in errors.
+    fn wait_for<F>(&mut self, pattern: &str, timeout: Duration, partial: bool,
+                   matches: F) -> Result<String, Error>
+        where F: Fn(&str) -> bool
+    {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(line) = self.take_match(partial, &matches) {
+                return Ok(line);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::Timeout(pattern.to_string(), self.pending_text()));
+            }
+
+            match self.transport.recv(deadline - now) {
+                Ok(bytes) => self.pending.push_str(&String::from_utf8_lossy(&bytes)),
+                Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {  }
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Err(Error::Closed(pattern.to_string(), self.pending_text()));
+                }
+                Err(e) => return Err(Error::Io(e)),
+            }
+        }
+    }
+
+    /// Finds the first pending line for which `matches` returns `true`,
+    /// consumes the output through it, and returns it normalized. The
+    /// unfinished last line is only considered if `partial`.
+    fn take_match<F>(&mut self, partial: bool, matches: &F) -> Option<String>
+        where F: Fn(&str) -> bool
+    {
+        let mut start = 0;
+        while start < self.pending.len() {
+            let end = match self.pending[start..].find('\n') {
+                Some(i) => start + i + 1,
+                None if partial => self.pending.len(),
+                None => break,
+            };
+
+            let line = normalize(&self.pending[start..end]);
+            if matches(&line) {
+                self.pending.drain(..end);
+                return Some(line);
+            }
+
+            start = end;
+        }
+
+        None
+    }
+
+    /// Returns the pending output, one normalized line per line.
+    fn pending_text(&self) -> String {
+        self.pending.lines().map(normalize).collect::<Vec<_>>().join("\n")
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+use std::io::{self, Cursor, Read};
+use std::thread;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use {Transport, PipeTransport, Session, Error, normalize, qemu_args};
+
+/// A transport that replays scripted output and records input. Once the
+/// script runs out, receives time out, or report end of file if `closed`.
+struct Script {
+    output: VecDeque<&'static str>,
+    input: Vec<u8>,
+    closed: bool,
+}
+
+impl Script {
+    fn new(output: &[&'static str]) -> Script {
+        Script { output: output.iter().cloned().collect(), input: vec![], closed: false }
+    }
+}
+
+impl Transport for Script {
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.input.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn recv(&mut self, _timeout: Duration) -> io::Result<Vec<u8>> {
+        match self.output.pop_front() {
+            Some(chunk) => Ok(chunk.as_bytes().to_vec()),
+            None if self.closed => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "closed")),
+            None => Err(io::Error::new(io::ErrorKind::TimedOut, "timed out")),
+        }
+    }
+}
+
+/// A reader that never produces anything.
+struct Stalled;
+
+impl Read for Stalled {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            thread::sleep(Duration::from_secs(3600));
+        }
+    }
+}
+
+/// How long the scripted tests wait for output.
+fn wait() -> Duration {
+    Duration::from_millis(50)
+}
+
+#[test]
+fn normalize_strips_varying_prefixes() {
+    assert_eq!(normalize("hello\r\n"), "hello");
+    assert_eq!(normalize("[    0.001234] booted"), "booted");
+    assert_eq!(normalize("[12.5] INFO: booted"), "booted");
+    assert_eq!(normalize("[WARN] low memory"), "low memory");
+    assert_eq!(normalize("ERROR:  disk  failed "), "disk  failed");
+    assert_eq!(normalize("\x1b[1;31mred\x1b[0m text"), "red text");
+    assert_eq!(normalize("helx\x08lo\x07"), "hello");
+}
+
+#[test]
+fn normalize_keeps_meaningful_brackets() {
+    assert_eq!(normalize("[a] list"), "[a] list");
+    assert_eq!(normalize("[] empty"), "[] empty");
+    assert_eq!(normalize("INFORMATION: kept"), "INFORMATION: kept");
+    assert_eq!(normalize("note: INFO: kept"), "note: INFO: kept");
+}
+
+#[test]
+fn expect_matches_across_chunks_and_consumes() {
+    let mut session = Session::new(Script::new(&["[0.1] OS,O", "S,OS\r\n->", "echo hi\r\nhi\r\n"]));
+    assert_eq!(session.expect("OS,OS,OS", wait()).unwrap(), "OS,OS,OS");
+    assert_eq!(session.expect("->", wait()).unwrap(), "->");
+    assert_eq!(session.expect_line("hi", wait()).unwrap(), "hi");
+    match session.expect("hi", wait()) {
+        Err(Error::Timeout(..)) => {  }
+        other => panic!("expected a timeout, got {:?}", other),
+    }
+}
+
+#[test]
+fn expect_line_skips_echo_and_unfinished_lines() {
+    let mut session = Session::new(Script::new(&["->echo hello\r\n", "hello wor", "ld\r\nhello\r\n"]));
+    assert_eq!(session.expect_line("hello", wait()).unwrap(), "hello");
+
+    let mut session = Session::new(Script::new(&["hello"]));
+    assert!(session.expect_line("hello", wait()).is_err());
+}
+
+#[test]
+fn expect_reports_output_on_failure() {
+    let mut script = Script::new(&["one\r\n", "two"]);
+    script.closed = true;
+    let mut session = Session::new(script);
+    match session.expect("three", wait()) {
+        Err(Error::Closed(pattern, output)) => {
+            assert_eq!(pattern, "three");
+            assert_eq!(output, "one\ntwo");
+        }
+        other => panic!("expected end of output, got {:?}", other),
+    }
+
+    let mut session = Session::new(Script::new(&["[1.0] INFO: one\r\n"]));
+    let error = session.expect("two", wait()).unwrap_err();
+    assert_eq!(error.to_string(), "timed out waiting for \"two\"; output was:\none");
+}
+
+#[test]
+fn expect_honors_timeout() {
+    let mut session = Session::new(PipeTransport::new(vec![], Stalled));
+    let start = Instant::now();
+    assert!(session.expect("never", Duration::from_millis(100)).is_err());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn send_line_ends_with_carriage_return() {
+    let mut session = Session::new(Script::new(&[]));
+    session.send_line("echo hello").unwrap();
+    assert_eq!(session.transport().input, b"echo hello\r");
+}
+
+#[test]
+fn pipe_transport_forwards_output_then_eof() {
+    let mut transport = PipeTransport::new(vec![], Cursor::new(b"boot".to_vec()));
+    assert_eq!(transport.recv(Duration::from_secs(5)).unwrap(), b"boot");
+    let error = transport.recv(Duration::from_secs(5)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn qemu_uses_mini_uart_on_stdio() {
+    let args = qemu_args(Path::new("kernel.elf"));
+    let args: Vec<_> = args.iter().map(|arg| arg.to_str().unwrap()).collect();
+    assert_eq!(args, ["-M", "raspi3b", "-display", "none", "-serial", "null",
+                      "-serial", "stdio", "-kernel", "kernel.elf"]);
+}
@@ -0,0 +1,76 @@
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// A connection to the guest's serial port.
+pub trait Transport {
+    /// Writes `bytes` to the guest.
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()>;
+
+    /// Returns the next bytes written by the guest, waiting at most `timeout`
+    /// for them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `TimedOut` if nothing arrives in time and of
+    /// kind `UnexpectedEof` once the guest's output has ended.
+    fn recv(&mut self, timeout: Duration) -> io::Result<Vec<u8>>;
+}
+
+/// A `Transport` over a pair of pipes, such as a child process's standard
+/// input and output.
+///
+/// The output pipe is read by a background thread so that waits can time out
+/// no matter how the pipe behaves.
+pub struct PipeTransport<W> {
+    input: W,
+    output: Receiver<io::Result<Vec<u8>>>,
+}
+
+/// The most bytes forwarded from the output pipe at once.
+const CHUNK_LEN: usize = 512;
+
+impl<W: Write> PipeTransport<W> {
+    /// Returns a transport sending to `input` and receiving from `output`.
+    pub fn new<R: Read + Send + 'static>(input: W, mut output: R) -> PipeTransport<W> {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buf = [0u8; CHUNK_LEN];
+            loop {
+                let result = match output.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => Ok(buf[..n].to_vec()),
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => Err(e),
+                };
+
+                let failed = result.is_err();
+                if tx.send(result).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        PipeTransport { input, output: rx }
+    }
+}
+
+impl<W: Write> Transport for PipeTransport<W> {
+    fn send(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.input.write_all(bytes)?;
+        self.input.flush()
+    }
+
+    fn recv(&mut self, timeout: Duration) -> io::Result<Vec<u8>> {
+        match self.output.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, "no output from the guest"))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the guest's output ended"))
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Boots the kernel under QEMU and drives its shell over the mini UART.
+//!
+//! Each test is skipped, passing, when `qemu-system-aarch64` isn't installed.
+//! The kernel is built with `make` unless `$KERNEL_IMAGE` names an image.
+
+extern crate qemu_test;
+
+use std::process::ChildStdin;
+use std::time::Duration;
+
+use qemu_test::{Qemu, PipeTransport, Session, QEMU, qemu_available, kernel_image};
+
+/// How long the kernel may take to boot to its prompt.
+fn boot_timeout() -> Duration {
+    Duration::from_secs(20)
+}
+
+/// How long a command may take to respond.
+fn reply_timeout() -> Duration {
+    Duration::from_secs(5)
+}
+
+/// Boots the kernel and waits for the shell's prompt. Returns `None` if QEMU
+/// isn't installed.
+fn boot() -> Option<(Qemu, Session<PipeTransport<ChildStdin>>)> {
+    if !qemu_available() {
+        eprintln!("skipping: {} is not installed", QEMU);
+        return None;
+    }
+
+    let image = kernel_image().expect("kernel image");
+    let (qemu, transport) = Qemu::launch(&image).expect("launch QEMU");
+    let mut session = Session::new(transport);
+    if let Err(e) = session.expect("->", boot_timeout()) {
+        panic!("no shell prompt: {}", e);
+    }
+
+    Some((qemu, session))
+}
+
+/// Unwraps `result`, panicking with the output seen so far if it failed.
+fn check<T>(result: Result<T, qemu_test::Error>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn boot_banner_appears() {
+    if !qemu_available() {
+        eprintln!("skipping: {} is not installed", QEMU);
+        return;
+    }
+
+    let image = kernel_image().expect("kernel image");
+    let (_qemu, transport) = Qemu::launch(&image).expect("launch QEMU");
+    let mut session = Session::new(transport);
+    check(session.expect_line("OS,OS,OS", boot_timeout()));
+    check(session.expect("->", reply_timeout()));
+}
+
+#[test]
+fn echo_round_trips() {
+    let (_qemu, mut session) = match boot() {
+        Some(booted) => booted,
+        None => return,
+    };
+
+    session.send_line("echo hello").expect("send");
+    check(session.expect_line("hello", reply_timeout()));
+    session.send_line("echo a  b c").expect("send");
+    check(session.expect_line("a b c", reply_timeout()));
+}
+
+#[test]
+fn unknown_command_is_reported() {
+    let (_qemu, mut session) = match boot() {
+        Some(booted) => booted,
+        None => return,
+    };
+
+    session.send_line("frobnicate now").expect("send");
+    check(session.expect_line("Unknown command: frobnicate; type 'help' for a list",
+                              reply_timeout()));
+    check(session.expect("->", reply_timeout()));
+}
+
+#[test]
+fn panic_is_reported() {
+    let (_qemu, mut session) = match boot() {
+        Some(booted) => booted,
+        None => return,
+    };
+
+    session.send_line("crash panic --yes").expect("send");
+    let prefix = "!!! kernel panic on core 0 at src/shell.rs:";
+    let header = check(session.expect(prefix, reply_timeout()));
+    let numbers: Vec<&str> = header.split(prefix).nth(1).unwrap_or("").split(':').collect();
+    assert_eq!(numbers.len(), 2, "line and column in {:?}", header);
+    assert!(numbers.iter().all(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit())),
+            "line and column in {:?}", header);
+
+    check(session.expect_line("crash requested from the shell", reply_timeout()));
+    let first = check(session.expect("x00: 0x", reply_timeout()));
+    assert!(first.starts_with("x00: 0x") && first.contains("  x03: 0x"), "{:?}", first);
+    let last = check(session.expect("sp: 0x", reply_timeout()));
+    assert!(last.starts_with("sp: 0x") && last.contains("  el: ") && last.contains("  daif: 0x"),
+            "{:?}", last);
+}
@@ -0,0 +1,18 @@
+//! Collection types.
+//!
+//! TODO: This is an addition. Only the collections from `alloc` are
+//! available; `HashMap` and `HashSet` need a source of randomness for their
+//! hashers, which the kernel doesn't have.
+
+#![stable(feature = "rust1", since = "1.0.0")]
+
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use alloc::Bound;
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use alloc::{BinaryHeap, BTreeMap, BTreeSet};
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use alloc::{LinkedList, VecDeque};
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use alloc::{binary_heap, btree_map, btree_set};
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use alloc::{linked_list, vec_deque};
@@ -0,0 +1,222 @@
+//! Platform-specific strings.
+//!
+//! TODO: This is an addition. The kernel is its own platform, and its
+//! strings are arbitrary bytes that are usually, but not always, UTF-8. Only
+//! `OsStr` and `OsString` are provided; there is no C FFI.
+
+#![stable(feature = "rust1", since = "1.0.0")]
+
+use borrow::{Borrow, Cow};
+use fmt;
+use ops::Deref;
+use str;
+
+/// An owned, mutable platform string: a sequence of bytes.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[stable(feature = "rust1", since = "1.0.0")]
+pub struct OsString {
+    inner: Vec<u8>,
+}
+
+/// A borrowed platform string: a slice of bytes.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[stable(feature = "rust1", since = "1.0.0")]
+pub struct OsStr {
+    inner: [u8],
+}
+
+impl OsString {
+    /// Creates a new, empty `OsString`.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn new() -> OsString {
+        OsString { inner: Vec::new() }
+    }
+
+    /// Converts `bytes` into an `OsString` without copying.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn from_vec(bytes: Vec<u8>) -> OsString {
+        OsString { inner: bytes }
+    }
+
+    /// Converts to an `OsStr` slice.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn as_os_str(&self) -> &OsStr {
+        self
+    }
+
+    /// Converts into a `String` if it contains valid UTF-8 data. On failure,
+    /// ownership of the original `OsString` is returned.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn into_string(self) -> Result<String, OsString> {
+        String::from_utf8(self.inner).map_err(|e| OsString { inner: e.into_bytes() })
+    }
+
+    /// Converts into the underlying bytes without copying.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn into_vec(self) -> Vec<u8> {
+        self.inner
+    }
+
+    /// Extends the string with `s`.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn push<T: AsRef<OsStr>>(&mut self, s: T) {
+        self.inner.extend_from_slice(&s.as_ref().inner)
+    }
+
+    /// Truncates the string to `len` bytes.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn truncate(&mut self, len: usize) {
+        self.inner.truncate(len)
+    }
+
+    /// Removes every byte.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn clear(&mut self) {
+        self.inner.clear()
+    }
+}
+
+impl OsStr {
+    /// Coerces into an `OsStr` slice.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn new<S: AsRef<OsStr> + ?Sized>(s: &S) -> &OsStr {
+        s.as_ref()
+    }
+
+    /// Wraps `bytes` into an `OsStr` without copying.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn from_bytes(bytes: &[u8]) -> &OsStr {
+        unsafe { &*(bytes as *const [u8] as *const OsStr) }
+    }
+
+    /// Yields a `&str` slice if the `OsStr` is valid UTF-8.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn to_str(&self) -> Option<&str> {
+        str::from_utf8(&self.inner).ok()
+    }
+
+    /// Converts to a `Cow<str>`, replacing invalid UTF-8 with U+FFFD.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn to_string_lossy(&self) -> Cow<str> {
+        String::from_utf8_lossy(&self.inner)
+    }
+
+    /// Copies the slice into an owned `OsString`.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn to_os_string(&self) -> OsString {
+        OsString { inner: self.inner.to_vec() }
+    }
+
+    /// Returns the underlying bytes.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.inner
+    }
+
+    /// Returns `true` if the string has a length of zero.
+    #[stable(feature = "osstring_simple_functions", since = "1.9.0")]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns the length of the string in bytes.
+    #[stable(feature = "osstring_simple_functions", since = "1.9.0")]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl Deref for OsString {
+    type Target = OsStr;
+
+    fn deref(&self) -> &OsStr {
+        OsStr::from_bytes(&self.inner)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl Borrow<OsStr> for OsString {
+    fn borrow(&self) -> &OsStr {
+        self
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl ToOwned for OsStr {
+    type Owned = OsString;
+
+    fn to_owned(&self) -> OsString {
+        self.to_os_string()
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl From<String> for OsString {
+    fn from(s: String) -> OsString {
+        OsString { inner: s.into_bytes() }
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a, T: ?Sized + AsRef<OsStr>> From<&'a T> for OsString {
+    fn from(s: &'a T) -> OsString {
+        s.as_ref().to_os_string()
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<OsStr> for OsStr {
+    fn as_ref(&self) -> &OsStr {
+        self
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<OsStr> for OsString {
+    fn as_ref(&self) -> &OsStr {
+        self
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<OsStr> for str {
+    fn as_ref(&self) -> &OsStr {
+        OsStr::from_bytes(self.as_bytes())
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<OsStr> for String {
+    fn as_ref(&self) -> &OsStr {
+        (&**self).as_ref()
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl PartialEq<str> for OsStr {
+    fn eq(&self, other: &str) -> bool {
+        self.inner == *other.as_bytes()
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl PartialEq<str> for OsString {
+    fn eq(&self, other: &str) -> bool {
+        &**self == other
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl fmt::Debug for OsStr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&*self.to_string_lossy(), f)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl fmt::Debug for OsString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
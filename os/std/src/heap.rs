@@ -0,0 +1,8 @@
+//! Memory allocation APIs.
+//!
+//! TODO: This is an addition. There is no `System` allocator: the kernel
+//! provides the global allocator with `#[global_allocator]`.
+
+#![unstable(issue = "32838", feature = "allocator_api")]
+
+pub use alloc::heap::{Heap, Alloc, Layout, Excess, CannotReallocInPlace, AllocErr};
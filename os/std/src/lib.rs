@@ -120,9 +120,9 @@ use prelude::v1::*;
                  debug_assert_ne, unreachable, unimplemented, write, writeln, try)]
 extern crate core as __core;
 
-// #[macro_use]
-// #[macro_reexport(vec, format)]
-// extern crate alloc;
+#[macro_use]
+#[macro_reexport(vec, format)]
+extern crate alloc;
 // extern crate alloc_system;
 extern crate std_unicode;
 // #[doc(masked)]
@@ -207,12 +207,12 @@ pub use core::u16;
 pub use core::u32;
 #[stable(feature = "rust1", since = "1.0.0")]
 pub use core::u64;
-// #[stable(feature = "rust1", since = "1.0.0")]
-// pub use alloc::boxed;
-// #[stable(feature = "rust1", since = "1.0.0")]
-// pub use alloc::rc;
-// #[stable(feature = "rust1", since = "1.0.0")]
-// pub use alloc::borrow;
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use alloc::boxed;
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use alloc::rc;
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use alloc::borrow;
 // #[stable(feature = "rust1", since = "1.0.0")]
 // pub use alloc::fmt;
 #[stable(feature = "rust1", since = "1.0.0")]
@@ -221,10 +221,10 @@ pub use core::fmt;
 // pub use alloc::slice;
 // #[stable(feature = "rust1", since = "1.0.0")]
 // pub use alloc::str;
-// #[stable(feature = "rust1", since = "1.0.0")]
-// pub use alloc::string;
-// #[stable(feature = "rust1", since = "1.0.0")]
-// pub use alloc::vec;
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use alloc::string;
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use alloc::vec;
 #[stable(feature = "rust1", since = "1.0.0")]
 pub use std_unicode::char;
 #[unstable(feature = "i128", issue = "35118")]
@@ -240,21 +240,21 @@ pub mod slice;
 // #[macro_use]
 // pub mod thread;
 // pub mod ascii;
-// pub mod collections;
+pub mod collections;
 // pub mod env;
 // pub mod error;
-// pub mod ffi;
+pub mod ffi;
 // pub mod fs;
 pub mod io;
 // pub mod net;
 // pub mod num;
 // pub mod os;
 // pub mod panic;
-// pub mod path;
+pub mod path;
 // pub mod process;
 pub mod sync;
-// pub mod time;
-// pub mod heap;
+pub mod time;
+pub mod heap;
 
 // // Platform-abstraction modules
 // #[macro_use]
@@ -0,0 +1,515 @@
+//! Cross-platform path manipulation.
+//!
+//! TODO: This is an addition. Paths follow Unix conventions only: `/` is the
+//! sole separator and there are no prefixes.
+
+#![stable(feature = "rust1", since = "1.0.0")]
+
+use borrow::{Borrow, Cow};
+use ffi::{OsStr, OsString};
+use fmt;
+use ops::Deref;
+
+/// The separator of path components.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub const MAIN_SEPARATOR: char = '/';
+
+/// A single component of a path.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[stable(feature = "rust1", since = "1.0.0")]
+pub enum Component<'a> {
+    /// The root directory, from a leading `/`.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    RootDir,
+    /// A `.` at the start of a relative path. Other `.`s are dropped.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    CurDir,
+    /// A `..`.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    ParentDir,
+    /// A file or directory name.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    Normal(#[stable(feature = "rust1", since = "1.0.0")] &'a OsStr),
+}
+
+impl<'a> Component<'a> {
+    /// Extracts the underlying `OsStr` slice.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn as_os_str(self) -> &'a OsStr {
+        match self {
+            Component::RootDir => OsStr::new("/"),
+            Component::CurDir => OsStr::new("."),
+            Component::ParentDir => OsStr::new(".."),
+            Component::Normal(name) => name,
+        }
+    }
+
+    /// Classifies the name `name` found at byte `start` of its path.
+    fn from_name(name: &'a [u8], start: usize) -> Option<Component<'a>> {
+        match name {
+            b"." if start == 0 => Some(Component::CurDir),
+            b"." => None,
+            b".." => Some(Component::ParentDir),
+            _ => Some(Component::Normal(OsStr::from_bytes(name))),
+        }
+    }
+}
+
+/// An iterator over the components of a path, from either end.
+///
+/// Repeated separators and `.`s other than a leading one are skipped, so
+/// `a//b/./c/` has the components `a`, `b` and `c`.
+#[derive(Clone)]
+#[stable(feature = "rust1", since = "1.0.0")]
+pub struct Components<'a> {
+    path: &'a [u8],
+    /// The start of the components not yet yielded from the front.
+    front: usize,
+    /// The end of the components not yet yielded from the back.
+    back: usize,
+    /// Whether the root directory is yet to be yielded.
+    root: bool,
+}
+
+impl<'a> Components<'a> {
+    /// Extracts a slice corresponding to the portion of the path remaining
+    /// for iteration.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn as_path(&self) -> &'a Path {
+        let mut start = if self.root { 0 } else { self.front };
+        let mut end = self.back;
+
+        // Separators and skipped `.`s aren't part of the remaining components.
+        while !self.root {
+            while start < end && self.path[start] == b'/' {
+                start += 1;
+            }
+
+            if start > 0 && start < end && self.path[start] == b'.'
+                && (start + 1 == end || self.path[start + 1] == b'/') {
+                start += 1;
+            } else {
+                break;
+            }
+        }
+
+        // The root is a separator too, but it stays.
+        let min_end = if self.root { 1 } else { start };
+        loop {
+            while end > min_end && self.path[end - 1] == b'/' {
+                end -= 1;
+            }
+
+            if end > min_end && end >= 2 && &self.path[end - 2..end] == b"/." {
+                end -= 1;
+            } else {
+                break;
+            }
+        }
+
+        Path::from_bytes(&self.path[start..end])
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a> Iterator for Components<'a> {
+    type Item = Component<'a>;
+
+    fn next(&mut self) -> Option<Component<'a>> {
+        if self.root {
+            self.root = false;
+            return Some(Component::RootDir);
+        }
+
+        loop {
+            while self.front < self.back && self.path[self.front] == b'/' {
+                self.front += 1;
+            }
+
+            if self.front >= self.back {
+                return None;
+            }
+
+            let start = self.front;
+            let end = self.path[start..self.back].iter().position(|&b| b == b'/')
+                .map_or(self.back, |i| start + i);
+            self.front = end;
+
+            if let Some(component) = Component::from_name(&self.path[start..end], start) {
+                return Some(component);
+            }
+        }
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a> DoubleEndedIterator for Components<'a> {
+    fn next_back(&mut self) -> Option<Component<'a>> {
+        loop {
+            while self.back > self.front && self.path[self.back - 1] == b'/' {
+                self.back -= 1;
+            }
+
+            if self.back <= self.front {
+                if self.root {
+                    self.root = false;
+                    return Some(Component::RootDir);
+                }
+
+                return None;
+            }
+
+            let end = self.back;
+            let start = self.path[self.front..end].iter().rposition(|&b| b == b'/')
+                .map_or(self.front, |i| self.front + i + 1);
+            self.back = start;
+
+            if let Some(component) = Component::from_name(&self.path[start..end], start) {
+                return Some(component);
+            }
+        }
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a> fmt::Debug for Components<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+/// A slice of a path.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub struct Path {
+    inner: OsStr,
+}
+
+/// An owned, mutable path.
+#[derive(Clone, Default)]
+#[stable(feature = "rust1", since = "1.0.0")]
+pub struct PathBuf {
+    inner: OsString,
+}
+
+impl Path {
+    /// Directly wraps a string slice as a `Path` slice.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn new<S: AsRef<OsStr> + ?Sized>(s: &S) -> &Path {
+        Path::from_bytes(s.as_ref().as_bytes())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> &Path {
+        unsafe { &*(bytes as *const [u8] as *const Path) }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        self.inner.as_bytes()
+    }
+
+    /// Yields the underlying `OsStr` slice.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn as_os_str(&self) -> &OsStr {
+        &self.inner
+    }
+
+    /// Yields a `&str` slice if the `Path` is valid UTF-8.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn to_str(&self) -> Option<&str> {
+        self.inner.to_str()
+    }
+
+    /// Converts to a `Cow<str>`, replacing invalid UTF-8 with U+FFFD.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn to_string_lossy(&self) -> Cow<str> {
+        self.inner.to_string_lossy()
+    }
+
+    /// Converts to an owned `PathBuf`.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn to_path_buf(&self) -> PathBuf {
+        PathBuf { inner: self.inner.to_os_string() }
+    }
+
+    /// Returns `true` if the path starts at the root directory.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn is_absolute(&self) -> bool {
+        self.has_root()
+    }
+
+    /// Returns `true` if the path is not absolute.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn is_relative(&self) -> bool {
+        !self.is_absolute()
+    }
+
+    /// Returns `true` if the path has a root.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn has_root(&self) -> bool {
+        self.as_bytes().first() == Some(&b'/')
+    }
+
+    /// Returns the path without its final component, if there is one.
+    ///
+    /// Returns `None` if the path is the root directory or empty.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn parent(&self) -> Option<&Path> {
+        let mut components = self.components();
+        match components.next_back() {
+            Some(Component::Normal(_)) | Some(Component::CurDir)
+                | Some(Component::ParentDir) => Some(components.as_path()),
+            _ => None,
+        }
+    }
+
+    /// Returns the final component of the path, if it is a normal file or
+    /// directory name.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn file_name(&self) -> Option<&OsStr> {
+        match self.components().next_back() {
+            Some(Component::Normal(name)) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Determines whether `base` is a prefix of `self`. Only whole
+    /// components match.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn starts_with<P: AsRef<Path>>(&self, base: P) -> bool {
+        let mut components = self.components();
+        base.as_ref().components().all(|c| components.next() == Some(c))
+    }
+
+    /// Creates an owned `PathBuf` with `path` adjoined to `self`. If `path`
+    /// is absolute, it replaces `self`.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn join<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let mut buf = self.to_path_buf();
+        buf.push(path);
+        buf
+    }
+
+    /// Produces an iterator over the components of the path.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn components(&self) -> Components {
+        let path = self.as_bytes();
+        let root = self.has_root();
+        Components { path, front: 0, back: path.len(), root }
+    }
+
+    /// Returns an object that implements `Display` for the path.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn display(&self) -> Display {
+        Display { path: self }
+    }
+}
+
+impl PathBuf {
+    /// Allocates an empty `PathBuf`.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn new() -> PathBuf {
+        PathBuf { inner: OsString::new() }
+    }
+
+    /// Coerces to a `Path` slice.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn as_path(&self) -> &Path {
+        self
+    }
+
+    /// Extends `self` with `path`, adding a separator if needed. If `path`
+    /// is absolute, it replaces `self`.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn push<P: AsRef<Path>>(&mut self, path: P) {
+        let path = path.as_ref();
+        if path.is_absolute() {
+            self.inner.clear();
+        } else if !self.inner.is_empty() && self.inner.as_bytes().last() != Some(&b'/') {
+            self.inner.push("/");
+        }
+
+        self.inner.push(&path.inner);
+    }
+
+    /// Truncates `self` to its parent. Returns `false` and does nothing if
+    /// there is no parent.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn pop(&mut self) -> bool {
+        let len = self.parent().map(|parent| parent.as_bytes().len());
+        match len {
+            Some(len) => {
+                self.inner.truncate(len);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Consumes the `PathBuf`, yielding its underlying `OsString`.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn into_os_string(self) -> OsString {
+        self.inner
+    }
+}
+
+/// Helper struct for safely printing paths with `format!` and `{}`.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub struct Display<'a> {
+    path: &'a Path,
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a> fmt::Display for Display<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&*self.path.to_string_lossy(), f)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a> fmt::Debug for Display<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.path, f)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl fmt::Debug for Path {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl fmt::Debug for PathBuf {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl PartialEq for Path {
+    fn eq(&self, other: &Path) -> bool {
+        self.components().eq(other.components())
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl Eq for Path { }
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl PartialEq for PathBuf {
+    fn eq(&self, other: &PathBuf) -> bool {
+        **self == **other
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl Eq for PathBuf { }
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl Deref for PathBuf {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        Path::new(&self.inner)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl Borrow<Path> for PathBuf {
+    fn borrow(&self) -> &Path {
+        self
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl ToOwned for Path {
+    type Owned = PathBuf;
+
+    fn to_owned(&self) -> PathBuf {
+        self.to_path_buf()
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a, T: ?Sized + AsRef<OsStr>> From<&'a T> for PathBuf {
+    fn from(s: &'a T) -> PathBuf {
+        PathBuf { inner: s.as_ref().to_os_string() }
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl From<String> for PathBuf {
+    fn from(s: String) -> PathBuf {
+        PathBuf { inner: OsString::from(s) }
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl From<OsString> for PathBuf {
+    fn from(s: OsString) -> PathBuf {
+        PathBuf { inner: s }
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<OsStr> for Path {
+    fn as_ref(&self) -> &OsStr {
+        &self.inner
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<OsStr> for PathBuf {
+    fn as_ref(&self) -> &OsStr {
+        &self.inner
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<Path> for Path {
+    fn as_ref(&self) -> &Path {
+        self
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<Path> for PathBuf {
+    fn as_ref(&self) -> &Path {
+        self
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<Path> for OsStr {
+    fn as_ref(&self) -> &Path {
+        Path::new(self)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<Path> for OsString {
+    fn as_ref(&self) -> &Path {
+        Path::new(self)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<Path> for str {
+    fn as_ref(&self) -> &Path {
+        Path::new(self)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl AsRef<Path> for String {
+    fn as_ref(&self) -> &Path {
+        Path::new(self)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a> AsRef<Path> for Component<'a> {
+    fn as_ref(&self) -> &Path {
+        Path::new(self.as_os_str())
+    }
+}
@@ -28,6 +28,9 @@ pub use core::sync::atomic;
 // pub use self::condvar::{Condvar, WaitTimeoutResult};
 // #[stable(feature = "rust1", since = "1.0.0")]
 // pub use self::mutex::{Mutex, MutexGuard};
+// TODO: This is an addition. See `spin`.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub use self::spin::{Mutex, MutexGuard, LockResult, PoisonError};
 // #[stable(feature = "rust1", since = "1.0.0")]
 // pub use self::once::{Once, OnceState, ONCE_INIT};
 // #[stable(feature = "rust1", since = "1.0.0")]
@@ -42,3 +45,4 @@ pub use core::sync::atomic;
 // mod mutex;
 // mod once;
 // mod rwlock;
+mod spin;
@@ -0,0 +1,165 @@
+//! A spin lock standing in for `Mutex` until there are threads to block.
+//!
+//! TODO: This is an addition. The real `Mutex` needs `sys_common`. Without an
+//! enabled MMU/cache, the processor faults on exclusive accesses, so the lock
+//! is taken with plain loads and stores on AArch64. That is only correct while
+//! there is a single core and nothing preempts a lock holder, which is all the
+//! kernel needs for now. Locks are never poisoned: a panic doesn't unwind.
+
+use cell::UnsafeCell;
+use fmt;
+use ops::{Deref, DerefMut};
+use sync::atomic::{AtomicBool, Ordering};
+
+/// A mutual exclusion primitive useful for protecting shared data.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub struct Mutex<T: ?Sized> {
+    lock: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> { }
+#[stable(feature = "rust1", since = "1.0.0")]
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> { }
+
+/// An RAII guard for a locked `Mutex`. The lock is released when the guard
+/// is dropped.
+#[must_use]
+#[stable(feature = "rust1", since = "1.0.0")]
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    lock: &'a Mutex<T>,
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a, T: ?Sized> !Send for MutexGuard<'a, T> { }
+#[stable(feature = "rust1", since = "1.0.0")]
+unsafe impl<'a, T: ?Sized + Sync> Sync for MutexGuard<'a, T> { }
+
+/// The error a poisoned lock would return. Locks are never poisoned, so this
+/// is never actually returned; it exists for compatibility with `std`.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub struct PoisonError<T> {
+    guard: T,
+}
+
+/// The result of taking a lock, which could be poisoned.
+#[stable(feature = "rust1", since = "1.0.0")]
+pub type LockResult<Guard> = Result<Guard, PoisonError<Guard>>;
+
+impl<T> Mutex<T> {
+    /// Creates a new unlocked mutex protecting `t`.
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn new(t: T) -> Mutex<T> {
+        Mutex {
+            lock: AtomicBool::new(false),
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    /// Consumes the mutex, returning the data it protects.
+    #[stable(feature = "mutex_into_inner", since = "1.6.0")]
+    pub fn into_inner(self) -> LockResult<T> {
+        Ok(self.data.into_inner())
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    /// Acquires the mutex, spinning until it is available.
+    ///
+    /// Taking a lock that the caller already holds spins forever.
+    #[cfg(target_arch = "aarch64")]
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn lock(&self) -> LockResult<MutexGuard<T>> {
+        while self.lock.load(Ordering::Relaxed) { }
+        self.lock.store(true, Ordering::Relaxed);
+        Ok(MutexGuard { lock: self })
+    }
+
+    /// Acquires the mutex, spinning until it is available.
+    ///
+    /// Taking a lock that the caller already holds spins forever.
+    #[cfg(not(target_arch = "aarch64"))]
+    #[stable(feature = "rust1", since = "1.0.0")]
+    pub fn lock(&self) -> LockResult<MutexGuard<T>> {
+        while self.lock.compare_and_swap(false, true, Ordering::Acquire) { }
+        Ok(MutexGuard { lock: self })
+    }
+
+    /// Returns a mutable reference to the data. No locking is needed since
+    /// the borrow is exclusive.
+    #[stable(feature = "mutex_get_mut", since = "1.6.0")]
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        Ok(unsafe { &mut *self.data.get() })
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Mutex<T> {
+        Mutex::new(Default::default())
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.lock.load(Ordering::Relaxed) {
+            f.write_str("Mutex { <locked> }")
+        } else {
+            f.debug_struct("Mutex").field("data", &unsafe { &*self.data.get() }).finish()
+        }
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a, T: ?Sized> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.lock.store(false, Ordering::Release);
+    }
+}
+
+#[stable(feature = "std_debug", since = "1.16.0")]
+impl<'a, T: ?Sized + fmt::Debug> fmt::Debug for MutexGuard<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MutexGuard").field("lock", &self.lock).finish()
+    }
+}
+
+impl<T> PoisonError<T> {
+    /// Consumes the error, returning the guard it holds.
+    #[stable(feature = "sync_poison", since = "1.2.0")]
+    pub fn into_inner(self) -> T {
+        self.guard
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<T> fmt::Debug for PoisonError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        "PoisonError { inner: .. }".fmt(f)
+    }
+}
+
+#[stable(feature = "rust1", since = "1.0.0")]
+impl<T> fmt::Display for PoisonError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        "poisoned lock: another task failed inside".fmt(f)
+    }
+}
@@ -0,0 +1,9 @@
+//! Temporal quantification.
+//!
+//! TODO: This is an addition. Only `Duration` is provided: there is no clock
+//! to build `Instant` or `SystemTime` on.
+
+#![stable(feature = "time", since = "1.3.0")]
+
+#[stable(feature = "time", since = "1.3.0")]
+pub use core::time::Duration;
@@ -0,0 +1,48 @@
+//! Bit field arithmetic behind the field helpers on the wrapper traits.
+//!
+//! A field is named by the index of its lowest bit, `shift`, and its `width`
+//! in bits. Every function here panics if the field doesn't lie entirely
+//! within `T`.
+
+use traits::Bits;
+
+/// Returns a mask with the `width` bits starting at bit `shift` set.
+///
+/// # Panics
+///
+/// Panics if `width` is zero or if `shift + width` exceeds the width of `T`.
+#[inline(always)]
+pub fn mask<T: Bits>(shift: u32, width: u32) -> T {
+    assert!(width > 0 && width <= T::BITS && shift <= T::BITS - width,
+            "bit field at {} of width {} doesn't fit in {} bits", shift, width, T::BITS);
+
+    let ones = if width == T::BITS { !T::ZERO } else { !(!T::ZERO << width) };
+    ones << shift
+}
+
+/// Returns the `width` bit field of `value` starting at bit `shift`, shifted
+/// down to bit 0.
+///
+/// # Panics
+///
+/// Panics if the field doesn't fit in `T`. See `mask()`.
+#[inline(always)]
+pub fn extract<T: Bits>(value: T, shift: u32, width: u32) -> T {
+    (value & mask(shift, width)) >> shift
+}
+
+/// Returns `value` with its `width` bit field starting at bit `shift`
+/// replaced by `field`.
+///
+/// # Panics
+///
+/// Panics if the field doesn't fit in `T` (see `mask()`) or if `field` has
+/// bits set above its lowest `width` bits.
+#[inline(always)]
+pub fn insert<T: Bits>(value: T, shift: u32, width: u32, field: T) -> T {
+    let mask: T = mask(shift, width);
+    assert!(field & !(mask >> shift) == T::ZERO,
+            "value doesn't fit in a bit field of width {}", width);
+
+    (value & !mask) | (field << shift)
+}
@@ -3,8 +3,11 @@
 
 #![no_std]
 
+#[cfg(test)]
+mod tests;
 mod traits;
 mod macros;
+pub mod field;
 
 pub use traits::*;
 use macros::*;
@@ -19,7 +22,7 @@ use macros::*;
 /// ```
 pub mod prelude {
 	#[doc(no_inline)]
-    pub use super::{Readable, Writeable, ReadableWriteable, Wrapper};
+    pub use super::{Readable, Writeable, ReadableWriteable, Wrapper, Bits};
 }
 
 /// A wrapper type that enforces **read-only** _volatile_ accesses to a raw
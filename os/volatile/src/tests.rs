@@ -0,0 +1,124 @@
+use field::{mask, extract, insert};
+use prelude::*;
+use {Volatile, ReadVolatile};
+
+#[test]
+fn masks() {
+    assert_eq!(mask::<u32>(0, 1), 0b1);
+    assert_eq!(mask::<u32>(4, 3), 0b111_0000);
+    assert_eq!(mask::<u32>(0, 32), !0);
+    assert_eq!(mask::<u32>(31, 1), 1 << 31);
+    assert_eq!(mask::<u8>(4, 4), 0xF0);
+    assert_eq!(mask::<u16>(0, 16), 0xFFFF);
+    assert_eq!(mask::<u64>(32, 32), 0xFFFF_FFFF_0000_0000);
+}
+
+#[test]
+fn extracts_fields() {
+    let value: u32 = 0xDEAD_BEEF;
+    assert_eq!(extract(value, 0, 8), 0xEF);
+    assert_eq!(extract(value, 8, 8), 0xBE);
+    assert_eq!(extract(value, 28, 4), 0xD);
+    assert_eq!(extract(value, 0, 32), value);
+    assert_eq!(extract(value, 31, 1), 1);
+    assert_eq!(extract(value, 4, 1), 0);
+}
+
+#[test]
+fn inserts_fields() {
+    assert_eq!(insert(0u32, 3, 3, 0b101), 0b101_000);
+    assert_eq!(insert(!0u32, 3, 3, 0), !0b111_000);
+    assert_eq!(insert(0xDEAD_BEEFu32, 16, 16, 0xF00D), 0xF00D_BEEF);
+    assert_eq!(insert(0u32, 0, 32, 0x1234_5678), 0x1234_5678);
+    assert_eq!(insert(0u8, 7, 1, 1), 0x80);
+
+    for shift in 0..30 {
+        let value = insert(0x5555_5555u32, shift, 3, 0b110);
+        assert_eq!(extract(value, shift, 3), 0b110);
+        assert_eq!(value & !mask::<u32>(shift, 3), 0x5555_5555 & !mask::<u32>(shift, 3));
+    }
+}
+
+#[test]
+#[should_panic]
+fn zero_width_field_panics() {
+    mask::<u32>(0, 0);
+}
+
+#[test]
+#[should_panic]
+fn field_past_top_bit_panics() {
+    mask::<u32>(30, 3);
+}
+
+#[test]
+#[should_panic]
+fn field_wider_than_type_panics() {
+    mask::<u8>(0, 9);
+}
+
+#[test]
+#[should_panic]
+fn huge_shift_panics() {
+    extract(0u32, u32::max_value(), 1);
+}
+
+#[test]
+#[should_panic]
+fn oversized_value_panics() {
+    insert(0u32, 0, 3, 0b1000);
+}
+
+#[test]
+fn update_reads_and_writes_once() {
+    let mut reg = Volatile(0b1010u32);
+    let mut calls = 0;
+    reg.update(|value| { calls += 1; value << 1 });
+    assert_eq!(calls, 1);
+    assert_eq!(reg.read(), 0b10100);
+}
+
+#[test]
+fn set_and_clear_bits() {
+    let mut reg = Volatile(0b1000_0001u8);
+    reg.set_bits(0b0000_0110);
+    assert_eq!(reg.read(), 0b1000_0111);
+    reg.clear_bits(0b1000_0010);
+    assert_eq!(reg.read(), 0b0000_0101);
+    reg.set_bits(0);
+    reg.clear_bits(0);
+    assert_eq!(reg.read(), 0b0000_0101);
+}
+
+#[test]
+fn write_and_read_field() {
+    let mut reg = Volatile(!0u32);
+    reg.write_field(12, 3, 0b001);
+    assert_eq!(reg.read(), !0b110_0000_0000_0000);
+    assert_eq!(reg.read_field(12, 3), 0b001);
+    assert_eq!(reg.read_field(15, 17), 0x1FFFF);
+
+    reg.write_field(0, 32, 0);
+    assert_eq!(reg.read(), 0);
+}
+
+#[test]
+fn read_only_field() {
+    let reg = ReadVolatile(0xABCDu16);
+    assert_eq!(reg.read_field(4, 8), 0xBC);
+    assert_eq!(reg.read_field(12, 4), 0xA);
+}
+
+#[test]
+#[should_panic]
+fn write_field_rejects_oversized_value() {
+    let mut reg = Volatile(0u32);
+    reg.write_field(4, 2, 0b100);
+}
+
+#[test]
+#[should_panic]
+fn read_field_rejects_overflowing_width() {
+    let reg = ReadVolatile(0u32);
+    reg.read_field(16, 17);
+}
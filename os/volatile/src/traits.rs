@@ -1,3 +1,7 @@
+use core::ops::{BitAnd, BitOr, Not, Shl, Shr};
+
+use field;
+
 /// Trait implemented by all of the wrapper types in this crate.
 ///
 /// The inner type of wrapper is specified as an associated constant `Inner`.
@@ -10,6 +14,28 @@ pub trait Wrapper {
     fn ptr(&self) -> *const Self::Inner;
 }
 
+/// Trait implemented by the unsigned integer types that registers hold,
+/// providing what the bit field helpers need.
+pub trait Bits: Copy + PartialEq
+    + BitAnd<Output = Self> + BitOr<Output = Self> + Not<Output = Self>
+    + Shl<u32, Output = Self> + Shr<u32, Output = Self>
+{
+    /// The width of the type in bits.
+    const BITS: u32;
+
+    /// The value with no bits set.
+    const ZERO: Self;
+}
+
+macro bits($($t:ty => $n:expr),*) {
+    $(impl Bits for $t {
+        const BITS: u32 = $n;
+        const ZERO: $t = 0;
+    })*
+}
+
+bits!(u8 => 8, u16 => 16, u32 => 32, u64 => 64);
+
 /// Trait implemented by **readable** volatile wrappers.
 pub trait Readable<T> {
     /// Returns the inner pointer.
@@ -32,6 +58,20 @@ pub trait Readable<T> {
     {
         (self.read() & mask) == mask
     }
+
+    /// Reads the value pointed to by `self` and returns its `width` bit field
+    /// starting at bit `shift`, shifted down to bit 0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is zero or if the field extends past the top bit of
+    /// `T`.
+    #[inline(always)]
+    fn read_field(&self, shift: u32, width: u32) -> T
+        where T: Bits
+    {
+        field::extract(self.read(), shift, width)
+    }
 }
 
 /// Trait implemented by **writeable** volatile wrappers.
@@ -50,21 +90,58 @@ pub trait Writeable<T> {
 
 /// Trait implemented by **readable _and_ writeable** volatile wrappers.
 pub trait ReadableWriteable<T>: Readable<T> + Writeable<T>
-    where T: ::core::ops::BitAnd<Output = T>,
-          T: ::core::ops::BitOr<Output = T>
+    where T: BitAnd<Output = T>, T: BitOr<Output = T>
 {
+    /// Reads the value referred to by `self` once, passes it to `f`, and
+    /// writes the value `f` returns back once. Use this rather than separate
+    /// `read()` and `write()` calls so that a read-modify-write sequence is
+    /// visibly a single one.
+    #[inline(always)]
+    fn update<F: FnOnce(T) -> T>(&mut self, f: F) {
+        let value = self.read();
+        self.write(f(value));
+    }
+
     /// Applies the mask `mask` using `&` to the value referred to by `self`.
     /// This is equivalent to `self.write(self.read() & mask)`.
     fn and_mask(&mut self, mask: T) {
-        let init_val = self.read();
-        self.write(init_val & mask);
+        self.update(|value| value & mask);
     }
 
     /// Applies the mask `mask` using `|` to the value referred to by `self`.
     /// This is equivalent to `self.write(self.read() | mask)`.
     fn or_mask(&mut self, mask: T) {
-        let init_val = self.read();
-        self.write(init_val | mask);
+        self.update(|value| value | mask);
+    }
+
+    /// Sets the bits that are set in `mask`, leaving the others as they are.
+    #[inline(always)]
+    fn set_bits(&mut self, mask: T) {
+        self.update(|value| value | mask);
+    }
+
+    /// Clears the bits that are set in `mask`, leaving the others as they are.
+    #[inline(always)]
+    fn clear_bits(&mut self, mask: T)
+        where T: Not<Output = T>
+    {
+        self.update(|value| value & !mask);
     }
-}
 
+    /// Replaces the `width` bit field starting at bit `shift` with `value`,
+    /// leaving the other bits as they are.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `width` is zero, if the field extends past the top bit of
+    /// `T`, or if `value` doesn't fit in `width` bits. The checks happen
+    /// before the register is read.
+    #[inline(always)]
+    fn write_field(&mut self, shift: u32, width: u32, value: T)
+        where T: Bits
+    {
+        let mask = field::mask::<T>(shift, width);
+        let bits = field::insert(T::ZERO, shift, width, value);
+        self.update(|old| (old & !mask) | bits);
+    }
+}